@@ -24,6 +24,11 @@ use std::os::unix::fs::MetadataExt;
 use std::path::Path;
 use std::time::Duration;
 
+use kanidm_proto::internal::{
+    AuditLogEntry, AuditLogQuery, PasswordKdfReport, SchemaAttributeCreateRequest,
+    SchemaAttributeUpdateRequest, SchemaClassCreateRequest, SchemaClassUpdateRequest,
+    SourceLockStatus,
+};
 use kanidm_proto::v1::*;
 use reqwest::header::CONTENT_TYPE;
 pub use reqwest::StatusCode;
@@ -1035,6 +1040,23 @@ impl KanidmClient {
         r
     }
 
+    pub async fn auth_step_devicekey_complete(
+        &self,
+        pkc: Box<PublicKeyCredential>,
+    ) -> Result<AuthResponse, ClientError> {
+        let auth_req = AuthRequest {
+            step: AuthStep::Cred(AuthCredential::DeviceKey(pkc)),
+        };
+        let r: Result<AuthResponse, _> = self.perform_auth_post_request("/v1/auth", auth_req).await;
+
+        if let Ok(ar) = &r {
+            if let AuthState::Success(token) = &ar.state {
+                self.set_token(token.clone()).await;
+            };
+        };
+        r
+    }
+
     pub async fn auth_anonymous(&self) -> Result<(), ClientError> {
         let mechs = match self.auth_step_init("anonymous").await {
             Ok(s) => s,
@@ -1487,6 +1509,74 @@ impl KanidmClient {
             .await
     }
 
+    pub async fn idm_group_account_policy_enable(&self, id: &str) -> Result<(), ClientError> {
+        self.perform_post_request(
+            format!("/v1/group/{}/_attr/class", id).as_str(),
+            vec!["account_policy".to_string()],
+        )
+        .await
+    }
+
+    pub async fn idm_group_account_policy_authsession_expiry_set(
+        &self,
+        id: &str,
+        expiry: u32,
+    ) -> Result<(), ClientError> {
+        self.perform_put_request(
+            format!("/v1/group/{}/_attr/authsession_expiry", id).as_str(),
+            vec![expiry.to_string()],
+        )
+        .await
+    }
+
+    pub async fn idm_group_account_policy_privilege_expiry_set(
+        &self,
+        id: &str,
+        expiry: u32,
+    ) -> Result<(), ClientError> {
+        self.perform_put_request(
+            format!("/v1/group/{}/_attr/privilege_expiry", id).as_str(),
+            vec![expiry.to_string()],
+        )
+        .await
+    }
+
+    pub async fn idm_group_account_policy_password_minimum_length_set(
+        &self,
+        id: &str,
+        length: u32,
+    ) -> Result<(), ClientError> {
+        self.perform_put_request(
+            format!("/v1/group/{}/_attr/auth_password_minimum_length", id).as_str(),
+            vec![length.to_string()],
+        )
+        .await
+    }
+
+    pub async fn idm_group_account_policy_credential_type_minimum_set(
+        &self,
+        id: &str,
+        value: &str,
+    ) -> Result<(), ClientError> {
+        self.perform_put_request(
+            format!("/v1/group/{}/_attr/credential_type_minimum", id).as_str(),
+            vec![value.to_string()],
+        )
+        .await
+    }
+
+    pub async fn idm_group_account_policy_oauth2_access_token_expiry_set(
+        &self,
+        id: &str,
+        expiry: u32,
+    ) -> Result<(), ClientError> {
+        self.perform_put_request(
+            format!("/v1/group/{}/_attr/oauth2_access_token_expiry", id).as_str(),
+            vec![expiry.to_string()],
+        )
+        .await
+    }
+
     pub async fn idm_group_delete(&self, id: &str) -> Result<(), ClientError> {
         self.perform_delete_request(["/v1/group/", id].concat().as_str())
             .await
@@ -1510,6 +1600,17 @@ impl KanidmClient {
             .await
     }
 
+    pub async fn idm_person_account_credential_update_intent_send(
+        &self,
+        id: &str,
+    ) -> Result<(), ClientError> {
+        self.perform_post_request(
+            format!("/v1/person/{}/_credential/_update_intent_send", id).as_str(),
+            (),
+        )
+        .await
+    }
+
     pub async fn idm_account_credential_update_begin(
         &self,
         id: &str,
@@ -1641,6 +1742,36 @@ impl KanidmClient {
             .await
     }
 
+    pub async fn idm_account_credential_update_devicekey_init(
+        &self,
+        session_token: &CUSessionToken,
+    ) -> Result<CUStatus, ClientError> {
+        let scr = CURequest::DeviceKeyInit;
+        self.perform_simple_post_request("/v1/credential/_update", &(scr, &session_token))
+            .await
+    }
+
+    pub async fn idm_account_credential_update_devicekey_finish(
+        &self,
+        session_token: &CUSessionToken,
+        label: String,
+        registration: RegisterPublicKeyCredential,
+    ) -> Result<CUStatus, ClientError> {
+        let scr = CURequest::DeviceKeyFinish(label, registration);
+        self.perform_simple_post_request("/v1/credential/_update", &(scr, &session_token))
+            .await
+    }
+
+    pub async fn idm_account_credential_update_devicekey_remove(
+        &self,
+        session_token: &CUSessionToken,
+        uuid: Uuid,
+    ) -> Result<CUStatus, ClientError> {
+        let scr = CURequest::DeviceKeyRemove(uuid);
+        self.perform_simple_post_request("/v1/credential/_update", &(scr, &session_token))
+            .await
+    }
+
     pub async fn idm_account_credential_update_commit(
         &self,
         session_token: &CUSessionToken,
@@ -1728,6 +1859,21 @@ impl KanidmClient {
             .await
     }
 
+    /// Set the CAs that device keys must be attested by. Each CA is a base64 encoded
+    /// DER certificate.
+    pub async fn idm_domain_set_attestation_ca_list(
+        &self,
+        cas: &[String],
+    ) -> Result<(), ClientError> {
+        self.perform_put_request("/v1/domain/_attr/webauthn_attestation_ca_list", cas)
+            .await
+    }
+
+    pub async fn idm_domain_clear_attestation_ca_list(&self) -> Result<(), ClientError> {
+        self.perform_delete_request("/v1/domain/_attr/webauthn_attestation_ca_list")
+            .await
+    }
+
     pub async fn idm_domain_reset_token_key(&self) -> Result<(), ClientError> {
         self.perform_delete_request("/v1/domain/_attr/es256_private_key_der")
             .await
@@ -1759,6 +1905,57 @@ impl KanidmClient {
             .await
     }
 
+    pub async fn idm_schema_attributetype_create(
+        &self,
+        req: &SchemaAttributeCreateRequest,
+    ) -> Result<(), ClientError> {
+        self.perform_post_request("/v1/schema/attributetype", req)
+            .await
+    }
+
+    pub async fn idm_schema_attributetype_update(
+        &self,
+        id: &str,
+        req: &SchemaAttributeUpdateRequest,
+    ) -> Result<(), ClientError> {
+        self.perform_patch_request(format!("/v1/schema/attributetype/{}", id).as_str(), req)
+            .await
+    }
+
+    /// Delete a custom attribute. If purge is set, the attribute is also removed from every
+    /// entry and class that has it.
+    pub async fn idm_schema_attributetype_delete(
+        &self,
+        id: &str,
+        purge: bool,
+    ) -> Result<(), ClientError> {
+        self.perform_delete_request(
+            format!("/v1/schema/attributetype/{}?purge={}", id, purge).as_str(),
+        )
+        .await
+    }
+
+    pub async fn idm_schema_classtype_create(
+        &self,
+        req: &SchemaClassCreateRequest,
+    ) -> Result<(), ClientError> {
+        self.perform_post_request("/v1/schema/classtype", req).await
+    }
+
+    pub async fn idm_schema_classtype_update(
+        &self,
+        id: &str,
+        req: &SchemaClassUpdateRequest,
+    ) -> Result<(), ClientError> {
+        self.perform_patch_request(format!("/v1/schema/classtype/{}", id).as_str(), req)
+            .await
+    }
+
+    pub async fn idm_schema_classtype_delete(&self, id: &str) -> Result<(), ClientError> {
+        self.perform_delete_request(format!("/v1/schema/classtype/{}", id).as_str())
+            .await
+    }
+
     // ==== Oauth2 resource server configuration
     pub async fn idm_oauth2_rs_list(&self) -> Result<Vec<Entry>, ClientError> {
         self.perform_get_request("/v1/oauth2").await
@@ -1903,6 +2100,29 @@ impl KanidmClient {
             .await
     }
 
+    pub async fn idm_oauth2_rs_add_token_exchange_audience(
+        &self,
+        id: &str,
+        audience: &str,
+    ) -> Result<(), ClientError> {
+        self.perform_post_request(
+            format!("/v1/oauth2/{}/_token_exchange/{}", id, audience).as_str(),
+            (),
+        )
+        .await
+    }
+
+    pub async fn idm_oauth2_rs_remove_token_exchange_audience(
+        &self,
+        id: &str,
+        audience: &str,
+    ) -> Result<(), ClientError> {
+        self.perform_delete_request(
+            format!("/v1/oauth2/{}/_token_exchange/{}", id, audience).as_str(),
+        )
+        .await
+    }
+
     pub async fn idm_oauth2_rs_delete(&self, id: &str) -> Result<(), ClientError> {
         self.perform_delete_request(["/v1/oauth2/", id].concat().as_str())
             .await
@@ -1980,6 +2200,188 @@ impl KanidmClient {
             .await
     }
 
+    pub async fn idm_oauth2_rs_enable_require_par(&self, id: &str) -> Result<(), ClientError> {
+        let mut update_oauth2_rs = Entry {
+            attrs: BTreeMap::new(),
+        };
+        update_oauth2_rs
+            .attrs
+            .insert("oauth2_require_par".to_string(), vec!["true".to_string()]);
+        self.perform_patch_request(format!("/v1/oauth2/{}", id).as_str(), update_oauth2_rs)
+            .await
+    }
+
+    pub async fn idm_oauth2_rs_disable_require_par(&self, id: &str) -> Result<(), ClientError> {
+        let mut update_oauth2_rs = Entry {
+            attrs: BTreeMap::new(),
+        };
+        update_oauth2_rs
+            .attrs
+            .insert("oauth2_require_par".to_string(), vec!["false".to_string()]);
+        self.perform_patch_request(format!("/v1/oauth2/{}", id).as_str(), update_oauth2_rs)
+            .await
+    }
+
+    /// Replace the set of keys that this resource server signs request objects with. Each
+    /// key is a json formatted jwk. An empty set removes all keys.
+    pub async fn idm_oauth2_rs_set_client_jwks(
+        &self,
+        id: &str,
+        jwks: Vec<String>,
+    ) -> Result<(), ClientError> {
+        let mut update_oauth2_rs = Entry {
+            attrs: BTreeMap::new(),
+        };
+        update_oauth2_rs
+            .attrs
+            .insert("oauth2_rs_client_jwk".to_string(), jwks);
+        self.perform_patch_request(format!("/v1/oauth2/{}", id).as_str(), update_oauth2_rs)
+            .await
+    }
+
+    pub async fn idm_oauth2_rs_set_client_jwks_url(
+        &self,
+        id: &str,
+        url: &Url,
+    ) -> Result<(), ClientError> {
+        let mut update_oauth2_rs = Entry {
+            attrs: BTreeMap::new(),
+        };
+        update_oauth2_rs.attrs.insert(
+            "oauth2_rs_client_jwks_url".to_string(),
+            vec![url.to_string()],
+        );
+        self.perform_patch_request(format!("/v1/oauth2/{}", id).as_str(), update_oauth2_rs)
+            .await
+    }
+
+    /// Fetch the jwk set from the client jwks url of this resource server, replacing its
+    /// current client jwks.
+    pub async fn idm_oauth2_rs_refresh_client_jwks(&self, id: &str) -> Result<(), ClientError> {
+        self.perform_post_request(
+            format!("/v1/oauth2/{}/_client_jwks_refresh", id).as_str(),
+            (),
+        )
+        .await
+    }
+
+    /// Set where this resource server receives logout tokens when a user's session ends.
+    pub async fn idm_oauth2_rs_set_backchannel_logout_uri(
+        &self,
+        id: &str,
+        uri: &Url,
+    ) -> Result<(), ClientError> {
+        let mut update_oauth2_rs = Entry {
+            attrs: BTreeMap::new(),
+        };
+        update_oauth2_rs.attrs.insert(
+            "oauth2_rs_backchannel_logout_uri".to_string(),
+            vec![uri.to_string()],
+        );
+        self.perform_patch_request(format!("/v1/oauth2/{}", id).as_str(), update_oauth2_rs)
+            .await
+    }
+
+    pub async fn idm_oauth2_rs_clear_backchannel_logout_uri(
+        &self,
+        id: &str,
+    ) -> Result<(), ClientError> {
+        let mut update_oauth2_rs = Entry {
+            attrs: BTreeMap::new(),
+        };
+        update_oauth2_rs
+            .attrs
+            .insert("oauth2_rs_backchannel_logout_uri".to_string(), Vec::new());
+        self.perform_patch_request(format!("/v1/oauth2/{}", id).as_str(), update_oauth2_rs)
+            .await
+    }
+
+    /// Set the service account that client credential grants of this resource server act as.
+    pub async fn idm_oauth2_rs_set_service_account(
+        &self,
+        id: &str,
+        service_account: &str,
+    ) -> Result<(), ClientError> {
+        let mut update_oauth2_rs = Entry {
+            attrs: BTreeMap::new(),
+        };
+        update_oauth2_rs.attrs.insert(
+            "oauth2_rs_service_account".to_string(),
+            vec![service_account.to_string()],
+        );
+        self.perform_patch_request(format!("/v1/oauth2/{}", id).as_str(), update_oauth2_rs)
+            .await
+    }
+
+    pub async fn idm_oauth2_rs_clear_service_account(&self, id: &str) -> Result<(), ClientError> {
+        let mut update_oauth2_rs = Entry {
+            attrs: BTreeMap::new(),
+        };
+        update_oauth2_rs
+            .attrs
+            .insert("oauth2_rs_service_account".to_string(), Vec::new());
+        self.perform_patch_request(format!("/v1/oauth2/{}", id).as_str(), update_oauth2_rs)
+            .await
+    }
+
+    // ==== message queue
+    pub async fn idm_message_queue_list(&self) -> Result<Vec<Entry>, ClientError> {
+        self.perform_get_request("/v1/message").await
+    }
+
+    pub async fn idm_message_queue_get(&self, id: &str) -> Result<Option<Entry>, ClientError> {
+        self.perform_get_request(format!("/v1/message/{}", id).as_str())
+            .await
+    }
+
+    pub async fn idm_message_queue_delete(&self, id: &str) -> Result<(), ClientError> {
+        self.perform_delete_request(format!("/v1/message/{}", id).as_str())
+            .await
+    }
+
+    // ==== audit log
+    pub async fn idm_audit_log_search(
+        &self,
+        query: &AuditLogQuery,
+    ) -> Result<Vec<AuditLogEntry>, ClientError> {
+        let mut params = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(actor) = &query.actor {
+            params.append_pair("actor", actor);
+        }
+        if let Some(target) = &query.target {
+            params.append_pair("target", target);
+        }
+        if let Some(from) = query.from {
+            params.append_pair("from", &from.unix_timestamp().to_string());
+        }
+        if let Some(to) = query.to {
+            params.append_pair("to", &to.unix_timestamp().to_string());
+        }
+        if let Some(kind) = &query.kind {
+            params.append_pair("kind", kind);
+        }
+        if let Some(limit) = query.limit {
+            params.append_pair("limit", &limit.to_string());
+        }
+        self.perform_get_request(format!("/v1/audit?{}", params.finish()).as_str())
+            .await
+    }
+
+    // ==== source locks
+    pub async fn idm_source_lock_list(&self) -> Result<Vec<SourceLockStatus>, ClientError> {
+        self.perform_get_request("/v1/system/_source_lock").await
+    }
+
+    pub async fn idm_source_lock_clear(&self, source: &str) -> Result<(), ClientError> {
+        self.perform_delete_request(format!("/v1/system/_source_lock/{}", source).as_str())
+            .await
+    }
+
+    pub async fn system_password_kdf_report(&self) -> Result<PasswordKdfReport, ClientError> {
+        self.perform_get_request("/v1/system/_password_kdf_report")
+            .await
+    }
+
     // ==== recycle bin
     pub async fn recycle_bin_list(&self) -> Result<Vec<Entry>, ClientError> {
         self.perform_get_request("/v1/recycle_bin").await
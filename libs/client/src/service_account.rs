@@ -2,6 +2,7 @@ use std::collections::BTreeMap;
 
 use kanidm_proto::v1::{AccountUnixExtend, ApiToken, ApiTokenGenerate, CredentialStatus, Entry};
 use time::OffsetDateTime;
+use url::Url;
 use uuid::Uuid;
 
 use crate::{ClientError, KanidmClient};
@@ -239,4 +240,68 @@ impl KanidmClient {
         )
         .await
     }
+
+    pub async fn idm_service_account_set_oauth2_registration_origins(
+        &self,
+        id: &str,
+        origins: &[Url],
+    ) -> Result<(), ClientError> {
+        let m: Vec<_> = origins.iter().map(|u| u.to_string()).collect();
+        self.perform_put_request(
+            format!(
+                "/v1/service_account/{}/_attr/oauth2_registration_origin",
+                id
+            )
+            .as_str(),
+            m,
+        )
+        .await
+    }
+
+    pub async fn idm_service_account_clear_oauth2_registration_origins(
+        &self,
+        id: &str,
+    ) -> Result<(), ClientError> {
+        self.perform_delete_request(
+            format!(
+                "/v1/service_account/{}/_attr/oauth2_registration_origin",
+                id
+            )
+            .as_str(),
+        )
+        .await
+    }
+
+    pub async fn idm_service_account_update_oauth2_registration_scope_map(
+        &self,
+        id: &str,
+        group: &str,
+        scopes: Vec<&str>,
+    ) -> Result<(), ClientError> {
+        let scopes: Vec<String> = scopes.into_iter().map(str::to_string).collect();
+        self.perform_post_request(
+            format!(
+                "/v1/service_account/{}/_oauth2_registration_scopemap/{}",
+                id, group
+            )
+            .as_str(),
+            scopes,
+        )
+        .await
+    }
+
+    pub async fn idm_service_account_delete_oauth2_registration_scope_map(
+        &self,
+        id: &str,
+        group: &str,
+    ) -> Result<(), ClientError> {
+        self.perform_delete_request(
+            format!(
+                "/v1/service_account/{}/_oauth2_registration_scopemap/{}",
+                id, group
+            )
+            .as_str(),
+        )
+        .await
+    }
 }
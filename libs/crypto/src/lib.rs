@@ -9,29 +9,52 @@ use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::time::{Duration, Instant};
 
+use argon2::password_hash::{self, PasswordHash, PasswordVerifier};
+use argon2::{Algorithm, Argon2, Params, Version};
+use bcrypt::HashParts;
 use kanidm_proto::v1::OperationError;
 use openssl::hash::{self, MessageDigest};
 use openssl::nid::Nid;
 use openssl::pkcs5::pbkdf2_hmac;
 use openssl::sha::Sha512;
 
-// NIST 800-63.b salt should be 112 bits -> 14  8u8.
-// I choose tinfoil hat though ...
-const PBKDF2_SALT_LEN: usize = 24;
-
-const PBKDF2_MIN_NIST_SALT_LEN: usize = 14;
-
 // Min number of rounds for a pbkdf2
 pub const PBKDF2_MIN_NIST_COST: usize = 10000;
 
-// 64 * u8 -> 512 bits of out.
-const PBKDF2_KEY_LEN: usize = 64;
 const PBKDF2_MIN_NIST_KEY_LEN: usize = 32;
 const PBKDF2_SHA1_MIN_KEY_LEN: usize = 19;
 
 const DS_SSHA512_SALT_LEN: usize = 8;
 const DS_SSHA512_HASH_LEN: usize = 64;
 
+// The length of a complete bcrypt string, and of the encoded hash in sha256-crypt
+// and sha512-crypt.
+const BCRYPT_HASH_LEN: usize = 60;
+const SHA256_CRYPT_HASH_LEN: usize = 43;
+const SHA512_CRYPT_HASH_LEN: usize = 86;
+const SHA_CRYPT_MAX_SALT_LEN: usize = 16;
+// The cost of an imported hash is chosen by whoever made it, so these limit how long a
+// single authentication can take. Both are well above what is used in practice.
+const SHA_CRYPT_MAX_ROUNDS: u32 = 5_000_000;
+const BCRYPT_MAX_COST: u32 = 15;
+
+// Argon2id parameters. The memory cost is in KiB, and we only accept version 0x13.
+const ARGON2_VERSION: u32 = 0x13;
+const ARGON2_SALT_LEN: usize = 16;
+const ARGON2_KEY_LEN: usize = 32;
+const ARGON2_MIN_SALT_LEN: usize = 8;
+const ARGON2_MIN_KEY_LEN: usize = 16;
+const ARGON2_MIN_RAM_KIB: u32 = 8 * 1024;
+// Every concurrent authentication holds this much memory, so don't let tuning run away.
+const ARGON2_MAX_RAM_KIB: u32 = 64 * 1024;
+const ARGON2_MIN_T_COST: u32 = 2;
+const ARGON2_MAX_T_COST: u32 = 16;
+const ARGON2_P_COST: u32 = 1;
+// Imported hashes may use more memory and lanes than we choose, but are upgraded to our
+// own parameters on their next use.
+const ARGON2_MAX_IMPORT_RAM_KIB: u32 = 256 * 1024;
+const ARGON2_MAX_P_COST: u32 = 16;
+
 #[derive(Serialize, Deserialize)]
 #[allow(non_camel_case_types)]
 pub enum DbPasswordV1 {
@@ -40,6 +63,18 @@ pub enum DbPasswordV1 {
     PBKDF2_SHA512(usize, Vec<u8>, Vec<u8>),
     SSHA512(Vec<u8>, Vec<u8>),
     NT_MD4(Vec<u8>),
+    BCRYPT(String),
+    SHA256_CRYPT(String),
+    SHA512_CRYPT(String),
+    ARGON2_PHC(String),
+    ARGON2ID {
+        m: u32,
+        t: u32,
+        p: u32,
+        v: u32,
+        s: Vec<u8>,
+        k: Vec<u8>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -67,6 +102,26 @@ pub enum ReplPasswordV1 {
     NT_MD4 {
         hash: Base64UrlSafeData,
     },
+    BCRYPT {
+        hash: String,
+    },
+    SHA256_CRYPT {
+        hash: String,
+    },
+    SHA512_CRYPT {
+        hash: String,
+    },
+    ARGON2_PHC {
+        hash: String,
+    },
+    ARGON2ID {
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+        version: u32,
+        salt: Base64UrlSafeData,
+        key: Base64UrlSafeData,
+    },
 }
 
 impl fmt::Debug for DbPasswordV1 {
@@ -77,62 +132,97 @@ impl fmt::Debug for DbPasswordV1 {
             DbPasswordV1::PBKDF2_SHA512(_, _, _) => write!(f, "PBKDF2_SHA512"),
             DbPasswordV1::SSHA512(_, _) => write!(f, "SSHA512"),
             DbPasswordV1::NT_MD4(_) => write!(f, "NT_MD4"),
+            DbPasswordV1::BCRYPT(_) => write!(f, "BCRYPT"),
+            DbPasswordV1::SHA256_CRYPT(_) => write!(f, "SHA256_CRYPT"),
+            DbPasswordV1::SHA512_CRYPT(_) => write!(f, "SHA512_CRYPT"),
+            DbPasswordV1::ARGON2_PHC(_) => write!(f, "ARGON2_PHC"),
+            DbPasswordV1::ARGON2ID { .. } => write!(f, "ARGON2ID"),
         }
     }
 }
 
 #[derive(Debug)]
 pub struct CryptoPolicy {
-    pub(crate) pbkdf2_cost: usize,
+    pub(crate) argon2id_params: Params,
 }
 
 impl CryptoPolicy {
     pub fn minimum() -> Self {
         CryptoPolicy {
-            pbkdf2_cost: PBKDF2_MIN_NIST_COST,
+            argon2id_params: Self::argon2id_minimum(),
         }
     }
 
-    pub fn time_target(t: Duration) -> Self {
-        let r = match Password::bench_pbkdf2(PBKDF2_MIN_NIST_COST * 10) {
-            Some(bt) => {
-                let ubt = bt.as_nanos() as usize;
+    fn argon2id_minimum() -> Params {
+        Params::new(
+            ARGON2_MIN_RAM_KIB,
+            ARGON2_MIN_T_COST,
+            ARGON2_P_COST,
+            Some(ARGON2_KEY_LEN),
+        )
+        .unwrap_or_default()
+    }
 
-                // Get the cost per thousand rounds
-                let per_thou = (PBKDF2_MIN_NIST_COST * 10) / 1000;
-                let t_per_thou = ubt / per_thou;
-                // eprintln!("{} / {}", ubt, per_thou);
+    /// Find the most expensive argon2id parameters that still hash within the time target.
+    /// Memory is raised first since that is what hurts an attacker most, then iterations.
+    fn argon2id_time_target(t: Duration) -> Params {
+        let mut params = Self::argon2id_minimum();
 
-                // Now we need the attacker work in nanos
-                let attack_time = t.as_nanos() as usize;
-                let r = (attack_time / t_per_thou) * 1000;
+        loop {
+            let (m_cost, t_cost) = if params.m_cost() < ARGON2_MAX_RAM_KIB {
+                (
+                    (params.m_cost() * 2).min(ARGON2_MAX_RAM_KIB),
+                    params.t_cost(),
+                )
+            } else if params.t_cost() < ARGON2_MAX_T_COST {
+                (params.m_cost(), params.t_cost() + 1)
+            } else {
+                break;
+            };
 
-                // eprintln!("({} / {} ) * 1000", attack_time, t_per_thou);
-                // eprintln!("Maybe rounds -> {}", r);
+            let next = match Params::new(m_cost, t_cost, ARGON2_P_COST, Some(ARGON2_KEY_LEN)) {
+                Ok(next) => next,
+                Err(_) => break,
+            };
 
-                if r < PBKDF2_MIN_NIST_COST {
-                    PBKDF2_MIN_NIST_COST
-                } else {
-                    r
-                }
+            match Password::bench_argon2id(next.clone()) {
+                Some(bt) if bt <= t => params = next,
+                _ => break,
             }
-            None => PBKDF2_MIN_NIST_COST,
-        };
+        }
+
+        debug!(
+            m_cost = params.m_cost(),
+            t_cost = params.t_cost(),
+            "argon2id parameters selected"
+        );
+        params
+    }
 
-        CryptoPolicy { pbkdf2_cost: r }
+    pub fn time_target(t: Duration) -> Self {
+        CryptoPolicy {
+            argon2id_params: Self::argon2id_time_target(t),
+        }
     }
 }
 
-// Why PBKDF2? Rust's bcrypt has a number of hardcodings like max pw len of 72
-// I don't really feel like adding in so many restrictions, so I'll use
-// pbkdf2 in openssl because it doesn't have the same limits.
+// New passwords use argon2id, as it is memory hard. Everything else is either a
+// hash created by an older release, or imported, and is upgraded on next use.
 #[derive(Clone, Debug, PartialEq)]
-#[allow(non_camel_case_types)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 enum Kdf {
+    ARGON2ID {
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+        version: u32,
+        salt: Vec<u8>,
+        key: Vec<u8>,
+    },
+
+    // Legacy and imported types, will upgrade to the above.
     //     cost, salt,   hash
     PBKDF2(usize, Vec<u8>, Vec<u8>),
-
-    // Imported types, will upgrade to the above.
     //         cost,   salt,    hash
     PBKDF2_SHA1(usize, Vec<u8>, Vec<u8>),
     //           cost,   salt,    hash
@@ -141,6 +231,11 @@ enum Kdf {
     SSHA512(Vec<u8>, Vec<u8>),
     //     hash
     NT_MD4(Vec<u8>),
+    // The full crypt(3) or phc string, as these are verified by their own crates.
+    BCRYPT(String),
+    SHA256_CRYPT(String),
+    SHA512_CRYPT(String),
+    ARGON2_PHC(String),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -168,6 +263,28 @@ impl TryFrom<DbPasswordV1> for Password {
             DbPasswordV1::NT_MD4(h) => Ok(Password {
                 material: Kdf::NT_MD4(h),
             }),
+            DbPasswordV1::BCRYPT(h) => Ok(Password {
+                material: Kdf::BCRYPT(h),
+            }),
+            DbPasswordV1::SHA256_CRYPT(h) => Ok(Password {
+                material: Kdf::SHA256_CRYPT(h),
+            }),
+            DbPasswordV1::SHA512_CRYPT(h) => Ok(Password {
+                material: Kdf::SHA512_CRYPT(h),
+            }),
+            DbPasswordV1::ARGON2_PHC(h) => Ok(Password {
+                material: Kdf::ARGON2_PHC(h),
+            }),
+            DbPasswordV1::ARGON2ID { m, t, p, v, s, k } => Ok(Password {
+                material: Kdf::ARGON2ID {
+                    m_cost: m,
+                    t_cost: t,
+                    p_cost: p,
+                    version: v,
+                    salt: s,
+                    key: k,
+                },
+            }),
         }
     }
 }
@@ -192,6 +309,35 @@ impl TryFrom<&ReplPasswordV1> for Password {
             ReplPasswordV1::NT_MD4 { hash } => Ok(Password {
                 material: Kdf::NT_MD4(hash.0.clone()),
             }),
+            ReplPasswordV1::BCRYPT { hash } => Ok(Password {
+                material: Kdf::BCRYPT(hash.clone()),
+            }),
+            ReplPasswordV1::SHA256_CRYPT { hash } => Ok(Password {
+                material: Kdf::SHA256_CRYPT(hash.clone()),
+            }),
+            ReplPasswordV1::SHA512_CRYPT { hash } => Ok(Password {
+                material: Kdf::SHA512_CRYPT(hash.clone()),
+            }),
+            ReplPasswordV1::ARGON2_PHC { hash } => Ok(Password {
+                material: Kdf::ARGON2_PHC(hash.clone()),
+            }),
+            ReplPasswordV1::ARGON2ID {
+                m_cost,
+                t_cost,
+                p_cost,
+                version,
+                salt,
+                key,
+            } => Ok(Password {
+                material: Kdf::ARGON2ID {
+                    m_cost: *m_cost,
+                    t_cost: *t_cost,
+                    p_cost: *p_cost,
+                    version: *version,
+                    salt: salt.0.clone(),
+                    key: key.0.clone(),
+                },
+            }),
         }
     }
 }
@@ -219,6 +365,35 @@ macro_rules! ab64_to_b64 {
     }};
 }
 
+// sha-crypt is $5$ or $6$, optionally rounds=N, then a salt of at most 16 characters
+// and the hash. Everything else is checked when the hash is verified.
+fn valid_sha_crypt(value: &str, hash_len: usize) -> bool {
+    let parts: Vec<&str> = value.split('$').collect();
+    let (salt, hash) = match parts.as_slice() {
+        ["", _, rounds, salt, hash] => {
+            match rounds.strip_prefix("rounds=").map(|r| r.parse::<u32>()) {
+                Some(Ok(rounds)) if rounds <= SHA_CRYPT_MAX_ROUNDS => (salt, hash),
+                Some(Ok(rounds)) => {
+                    warn!(rounds, "sha-crypt rounds exceed the allowed maximum");
+                    return false;
+                }
+                _ => return false,
+            }
+        }
+        ["", _, salt, hash] => (salt, hash),
+        _ => return false,
+    };
+    salt.len() <= SHA_CRYPT_MAX_SALT_LEN && hash.len() == hash_len
+}
+
+// The costs of a stored argon2 hash are chosen by whoever made it, so refuse any that would
+// let a single authentication exhaust the server.
+fn argon2_costs_in_bounds(m_cost: u32, t_cost: u32, p_cost: u32) -> bool {
+    m_cost <= ARGON2_MAX_IMPORT_RAM_KIB
+        && t_cost <= ARGON2_MAX_T_COST
+        && p_cost <= ARGON2_MAX_P_COST
+}
+
 impl TryFrom<&str> for Password {
     type Error = ();
 
@@ -295,6 +470,76 @@ impl TryFrom<&str> for Password {
             });
         }
 
+        // Test for argon2 in phc string format - $argon2id$v=19$m=65536,t=2,p=1$salt$hash
+        // OpenLDAP prefixes the same format with {ARGON2}. These are kept as the phc string
+        // until they are upgraded, even for argon2id, as the parameters are not our own.
+        let argon2_phc = value.strip_prefix("{ARGON2}").unwrap_or(value);
+        if argon2_phc.starts_with("$argon2") {
+            let phc = PasswordHash::new(argon2_phc).map_err(|e| {
+                error!(?e, "Invalid argon2 phc string");
+            })?;
+            if Algorithm::new(phc.algorithm.as_str()).is_err()
+                || phc.salt.is_none()
+                || phc.hash.is_none()
+            {
+                warn!("argon2 phc string found but invalid algorithm or missing elements?");
+                return Err(());
+            }
+            let params = Params::try_from(&phc).map_err(|e| {
+                error!(?e, "Invalid argon2 parameters");
+            })?;
+            if !argon2_costs_in_bounds(params.m_cost(), params.t_cost(), params.p_cost()) {
+                warn!(
+                    m_cost = params.m_cost(),
+                    t_cost = params.t_cost(),
+                    p_cost = params.p_cost(),
+                    "argon2 costs exceed the allowed maximum"
+                );
+                return Err(());
+            }
+            return Ok(Password {
+                material: Kdf::ARGON2_PHC(argon2_phc.to_string()),
+            });
+        }
+
+        // Test crypt(3) formats, such as from /etc/shadow.
+        if value.starts_with("$2a$") || value.starts_with("$2b$") || value.starts_with("$2y$") {
+            if value.len() != BCRYPT_HASH_LEN {
+                return Err(());
+            }
+            let parts = value.parse::<HashParts>().map_err(|e| {
+                error!(?e, "Invalid bcrypt hash");
+            })?;
+            if parts.get_cost() > BCRYPT_MAX_COST {
+                warn!(
+                    cost = parts.get_cost(),
+                    "bcrypt cost exceeds the allowed maximum"
+                );
+                return Err(());
+            }
+            return Ok(Password {
+                material: Kdf::BCRYPT(value.to_string()),
+            });
+        }
+
+        if value.starts_with("$5$") {
+            if !valid_sha_crypt(value, SHA256_CRYPT_HASH_LEN) {
+                return Err(());
+            }
+            return Ok(Password {
+                material: Kdf::SHA256_CRYPT(value.to_string()),
+            });
+        }
+
+        if value.starts_with("$6$") {
+            if !valid_sha_crypt(value, SHA512_CRYPT_HASH_LEN) {
+                return Err(());
+            }
+            return Ok(Password {
+                material: Kdf::SHA512_CRYPT(value.to_string()),
+            });
+        }
+
         // Test for OpenLDAP formats
         if value.starts_with("{PBKDF2}")
             || value.starts_with("{PBKDF2-SHA1}")
@@ -373,53 +618,87 @@ impl TryFrom<&str> for Password {
 }
 
 impl Password {
-    fn bench_pbkdf2(pbkdf2_cost: usize) -> Option<Duration> {
+    fn bench_argon2id(params: Params) -> Option<Duration> {
         let mut rng = rand::thread_rng();
-        let salt: Vec<u8> = (0..PBKDF2_SALT_LEN).map(|_| rng.gen()).collect();
-        let input: Vec<u8> = (0..PBKDF2_SALT_LEN).map(|_| rng.gen()).collect();
-        // This is 512 bits of output
-        let mut key: Vec<u8> = (0..PBKDF2_KEY_LEN).map(|_| 0).collect();
+        let salt: Vec<u8> = (0..ARGON2_SALT_LEN).map(|_| rng.gen()).collect();
+        let input: Vec<u8> = (0..ARGON2_SALT_LEN).map(|_| rng.gen()).collect();
+        let mut key: Vec<u8> = (0..ARGON2_KEY_LEN).map(|_| 0).collect();
+
+        let argon = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
 
         let start = Instant::now();
-        pbkdf2_hmac(
-            input.as_slice(),
-            salt.as_slice(),
-            pbkdf2_cost,
-            MessageDigest::sha256(),
-            key.as_mut_slice(),
-        )
-        .ok()?;
+        argon
+            .hash_password_into(input.as_slice(), salt.as_slice(), key.as_mut_slice())
+            .ok()?;
         let end = Instant::now();
 
         end.checked_duration_since(start)
     }
 
-    fn new_pbkdf2(pbkdf2_cost: usize, cleartext: &str) -> Result<Kdf, OperationError> {
+    fn new_argon2id(params: &Params, cleartext: &str) -> Result<Kdf, OperationError> {
         let mut rng = rand::thread_rng();
-        let salt: Vec<u8> = (0..PBKDF2_SALT_LEN).map(|_| rng.gen()).collect();
-        // This is 512 bits of output
-        let mut key: Vec<u8> = (0..PBKDF2_KEY_LEN).map(|_| 0).collect();
-
-        pbkdf2_hmac(
-            cleartext.as_bytes(),
-            salt.as_slice(),
-            pbkdf2_cost,
-            MessageDigest::sha256(),
-            key.as_mut_slice(),
-        )
-        .map(|()| {
-            // Turn key to a vec.
-            Kdf::PBKDF2(pbkdf2_cost, salt, key)
-        })
-        .map_err(|_| OperationError::CryptographyError)
+        let salt: Vec<u8> = (0..ARGON2_SALT_LEN).map(|_| rng.gen()).collect();
+        let mut key: Vec<u8> = (0..ARGON2_KEY_LEN).map(|_| 0).collect();
+
+        let argon = Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone());
+
+        argon
+            .hash_password_into(cleartext.as_bytes(), salt.as_slice(), key.as_mut_slice())
+            .map(|()| Kdf::ARGON2ID {
+                m_cost: params.m_cost(),
+                t_cost: params.t_cost(),
+                p_cost: params.p_cost(),
+                version: ARGON2_VERSION,
+                salt,
+                key,
+            })
+            .map_err(|e| {
+                error!(?e, "Unable to hash password with argon2id");
+                OperationError::CryptographyError
+            })
     }
 
     pub fn new(policy: &CryptoPolicy, cleartext: &str) -> Result<Self, OperationError> {
-        Self::new_pbkdf2(policy.pbkdf2_cost, cleartext).map(|material| Password { material })
+        Self::new_argon2id(&policy.argon2id_params, cleartext).map(|material| Password { material })
     }
 
     pub fn verify(&self, cleartext: &str) -> Result<bool, OperationError> {
         match &self.material {
+            Kdf::ARGON2ID {
+                m_cost,
+                t_cost,
+                p_cost,
+                version,
+                salt,
+                key,
+            } => {
+                if !argon2_costs_in_bounds(*m_cost, *t_cost, *p_cost) {
+                    error!("argon2 costs exceed the allowed maximum");
+                    return Err(OperationError::CryptographyError);
+                }
+                let version = Version::try_from(*version).map_err(|e| {
+                    error!(?e, "Unsupported argon2 version");
+                    OperationError::CryptographyError
+                })?;
+                let params =
+                    Params::new(*m_cost, *t_cost, *p_cost, Some(key.len())).map_err(|e| {
+                        error!(?e, "Invalid argon2 parameters");
+                        OperationError::CryptographyError
+                    })?;
+                let argon = Argon2::new(Algorithm::Argon2id, version, params);
+
+                let mut chal_key: Vec<u8> = (0..key.len()).map(|_| 0).collect();
+                argon
+                    .hash_password_into(cleartext.as_bytes(), salt.as_slice(), &mut chal_key)
+                    .map_err(|e| {
+                        error!(?e, "Unable to hash password with argon2id");
+                        OperationError::CryptographyError
+                    })
+                    .map(|()| {
+                        // Actually compare the outputs.
+                        &chal_key == key
+                    })
+            }
             Kdf::PBKDF2(cost, salt, key) => {
                 // We have to get the number of bits to derive from our stored hash
                 // as some imported hash types may have variable lengths
@@ -480,6 +759,56 @@ impl Password {
                 let r = hasher.finish();
                 Ok(key == &(r.to_vec()))
             }
+            Kdf::BCRYPT(hash) => {
+                let parts = hash.parse::<HashParts>().map_err(|e| {
+                    error!(?e, "Invalid bcrypt hash");
+                    OperationError::CryptographyError
+                })?;
+                if parts.get_cost() > BCRYPT_MAX_COST {
+                    error!("bcrypt cost exceeds the allowed maximum");
+                    return Err(OperationError::CryptographyError);
+                }
+                bcrypt::verify(cleartext, hash).map_err(|e| {
+                    error!(?e, "Unable to verify bcrypt hash");
+                    OperationError::CryptographyError
+                })
+            }
+            Kdf::SHA256_CRYPT(hash) => {
+                if !valid_sha_crypt(hash, SHA256_CRYPT_HASH_LEN) {
+                    error!("Invalid sha256-crypt hash");
+                    return Err(OperationError::CryptographyError);
+                }
+                Ok(sha_crypt::sha256_check(cleartext, hash).is_ok())
+            }
+            Kdf::SHA512_CRYPT(hash) => {
+                if !valid_sha_crypt(hash, SHA512_CRYPT_HASH_LEN) {
+                    error!("Invalid sha512-crypt hash");
+                    return Err(OperationError::CryptographyError);
+                }
+                Ok(sha_crypt::sha512_check(cleartext, hash).is_ok())
+            }
+            Kdf::ARGON2_PHC(hash) => {
+                let phc = PasswordHash::new(hash).map_err(|e| {
+                    error!(?e, "Invalid argon2 phc string");
+                    OperationError::CryptographyError
+                })?;
+                let params = Params::try_from(&phc).map_err(|e| {
+                    error!(?e, "Invalid argon2 parameters");
+                    OperationError::CryptographyError
+                })?;
+                if !argon2_costs_in_bounds(params.m_cost(), params.t_cost(), params.p_cost()) {
+                    error!("argon2 costs exceed the allowed maximum");
+                    return Err(OperationError::CryptographyError);
+                }
+                match Argon2::default().verify_password(cleartext.as_bytes(), &phc) {
+                    Ok(()) => Ok(true),
+                    Err(password_hash::Error::Password) => Ok(false),
+                    Err(e) => {
+                        error!(?e, "Unable to verify argon2 hash");
+                        Err(OperationError::CryptographyError)
+                    }
+                }
+            }
             Kdf::NT_MD4(key) => {
                 // We need to get the cleartext to utf16le for reasons.
                 let clear_utf16le: Vec<u8> = cleartext
@@ -519,6 +848,25 @@ impl Password {
             }
             Kdf::SSHA512(salt, hash) => DbPasswordV1::SSHA512(salt.clone(), hash.clone()),
             Kdf::NT_MD4(hash) => DbPasswordV1::NT_MD4(hash.clone()),
+            Kdf::BCRYPT(hash) => DbPasswordV1::BCRYPT(hash.clone()),
+            Kdf::SHA256_CRYPT(hash) => DbPasswordV1::SHA256_CRYPT(hash.clone()),
+            Kdf::SHA512_CRYPT(hash) => DbPasswordV1::SHA512_CRYPT(hash.clone()),
+            Kdf::ARGON2_PHC(hash) => DbPasswordV1::ARGON2_PHC(hash.clone()),
+            Kdf::ARGON2ID {
+                m_cost,
+                t_cost,
+                p_cost,
+                version,
+                salt,
+                key,
+            } => DbPasswordV1::ARGON2ID {
+                m: *m_cost,
+                t: *t_cost,
+                p: *p_cost,
+                v: *version,
+                s: salt.clone(),
+                k: key.clone(),
+            },
         }
     }
 
@@ -546,17 +894,76 @@ impl Password {
             Kdf::NT_MD4(hash) => ReplPasswordV1::NT_MD4 {
                 hash: hash.clone().into(),
             },
+            Kdf::BCRYPT(hash) => ReplPasswordV1::BCRYPT { hash: hash.clone() },
+            Kdf::SHA256_CRYPT(hash) => ReplPasswordV1::SHA256_CRYPT { hash: hash.clone() },
+            Kdf::SHA512_CRYPT(hash) => ReplPasswordV1::SHA512_CRYPT { hash: hash.clone() },
+            Kdf::ARGON2_PHC(hash) => ReplPasswordV1::ARGON2_PHC { hash: hash.clone() },
+            Kdf::ARGON2ID {
+                m_cost,
+                t_cost,
+                p_cost,
+                version,
+                salt,
+                key,
+            } => ReplPasswordV1::ARGON2ID {
+                m_cost: *m_cost,
+                t_cost: *t_cost,
+                p_cost: *p_cost,
+                version: *version,
+                salt: salt.clone().into(),
+                key: key.clone().into(),
+            },
         }
     }
 
+    /// The name of the key derivation function protecting this password.
+    pub fn kdf_name(&self) -> &'static str {
+        match &self.material {
+            Kdf::ARGON2ID { .. } => "argon2id",
+            Kdf::PBKDF2(_, _, _) => "pbkdf2_sha256",
+            Kdf::PBKDF2_SHA1(_, _, _) => "pbkdf2_sha1",
+            Kdf::PBKDF2_SHA512(_, _, _) => "pbkdf2_sha512",
+            Kdf::SSHA512(_, _) => "ssha512",
+            Kdf::NT_MD4(_) => "nt_md4",
+            Kdf::BCRYPT(_) => "bcrypt",
+            Kdf::SHA256_CRYPT(_) => "sha256_crypt",
+            Kdf::SHA512_CRYPT(_) => "sha512_crypt",
+            Kdf::ARGON2_PHC(_) => "argon2_imported",
+        }
+    }
+
+    /// If this password is protected by anything other than argon2id.
+    pub fn is_legacy(&self) -> bool {
+        !matches!(self.material, Kdf::ARGON2ID { .. })
+    }
+
     pub fn requires_upgrade(&self) -> bool {
         match &self.material {
-            Kdf::PBKDF2_SHA512(cost, salt, hash) | Kdf::PBKDF2(cost, salt, hash) => {
-                *cost < PBKDF2_MIN_NIST_COST
-                    || salt.len() < PBKDF2_MIN_NIST_SALT_LEN
-                    || hash.len() < PBKDF2_MIN_NIST_KEY_LEN
+            Kdf::ARGON2ID {
+                m_cost,
+                t_cost,
+                p_cost,
+                salt,
+                key,
+                ..
+            } => {
+                *m_cost < ARGON2_MIN_RAM_KIB
+                    || *t_cost < ARGON2_MIN_T_COST
+                    || *p_cost != ARGON2_P_COST
+                    || !argon2_costs_in_bounds(*m_cost, *t_cost, *p_cost)
+                    || salt.len() < ARGON2_MIN_SALT_LEN
+                    || key.len() < ARGON2_MIN_KEY_LEN
             }
-            Kdf::PBKDF2_SHA1(_, _, _) | Kdf::SSHA512(_, _) | Kdf::NT_MD4(_) => true,
+            // All other kdfs are upgraded to argon2id once we have the cleartext.
+            Kdf::PBKDF2_SHA512(_, _, _)
+            | Kdf::PBKDF2(_, _, _)
+            | Kdf::PBKDF2_SHA1(_, _, _)
+            | Kdf::SSHA512(_, _)
+            | Kdf::NT_MD4(_)
+            | Kdf::BCRYPT(_)
+            | Kdf::SHA256_CRYPT(_)
+            | Kdf::SHA512_CRYPT(_)
+            | Kdf::ARGON2_PHC(_) => true,
         }
     }
 }
@@ -627,7 +1034,7 @@ mod tests {
         let im_pw = "{PBKDF2-SHA256}10000$henZGfPWw79Cs8ORDeVNrQ$1dTJy73v6n3bnTmTZFghxHXHLsAzKaAy8SksDfZBPIw";
         let password = "password";
         let r = Password::try_from(im_pw).expect("Failed to parse");
-        assert!(!r.requires_upgrade());
+        assert!(r.requires_upgrade());
         assert!(r.verify(password).unwrap_or(false));
     }
 
@@ -636,21 +1043,177 @@ mod tests {
         let im_pw = "{PBKDF2-SHA512}10000$Je1Uw19Bfv5lArzZ6V3EPw$g4T/1sqBUYWl9o93MVnyQ/8zKGSkPbKaXXsT8WmysXQJhWy8MRP2JFudSL.N9RklQYgDPxPjnfum/F2f/TrppA";
         let password = "password";
         let r = Password::try_from(im_pw).expect("Failed to parse");
-        assert!(!r.requires_upgrade());
+        assert!(r.requires_upgrade());
         assert!(r.verify(password).unwrap_or(false));
     }
 
-    /*
-    // Not supported in openssl, may need an external crate.
     #[test]
     fn test_password_from_openldap_argon2() {
-        let im_pw = "{ARGON2}$argon2id$v=19$m=65536,t=2,p=1$IyTQMsvzB2JHDiWx8fq7Ew$VhYOA7AL0kbRXI5g2kOyyp8St1epkNj7WZyUY4pAIQQ"
+        let im_pw = "{ARGON2}$argon2id$v=19$m=65536,t=2,p=1$IyTQMsvzB2JHDiWx8fq7Ew$VhYOA7AL0kbRXI5g2kOyyp8St1epkNj7WZyUY4pAIQQ";
         let password = "password";
         let r = Password::try_from(im_pw).expect("Failed to parse");
         assert!(r.requires_upgrade());
+        assert!(r.is_legacy());
         assert!(r.verify(password).unwrap_or(false));
+        assert!(!r.verify("password1").unwrap_or(true));
+    }
+
+    #[test]
+    fn test_password_from_argon2id_phc() {
+        let im_pw = "$argon2id$v=19$m=65536,t=2,p=1$IyTQMsvzB2JHDiWx8fq7Ew$VhYOA7AL0kbRXI5g2kOyyp8St1epkNj7WZyUY4pAIQQ";
+        let password = "password";
+        let r = Password::try_from(im_pw).expect("Failed to parse");
+        // Even argon2id is upgraded, as the parameters were chosen elsewhere.
+        assert!(r.requires_upgrade());
+        assert!(r.is_legacy());
+        assert!(r.kdf_name() == "argon2_imported");
+        assert!(r.verify(password).unwrap_or(false));
+        assert!(!r.verify("password1").unwrap_or(true));
+
+        // It must still be an import after a trip through the db and replication.
+        let db = Password::try_from(r.to_dbpasswordv1()).unwrap();
+        let repl = Password::try_from(&r.to_repl_v1()).unwrap();
+        assert!(db == r && repl == r);
+        assert!(db.requires_upgrade() && repl.requires_upgrade());
+
+        // Upgrading it gives our own argon2id.
+        let upgraded = Password::new(&CryptoPolicy::minimum(), password).unwrap();
+        assert!(!upgraded.requires_upgrade());
+        assert!(!upgraded.is_legacy());
+        assert!(upgraded.verify(password).unwrap_or(false));
+
+        // Common exports use more memory and lanes than we do, and are accepted.
+        let r = Password::try_from("$argon2id$v=19$m=102400,t=2,p=8$IyTQMsvzB2JHDiWx8fq7Ew$VhYOA7AL0kbRXI5g2kOyyp8St1epkNj7WZyUY4pAIQQ").expect("Failed to parse");
+        assert!(r.requires_upgrade());
+
+        assert!(
+            Password::try_from("$argon2id$v=19$m=65536,t=2,p=1$IyTQMsvzB2JHDiWx8fq7Ew").is_err()
+        );
+        assert!(Password::try_from("$argon2x$v=19$m=65536,t=2,p=1$IyTQMsvzB2JHDiWx8fq7Ew$VhYOA7AL0kbRXI5g2kOyyp8St1epkNj7WZyUY4pAIQQ").is_err());
+    }
+
+    #[test]
+    fn test_password_from_argon2i_phc() {
+        let im_pw = "$argon2i$v=19$m=65536,t=2,p=1$IyTQMsvzB2JHDiWx8fq7Ew$eHHREw6J+BY8eTQEcKzRlykNzmvQ6y+YCN7ypp6+XSk";
+        let password = "password";
+        let r = Password::try_from(im_pw).expect("Failed to parse");
+        assert!(r.requires_upgrade());
+        assert!(r.is_legacy());
+        assert!(r.verify(password).unwrap_or(false));
+        assert!(!r.verify("password1").unwrap_or(true));
+    }
+
+    #[test]
+    fn test_password_from_argon2_excessive_costs() {
+        // The hashes don't matter, as the costs are checked before anything is derived.
+        for im_pw in [
+            "$argon2id$v=19$m=4194304,t=2,p=1$IyTQMsvzB2JHDiWx8fq7Ew$VhYOA7AL0kbRXI5g2kOyyp8St1epkNj7WZyUY4pAIQQ",
+            "$argon2id$v=19$m=65536,t=1000,p=1$IyTQMsvzB2JHDiWx8fq7Ew$VhYOA7AL0kbRXI5g2kOyyp8St1epkNj7WZyUY4pAIQQ",
+            "$argon2id$v=19$m=65536,t=2,p=64$IyTQMsvzB2JHDiWx8fq7Ew$VhYOA7AL0kbRXI5g2kOyyp8St1epkNj7WZyUY4pAIQQ",
+            "$argon2id$v=19$m=524288,t=2,p=1$IyTQMsvzB2JHDiWx8fq7Ew$VhYOA7AL0kbRXI5g2kOyyp8St1epkNj7WZyUY4pAIQQ",
+            "{ARGON2}$argon2id$v=19$m=4194304,t=2,p=1$IyTQMsvzB2JHDiWx8fq7Ew$VhYOA7AL0kbRXI5g2kOyyp8St1epkNj7WZyUY4pAIQQ",
+            "$argon2i$v=19$m=4194304,t=2,p=1$IyTQMsvzB2JHDiWx8fq7Ew$eHHREw6J+BY8eTQEcKzRlykNzmvQ6y+YCN7ypp6+XSk",
+            "$argon2d$v=19$m=65536,t=1000,p=1$IyTQMsvzB2JHDiWx8fq7Ew$eHHREw6J+BY8eTQEcKzRlykNzmvQ6y+YCN7ypp6+XSk",
+        ] {
+            assert!(Password::try_from(im_pw).is_err());
+        }
+
+        // Nor can one that arrives from elsewhere be verified.
+        let r = Password::try_from(DbPasswordV1::ARGON2ID {
+            m: 4194304,
+            t: 2,
+            p: 1,
+            v: ARGON2_VERSION,
+            s: vec![0; ARGON2_SALT_LEN],
+            k: vec![0; ARGON2_KEY_LEN],
+        })
+        .expect("Failed to load");
+        assert!(r.requires_upgrade());
+        assert!(r.verify("password").is_err());
+    }
+
+    #[test]
+    fn test_password_from_bcrypt() {
+        let password = "password";
+        for im_pw in [
+            "$2b$05$abcdefghijklmnopqrstuuWG29KuyeAicPCJODk1zjyGvyQUU2awu",
+            "$2y$05$abcdefghijklmnopqrstuuWG29KuyeAicPCJODk1zjyGvyQUU2awu",
+        ] {
+            let r = Password::try_from(im_pw).expect("Failed to parse");
+            assert!(r.requires_upgrade());
+            assert!(r.verify(password).unwrap_or(false));
+            assert!(!r.verify("password1").unwrap_or(true));
+        }
+
+        assert!(Password::try_from("$2b$05$abcdefghijklmnopqrstuu").is_err());
+
+        // Excessive costs are refused, both on import and when loaded from elsewhere.
+        let im_pw = "$2b$31$abcdefghijklmnopqrstuuWG29KuyeAicPCJODk1zjyGvyQUU2awu";
+        assert!(Password::try_from(im_pw).is_err());
+        let r =
+            Password::try_from(DbPasswordV1::BCRYPT(im_pw.to_string())).expect("Failed to load");
+        assert!(r.verify(password).is_err());
+    }
+
+    #[test]
+    fn test_password_from_sha256_crypt() {
+        let password = "password";
+        for im_pw in [
+            "$5$saltsalt$gOjOtoMpVhru2uyjeJSEc/JaLQWOXMNmlOnj6T4AtC.",
+            "$5$rounds=10000$saltsalt$a6WJS3V6B3leg7T3.ELC5.vcUmHOyFDvLaurLBy.mc8",
+        ] {
+            let r = Password::try_from(im_pw).expect("Failed to parse");
+            assert!(r.requires_upgrade());
+            assert!(r.verify(password).unwrap_or(false));
+            assert!(!r.verify("password1").unwrap_or(true));
+        }
+
+        assert!(Password::try_from("$5$saltsalt$gOjOtoMpVhru2uyjeJSEc").is_err());
+        assert!(Password::try_from(
+            "$5$rounds=many$saltsalt$a6WJS3V6B3leg7T3.ELC5.vcUmHOyFDvLaurLBy.mc8"
+        )
+        .is_err());
+
+        // Excessive rounds are refused, both on import and when loaded from elsewhere.
+        let im_pw = "$5$rounds=999999999$saltsalt$a6WJS3V6B3leg7T3.ELC5.vcUmHOyFDvLaurLBy.mc8";
+        assert!(Password::try_from(im_pw).is_err());
+        let r = Password::try_from(DbPasswordV1::SHA256_CRYPT(im_pw.to_string()))
+            .expect("Failed to load");
+        assert!(r.verify(password).is_err());
+    }
+
+    #[test]
+    fn test_password_from_sha512_crypt() {
+        let password = "password";
+        for im_pw in [
+            "$6$saltsalt$qFmFH.bQmmtXzyBY0s9v7Oicd2z4XSIecDzlB5KiA2/jctKu9YterLp8wwnSq.qc.eoxqOmSuNp2xS0ktL3nh/",
+            "$6$rounds=10000$saltsalt$ZqOTO2O04D/DgwZlm.rZTgWxvBaIf4LQsZKtXFEu9UHJ4CvgmdLAGxKUzJ0mPO98OevETdY6oK/Oac6j2Axxq/",
+        ] {
+            let r = Password::try_from(im_pw).expect("Failed to parse");
+            assert!(r.requires_upgrade());
+            assert!(r.verify(password).unwrap_or(false));
+            assert!(!r.verify("password1").unwrap_or(true));
+
+            // These are stored as-is, so must survive the db and replication.
+            let db = Password::try_from(r.to_dbpasswordv1()).unwrap();
+            let repl = Password::try_from(&r.to_repl_v1()).unwrap();
+            assert!(db == r && repl == r);
+        }
+    }
+
+    #[test]
+    fn test_password_argon2id_roundtrip() {
+        let p = CryptoPolicy::minimum();
+        let c = Password::new(&p, "password").unwrap();
+        assert!(!c.requires_upgrade());
+        assert!(c.kdf_name() == "argon2id");
+
+        let db = Password::try_from(c.to_dbpasswordv1()).unwrap();
+        assert!(db.verify("password").unwrap());
+        let repl = Password::try_from(&c.to_repl_v1()).unwrap();
+        assert!(repl.verify("password").unwrap());
+        assert!(db == c && repl == c);
     }
-    */
 
     /*
      * wbrown - 20221104 - I tried to programmatically enable the legacy provider, but
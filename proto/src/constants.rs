@@ -4,3 +4,6 @@
 pub const DEFAULT_CLIENT_CONFIG_PATH: &str = "/etc/kanidm/config";
 /// The user-owned path for Kanidm client config
 pub const DEFAULT_CLIENT_CONFIG_PATH_HOME: &str = "~/.config/kanidm";
+/// Administrator defined schema attributes and classes must be named with this prefix, so
+/// that they can never collide with schema that a later release adds.
+pub const CUSTOM_SCHEMA_PREFIX: &str = "ext_";
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 use url::Url;
+use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 /// This is a description of a linked or connected application for a user. This is
@@ -13,3 +15,159 @@ pub enum AppLink {
         icon: Option<Url>,
     },
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A notification about a security relevant event on the account of a user, such as a change
+/// to its credentials. These are shown to the user in the UI.
+pub struct UserNotification {
+    pub id: Uuid,
+    pub subject: String,
+    pub body: String,
+    /// When the event happened.
+    #[serde(with = "time::serde::timestamp")]
+    pub time: OffsetDateTime,
+    /// If the notification has been sent to the mail address of the user.
+    pub delivered: bool,
+}
+
+/// A search of the audit log. Each condition that is set must match. Actors and targets may be
+/// given as a name, spn or uuid.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AuditLogQuery {
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    /// Only events at or after this time.
+    #[serde(default, with = "time::serde::timestamp::option")]
+    pub from: Option<OffsetDateTime>,
+    /// Only events before this time.
+    #[serde(default, with = "time::serde::timestamp::option")]
+    pub to: Option<OffsetDateTime>,
+    pub kind: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// An event from the audit log.
+pub struct AuditLogEntry {
+    pub id: i64,
+    #[serde(with = "time::serde::timestamp")]
+    pub time: OffsetDateTime,
+    pub kind: String,
+    /// The account that caused the event, if known.
+    pub actor: Option<Uuid>,
+    /// The entry that the event affected.
+    pub target: Uuid,
+    /// The full event, as recorded.
+    pub event: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+/// A client address or network that has recently failed to authenticate.
+pub struct SourceLockStatus {
+    /// An address, or a network in cidr notation.
+    pub source: String,
+    /// How many authentication failures have come from this source in the current window.
+    pub failures: u32,
+    /// If set, authentication from this source is refused until this time.
+    #[serde(default, with = "time::serde::timestamp::option")]
+    pub locked_until: Option<OffsetDateTime>,
+    /// If this source has been banned, rather than delayed.
+    pub banned: bool,
+}
+
+impl std::fmt::Display for SourceLockStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} failures={}", self.source, self.failures)?;
+        match self.locked_until {
+            Some(until) if self.banned => write!(f, " banned_until={}", until),
+            Some(until) => write!(f, " locked_until={}", until),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+/// How the passwords of accounts are currently hashed. Legacy hashes are upgraded as each
+/// account next authenticates, so this shows how far that has progressed.
+pub struct PasswordKdfReport {
+    /// How many accounts have a primary or unix password.
+    pub accounts: u64,
+    /// How many of those accounts have a password that still uses a legacy kdf.
+    pub legacy_accounts: u64,
+    /// How many passwords use each kdf.
+    pub kdfs: std::collections::BTreeMap<String, u64>,
+}
+
+impl std::fmt::Display for PasswordKdfReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "accounts with passwords: {}", self.accounts)?;
+        writeln!(
+            f,
+            "accounts with legacy password hashes: {}",
+            self.legacy_accounts
+        )?;
+        for (kdf, count) in self.kdfs.iter() {
+            writeln!(f, "  {}: {}", kdf, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+/// The definition of an administrator defined schema attribute.
+pub struct SchemaAttributeCreateRequest {
+    /// The name of the attribute, which must start with the custom schema prefix.
+    pub name: String,
+    pub description: String,
+    /// The syntax of the attribute's values, such as `UTF8STRING`. This can not be changed
+    /// once the attribute exists.
+    pub syntax: String,
+    #[serde(default)]
+    pub multivalue: bool,
+    #[serde(default)]
+    pub unique: bool,
+    /// The index types to maintain for the attribute, such as `EQUALITY`.
+    #[serde(default)]
+    pub index: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+/// Changes to an administrator defined schema attribute. Fields that are not set are left as
+/// they are.
+pub struct SchemaAttributeUpdateRequest {
+    pub description: Option<String>,
+    pub multivalue: Option<bool>,
+    pub unique: Option<bool>,
+    /// Replaces the index types of the attribute. Entries are reindexed when this changes.
+    pub index: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+/// The definition of an administrator defined schema class.
+pub struct SchemaClassCreateRequest {
+    /// The name of the class, which must start with the custom schema prefix.
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub may: Vec<String>,
+    #[serde(default)]
+    pub must: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+/// Changes to an administrator defined schema class. Fields that are not set are left as
+/// they are, and lists that are set replace the current list.
+pub struct SchemaClassUpdateRequest {
+    pub description: Option<String>,
+    pub may: Option<Vec<String>>,
+    pub must: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+/// Options for deleting an administrator defined schema attribute.
+pub struct SchemaDeleteQuery {
+    /// Remove the attribute from every entry and class that has it, rather than refusing the
+    /// delete while it is in use.
+    #[serde(default)]
+    pub purge: bool,
+}
@@ -4,6 +4,19 @@ use base64urlsafedata::Base64UrlSafeData;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+/// The token type identifier for an access token, as used in token exchange.
+/// <https://datatracker.ietf.org/doc/html/rfc8693#section-3>
+pub const OAUTH2_TOKEN_TYPE_ACCESS_TOKEN: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// The prefix of a `request_uri` that was issued by the pushed authorisation endpoint.
+/// <https://datatracker.ietf.org/doc/html/rfc9126#section-2.2>
+pub const OAUTH2_PAR_REQUEST_URI_PREFIX: &str = "urn:ietf:params:oauth:request_uri:";
+
+/// The client assertion type for a client authenticating with a signed jwt.
+/// <https://datatracker.ietf.org/doc/html/rfc7523#section-2.2>
+pub const OAUTH2_CLIENT_ASSERTION_TYPE_JWT_BEARER: &str =
+    "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum CodeChallengeMethod {
     // default to plain if not requested as S256. Reject the auth?
@@ -58,6 +71,51 @@ pub struct AuthorisationRequestOidc {
     pub acr: Option<String>,
 }
 
+/// An authorisation request where the parameters are not sent directly, but
+/// are either a reference to a pushed request, or contained in a signed
+/// request object. See <https://datatracker.ietf.org/doc/html/rfc9126#section-4>
+/// and <https://datatracker.ietf.org/doc/html/rfc9101#section-5>
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuthorisationRequestReference {
+    pub client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum AuthorisationRequestKind {
+    Request(AuthorisationRequest),
+    Reference(AuthorisationRequestReference),
+}
+
+impl From<AuthorisationRequest> for AuthorisationRequestKind {
+    fn from(req: AuthorisationRequest) -> AuthorisationRequestKind {
+        AuthorisationRequestKind::Request(req)
+    }
+}
+
+/// The body of a pushed authorisation request. This is the same as an authorisation
+/// request, but the client must authenticate.
+/// <https://datatracker.ietf.org/doc/html/rfc9126#section-2.1>
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PushedAuthorisationRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    #[serde(flatten)]
+    pub request: AuthorisationRequestKind,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PushedAuthorisationResponse {
+    /// The reference to pass as `request_uri` to the authorisation endpoint.
+    pub request_uri: String,
+    // seconds.
+    pub expires_in: u32,
+}
+
 /// When we request to authorise, it can either prompt us for consent,
 /// or it can immediately be granted due the past grant.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -93,6 +151,29 @@ pub enum GrantTypeReq {
         #[serde(skip_serializing_if = "Option::is_none")]
         scope: Option<BTreeSet<String>>,
     },
+    ClientCredentials {
+        // Space separated, as per the authorisation request.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        scope: Option<String>,
+    },
+    #[serde(rename = "urn:ietf:params:oauth:grant-type:device_code")]
+    DeviceCode {
+        // As issued in the DeviceAuthorisationResponse
+        device_code: String,
+    },
+    #[serde(rename = "urn:ietf:params:oauth:grant-type:token-exchange")]
+    TokenExchange {
+        // The access token that was issued to the requesting client.
+        subject_token: String,
+        subject_token_type: String,
+        // The client_id of the resource server the new token is for.
+        audience: String,
+        // Space separated, as per the authorisation request.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        scope: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        requested_token_type: Option<String>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -105,6 +186,11 @@ pub struct AccessTokenRequest {
     pub client_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub client_secret: Option<String>,
+    // Used instead of the client_secret for private_key_jwt and client_secret_jwt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_assertion_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_assertion: Option<String>,
 }
 
 impl From<GrantTypeReq> for AccessTokenRequest {
@@ -113,10 +199,40 @@ impl From<GrantTypeReq> for AccessTokenRequest {
             grant_type: req,
             client_id: None,
             client_secret: None,
+            client_assertion_type: None,
+            client_assertion: None,
         }
     }
 }
 
+/// A request from a client that can not perform a browser redirect, to begin
+/// the device authorisation flow. See
+/// <https://datatracker.ietf.org/doc/html/rfc8628#section-3.1>
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeviceAuthorisationRequest {
+    // Space separated, as per the authorisation request.
+    pub scope: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeviceAuthorisationResponse {
+    /// The code the client polls the token endpoint with.
+    pub device_code: String,
+    /// The code the user enters at the verification uri.
+    pub user_code: String,
+    pub verification_uri: Url,
+    /// The verification uri with the user code included, for display as a qr code or link.
+    pub verification_uri_complete: Url,
+    // seconds.
+    pub expires_in: u32,
+    /// The minimum time in seconds between polls of the token endpoint.
+    pub interval: u32,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TokenRevokeRequest {
     pub token: String,
@@ -128,6 +244,53 @@ pub struct TokenRevokeRequest {
 
 // The corresponding Response to a revoke request is empty body with 200.
 
+/// A request from a resource server to end the user's session.
+/// <https://openid.net/specs/openid-connect-rpinitiated-1_0.html#RPLogout>
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct EndSessionRequest {
+    /// An id_token we previously issued to the client, identifying the user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token_hint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Where to send the user after logout. This must be within the client's origin.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_logout_redirect_uri: Option<Url>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+}
+
+/// The metadata of a client being registered. Metadata we don't support is ignored.
+/// <https://datatracker.ietf.org/doc/html/rfc7591#section-2>
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ClientRegistrationRequest {
+    /// Kanidm validates redirects by origin, so these must all share the same origin.
+    pub redirect_uris: Vec<Url>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+    /// A space separated list of the scopes the client would like to be able to request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_endpoint_auth_method: Option<TokenEndpointAuthMethod>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backchannel_logout_uri: Option<Url>,
+}
+
+/// <https://datatracker.ietf.org/doc/html/rfc7591#section-3.2.1>
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ClientRegistrationResponse {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Our client secrets do not expire, so this is always 0.
+    pub client_secret_expires_at: u64,
+    /// Where the client can be read, updated or deleted by its registrant.
+    /// <https://datatracker.ietf.org/doc/html/rfc7592#section-2>
+    pub registration_client_uri: Url,
+    #[serde(flatten)]
+    pub metadata: ClientRegistrationRequest,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AccessTokenResponse {
     // Could be  Base64UrlSafeData
@@ -145,6 +308,9 @@ pub struct AccessTokenResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     /// Oidc puts the token here.
     pub id_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The type of the token issued in a token exchange.
+    pub issued_token_type: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -227,6 +393,11 @@ pub enum GrantType {
     #[serde(rename = "authorization_code")]
     AuthorisationCode,
     Implicit,
+    ClientCredentials,
+    #[serde(rename = "urn:ietf:params:oauth:grant-type:device_code")]
+    DeviceCode,
+    #[serde(rename = "urn:ietf:params:oauth:grant-type:token-exchange")]
+    TokenExchange,
 }
 
 fn grant_types_supported_default() -> Vec<GrantType> {
@@ -248,7 +419,7 @@ pub enum IdTokenSignAlg {
     RS256,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum TokenEndpointAuthMethod {
     ClientSecretPost,
@@ -310,6 +481,22 @@ pub struct OidcDiscoveryResponse {
     pub jwks_uri: Url,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub registration_endpoint: Option<Url>,
+    // https://datatracker.ietf.org/doc/html/rfc8628#section-4
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_authorization_endpoint: Option<Url>,
+    // https://datatracker.ietf.org/doc/html/rfc9126#section-5
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pushed_authorization_request_endpoint: Option<Url>,
+    #[serde(default)]
+    pub require_pushed_authorization_requests: bool,
+    // https://openid.net/specs/openid-connect-rpinitiated-1_0.html#OPMetadata
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_session_endpoint: Option<Url>,
+    // https://openid.net/specs/openid-connect-backchannel-1_0.html#BCSupport
+    #[serde(default)]
+    pub backchannel_logout_supported: bool,
+    #[serde(default)]
+    pub backchannel_logout_session_supported: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub scopes_supported: Option<Vec<String>>,
     // https://datatracker.ietf.org/doc/html/rfc6749#section-3.1.1
@@ -386,7 +573,12 @@ pub struct ErrorResponse {
 
 #[cfg(test)]
 mod tests {
-    use super::{AccessTokenRequest, GrantTypeReq};
+    use super::{
+        AccessTokenRequest, AuthorisationRequestKind, ClientRegistrationRequest,
+        ClientRegistrationResponse, GrantTypeReq, PushedAuthorisationRequest,
+        TokenEndpointAuthMethod, OAUTH2_CLIENT_ASSERTION_TYPE_JWT_BEARER,
+        OAUTH2_TOKEN_TYPE_ACCESS_TOKEN,
+    };
     use url::Url;
 
     #[test]
@@ -400,4 +592,134 @@ mod tests {
 
         println!("{:?}", serde_json::to_string(&atr).expect("JSON failure"));
     }
+
+    #[test]
+    fn test_oauth2_access_token_req_client_credentials() {
+        let atr: AccessTokenRequest = serde_json::from_str(
+            r#"{"grant_type":"client_credentials","scope":"read write","client_id":"test"}"#,
+        )
+        .expect("Failed to deserialise");
+
+        match atr.grant_type {
+            GrantTypeReq::ClientCredentials { scope } => {
+                assert!(scope.as_deref() == Some("read write"))
+            }
+            _ => panic!("Invalid grant type"),
+        }
+        assert!(atr.client_id.as_deref() == Some("test"));
+    }
+
+    #[test]
+    fn test_oauth2_access_token_req_client_assertion() {
+        let atr: AccessTokenRequest = serde_json::from_str(
+            r#"{"grant_type":"client_credentials","client_assertion_type":"urn:ietf:params:oauth:client-assertion-type:jwt-bearer","client_assertion":"abcd"}"#,
+        )
+        .expect("Failed to deserialise");
+
+        assert!(atr.client_secret.is_none());
+        assert!(
+            atr.client_assertion_type.as_deref() == Some(OAUTH2_CLIENT_ASSERTION_TYPE_JWT_BEARER)
+        );
+        assert!(atr.client_assertion.as_deref() == Some("abcd"));
+    }
+
+    #[test]
+    fn test_oauth2_access_token_req_device_code() {
+        let atr: AccessTokenRequest = serde_json::from_str(
+            r#"{"grant_type":"urn:ietf:params:oauth:grant-type:device_code","device_code":"abcd"}"#,
+        )
+        .expect("Failed to deserialise");
+
+        match atr.grant_type {
+            GrantTypeReq::DeviceCode { device_code } => assert!(device_code == "abcd"),
+            _ => panic!("Invalid grant type"),
+        }
+    }
+
+    #[test]
+    fn test_oauth2_access_token_req_token_exchange() {
+        let atr: AccessTokenRequest = serde_json::from_str(
+            r#"{"grant_type":"urn:ietf:params:oauth:grant-type:token-exchange","subject_token":"abcd","subject_token_type":"urn:ietf:params:oauth:token-type:access_token","audience":"downstream"}"#,
+        )
+        .expect("Failed to deserialise");
+
+        match atr.grant_type {
+            GrantTypeReq::TokenExchange {
+                subject_token,
+                subject_token_type,
+                audience,
+                scope,
+                requested_token_type,
+            } => {
+                assert!(subject_token == "abcd");
+                assert!(subject_token_type == OAUTH2_TOKEN_TYPE_ACCESS_TOKEN);
+                assert!(audience == "downstream");
+                assert!(scope.is_none());
+                assert!(requested_token_type.is_none());
+            }
+            _ => panic!("Invalid grant type"),
+        }
+    }
+
+    #[test]
+    fn test_oauth2_pushed_authorisation_req() {
+        let par: PushedAuthorisationRequest = serde_json::from_str(
+            r#"{"response_type":"code","client_id":"test","client_secret":"abcd","state":"123","redirect_uri":"https://demo.example.com/oauth2/result","scope":"openid"}"#,
+        )
+        .expect("Failed to deserialise");
+
+        assert!(par.client_secret.as_deref() == Some("abcd"));
+        match par.request {
+            AuthorisationRequestKind::Request(req) => {
+                assert!(req.client_id == "test");
+                assert!(!req.unknown_keys.contains_key("client_secret"));
+            }
+            _ => panic!("Invalid request kind"),
+        }
+
+        let akr: AuthorisationRequestKind = serde_json::from_str(
+            r#"{"client_id":"test","request_uri":"urn:ietf:params:oauth:request_uri:abcd"}"#,
+        )
+        .expect("Failed to deserialise");
+
+        match akr {
+            AuthorisationRequestKind::Reference(rf) => {
+                assert!(rf.client_id == "test");
+                assert!(
+                    rf.request_uri.as_deref() == Some("urn:ietf:params:oauth:request_uri:abcd")
+                );
+                assert!(rf.request.is_none());
+            }
+            _ => panic!("Invalid request kind"),
+        }
+    }
+
+    #[test]
+    fn test_oauth2_client_registration() {
+        let reg_req: ClientRegistrationRequest = serde_json::from_str(
+            r#"{"redirect_uris":["https://pr-1.review.example.com/oauth2/result"],"client_name":"Review","grant_types":["authorization_code"],"token_endpoint_auth_method":"client_secret_basic"}"#,
+        )
+        .expect("Failed to deserialise");
+
+        assert!(reg_req.redirect_uris.len() == 1);
+        assert!(reg_req.client_name.as_deref() == Some("Review"));
+        assert!(reg_req.scope.is_none());
+        assert!(
+            reg_req.token_endpoint_auth_method == Some(TokenEndpointAuthMethod::ClientSecretBasic)
+        );
+
+        // The metadata is at the top level of the response.
+        let reg_resp = ClientRegistrationResponse {
+            client_id: "test".to_string(),
+            client_secret: "abcd".to_string(),
+            client_secret_expires_at: 0,
+            registration_client_uri: Url::parse("https://idm.example.com/oauth2/register/test")
+                .unwrap(),
+            metadata: reg_req,
+        };
+        let value = serde_json::to_value(&reg_resp).expect("Failed to serialise");
+        assert!(value["client_id"] == "test");
+        assert!(value["client_name"] == "Review");
+        assert!(value["redirect_uris"][0] == "https://pr-1.review.example.com/oauth2/result");
+    }
 }
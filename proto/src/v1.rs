@@ -706,6 +706,7 @@ pub struct PasskeyDetail {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CredentialStatus {
     pub creds: Vec<CredentialDetail>,
+    pub devicekeys: Vec<PasskeyDetail>,
 }
 
 impl fmt::Display for CredentialStatus {
@@ -714,6 +715,13 @@ impl fmt::Display for CredentialStatus {
             writeln!(f, "---")?;
             cred.fmt(f)?;
         }
+        if !self.devicekeys.is_empty() {
+            writeln!(f, "---")?;
+            writeln!(f, "device keys:")?;
+            for dk in &self.devicekeys {
+                writeln!(f, " * {}", dk.tag)?;
+            }
+        }
         writeln!(f, "---")
     }
 }
@@ -754,6 +762,10 @@ pub enum Filter {
     Sub(String, String),
     #[serde(alias = "Pres")]
     Pres(String),
+    #[serde(alias = "LessThan")]
+    LessThan(String, String),
+    #[serde(alias = "GreaterThan")]
+    GreaterThan(String, String),
     #[serde(alias = "Or")]
     Or(Vec<Filter>),
     #[serde(alias = "And")]
@@ -862,6 +874,7 @@ pub enum AuthCredential {
     BackupCode(String),
     // Should this just be discoverable?
     Passkey(Box<PublicKeyCredential>),
+    DeviceKey(Box<PublicKeyCredential>),
 }
 
 impl fmt::Debug for AuthCredential {
@@ -873,6 +886,7 @@ impl fmt::Debug for AuthCredential {
             AuthCredential::SecurityKey(_) => write!(fmt, "SecurityKey(_)"),
             AuthCredential::BackupCode(_) => write!(fmt, "BackupCode(_)"),
             AuthCredential::Passkey(_) => write!(fmt, "Passkey(_)"),
+            AuthCredential::DeviceKey(_) => write!(fmt, "DeviceKey(_)"),
         }
     }
 }
@@ -884,6 +898,7 @@ pub enum AuthMech {
     Password,
     PasswordMfa,
     Passkey,
+    DeviceKey,
 }
 
 impl PartialEq for AuthMech {
@@ -899,6 +914,7 @@ impl fmt::Display for AuthMech {
             AuthMech::Password => write!(f, "Password"),
             AuthMech::PasswordMfa => write!(f, "TOTP/Backup Code and Password"),
             AuthMech::Passkey => write!(f, "Passkey"),
+            AuthMech::DeviceKey => write!(f, "Device Key"),
         }
     }
 }
@@ -945,6 +961,7 @@ pub enum AuthAllowed {
     Totp,
     SecurityKey(RequestChallengeResponse),
     Passkey(RequestChallengeResponse),
+    DeviceKey(RequestChallengeResponse),
 }
 
 impl PartialEq for AuthAllowed {
@@ -973,8 +990,10 @@ impl Ord for AuthAllowed {
                 (AuthAllowed::SecurityKey(_), _) => Ordering::Less,
                 (_, AuthAllowed::SecurityKey(_)) => Ordering::Greater,
                 (AuthAllowed::Passkey(_), _) => Ordering::Less,
+                (_, AuthAllowed::Passkey(_)) => Ordering::Greater,
+                (AuthAllowed::DeviceKey(_), _) => Ordering::Less,
                 // Unreachable
-                // (_, AuthAllowed::Passkey(_)) => Ordering::Greater,
+                // (_, AuthAllowed::DeviceKey(_)) => Ordering::Greater,
             }
         }
     }
@@ -995,6 +1014,7 @@ impl fmt::Display for AuthAllowed {
             AuthAllowed::Totp => write!(f, "TOTP"),
             AuthAllowed::SecurityKey(_) => write!(f, "Security Token"),
             AuthAllowed::Passkey(_) => write!(f, "Passkey"),
+            AuthAllowed::DeviceKey(_) => write!(f, "Device Key"),
         }
     }
 }
@@ -1126,6 +1146,9 @@ pub enum CURequest {
     PasskeyInit,
     PasskeyFinish(String, RegisterPublicKeyCredential),
     PasskeyRemove(Uuid),
+    DeviceKeyInit,
+    DeviceKeyFinish(String, RegisterPublicKeyCredential),
+    DeviceKeyRemove(Uuid),
 }
 
 impl fmt::Debug for CURequest {
@@ -1143,6 +1166,9 @@ impl fmt::Debug for CURequest {
             CURequest::PasskeyInit => "CURequest::PasskeyInit",
             CURequest::PasskeyFinish(_, _) => "CURequest::PasskeyFinish",
             CURequest::PasskeyRemove(_) => "CURequest::PasskeyRemove",
+            CURequest::DeviceKeyInit => "CURequest::DeviceKeyInit",
+            CURequest::DeviceKeyFinish(_, _) => "CURequest::DeviceKeyFinish",
+            CURequest::DeviceKeyRemove(_) => "CURequest::DeviceKeyRemove",
         };
         writeln!(f, "{}", t)
     }
@@ -1157,6 +1183,7 @@ pub enum CURegState {
     TotpInvalidSha1,
     BackupCodes(Vec<String>),
     Passkey(CreationChallengeResponse),
+    DeviceKey(CreationChallengeResponse),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1166,6 +1193,7 @@ pub struct CUStatus {
     pub can_commit: bool,
     pub primary: Option<CredentialDetail>,
     pub passkeys: Vec<PasskeyDetail>,
+    pub devicekeys: Vec<PasskeyDetail>,
     pub mfaregstate: CURegState,
 }
 
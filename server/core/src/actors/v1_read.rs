@@ -4,7 +4,9 @@ use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use kanidm_proto::internal::AppLink;
+use kanidm_proto::internal::{
+    AppLink, AuditLogEntry, AuditLogQuery, PasswordKdfReport, SourceLockStatus, UserNotification,
+};
 use kanidm_proto::v1::{
     ApiToken, AuthIssueSession, AuthRequest, BackupCodesView, CURequest, CUSessionToken, CUStatus,
     CredentialStatus, Entry as ProtoEntry, OperationError, RadiusAuthToken, SearchRequest,
@@ -15,21 +17,23 @@ use regex::Regex;
 use tracing::{error, info, instrument, trace};
 use uuid::Uuid;
 
-use kanidmd_lib::be::BackendTransaction;
+use kanidmd_lib::be::{BackendTransaction, BackupOptions};
 use kanidmd_lib::prelude::*;
 use kanidmd_lib::{
     event::{OnlineBackupEvent, SearchEvent, SearchResult, WhoamiResult},
     filter::{Filter, FilterInvalid},
     idm::account::ListUserAuthTokenEvent,
+    idm::audit::AuditLog,
     idm::credupdatesession::CredentialUpdateSessionToken,
     idm::event::{
         AuthEvent, AuthResult, CredentialStatusEvent, RadiusAuthTokenEvent, ReadBackupCodeEvent,
         UnixGroupTokenEvent, UnixUserAuthEvent, UnixUserTokenEvent,
     },
-    idm::ldap::{LdapBoundToken, LdapResponseState, LdapServer},
+    idm::ldap::{LdapBoundToken, LdapPagedSearch, LdapResponseState, LdapServer, LdapWriteOps},
     idm::oauth2::{
-        AccessTokenIntrospectRequest, AccessTokenIntrospectResponse, AuthorisationRequest,
-        AuthoriseResponse, JwkKeySet, Oauth2Error, OidcDiscoveryResponse, OidcToken,
+        AccessTokenIntrospectRequest, AccessTokenIntrospectResponse, AuthorisationRequestKind,
+        AuthoriseResponse, ClientRegistrationResponse, JwkKeySet, Oauth2Error,
+        OidcDiscoveryResponse, OidcToken,
     },
     idm::server::{IdmServer, IdmServerTransaction},
     idm::serviceaccount::ListApiTokenEvent,
@@ -40,16 +44,25 @@ use kanidmd_lib::{
 pub struct QueryServerReadV1 {
     pub(crate) idms: Arc<IdmServer>,
     ldap: Arc<LdapServer>,
+    audit_log: Arc<AuditLog>,
 }
 
 impl QueryServerReadV1 {
-    pub fn new(idms: Arc<IdmServer>, ldap: Arc<LdapServer>) -> Self {
+    pub fn new(idms: Arc<IdmServer>, ldap: Arc<LdapServer>, audit_log: Arc<AuditLog>) -> Self {
         info!("Starting query server v1 worker ...");
-        QueryServerReadV1 { idms, ldap }
+        QueryServerReadV1 {
+            idms,
+            ldap,
+            audit_log,
+        }
     }
 
-    pub fn start_static(idms: Arc<IdmServer>, ldap: Arc<LdapServer>) -> &'static Self {
-        let x = Box::new(QueryServerReadV1::new(idms, ldap));
+    pub fn start_static(
+        idms: Arc<IdmServer>,
+        ldap: Arc<LdapServer>,
+        audit_log: Arc<AuditLog>,
+    ) -> &'static Self {
+        let x = Box::new(QueryServerReadV1::new(idms, ldap, audit_log));
 
         let x_ref = Box::leak(x);
         &(*x_ref)
@@ -202,6 +215,7 @@ impl QueryServerReadV1 {
         msg: OnlineBackupEvent,
         outpath: &str,
         versions: usize,
+        options: &BackupOptions,
     ) -> Result<(), OperationError> {
         trace!(eventid = ?msg.eventid, "Begin online backup event");
 
@@ -213,7 +227,12 @@ impl QueryServerReadV1 {
             }
         };
         let timestamp = now.format(&Rfc3339).unwrap();
-        let dest_file = format!("{}/backup-{}.json", outpath, timestamp);
+        let dest_file = format!(
+            "{}/backup-{}.{}",
+            outpath,
+            timestamp,
+            options.file_extension()
+        );
 
         if Path::new(&dest_file).exists() {
             error!(
@@ -229,7 +248,7 @@ impl QueryServerReadV1 {
             idms_prox_read
                 .qs_read
                 .get_be_txn()
-                .backup(&dest_file)
+                .backup(&dest_file, options)
                 .map(|()| {
                     info!("Online backup created {} successfully", dest_file);
                 })
@@ -240,15 +259,16 @@ impl QueryServerReadV1 {
         }
 
         // pattern to find automatically generated backup files
-        let re = Regex::new(r"^backup-\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}Z\.json$").map_err(
-            |error| {
-                error!(
-                    "Failed to parse regexp for online backup files: {:?}",
-                    error
-                );
-                OperationError::InvalidState
-            },
-        )?;
+        let re = Regex::new(
+            r"^backup-\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}Z\.jsonl?(\.gz|\.zst)?(\.age)?$",
+        )
+        .map_err(|error| {
+            error!(
+                "Failed to parse regexp for online backup files: {:?}",
+                error
+            );
+            OperationError::InvalidState
+        })?;
 
         // cleanup of maximum backup versions to keep
         let mut backup_file_list: Vec<PathBuf> = Vec::new();
@@ -1174,6 +1194,33 @@ impl QueryServerReadV1 {
                     );
                     e
                 }),
+            CURequest::DeviceKeyInit => idms_cred_update
+                .credential_devicekey_init(&session_token, ct)
+                .map_err(|e| {
+                    admin_error!(
+                        err = ?e,
+                        "Failed to begin credential_devicekey_init",
+                    );
+                    e
+                }),
+            CURequest::DeviceKeyFinish(label, rpkc) => idms_cred_update
+                .credential_devicekey_finish(&session_token, ct, label, &rpkc)
+                .map_err(|e| {
+                    admin_error!(
+                        err = ?e,
+                        "Failed to begin credential_devicekey_finish",
+                    );
+                    e
+                }),
+            CURequest::DeviceKeyRemove(uuid) => idms_cred_update
+                .credential_devicekey_remove(&session_token, ct, uuid)
+                .map_err(|e| {
+                    admin_error!(
+                        err = ?e,
+                        "Failed to begin credential_devicekey_remove",
+                    );
+                    e
+                }),
         }
         .map(|sta| sta.into())
     }
@@ -1239,7 +1286,7 @@ impl QueryServerReadV1 {
     pub async fn handle_oauth2_authorise(
         &self,
         uat: Option<String>,
-        auth_req: AuthorisationRequest,
+        auth_req: AuthorisationRequestKind,
         eventid: Uuid,
     ) -> Result<AuthoriseResponse, Oauth2Error> {
         let ct = duration_from_epoch_now();
@@ -1257,7 +1304,42 @@ impl QueryServerReadV1 {
             })?;
 
         // Now we can send to the idm server for authorisation checking.
-        idms_prox_read.check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+        match auth_req {
+            AuthorisationRequestKind::Request(auth_req) => {
+                idms_prox_read.check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+            }
+            AuthorisationRequestKind::Reference(auth_ref) => {
+                idms_prox_read.check_oauth2_authorisation_reference(&ident, &uat, &auth_ref, ct)
+            }
+        }
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_device_authorisation(
+        &self,
+        uat: Option<String>,
+        user_code: String,
+        eventid: Uuid,
+    ) -> Result<AuthoriseResponse, Oauth2Error> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_read = self.idms.proxy_read().await;
+        let (ident, uat) = idms_prox_read
+            .validate_and_parse_uat(uat.as_deref(), ct)
+            .and_then(|uat| {
+                idms_prox_read
+                    .process_uat_to_identity(&uat, ct)
+                    .map(|ident| (ident, uat))
+            })
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                Oauth2Error::AuthenticationRequired
+            })?;
+
+        idms_prox_read.check_oauth2_device_authorisation(&ident, &uat, &user_code, ct)
     }
 
     #[instrument(
@@ -1321,6 +1403,29 @@ impl QueryServerReadV1 {
         idms_prox_read.oauth2_openid_userinfo(&client_id, &client_authz, ct)
     }
 
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_client_registration_get(
+        &self,
+        token: Option<String>,
+        client_id: String,
+        eventid: Uuid,
+    ) -> Result<ClientRegistrationResponse, Oauth2Error> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_read = self.idms.proxy_read().await;
+        let ident = idms_prox_read
+            .validate_and_parse_token_to_ident(token.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                Oauth2Error::AuthenticationRequired
+            })?;
+
+        idms_prox_read.oauth2_client_registration_get(&ident, &client_id)
+    }
+
     #[instrument(
         level = "info",
         skip_all,
@@ -1372,6 +1477,118 @@ impl QueryServerReadV1 {
         idms_prox_read.list_applinks(&ident)
     }
 
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_list_notifications(
+        &self,
+        uat: Option<String>,
+        eventid: Uuid,
+    ) -> Result<Vec<UserNotification>, OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_read = self.idms.proxy_read().await;
+        let ident = idms_prox_read
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                e
+            })?;
+
+        idms_prox_read.list_notifications(&ident)
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_audit_log_search(
+        &self,
+        uat: Option<String>,
+        query: AuditLogQuery,
+        eventid: Uuid,
+    ) -> Result<Vec<AuditLogEntry>, OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_read = self.idms.proxy_read().await;
+        let ident = idms_prox_read
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                e
+            })?;
+
+        idms_prox_read.audit_log_search(&ident, &self.audit_log, &query)
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_source_lock_list(
+        &self,
+        uat: Option<String>,
+        eventid: Uuid,
+    ) -> Result<Vec<SourceLockStatus>, OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_read = self.idms.proxy_read().await;
+        let ident = idms_prox_read
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                e
+            })?;
+
+        self.idms.source_locks().list(&ident, ct)
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_source_lock_clear(
+        &self,
+        uat: Option<String>,
+        source: String,
+        eventid: Uuid,
+    ) -> Result<(), OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_read = self.idms.proxy_read().await;
+        let ident = idms_prox_read
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                e
+            })?;
+
+        self.idms.source_locks().clear(&ident, &source)
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_password_kdf_report(
+        &self,
+        uat: Option<String>,
+        eventid: Uuid,
+    ) -> Result<PasswordKdfReport, OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_read = self.idms.proxy_read().await;
+        let ident = idms_prox_read
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                e
+            })?;
+
+        idms_prox_read.password_kdf_report(&ident)
+    }
+
     #[instrument(
         level = "info",
         skip_all,
@@ -1416,11 +1633,45 @@ impl QueryServerReadV1 {
         eventid: Uuid,
         protomsg: LdapMsg,
         uat: Option<LdapBoundToken>,
+        paged_search: &mut Option<LdapPagedSearch>,
+        ip_addr: IpAddr,
     ) -> Option<LdapResponseState> {
+        // Write operations are decoded first, anything else falls through to the
+        // standard server operations. The ldap server holds its own write transactions
+        // so these are safe to process from the read actor.
+        let protomsg = match LdapWriteOps::try_from(protomsg) {
+            Ok(write_op) => {
+                let res = self
+                    .ldap
+                    .do_write_op(&self.idms, write_op, uat, eventid)
+                    .await
+                    .unwrap_or_else(|e| {
+                        admin_error!("do_write_op failed -> {:?}", e);
+                        LdapResponseState::Disconnect(DisconnectionNotice::gen(
+                            LdapResultCode::Other,
+                            format!("Internal Server Error {:?}", &eventid).as_str(),
+                        ))
+                    });
+                return Some(res);
+            }
+            Err(protomsg) => protomsg,
+        };
+
+        // ServerOps does not retain the request controls, so we take them first.
+        let ctrl = protomsg.ctrl.clone();
+
         let res = match ServerOps::try_from(protomsg) {
             Ok(server_op) => self
                 .ldap
-                .do_op(&self.idms, server_op, uat, eventid)
+                .do_op(
+                    &self.idms,
+                    server_op,
+                    uat,
+                    &ctrl,
+                    paged_search,
+                    eventid,
+                    Source::Ldaps(ip_addr),
+                )
                 .await
                 .unwrap_or_else(|e| {
                     admin_error!("do_op failed -> {:?}", e);
@@ -2,6 +2,10 @@ use std::iter;
 use std::sync::Arc;
 use std::time::Duration;
 
+use kanidm_proto::internal::{
+    SchemaAttributeCreateRequest, SchemaAttributeUpdateRequest, SchemaClassCreateRequest,
+    SchemaClassUpdateRequest,
+};
 use kanidm_proto::v1::{
     AccountUnixExtend, CUIntentToken, CUSessionToken, CUStatus, CreateRequest, DeleteRequest,
     Entry as ProtoEntry, GroupUnixExtend, Modify as ProtoModify, ModifyList as ProtoModifyList,
@@ -24,11 +28,14 @@ use kanidmd_lib::{
     },
     idm::delayed::DelayedAction,
     idm::event::{GeneratePasswordEvent, RegenerateRadiusSecretEvent, UnixPasswordChangeEvent},
+    idm::message::PendingMessage,
     idm::oauth2::{
-        AccessTokenRequest, AccessTokenResponse, AuthorisePermitSuccess, Oauth2Error,
-        TokenRevokeRequest,
+        AccessTokenRequest, AccessTokenResponse, AuthorisePermitSuccess, ClientRegistrationRequest,
+        ClientRegistrationResponse, DeviceAuthorisationRequest, DeviceAuthorisationResponse,
+        EndSessionRequest, JwkKeySet, Oauth2BackchannelLogout, Oauth2Error,
+        PushedAuthorisationRequest, PushedAuthorisationResponse, TokenRevokeRequest,
     },
-    idm::server::{IdmServer, IdmServerTransaction},
+    idm::server::{IdmServer, IdmServerProxyWriteTransaction, IdmServerTransaction},
     idm::serviceaccount::{DestroyApiTokenEvent, GenerateApiTokenEvent},
     modify::{Modify, ModifyInvalid, ModifyList},
     utils::duration_from_epoch_now,
@@ -37,6 +44,102 @@ use kanidmd_lib::{
 
 use kanidmd_lib::prelude::*;
 
+// Ends a user's session, and then tells the resource servers holding oauth2 sessions
+// that descend from it.
+fn destroy_session_token(
+    mut idms_prox_write: IdmServerProxyWriteTransaction<'_>,
+    dte: &DestroySessionTokenEvent,
+    ct: Duration,
+) -> Result<(), OperationError> {
+    // These must be found before the session is removed, as the oauth2 sessions go with it.
+    // A failure here must not prevent the logout.
+    let logouts = idms_prox_write
+        .oauth2_backchannel_logouts(dte.target, dte.token_id, ct)
+        .unwrap_or_else(|e| {
+            admin_error!(err = ?e, "Unable to create oauth2 logout tokens");
+            Vec::new()
+        });
+
+    idms_prox_write
+        .account_destroy_session_token(dte)
+        .and_then(|_| idms_prox_write.commit())?;
+
+    if !logouts.is_empty() {
+        tokio::spawn(deliver_oauth2_backchannel_logouts(logouts));
+    }
+
+    Ok(())
+}
+
+// https://openid.net/specs/openid-connect-backchannel-1_0.html#BCRequest
+// This is best effort, and we don't retry. A resource server that misses the
+// logout still finds the session is gone when it next introspects or refreshes.
+async fn deliver_oauth2_backchannel_logouts(logouts: Vec<Oauth2BackchannelLogout>) {
+    let client = reqwest::Client::new();
+
+    for logout in logouts {
+        let res = client
+            .post(logout.backchannel_logout_uri.clone())
+            .form(&[("logout_token", logout.logout_token.as_str())])
+            .timeout(Duration::from_secs(OAUTH2_BACKCHANNEL_LOGOUT_TIMEOUT))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        match res {
+            Ok(_) => {
+                info!(backchannel_logout_uri = %logout.backchannel_logout_uri, "Delivered oauth2 logout token");
+            }
+            Err(e) => {
+                admin_warn!(err = ?e, backchannel_logout_uri = %logout.backchannel_logout_uri, "Unable to deliver oauth2 logout token");
+            }
+        }
+    }
+}
+
+// The jwks url is set by an administrator, but it's still a remote server we don't
+// control, so bound how long we wait on it and how much we read from it.
+async fn fetch_oauth2_client_jwks(jwks_url: &Url) -> Result<Vec<u8>, OperationError> {
+    let timeout = Duration::from_secs(OAUTH2_CLIENT_JWKS_FETCH_TIMEOUT);
+
+    let mut response = reqwest::Client::builder()
+        .connect_timeout(timeout)
+        .timeout(timeout)
+        .build()
+        .map_err(|e| {
+            admin_error!(err = ?e, "Unable to build http client");
+            OperationError::InvalidState
+        })?
+        .get(jwks_url.clone())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| {
+            admin_error!(err = ?e, %jwks_url, "Unable to fetch client jwks");
+            OperationError::InvalidState
+        })?;
+
+    if response.content_length().unwrap_or(0) > OAUTH2_CLIENT_JWKS_MAX_SIZE as u64 {
+        admin_error!(%jwks_url, "Client jwks is too large");
+        return Err(OperationError::InvalidState);
+    }
+
+    // The content length may be absent or wrong, so enforce the limit as we read.
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| {
+        admin_error!(err = ?e, %jwks_url, "Unable to fetch client jwks");
+        OperationError::InvalidState
+    })? {
+        if body.len() + chunk.len() > OAUTH2_CLIENT_JWKS_MAX_SIZE {
+            admin_error!(%jwks_url, "Client jwks is too large");
+            return Err(OperationError::InvalidState);
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body)
+}
+
 pub struct QueryServerWriteV1 {
     pub(crate) idms: Arc<IdmServer>,
 }
@@ -543,9 +646,7 @@ impl QueryServerWriteV1 {
             token_id,
         };
 
-        idms_prox_write
-            .account_destroy_session_token(&dte)
-            .and_then(|r| idms_prox_write.commit().map(|_| r))
+        destroy_session_token(idms_prox_write, &dte, ct)
     }
 
     #[instrument(
@@ -588,9 +689,7 @@ impl QueryServerWriteV1 {
             token_id,
         };
 
-        idms_prox_write
-            .account_destroy_session_token(&dte)
-            .and_then(|r| idms_prox_write.commit().map(|_| r))
+        destroy_session_token(idms_prox_write, &dte, ct)
     }
 
     #[instrument(
@@ -688,6 +787,50 @@ impl QueryServerWriteV1 {
             })
     }
 
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_idmcredentialupdateintentsend(
+        &self,
+        uat: Option<String>,
+        uuid_or_name: String,
+        ttl: Option<Duration>,
+        eventid: Uuid,
+    ) -> Result<(), OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!(err = ?e, "Invalid identity");
+                e
+            })?;
+
+        let target_uuid = idms_prox_write
+            .qs_write
+            .name_to_uuid(uuid_or_name.as_str())
+            .map_err(|e| {
+                admin_error!(err = ?e, "Error resolving id to target");
+                e
+            })?;
+
+        idms_prox_write
+            .init_credential_update_intent_send(
+                &InitCredentialUpdateIntentEvent::new(ident, target_uuid, ttl),
+                ct,
+            )
+            .and_then(|_| idms_prox_write.commit())
+            .map_err(|e| {
+                admin_error!(
+                    err = ?e,
+                    "Failed to begin init_credential_update_intent_send",
+                );
+                e
+            })
+    }
+
     #[instrument(
         level = "info",
         skip_all,
@@ -1292,7 +1435,7 @@ impl QueryServerWriteV1 {
         skip_all,
         fields(uuid = ?eventid)
     )]
-    pub async fn handle_oauth2_sup_scopemap_update(
+    pub async fn handle_oauth2_registration_scopemap_update(
         &self,
         uat: Option<String>,
         group: String,
@@ -1300,8 +1443,6 @@ impl QueryServerWriteV1 {
         filter: Filter<FilterInvalid>,
         eventid: Uuid,
     ) -> Result<(), OperationError> {
-        // Because this is from internal, we can generate a real modlist, rather
-        // than relying on the proto ones.
         let mut idms_prox_write = self.idms.proxy_write(duration_from_epoch_now()).await;
         let ct = duration_from_epoch_now();
 
@@ -1321,7 +1462,7 @@ impl QueryServerWriteV1 {
             })?;
 
         let ml = ModifyList::new_append(
-            "oauth2_rs_sup_scope_map",
+            "oauth2_registration_scope_map",
             Value::new_oauthscopemap(group_uuid, scopes.into_iter().collect()).ok_or_else(
                 || OperationError::InvalidAttribute("Invalid Oauth Scope Map syntax".to_string()),
             )?,
@@ -1353,15 +1494,15 @@ impl QueryServerWriteV1 {
         skip_all,
         fields(uuid = ?eventid)
     )]
-    pub async fn handle_oauth2_sup_scopemap_delete(
+    pub async fn handle_oauth2_registration_scopemap_delete(
         &self,
         uat: Option<String>,
         group: String,
         filter: Filter<FilterInvalid>,
         eventid: Uuid,
     ) -> Result<(), OperationError> {
+        let mut idms_prox_write = self.idms.proxy_write(duration_from_epoch_now()).await;
         let ct = duration_from_epoch_now();
-        let mut idms_prox_write = self.idms.proxy_write(ct).await;
 
         let ident = idms_prox_write
             .validate_and_parse_token_to_ident(uat.as_deref(), ct)
@@ -1378,7 +1519,10 @@ impl QueryServerWriteV1 {
                 e
             })?;
 
-        let ml = ModifyList::new_remove("oauth2_rs_sup_scope_map", PartialValue::Refer(group_uuid));
+        let ml = ModifyList::new_remove(
+            "oauth2_registration_scope_map",
+            PartialValue::Refer(group_uuid),
+        );
 
         let mdf = match ModifyEvent::from_internal_parts(
             ident,
@@ -1406,29 +1550,60 @@ impl QueryServerWriteV1 {
         skip_all,
         fields(uuid = ?eventid)
     )]
-    pub async fn handle_oauth2_authorise_permit(
+    pub async fn handle_oauth2_sup_scopemap_update(
         &self,
         uat: Option<String>,
-        consent_req: String,
+        group: String,
+        scopes: Vec<String>,
+        filter: Filter<FilterInvalid>,
         eventid: Uuid,
-    ) -> Result<AuthorisePermitSuccess, OperationError> {
+    ) -> Result<(), OperationError> {
+        // Because this is from internal, we can generate a real modlist, rather
+        // than relying on the proto ones.
+        let mut idms_prox_write = self.idms.proxy_write(duration_from_epoch_now()).await;
         let ct = duration_from_epoch_now();
-        let mut idms_prox_write = self.idms.proxy_write(ct).await;
-        let (ident, uat) = idms_prox_write
-            .validate_and_parse_uat(uat.as_deref(), ct)
-            .and_then(|uat| {
-                idms_prox_write
-                    .process_uat_to_identity(&uat, ct)
-                    .map(|ident| (ident, uat))
-            })
+
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
             .map_err(|e| {
-                admin_error!("Invalid identity: {:?}", e);
+                admin_error!(err = ?e, "Invalid identity");
+                e
+            })?;
+
+        let group_uuid = idms_prox_write
+            .qs_write
+            .name_to_uuid(group.as_str())
+            .map_err(|e| {
+                admin_error!(err = ?e, "Error resolving group name to target");
                 e
             })?;
 
+        let ml = ModifyList::new_append(
+            "oauth2_rs_sup_scope_map",
+            Value::new_oauthscopemap(group_uuid, scopes.into_iter().collect()).ok_or_else(
+                || OperationError::InvalidAttribute("Invalid Oauth Scope Map syntax".to_string()),
+            )?,
+        );
+
+        let mdf = match ModifyEvent::from_internal_parts(
+            ident,
+            &ml,
+            &filter,
+            &idms_prox_write.qs_write,
+        ) {
+            Ok(m) => m,
+            Err(e) => {
+                admin_error!(err = ?e, "Failed to begin modify");
+                return Err(e);
+            }
+        };
+
+        trace!(?mdf, "Begin modify event");
+
         idms_prox_write
-            .check_oauth2_authorise_permit(&ident, &uat, &consent_req, ct)
-            .and_then(|r| idms_prox_write.commit().map(|()| r))
+            .qs_write
+            .modify(&mdf)
+            .and_then(|_| idms_prox_write.commit().map(|_| ()))
     }
 
     #[instrument(
@@ -1436,72 +1611,742 @@ impl QueryServerWriteV1 {
         skip_all,
         fields(uuid = ?eventid)
     )]
-    pub async fn handle_oauth2_token_exchange(
+    pub async fn handle_oauth2_sup_scopemap_delete(
         &self,
-        client_authz: Option<String>,
-        token_req: AccessTokenRequest,
+        uat: Option<String>,
+        group: String,
+        filter: Filter<FilterInvalid>,
         eventid: Uuid,
-    ) -> Result<AccessTokenResponse, Oauth2Error> {
+    ) -> Result<(), OperationError> {
         let ct = duration_from_epoch_now();
         let mut idms_prox_write = self.idms.proxy_write(ct).await;
-        // Now we can send to the idm server for authorisation checking.
-        let resp =
-            idms_prox_write.check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct);
 
-        match &resp {
-            Err(Oauth2Error::InvalidGrant) | Ok(_) => {
-                idms_prox_write.commit().map_err(Oauth2Error::ServerError)?;
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!(err = ?e, "Invalid identity");
+                e
+            })?;
+
+        let group_uuid = idms_prox_write
+            .qs_write
+            .name_to_uuid(group.as_str())
+            .map_err(|e| {
+                admin_error!(err = ?e, "Error resolving group name to target");
+                e
+            })?;
+
+        let ml = ModifyList::new_remove("oauth2_rs_sup_scope_map", PartialValue::Refer(group_uuid));
+
+        let mdf = match ModifyEvent::from_internal_parts(
+            ident,
+            &ml,
+            &filter,
+            &idms_prox_write.qs_write,
+        ) {
+            Ok(m) => m,
+            Err(e) => {
+                admin_error!(err = ?e, "Failed to begin modify");
+                return Err(e);
             }
-            _ => {}
         };
 
-        resp
-    }
+        trace!(?mdf, "Begin modify event");
 
+        idms_prox_write
+            .qs_write
+            .modify(&mdf)
+            .and_then(|_| idms_prox_write.commit().map(|_| ()))
+    }
     #[instrument(
         level = "info",
         skip_all,
         fields(uuid = ?eventid)
     )]
-    pub async fn handle_oauth2_token_revoke(
+    pub async fn handle_oauth2_token_exchange_audience_add(
         &self,
-        client_authz: String,
-        intr_req: TokenRevokeRequest,
+        uat: Option<String>,
+        audience: String,
+        filter: Filter<FilterInvalid>,
         eventid: Uuid,
-    ) -> Result<(), Oauth2Error> {
+    ) -> Result<(), OperationError> {
+        let mut idms_prox_write = self.idms.proxy_write(duration_from_epoch_now()).await;
         let ct = duration_from_epoch_now();
-        let mut idms_prox_write = self.idms.proxy_write(ct).await;
-        idms_prox_write
-            .oauth2_token_revoke(&client_authz, &intr_req, ct)
-            .and_then(|()| idms_prox_write.commit().map_err(Oauth2Error::ServerError))
-    }
 
-    // ===== These below are internal only event types. =====
-    #[instrument(
-        level = "info",
-        skip_all,
-        fields(uuid = ?msg.eventid)
-    )]
-    pub async fn handle_purgetombstoneevent(&self, msg: PurgeTombstoneEvent) {
-        trace!(?msg, "Begin purge tombstone event");
-        let mut idms_prox_write = self.idms.proxy_write(duration_from_epoch_now()).await;
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!(err = ?e, "Invalid identity");
+                e
+            })?;
 
-        let res = idms_prox_write
+        let audience_uuid = oauth2_rs_name_to_uuid(&mut idms_prox_write, audience.as_str())?;
+
+        let ml = ModifyList::new_append(
+            "oauth2_rs_token_exchange_audience",
+            Value::Refer(audience_uuid),
+        );
+
+        let mdf = match ModifyEvent::from_internal_parts(
+            ident,
+            &ml,
+            &filter,
+            &idms_prox_write.qs_write,
+        ) {
+            Ok(m) => m,
+            Err(e) => {
+                admin_error!(err = ?e, "Failed to begin modify");
+                return Err(e);
+            }
+        };
+
+        trace!(?mdf, "Begin modify event");
+
+        idms_prox_write
             .qs_write
-            .purge_tombstones()
-            .and_then(|_| idms_prox_write.commit());
-        admin_info!(?res, "Purge tombstones result");
-        #[allow(clippy::expect_used)]
-        res.expect("Invalid Server State");
+            .modify(&mdf)
+            .and_then(|_| idms_prox_write.commit().map(|_| ()))
     }
 
     #[instrument(
         level = "info",
         skip_all,
-        fields(uuid = ?msg.eventid)
+        fields(uuid = ?eventid)
     )]
-    pub async fn handle_purgerecycledevent(&self, msg: PurgeRecycledEvent) {
-        trace!(?msg, "Begin purge recycled event");
+    pub async fn handle_oauth2_token_exchange_audience_delete(
+        &self,
+        uat: Option<String>,
+        audience: String,
+        filter: Filter<FilterInvalid>,
+        eventid: Uuid,
+    ) -> Result<(), OperationError> {
+        let mut idms_prox_write = self.idms.proxy_write(duration_from_epoch_now()).await;
+        let ct = duration_from_epoch_now();
+
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!(err = ?e, "Invalid identity");
+                e
+            })?;
+
+        let audience_uuid = oauth2_rs_name_to_uuid(&mut idms_prox_write, audience.as_str())?;
+
+        let ml = ModifyList::new_remove(
+            "oauth2_rs_token_exchange_audience",
+            PartialValue::Refer(audience_uuid),
+        );
+
+        let mdf = match ModifyEvent::from_internal_parts(
+            ident,
+            &ml,
+            &filter,
+            &idms_prox_write.qs_write,
+        ) {
+            Ok(m) => m,
+            Err(e) => {
+                admin_error!(err = ?e, "Failed to begin modify");
+                return Err(e);
+            }
+        };
+
+        trace!(?mdf, "Begin modify event");
+
+        idms_prox_write
+            .qs_write
+            .modify(&mdf)
+            .and_then(|_| idms_prox_write.commit().map(|_| ()))
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_end_session(
+        &self,
+        uat: Option<String>,
+        client_id: String,
+        end_session_req: EndSessionRequest,
+        eventid: Uuid,
+    ) -> Result<Option<Url>, Oauth2Error> {
+        let ct = duration_from_epoch_now();
+        let post_logout_redirect_uri = {
+            let idms_prox_read = self.idms.proxy_read().await;
+            // An expired or invalid session is treated as already ended.
+            let session_uat = idms_prox_read
+                .validate_and_parse_uat(uat.as_deref(), ct)
+                .ok();
+            idms_prox_read.check_oauth2_end_session(
+                &client_id,
+                &end_session_req,
+                session_uat.as_ref(),
+            )?
+        };
+
+        // The user may have already logged out, or their session may have expired. In
+        // either case there is nothing left for us to end.
+        if uat.is_some() {
+            if let Err(e) = self.handle_logout(uat, eventid).await {
+                admin_warn!(err = ?e, "Unable to end session");
+            }
+        }
+
+        Ok(post_logout_redirect_uri)
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_client_register(
+        &self,
+        token: Option<String>,
+        reg_req: ClientRegistrationRequest,
+        eventid: Uuid,
+    ) -> Result<ClientRegistrationResponse, Oauth2Error> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(token.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                Oauth2Error::AuthenticationRequired
+            })?;
+
+        idms_prox_write
+            .oauth2_client_register(&ident, &reg_req)
+            .and_then(|reg_resp| {
+                idms_prox_write
+                    .commit()
+                    .map(|_| reg_resp)
+                    .map_err(Oauth2Error::ServerError)
+            })
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_client_registration_update(
+        &self,
+        token: Option<String>,
+        client_id: String,
+        reg_req: ClientRegistrationRequest,
+        eventid: Uuid,
+    ) -> Result<ClientRegistrationResponse, Oauth2Error> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(token.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                Oauth2Error::AuthenticationRequired
+            })?;
+
+        idms_prox_write
+            .oauth2_client_registration_update(&ident, &client_id, &reg_req)
+            .and_then(|reg_resp| {
+                idms_prox_write
+                    .commit()
+                    .map(|_| reg_resp)
+                    .map_err(Oauth2Error::ServerError)
+            })
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_client_registration_delete(
+        &self,
+        token: Option<String>,
+        client_id: String,
+        eventid: Uuid,
+    ) -> Result<(), Oauth2Error> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(token.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                Oauth2Error::AuthenticationRequired
+            })?;
+
+        idms_prox_write
+            .oauth2_client_registration_delete(&ident, &client_id)
+            .and_then(|_| idms_prox_write.commit().map_err(Oauth2Error::ServerError))
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_client_jwks_refresh(
+        &self,
+        uat: Option<String>,
+        filter: Filter<FilterInvalid>,
+        eventid: Uuid,
+    ) -> Result<(), OperationError> {
+        let ct = duration_from_epoch_now();
+
+        // Find where to fetch from. We can't hold a transaction open while we wait on the
+        // resource server, so this is done in its own read.
+        let jwks_url = {
+            let mut idms_prox_read = self.idms.proxy_read().await;
+            let ident = idms_prox_read
+                .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+                .map_err(|e| {
+                    admin_error!(err = ?e, "Invalid identity");
+                    e
+                })?;
+
+            let entries = idms_prox_read.qs_read.impersonate_search(
+                filter.clone(),
+                filter.clone(),
+                &ident,
+            )?;
+
+            entries
+                .first()
+                .ok_or(OperationError::NoMatchingEntries)?
+                .get_ava_single_url("oauth2_rs_client_jwks_url")
+                .cloned()
+                .ok_or_else(|| {
+                    admin_error!("Resource server does not have a client jwks url");
+                    OperationError::NoMatchingAttributes
+                })?
+        };
+
+        let jwks: JwkKeySet = fetch_oauth2_client_jwks(&jwks_url).await.and_then(|body| {
+            serde_json::from_slice(&body).map_err(|e| {
+                admin_error!(err = ?e, %jwks_url, "Unable to parse client jwks");
+                OperationError::SerdeJsonError
+            })
+        })?;
+
+        let values = jwks
+            .keys
+            .iter()
+            .map(|jwk| serde_json::to_string(jwk).map(Value::new_utf8))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                admin_error!(err = ?e, "Unable to serialise client jwk");
+                OperationError::SerdeJsonError
+            })?;
+
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!(err = ?e, "Invalid identity");
+                e
+            })?;
+
+        // Replace the current keys with the fetched set.
+        let ml = ModifyList::new_list(
+            iter::once(Modify::Purged("oauth2_rs_client_jwk".into()))
+                .chain(
+                    values
+                        .into_iter()
+                        .map(|v| Modify::Present("oauth2_rs_client_jwk".into(), v)),
+                )
+                .collect(),
+        );
+
+        let mdf = match ModifyEvent::from_internal_parts(
+            ident,
+            &ml,
+            &filter,
+            &idms_prox_write.qs_write,
+        ) {
+            Ok(m) => m,
+            Err(e) => {
+                admin_error!(err = ?e, "Failed to begin modify");
+                return Err(e);
+            }
+        };
+
+        trace!(?mdf, "Begin modify event");
+
+        idms_prox_write
+            .qs_write
+            .modify(&mdf)
+            .and_then(|_| idms_prox_write.commit().map(|_| ()))
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_authorise_permit(
+        &self,
+        uat: Option<String>,
+        consent_req: String,
+        eventid: Uuid,
+    ) -> Result<AuthorisePermitSuccess, OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let (ident, uat) = idms_prox_write
+            .validate_and_parse_uat(uat.as_deref(), ct)
+            .and_then(|uat| {
+                idms_prox_write
+                    .process_uat_to_identity(&uat, ct)
+                    .map(|ident| (ident, uat))
+            })
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                e
+            })?;
+
+        idms_prox_write
+            .check_oauth2_authorise_permit(&ident, &uat, &consent_req, ct)
+            .and_then(|r| idms_prox_write.commit().map(|()| r))
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_token_exchange(
+        &self,
+        client_authz: Option<String>,
+        token_req: AccessTokenRequest,
+        eventid: Uuid,
+    ) -> Result<AccessTokenResponse, Oauth2Error> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        // Now we can send to the idm server for authorisation checking.
+        let resp =
+            idms_prox_write.check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct);
+
+        // The device flow errors also update the pending device authorisation
+        // state, so these must be committed too.
+        match &resp {
+            Err(Oauth2Error::InvalidGrant)
+            | Err(Oauth2Error::AuthorizationPending)
+            | Err(Oauth2Error::SlowDown)
+            | Err(Oauth2Error::ExpiredToken)
+            | Err(Oauth2Error::AccessDenied)
+            | Ok(_) => {
+                idms_prox_write.commit().map_err(Oauth2Error::ServerError)?;
+            }
+            _ => {}
+        };
+
+        resp
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_device_authorise(
+        &self,
+        client_authz: Option<String>,
+        device_req: DeviceAuthorisationRequest,
+        eventid: Uuid,
+    ) -> Result<DeviceAuthorisationResponse, Oauth2Error> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        idms_prox_write
+            .check_oauth2_device_authorise(client_authz.as_deref(), &device_req, ct)
+            .and_then(|r| {
+                idms_prox_write
+                    .commit()
+                    .map(|()| r)
+                    .map_err(Oauth2Error::ServerError)
+            })
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_pushed_authorisation(
+        &self,
+        client_authz: Option<String>,
+        par_req: PushedAuthorisationRequest,
+        eventid: Uuid,
+    ) -> Result<PushedAuthorisationResponse, Oauth2Error> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        idms_prox_write
+            .check_oauth2_pushed_authorisation(client_authz.as_deref(), &par_req, ct)
+            .and_then(|r| {
+                idms_prox_write
+                    .commit()
+                    .map(|()| r)
+                    .map_err(Oauth2Error::ServerError)
+            })
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_device_permit(
+        &self,
+        uat: Option<String>,
+        consent_req: String,
+        eventid: Uuid,
+    ) -> Result<(), OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let (ident, uat) = idms_prox_write
+            .validate_and_parse_uat(uat.as_deref(), ct)
+            .and_then(|uat| {
+                idms_prox_write
+                    .process_uat_to_identity(&uat, ct)
+                    .map(|ident| (ident, uat))
+            })
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                e
+            })?;
+
+        idms_prox_write
+            .check_oauth2_device_permit(&ident, &uat, &consent_req, ct)
+            .and_then(|()| idms_prox_write.commit())
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_device_reject(
+        &self,
+        uat: Option<String>,
+        consent_req: String,
+        eventid: Uuid,
+    ) -> Result<(), OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let (ident, uat) = idms_prox_write
+            .validate_and_parse_uat(uat.as_deref(), ct)
+            .and_then(|uat| {
+                idms_prox_write
+                    .process_uat_to_identity(&uat, ct)
+                    .map(|ident| (ident, uat))
+            })
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                e
+            })?;
+
+        idms_prox_write
+            .check_oauth2_device_reject(&ident, &uat, &consent_req, ct)
+            .and_then(|()| idms_prox_write.commit())
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_oauth2_token_revoke(
+        &self,
+        client_authz: String,
+        intr_req: TokenRevokeRequest,
+        eventid: Uuid,
+    ) -> Result<(), Oauth2Error> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        idms_prox_write
+            .oauth2_token_revoke(&client_authz, &intr_req, ct)
+            .and_then(|()| idms_prox_write.commit().map_err(Oauth2Error::ServerError))
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_schema_attribute_create(
+        &self,
+        uat: Option<String>,
+        req: SchemaAttributeCreateRequest,
+        eventid: Uuid,
+    ) -> Result<(), OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!(err = ?e, "Invalid identity");
+                e
+            })?;
+
+        idms_prox_write
+            .schema_attribute_create(&ident, &req)
+            .and_then(|_| idms_prox_write.commit())
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_schema_attribute_update(
+        &self,
+        uat: Option<String>,
+        name: String,
+        req: SchemaAttributeUpdateRequest,
+        eventid: Uuid,
+    ) -> Result<(), OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!(err = ?e, "Invalid identity");
+                e
+            })?;
+
+        idms_prox_write
+            .schema_attribute_update(&ident, &name, &req)
+            .and_then(|_| idms_prox_write.commit())
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_schema_attribute_delete(
+        &self,
+        uat: Option<String>,
+        name: String,
+        purge: bool,
+        eventid: Uuid,
+    ) -> Result<(), OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!(err = ?e, "Invalid identity");
+                e
+            })?;
+
+        idms_prox_write
+            .schema_attribute_delete(&ident, &name, purge)
+            .and_then(|_| idms_prox_write.commit())
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_schema_class_create(
+        &self,
+        uat: Option<String>,
+        req: SchemaClassCreateRequest,
+        eventid: Uuid,
+    ) -> Result<(), OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!(err = ?e, "Invalid identity");
+                e
+            })?;
+
+        idms_prox_write
+            .schema_class_create(&ident, &req)
+            .and_then(|_| idms_prox_write.commit())
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_schema_class_update(
+        &self,
+        uat: Option<String>,
+        name: String,
+        req: SchemaClassUpdateRequest,
+        eventid: Uuid,
+    ) -> Result<(), OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!(err = ?e, "Invalid identity");
+                e
+            })?;
+
+        idms_prox_write
+            .schema_class_update(&ident, &name, &req)
+            .and_then(|_| idms_prox_write.commit())
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?eventid)
+    )]
+    pub async fn handle_schema_class_delete(
+        &self,
+        uat: Option<String>,
+        name: String,
+        eventid: Uuid,
+    ) -> Result<(), OperationError> {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let ident = idms_prox_write
+            .validate_and_parse_token_to_ident(uat.as_deref(), ct)
+            .map_err(|e| {
+                admin_error!(err = ?e, "Invalid identity");
+                e
+            })?;
+
+        idms_prox_write
+            .schema_class_delete(&ident, &name)
+            .and_then(|_| idms_prox_write.commit())
+    }
+
+    // ===== These below are internal only event types. =====
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?msg.eventid)
+    )]
+    pub async fn handle_purgetombstoneevent(&self, msg: PurgeTombstoneEvent) {
+        trace!(?msg, "Begin purge tombstone event");
+        let mut idms_prox_write = self.idms.proxy_write(duration_from_epoch_now()).await;
+
+        let res = idms_prox_write
+            .qs_write
+            .purge_tombstones()
+            .and_then(|_| idms_prox_write.commit());
+        admin_info!(?res, "Purge tombstones result");
+        #[allow(clippy::expect_used)]
+        res.expect("Invalid Server State");
+    }
+
+    #[instrument(
+        level = "info",
+        skip_all,
+        fields(uuid = ?msg.eventid)
+    )]
+    pub async fn handle_purgerecycledevent(&self, msg: PurgeRecycledEvent) {
+        trace!(?msg, "Begin purge recycled event");
         let mut idms_prox_write = self.idms.proxy_write(duration_from_epoch_now()).await;
         let res = idms_prox_write
             .qs_write
@@ -1512,6 +2357,47 @@ impl QueryServerWriteV1 {
         res.expect("Invalid Server State");
     }
 
+    #[instrument(level = "debug", skip_all)]
+    pub(crate) async fn handle_pending_messages(
+        &self,
+        require_mail_destination: bool,
+    ) -> Result<Vec<PendingMessage>, OperationError> {
+        let ct = duration_from_epoch_now();
+        // Nothing is changed here, so the txn is dropped rather than committed.
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        idms_prox_write.pending_messages(require_mail_destination, ct)
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub(crate) async fn handle_message_delivery_results(
+        &self,
+        results: Vec<(Uuid, Result<(), String>)>,
+    ) {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let res = results
+            .into_iter()
+            .try_for_each(|(uuid, result)| {
+                idms_prox_write.record_message_delivery(uuid, result, ct)
+            })
+            .and_then(|_| idms_prox_write.commit());
+        if let Err(e) = res {
+            admin_error!(err = ?e, "Unable to record message delivery results");
+        }
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub(crate) async fn handle_purge_messages(&self) {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = self.idms.proxy_write(ct).await;
+        let res = idms_prox_write
+            .purge_messages(ct)
+            .and_then(|_| idms_prox_write.commit());
+        if let Err(e) = res {
+            admin_error!(err = ?e, "Unable to purge outbound messages");
+        }
+    }
+
     pub(crate) async fn handle_delayedaction(&self, da: DelayedAction) {
         let eventid = Uuid::new_v4();
         let nspan = span!(Level::INFO, "process_delayed_action", uuid = ?eventid);
@@ -1528,3 +2414,26 @@ impl QueryServerWriteV1 {
         }
     }
 }
+
+// Resource servers don't have a name, so we resolve them by their oauth2_rs_name instead.
+fn oauth2_rs_name_to_uuid(
+    idms_prox_write: &mut IdmServerProxyWriteTransaction<'_>,
+    rs_name: &str,
+) -> Result<Uuid, OperationError> {
+    idms_prox_write
+        .qs_write
+        .internal_search(filter!(f_and!([
+            f_eq("class", PartialValue::new_class("oauth2_resource_server")),
+            f_eq("oauth2_rs_name", PartialValue::new_iname(rs_name))
+        ])))
+        .and_then(|entries| {
+            entries
+                .first()
+                .map(|e| e.get_uuid())
+                .ok_or(OperationError::NoMatchingEntries)
+        })
+        .map_err(|e| {
+            admin_error!(err = ?e, "Error resolving oauth2 resource server name to target");
+            e
+        })
+}
@@ -0,0 +1,919 @@
+//! Sinks that export audit events to external systems, such as a SIEM. Each sink receives every
+//! audit event as an [AuditRecord], which is the stable, versioned representation of an event
+//! that other systems can rely on.
+//!
+//! If a sink is unavailable, records are appended to a buffer file on disk, and are sent in order
+//! once the sink can be reached again. This means that a restart or an outage of the receiver
+//! doesn't lose events, up to the size limit of the buffer.
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use kanidmd_lib::idm::audit::{AuditEvent, AuditSource};
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
+
+use crate::CoreAction;
+
+/// The version of the [AuditRecord] format. This is only changed if a field is removed or
+/// changes meaning - new fields may be added without changing the version.
+pub const AUDIT_RECORD_SCHEMA_VERSION: u32 = 1;
+
+/// How often a sink with buffered records retries sending them.
+const AUDIT_SINK_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long we wait to connect to a tcp syslog server.
+const AUDIT_SINK_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const fn default_file_max_size() -> u64 {
+    // 64MiB
+    64 * 1024 * 1024
+}
+
+const fn default_file_max_files() -> usize {
+    8
+}
+
+const fn default_buffer_max_size() -> u64 {
+    // 256MiB
+    256 * 1024 * 1024
+}
+
+fn default_syslog_app_name() -> String {
+    "kanidmd".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogTransport {
+    /// Best effort. A message is sent once it leaves this host, even if nothing receives it, so
+    /// records are only buffered if the network itself is unavailable.
+    #[default]
+    Udp,
+    /// Frames each message with its length, as described in RFC 6587.
+    Tcp,
+    /// A local datagram socket, such as `/dev/log`.
+    Unix,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogFacility {
+    Auth,
+    #[default]
+    Authpriv,
+    Daemon,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl SyslogFacility {
+    fn code(self) -> u8 {
+        match self {
+            SyslogFacility::Auth => 4,
+            SyslogFacility::Authpriv => 10,
+            SyslogFacility::Daemon => 3,
+            SyslogFacility::Local0 => 16,
+            SyslogFacility::Local1 => 17,
+            SyslogFacility::Local2 => 18,
+            SyslogFacility::Local3 => 19,
+            SyslogFacility::Local4 => 20,
+            SyslogFacility::Local5 => 21,
+            SyslogFacility::Local6 => 22,
+            SyslogFacility::Local7 => 23,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "sink", rename_all = "lowercase")]
+pub enum AuditSinkTarget {
+    /// Send each record as an RFC 5424 syslog message.
+    Syslog {
+        #[serde(default)]
+        transport: SyslogTransport,
+        /// A `host:port` for udp and tcp, or a socket path for unix.
+        address: String,
+        #[serde(default)]
+        facility: SyslogFacility,
+        #[serde(default = "default_syslog_app_name")]
+        app_name: String,
+    },
+    /// Append each record as a line of json to a file, which is rotated once it grows too large.
+    File {
+        path: String,
+        /// The size in bytes at which the file is rotated.
+        #[serde(default = "default_file_max_size")]
+        max_size: u64,
+        /// How many rotated files are kept.
+        #[serde(default = "default_file_max_files")]
+        max_files: usize,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditSinkConfig {
+    /// A unique name for this sink, used in logs and to name its buffer.
+    pub name: String,
+    /// Where records are kept while the sink is unavailable. Defaults to a file next to the
+    /// database.
+    pub buffer_path: Option<String>,
+    /// The size in bytes that the buffer may grow to. Once it is full, new records are written to
+    /// the server log instead.
+    #[serde(default = "default_buffer_max_size")]
+    pub buffer_max_size: u64,
+    #[serde(flatten)]
+    pub target: AuditSinkTarget,
+}
+
+/// The exported form of an [AuditEvent].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditRecord {
+    pub schema: u32,
+    /// A unique id for this record, so that receivers can discard duplicates.
+    pub id: Uuid,
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+    pub kind: String,
+    /// If this event indicates a failure, such as a denied authentication.
+    pub outcome: AuditOutcome,
+    pub actor: Option<Uuid>,
+    pub target: Uuid,
+    /// The remote address of the client, if the event came from a client connection.
+    pub source: Option<String>,
+    /// The fields of the event, which depend on the kind.
+    pub event: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl From<&AuditEvent> for AuditRecord {
+    fn from(event: &AuditEvent) -> Self {
+        let (outcome, source) = match event {
+            AuditEvent::AuthenticationDenied { source, .. } => {
+                (AuditOutcome::Failure, Some(source))
+            }
+            AuditEvent::AuthenticationSucceeded { source, .. }
+            | AuditEvent::PrivilegeEscalated { source, .. } => {
+                (AuditOutcome::Success, Some(source))
+            }
+            AuditEvent::CredentialsChanged { .. }
+            | AuditEvent::AccessControlModified { .. }
+            | AuditEvent::Oauth2ConsentGranted { .. } => (AuditOutcome::Success, None),
+        };
+
+        let source = source.and_then(|source| match source {
+            AuditSource::Internal => None,
+            AuditSource::Https(ip) | AuditSource::Ldaps(ip) => Some(ip.to_string()),
+        });
+
+        // Events serialise as a map of the variant name to its fields. The kind already names the
+        // variant, so only the fields are kept.
+        let fields = match serde_json::to_value(event) {
+            Ok(serde_json::Value::Object(map)) => map
+                .into_iter()
+                .next()
+                .map(|(_, fields)| fields)
+                .unwrap_or(serde_json::Value::Null),
+            Ok(_) => serde_json::Value::Null,
+            Err(e) => {
+                error!(?e, "Unable to serialise audit event");
+                serde_json::Value::Null
+            }
+        };
+
+        AuditRecord {
+            schema: AUDIT_RECORD_SCHEMA_VERSION,
+            id: Uuid::new_v4(),
+            time: event.time(),
+            kind: event.kind().to_string(),
+            outcome,
+            actor: event.actor(),
+            target: event.target(),
+            source,
+            event: fields,
+        }
+    }
+}
+
+#[async_trait]
+trait AuditSink: Send {
+    async fn send(&mut self, record: &AuditRecord) -> Result<(), String>;
+}
+
+struct SyslogSink {
+    transport: SyslogTransport,
+    address: String,
+    facility: SyslogFacility,
+    app_name: String,
+    hostname: String,
+    udp: Option<UdpSocket>,
+    tcp: Option<TcpStream>,
+    #[cfg(not(target_family = "windows"))]
+    unix: Option<tokio::net::UnixDatagram>,
+}
+
+impl SyslogSink {
+    /// Format a record as an RFC 5424 message. The record is the message body, and the kind of
+    /// event is the message id, so that it can be filtered on without parsing the body.
+    fn format(&self, record: &AuditRecord) -> Result<String, String> {
+        // Warning for failures, otherwise notice.
+        let severity = match record.outcome {
+            AuditOutcome::Failure => 4,
+            AuditOutcome::Success => 5,
+        };
+        let priority = u16::from(self.facility.code()) * 8 + severity;
+
+        // RFC 5424 allows at most microsecond precision.
+        let timestamp = record
+            .time
+            .replace_microsecond(record.time.microsecond())
+            .map_err(|e| e.to_string())?
+            .format(&Rfc3339)
+            .map_err(|e| e.to_string())?;
+
+        // Json escapes any line breaks, so the body is always a single line.
+        let body = serde_json::to_string(record).map_err(|e| e.to_string())?;
+
+        let msgid = if valid_header_field(&record.kind, 32) {
+            record.kind.as_str()
+        } else {
+            "-"
+        };
+
+        Ok(format!(
+            "<{}>1 {} {} {} {} {} - {}",
+            priority,
+            timestamp,
+            self.hostname,
+            self.app_name,
+            std::process::id(),
+            msgid,
+            body
+        ))
+    }
+
+    async fn send_udp(&mut self, message: &str) -> Result<(), String> {
+        let socket = match self.udp.take() {
+            Some(socket) => socket,
+            None => {
+                let remote = tokio::net::lookup_host(&self.address)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .next()
+                    .ok_or_else(|| format!("unable to resolve '{}'", self.address))?;
+                let local: SocketAddr = if remote.is_ipv4() {
+                    ([0, 0, 0, 0], 0).into()
+                } else {
+                    ([0u16; 8], 0).into()
+                };
+                let socket = UdpSocket::bind(local).await.map_err(|e| e.to_string())?;
+                socket.connect(remote).await.map_err(|e| e.to_string())?;
+                socket
+            }
+        };
+
+        socket
+            .send(message.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        self.udp = Some(socket);
+        Ok(())
+    }
+
+    async fn send_tcp(&mut self, message: &str) -> Result<(), String> {
+        let mut stream = match self.tcp.take() {
+            Some(stream) => stream,
+            None => tokio::time::timeout(
+                AUDIT_SINK_CONNECT_TIMEOUT,
+                TcpStream::connect(&self.address),
+            )
+            .await
+            .map_err(|_| format!("timed out connecting to '{}'", self.address))?
+            .map_err(|e| e.to_string())?,
+        };
+
+        // Octet counting, so that messages may contain newlines.
+        let frame = format!("{} {}", message.len(), message);
+        stream
+            .write_all(frame.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        // Only keep the connection if it worked, so that the next send reconnects.
+        self.tcp = Some(stream);
+        Ok(())
+    }
+
+    #[cfg(not(target_family = "windows"))]
+    async fn send_unix(&mut self, message: &str) -> Result<(), String> {
+        let socket = match self.unix.take() {
+            Some(socket) => socket,
+            None => tokio::net::UnixDatagram::unbound().map_err(|e| e.to_string())?,
+        };
+
+        socket
+            .send_to(message.as_bytes(), &self.address)
+            .await
+            .map_err(|e| e.to_string())?;
+        self.unix = Some(socket);
+        Ok(())
+    }
+
+    #[cfg(target_family = "windows")]
+    async fn send_unix(&mut self, _message: &str) -> Result<(), String> {
+        Err("unix sockets are not supported on this platform".to_string())
+    }
+}
+
+#[async_trait]
+impl AuditSink for SyslogSink {
+    async fn send(&mut self, record: &AuditRecord) -> Result<(), String> {
+        let message = self.format(record)?;
+        match self.transport {
+            SyslogTransport::Udp => self.send_udp(&message).await,
+            SyslogTransport::Tcp => self.send_tcp(&message).await,
+            SyslogTransport::Unix => self.send_unix(&message).await,
+        }
+    }
+}
+
+struct FileSink {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl FileSink {
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    /// Move the current file to `.1`, `.1` to `.2` and so on, removing the oldest.
+    async fn rotate(&self) -> Result<(), String> {
+        if self.max_files == 0 {
+            return tokio::fs::remove_file(&self.path)
+                .await
+                .map_err(|e| e.to_string());
+        }
+
+        for n in (1..self.max_files).rev() {
+            let from = self.rotated_path(n);
+            if tokio::fs::metadata(&from).await.is_ok() {
+                tokio::fs::rename(&from, self.rotated_path(n + 1))
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        tokio::fs::rename(&self.path, self.rotated_path(1))
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+async fn append_line(path: &Path, line: &str) -> Result<(), String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(|e| format!("unable to write '{}': {}", path.display(), e))?;
+    file.write_all(line.as_bytes())
+        .await
+        .map_err(|e| format!("unable to write '{}': {}", path.display(), e))
+}
+
+async fn file_size(path: &Path) -> u64 {
+    match tokio::fs::metadata(path).await {
+        Ok(md) => md.len(),
+        Err(_) => 0,
+    }
+}
+
+#[async_trait]
+impl AuditSink for FileSink {
+    async fn send(&mut self, record: &AuditRecord) -> Result<(), String> {
+        let mut line = serde_json::to_string(record).map_err(|e| e.to_string())?;
+        line.push('\n');
+
+        let current_size = file_size(&self.path).await;
+        if current_size > 0 && current_size + line.len() as u64 > self.max_size {
+            self.rotate().await?;
+        }
+
+        append_line(&self.path, &line).await
+    }
+}
+
+/// Records that couldn't be sent, stored as json lines in the order they happened.
+struct AuditBuffer {
+    path: PathBuf,
+    max_size: u64,
+}
+
+impl AuditBuffer {
+    async fn is_empty(&self) -> bool {
+        file_size(&self.path).await == 0
+    }
+
+    /// Append a record, unless that would grow the buffer past its size limit.
+    async fn push(&self, record: &AuditRecord) -> Result<(), String> {
+        let mut line = serde_json::to_string(record).map_err(|e| e.to_string())?;
+        line.push('\n');
+
+        if file_size(&self.path).await + line.len() as u64 > self.max_size {
+            return Err(format!("'{}' is full", self.path.display()));
+        }
+
+        append_line(&self.path, &line).await
+    }
+
+    /// Send the buffered records in order. If the sink fails, the records that weren't sent are
+    /// kept for the next attempt.
+    async fn flush(&self, sink: &mut dyn AuditSink) -> Result<usize, String> {
+        let contents = tokio::fs::read_to_string(&self.path)
+            .await
+            .map_err(|e| format!("unable to read '{}': {}", self.path.display(), e))?;
+        let lines: Vec<&str> = contents.lines().collect();
+
+        for (idx, line) in lines.iter().enumerate() {
+            let record: AuditRecord = match serde_json::from_str(line) {
+                Ok(record) => record,
+                Err(e) => {
+                    error!(?e, %line, "Discarding invalid buffered audit record");
+                    continue;
+                }
+            };
+
+            if let Err(e) = sink.send(&record).await {
+                self.replace(&lines[idx..]).await?;
+                return Err(e);
+            }
+        }
+
+        tokio::fs::remove_file(&self.path)
+            .await
+            .map_err(|e| format!("unable to remove '{}': {}", self.path.display(), e))?;
+        Ok(lines.len())
+    }
+
+    async fn replace(&self, lines: &[&str]) -> Result<(), String> {
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        match tokio::fs::write(&tmp, contents).await {
+            Ok(()) => tokio::fs::rename(&tmp, &self.path).await,
+            Err(e) => Err(e),
+        }
+        .map_err(|e| format!("unable to write '{}': {}", self.path.display(), e))
+    }
+}
+
+#[cfg(not(target_family = "windows"))]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if rc != 0 {
+        return "-".to_string();
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    match std::str::from_utf8(&buf[..len]) {
+        Ok(name) if !name.is_empty() => name.to_string(),
+        _ => "-".to_string(),
+    }
+}
+
+#[cfg(target_family = "windows")]
+fn hostname() -> String {
+    "-".to_string()
+}
+
+/// RFC 5424 header fields must be printable ascii without spaces.
+fn valid_header_field(value: &str, max_len: usize) -> bool {
+    !value.is_empty() && value.len() <= max_len && value.bytes().all(|b| (33..=126).contains(&b))
+}
+
+impl AuditSinkConfig {
+    fn build_sink(&self) -> Result<Box<dyn AuditSink>, String> {
+        match &self.target {
+            AuditSinkTarget::Syslog {
+                transport,
+                address,
+                facility,
+                app_name,
+            } => {
+                if !valid_header_field(app_name, 48) {
+                    return Err(format!("invalid syslog app_name '{}'", app_name));
+                }
+                let hostname = hostname();
+                let hostname = if valid_header_field(&hostname, 255) {
+                    hostname
+                } else {
+                    "-".to_string()
+                };
+
+                Ok(Box::new(SyslogSink {
+                    transport: *transport,
+                    address: address.clone(),
+                    facility: *facility,
+                    app_name: app_name.clone(),
+                    hostname,
+                    udp: None,
+                    tcp: None,
+                    #[cfg(not(target_family = "windows"))]
+                    unix: None,
+                }))
+            }
+            AuditSinkTarget::File {
+                path,
+                max_size,
+                max_files,
+            } => {
+                let path = PathBuf::from(path);
+                if let Some(parent) = path.parent() {
+                    if !parent.as_os_str().is_empty() {
+                        fs::create_dir_all(parent).map_err(|e| {
+                            format!("unable to create directory '{}': {}", parent.display(), e)
+                        })?;
+                    }
+                }
+                Ok(Box::new(FileSink {
+                    path,
+                    max_size: *max_size,
+                    max_files: *max_files,
+                }))
+            }
+        }
+    }
+
+    fn buffer(&self, default_dir: Option<&Path>) -> Result<AuditBuffer, String> {
+        let path = match (&self.buffer_path, default_dir) {
+            (Some(path), _) => PathBuf::from(path),
+            (None, Some(dir)) => dir.join(format!("audit_sink_{}.buffer", self.name)),
+            (None, None) => return Err("buffer_path must be set".to_string()),
+        };
+        Ok(AuditBuffer {
+            path,
+            max_size: self.buffer_max_size,
+        })
+    }
+}
+
+/// The running audit sinks. Records are handed to each sink's task, so that a slow sink can't
+/// hold up the others.
+pub struct AuditSinks {
+    senders: Vec<(String, mpsc::UnboundedSender<AuditRecord>)>,
+}
+
+impl AuditSinks {
+    pub fn start(
+        configs: &[AuditSinkConfig],
+        default_buffer_dir: Option<&Path>,
+        broadcast_tx: &broadcast::Sender<CoreAction>,
+    ) -> Result<(Self, Vec<tokio::task::JoinHandle<()>>), ()> {
+        let mut senders = Vec::with_capacity(configs.len());
+        let mut handles = Vec::with_capacity(configs.len());
+
+        for config in configs {
+            let valid_name = !config.name.is_empty()
+                && config
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_name {
+                error!(name = %config.name, "Audit sink names may only contain letters, numbers, - and _");
+                return Err(());
+            }
+            if senders.iter().any(|(name, _)| name == &config.name) {
+                error!(name = %config.name, "Audit sink names must be unique");
+                return Err(());
+            }
+
+            let sink = config.build_sink().map_err(|e| {
+                error!(name = %config.name, "Unable to configure audit sink: {}", e);
+            })?;
+            let buffer = config.buffer(default_buffer_dir).map_err(|e| {
+                error!(name = %config.name, "Unable to configure audit sink: {}", e);
+            })?;
+
+            if matches!(
+                config.target,
+                AuditSinkTarget::Syslog {
+                    transport: SyslogTransport::Udp,
+                    ..
+                }
+            ) {
+                warn!(name = %config.name, "Audit sink uses udp, records will be lost if the syslog server is unavailable");
+            }
+
+            let (tx, rx) = mpsc::unbounded_channel();
+            handles.push(tokio::spawn(run_sink(
+                config.name.clone(),
+                sink,
+                buffer,
+                rx,
+                broadcast_tx.subscribe(),
+            )));
+            senders.push((config.name.clone(), tx));
+        }
+
+        Ok((AuditSinks { senders }, handles))
+    }
+
+    pub fn send(&self, event: &AuditEvent) {
+        if self.senders.is_empty() {
+            return;
+        }
+
+        let record = AuditRecord::from(event);
+        for (name, tx) in self.senders.iter() {
+            if tx.send(record.clone()).is_err() {
+                error!(%name, id = %record.id, "Audit sink has stopped, unable to export record");
+            }
+        }
+    }
+}
+
+async fn run_sink(
+    name: String,
+    mut sink: Box<dyn AuditSink>,
+    buffer: AuditBuffer,
+    mut rx: mpsc::UnboundedReceiver<AuditRecord>,
+    mut shutdown_rx: broadcast::Receiver<CoreAction>,
+) {
+    let mut retry = tokio::time::interval(AUDIT_SINK_RETRY_INTERVAL);
+
+    loop {
+        tokio::select! {
+            Ok(action) = shutdown_rx.recv() => {
+                match action {
+                    CoreAction::Shutdown => break,
+                }
+            }
+            record = rx.recv() => {
+                let record = match record {
+                    Some(record) => record,
+                    // Channel has closed, stop the task.
+                    None => break,
+                };
+                // Records are kept in order, so while anything is buffered new records queue
+                // behind it until the next retry.
+                if !buffer.is_empty().await {
+                    buffer_record(&name, &buffer, &record).await;
+                } else if let Err(e) = sink.send(&record).await {
+                    warn!(%name, "Audit sink unavailable, buffering records: {}", e);
+                    buffer_record(&name, &buffer, &record).await;
+                }
+            }
+            _ = retry.tick() => {
+                if buffer.is_empty().await {
+                    continue;
+                }
+                match buffer.flush(sink.as_mut()).await {
+                    Ok(count) => info!(%name, %count, "Sent buffered audit records"),
+                    Err(e) => warn!(%name, "Audit sink still unavailable: {}", e),
+                }
+            }
+        }
+    }
+
+    // Anything not yet sent is kept for when the server next starts.
+    while let Ok(record) = rx.try_recv() {
+        buffer_record(&name, &buffer, &record).await;
+    }
+
+    info!(%name, "Stopped AuditSinkActor");
+}
+
+async fn buffer_record(name: &str, buffer: &AuditBuffer, record: &AuditRecord) {
+    if let Err(e) = buffer.push(record).await {
+        // Last resort, so that the record can at least be recovered from the server log.
+        error!(%name, ?record, "Unable to buffer audit record: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use async_trait::async_trait;
+    use time::OffsetDateTime;
+    use uuid::Uuid;
+
+    use super::{
+        AuditBuffer, AuditOutcome, AuditRecord, AuditSink, FileSink, SyslogFacility, SyslogSink,
+        SyslogTransport, AUDIT_RECORD_SCHEMA_VERSION,
+    };
+
+    fn test_record(kind: &str, outcome: AuditOutcome) -> AuditRecord {
+        AuditRecord {
+            schema: AUDIT_RECORD_SCHEMA_VERSION,
+            id: Uuid::new_v4(),
+            // 2023-06-02T04:11:43.120518789Z
+            time: OffsetDateTime::from_unix_timestamp_nanos(1_685_679_103_120_518_789)
+                .expect("Invalid time"),
+            kind: kind.to_string(),
+            outcome,
+            actor: None,
+            target: Uuid::new_v4(),
+            source: Some("192.0.2.1".to_string()),
+            event: serde_json::json!({ "label": "line one\nline two" }),
+        }
+    }
+
+    fn test_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kanidm_audit_test_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("Unable to create test directory");
+        dir
+    }
+
+    /// Records what it is sent, and fails once it has accepted a given number of records.
+    struct TestSink {
+        sent: Vec<Uuid>,
+        fail_after: Option<usize>,
+    }
+
+    #[async_trait]
+    impl AuditSink for TestSink {
+        async fn send(&mut self, record: &AuditRecord) -> Result<(), String> {
+            if self.fail_after == Some(self.sent.len()) {
+                return Err("unavailable".to_string());
+            }
+            self.sent.push(record.id);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_audit_syslog_format() {
+        let sink = SyslogSink {
+            transport: SyslogTransport::Udp,
+            address: "127.0.0.1:514".to_string(),
+            facility: SyslogFacility::Authpriv,
+            app_name: "kanidmd".to_string(),
+            hostname: "idm1".to_string(),
+            udp: None,
+            tcp: None,
+            #[cfg(not(target_family = "windows"))]
+            unix: None,
+        };
+
+        let record = test_record("authentication_denied", AuditOutcome::Failure);
+        let message = sink.format(&record).expect("Unable to format record");
+
+        // authpriv (10) * 8 + warning (4), and the time truncated to microseconds.
+        let header = format!(
+            "<84>1 2023-06-02T04:11:43.120518Z idm1 kanidmd {} authentication_denied - {{",
+            std::process::id()
+        );
+        assert!(message.starts_with(&header));
+        // A line break in the event can't split the message.
+        assert!(!message.contains('\n'));
+        let body = message.split_once(" - ").expect("Missing body").1;
+        let parsed: AuditRecord = serde_json::from_str(body).expect("Invalid body");
+        assert_eq!(parsed.id, record.id);
+
+        // Successes are notices, and a kind that isn't a valid header field is left out.
+        let record = test_record("not a msgid", AuditOutcome::Success);
+        let message = sink.format(&record).expect("Unable to format record");
+        assert!(message.starts_with("<85>1 "));
+        assert!(message.contains(&format!(" {} - - {{", std::process::id())));
+    }
+
+    #[tokio::test]
+    async fn test_audit_file_sink_rotation() {
+        let dir = test_dir();
+        let path = dir.join("audit.jsonl");
+
+        let record = test_record("authentication_denied", AuditOutcome::Failure);
+        let line_len = serde_json::to_string(&record)
+            .expect("Invalid record")
+            .len() as u64
+            + 1;
+
+        // Two records fit in each file.
+        let mut sink = FileSink {
+            path: path.clone(),
+            max_size: line_len * 2,
+            max_files: 2,
+        };
+
+        let mut ids = Vec::new();
+        for _ in 0..7 {
+            let record = test_record("authentication_denied", AuditOutcome::Failure);
+            ids.push(record.id);
+            sink.send(&record).await.expect("Unable to write record");
+        }
+
+        let read_ids = |path: PathBuf| -> Vec<Uuid> {
+            std::fs::read_to_string(path)
+                .expect("Unable to read file")
+                .lines()
+                .map(|line| {
+                    serde_json::from_str::<AuditRecord>(line)
+                        .expect("Invalid record")
+                        .id
+                })
+                .collect()
+        };
+
+        // The oldest records were rotated out.
+        assert_eq!(read_ids(path.clone()), ids[6..]);
+        assert_eq!(read_ids(sink.rotated_path(1)), ids[4..6]);
+        assert_eq!(read_ids(sink.rotated_path(2)), ids[2..4]);
+        assert!(!sink.rotated_path(3).exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_audit_buffer_flush_order() {
+        let dir = test_dir();
+        let buffer = AuditBuffer {
+            path: dir.join("sink.buffer"),
+            max_size: 64 * 1024,
+        };
+        assert!(buffer.is_empty().await);
+
+        let mut ids = Vec::new();
+        for _ in 0..4 {
+            let record = test_record("authentication_denied", AuditOutcome::Failure);
+            ids.push(record.id);
+            buffer.push(&record).await.expect("Unable to buffer record");
+        }
+
+        // The sink fails part way, so only the records it didn't accept are kept.
+        let mut sink = TestSink {
+            sent: Vec::new(),
+            fail_after: Some(2),
+        };
+        assert!(buffer.flush(&mut sink).await.is_err());
+        assert_eq!(sink.sent, ids[..2]);
+        assert!(!buffer.is_empty().await);
+
+        // New records queue behind those that are already buffered.
+        let record = test_record("authentication_denied", AuditOutcome::Failure);
+        ids.push(record.id);
+        buffer.push(&record).await.expect("Unable to buffer record");
+
+        let mut sink = TestSink {
+            sent: Vec::new(),
+            fail_after: None,
+        };
+        assert_eq!(buffer.flush(&mut sink).await, Ok(3));
+        assert_eq!(sink.sent, ids[2..]);
+        assert!(buffer.is_empty().await);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_audit_buffer_max_size() {
+        let dir = test_dir();
+        let record = test_record("authentication_denied", AuditOutcome::Failure);
+        let line_len = serde_json::to_string(&record)
+            .expect("Invalid record")
+            .len() as u64
+            + 1;
+
+        let buffer = AuditBuffer {
+            path: dir.join("sink.buffer"),
+            max_size: line_len * 2,
+        };
+        buffer.push(&record).await.expect("Unable to buffer record");
+        buffer.push(&record).await.expect("Unable to buffer record");
+        assert!(buffer.push(&record).await.is_err());
+
+        let mut sink = TestSink {
+            sent: Vec::new(),
+            fail_after: None,
+        };
+        assert_eq!(buffer.flush(&mut sink).await, Ok(2));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
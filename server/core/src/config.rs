@@ -12,8 +12,13 @@ use std::path::Path;
 use std::str::FromStr;
 
 use kanidm_proto::messages::ConsoleOutputMode;
+pub use kanidmd_lib::be::{BackupCompression, BackupFormat, BackupOptions, RestoreOptions};
+use kanidmd_lib::idm::sourcelock::SourceLockPolicy;
 use serde::{Deserialize, Serialize};
 
+use crate::audit::AuditSinkConfig;
+use crate::messages::MessageConfig;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct IntegrationTestConfig {
     pub admin_user: String,
@@ -27,6 +32,9 @@ pub struct OnlineBackup {
     pub schedule: String,
     #[serde(default = "default_online_backup_versions")]
     pub versions: usize,
+    /// The format, compression and encryption of each backup.
+    #[serde(flatten)]
+    pub options: BackupOptions,
 }
 
 fn default_online_backup_schedule() -> String {
@@ -37,6 +45,18 @@ fn default_online_backup_versions() -> usize {
     7
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditLogConfig {
+    pub path: String,
+    /// How many days audit events are kept for.
+    #[serde(default = "default_audit_log_retention_days")]
+    pub retention_days: u32,
+}
+
+fn default_audit_log_retention_days() -> u32 {
+    90
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TlsConfiguration {
     pub chain: String,
@@ -47,6 +67,7 @@ pub struct TlsConfiguration {
 pub struct ServerConfig {
     pub bindaddress: Option<String>,
     pub ldapbindaddress: Option<String>,
+    pub ldapstarttlsbindaddress: Option<String>,
     pub trust_x_forward_for: Option<bool>,
     // pub threads: Option<usize>,
     pub db_path: String,
@@ -55,6 +76,11 @@ pub struct ServerConfig {
     pub tls_chain: Option<String>,
     pub tls_key: Option<String>,
     pub online_backup: Option<OnlineBackup>,
+    pub messages: Option<MessageConfig>,
+    pub audit_log: Option<AuditLogConfig>,
+    #[serde(default)]
+    pub audit_sinks: Vec<AuditSinkConfig>,
+    pub source_lock: Option<SourceLockPolicy>,
     pub domain: String,
     pub origin: String,
     #[serde(default)]
@@ -110,6 +136,7 @@ impl FromStr for ServerRole {
 pub struct Configuration {
     pub address: String,
     pub ldapaddress: Option<String>,
+    pub ldapstarttlsaddress: Option<String>,
     pub threads: usize,
     // db type later
     pub db_path: String,
@@ -121,6 +148,10 @@ pub struct Configuration {
     pub tls_config: Option<TlsConfiguration>,
     pub integration_test_config: Option<Box<IntegrationTestConfig>>,
     pub online_backup: Option<OnlineBackup>,
+    pub messages: Option<MessageConfig>,
+    pub audit_log: Option<AuditLogConfig>,
+    pub audit_sinks: Vec<AuditSinkConfig>,
+    pub source_lock: SourceLockPolicy,
     pub domain: String,
     pub origin: String,
     pub role: ServerRole,
@@ -135,6 +166,10 @@ impl fmt::Display for Configuration {
                 Some(la) => write!(f, "ldap address: {}, ", la),
                 None => write!(f, "ldap address: disabled, "),
             })
+            .and_then(|_| match &self.ldapstarttlsaddress {
+                Some(la) => write!(f, "ldap starttls address: {}, ", la),
+                None => write!(f, "ldap starttls address: disabled, "),
+            })
             .and_then(|_| write!(f, "thread count: {}, ", self.threads))
             .and_then(|_| write!(f, "dbpath: {}, ", self.db_path))
             .and_then(|_| match self.db_arc_size {
@@ -150,6 +185,22 @@ impl fmt::Display for Configuration {
                 Some(_) => write!(f, "online_backup: enabled, "),
                 None => write!(f, "online_backup: disabled, "),
             })
+            .and_then(|_| match &self.messages {
+                Some(_) => write!(f, "message delivery: enabled, "),
+                None => write!(f, "message delivery: disabled, "),
+            })
+            .and_then(|_| match &self.audit_log {
+                Some(al) => write!(f, "audit log: {} ({} days), ", al.path, al.retention_days),
+                None => write!(f, "audit log: default, "),
+            })
+            .and_then(|_| write!(f, "audit sinks: {}, ", self.audit_sinks.len()))
+            .and_then(|_| {
+                write!(
+                    f,
+                    "source lock allowlist: {}, ",
+                    self.source_lock.allowlist.len()
+                )
+            })
             .and_then(|_| write!(f, "role: {}, ", self.role.to_string()))
             .and_then(|_| {
                 write!(
@@ -167,6 +218,7 @@ impl Configuration {
         Configuration {
             address: String::from("127.0.0.1:8080"),
             ldapaddress: None,
+            ldapstarttlsaddress: None,
             threads: std::thread::available_parallelism()
                 .map(|t| t.get())
                 .unwrap_or_else(|_e| {
@@ -184,6 +236,10 @@ impl Configuration {
             tls_config: None,
             integration_test_config: None,
             online_backup: None,
+            messages: None,
+            audit_log: None,
+            audit_sinks: Vec::new(),
+            source_lock: SourceLockPolicy::default(),
             domain: "idm.example.com".to_string(),
             origin: "https://idm.example.com".to_string(),
             role: ServerRole::WriteReplica,
@@ -198,15 +254,58 @@ impl Configuration {
                 let path = cfg.path.to_string();
                 let schedule = cfg.schedule.to_string();
                 let versions = cfg.versions;
+                let options = cfg.options.clone();
                 self.online_backup = Some(OnlineBackup {
                     path,
                     schedule,
                     versions,
+                    options,
                 })
             }
         }
     }
 
+    pub fn update_messages(&mut self, cfg: &Option<MessageConfig>) {
+        self.messages = cfg.clone();
+    }
+
+    pub fn update_audit_log(&mut self, cfg: &Option<AuditLogConfig>) {
+        self.audit_log = cfg.clone();
+    }
+
+    pub fn update_audit_sinks(&mut self, cfg: &[AuditSinkConfig]) {
+        self.audit_sinks = cfg.to_vec();
+    }
+
+    pub fn update_source_lock(&mut self, cfg: &Option<SourceLockPolicy>) {
+        self.source_lock = cfg.clone().unwrap_or_default();
+    }
+
+    /// Where audit sinks keep records they couldn't send, unless they set their own path.
+    pub fn audit_sink_buffer_dir(&self) -> Option<&Path> {
+        if self.db_path.is_empty() {
+            None
+        } else {
+            Path::new(&self.db_path).parent()
+        }
+    }
+
+    /// The audit log to use. If it isn't configured the log is kept next to the database, with
+    /// the default retention.
+    pub fn audit_log_config(&self) -> AuditLogConfig {
+        self.audit_log.clone().unwrap_or_else(|| AuditLogConfig {
+            path: if self.db_path.is_empty() {
+                String::new()
+            } else {
+                Path::new(&self.db_path)
+                    .with_file_name("audit.db")
+                    .to_string_lossy()
+                    .to_string()
+            },
+            retention_days: default_audit_log_retention_days(),
+        })
+    }
+
     // Startup config action, used in kanidmd server etc
     pub fn update_config_for_server_mode(&mut self, sconfig: &ServerConfig) {
         #[cfg(debug_assertions)]
@@ -214,7 +313,12 @@ impl Configuration {
         self.update_tls(&sconfig.tls_chain, &sconfig.tls_key);
         self.update_bind(&sconfig.bindaddress);
         self.update_ldapbind(&sconfig.ldapbindaddress);
+        self.update_ldapstarttlsbind(&sconfig.ldapstarttlsbindaddress);
         self.update_online_backup(&sconfig.online_backup);
+        self.update_messages(&sconfig.messages);
+        self.update_audit_log(&sconfig.audit_log);
+        self.update_audit_sinks(&sconfig.audit_sinks);
+        self.update_source_lock(&sconfig.source_lock);
     }
 
     pub fn update_trust_x_forward_for(&mut self, t: Option<bool>) {
@@ -244,6 +348,10 @@ impl Configuration {
         self.ldapaddress = l.clone();
     }
 
+    pub fn update_ldapstarttlsbind(&mut self, l: &Option<String>) {
+        self.ldapstarttlsaddress = l.clone();
+    }
+
     pub fn update_origin(&mut self, o: &str) {
         self.origin = o.to_string();
     }
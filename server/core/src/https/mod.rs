@@ -568,22 +568,22 @@ pub async fn create_https_server(
     schema_route
         .at("/attributetype")
         .mapped_get(&mut routemap, schema_attributetype_get)
-        .mapped_post(&mut routemap, do_nothing);
+        .mapped_post(&mut routemap, schema_attributetype_post);
     schema_route
         .at("/attributetype/:id")
         .mapped_get(&mut routemap, schema_attributetype_get_id)
-        .mapped_put(&mut routemap, do_nothing)
-        .mapped_patch(&mut routemap, do_nothing);
+        .mapped_patch(&mut routemap, schema_attributetype_patch_id)
+        .mapped_delete(&mut routemap, schema_attributetype_delete_id);
 
     schema_route
         .at("/classtype")
         .mapped_get(&mut routemap, schema_classtype_get)
-        .mapped_post(&mut routemap, do_nothing);
+        .mapped_post(&mut routemap, schema_classtype_post);
     schema_route
         .at("/classtype/:id")
         .mapped_get(&mut routemap, schema_classtype_get_id)
-        .mapped_put(&mut routemap, do_nothing)
-        .mapped_patch(&mut routemap, do_nothing);
+        .mapped_patch(&mut routemap, schema_classtype_patch_id)
+        .mapped_delete(&mut routemap, schema_classtype_delete_id);
 
     let mut oauth2_route = appserver.at("/v1/oauth2");
     oauth2_route.at("/").mapped_get(&mut routemap, oauth2_get);
@@ -614,6 +614,15 @@ pub async fn create_https_server(
         .mapped_post(&mut routemap, oauth2_id_sup_scopemap_post)
         .mapped_delete(&mut routemap, oauth2_id_sup_scopemap_delete);
 
+    oauth2_route
+        .at("/:id/_token_exchange/:audience")
+        .mapped_post(&mut routemap, oauth2_id_token_exchange_post)
+        .mapped_delete(&mut routemap, oauth2_id_token_exchange_delete);
+
+    oauth2_route
+        .at("/:id/_client_jwks_refresh")
+        .mapped_post(&mut routemap, oauth2_id_client_jwks_refresh_post);
+
     let mut self_route = appserver.at("/v1/self");
     self_route.at("/").mapped_get(&mut routemap, whoami);
     self_route.at("/_uat").mapped_get(&mut routemap, whoami_uat);
@@ -650,6 +659,11 @@ pub async fn create_https_server(
         .at("/_applinks")
         .mapped_get(&mut routemap, applinks_get);
 
+    // Notifications about security relevant events on this account.
+    self_route
+        .at("/_notifications")
+        .mapped_get(&mut routemap, notifications_get);
+
     let mut person_route = appserver.at("/v1/person");
     person_route
         .at("/")
@@ -688,6 +702,12 @@ pub async fn create_https_server(
     person_route
         .at("/:id/_credential/_update_intent/:ttl")
         .mapped_get(&mut routemap, account_get_id_credential_update_intent);
+    person_route
+        .at("/:id/_credential/_update_intent_send")
+        .mapped_post(&mut routemap, account_post_id_credential_update_intent_send);
+    person_route
+        .at("/:id/_credential/_update_intent_send/:ttl")
+        .mapped_post(&mut routemap, account_post_id_credential_update_intent_send);
 
     person_route
         .at("/:id/_ssh_pubkeys")
@@ -773,6 +793,17 @@ pub async fn create_https_server(
         .at("/:id/_unix")
         .mapped_post(&mut routemap, account_post_id_unix);
 
+    service_account_route
+        .at("/:id/_oauth2_registration_scopemap/:group")
+        .mapped_post(
+            &mut routemap,
+            service_account_id_oauth2_registration_scopemap_post,
+        )
+        .mapped_delete(
+            &mut routemap,
+            service_account_id_oauth2_registration_scopemap_delete,
+        );
+
     // Shared account features only - mainly this is for unix-like
     // features.
     let mut account_route = appserver.at("/v1/account");
@@ -848,6 +879,26 @@ pub async fn create_https_server(
         .mapped_get(&mut routemap, system_get_attr)
         .mapped_post(&mut routemap, system_post_attr)
         .mapped_delete(&mut routemap, system_delete_attr);
+    system_route
+        .at("/_source_lock")
+        .mapped_get(&mut routemap, source_lock_get);
+    system_route
+        .at("/_source_lock/*id")
+        .mapped_delete(&mut routemap, source_lock_id_delete);
+    system_route
+        .at("/_password_kdf_report")
+        .mapped_get(&mut routemap, password_kdf_report_get);
+
+    let mut message_route = appserver.at("/v1/message");
+    message_route.at("/").mapped_get(&mut routemap, message_get);
+    message_route
+        .at("/:id")
+        .mapped_get(&mut routemap, message_id_get)
+        .mapped_delete(&mut routemap, message_id_delete);
+
+    appserver
+        .at("/v1/audit")
+        .mapped_get(&mut routemap, audit_log_get);
 
     let mut recycle_route = appserver.at("/v1/recycle_bin");
     recycle_route
@@ -1,8 +1,10 @@
 use kanidm_proto::oauth2::AuthorisationResponse;
 use kanidm_proto::v1::Entry as ProtoEntry;
 use kanidmd_lib::idm::oauth2::{
-    AccessTokenIntrospectRequest, AccessTokenRequest, AuthorisationRequest, AuthorisePermitSuccess,
-    AuthoriseResponse, ErrorResponse, Oauth2Error, TokenRevokeRequest,
+    AccessTokenIntrospectRequest, AccessTokenRequest, AuthorisationRequestKind,
+    AuthorisePermitSuccess, AuthoriseResponse, ClientRegistrationRequest,
+    ClientRegistrationResponse, DeviceAuthorisationRequest, EndSessionRequest, ErrorResponse,
+    Oauth2Error, PushedAuthorisationRequest, TokenRevokeRequest,
 };
 use kanidmd_lib::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -159,6 +161,53 @@ pub async fn oauth2_id_sup_scopemap_delete(req: tide::Request<AppState>) -> tide
     to_tide_response(res, hvalue)
 }
 
+pub async fn oauth2_id_token_exchange_post(req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let id = req.get_url_param("id")?;
+    let audience = req.get_url_param("audience")?;
+
+    let filter = oauth2_id(&id);
+
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_token_exchange_audience_add(uat, audience, filter, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
+pub async fn oauth2_id_token_exchange_delete(req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let id = req.get_url_param("id")?;
+    let audience = req.get_url_param("audience")?;
+
+    let filter = oauth2_id(&id);
+
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_token_exchange_audience_delete(uat, audience, filter, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
+pub async fn oauth2_id_client_jwks_refresh_post(req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let id = req.get_url_param("id")?;
+
+    let filter = oauth2_id(&id);
+
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_client_jwks_refresh(uat, filter, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
 pub async fn oauth2_id_delete(req: tide::Request<AppState>) -> tide::Result {
     // Delete this
     let uat = req.get_current_uat();
@@ -238,7 +287,7 @@ pub async fn oauth2_id_delete(req: tide::Request<AppState>) -> tide::Result {
 //
 
 pub async fn oauth2_authorise_post(mut req: tide::Request<AppState>) -> tide::Result {
-    let auth_req: AuthorisationRequest = req.body_json().await?;
+    let auth_req: AuthorisationRequestKind = req.body_json().await?;
     oauth2_authorise(req, auth_req).await.map(|mut res| {
         if res.status() == 302 {
             // in post, we need the redirect not to be issued, so we mask 302 to 200
@@ -252,7 +301,7 @@ pub async fn oauth2_authorise_get(req: tide::Request<AppState>) -> tide::Result
     // Start the oauth2 authorisation flow to present to the user.
     debug!("Request Query - {:?}", req.url().query());
     // Get the authorisation request.
-    let auth_req: AuthorisationRequest = req.query().map_err(|e| {
+    let auth_req: AuthorisationRequestKind = req.query().map_err(|e| {
         error!("{:?}", e);
         tide::Error::from_str(
             tide::StatusCode::BadRequest,
@@ -265,7 +314,7 @@ pub async fn oauth2_authorise_get(req: tide::Request<AppState>) -> tide::Result
 
 async fn oauth2_authorise(
     req: tide::Request<AppState>,
-    auth_req: AuthorisationRequest,
+    auth_req: AuthorisationRequestKind,
 ) -> tide::Result {
     let uat = req.get_current_uat();
     let (eventid, hvalue) = req.new_eventid();
@@ -361,6 +410,69 @@ async fn oauth2_authorise(
     })
 }
 
+// Pushed authorisation requests allow the client to send the authorisation request
+// directly to us, rather than through the user agent. The client is given a request_uri
+// which it then sends the user agent to the authorisation endpoint with. Like the device
+// flow, the pushed request is held in memory on this server.
+// https://datatracker.ietf.org/doc/html/rfc9126
+
+pub async fn oauth2_par_post(mut req: tide::Request<AppState>) -> tide::Result {
+    // This is called directly by the resource server.
+    let (eventid, hvalue) = req.new_eventid();
+
+    let client_authz = req
+        .header("authorization")
+        .and_then(|hv| hv.get(0))
+        .and_then(|h| h.as_str().strip_prefix("Basic "))
+        .map(str::to_string);
+
+    let par_req: PushedAuthorisationRequest = req.body_form().await.map_err(|e| {
+        request_error!("{:?}", e);
+        tide::Error::from_str(
+            tide::StatusCode::BadRequest,
+            "Invalid Oauth2 PushedAuthorisationRequest",
+        )
+    })?;
+
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_pushed_authorisation(client_authz, par_req, eventid)
+        .await;
+
+    match res {
+        Ok(par) => {
+            // https://datatracker.ietf.org/doc/html/rfc9126#section-2.2
+            let mut res = tide::Response::new(201);
+            tide::Body::from_json(&par).map(|b| {
+                res.set_body(b);
+                res
+            })
+        }
+        Err(Oauth2Error::AuthenticationRequired) => {
+            Ok(tide::Response::new(tide::StatusCode::Unauthorized))
+        }
+        Err(e) => {
+            // https://datatracker.ietf.org/doc/html/rfc9126#section-2.3
+            let err = ErrorResponse {
+                error: e.to_string(),
+                error_description: None,
+                error_uri: None,
+            };
+
+            let mut res = tide::Response::new(400);
+            tide::Body::from_json(&err).map(|b| {
+                res.set_body(b);
+                res
+            })
+        }
+    }
+    .map(|mut res| {
+        res.insert_header("X-KANIDM-OPID", hvalue);
+        res
+    })
+}
+
 pub async fn oauth2_authorise_permit_post(mut req: tide::Request<AppState>) -> tide::Result {
     let consent_req: String = req.body_json().await?;
     oauth2_authorise_permit(req, consent_req)
@@ -572,6 +684,159 @@ pub async fn oauth2_token_post(mut req: tide::Request<AppState>) -> tide::Result
     })
 }
 
+// == OAUTH2 DEVICE AUTHORISATION FLOW HANDLERS ==
+// https://datatracker.ietf.org/doc/html/rfc8628
+//
+// The device (client) starts the flow with oauth2_device_post, and then polls
+// oauth2_token_post with the device code. Meanwhile the user visits the
+// verification uri in the web ui, and enters their user code which is submitted
+// to oauth2_device_authorise_post. This returns the same consent request as the
+// authorisation code flow, which the user permits or rejects. Unlike the
+// authorisation code flow, the pending state is held in memory on this server.
+
+pub async fn oauth2_device_post(mut req: tide::Request<AppState>) -> tide::Result {
+    // This is called directly by the device.
+    let (eventid, hvalue) = req.new_eventid();
+
+    let client_authz = req
+        .header("authorization")
+        .and_then(|hv| hv.get(0))
+        .and_then(|h| h.as_str().strip_prefix("Basic "))
+        .map(str::to_string);
+
+    let device_req: DeviceAuthorisationRequest = req.body_form().await.map_err(|e| {
+        request_error!("{:?}", e);
+        tide::Error::from_str(
+            tide::StatusCode::BadRequest,
+            "Invalid Oauth2 DeviceAuthorisationRequest",
+        )
+    })?;
+
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_device_authorise(client_authz, device_req, eventid)
+        .await;
+
+    match res {
+        Ok(dar) => {
+            let mut res = tide::Response::new(200);
+            tide::Body::from_json(&dar).map(|b| {
+                res.set_body(b);
+                res
+            })
+        }
+        Err(Oauth2Error::AuthenticationRequired) => {
+            Ok(tide::Response::new(tide::StatusCode::Unauthorized))
+        }
+        Err(e) => {
+            // https://datatracker.ietf.org/doc/html/rfc8628#section-3.2
+            let err = ErrorResponse {
+                error: e.to_string(),
+                error_description: None,
+                error_uri: None,
+            };
+
+            let mut res = tide::Response::new(400);
+            tide::Body::from_json(&err).map(|b| {
+                res.set_body(b);
+                res
+            })
+        }
+    }
+    .map(|mut res| {
+        res.insert_header("X-KANIDM-OPID", hvalue);
+        res
+    })
+}
+
+pub async fn oauth2_device_authorise_post(mut req: tide::Request<AppState>) -> tide::Result {
+    let user_code: String = req.body_json().await?;
+    let uat = req.get_current_uat();
+    let (eventid, hvalue) = req.new_eventid();
+
+    let res = req
+        .state()
+        .qe_r_ref
+        .handle_oauth2_device_authorisation(uat, user_code, eventid)
+        .await;
+
+    match res {
+        Ok(AuthoriseResponse::ConsentRequested {
+            client_name,
+            scopes,
+            pii_scopes,
+            consent_token,
+        }) => {
+            let mut res = tide::Response::new(200);
+            tide::Body::from_json(&AuthorisationResponse::ConsentRequested {
+                client_name,
+                scopes,
+                pii_scopes,
+                consent_token,
+            })
+            .map(|b| {
+                res.set_body(b);
+                res
+            })
+        }
+        Ok(AuthoriseResponse::Permitted(_)) => {
+            // Device authorisation always requires consent.
+            Ok(tide::Response::new(tide::StatusCode::InternalServerError))
+        }
+        Err(Oauth2Error::AuthenticationRequired) => {
+            // This will trigger our ui to auth and retry.
+            let mut res = tide::Response::new(tide::StatusCode::Unauthorized);
+            res.insert_header("WWW-Authenticate", "Bearer");
+            Ok(res)
+        }
+        Err(Oauth2Error::AccessDenied) => {
+            // If scopes are not available for this account.
+            Ok(tide::Response::new(tide::StatusCode::Forbidden))
+        }
+        Err(e) => {
+            admin_error!(
+                "Unable to authorise device - Error ID: {} error: {}",
+                &hvalue,
+                &e.to_string()
+            );
+            Ok(tide::Response::new(tide::StatusCode::BadRequest))
+        }
+    }
+    .map(|mut res| {
+        res.insert_header("X-KANIDM-OPID", hvalue);
+        res
+    })
+}
+
+pub async fn oauth2_device_permit_post(mut req: tide::Request<AppState>) -> tide::Result {
+    let consent_req: String = req.body_json().await?;
+    let uat = req.get_current_uat();
+    let (eventid, hvalue) = req.new_eventid();
+
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_device_permit(uat, consent_req, eventid)
+        .await;
+
+    to_tide_response(res, hvalue)
+}
+
+pub async fn oauth2_device_reject_post(mut req: tide::Request<AppState>) -> tide::Result {
+    let consent_req: String = req.body_json().await?;
+    let uat = req.get_current_uat();
+    let (eventid, hvalue) = req.new_eventid();
+
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_device_reject(uat, consent_req, eventid)
+        .await;
+
+    to_tide_response(res, hvalue)
+}
+
 // For future openid integration
 pub async fn oauth2_openid_discovery_get(req: tide::Request<AppState>) -> tide::Result {
     let (eventid, hvalue) = req.new_eventid();
@@ -650,6 +915,83 @@ pub async fn oauth2_openid_publickey_get(req: tide::Request<AppState>) -> tide::
     to_tide_response(res, hvalue)
 }
 
+pub async fn oauth2_openid_end_session_get(req: tide::Request<AppState>) -> tide::Result {
+    let end_session_req: EndSessionRequest = req.query().map_err(|e| {
+        error!("{:?}", e);
+        tide::Error::from_str(
+            tide::StatusCode::BadRequest,
+            "Invalid Oauth2 EndSessionRequest",
+        )
+    })?;
+
+    oauth2_openid_end_session(req, end_session_req).await
+}
+
+pub async fn oauth2_openid_end_session_post(mut req: tide::Request<AppState>) -> tide::Result {
+    let end_session_req: EndSessionRequest = req.body_form().await.map_err(|e| {
+        error!("{:?}", e);
+        tide::Error::from_str(
+            tide::StatusCode::BadRequest,
+            "Invalid Oauth2 EndSessionRequest",
+        )
+    })?;
+
+    oauth2_openid_end_session(req, end_session_req).await
+}
+
+// https://openid.net/specs/openid-connect-rpinitiated-1_0.html
+async fn oauth2_openid_end_session(
+    mut req: tide::Request<AppState>,
+    end_session_req: EndSessionRequest,
+) -> tide::Result {
+    let uat = req.get_current_uat();
+    let (eventid, hvalue) = req.new_eventid();
+    let client_id = req.get_url_param("client_id")?;
+
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_end_session(uat, client_id, end_session_req, eventid)
+        .await;
+
+    match res {
+        Ok(post_logout_redirect_uri) => {
+            // As with /v1/logout, the session cookies go too.
+            let msession = req.session_mut();
+            msession.remove("auth-session-id");
+            msession.remove("bearer");
+
+            let mut res = tide::Response::new(302);
+            match post_logout_redirect_uri {
+                Some(post_logout_redirect_uri) => {
+                    res.insert_header("Location", post_logout_redirect_uri.as_str())
+                }
+                None => res.insert_header("Location", "/ui/login"),
+            };
+            Ok(res)
+        }
+        Err(e) => {
+            // As with authorisation, we must not redirect to a uri we could not validate, so
+            // the error is rendered here.
+            let err = ErrorResponse {
+                error: e.to_string(),
+                error_description: None,
+                error_uri: None,
+            };
+
+            let mut res = tide::Response::new(400);
+            tide::Body::from_json(&err).map(|b| {
+                res.set_body(b);
+                res
+            })
+        }
+    }
+    .map(|mut res| {
+        res.insert_header("X-KANIDM-OPID", hvalue);
+        res
+    })
+}
+
 pub async fn oauth2_token_introspect_post(mut req: tide::Request<AppState>) -> tide::Result {
     // This is called directly by the resource server, where we then issue
     // information about this token to the caller.
@@ -780,6 +1122,128 @@ pub async fn oauth2_token_revoke_post(mut req: tide::Request<AppState>) -> tide:
     })
 }
 
+// https://datatracker.ietf.org/doc/html/rfc7591
+// The bearer is an api token of a service account with oauth2 registration origins.
+pub async fn oauth2_register_post(mut req: tide::Request<AppState>) -> tide::Result {
+    let token = req.get_auth_bearer();
+    let (eventid, hvalue) = req.new_eventid();
+
+    let reg_req: ClientRegistrationRequest = req.body_json().await.map_err(|e| {
+        request_error!("{:?}", e);
+        tide::Error::from_str(
+            tide::StatusCode::BadRequest,
+            "Invalid Oauth2 ClientRegistrationRequest",
+        )
+    })?;
+
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_client_register(token, reg_req, eventid)
+        .await
+        .map(Some);
+
+    oauth2_client_registration_response(res, tide::StatusCode::Created, hvalue)
+}
+
+// https://datatracker.ietf.org/doc/html/rfc7592
+pub async fn oauth2_register_client_get(req: tide::Request<AppState>) -> tide::Result {
+    let token = req.get_auth_bearer();
+    let client_id = req.get_url_param("client_id")?;
+    let (eventid, hvalue) = req.new_eventid();
+
+    let res = req
+        .state()
+        .qe_r_ref
+        .handle_oauth2_client_registration_get(token, client_id, eventid)
+        .await
+        .map(Some);
+
+    oauth2_client_registration_response(res, tide::StatusCode::Ok, hvalue)
+}
+
+pub async fn oauth2_register_client_put(mut req: tide::Request<AppState>) -> tide::Result {
+    let token = req.get_auth_bearer();
+    let client_id = req.get_url_param("client_id")?;
+    let (eventid, hvalue) = req.new_eventid();
+
+    let reg_req: ClientRegistrationRequest = req.body_json().await.map_err(|e| {
+        request_error!("{:?}", e);
+        tide::Error::from_str(
+            tide::StatusCode::BadRequest,
+            "Invalid Oauth2 ClientRegistrationRequest",
+        )
+    })?;
+
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_client_registration_update(token, client_id, reg_req, eventid)
+        .await
+        .map(Some);
+
+    oauth2_client_registration_response(res, tide::StatusCode::Ok, hvalue)
+}
+
+pub async fn oauth2_register_client_delete(req: tide::Request<AppState>) -> tide::Result {
+    let token = req.get_auth_bearer();
+    let client_id = req.get_url_param("client_id")?;
+    let (eventid, hvalue) = req.new_eventid();
+
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_client_registration_delete(token, client_id, eventid)
+        .await
+        .map(|()| None);
+
+    oauth2_client_registration_response(res, tide::StatusCode::NoContent, hvalue)
+}
+
+fn oauth2_client_registration_response(
+    res: Result<Option<ClientRegistrationResponse>, Oauth2Error>,
+    status: tide::StatusCode,
+    hvalue: String,
+) -> tide::Result {
+    match res {
+        Ok(Some(reg_resp)) => {
+            let mut res = tide::Response::new(status);
+            // The response carries the client secret.
+            res.insert_header("Cache-Control", "no-store");
+            tide::Body::from_json(&reg_resp).map(|b| {
+                res.set_body(b);
+                res
+            })
+        }
+        Ok(None) => Ok(tide::Response::new(status)),
+        // https://datatracker.ietf.org/doc/html/rfc6750#section-3.1
+        Err(Oauth2Error::AuthenticationRequired) | Err(Oauth2Error::InvalidToken) => {
+            let mut res = tide::Response::new(tide::StatusCode::Unauthorized);
+            res.insert_header("WWW-Authenticate", "Bearer error=\"invalid_token\"");
+            Ok(res)
+        }
+        Err(Oauth2Error::AccessDenied) => Ok(tide::Response::new(tide::StatusCode::Forbidden)),
+        Err(e) => {
+            // https://datatracker.ietf.org/doc/html/rfc7591#section-3.2.2
+            let err = ErrorResponse {
+                error: e.to_string(),
+                error_description: None,
+                error_uri: None,
+            };
+
+            let mut res = tide::Response::new(400);
+            tide::Body::from_json(&err).map(|b| {
+                res.set_body(b);
+                res
+            })
+        }
+    }
+    .map(|mut res| {
+        res.insert_header("X-KANIDM-OPID", hvalue);
+        res
+    })
+}
+
 pub fn oauth2_route_setup(appserver: &mut tide::Route<'_, AppState>, routemap: &mut RouteMap) {
     let mut oauth2_process = appserver.at("/oauth2");
     // ⚠️  ⚠️   WARNING  ⚠️  ⚠️
@@ -789,6 +1253,12 @@ pub fn oauth2_route_setup(appserver: &mut tide::Route<'_, AppState>, routemap: &
         .mapped_post(routemap, oauth2_authorise_post)
         .mapped_get(routemap, oauth2_authorise_get);
 
+    // ⚠️  ⚠️   WARNING  ⚠️  ⚠️
+    // IF YOU CHANGE THESE VALUES YOU MUST UPDATE OIDC DISCOVERY URLS
+    oauth2_process
+        .at("/par")
+        .mapped_post(routemap, oauth2_par_post);
+
     // ⚠️  ⚠️   WARNING  ⚠️  ⚠️
     // IF YOU CHANGE THESE VALUES YOU MUST UPDATE OIDC DISCOVERY URLS
     oauth2_process
@@ -818,6 +1288,32 @@ pub fn oauth2_route_setup(appserver: &mut tide::Route<'_, AppState>, routemap: &
         .at("/token/revoke")
         .mapped_post(routemap, oauth2_token_revoke_post);
 
+    // ⚠️  ⚠️   WARNING  ⚠️  ⚠️
+    // IF YOU CHANGE THESE VALUES YOU MUST UPDATE OIDC DISCOVERY URLS
+    oauth2_process
+        .at("/device")
+        .mapped_post(routemap, oauth2_device_post);
+    oauth2_process
+        .at("/device/authorise")
+        .mapped_post(routemap, oauth2_device_authorise_post);
+    oauth2_process
+        .at("/device/permit")
+        .mapped_post(routemap, oauth2_device_permit_post);
+    oauth2_process
+        .at("/device/reject")
+        .mapped_post(routemap, oauth2_device_reject_post);
+
+    // ⚠️  ⚠️   WARNING  ⚠️  ⚠️
+    // IF YOU CHANGE THESE VALUES YOU MUST UPDATE OIDC DISCOVERY URLS
+    oauth2_process
+        .at("/register")
+        .mapped_post(routemap, oauth2_register_post);
+    oauth2_process
+        .at("/register/:client_id")
+        .mapped_get(routemap, oauth2_register_client_get)
+        .mapped_put(routemap, oauth2_register_client_put)
+        .mapped_delete(routemap, oauth2_register_client_delete);
+
     let mut openid_process = appserver.at("/oauth2/openid");
     // ⚠️  ⚠️   WARNING  ⚠️  ⚠️
     // IF YOU CHANGE THESE VALUES YOU MUST UPDATE OIDC DISCOVERY URLS
@@ -837,4 +1333,11 @@ pub fn oauth2_route_setup(appserver: &mut tide::Route<'_, AppState>, routemap: &
     openid_process
         .at("/:client_id/public_key.jwk")
         .mapped_get(routemap, oauth2_openid_publickey_get);
+    // ⚠️  ⚠️   WARNING  ⚠️  ⚠️
+    // IF YOU CHANGE THESE VALUES YOU MUST UPDATE OIDC DISCOVERY URLS
+
+    openid_process
+        .at("/:client_id/end_session")
+        .mapped_get(routemap, oauth2_openid_end_session_get)
+        .mapped_post(routemap, oauth2_openid_end_session_post);
 }
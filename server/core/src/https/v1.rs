@@ -2,6 +2,10 @@ use std::str::FromStr;
 use std::time::Duration;
 
 use compact_jwt::Jws;
+use kanidm_proto::internal::{
+    AuditLogQuery, SchemaAttributeCreateRequest, SchemaAttributeUpdateRequest,
+    SchemaClassCreateRequest, SchemaClassUpdateRequest, SchemaDeleteQuery,
+};
 use kanidm_proto::v1::{
     AccountUnixExtend, ApiTokenGenerate, AuthIssueSession, AuthRequest, AuthResponse,
     AuthState as ProtoAuthState, CUIntentToken, CURequest, CUSessionToken, CreateRequest,
@@ -373,6 +377,87 @@ pub async fn schema_classtype_get_id(req: tide::Request<AppState>) -> tide::Resu
     to_tide_response(res, hvalue)
 }
 
+pub async fn schema_attributetype_post(mut req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let msg: SchemaAttributeCreateRequest = req.body_json().await?;
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_schema_attribute_create(uat, msg, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
+pub async fn schema_attributetype_patch_id(mut req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let id = req.get_url_param("id")?;
+    let msg: SchemaAttributeUpdateRequest = req.body_json().await?;
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_schema_attribute_update(uat, id, msg, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
+pub async fn schema_attributetype_delete_id(req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let id = req.get_url_param("id")?;
+    let query: SchemaDeleteQuery = req.query().map_err(|e| {
+        error!(?e, "Invalid schema delete query");
+        tide::Error::from_str(
+            tide::StatusCode::BadRequest,
+            "Invalid schema delete query parameters",
+        )
+    })?;
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_schema_attribute_delete(uat, id, query.purge, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
+pub async fn schema_classtype_post(mut req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let msg: SchemaClassCreateRequest = req.body_json().await?;
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_schema_class_create(uat, msg, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
+pub async fn schema_classtype_patch_id(mut req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let id = req.get_url_param("id")?;
+    let msg: SchemaClassUpdateRequest = req.body_json().await?;
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_schema_class_update(uat, id, msg, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
+pub async fn schema_classtype_delete_id(req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let id = req.get_url_param("id")?;
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_schema_class_delete(uat, id, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
 // == person ==
 
 pub async fn person_get(req: tide::Request<AppState>) -> tide::Result {
@@ -515,6 +600,46 @@ pub async fn service_account_api_token_delete(req: tide::Request<AppState>) -> t
     to_tide_response(res, hvalue)
 }
 
+pub async fn service_account_id_oauth2_registration_scopemap_post(
+    mut req: tide::Request<AppState>,
+) -> tide::Result {
+    let uat = req.get_current_uat();
+    let id = req.get_url_param("id")?;
+    let group = req.get_url_param("group")?;
+
+    let scopes: Vec<String> = req.body_json().await?;
+
+    let filter = filter_all!(f_eq("class", PartialValue::new_class("service_account")));
+    let filter = Filter::join_parts_and(filter, filter_all!(f_id(id.as_str())));
+
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_registration_scopemap_update(uat, group, scopes, filter, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
+pub async fn service_account_id_oauth2_registration_scopemap_delete(
+    req: tide::Request<AppState>,
+) -> tide::Result {
+    let uat = req.get_current_uat();
+    let id = req.get_url_param("id")?;
+    let group = req.get_url_param("group")?;
+
+    let filter = filter_all!(f_eq("class", PartialValue::new_class("service_account")));
+    let filter = Filter::join_parts_and(filter, filter_all!(f_id(id.as_str())));
+
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_oauth2_registration_scopemap_delete(uat, group, filter, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
 // Account stuff
 pub async fn account_id_get_attr(req: tide::Request<AppState>) -> tide::Result {
     let filter = filter_all!(f_eq("class", PartialValue::new_class("account")));
@@ -596,6 +721,33 @@ pub async fn account_get_id_credential_update_intent(req: tide::Request<AppState
     to_tide_response(res, hvalue)
 }
 
+pub async fn account_post_id_credential_update_intent_send(
+    req: tide::Request<AppState>,
+) -> tide::Result {
+    let uat = req.get_current_uat();
+    let uuid_or_name = req.get_url_param("id")?;
+    let ttl = req
+        .param("ttl")
+        .ok()
+        .and_then(|s| {
+            u64::from_str(s)
+                .map_err(|_e| {
+                    error!("Invalid TTL integer, ignoring.");
+                })
+                .ok()
+        })
+        .map(Duration::from_secs);
+
+    let (eventid, hvalue) = req.new_eventid();
+
+    let res = req
+        .state()
+        .qe_w_ref
+        .handle_idmcredentialupdateintentsend(uat, uuid_or_name, ttl, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
 pub async fn account_get_id_user_auth_token(req: tide::Request<AppState>) -> tide::Result {
     let uat = req.get_current_uat();
     let uuid_or_name = req.get_url_param("id")?;
@@ -994,6 +1146,21 @@ pub async fn system_delete_attr(req: tide::Request<AppState>) -> tide::Result {
     json_rest_event_delete_attr(req, filter, STR_UUID_SYSTEM_CONFIG.to_string(), attr).await
 }
 
+pub async fn message_get(req: tide::Request<AppState>) -> tide::Result {
+    let filter = filter_all!(f_eq("class", PartialValue::new_class("outbound_message")));
+    json_rest_event_get(req, filter, None).await
+}
+
+pub async fn message_id_get(req: tide::Request<AppState>) -> tide::Result {
+    let filter = filter_all!(f_eq("class", PartialValue::new_class("outbound_message")));
+    json_rest_event_get_id(req, filter, None).await
+}
+
+pub async fn message_id_delete(req: tide::Request<AppState>) -> tide::Result {
+    let filter = filter_all!(f_eq("class", PartialValue::new_class("outbound_message")));
+    json_rest_event_delete_id(req, filter).await
+}
+
 pub async fn recycle_bin_get(req: tide::Request<AppState>) -> tide::Result {
     let filter = filter_all!(f_pres("class"));
     let uat = req.get_current_uat();
@@ -1051,6 +1218,69 @@ pub async fn applinks_get(req: tide::Request<AppState>) -> tide::Result {
     to_tide_response(res, hvalue)
 }
 
+pub async fn notifications_get(req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_r_ref
+        .handle_list_notifications(uat, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
+pub async fn audit_log_get(req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let (eventid, hvalue) = req.new_eventid();
+    let query: AuditLogQuery = req.query().map_err(|e| {
+        error!(?e, "Invalid audit log query");
+        tide::Error::from_str(
+            tide::StatusCode::BadRequest,
+            "Invalid audit log query parameters",
+        )
+    })?;
+    let res = req
+        .state()
+        .qe_r_ref
+        .handle_audit_log_search(uat, query, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
+pub async fn source_lock_get(req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_r_ref
+        .handle_source_lock_list(uat, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
+pub async fn source_lock_id_delete(req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let source = req.get_url_param("id")?;
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_r_ref
+        .handle_source_lock_clear(uat, source, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
+pub async fn password_kdf_report_get(req: tide::Request<AppState>) -> tide::Result {
+    let uat = req.get_current_uat();
+    let (eventid, hvalue) = req.new_eventid();
+    let res = req
+        .state()
+        .qe_r_ref
+        .handle_password_kdf_report(uat, eventid)
+        .await;
+    to_tide_response(res, hvalue)
+}
+
 pub async fn do_routemap(req: tide::Request<RouteMap>) -> tide::Result {
     let mut res = tide::Response::new(200);
 
@@ -12,11 +12,12 @@ use tokio::sync::broadcast;
 use tokio::time::{interval, sleep, Duration};
 
 use crate::config::OnlineBackup;
+use crate::messages::MessageConfig;
 use crate::CoreAction;
 
 use crate::actors::v1_read::QueryServerReadV1;
 use crate::actors::v1_write::QueryServerWriteV1;
-use kanidmd_lib::constants::PURGE_FREQUENCY;
+use kanidmd_lib::constants::{MESSAGE_DELIVERY_FREQUENCY, PURGE_FREQUENCY};
 use kanidmd_lib::event::{OnlineBackupEvent, PurgeRecycledEvent, PurgeTombstoneEvent};
 
 pub struct IntervalActor;
@@ -43,6 +44,7 @@ impl IntervalActor {
                         server
                             .handle_purgerecycledevent(PurgeRecycledEvent::new())
                             .await;
+                        server.handle_purge_messages().await;
                     }
                 }
             }
@@ -61,6 +63,7 @@ impl IntervalActor {
     ) -> Result<tokio::task::JoinHandle<()>, ()> {
         let outpath = online_backup_config.path.to_owned();
         let versions = online_backup_config.versions;
+        let options = online_backup_config.options.clone();
         let crono_expr = online_backup_config.schedule.as_str().to_string();
         let mut crono_expr_values = crono_expr.split_ascii_whitespace().collect::<Vec<&str>>();
         let chrono_expr_uses_standard_syntax = crono_expr_values.len() == 5;
@@ -141,6 +144,7 @@ impl IntervalActor {
                                 OnlineBackupEvent::new(),
                                 outpath.clone().as_str(),
                                 versions,
+                                &options,
                             )
                             .await
                         {
@@ -154,4 +158,57 @@ impl IntervalActor {
 
         Ok(handle)
     }
+
+    #[allow(clippy::result_unit_err)]
+    pub fn start_message_delivery(
+        server: &'static QueryServerWriteV1,
+        message_config: &MessageConfig,
+        mut rx: broadcast::Receiver<CoreAction>,
+    ) -> Result<tokio::task::JoinHandle<()>, ()> {
+        let transport = message_config.build_transport().map_err(|e| {
+            error!("Unable to configure message delivery: {}", e);
+        })?;
+
+        let handle = tokio::spawn(async move {
+            let mut inter = interval(Duration::from_secs(MESSAGE_DELIVERY_FREQUENCY));
+
+            loop {
+                tokio::select! {
+                    Ok(action) = rx.recv() => {
+                        match action {
+                            CoreAction::Shutdown => break,
+                        }
+                    }
+                    _ = inter.tick() => {
+                        let pending = match server.handle_pending_messages(transport.requires_mail_destination()).await {
+                            Ok(pending) => pending,
+                            Err(e) => {
+                                error!(?e, "Unable to load pending messages.");
+                                continue;
+                            }
+                        };
+
+                        if pending.is_empty() {
+                            continue;
+                        }
+
+                        let mut results = Vec::with_capacity(pending.len());
+                        for message in pending.iter() {
+                            let result = transport.send(message).await;
+                            if let Err(e) = &result {
+                                warn!(uuid = %message.uuid, "Unable to deliver message: {}", e);
+                            }
+                            results.push((message.uuid, result));
+                        }
+
+                        server.handle_message_delivery_results(results).await;
+                    }
+                }
+            }
+
+            info!("Stopped MessageDeliveryActor");
+        });
+
+        Ok(handle)
+    }
 }
@@ -1,25 +1,32 @@
 use std::marker::Unpin;
 use std::net;
 use std::str::FromStr;
+use std::sync::Arc;
 
 use crate::actors::v1_read::QueryServerReadV1;
 use futures_util::sink::SinkExt;
 use futures_util::stream::StreamExt;
-use kanidmd_lib::idm::ldap::{LdapBoundToken, LdapResponseState};
+use kanidmd_lib::idm::ldap::{LdapBoundToken, LdapPagedSearch, LdapResponseState, OID_STARTTLS};
 use kanidmd_lib::prelude::*;
-use ldap3_proto::proto::LdapMsg;
+use ldap3_proto::proto::{
+    LdapExtendedRequest, LdapExtendedResponse, LdapMsg, LdapOp, LdapResult, LdapResultCode,
+    LdapSearchScope,
+};
+use ldap3_proto::simple::DisconnectionNotice;
 use ldap3_proto::LdapCodec;
 use openssl::ssl::{Ssl, SslAcceptor, SslAcceptorBuilder};
 use tokio::io::{AsyncRead, AsyncWrite};
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
 use tokio_openssl::SslStream;
-use tokio_util::codec::{FramedRead, FramedWrite};
+use tokio_util::codec::{Framed, FramedRead, FramedWrite};
 
 use crate::CoreAction;
 use tokio::sync::broadcast;
 
 struct LdapSession {
     uat: Option<LdapBoundToken>,
+    // The in progress paged search of this connection, if any.
+    paged_search: Option<LdapPagedSearch>,
 }
 
 impl LdapSession {
@@ -27,15 +34,17 @@ impl LdapSession {
         LdapSession {
             // We start un-authenticated
             uat: None,
+            paged_search: None,
         }
     }
 }
 
-#[instrument(name = "ldap-request", skip(client_address, qe_r_ref))]
+#[instrument(name = "ldap-request", skip(client_address, paged_search, qe_r_ref))]
 async fn client_process_msg(
     uat: Option<LdapBoundToken>,
     client_address: net::SocketAddr,
     protomsg: LdapMsg,
+    paged_search: &mut Option<LdapPagedSearch>,
     qe_r_ref: &'static QueryServerReadV1,
 ) -> Option<LdapResponseState> {
     let eventid = sketching::tracing_forest::id();
@@ -44,7 +53,9 @@ async fn client_process_msg(
         client_port = %client_address.port(),
         "LDAP client"
     );
-    qe_r_ref.handle_ldaprequest(eventid, protomsg, uat).await
+    qe_r_ref
+        .handle_ldaprequest(eventid, protomsg, uat, paged_search, client_address.ip())
+        .await
 }
 
 async fn client_process<W: AsyncWrite + Unpin, R: AsyncRead + Unpin>(
@@ -61,7 +72,7 @@ async fn client_process<W: AsyncWrite + Unpin, R: AsyncRead + Unpin>(
         let uat = session.uat.clone();
         let caddr = client_address;
 
-        match client_process_msg(uat, caddr, protomsg, qe_r_ref).await {
+        match client_process_msg(uat, caddr, protomsg, &mut session.paged_search, qe_r_ref).await {
             // I'd really have liked to have put this near the [LdapResponseState::Bind] but due
             // to the handing of `audit` it isn't possible due to borrows, etc.
             Some(LdapResponseState::Unbind) => return,
@@ -72,6 +83,8 @@ async fn client_process<W: AsyncWrite + Unpin, R: AsyncRead + Unpin>(
                 break;
             }
             Some(LdapResponseState::Bind(uat, rmsg)) => {
+                // A change of identity must not be able to continue a prior paged search.
+                session.paged_search = None;
                 session.uat = Some(uat);
                 if w.send(rmsg).await.is_err() {
                     break;
@@ -105,6 +118,33 @@ async fn client_process<W: AsyncWrite + Unpin, R: AsyncRead + Unpin>(
     }
 }
 
+/// Upgrade an accepted tcp stream to TLS, and then hand off to [client_process]
+async fn client_tls_accept(
+    tcpstream: TcpStream,
+    client_socket_addr: net::SocketAddr,
+    tls_parms: &SslAcceptor,
+    qe_r_ref: &'static QueryServerReadV1,
+) {
+    // From the parameters we need to create an SslContext.
+    let mut tlsstream = match Ssl::new(tls_parms.context())
+        .and_then(|tls_obj| SslStream::new(tls_obj, tcpstream))
+    {
+        Ok(ta) => ta,
+        Err(e) => {
+            error!("LDAP TLS setup error, continuing -> {:?}", e);
+            return;
+        }
+    };
+    if let Err(e) = SslStream::accept(Pin::new(&mut tlsstream)).await {
+        error!("LDAP TLS accept error, continuing -> {:?}", e);
+        return;
+    };
+    let (r, w) = tokio::io::split(tlsstream);
+    let r = FramedRead::new(r, LdapCodec);
+    let w = FramedWrite::new(w, LdapCodec);
+    tokio::spawn(client_process(r, w, client_socket_addr, qe_r_ref));
+}
+
 /// TLS LDAP Listener, hands off to [client_process]
 async fn tls_acceptor(
     listener: TcpListener,
@@ -123,24 +163,7 @@ async fn tls_acceptor(
                 match accept_result {
                     Ok((tcpstream, client_socket_addr)) => {
                         // Start the event
-                        // From the parameters we need to create an SslContext.
-                        let mut tlsstream = match Ssl::new(tls_parms.context())
-                            .and_then(|tls_obj| SslStream::new(tls_obj, tcpstream))
-                        {
-                            Ok(ta) => ta,
-                            Err(e) => {
-                                error!("LDAP TLS setup error, continuing -> {:?}", e);
-                                continue;
-                            }
-                        };
-                        if let Err(e) = SslStream::accept(Pin::new(&mut tlsstream)).await {
-                            error!("LDAP TLS accept error, continuing -> {:?}", e);
-                            continue;
-                        };
-                        let (r, w) = tokio::io::split(tlsstream);
-                        let r = FramedRead::new(r, LdapCodec);
-                        let w = FramedWrite::new(w, LdapCodec);
-                        tokio::spawn(client_process(r, w, client_socket_addr, qe_r_ref));
+                        client_tls_accept(tcpstream, client_socket_addr, &tls_parms, qe_r_ref).await;
                     }
                     Err(e) => {
                         error!("LDAP acceptor error, continuing -> {:?}", e);
@@ -152,12 +175,173 @@ async fn tls_acceptor(
     info!("Stopped LdapAcceptorActor");
 }
 
-pub(crate) async fn create_ldap_server(
-    address: &str,
-    opt_tls_params: Option<SslAcceptorBuilder>,
+/// The operations a plaintext connection may perform before it is upgraded with StartTLS.
+#[derive(Debug, PartialEq, Eq)]
+enum StartTlsAction {
+    Upgrade,
+    RootDse,
+    Unbind,
+    Refuse,
+}
+
+impl From<&LdapOp> for StartTlsAction {
+    fn from(op: &LdapOp) -> Self {
+        match op {
+            LdapOp::ExtendedRequest(LdapExtendedRequest { name, .. }) if name == OID_STARTTLS => {
+                StartTlsAction::Upgrade
+            }
+            LdapOp::SearchRequest(lsr)
+                if lsr.base.is_empty() && lsr.scope == LdapSearchScope::Base =>
+            {
+                StartTlsAction::RootDse
+            }
+            LdapOp::UnbindRequest => StartTlsAction::Unbind,
+            _ => StartTlsAction::Refuse,
+        }
+    }
+}
+
+fn starttls_response(msgid: i32) -> LdapMsg {
+    LdapMsg {
+        msgid,
+        op: LdapOp::ExtendedResponse(LdapExtendedResponse {
+            res: LdapResult {
+                code: LdapResultCode::Success,
+                matcheddn: "".to_string(),
+                message: "".to_string(),
+                referral: vec![],
+            },
+            name: Some(OID_STARTTLS.to_string()),
+            value: None,
+        }),
+        ctrl: vec![],
+    }
+}
+
+fn starttls_required() -> LdapMsg {
+    DisconnectionNotice::gen(
+        LdapResultCode::ConfidentialityRequired,
+        "StartTLS is required",
+    )
+}
+
+/// Process a plaintext ldap connection. Until the client upgrades with StartTLS the only
+/// operations allowed are reading the rootdse (so that clients can discover StartTLS is
+/// supported) and the StartTLS request itself. Once upgraded this hands off to [client_process].
+async fn client_starttls_process(
+    tcpstream: TcpStream,
+    client_address: net::SocketAddr,
+    tls_parms: Arc<SslAcceptor>,
     qe_r_ref: &'static QueryServerReadV1,
-    rx: broadcast::Receiver<CoreAction>,
-) -> Result<tokio::task::JoinHandle<()>, ()> {
+) {
+    let mut framed = Framed::new(tcpstream, LdapCodec);
+
+    while let Some(Ok(protomsg)) = framed.next().await {
+        match StartTlsAction::from(&protomsg.op) {
+            StartTlsAction::Upgrade => {
+                if framed
+                    .send(starttls_response(protomsg.msgid))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+
+                let parts = framed.into_parts();
+                // The client must wait for our response before beginning the TLS handshake. If
+                // there is anything buffered it was sent in plaintext, and can't be trusted.
+                if !parts.read_buf.is_empty() {
+                    security_info!(
+                        client_ip = %client_address.ip(),
+                        "LDAP client sent data before StartTLS completed, disconnecting"
+                    );
+                    return;
+                }
+
+                security_info!(
+                    client_ip = %client_address.ip(),
+                    client_port = %client_address.port(),
+                    "LDAP client upgrading with StartTLS"
+                );
+                client_tls_accept(parts.io, client_address, &tls_parms, qe_r_ref).await;
+                return;
+            }
+            StartTlsAction::RootDse => {
+                // Allow reading the rootdse, but don't retain the implicit anonymous bind
+                // or any paging state.
+                let mut paged_search = None;
+                let rmsgs = match client_process_msg(
+                    None,
+                    client_address,
+                    protomsg,
+                    &mut paged_search,
+                    qe_r_ref,
+                )
+                .await
+                {
+                    Some(LdapResponseState::MultiPartResponse(v))
+                    | Some(LdapResponseState::BindMultiPartResponse(_, v)) => v,
+                    Some(LdapResponseState::Respond(rmsg)) => vec![rmsg],
+                    _ => {
+                        error!("Internal server error");
+                        return;
+                    }
+                };
+                for rmsg in rmsgs.into_iter() {
+                    if framed.send(rmsg).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            StartTlsAction::Unbind => return,
+            StartTlsAction::Refuse => {
+                security_info!(
+                    client_ip = %client_address.ip(),
+                    "LDAP client attempted an operation before StartTLS, disconnecting"
+                );
+                let _ = framed.send(starttls_required()).await;
+                return;
+            }
+        }
+    }
+}
+
+/// Plaintext LDAP Listener that only permits StartTLS, hands off to [client_starttls_process]
+async fn starttls_acceptor(
+    listener: TcpListener,
+    tls_parms: SslAcceptor,
+    qe_r_ref: &'static QueryServerReadV1,
+    mut rx: broadcast::Receiver<CoreAction>,
+) {
+    let tls_parms = Arc::new(tls_parms);
+    loop {
+        tokio::select! {
+            Ok(action) = rx.recv() => {
+                match action {
+                    CoreAction::Shutdown => break,
+                }
+            }
+            accept_result = listener.accept() => {
+                match accept_result {
+                    Ok((tcpstream, client_socket_addr)) => {
+                        tokio::spawn(client_starttls_process(
+                            tcpstream,
+                            client_socket_addr,
+                            tls_parms.clone(),
+                            qe_r_ref,
+                        ));
+                    }
+                    Err(e) => {
+                        error!("LDAP StartTLS acceptor error, continuing -> {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+    info!("Stopped LdapStartTlsAcceptorActor");
+}
+
+async fn bind_ldap_listener(address: &str) -> Result<TcpListener, ()> {
     if address.starts_with(":::") {
         // takes :::xxxx to xxxx
         let port = address.replacen(":::", "", 1);
@@ -168,12 +352,21 @@ pub(crate) async fn create_ldap_server(
         error!("Could not parse LDAP server address {} -> {:?}", address, e);
     })?;
 
-    let listener = TcpListener::bind(&addr).await.map_err(|e| {
+    TcpListener::bind(&addr).await.map_err(|e| {
         error!(
             "Could not bind to LDAP server address {} -> {:?}",
             address, e
         );
-    })?;
+    })
+}
+
+pub(crate) async fn create_ldap_server(
+    address: &str,
+    opt_tls_params: Option<SslAcceptorBuilder>,
+    qe_r_ref: &'static QueryServerReadV1,
+    rx: broadcast::Receiver<CoreAction>,
+) -> Result<tokio::task::JoinHandle<()>, ()> {
+    let listener = bind_ldap_listener(address).await?;
 
     let ldap_acceptor_handle = match opt_tls_params {
         Some(tls_params) => {
@@ -190,3 +383,113 @@ pub(crate) async fn create_ldap_server(
     info!("Created LDAP interface");
     Ok(ldap_acceptor_handle)
 }
+
+pub(crate) async fn create_ldap_starttls_server(
+    address: &str,
+    opt_tls_params: Option<SslAcceptorBuilder>,
+    qe_r_ref: &'static QueryServerReadV1,
+    rx: broadcast::Receiver<CoreAction>,
+) -> Result<tokio::task::JoinHandle<()>, ()> {
+    let listener = bind_ldap_listener(address).await?;
+
+    let ldap_acceptor_handle = match opt_tls_params {
+        Some(tls_params) => {
+            info!("Starting LDAP StartTLS interface ldap://{} ...", address);
+            let tls_parms = tls_params.build();
+            tokio::spawn(starttls_acceptor(listener, tls_parms, qe_r_ref, rx))
+        }
+        None => {
+            error!("The server won't run StartTLS without TLS!");
+            return Err(());
+        }
+    };
+
+    info!("Created LDAP StartTLS interface");
+    Ok(ldap_acceptor_handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use ldap3_proto::proto::{
+        LdapBindCred, LdapBindRequest, LdapDerefAliases, LdapExtendedRequest, LdapFilter, LdapOp,
+        LdapResultCode, LdapSearchRequest, LdapSearchScope,
+    };
+
+    use super::{starttls_required, starttls_response, StartTlsAction, OID_STARTTLS};
+
+    fn search_op(base: &str, scope: LdapSearchScope) -> LdapOp {
+        LdapOp::SearchRequest(LdapSearchRequest {
+            base: base.to_string(),
+            scope,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter: LdapFilter::Present("objectclass".to_string()),
+            attrs: vec![],
+        })
+    }
+
+    #[test]
+    fn test_ldap_starttls_response() {
+        let op = LdapOp::ExtendedRequest(LdapExtendedRequest {
+            name: OID_STARTTLS.to_string(),
+            value: None,
+        });
+        assert_eq!(StartTlsAction::from(&op), StartTlsAction::Upgrade);
+
+        let rmsg = starttls_response(3);
+        assert_eq!(rmsg.msgid, 3);
+        match rmsg.op {
+            LdapOp::ExtendedResponse(ler) => {
+                assert_eq!(ler.res.code, LdapResultCode::Success);
+                assert_eq!(ler.name.as_deref(), Some(OID_STARTTLS));
+                assert!(ler.value.is_none());
+            }
+            _ => panic!("not an extended response"),
+        }
+    }
+
+    #[test]
+    fn test_ldap_starttls_plaintext_refused() {
+        // Only the rootdse may be read before the upgrade.
+        assert_eq!(
+            StartTlsAction::from(&search_op("", LdapSearchScope::Base)),
+            StartTlsAction::RootDse
+        );
+        assert_eq!(
+            StartTlsAction::from(&search_op("", LdapSearchScope::Subtree)),
+            StartTlsAction::Refuse
+        );
+        assert_eq!(
+            StartTlsAction::from(&search_op("dc=example,dc=com", LdapSearchScope::Base)),
+            StartTlsAction::Refuse
+        );
+
+        // Binds would send credentials in plaintext.
+        let op = LdapOp::BindRequest(LdapBindRequest {
+            dn: "name=admin,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("password".to_string()),
+        });
+        assert_eq!(StartTlsAction::from(&op), StartTlsAction::Refuse);
+
+        // As are other extended operations.
+        let op = LdapOp::ExtendedRequest(LdapExtendedRequest {
+            name: "1.3.6.1.4.1.4203.1.11.3".to_string(),
+            value: None,
+        });
+        assert_eq!(StartTlsAction::from(&op), StartTlsAction::Refuse);
+
+        assert_eq!(
+            StartTlsAction::from(&LdapOp::UnbindRequest),
+            StartTlsAction::Unbind
+        );
+
+        match starttls_required().op {
+            LdapOp::ExtendedResponse(ler) => {
+                assert_eq!(ler.res.code, LdapResultCode::ConfidentialityRequired);
+            }
+            _ => panic!("not a disconnection notice"),
+        }
+    }
+}
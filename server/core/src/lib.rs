@@ -26,18 +26,23 @@ extern crate tracing;
 extern crate kanidmd_lib;
 
 pub mod actors;
+pub mod audit;
 pub mod config;
 mod crypto;
 pub mod https;
 mod interval;
 mod ldaps;
+pub mod messages;
 
 use std::sync::Arc;
 
 use compact_jwt::JwsSigner;
 use kanidm_proto::messages::{AccountChangeMessage, MessageStatus};
 use kanidm_proto::v1::OperationError;
-use kanidmd_lib::be::{Backend, BackendConfig, BackendTransaction, FsType};
+use kanidmd_lib::be::{
+    backup, Backend, BackendConfig, BackendTransaction, BackupOptions, FsType, RestoreOptions,
+};
+use kanidmd_lib::idm::audit::AuditLog;
 use kanidmd_lib::idm::ldap::LdapServer;
 use kanidmd_lib::prelude::*;
 use kanidmd_lib::schema::Schema;
@@ -46,14 +51,19 @@ use kanidmd_lib::utils::{duration_from_epoch_now, touch_file_or_quit};
 #[cfg(not(target_family = "windows"))]
 use libc::umask;
 
+use time::OffsetDateTime;
 use tokio::sync::broadcast;
 
 use crate::actors::v1_read::QueryServerReadV1;
 use crate::actors::v1_write::QueryServerWriteV1;
-use crate::config::Configuration;
+use crate::audit::AuditSinks;
+use crate::config::{Configuration, ServerRole};
 use crate::crypto::setup_tls;
 use crate::interval::IntervalActor;
 
+/// How often audit events older than the retention period are removed.
+const AUDIT_LOG_PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
 // === internal setup helpers
 
 fn setup_backend(config: &Configuration, schema: &Schema) -> Result<Backend, OperationError> {
@@ -92,6 +102,19 @@ fn setup_backend_vacuum(
     Backend::new(cfg, idxmeta, vacuum)
 }
 
+/// An in memory backend, so that a backup can be restored and checked without touching
+/// the real database.
+fn setup_scratch_backend(
+    config: &Configuration,
+    schema: &Schema,
+) -> Result<Backend, OperationError> {
+    let schema_txn = schema.write();
+    let idxmeta = schema_txn.reload_idxmeta();
+
+    let cfg = BackendConfig::new_scratch(config.db_arc_size);
+    Backend::new(cfg, idxmeta, false)
+}
+
 // TODO #54: We could move most of the be/schema/qs setup and startup
 // outside of this call, then pass in "what we need" in a cloneable
 // form, this way we could have separate Idm vs Qs threads, and dedicated
@@ -118,8 +141,12 @@ async fn setup_qs_idms(
 
     // We generate a SINGLE idms only!
 
-    let (idms, idms_delayed, idms_audit) =
-        IdmServer::new(query_server.clone(), &config.origin).await?;
+    let (idms, idms_delayed, idms_audit) = IdmServer::new(
+        query_server.clone(),
+        &config.origin,
+        config.source_lock.clone(),
+    )
+    .await?;
 
     Ok((query_server, idms, idms_delayed, idms_audit))
 }
@@ -237,7 +264,7 @@ pub fn dbscan_get_id2entry_core(config: &Configuration, id: u64) {
     };
 }
 
-pub fn backup_server_core(config: &Configuration, dst_path: &str) {
+pub fn backup_server_core(config: &Configuration, dst_path: &str, options: &BackupOptions) {
     let schema = match Schema::new() {
         Ok(s) => s,
         Err(e) => {
@@ -255,7 +282,7 @@ pub fn backup_server_core(config: &Configuration, dst_path: &str) {
     };
 
     let mut be_ro_txn = be.read();
-    let r = be_ro_txn.backup(dst_path);
+    let r = be_ro_txn.backup(dst_path, options);
     match r {
         Ok(_) => info!("Backup success!"),
         Err(e) => {
@@ -266,7 +293,7 @@ pub fn backup_server_core(config: &Configuration, dst_path: &str) {
     // Let the txn abort, even on success.
 }
 
-pub async fn restore_server_core(config: &Configuration, dst_path: &str) {
+pub async fn restore_server_core(config: &Configuration, dst_path: &str, options: &RestoreOptions) {
     touch_file_or_quit(config.db_path.as_str());
 
     // First, we provide the in-memory schema so that core attrs are indexed correctly.
@@ -287,7 +314,9 @@ pub async fn restore_server_core(config: &Configuration, dst_path: &str) {
     };
 
     let mut be_wr_txn = be.write();
-    let r = be_wr_txn.restore(dst_path).and_then(|_| be_wr_txn.commit());
+    let r = be_wr_txn
+        .restore(dst_path, options)
+        .and_then(|_| be_wr_txn.commit());
 
     if r.is_err() {
         error!("Failed to restore database: {:?}", r);
@@ -322,6 +351,66 @@ pub async fn restore_server_core(config: &Configuration, dst_path: &str) {
     info!("✅ Restore Success!");
 }
 
+pub fn verify_backup_core(src_path: &str, options: &RestoreOptions) {
+    match backup::verify_backup(src_path, options) {
+        Ok(summary) => {
+            if summary.manifest.is_none() {
+                warn!("Backup has no manifest, so it was only checked as readable");
+            }
+            info!("✅ Backup verified - {}", summary);
+        }
+        Err(e) => {
+            error!("Backup verification failed: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub async fn restore_dry_run_core(
+    config: &Configuration,
+    src_path: &str,
+    options: &RestoreOptions,
+) {
+    let schema = match Schema::new() {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Failed to setup in memory schema: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let be = match setup_scratch_backend(config, &schema) {
+        Ok(be) => be,
+        Err(e) => {
+            error!("Failed to setup scratch backend: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut be_wr_txn = be.write();
+    let r = be_wr_txn
+        .restore(src_path, options)
+        .and_then(|_| be_wr_txn.commit());
+
+    if let Err(e) = r {
+        error!("Dry run restore failed: {:?}", e);
+        std::process::exit(1);
+    }
+    info!("Backup loaded into scratch database");
+
+    let server = QueryServer::new(be, schema, config.domain.clone());
+    let r = server.verify().await;
+
+    if r.is_empty() {
+        info!("✅ Dry run restore success! The live database was not changed.");
+    } else {
+        for er in r {
+            error!("{:?}", er);
+        }
+        std::process::exit(1);
+    }
+}
+
 pub async fn reindex_server_core(config: &Configuration) {
     eprintln!("Start Index Phase 1 ...");
     // First, we provide the in-memory schema so that core attrs are indexed correctly.
@@ -697,7 +786,7 @@ pub async fn create_server_core(
         None => {}
     }
 
-    let ldap = match LdapServer::new(&idms).await {
+    let mut ldap = match LdapServer::new(&idms).await {
         Ok(l) => l,
         Err(e) => {
             error!("Unable to start LdapServer -> {:?}", e);
@@ -705,13 +794,38 @@ pub async fn create_server_core(
         }
     };
 
+    if config.ldapstarttlsaddress.is_some() {
+        ldap.enable_starttls();
+    }
+
+    let audit_log_config = config.audit_log_config();
+    let audit_log = match AuditLog::new(&audit_log_config.path) {
+        Ok(l) => Arc::new(l),
+        Err(e) => {
+            error!(
+                "Unable to open audit log {} -> {:?}",
+                audit_log_config.path, e
+            );
+            return Err(());
+        }
+    };
+    let audit_log_retention =
+        Duration::from_secs(u64::from(audit_log_config.retention_days) * 86400);
+
+    let (audit_sinks, audit_sink_handles) = AuditSinks::start(
+        &config.audit_sinks,
+        config.audit_sink_buffer_dir(),
+        &broadcast_tx,
+    )?;
+
     // Arc the idms and ldap
     let idms_arc = Arc::new(idms);
     let ldap_arc = Arc::new(ldap);
 
     // Pass it to the actor for threading.
     // Start the read query server with the given be path: future config
-    let server_read_ref = QueryServerReadV1::start_static(idms_arc.clone(), ldap_arc.clone());
+    let server_read_ref =
+        QueryServerReadV1::start_static(idms_arc.clone(), ldap_arc.clone(), audit_log.clone());
 
     // Create the server async write entry point.
     let server_write_ref = QueryServerWriteV1::start_static(idms_arc.clone());
@@ -739,6 +853,7 @@ pub async fn create_server_core(
     let mut broadcast_rx = broadcast_tx.subscribe();
 
     let auditd_handle = tokio::spawn(async move {
+        let mut retention_interval = tokio::time::interval(AUDIT_LOG_PURGE_INTERVAL);
         loop {
             tokio::select! {
                 Ok(action) = broadcast_rx.recv() => {
@@ -746,17 +861,43 @@ pub async fn create_server_core(
                         CoreAction::Shutdown => break,
                     }
                 }
+                _ = retention_interval.tick() => {
+                    // The audit log is sqlite, so this must not block the runtime.
+                    let before = OffsetDateTime::now_utc() - audit_log_retention;
+                    let purge_log = audit_log.clone();
+                    match tokio::task::spawn_blocking(move || purge_log.purge_before(before)).await {
+                        Ok(Ok(0)) => {}
+                        Ok(Ok(count)) => info!(%count, "Removed expired audit events"),
+                        Ok(Err(e)) => error!(err=?e, "Unable to remove expired audit events"),
+                        Err(e) => error!(err=?e, "Audit log purge task failed"),
+                    }
+                }
                 audit_event = idms_audit.audit_rx().recv() => {
-                    match serde_json::to_string(&audit_event) {
-                        Ok(audit_event) => {
-                            warn!(%audit_event);
-                        }
-                        Err(e) => {
-                            error!(err=?e, "Unable to process audit event to json.");
-                            warn!(?audit_event, json=false);
+                    match audit_event {
+                        Some(audit_event) => {
+                            // While this waits for the disk the queue is not read, so a slow
+                            // disk fills the bounded queue rather than growing memory.
+                            let append_log = audit_log.clone();
+                            let append_event = audit_event.clone();
+                            match tokio::task::spawn_blocking(move || append_log.append(&append_event)).await {
+                                Ok(Ok(())) => {}
+                                Ok(Err(e)) => error!(err=?e, "Unable to record audit event."),
+                                Err(e) => error!(err=?e, "Audit log append task failed"),
+                            }
+                            audit_sinks.send(&audit_event);
+                            match serde_json::to_string(&audit_event) {
+                                Ok(audit_event) => {
+                                    warn!(%audit_event);
+                                }
+                                Err(e) => {
+                                    error!(err=?e, "Unable to process audit event to json.");
+                                    warn!(?audit_event, json=false);
+                                }
+                            }
                         }
+                        // Channel has closed, stop the task.
+                        None => break,
                     }
-
                 }
             }
         }
@@ -781,6 +922,26 @@ pub async fn create_server_core(
         }
     };
 
+    // Outbound messages are only delivered by writeable servers, since delivery must be recorded.
+    let maybe_message_handle = match (&config.messages, config.role) {
+        (Some(_), ServerRole::ReadOnlyReplica) => {
+            info!("Message delivery is not available on read only replicas, skipping");
+            None
+        }
+        (Some(message_config), _) => {
+            let handle = IntervalActor::start_message_delivery(
+                server_write_ref,
+                message_config,
+                broadcast_tx.subscribe(),
+            )?;
+            Some(handle)
+        }
+        (None, _) => {
+            debug!("Message delivery not configured, skipping");
+            None
+        }
+    };
+
     // If we have been requested to init LDAP, configure it now.
     let maybe_ldap_acceptor_handle = match &config.ldapaddress {
         Some(la) => {
@@ -811,6 +972,36 @@ pub async fn create_server_core(
         }
     };
 
+    // The plaintext listener only permits StartTLS, so it needs its own TLS parameters.
+    let maybe_ldap_starttls_acceptor_handle = match &config.ldapstarttlsaddress {
+        Some(la) => {
+            let opt_ldap_tls_params = match setup_tls(&config) {
+                Ok(t) => t,
+                Err(e) => {
+                    error!("Failed to configure LDAP StartTLS parameters -> {:?}", e);
+                    return Err(());
+                }
+            };
+            if !config_test {
+                // ⚠️  only start the sockets and listeners in non-config-test modes.
+                let h = ldaps::create_ldap_starttls_server(
+                    la.as_str(),
+                    opt_ldap_tls_params,
+                    server_read_ref,
+                    broadcast_tx.subscribe(),
+                )
+                .await?;
+                Some(h)
+            } else {
+                None
+            }
+        }
+        None => {
+            debug!("LDAP StartTLS not requested, skipping");
+            None
+        }
+    };
+
     // TODO: Remove these when we go to auth bearer!
     // Copy the max size
     let _secure_cookies = config.secure_cookies;
@@ -840,15 +1031,24 @@ pub async fn create_server_core(
     };
 
     let mut handles = vec![interval_handle, delayed_handle, auditd_handle];
+    handles.extend(audit_sink_handles);
 
     if let Some(backup_handle) = maybe_backup_handle {
         handles.push(backup_handle)
     }
 
+    if let Some(message_handle) = maybe_message_handle {
+        handles.push(message_handle)
+    }
+
     if let Some(ldap_handle) = maybe_ldap_acceptor_handle {
         handles.push(ldap_handle)
     }
 
+    if let Some(ldap_starttls_handle) = maybe_ldap_starttls_acceptor_handle {
+        handles.push(ldap_starttls_handle)
+    }
+
     if let Some(http_handle) = maybe_http_acceptor_handle {
         handles.push(http_handle)
     }
@@ -0,0 +1,533 @@
+//! Transports that deliver the outbound messages queued by the server, such as credential reset
+//! links and notifications. The server decides what to send and to whom - a transport only has to
+//! get the rendered message to its destination, and report whether that worked.
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use kanidmd_lib::idm::message::PendingMessage;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::Message;
+use openssl::ssl::{SslConnector, SslMethod};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_openssl::SslStream;
+use uuid::Uuid;
+
+/// How long we wait on a webhook to accept a message.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long we wait on a mail server to accept a message, including connecting to it.
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The longest reply line we will read from a mail server. RFC 5321 limits these to 512 bytes.
+const SMTP_MAX_REPLY_LINE: u64 = 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Connect with TLS, usually on port 465.
+    Tls,
+    /// Connect in plain text and then upgrade with STARTTLS, usually on port 587.
+    #[default]
+    Starttls,
+    /// Never use TLS. This should only be used with a relay on the same host.
+    None,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "transport", rename_all = "lowercase")]
+pub enum MessageTransportConfig {
+    Smtp {
+        host: String,
+        port: Option<u16>,
+        #[serde(default)]
+        security: SmtpSecurity,
+        username: Option<String>,
+        password: Option<String>,
+    },
+    /// Write each message to a file in a directory rather than sending it. This is intended
+    /// for testing, or for handing messages to another system.
+    Spool { path: String },
+    /// POST each message as json to a url, such as a chat or paging system. Messages are sent
+    /// even if the account has no mail address.
+    Webhook {
+        url: String,
+        /// Sent as a bearer token in the authorization header, if set.
+        bearer_token: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessageConfig {
+    /// The address messages are sent from, such as `Kanidm <idm@example.com>`.
+    pub from_address: String,
+    #[serde(flatten)]
+    pub transport: MessageTransportConfig,
+}
+
+#[async_trait]
+pub trait MessageTransport: Send + Sync {
+    async fn send(&self, message: &PendingMessage) -> Result<(), String>;
+
+    /// If this transport can only deliver messages that have a mail destination.
+    fn requires_mail_destination(&self) -> bool {
+        true
+    }
+}
+
+impl MessageConfig {
+    pub fn build_transport(&self) -> Result<Box<dyn MessageTransport>, String> {
+        let from = self
+            .from_address
+            .parse::<Mailbox>()
+            .map_err(|e| format!("invalid from_address '{}': {}", self.from_address, e))?;
+
+        match &self.transport {
+            MessageTransportConfig::Smtp {
+                host,
+                port,
+                security,
+                username,
+                password,
+            } => {
+                let port = port.unwrap_or(match security {
+                    SmtpSecurity::Tls => 465,
+                    SmtpSecurity::Starttls => 587,
+                    SmtpSecurity::None => 25,
+                });
+
+                let credentials = match (username, password) {
+                    (Some(username), Some(password)) => Some((username.clone(), password.clone())),
+                    (None, None) => None,
+                    _ => return Err("smtp username and password must be set together".to_string()),
+                };
+
+                // The default client settings verify the server certificate against the
+                // system trust store.
+                let tls = SslConnector::builder(SslMethod::tls_client())
+                    .map(|builder| builder.build())
+                    .map_err(|e| format!("unable to configure smtp tls: {}", e))?;
+
+                Ok(Box::new(SmtpTransport {
+                    from,
+                    host: host.clone(),
+                    port,
+                    security: *security,
+                    credentials,
+                    tls,
+                }))
+            }
+            MessageTransportConfig::Spool { path } => {
+                let path = PathBuf::from(path);
+                fs::create_dir_all(&path).map_err(|e| {
+                    format!(
+                        "unable to create spool directory '{}': {}",
+                        path.display(),
+                        e
+                    )
+                })?;
+                Ok(Box::new(SpoolTransport { from, path }))
+            }
+            MessageTransportConfig::Webhook { url, bearer_token } => {
+                let url =
+                    Url::parse(url).map_err(|e| format!("invalid webhook url '{}': {}", url, e))?;
+                Ok(Box::new(WebhookTransport {
+                    from: self.from_address.clone(),
+                    url,
+                    bearer_token: bearer_token.clone(),
+                    client: reqwest::Client::new(),
+                }))
+            }
+        }
+    }
+}
+
+fn build_message(from: &Mailbox, message: &PendingMessage) -> Result<Message, String> {
+    let to = message
+        .mail_destination
+        .as_deref()
+        .ok_or_else(|| "message has no mail destination".to_string())?
+        .parse::<Mailbox>()
+        .map_err(|e| format!("invalid mail destination: {}", e))?;
+
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(message.message.subject.as_str())
+        .header(ContentType::TEXT_PLAIN)
+        .body(message.message.body.clone())
+        .map_err(|e| format!("unable to build message: {}", e))
+}
+
+/// Escape any line of the message that starts with a `.` so that it can't end the DATA command
+/// early, and make sure the message ends with a line break.
+fn dot_stuff(data: &[u8]) -> Vec<u8> {
+    let mut stuffed = Vec::with_capacity(data.len() + 2);
+    let mut line_start = true;
+    for &b in data {
+        if line_start && b == b'.' {
+            stuffed.push(b'.');
+        }
+        stuffed.push(b);
+        line_start = b == b'\n';
+    }
+    if !stuffed.ends_with(b"\r\n") {
+        stuffed.extend_from_slice(b"\r\n");
+    }
+    stuffed
+}
+
+/// The client side of an SMTP session, over either a plain or a TLS stream.
+struct SmtpClient<S> {
+    stream: BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> SmtpClient<S> {
+    fn new(stream: S) -> Self {
+        SmtpClient {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+
+    /// Read a reply, which may span several lines, and check that it is in the same class as
+    /// the expected code. Returns the text of each line.
+    async fn read_reply(&mut self, expect: u16) -> Result<Vec<String>, String> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            let read = (&mut self.stream)
+                .take(SMTP_MAX_REPLY_LINE)
+                .read_line(&mut line)
+                .await
+                .map_err(|e| format!("unable to read smtp reply: {}", e))?;
+            if read == 0 {
+                return Err("smtp server closed the connection".to_string());
+            }
+
+            let line = line.trim_end();
+            let code = line
+                .get(..3)
+                .and_then(|code| code.parse::<u16>().ok())
+                .ok_or_else(|| format!("invalid smtp reply '{}'", line))?;
+            lines.push(line.get(4..).unwrap_or_default().to_string());
+
+            // Every line but the last of a reply has a `-` after the code.
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return if code / 100 == expect / 100 {
+                    Ok(lines)
+                } else {
+                    Err(format!("smtp server replied {} {}", code, lines.join(" ")))
+                };
+            }
+        }
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        let stream = self.stream.get_mut();
+        match stream.write_all(data).await {
+            Ok(()) => stream.flush().await,
+            Err(e) => Err(e),
+        }
+        .map_err(|e| format!("unable to write to smtp server: {}", e))
+    }
+
+    async fn command(&mut self, command: &str, expect: u16) -> Result<Vec<String>, String> {
+        self.write(format!("{}\r\n", command).as_bytes()).await?;
+        self.read_reply(expect).await
+    }
+
+    /// Send a message once the session has been set up, and end the session.
+    async fn send_message(
+        mut self,
+        credentials: Option<&(String, String)>,
+        email: &Message,
+    ) -> Result<(), String> {
+        if let Some((username, password)) = credentials {
+            let token = general_purpose::STANDARD.encode(format!("\0{}\0{}", username, password));
+            self.command(&format!("AUTH PLAIN {}", token), 235).await?;
+        }
+
+        let envelope = email.envelope();
+        let from = envelope
+            .from()
+            .map(|address| address.to_string())
+            .unwrap_or_default();
+        self.command(&format!("MAIL FROM:<{}>", from), 250).await?;
+        for to in envelope.to() {
+            self.command(&format!("RCPT TO:<{}>", to), 250).await?;
+        }
+
+        self.command("DATA", 354).await?;
+        self.write(&dot_stuff(&email.formatted())).await?;
+        self.command(".", 250).await?;
+
+        // The message has been accepted, so a failure to end the session cleanly is not an
+        // error.
+        let _ = self.command("QUIT", 221).await;
+        Ok(())
+    }
+}
+
+struct SmtpTransport {
+    from: Mailbox,
+    host: String,
+    port: u16,
+    security: SmtpSecurity,
+    credentials: Option<(String, String)>,
+    tls: SslConnector,
+}
+
+impl SmtpTransport {
+    async fn tls_connect(&self, stream: TcpStream) -> Result<SslStream<TcpStream>, String> {
+        let ssl = self
+            .tls
+            .configure()
+            .and_then(|config| config.into_ssl(&self.host))
+            .map_err(|e| format!("unable to configure smtp tls: {}", e))?;
+        let mut stream = SslStream::new(ssl, stream)
+            .map_err(|e| format!("unable to configure smtp tls: {}", e))?;
+        Pin::new(&mut stream)
+            .connect()
+            .await
+            .map_err(|e| format!("tls handshake with '{}' failed: {}", self.host, e))?;
+        Ok(stream)
+    }
+
+    async fn deliver(&self, email: &Message) -> Result<(), String> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .map_err(|e| format!("unable to connect to '{}:{}': {}", self.host, self.port, e))?;
+
+        // We identify ourselves by address, as we may not have a resolvable host name.
+        let ehlo = match stream.local_addr() {
+            Ok(SocketAddr::V4(addr)) => format!("EHLO [{}]", addr.ip()),
+            Ok(SocketAddr::V6(addr)) => format!("EHLO [IPv6:{}]", addr.ip()),
+            Err(e) => return Err(format!("unable to find local address: {}", e)),
+        };
+        let credentials = self.credentials.as_ref();
+
+        match self.security {
+            SmtpSecurity::Tls => {
+                let mut client = SmtpClient::new(self.tls_connect(stream).await?);
+                client.read_reply(220).await?;
+                client.command(&ehlo, 250).await?;
+                client.send_message(credentials, email).await
+            }
+            SmtpSecurity::Starttls => {
+                let mut client = SmtpClient::new(stream);
+                client.read_reply(220).await?;
+                let extensions = client.command(&ehlo, 250).await?;
+                if !extensions
+                    .iter()
+                    .any(|extension| extension.eq_ignore_ascii_case("STARTTLS"))
+                {
+                    return Err(format!(
+                        "smtp server '{}' does not offer STARTTLS",
+                        self.host
+                    ));
+                }
+                client.command("STARTTLS", 220).await?;
+
+                let mut client = SmtpClient::new(self.tls_connect(client.into_inner()).await?);
+                client.command(&ehlo, 250).await?;
+                client.send_message(credentials, email).await
+            }
+            SmtpSecurity::None => {
+                let mut client = SmtpClient::new(stream);
+                client.read_reply(220).await?;
+                client.command(&ehlo, 250).await?;
+                client.send_message(credentials, email).await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MessageTransport for SmtpTransport {
+    async fn send(&self, message: &PendingMessage) -> Result<(), String> {
+        let email = build_message(&self.from, message)?;
+        tokio::time::timeout(SMTP_TIMEOUT, self.deliver(&email))
+            .await
+            .map_err(|_| format!("timed out sending to '{}'", self.host))?
+    }
+}
+
+struct SpoolTransport {
+    from: Mailbox,
+    path: PathBuf,
+}
+
+#[async_trait]
+impl MessageTransport for SpoolTransport {
+    async fn send(&self, message: &PendingMessage) -> Result<(), String> {
+        let email = build_message(&self.from, message)?;
+        let path = self.path.join(format!("{}.eml", message.uuid));
+        tokio::fs::write(&path, email.formatted())
+            .await
+            .map_err(|e| format!("unable to write '{}': {}", path.display(), e))
+    }
+}
+
+struct WebhookTransport {
+    from: String,
+    url: Url,
+    bearer_token: Option<String>,
+    client: reqwest::Client,
+}
+
+#[derive(Serialize)]
+struct WebhookMessage<'a> {
+    id: Uuid,
+    recipient: Option<Uuid>,
+    from: &'a str,
+    to: Option<&'a str>,
+    subject: &'a str,
+    body: &'a str,
+}
+
+#[async_trait]
+impl MessageTransport for WebhookTransport {
+    async fn send(&self, message: &PendingMessage) -> Result<(), String> {
+        let payload = WebhookMessage {
+            id: message.uuid,
+            recipient: message.recipient,
+            from: self.from.as_str(),
+            to: message.mail_destination.as_deref(),
+            subject: message.message.subject.as_str(),
+            body: message.message.body.as_str(),
+        };
+
+        let request = self
+            .client
+            .post(self.url.clone())
+            .json(&payload)
+            .timeout(WEBHOOK_TIMEOUT);
+
+        let request = match &self.bearer_token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn requires_mail_destination(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lettre::message::Mailbox;
+    use lettre::Message;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    use super::{dot_stuff, SmtpClient};
+
+    #[test]
+    fn test_smtp_dot_stuff() {
+        assert_eq!(dot_stuff(b"a\r\n.b\r\n.\r\nc"), b"a\r\n..b\r\n..\r\nc\r\n");
+        assert_eq!(dot_stuff(b".a\r\n"), b"..a\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_smtp_send_message() {
+        let (client, server) = tokio::io::duplex(4096);
+
+        let server = tokio::spawn(async move {
+            let mut server = BufReader::new(server);
+            let mut transcript = Vec::new();
+            let replies: &[&[u8]] = &[
+                b"235 ok\r\n",
+                b"250 ok\r\n",
+                b"250-first\r\n250 ok\r\n",
+                b"354 go ahead\r\n",
+            ];
+            for reply in replies {
+                let mut line = String::new();
+                server.read_line(&mut line).await.expect("read failed");
+                transcript.push(line);
+                server
+                    .get_mut()
+                    .write_all(reply)
+                    .await
+                    .expect("write failed");
+            }
+            // The message body, until the lone dot that ends it.
+            loop {
+                let mut line = String::new();
+                server.read_line(&mut line).await.expect("read failed");
+                if line == ".\r\n" {
+                    break;
+                }
+                transcript.push(line);
+            }
+            server
+                .get_mut()
+                .write_all(b"250 queued\r\n")
+                .await
+                .expect("write failed");
+            transcript
+        });
+
+        let email = Message::builder()
+            .from(
+                "idm@example.com"
+                    .parse::<Mailbox>()
+                    .expect("invalid mailbox"),
+            )
+            .to("person@example.com"
+                .parse::<Mailbox>()
+                .expect("invalid mailbox"))
+            .subject("test")
+            .body(".hidden".to_string())
+            .expect("unable to build message");
+
+        let credentials = ("user".to_string(), "pass".to_string());
+        // QUIT is never answered, which must not fail the send.
+        SmtpClient::new(client)
+            .send_message(Some(&credentials), &email)
+            .await
+            .expect("send failed");
+
+        let transcript = server.await.expect("server failed");
+        assert_eq!(transcript[0], "AUTH PLAIN AHVzZXIAcGFzcw==\r\n");
+        assert_eq!(transcript[1], "MAIL FROM:<idm@example.com>\r\n");
+        assert_eq!(transcript[2], "RCPT TO:<person@example.com>\r\n");
+        assert_eq!(transcript[3], "DATA\r\n");
+        assert!(transcript.iter().any(|line| line == "..hidden\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_smtp_rejected_reply() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        server
+            .write_all(b"550-mailbox\r\n550 unavailable\r\n")
+            .await
+            .expect("write failed");
+
+        let err = SmtpClient::new(client)
+            .read_reply(250)
+            .await
+            .expect_err("reply should be rejected");
+        assert_eq!(err, "smtp server replied 550 mailbox unavailable");
+    }
+}
@@ -21,14 +21,17 @@ use fs2::FileExt;
 use std::os::unix::fs::MetadataExt;
 use std::path::PathBuf;
 use std::process::ExitCode;
+use std::str::FromStr;
 
 use clap::{Args, Parser, Subcommand};
-use kanidmd_core::config::{Configuration, ServerConfig};
+use kanidmd_core::config::{
+    BackupCompression, BackupFormat, Configuration, RestoreOptions, ServerConfig,
+};
 use kanidmd_core::{
     backup_server_core, create_server_core, dbscan_get_id2entry_core, dbscan_list_id2entry_core,
     dbscan_list_index_analysis_core, dbscan_list_index_core, dbscan_list_indexes_core,
-    domain_rename_core, recover_account_core, reindex_server_core, restore_server_core,
-    vacuum_server_core, verify_server_core,
+    domain_rename_core, recover_account_core, reindex_server_core, restore_dry_run_core,
+    restore_server_core, vacuum_server_core, verify_backup_core, verify_server_core,
 };
 use sketching::tracing_forest::traits::*;
 use sketching::tracing_forest::util::*;
@@ -60,6 +63,9 @@ impl KanidmdOpt {
             KanidmdOpt::Database {
                 commands: DbCommands::Restore(ropt),
             } => &ropt.commonopts,
+            KanidmdOpt::Database {
+                commands: DbCommands::VerifyBackup(vopt),
+            } => &vopt.commonopts,
             KanidmdOpt::RecoverAccount(ropt) => &ropt.commonopts,
             KanidmdOpt::DbScan {
                 commands: DbScanOpt::ListIndex(dopt),
@@ -395,7 +401,34 @@ async fn main() -> ExitCode {
                             return ExitCode::FAILURE
                         }
                     };
-                    backup_server_core(&config, p);
+                    let mut options = config
+                        .online_backup
+                        .as_ref()
+                        .map(|ob| ob.options.clone())
+                        .unwrap_or_default();
+                    if let Some(format) = bopt.format.as_deref() {
+                        options.format = match BackupFormat::from_str(format) {
+                            Ok(f) => f,
+                            Err(e) => {
+                                error!("{}", e);
+                                return ExitCode::FAILURE
+                            }
+                        };
+                    }
+                    if let Some(compression) = bopt.compression.as_deref() {
+                        options.compression = match BackupCompression::from_str(compression) {
+                            Ok(c) => c,
+                            Err(e) => {
+                                error!("{}", e);
+                                return ExitCode::FAILURE
+                            }
+                        };
+                    }
+                    if bopt.recipient.is_some() || bopt.passphrase_file.is_some() {
+                        options.recipient = bopt.recipient.clone();
+                        options.passphrase_file = bopt.passphrase_file.clone();
+                    }
+                    backup_server_core(&config, p, &options);
                 }
                 KanidmdOpt::Database {
                     commands: DbCommands::Restore(ropt),
@@ -408,7 +441,32 @@ async fn main() -> ExitCode {
                             return ExitCode::FAILURE
                         }
                     };
-                    restore_server_core(&config, p).await;
+                    let options = RestoreOptions {
+                        identity_file: ropt.identity_file.clone(),
+                        passphrase_file: ropt.passphrase_file.clone(),
+                    };
+                    if ropt.dry_run {
+                        restore_dry_run_core(&config, p, &options).await;
+                    } else {
+                        restore_server_core(&config, p, &options).await;
+                    }
+                }
+                KanidmdOpt::Database {
+                    commands: DbCommands::VerifyBackup(vopt),
+                } => {
+                    info!("Running in backup verification mode ...");
+                    let p = match vopt.path.to_str() {
+                        Some(p) => p,
+                        None => {
+                            error!("Invalid backup path");
+                            return ExitCode::FAILURE
+                        }
+                    };
+                    let options = RestoreOptions {
+                        identity_file: vopt.identity_file.clone(),
+                        passphrase_file: vopt.passphrase_file.clone(),
+                    };
+                    verify_backup_core(p, &options);
                 }
                 KanidmdOpt::Database {
                     commands: DbCommands::Verify(_vopt),
@@ -13,6 +13,18 @@ struct BackupOpt {
     #[clap(parse(from_os_str))]
     /// Output path for the backup content.
     path: PathBuf,
+    #[clap(long)]
+    /// The backup format, either "json" or "stream". Defaults to the online_backup setting.
+    format: Option<String>,
+    #[clap(long)]
+    /// Compress the backup with "none", "gzip" or "zstd".
+    compression: Option<String>,
+    #[clap(long)]
+    /// Encrypt the backup to this age recipient (age1...).
+    recipient: Option<String>,
+    #[clap(long)]
+    /// Encrypt the backup with the passphrase stored in this file.
+    passphrase_file: Option<String>,
     #[clap(flatten)]
     commonopts: CommonOpt,
 }
@@ -22,6 +34,30 @@ struct RestoreOpt {
     #[clap(parse(from_os_str))]
     /// Restore from this path. Should be created with "backup".
     path: PathBuf,
+    #[clap(long)]
+    /// Decrypt the backup with the age identities in this file.
+    identity_file: Option<String>,
+    #[clap(long)]
+    /// Decrypt the backup with the passphrase stored in this file.
+    passphrase_file: Option<String>,
+    #[clap(long)]
+    /// Restore into a scratch in-memory database and verify it, leaving the live database untouched.
+    dry_run: bool,
+    #[clap(flatten)]
+    commonopts: CommonOpt,
+}
+
+#[derive(Debug, Args)]
+struct VerifyBackupOpt {
+    #[clap(parse(from_os_str))]
+    /// The backup to check. Should be created with "backup".
+    path: PathBuf,
+    #[clap(long)]
+    /// Decrypt the backup with the age identities in this file.
+    identity_file: Option<String>,
+    #[clap(long)]
+    /// Decrypt the backup with the passphrase stored in this file.
+    passphrase_file: Option<String>,
     #[clap(flatten)]
     commonopts: CommonOpt,
 }
@@ -54,6 +90,9 @@ enum DbCommands {
     #[clap(name = "restore")]
     /// Restore the database content (offline)
     Restore(RestoreOpt),
+    #[clap(name = "verify-backup")]
+    /// Check that a backup is intact and complete, without restoring it.
+    VerifyBackup(VerifyBackupOpt),
     #[clap(name = "verify")]
     /// Verify database and entity consistency.
     Verify(CommonOpt),
@@ -270,7 +270,7 @@ pub(crate) fn idm_test(args: &TokenStream, item: TokenStream) -> TokenStream {
                 assert!(verifications.len() == 0);
 
                 idms_delayed.check_is_empty_or_panic();
-                idms_audit.check_is_empty_or_panic();
+                idms_audit.drain();
             };
             #[allow(clippy::expect_used, clippy::diverging_sub_expression)]
             {
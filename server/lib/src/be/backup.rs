@@ -0,0 +1,727 @@
+//! The encoding of backup files. A backup is the serialised database content, which may be
+//! compressed, and then may be encrypted with [age](https://age-encryption.org). Restore
+//! detects each layer from its leading bytes, so only the key material has to be supplied.
+//!
+//! Each backup carries a manifest of its content, which is checked before a restore removes
+//! anything from the database.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::str::FromStr;
+
+use age::secrecy::Secret;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use kanidm_proto::v1::OperationError;
+use openssl::sha;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::be::dbentry::{DbBackup, DbBackupManifest, DbBackupStream, DbEntry, DbEntryVers};
+use crate::be::dbvalue::DbValueSetV2;
+use crate::constants::{DOMAIN_MAX_LEVEL, UUID_DOMAIN_INFO, UUID_SYSTEM_INFO};
+
+/// The first line of a streaming backup. Each following line is a json
+/// [`DbBackupStream`](crate::be::dbentry::DbBackupStream) item.
+pub(crate) const BACKUP_STREAM_MAGIC: &[u8] = b"kanidm-backup-stream-v1\n";
+
+/// How many entries are written per line of a streaming backup.
+pub(crate) const BACKUP_CHUNK_SIZE: usize = 1000;
+
+const AGE_MAGIC: &[u8] = b"age-encryption.org/";
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupFormat {
+    /// A single json document. The whole database is held in memory while this is
+    /// written and restored.
+    #[default]
+    Json,
+    /// Entries are written and restored in chunks, so the database is never held in memory.
+    Stream,
+}
+
+impl FromStr for BackupFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(BackupFormat::Json),
+            "stream" => Ok(BackupFormat::Stream),
+            _ => Err(format!(
+                "unknown backup format '{}', expected json or stream",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl FromStr for BackupCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(BackupCompression::None),
+            "gzip" => Ok(BackupCompression::Gzip),
+            "zstd" => Ok(BackupCompression::Zstd),
+            _ => Err(format!(
+                "unknown backup compression '{}', expected none, gzip or zstd",
+                s
+            )),
+        }
+    }
+}
+
+/// How a backup is written.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupOptions {
+    #[serde(default)]
+    pub format: BackupFormat,
+    #[serde(default)]
+    pub compression: BackupCompression,
+    /// Encrypt the backup to this age X25519 recipient, such as `age1...`.
+    #[serde(default)]
+    pub recipient: Option<String>,
+    /// Encrypt the backup with the passphrase in this file.
+    #[serde(default)]
+    pub passphrase_file: Option<String>,
+}
+
+impl fmt::Display for BackupOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "format: {:?}, compression: {:?}",
+            self.format, self.compression
+        )?;
+        if self.recipient.is_some() {
+            write!(f, ", encrypted to recipient")
+        } else if self.passphrase_file.is_some() {
+            write!(f, ", encrypted with passphrase")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The key material needed to read an encrypted backup. Unencrypted backups need neither.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestoreOptions {
+    /// A file of age identities, such as created by `age-keygen`.
+    pub identity_file: Option<String>,
+    /// A file containing the passphrase the backup was encrypted with.
+    pub passphrase_file: Option<String>,
+}
+
+fn read_passphrase(path: &str) -> Result<Secret<String>, OperationError> {
+    let passphrase = fs::read_to_string(path).map_err(|e| {
+        admin_error!(?e, %path, "Unable to read backup passphrase file");
+        OperationError::FsError
+    })?;
+    let passphrase = passphrase.trim_end_matches(['\r', '\n']);
+    if passphrase.is_empty() {
+        admin_error!(%path, "Backup passphrase file is empty");
+        return Err(OperationError::InvalidState);
+    }
+    Ok(Secret::new(passphrase.to_string()))
+}
+
+impl BackupOptions {
+    /// The file extension for backups written with these options, such as `jsonl.zst.age`.
+    pub fn file_extension(&self) -> String {
+        let mut ext = match self.format {
+            BackupFormat::Json => "json".to_string(),
+            BackupFormat::Stream => "jsonl".to_string(),
+        };
+        match self.compression {
+            BackupCompression::None => {}
+            BackupCompression::Gzip => ext.push_str(".gz"),
+            BackupCompression::Zstd => ext.push_str(".zst"),
+        }
+        if self.recipient.is_some() || self.passphrase_file.is_some() {
+            ext.push_str(".age");
+        }
+        ext
+    }
+
+    pub(crate) fn create_writer(&self, path: &str) -> Result<BackupWriter, OperationError> {
+        let encryptor = match (&self.recipient, &self.passphrase_file) {
+            (Some(_), Some(_)) => {
+                admin_error!("A backup can be encrypted to a recipient or a passphrase, not both");
+                return Err(OperationError::InvalidState);
+            }
+            (Some(recipient), None) => {
+                let recipient = age::x25519::Recipient::from_str(recipient).map_err(|e| {
+                    admin_error!(?e, "Invalid backup recipient");
+                    OperationError::InvalidState
+                })?;
+                age::Encryptor::with_recipients(vec![
+                    Box::new(recipient) as Box<dyn age::Recipient + Send>
+                ])
+            }
+            (None, Some(passphrase_file)) => Some(age::Encryptor::with_user_passphrase(
+                read_passphrase(passphrase_file)?,
+            )),
+            (None, None) => None,
+        };
+
+        let file = File::create(path).map(BufWriter::new).map_err(|e| {
+            admin_error!(?e, %path, "Unable to create backup file");
+            OperationError::FsError
+        })?;
+
+        let inner = match encryptor {
+            Some(encryptor) => encryptor
+                .wrap_output(file)
+                .map(EncryptWriter::Age)
+                .map_err(|e| {
+                    admin_error!(?e, "Unable to start backup encryption");
+                    OperationError::CryptographyError
+                })?,
+            None => EncryptWriter::Plain(file),
+        };
+
+        match self.compression {
+            BackupCompression::None => Ok(BackupWriter::Plain(inner)),
+            BackupCompression::Gzip => Ok(BackupWriter::Gzip(GzEncoder::new(
+                inner,
+                flate2::Compression::default(),
+            ))),
+            BackupCompression::Zstd => zstd::Encoder::new(inner, 0)
+                .map(BackupWriter::Zstd)
+                .map_err(|e| {
+                    admin_error!(?e, "Unable to start backup compression");
+                    OperationError::FsError
+                }),
+        }
+    }
+}
+
+impl RestoreOptions {
+    fn decrypt<R: Read + 'static>(&self, input: R) -> Result<Box<dyn Read>, OperationError> {
+        let decryptor = age::Decryptor::new(input).map_err(|e| {
+            admin_error!(?e, "Unable to read backup encryption header");
+            OperationError::CryptographyError
+        })?;
+
+        let reader = match decryptor {
+            age::Decryptor::Recipients(decryptor) => {
+                let path = self.identity_file.as_deref().ok_or_else(|| {
+                    admin_error!(
+                        "Backup is encrypted to a recipient, but no identity file was provided"
+                    );
+                    OperationError::InvalidState
+                })?;
+                let identities = fs::read_to_string(path)
+                    .map_err(|e| {
+                        admin_error!(?e, %path, "Unable to read backup identity file");
+                        OperationError::FsError
+                    })?
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(age::x25519::Identity::from_str)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| {
+                        admin_error!(?e, %path, "Invalid backup identity file");
+                        OperationError::InvalidState
+                    })?;
+                decryptor
+                    .decrypt(identities.iter().map(|i| i as &dyn age::Identity))
+                    .map_err(|e| {
+                        admin_error!(?e, "Unable to decrypt backup");
+                        OperationError::CryptographyError
+                    })?
+            }
+            age::Decryptor::Passphrase(decryptor) => {
+                let path = self.passphrase_file.as_deref().ok_or_else(|| {
+                    admin_error!("Backup is encrypted with a passphrase, but no passphrase file was provided");
+                    OperationError::InvalidState
+                })?;
+                decryptor
+                    .decrypt(&read_passphrase(path)?, None)
+                    .map_err(|e| {
+                        admin_error!(?e, "Unable to decrypt backup");
+                        OperationError::CryptographyError
+                    })?
+            }
+        };
+
+        Ok(Box::new(reader))
+    }
+
+    /// Open a backup, removing any encryption and compression, and return a reader over
+    /// the serialised content.
+    pub(crate) fn open_reader(
+        &self,
+        path: &str,
+    ) -> Result<BufReader<Box<dyn Read>>, OperationError> {
+        let file = File::open(path).map_err(|e| {
+            admin_error!(?e, %path, "Unable to open backup file");
+            OperationError::FsError
+        })?;
+
+        let mut reader = BufReader::new(Box::new(file) as Box<dyn Read>);
+        if starts_with(&mut reader, AGE_MAGIC)? {
+            reader = BufReader::new(self.decrypt(reader)?);
+        }
+
+        let reader: Box<dyn Read> = if starts_with(&mut reader, ZSTD_MAGIC)? {
+            zstd::Decoder::with_buffer(reader)
+                .map(|d| Box::new(d) as Box<dyn Read>)
+                .map_err(|e| {
+                    admin_error!(?e, "Unable to start backup decompression");
+                    OperationError::FsError
+                })?
+        } else if starts_with(&mut reader, GZIP_MAGIC)? {
+            Box::new(GzDecoder::new(reader))
+        } else {
+            Box::new(reader)
+        };
+
+        Ok(BufReader::new(reader))
+    }
+}
+
+fn starts_with<R: Read>(reader: &mut BufReader<R>, magic: &[u8]) -> Result<bool, OperationError> {
+    reader
+        .fill_buf()
+        .map(|buf| buf.starts_with(magic))
+        .map_err(|e| {
+            admin_error!(?e, "Unable to read backup file");
+            OperationError::FsError
+        })
+}
+
+pub(crate) enum EncryptWriter {
+    Plain(BufWriter<File>),
+    Age(age::stream::StreamWriter<BufWriter<File>>),
+}
+
+impl Write for EncryptWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            EncryptWriter::Plain(w) => w.write(buf),
+            EncryptWriter::Age(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            EncryptWriter::Plain(w) => w.flush(),
+            EncryptWriter::Age(w) => w.flush(),
+        }
+    }
+}
+
+impl EncryptWriter {
+    fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            EncryptWriter::Plain(w) => w,
+            EncryptWriter::Age(w) => w.finish()?,
+        };
+        file.flush()?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()
+    }
+}
+
+/// Writes a backup through its compression and encryption layers. This must be finished
+/// for the file to be complete.
+pub(crate) enum BackupWriter {
+    Plain(EncryptWriter),
+    Gzip(GzEncoder<EncryptWriter>),
+    Zstd(zstd::Encoder<'static, EncryptWriter>),
+}
+
+impl Write for BackupWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            BackupWriter::Plain(w) => w.write(buf),
+            BackupWriter::Gzip(w) => w.write(buf),
+            BackupWriter::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            BackupWriter::Plain(w) => w.flush(),
+            BackupWriter::Gzip(w) => w.flush(),
+            BackupWriter::Zstd(w) => w.flush(),
+        }
+    }
+}
+
+impl BackupWriter {
+    pub(crate) fn finish(self) -> Result<(), OperationError> {
+        match self {
+            BackupWriter::Plain(w) => w.finish(),
+            BackupWriter::Gzip(w) => w.finish().and_then(EncryptWriter::finish),
+            BackupWriter::Zstd(w) => w.finish().and_then(EncryptWriter::finish),
+        }
+        .map_err(|e| {
+            admin_error!(?e, "Unable to complete backup file");
+            OperationError::FsError
+        })
+    }
+}
+
+pub(crate) fn write_line<T: Serialize>(
+    writer: &mut BackupWriter,
+    item: &T,
+) -> Result<(), OperationError> {
+    serde_json::to_writer(&mut *writer, item)
+        .map_err(|e| {
+            admin_error!(?e, "serde error");
+            OperationError::SerdeJsonError
+        })
+        .and_then(|()| {
+            writer.write_all(b"\n").map_err(|e| {
+                admin_error!(?e, "Unable to write backup file");
+                OperationError::FsError
+            })
+        })
+}
+
+/// What was found when a backup was read through to the end.
+#[derive(Debug, Clone)]
+pub struct BackupSummary {
+    pub format: BackupFormat,
+    pub entry_count: u64,
+    /// Backups from before manifests were added have none, and can only be checked as
+    /// readable.
+    pub manifest: Option<DbBackupManifest>,
+}
+
+impl fmt::Display for BackupSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "format: {:?}, entries: {}",
+            self.format, self.entry_count
+        )?;
+        match &self.manifest {
+            Some(m) => write!(
+                f,
+                ", domain uuid: {}, system version: {}, domain level: {}, chunks: {}",
+                m.db_d_uuid,
+                m.system_version,
+                m.domain_level,
+                m.chunk_checksums.len()
+            ),
+            None => write!(f, ", no manifest"),
+        }
+    }
+}
+
+/// Builds the manifest of a backup from its chunks of entries. When a backup is read the
+/// manifest is built again from what was found, and compared to the one in the backup.
+pub(crate) struct ManifestBuilder {
+    db_d_uuid: Uuid,
+    system_version: u32,
+    domain_level: u32,
+    entry_count: u64,
+    chunk_checksums: Vec<String>,
+}
+
+impl ManifestBuilder {
+    pub(crate) fn new(db_d_uuid: Uuid) -> Self {
+        ManifestBuilder {
+            db_d_uuid,
+            system_version: 0,
+            domain_level: 0,
+            entry_count: 0,
+            chunk_checksums: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add_chunk(&mut self, entries: &[DbEntry]) -> Result<(), OperationError> {
+        let data = serde_json::to_vec(entries).map_err(|e| {
+            admin_error!(?e, "serde error");
+            OperationError::SerdeJsonError
+        })?;
+        self.chunk_checksums.push(hex::encode(sha::sha256(&data)));
+        self.entry_count += entries.len() as u64;
+        if let Some(version) = entries
+            .iter()
+            .find_map(|dbe| info_version(dbe, UUID_SYSTEM_INFO))
+        {
+            self.system_version = version;
+        }
+        if let Some(level) = entries
+            .iter()
+            .find_map(|dbe| info_version(dbe, UUID_DOMAIN_INFO))
+        {
+            self.domain_level = level;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> DbBackupManifest {
+        DbBackupManifest {
+            db_d_uuid: self.db_d_uuid,
+            system_version: self.system_version,
+            domain_level: self.domain_level,
+            entry_count: self.entry_count,
+            chunk_size: BACKUP_CHUNK_SIZE,
+            chunk_checksums: self.chunk_checksums,
+        }
+    }
+
+    /// Check that the entries which were read match the manifest of the backup.
+    fn check(self, manifest: &DbBackupManifest) -> Result<(), OperationError> {
+        if manifest.domain_level > DOMAIN_MAX_LEVEL {
+            admin_error!(
+                "Backup is from domain level {}, but this server supports at most {}",
+                manifest.domain_level,
+                DOMAIN_MAX_LEVEL
+            );
+            return Err(OperationError::InvalidState);
+        }
+
+        if self.db_d_uuid != manifest.db_d_uuid {
+            admin_error!(
+                "Backup is for domain {}, but its manifest is for domain {}",
+                self.db_d_uuid,
+                manifest.db_d_uuid
+            );
+            return Err(OperationError::InvalidState);
+        }
+
+        if self.entry_count != manifest.entry_count
+            || self.chunk_checksums.len() != manifest.chunk_checksums.len()
+        {
+            admin_error!(
+                "Backup is incomplete, its manifest lists {} entries in {} chunks but {} entries in {} chunks were found",
+                manifest.entry_count,
+                manifest.chunk_checksums.len(),
+                self.entry_count,
+                self.chunk_checksums.len()
+            );
+            return Err(OperationError::InvalidState);
+        }
+
+        if let Some(chunk) = self
+            .chunk_checksums
+            .iter()
+            .zip(manifest.chunk_checksums.iter())
+            .position(|(found, expected)| found != expected)
+        {
+            admin_error!(
+                "Backup is damaged, chunk {} does not match its checksum",
+                chunk
+            );
+            return Err(OperationError::InvalidState);
+        }
+
+        if self.system_version != manifest.system_version {
+            admin_error!(
+                "Backup manifest lists system version {}, but the backup contains {}",
+                manifest.system_version,
+                self.system_version
+            );
+            return Err(OperationError::InvalidState);
+        }
+
+        if self.domain_level != manifest.domain_level {
+            admin_error!(
+                "Backup manifest lists domain level {}, but the backup contains {}",
+                manifest.domain_level,
+                self.domain_level
+            );
+            return Err(OperationError::InvalidState);
+        }
+
+        Ok(())
+    }
+}
+
+/// The version of the system info entry, which records the schema migrations applied, or of the
+/// domain info entry, which is the domain level.
+fn info_version(dbe: &DbEntry, info_uuid: Uuid) -> Option<u32> {
+    let attrs = match &dbe.ent {
+        DbEntryVers::V2(dbe) => &dbe.attrs,
+        DbEntryVers::V1(_) => return None,
+    };
+    match attrs.get("uuid") {
+        Some(DbValueSetV2::Uuid(uuids)) if uuids.as_slice() == [info_uuid] => {}
+        _ => return None,
+    }
+    match attrs.get("version") {
+        Some(DbValueSetV2::Uint32(versions)) => versions.first().copied(),
+        _ => None,
+    }
+}
+
+/// Read a backup through to the end and check it against its manifest, without restoring it.
+pub fn verify_backup(
+    path: &str,
+    options: &RestoreOptions,
+) -> Result<BackupSummary, OperationError> {
+    read_backup(path, options, |_| Ok(()))
+}
+
+/// Read a backup, passing each item to `f` in order. The manifest is not passed on, but is
+/// checked and an error returned if the backup is damaged or incomplete.
+///
+/// A json backup is checked before anything is passed to `f`. A stream can only be checked
+/// once it has been read to the end, so a caller that must not act on a damaged stream should
+/// read it once with [`verify_backup`] first.
+pub(crate) fn read_backup<F>(
+    path: &str,
+    options: &RestoreOptions,
+    mut f: F,
+) -> Result<BackupSummary, OperationError>
+where
+    F: FnMut(DbBackupStream) -> Result<(), OperationError>,
+{
+    let mut reader = options.open_reader(path)?;
+
+    let mut magic = Vec::with_capacity(BACKUP_STREAM_MAGIC.len());
+    reader
+        .by_ref()
+        .take(BACKUP_STREAM_MAGIC.len() as u64)
+        .read_to_end(&mut magic)
+        .map_err(|e| {
+            admin_error!(?e, "Unable to read backup file");
+            OperationError::FsError
+        })?;
+
+    if magic != BACKUP_STREAM_MAGIC {
+        // load all entries into RAM, this is what the stream format avoids.
+        let dbbak: DbBackup =
+            serde_json::from_reader(magic.as_slice().chain(reader)).map_err(|e| {
+                admin_error!("serde_json error {:?}", e);
+                OperationError::SerdeJsonError
+            })?;
+        return read_json_backup(dbbak, f);
+    }
+
+    let no_header = || {
+        admin_error!("Backup stream does not start with a header");
+        OperationError::InvalidState
+    };
+
+    let mut builder = None;
+    let mut manifest = None;
+    for line in reader.lines() {
+        let line = line.map_err(|e| {
+            admin_error!(?e, "Unable to read backup file");
+            OperationError::FsError
+        })?;
+
+        if manifest.is_some() {
+            admin_error!("Backup stream continues after its manifest");
+            return Err(OperationError::InvalidState);
+        }
+
+        let item: DbBackupStream = serde_json::from_str(&line).map_err(|e| {
+            admin_error!("serde_json error {:?}", e);
+            OperationError::SerdeJsonError
+        })?;
+
+        match &item {
+            DbBackupStream::Header { db_d_uuid, .. } => {
+                if builder.is_some() {
+                    admin_error!("Backup stream contains a second header");
+                    return Err(OperationError::InvalidState);
+                }
+                builder = Some(ManifestBuilder::new(*db_d_uuid));
+            }
+            DbBackupStream::Chunk(entries) => {
+                builder.as_mut().ok_or_else(no_header)?.add_chunk(entries)?
+            }
+            DbBackupStream::Manifest(m) => {
+                builder.as_ref().ok_or_else(no_header)?;
+                manifest = Some(m.clone());
+                continue;
+            }
+        }
+
+        f(item)?;
+    }
+
+    match (builder, manifest) {
+        (Some(builder), Some(manifest)) => {
+            builder.check(&manifest)?;
+            Ok(BackupSummary {
+                format: BackupFormat::Stream,
+                entry_count: manifest.entry_count,
+                manifest: Some(manifest),
+            })
+        }
+        (None, _) => Err(no_header()),
+        (Some(_), None) => {
+            admin_error!("Backup stream is incomplete, it ends without a manifest");
+            Err(OperationError::InvalidState)
+        }
+    }
+}
+
+fn read_json_backup<F>(dbbak: DbBackup, mut f: F) -> Result<BackupSummary, OperationError>
+where
+    F: FnMut(DbBackupStream) -> Result<(), OperationError>,
+{
+    let (entries, manifest) = match dbbak {
+        DbBackup::V1(entries) => {
+            admin_warn!("Backup has no manifest, it can not be checked for damage");
+            (entries, None)
+        }
+        DbBackup::V2 {
+            db_s_uuid,
+            db_d_uuid,
+            db_ts_max,
+            entries,
+        } => {
+            admin_warn!("Backup has no manifest, it can not be checked for damage");
+            f(DbBackupStream::Header {
+                db_s_uuid,
+                db_d_uuid,
+                db_ts_max,
+            })?;
+            (entries, None)
+        }
+        DbBackup::V3 {
+            db_s_uuid,
+            db_ts_max,
+            manifest,
+            entries,
+        } => {
+            if manifest.chunk_size == 0 {
+                admin_error!("Backup manifest has an invalid chunk size");
+                return Err(OperationError::InvalidState);
+            }
+            let mut builder = ManifestBuilder::new(manifest.db_d_uuid);
+            for chunk in entries.chunks(manifest.chunk_size) {
+                builder.add_chunk(chunk)?;
+            }
+            builder.check(&manifest)?;
+
+            f(DbBackupStream::Header {
+                db_s_uuid,
+                db_d_uuid: manifest.db_d_uuid,
+                db_ts_max,
+            })?;
+            (entries, Some(manifest))
+        }
+    };
+
+    let entry_count = entries.len() as u64;
+    f(DbBackupStream::Chunk(entries))?;
+    Ok(BackupSummary {
+        format: BackupFormat::Json,
+        entry_count,
+        manifest,
+    })
+}
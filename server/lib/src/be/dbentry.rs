@@ -56,6 +56,40 @@ pub enum DbBackup {
         db_ts_max: Duration,
         entries: Vec<DbEntry>,
     },
+    V3 {
+        db_s_uuid: Uuid,
+        db_ts_max: Duration,
+        manifest: DbBackupManifest,
+        entries: Vec<DbEntry>,
+    },
+}
+
+/// Describes what a backup contains, so that it can be checked as intact and complete
+/// before it is restored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DbBackupManifest {
+    pub db_d_uuid: Uuid,
+    /// The version of the system info entry, which is the level of schema migrations applied.
+    pub system_version: u32,
+    pub domain_level: u32,
+    pub entry_count: u64,
+    pub chunk_size: usize,
+    /// The hex encoded sha256 of each chunk of entries, in order.
+    pub chunk_checksums: Vec<String>,
+}
+
+/// A line of a streaming backup. The header comes first, followed by the entries in chunks,
+/// and finally the manifest. A stream without the manifest has been truncated.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DbBackupStream {
+    Header {
+        db_s_uuid: Uuid,
+        db_d_uuid: Uuid,
+        db_ts_max: Duration,
+    },
+    Chunk(Vec<DbEntry>),
+    Manifest(DbBackupManifest),
 }
 
 fn from_vec_dbval1(attr_val: NonEmpty<DbValueV1>) -> Result<DbValueSetV2, OperationError> {
@@ -1,8 +1,9 @@
+use std::collections::BTreeSet;
 use std::fmt;
 use std::time::Duration;
 
 use hashbrown::HashSet;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use url::Url;
 use uuid::Uuid;
 use webauthn_rs::prelude::{
@@ -84,9 +85,18 @@ pub struct DbWebauthnV1 {
 
 #[derive(Serialize, Deserialize)]
 pub struct DbBackupCodeV1 {
+    // Sorted when serialised, so that backups of the same entry have the same checksum.
+    #[serde(serialize_with = "serialize_sorted")]
     pub code_set: HashSet<String>, // has to use std::HashSet for serde
 }
 
+fn serialize_sorted<S>(set: &HashSet<String>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    set.iter().collect::<BTreeSet<_>>().serialize(serializer)
+}
+
 impl std::fmt::Debug for DbBackupCodeV1 {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "codes remaining: {}", self.code_set.len())
@@ -608,6 +618,12 @@ pub enum DbValueSetV2 {
     TotpSecret(Vec<(String, DbTotpV1)>),
     #[serde(rename = "AT")]
     ApiToken(Vec<DbValueApiToken>),
+    #[serde(rename = "I6")]
+    Int64(Vec<i64>),
+    #[serde(rename = "DC")]
+    Decimal(Vec<String>),
+    #[serde(rename = "DA")]
+    Date(Vec<String>),
 }
 
 impl DbValueSetV2 {
@@ -650,6 +666,9 @@ impl DbValueSetV2 {
             DbValueSetV2::JwsKeyRs256(set) => set.len(),
             DbValueSetV2::UiHint(set) => set.len(),
             DbValueSetV2::TotpSecret(set) => set.len(),
+            DbValueSetV2::Int64(set) => set.len(),
+            DbValueSetV2::Decimal(set) => set.len(),
+            DbValueSetV2::Date(set) => set.len(),
         }
     }
 
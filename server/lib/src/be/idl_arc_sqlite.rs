@@ -1,4 +1,4 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryInto;
 use std::ops::DerefMut;
 use std::sync::Arc;
@@ -19,7 +19,7 @@ use crate::be::idl_sqlite::{
 use crate::be::idxkey::{
     IdlCacheKey, IdlCacheKeyRef, IdlCacheKeyToRef, IdxKey, IdxKeyRef, IdxKeyToRef, IdxSlope,
 };
-use crate::be::{BackendConfig, IdList, IdRawEntry};
+use crate::be::{BackendConfig, IdList, IdRawEntry, IdxRange};
 use crate::entry::{Entry, EntryCommitted, EntrySealed};
 use crate::prelude::*;
 use crate::value::{IndexType, Value};
@@ -338,6 +338,12 @@ pub trait IdlArcSqliteTransaction {
 
     fn get_identry_raw(&self, idl: &IdList) -> Result<Vec<IdRawEntry>, OperationError>;
 
+    fn get_identry_raw_range(
+        &self,
+        after: u64,
+        limit: usize,
+    ) -> Result<Vec<IdRawEntry>, OperationError>;
+
     fn exists_idx(&mut self, attr: &str, itype: IndexType) -> Result<bool, OperationError>;
 
     fn get_idl(
@@ -347,6 +353,13 @@ pub trait IdlArcSqliteTransaction {
         idx_key: &str,
     ) -> Result<Option<IDLBitRange>, OperationError>;
 
+    fn get_idl_range(
+        &mut self,
+        attr: &str,
+        itype: IndexType,
+        range: IdxRange<'_>,
+    ) -> Result<Option<IDLBitRange>, OperationError>;
+
     fn get_db_s_uuid(&self) -> Result<Option<Uuid>, OperationError>;
 
     fn get_db_d_uuid(&self) -> Result<Option<Uuid>, OperationError>;
@@ -389,6 +402,15 @@ impl<'a> IdlArcSqliteTransaction for IdlArcSqliteReadTransaction<'a> {
         get_identry_raw!(self, idl)
     }
 
+    fn get_identry_raw_range(
+        &self,
+        after: u64,
+        limit: usize,
+    ) -> Result<Vec<IdRawEntry>, OperationError> {
+        // As with get_identry_raw, the cache has no part in this.
+        self.db.get_identry_raw_range(after, limit)
+    }
+
     fn exists_idx(&mut self, attr: &str, itype: IndexType) -> Result<bool, OperationError> {
         exists_idx!(self, attr, itype)
     }
@@ -403,6 +425,22 @@ impl<'a> IdlArcSqliteTransaction for IdlArcSqliteReadTransaction<'a> {
         get_idl!(self, attr, itype, idx_key)
     }
 
+    #[instrument(level = "trace", skip_all)]
+    fn get_idl_range(
+        &mut self,
+        attr: &str,
+        itype: IndexType,
+        range: IdxRange<'_>,
+    ) -> Result<Option<IDLBitRange>, OperationError> {
+        // Ranges are not cached, as a change to any key within them would invalidate them.
+        self.db.get_idl_range(attr, itype, range).map(|maybe_keys| {
+            maybe_keys.map(|keys| {
+                keys.into_iter()
+                    .fold(IDLBitRange::new(), |acc, (_, idl)| acc | idl)
+            })
+        })
+    }
+
     fn get_db_s_uuid(&self) -> Result<Option<Uuid>, OperationError> {
         self.db.get_db_s_uuid()
     }
@@ -475,6 +513,15 @@ impl<'a> IdlArcSqliteTransaction for IdlArcSqliteWriteTransaction<'a> {
         get_identry_raw!(self, idl)
     }
 
+    fn get_identry_raw_range(
+        &self,
+        after: u64,
+        limit: usize,
+    ) -> Result<Vec<IdRawEntry>, OperationError> {
+        // As with get_identry_raw, the cache has no part in this.
+        self.db.get_identry_raw_range(after, limit)
+    }
+
     fn exists_idx(&mut self, attr: &str, itype: IndexType) -> Result<bool, OperationError> {
         exists_idx!(self, attr, itype)
     }
@@ -489,6 +536,33 @@ impl<'a> IdlArcSqliteTransaction for IdlArcSqliteWriteTransaction<'a> {
         get_idl!(self, attr, itype, idx_key)
     }
 
+    #[instrument(level = "trace", skip_all)]
+    fn get_idl_range(
+        &mut self,
+        attr: &str,
+        itype: IndexType,
+        range: IdxRange<'_>,
+    ) -> Result<Option<IDLBitRange>, OperationError> {
+        let mut keys: BTreeMap<String, IDLBitRange> =
+            match self.db.get_idl_range(attr, itype, range)? {
+                Some(keys) => keys.into_iter().collect(),
+                None => return Ok(None),
+            };
+        // Index changes made in this transaction are only written to the db at commit, so
+        // any dirty idls in the range replace what the db has for that key.
+        self.idl_cache.iter_dirty().for_each(|(k, maybe_idl)| {
+            if let Some(idl) = maybe_idl {
+                if k.i == itype && k.a.as_str() == attr && range.contains(k.k.as_str()) {
+                    keys.insert(k.k.to_string(), IDLBitRange::clone(idl));
+                }
+            }
+        });
+        Ok(Some(
+            keys.into_values()
+                .fold(IDLBitRange::new(), |acc, idl| acc | idl),
+        ))
+    }
+
     fn get_db_s_uuid(&self) -> Result<Option<Uuid>, OperationError> {
         self.db.get_db_s_uuid()
     }
@@ -915,11 +989,21 @@ impl<'a> IdlArcSqliteWriteTransaction<'a> {
         *
         * And there we have it! A slope factor of the index! A way to compare these sets quickly
         * at query optimisation time to minimise index access.
+        *
+        * Ordering indexes are the exception. They are never read one key at a time, but as a
+        * range of keys that are merged. Without knowing the range ahead of time, we assume a
+        * range will cover half of the keys, and treat that as a single idl. This keeps ordering
+        * indexes steeper than an equality lookup of the same attribute would be.
         */
         let slopes: HashMap<_, _> = data
             .into_iter()
             .filter_map(|(k, lens)| {
-                let slope_factor = Self::calculate_sd_slope(&lens);
+                let slope_factor = if k.itype == IndexType::Ordering {
+                    let range_len = lens.iter().sum::<f64>() / 2.0;
+                    Self::calculate_sd_slope(&[range_len])
+                } else {
+                    Self::calculate_sd_slope(&lens)
+                };
                 if slope_factor == 0 || slope_factor == IdxSlope::MAX {
                     None
                 } else {
@@ -1062,6 +1146,37 @@ impl<'a> IdlArcSqliteWriteTransaction<'a> {
         self.db.create_idx(attr, itype)
     }
 
+    /// Drop the tables of these indexes. Changes made to any other index in this transaction
+    /// are kept.
+    pub unsafe fn drop_idxs(&mut self, idxkeys: &[IdxKey]) -> Result<(), OperationError> {
+        let dropped = |k: &IdlCacheKey| {
+            idxkeys
+                .iter()
+                .any(|ikey| ikey.itype == k.i && ikey.attr == k.a)
+        };
+
+        let dirty: Vec<_> = self
+            .idl_cache
+            .iter_dirty()
+            .filter_map(|(k, maybe_idl)| match maybe_idl {
+                Some(idl) if !dropped(k) => Some((k.clone(), idl.clone())),
+                _ => None,
+            })
+            .collect();
+
+        idxkeys
+            .iter()
+            .try_for_each(|ikey| self.db.drop_idx(&ikey.attr, ikey.itype))?;
+
+        // The cached idls of an index can't be found to remove them individually, so the
+        // whole cache is cleared and the changes to the remaining indexes put back.
+        self.idl_cache.clear();
+        dirty
+            .into_iter()
+            .for_each(|(k, idl)| self.idl_cache.insert_dirty(k, idl));
+        Ok(())
+    }
+
     pub unsafe fn purge_idxs(&mut self) -> Result<(), OperationError> {
         self.db.purge_idxs().map(|()| {
             self.idl_cache.clear();
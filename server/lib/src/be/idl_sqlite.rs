@@ -12,7 +12,7 @@ use rusqlite::{Connection, OpenFlags, OptionalExtension};
 use uuid::Uuid;
 
 use crate::be::dbentry::{DbEntry, DbIdentSpn};
-use crate::be::{BackendConfig, IdList, IdRawEntry, IdxKey, IdxSlope};
+use crate::be::{BackendConfig, IdList, IdRawEntry, IdxKey, IdxRange, IdxSlope};
 use crate::entry::{Entry, EntryCommitted, EntrySealed};
 use crate::prelude::*;
 use crate::value::{IndexType, Value};
@@ -201,6 +201,38 @@ pub trait IdlSqliteTransaction {
         }
     }
 
+    /// Fetch up to `limit` raw entries with an id greater than `after`, in id order. This
+    /// allows the whole of id2entry to be read in pieces.
+    fn get_identry_raw_range(
+        &self,
+        after: u64,
+        limit: usize,
+    ) -> Result<Vec<IdRawEntry>, OperationError> {
+        let mut stmt = self
+            .get_conn()
+            .prepare(&format!(
+                "SELECT id, data FROM {}.id2entry WHERE id > :after ORDER BY id ASC LIMIT :limit",
+                self.get_db_name()
+            ))
+            .map_err(sqlite_error)?;
+        let after = i64::try_from(after).map_err(|_| OperationError::InvalidEntryId)?;
+        let limit = i64::try_from(limit).map_err(|_| OperationError::InvalidState)?;
+        let id2entry_iter = stmt
+            .query_map(
+                named_params! { ":after": &after, ":limit": &limit },
+                |row| {
+                    Ok(IdSqliteEntry {
+                        id: row.get(0)?,
+                        data: row.get(1)?,
+                    })
+                },
+            )
+            .map_err(sqlite_error)?;
+        id2entry_iter
+            .map(|v| v.map_err(sqlite_error).and_then(|ise| ise.try_into()))
+            .collect()
+    }
+
     fn exists_table(&self, tname: &str) -> Result<bool, OperationError> {
         let mut stmt = self
             .get_conn()
@@ -271,6 +303,56 @@ pub trait IdlSqliteTransaction {
         Ok(Some(idl))
     }
 
+    /// Fetch every key and idl of an index that falls within range. Unlike get_idl the
+    /// idls are not merged, so that the caller can layer any uncommitted changes over them.
+    #[instrument(level = "trace", skip_all)]
+    fn get_idl_range(
+        &self,
+        attr: &str,
+        itype: IndexType,
+        range: IdxRange<'_>,
+    ) -> Result<Option<Vec<(String, IDLBitRange)>>, OperationError> {
+        if !(self.exists_idx(attr, itype)?) {
+            debug!(
+                "IdlSqliteTransaction: Index {:?} {:?} not found",
+                itype, attr
+            );
+            return Ok(None);
+        }
+
+        let (op, idx_key) = range.as_sql_op();
+        let query = format!(
+            "SELECT key, idl FROM {}.idx_{}_{} WHERE key {} :idx_key",
+            self.get_db_name(),
+            itype.as_idx_str(),
+            attr,
+            op
+        );
+        let mut stmt = self
+            .get_conn()
+            .prepare(query.as_str())
+            .map_err(sqlite_error)?;
+
+        let idx_iter = stmt
+            .query_map(&[(":idx_key", &idx_key)], |row| {
+                Ok(KeyIdl {
+                    key: row.get(0)?,
+                    data: row.get(1)?,
+                })
+            })
+            .map_err(sqlite_error)?;
+        idx_iter
+            .map(|v| {
+                v.map_err(sqlite_error).and_then(|KeyIdl { key, data }| {
+                    serde_json::from_slice(data.as_slice())
+                        .map_err(serde_json_error)
+                        .map(|idl| (key, idl))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
+
     fn name2uuid(&mut self, name: &str) -> Result<Option<Uuid>, OperationError> {
         // The table exists - lets now get the actual index itself.
         let mut stmt = self
@@ -1065,6 +1147,21 @@ impl IdlSqliteWriteTransaction {
             .map_err(sqlite_error)
     }
 
+    pub unsafe fn drop_idx(&self, attr: &str, itype: IndexType) -> Result<(), OperationError> {
+        let idx_stmt = format!(
+            "DROP TABLE IF EXISTS {}.idx_{}_{}",
+            self.get_db_name(),
+            itype.as_idx_str(),
+            attr
+        );
+        trace!(idx = %idx_stmt, "dropping index");
+
+        self.conn
+            .execute(idx_stmt.as_str(), [])
+            .map(|_| ())
+            .map_err(sqlite_error)
+    }
+
     pub unsafe fn purge_idxs(&self) -> Result<(), OperationError> {
         let idx_table_list = self.list_idxs()?;
 
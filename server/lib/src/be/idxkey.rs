@@ -8,6 +8,30 @@ use crate::value::IndexType;
 
 pub type IdxSlope = u8;
 
+/// The bound of a range lookup in an ordering index. The key is an ordering key of the
+/// value being compared to, and the bound itself is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdxRange<'a> {
+    LessThan(&'a str),
+    GreaterThan(&'a str),
+}
+
+impl<'a> IdxRange<'a> {
+    pub fn contains(&self, key: &str) -> bool {
+        match self {
+            IdxRange::LessThan(k) => key < *k,
+            IdxRange::GreaterThan(k) => key > *k,
+        }
+    }
+
+    pub(crate) fn as_sql_op(&self) -> (&str, &str) {
+        match self {
+            IdxRange::LessThan(k) => ("<", *k),
+            IdxRange::GreaterThan(k) => (">", *k),
+        }
+    }
+}
+
 // Huge props to https://github.com/sunshowers/borrow-complex-key-example/blob/master/src/lib.rs
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
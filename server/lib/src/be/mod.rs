@@ -6,6 +6,7 @@
 
 use std::collections::BTreeMap;
 use std::fs;
+use std::io::Write;
 use std::ops::DerefMut;
 use std::sync::Arc;
 use std::time::Duration;
@@ -19,7 +20,8 @@ use smartstring::alias::String as AttrString;
 use tracing::{trace, trace_span};
 use uuid::Uuid;
 
-use crate::be::dbentry::{DbBackup, DbEntry};
+use crate::be::backup::{ManifestBuilder, BACKUP_CHUNK_SIZE, BACKUP_STREAM_MAGIC};
+use crate::be::dbentry::{DbBackup, DbBackupStream, DbEntry};
 use crate::entry::Entry;
 use crate::filter::{Filter, FilterPlan, FilterResolved, FilterValidResolved};
 use crate::prelude::*;
@@ -31,18 +33,22 @@ use crate::repl::ruv::{
 };
 use crate::value::{IndexType, Value};
 
+pub mod backup;
 pub mod dbentry;
 pub mod dbvalue;
 mod idl_arc_sqlite;
 mod idl_sqlite;
 pub(crate) mod idxkey;
 
-pub(crate) use self::idxkey::{IdxKey, IdxKeyRef, IdxKeyToRef, IdxSlope};
+pub(crate) use self::idxkey::{IdxKey, IdxKeyRef, IdxKeyToRef, IdxRange, IdxSlope};
 use crate::be::idl_arc_sqlite::{
     IdlArcSqlite, IdlArcSqliteReadTransaction, IdlArcSqliteTransaction,
     IdlArcSqliteWriteTransaction,
 };
 // Re-export this
+pub use crate::be::backup::{
+    BackupCompression, BackupFormat, BackupOptions, BackupSummary, RestoreOptions,
+};
 pub use crate::be::idl_sqlite::FsType;
 
 // Currently disabled due to improvements in idlset for intersection handling.
@@ -57,6 +63,9 @@ pub struct Limits {
     pub search_max_results: usize,
     pub search_max_filter_test: usize,
     pub filter_max_elements: usize,
+    // When a search is paged, the total result set may be up to this size, but
+    // each page is still limited to search_max_results.
+    pub search_max_paged_results: usize,
 }
 
 impl Default for Limits {
@@ -66,6 +75,7 @@ impl Default for Limits {
             search_max_results: 256,
             search_max_filter_test: 512,
             filter_max_elements: 32,
+            search_max_paged_results: 65536,
         }
     }
 }
@@ -77,6 +87,7 @@ impl Limits {
             search_max_results: usize::MAX,
             search_max_filter_test: usize::MAX,
             filter_max_elements: usize::MAX,
+            search_max_paged_results: usize::MAX,
         }
     }
 }
@@ -127,6 +138,18 @@ impl BackendConfig {
         }
     }
 
+    /// An in memory database, such as used to check that a backup restores cleanly without
+    /// touching the real database.
+    pub fn new_scratch(arcsize: Option<usize>) -> Self {
+        BackendConfig {
+            pool_size: 1,
+            path: "".to_string(),
+            db_name: "main",
+            fstype: FsType::Generic,
+            arcsize,
+        }
+    }
+
     pub(crate) fn new_test(db_name: &'static str) -> Self {
         BackendConfig {
             pool_size: 1,
@@ -264,9 +287,47 @@ pub trait BackendTransaction {
                     (IdList::AllIds, FilterPlan::PresUnindexed(attr.clone()))
                 }
             }
-            FilterResolved::LessThan(attr, _subvalue, _idx) => {
-                // We have no process for indexing this right now.
-                (IdList::AllIds, FilterPlan::LessThanUnindexed(attr.clone()))
+            FilterResolved::LessThan(attr, value, idx) => {
+                // Only values of a syntax that can be ordered have an ordering key.
+                match (idx, value.get_idx_ord_key()) {
+                    (Some(_), Some(idx_key)) => match self.get_idlayer().get_idl_range(
+                        attr,
+                        IndexType::Ordering,
+                        IdxRange::LessThan(&idx_key),
+                    )? {
+                        Some(idl) => (
+                            IdList::Indexed(idl),
+                            FilterPlan::LessThanIndexed(attr.clone(), idx_key),
+                        ),
+                        None => (IdList::AllIds, FilterPlan::LessThanCorrupt(attr.clone())),
+                    },
+                    _ => {
+                        // Schema believes this is not indexed
+                        (IdList::AllIds, FilterPlan::LessThanUnindexed(attr.clone()))
+                    }
+                }
+            }
+            FilterResolved::GreaterThan(attr, value, idx) => {
+                match (idx, value.get_idx_ord_key()) {
+                    (Some(_), Some(idx_key)) => match self.get_idlayer().get_idl_range(
+                        attr,
+                        IndexType::Ordering,
+                        IdxRange::GreaterThan(&idx_key),
+                    )? {
+                        Some(idl) => (
+                            IdList::Indexed(idl),
+                            FilterPlan::GreaterThanIndexed(attr.clone(), idx_key),
+                        ),
+                        None => (IdList::AllIds, FilterPlan::GreaterThanCorrupt(attr.clone())),
+                    },
+                    _ => {
+                        // Schema believes this is not indexed
+                        (
+                            IdList::AllIds,
+                            FilterPlan::GreaterThanUnindexed(attr.clone()),
+                        )
+                    }
+                }
             }
             FilterResolved::Or(l, _) => {
                 // Importantly if this has no inner elements, this returns
@@ -853,22 +914,8 @@ pub trait BackendTransaction {
         self.get_ruv().verify(&entries, results);
     }
 
-    fn backup(&mut self, dst_path: &str) -> Result<(), OperationError> {
-        // load all entries into RAM, may need to change this later
-        // if the size of the database compared to RAM is an issue
-        let idl = IdList::AllIds;
+    fn backup(&mut self, dst_path: &str, options: &BackupOptions) -> Result<(), OperationError> {
         let idlayer = self.get_idlayer();
-        let raw_entries: Vec<IdRawEntry> = idlayer.get_identry_raw(&idl)?;
-
-        let entries: Result<Vec<DbEntry>, _> = raw_entries
-            .iter()
-            .map(|id_ent| {
-                serde_json::from_slice(id_ent.data.as_slice())
-                    .map_err(|_| OperationError::SerdeJsonError) // log?
-            })
-            .collect();
-
-        let entries = entries?;
 
         let db_s_uuid = idlayer
             .get_db_s_uuid()
@@ -880,24 +927,80 @@ pub trait BackendTransaction {
             .get_db_ts_max()
             .and_then(|u| u.ok_or(OperationError::InvalidDbState))?;
 
-        let bak = DbBackup::V2 {
-            db_s_uuid,
-            db_d_uuid,
-            db_ts_max,
-            entries,
-        };
+        let mut writer = options.create_writer(dst_path)?;
+        let mut manifest = ManifestBuilder::new(db_d_uuid);
+
+        match options.format {
+            BackupFormat::Json => {
+                // load all entries into RAM, this is what the stream format avoids.
+                let idl = IdList::AllIds;
+                let raw_entries: Vec<IdRawEntry> = idlayer.get_identry_raw(&idl)?;
+
+                let entries: Result<Vec<DbEntry>, _> = raw_entries
+                    .iter()
+                    .map(|id_ent| {
+                        serde_json::from_slice(id_ent.data.as_slice())
+                            .map_err(|_| OperationError::SerdeJsonError) // log?
+                    })
+                    .collect();
+                let entries = entries?;
+
+                for chunk in entries.chunks(BACKUP_CHUNK_SIZE) {
+                    manifest.add_chunk(chunk)?;
+                }
 
-        let serialized_entries_str = serde_json::to_string(&bak).map_err(|e| {
-            admin_error!(?e, "serde error");
-            OperationError::SerdeJsonError
-        })?;
+                let bak = DbBackup::V3 {
+                    db_s_uuid,
+                    db_ts_max,
+                    manifest: manifest.finish(),
+                    entries,
+                };
 
-        fs::write(dst_path, serialized_entries_str)
-            .map(|_| ())
-            .map_err(|e| {
-                admin_error!(?e, "fs::write error");
-                OperationError::FsError
-            })
+                serde_json::to_writer(&mut writer, &bak).map_err(|e| {
+                    admin_error!(?e, "serde error");
+                    OperationError::SerdeJsonError
+                })?;
+            }
+            BackupFormat::Stream => {
+                writer.write_all(BACKUP_STREAM_MAGIC).map_err(|e| {
+                    admin_error!(?e, "Unable to write backup file");
+                    OperationError::FsError
+                })?;
+                backup::write_line(
+                    &mut writer,
+                    &DbBackupStream::Header {
+                        db_s_uuid,
+                        db_d_uuid,
+                        db_ts_max,
+                    },
+                )?;
+
+                let mut after = 0;
+                loop {
+                    let raw_entries = idlayer.get_identry_raw_range(after, BACKUP_CHUNK_SIZE)?;
+                    let last_id = match raw_entries.last() {
+                        Some(last) => last.id,
+                        None => break,
+                    };
+
+                    let entries = raw_entries
+                        .iter()
+                        .map(|id_ent| {
+                            serde_json::from_slice(id_ent.data.as_slice())
+                                .map_err(|_| OperationError::SerdeJsonError)
+                        })
+                        .collect::<Result<Vec<DbEntry>, _>>()?;
+
+                    manifest.add_chunk(&entries)?;
+                    backup::write_line(&mut writer, &DbBackupStream::Chunk(entries))?;
+                    after = last_id;
+                }
+
+                backup::write_line(&mut writer, &DbBackupStream::Manifest(manifest.finish()))?;
+            }
+        }
+
+        writer.finish()
     }
 
     fn name2uuid(&mut self, name: &str) -> Result<Option<Uuid>, OperationError> {
@@ -1482,8 +1585,11 @@ impl<'a> BackendWriteTransaction<'a> {
         // get_idl to work. As a result, this causes a double borrow. To work around
         // this we discard the lifetime on idxmeta, because we know that it will
         // remain constant for the life of the operation.
+        //
+        // This uses the idxmeta of this transaction, so that if schema changed the
+        // indexes during the transaction, a reindex uses the new definitions.
 
-        let idxmeta = unsafe { &(*(&self.idxmeta.idxkeys as *const _)) };
+        let idxmeta = unsafe { &(*(&self.idxmeta_wr.idxkeys as *const _)) };
 
         let idx_diff = Entry::idx_diff(idxmeta, pre, post);
 
@@ -1567,7 +1673,7 @@ impl<'a> BackendWriteTransaction<'a> {
         trace!("Creating index -> uuid2rdn");
         self.idlayer.create_uuid2rdn()?;
 
-        self.idxmeta
+        self.idxmeta_wr
             .idxkeys
             .keys()
             .try_for_each(|ikey| self.idlayer.create_idx(&ikey.attr, ikey.itype))
@@ -1634,6 +1740,64 @@ impl<'a> BackendWriteTransaction<'a> {
         Ok(())
     }
 
+    /// Apply a change to the index definitions without a full reindex. Removed indexes are
+    /// dropped, and added indexes are created and populated from every entry.
+    pub fn reindex_changed(
+        &mut self,
+        added: &[IdxKey],
+        removed: &[IdxKey],
+    ) -> Result<(), OperationError> {
+        // An added index is dropped first, in case a stale table was left behind.
+        let dropped: Vec<_> = added.iter().chain(removed.iter()).cloned().collect();
+        unsafe { self.idlayer.drop_idxs(&dropped)? };
+
+        if added.is_empty() {
+            return Ok(());
+        }
+
+        added
+            .iter()
+            .try_for_each(|ikey| self.idlayer.create_idx(&ikey.attr, ikey.itype))?;
+
+        let idxmeta: Map<IdxKey, IdxSlope> = added
+            .iter()
+            .map(|ikey| (ikey.clone(), get_idx_slope_default(ikey)))
+            .collect();
+
+        let entries = self.idlayer.get_identry(&IdList::AllIds).map_err(|e| {
+            admin_error!(err = ?e, "get_identry failure");
+            e
+        })?;
+
+        entries
+            .iter()
+            .try_for_each(|e| {
+                let e_id = e.get_id();
+                Entry::idx_diff(&idxmeta, None, Some(e.as_ref()))
+                    .into_iter()
+                    .try_for_each(|act| match act {
+                        Ok((attr, itype, idx_key)) => {
+                            let mut idl = self
+                                .idlayer
+                                .get_idl(attr, itype, &idx_key)?
+                                .unwrap_or_else(IDLBitRange::new);
+                            idl.insert_id(e_id);
+                            self.idlayer.write_idl(attr, itype, &idx_key, &idl)
+                        }
+                        // Nothing is removed from an index that was just created.
+                        Err(_) => Ok(()),
+                    })
+            })
+            .map_err(|e| {
+                admin_error!("reindex of changed indexes failed -> {:?}", e);
+                e
+            })?;
+
+        self.idlayer.optimise_dirty_idls();
+        admin_info!(entries = entries.len(), ?added, "Populated new indexes");
+        Ok(())
+    }
+
     fn purge_idxs(&mut self) -> Result<(), OperationError> {
         unsafe { self.get_idlayer().purge_idxs() }
     }
@@ -1671,66 +1835,45 @@ impl<'a> BackendWriteTransaction<'a> {
         Ok(slope)
     }
 
-    pub fn restore(&mut self, src_path: &str) -> Result<(), OperationError> {
-        let idlayer = self.get_idlayer();
-        // load all entries into RAM, may need to change this later
-        // if the size of the database compared to RAM is an issue
-        let serialized_string = fs::read_to_string(src_path).map_err(|e| {
-            admin_error!("fs::read_to_string {:?}", e);
-            OperationError::FsError
-        })?;
-
-        unsafe { idlayer.purge_id2entry() }.map_err(|e| {
-            admin_error!("purge_id2entry failed {:?}", e);
-            e
-        })?;
-
-        let dbbak_option: Result<DbBackup, serde_json::Error> =
-            serde_json::from_str(&serialized_string);
-
-        let dbbak = dbbak_option.map_err(|e| {
-            admin_error!("serde_json error {:?}", e);
-            OperationError::SerdeJsonError
-        })?;
-
-        let dbentries = match dbbak {
-            DbBackup::V1(dbentries) => dbentries,
-            DbBackup::V2 {
-                db_s_uuid,
-                db_d_uuid,
-                db_ts_max,
-                entries,
-            } => {
-                // Do stuff.
-                idlayer.write_db_s_uuid(db_s_uuid)?;
-                idlayer.write_db_d_uuid(db_d_uuid)?;
-                idlayer.set_db_ts_max(db_ts_max)?;
-                entries
-            }
-        };
-
-        info!("Restoring {} entries ...", dbentries.len());
-
-        // Migrate any v1 entries to v2 if needed.
-        let dbentries = dbentries
-            .into_iter()
-            .map(|dbe| dbe.convert_to_v2())
-            .collect::<Result<Vec<_>, _>>()?;
+    pub fn restore(
+        &mut self,
+        src_path: &str,
+        options: &RestoreOptions,
+    ) -> Result<(), OperationError> {
+        // Check the whole backup before anything is removed. A stream can only be checked
+        // by reading it to the end, so the backup is read twice.
+        backup::verify_backup(src_path, options)?;
 
-        // Now, we setup all the entries with new ids.
+        let idlayer = self.get_idlayer();
+        let mut purged = false;
         let mut id_max = 0;
-        let identries: Result<Vec<IdRawEntry>, _> = dbentries
-            .iter()
-            .map(|e| {
-                id_max += 1;
-                let data = serde_json::to_vec(&e).map_err(|_| OperationError::SerdeCborError)?;
-                Ok(IdRawEntry { id: id_max, data })
-            })
-            .collect();
+        backup::read_backup(src_path, options, |item| {
+            if !purged {
+                unsafe { idlayer.purge_id2entry() }.map_err(|e| {
+                    admin_error!("purge_id2entry failed {:?}", e);
+                    e
+                })?;
+                purged = true;
+            }
 
-        idlayer.write_identries_raw(identries?.into_iter())?;
+            match item {
+                DbBackupStream::Header {
+                    db_s_uuid,
+                    db_d_uuid,
+                    db_ts_max,
+                } => {
+                    idlayer.write_db_s_uuid(db_s_uuid)?;
+                    idlayer.write_db_d_uuid(db_d_uuid)?;
+                    idlayer.set_db_ts_max(db_ts_max)
+                }
+                DbBackupStream::Chunk(dbentries) => {
+                    Self::restore_entries(idlayer, &mut id_max, dbentries)
+                }
+                DbBackupStream::Manifest(_) => Ok(()),
+            }
+        })?;
 
-        info!("Restored {} entries", dbentries.len());
+        info!("Restored {} entries", id_max);
 
         // Reindex now we are loaded.
         self.reindex()?;
@@ -1743,6 +1886,26 @@ impl<'a> BackendWriteTransaction<'a> {
         }
     }
 
+    /// Write a set of restored entries into id2entry, allocating them new ids.
+    fn restore_entries(
+        idlayer: &mut IdlArcSqliteWriteTransaction,
+        id_max: &mut u64,
+        dbentries: Vec<DbEntry>,
+    ) -> Result<(), OperationError> {
+        // Migrate any v1 entries to v2 if needed.
+        let identries = dbentries
+            .into_iter()
+            .map(|dbe| {
+                let dbe = dbe.convert_to_v2()?;
+                *id_max += 1;
+                let data = serde_json::to_vec(&dbe).map_err(|_| OperationError::SerdeCborError)?;
+                Ok(IdRawEntry { id: *id_max, data })
+            })
+            .collect::<Result<Vec<_>, OperationError>>()?;
+
+        idlayer.write_identries_raw(identries.into_iter())
+    }
+
     #[instrument(level = "debug", name = "be::ruv_rebuild", skip_all)]
     pub fn ruv_rebuild(&mut self) -> Result<(), OperationError> {
         // Rebuild the ruv!
@@ -1856,6 +2019,7 @@ fn get_idx_slope_default(ikey: &IdxKey) -> IdxSlope {
         (_, IndexType::Equality) => 45,
         (_, IndexType::SubString) => 90,
         (_, IndexType::Presence) => 90,
+        (_, IndexType::Ordering) => 120,
     }
 }
 
@@ -1985,16 +2149,19 @@ impl Backend {
 mod tests {
     use std::fs;
     use std::iter::FromIterator;
+    use std::num::NonZeroU8;
     use std::sync::Arc;
     use std::time::Duration;
 
     use idlset::v2::IDLBitRange;
 
     use super::super::entry::{Entry, EntryInit, EntryNew};
+    use super::super::filter::FilterResolved;
     use super::Limits;
     use super::{
-        Backend, BackendConfig, BackendTransaction, BackendWriteTransaction, DbBackup, IdList,
-        IdxKey, OperationError,
+        backup, Backend, BackendConfig, BackendTransaction, BackendWriteTransaction,
+        BackupCompression, BackupFormat, BackupOptions, DbBackup, IdList, IdxKey, OperationError,
+        RestoreOptions,
     };
     use crate::prelude::*;
     use crate::repl::cid::Cid;
@@ -2041,6 +2208,10 @@ mod tests {
                     attr: AttrString::from("tb"),
                     itype: IndexType::Equality,
                 },
+                IdxKey {
+                    attr: AttrString::from("tc"),
+                    itype: IndexType::Ordering,
+                },
             ];
 
             let be = Backend::new(BackendConfig::new_test("main"), idxmeta, false)
@@ -2361,8 +2532,10 @@ mod tests {
                 _ => (),
             }
 
-            be.backup(&db_backup_file_name).expect("Backup failed!");
-            be.restore(&db_backup_file_name).expect("Restore failed!");
+            be.backup(&db_backup_file_name, &BackupOptions::default())
+                .expect("Backup failed!");
+            be.restore(&db_backup_file_name, &RestoreOptions::default())
+                .expect("Restore failed!");
 
             assert!(be.verify().is_empty());
         });
@@ -2416,21 +2589,22 @@ mod tests {
                 _ => (),
             }
 
-            be.backup(&db_backup_file_name).expect("Backup failed!");
+            be.backup(&db_backup_file_name, &BackupOptions::default())
+                .expect("Backup failed!");
 
             // Now here, we need to tamper with the file.
             let serialized_string = fs::read_to_string(&db_backup_file_name).unwrap();
             let mut dbbak: DbBackup = serde_json::from_str(&serialized_string).unwrap();
 
             match &mut dbbak {
-                DbBackup::V1(_) => {
+                DbBackup::V1(_) | DbBackup::V2 { .. } => {
                     // We no longer use these format versions!
                     unreachable!()
                 }
-                DbBackup::V2 {
+                DbBackup::V3 {
                     db_s_uuid: _,
-                    db_d_uuid: _,
                     db_ts_max: _,
+                    manifest: _,
                     entries,
                 } => {
                     let _ = entries.pop();
@@ -2440,8 +2614,228 @@ mod tests {
             let serialized_entries_str = serde_json::to_string_pretty(&dbbak).unwrap();
             fs::write(&db_backup_file_name, serialized_entries_str).unwrap();
 
-            be.restore(&db_backup_file_name).expect("Restore failed!");
+            // The manifest no longer matches, so the restore is refused before anything
+            // is removed.
+            assert!(
+                backup::verify_backup(&db_backup_file_name, &RestoreOptions::default()).is_err()
+            );
+            assert!(be
+                .restore(&db_backup_file_name, &RestoreOptions::default())
+                .is_err());
 
+            assert!(entry_exists!(be, e1));
+            assert!(entry_exists!(be, e2));
+            assert!(entry_exists!(be, e3));
+            assert!(be.verify().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_be_backup_restore_stream() {
+        use age::secrecy::ExposeSecret;
+
+        let out_dir = option_env!("OUT_DIR").unwrap_or("/tmp");
+        let identity = age::x25519::Identity::generate();
+        let identity_file = format!("{}/.backup3_test.identity", out_dir);
+        fs::write(&identity_file, identity.to_string().expose_secret()).unwrap();
+        let passphrase_file = format!("{}/.backup3_test.passphrase", out_dir);
+        fs::write(&passphrase_file, "correct horse battery staple\n").unwrap();
+
+        let cases = [
+            (BackupCompression::None, None, None),
+            (
+                BackupCompression::Zstd,
+                Some(identity.to_public().to_string()),
+                None,
+            ),
+            (BackupCompression::Gzip, None, Some(passphrase_file.clone())),
+        ];
+
+        for (compression, recipient, passphrase) in cases {
+            let options = BackupOptions {
+                format: BackupFormat::Stream,
+                compression,
+                recipient,
+                passphrase_file: passphrase,
+            };
+            let db_backup_file_name =
+                format!("{}/.backup3_test.{}", out_dir, options.file_extension());
+            eprintln!(" ⚠️   {db_backup_file_name}");
+
+            run_test!(|be: &mut BackendWriteTransaction| {
+                be.reset_db_s_uuid().unwrap();
+                be.reset_db_d_uuid().unwrap();
+                be.set_db_ts_max(Duration::from_secs(1)).unwrap();
+
+                // Enough entries to span more than one chunk.
+                let entries: Vec<_> = (0..1500)
+                    .map(|i| {
+                        let mut e: Entry<EntryInit, EntryNew> = Entry::new();
+                        e.add_ava("userid", Value::from(format!("user{}", i).as_str()));
+                        e.add_ava("uuid", Value::Uuid(Uuid::new_v4()));
+                        unsafe { e.into_sealed_new() }
+                    })
+                    .collect();
+                assert!(be.create(&CID_ZERO, entries).is_ok());
+
+                let _ = fs::remove_file(&db_backup_file_name);
+                be.backup(&db_backup_file_name, &options)
+                    .expect("Backup failed!");
+
+                // Without the key material, the backup can't be restored.
+                if options.recipient.is_some() || options.passphrase_file.is_some() {
+                    assert!(be
+                        .restore(&db_backup_file_name, &RestoreOptions::default())
+                        .is_err());
+                }
+
+                let restore_options = RestoreOptions {
+                    identity_file: Some(identity_file.clone()),
+                    passphrase_file: Some(passphrase_file.clone()),
+                };
+                be.restore(&db_backup_file_name, &restore_options)
+                    .expect("Restore failed!");
+
+                let restored = be
+                    .get_idlayer()
+                    .get_identry(&IdList::AllIds)
+                    .expect("Failed to load entries");
+                assert!(restored.len() == 1500);
+                assert!(be.verify().is_empty());
+
+                let summary = backup::verify_backup(&db_backup_file_name, &restore_options)
+                    .expect("Verify failed!");
+                assert!(summary.entry_count == 1500);
+                assert!(summary.manifest.map(|m| m.chunk_checksums.len()) == Some(2));
+
+                // A stream that has lost its end is refused, even though every line in it
+                // is valid.
+                if options.compression == BackupCompression::None && options.recipient.is_none() {
+                    let content = fs::read_to_string(&db_backup_file_name).unwrap();
+                    let truncated: Vec<_> = content.lines().collect();
+                    let truncated = truncated[..truncated.len() - 2].join("\n");
+                    fs::write(&db_backup_file_name, truncated).unwrap();
+
+                    assert!(backup::verify_backup(&db_backup_file_name, &restore_options).is_err());
+                    assert!(be.restore(&db_backup_file_name, &restore_options).is_err());
+                    let restored = be
+                        .get_idlayer()
+                        .get_identry(&IdList::AllIds)
+                        .expect("Failed to load entries");
+                    assert!(restored.len() == 1500);
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn test_be_backup_verify_damaged() {
+        let out_dir = option_env!("OUT_DIR").unwrap_or("/tmp");
+        let options = BackupOptions {
+            format: BackupFormat::Stream,
+            ..Default::default()
+        };
+        let db_backup_file_name = format!("{}/.backup4_test.{}", out_dir, options.file_extension());
+        eprintln!(" ⚠️   {db_backup_file_name}");
+
+        run_test!(|be: &mut BackendWriteTransaction| {
+            be.reset_db_s_uuid().unwrap();
+            be.reset_db_d_uuid().unwrap();
+            be.set_db_ts_max(Duration::from_secs(1)).unwrap();
+
+            // The domain level is read from the domain info entry.
+            let mut domain_info: Entry<EntryInit, EntryNew> = Entry::new();
+            domain_info.add_ava("uuid", Value::Uuid(UUID_DOMAIN_INFO));
+            domain_info.add_ava("version", Value::new_uint32(DOMAIN_TGT_LEVEL));
+
+            let entries: Vec<_> = (0..1200)
+                .map(|i| {
+                    let mut e: Entry<EntryInit, EntryNew> = Entry::new();
+                    e.add_ava("userid", Value::from(format!("user{}", i).as_str()));
+                    e.add_ava("uuid", Value::Uuid(Uuid::new_v4()));
+                    unsafe { e.into_sealed_new() }
+                })
+                .chain(std::iter::once(unsafe { domain_info.into_sealed_new() }))
+                .collect();
+            assert!(be.create(&CID_ZERO, entries).is_ok());
+
+            let _ = fs::remove_file(&db_backup_file_name);
+            be.backup(&db_backup_file_name, &options)
+                .expect("Backup failed!");
+
+            let summary = backup::verify_backup(&db_backup_file_name, &RestoreOptions::default())
+                .expect("Verify failed!");
+            assert!(summary.entry_count == 1201);
+            assert!(summary.manifest.map(|m| m.domain_level) == Some(DOMAIN_TGT_LEVEL));
+
+            // The magic, header, two chunks and the manifest.
+            let content = fs::read_to_string(&db_backup_file_name).unwrap();
+            let lines: Vec<_> = content.lines().collect();
+            assert!(lines.len() == 5);
+
+            let damaged = [
+                // Cut off part way through a chunk.
+                content[..content.len() / 2].to_string(),
+                // A chunk is altered, but is still valid and has the same entries.
+                content.replacen("\"user5\"", "\"userX\"", 1),
+                // The manifest is missing.
+                lines[..4].join("\n"),
+            ];
+            for damaged in damaged {
+                assert!(damaged != content);
+                fs::write(&db_backup_file_name, damaged).unwrap();
+                assert!(
+                    backup::verify_backup(&db_backup_file_name, &RestoreOptions::default())
+                        .is_err()
+                );
+                assert!(be
+                    .restore(&db_backup_file_name, &RestoreOptions::default())
+                    .is_err());
+            }
+
+            // Nothing was removed by the failed restores.
+            let restored = be
+                .get_idlayer()
+                .get_identry(&IdList::AllIds)
+                .expect("Failed to load entries");
+            assert!(restored.len() == 1201);
+        });
+    }
+
+    #[test]
+    fn test_be_backup_restore_dry_run() {
+        let db_backup_file_name = format!(
+            "{}/.backup5_test.json",
+            option_env!("OUT_DIR").unwrap_or("/tmp")
+        );
+        eprintln!(" ⚠️   {db_backup_file_name}");
+
+        run_test!(|be: &mut BackendWriteTransaction| {
+            be.reset_db_s_uuid().unwrap();
+            be.reset_db_d_uuid().unwrap();
+            be.set_db_ts_max(Duration::from_secs(1)).unwrap();
+
+            let mut e1: Entry<EntryInit, EntryNew> = Entry::new();
+            e1.add_ava("userid", Value::from("william"));
+            e1.add_ava("uuid", Value::from("db237e8a-0079-4b8c-8a56-593b22aa44d1"));
+            let ve1 = unsafe { e1.into_sealed_new() };
+            assert!(be.create(&CID_ZERO, vec![ve1]).is_ok());
+
+            let _ = fs::remove_file(&db_backup_file_name);
+            be.backup(&db_backup_file_name, &BackupOptions::default())
+                .expect("Backup failed!");
+        });
+
+        // A dry run restores into a scratch database, rather than the one that was backed up.
+        run_test!(|be: &mut BackendWriteTransaction| {
+            be.restore(&db_backup_file_name, &RestoreOptions::default())
+                .expect("Restore failed!");
+
+            let restored = be
+                .get_idlayer()
+                .get_identry(&IdList::AllIds)
+                .expect("Failed to load entries");
+            assert!(restored.len() == 1);
             assert!(be.verify().is_empty());
         });
     }
@@ -2464,7 +2858,7 @@ mod tests {
         run_test!(|be: &mut BackendWriteTransaction| {
             // Add some test data?
             let missing = be.missing_idxs().unwrap();
-            assert!(missing.len() == 7);
+            assert!(missing.len() == 8);
             assert!(be.reindex().is_ok());
             let missing = be.missing_idxs().unwrap();
             debug!("{:?}", missing);
@@ -2492,7 +2886,7 @@ mod tests {
             be.purge_idxs().unwrap();
             // Check they are gone
             let missing = be.missing_idxs().unwrap();
-            assert!(missing.len() == 7);
+            assert!(missing.len() == 8);
             assert!(be.reindex().is_ok());
             let missing = be.missing_idxs().unwrap();
             debug!("{:?}", missing);
@@ -3108,6 +3502,84 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_be_index_search_ordering() {
+        // The test idxmeta of filter_resolved! has no ordering indexes, so build the
+        // resolved terms directly.
+        macro_rules! ord_search {
+            ($be:expr, $term:ident, $value:expr, $expect:expr) => {{
+                let f = FilterResolved::$term(
+                    AttrString::from("tc"),
+                    PartialValue::new_uint32($value),
+                    NonZeroU8::new(1),
+                );
+                match $be.filter2idl(&f, 0).unwrap() {
+                    (IdList::Indexed(idl), _plan) => {
+                        assert_eq!(idl, IDLBitRange::from_iter($expect));
+                    }
+                    (r, plan) => panic!("Range was not indexed {:?} {:?}", r, plan),
+                }
+            }};
+        }
+
+        let _ = sketching::test_init();
+
+        let idxmeta = vec![
+            IdxKey::new("uuid", IndexType::Equality),
+            IdxKey::new("uuid", IndexType::Presence),
+            IdxKey::new("tc", IndexType::Ordering),
+        ];
+        let be = Backend::new(BackendConfig::new_test("main"), idxmeta, false)
+            .expect("Failed to setup backend");
+
+        let mut be_txn = be.write();
+        assert!(be_txn.reindex().is_ok());
+
+        let mut e1: Entry<EntryInit, EntryNew> = Entry::new();
+        e1.add_ava("uuid", Value::from("db237e8a-0079-4b8c-8a56-593b22aa44d1"));
+        e1.add_ava("tc", Value::new_uint32(5));
+        let e1 = unsafe { e1.into_sealed_new() };
+
+        let mut e2: Entry<EntryInit, EntryNew> = Entry::new();
+        e2.add_ava("uuid", Value::from("db237e8a-0079-4b8c-8a56-593b22aa44d2"));
+        e2.add_ava("tc", Value::new_uint32(20));
+        let e2 = unsafe { e2.into_sealed_new() };
+
+        let mut e3: Entry<EntryInit, EntryNew> = Entry::new();
+        e3.add_ava("uuid", Value::from("db237e8a-0079-4b8c-8a56-593b22aa44d3"));
+        e3.add_ava("tc", Value::new_uint32(12));
+        e3.add_ava("tc", Value::new_uint32(400));
+        let e3 = unsafe { e3.into_sealed_new() };
+
+        let rset = be_txn.create(&CID_ZERO, vec![e1, e2, e3]).unwrap();
+        let rset: Vec<_> = rset.into_iter().map(Arc::new).collect();
+
+        // The new keys are only in the cache of this transaction.
+        ord_search!(be_txn, LessThan, 10, vec![1]);
+        ord_search!(be_txn, LessThan, 15, vec![1, 3]);
+        ord_search!(be_txn, GreaterThan, 15, vec![2, 3]);
+        // 400 would sort before 5 if the keys were not fixed width.
+        ord_search!(be_txn, GreaterThan, 20, vec![3]);
+        ord_search!(be_txn, GreaterThan, 400, vec![]);
+
+        // Moving a value must move the entry between ranges.
+        let mut ce1 = unsafe { rset[0].as_ref().clone().into_invalid() };
+        ce1.purge_ava("tc");
+        ce1.add_ava("tc", Value::new_uint32(50));
+        let ce1 = unsafe { ce1.into_sealed_committed() };
+        be_txn.modify(&CID_ZERO, &rset[..1], &[ce1]).unwrap();
+
+        ord_search!(be_txn, LessThan, 10, vec![]);
+        ord_search!(be_txn, GreaterThan, 20, vec![1, 3]);
+
+        assert!(be_txn.commit().is_ok());
+
+        // And once committed, the same ranges are read from the db.
+        let mut be_txn = be.read();
+        ord_search!(be_txn, LessThan, 15, vec![3]);
+        ord_search!(be_txn, GreaterThan, 15, vec![1, 2, 3]);
+    }
+
     #[test]
     fn test_be_index_slope_generation() {
         run_test!(|be: &mut BackendWriteTransaction| {
@@ -880,6 +880,7 @@ lazy_static! {
         ("class", CLASS_OBJECT.clone()),
         ("class", CLASS_ACCESS_CONTROL_PROFILE.clone()),
         ("class", CLASS_ACCESS_CONTROL_CREATE.clone()),
+        ("class", CLASS_ACCESS_CONTROL_DELETE.clone()),
         ("class", CLASS_ACCESS_CONTROL_MODIFY.clone()),
         ("class", CLASS_ACCESS_CONTROL_SEARCH.clone()),
         ("name", Value::new_iname("idm_acp_schema_write_attrs_priv")),
@@ -1031,6 +1032,7 @@ lazy_static! {
         ("class", CLASS_OBJECT.clone()),
         ("class", CLASS_ACCESS_CONTROL_PROFILE.clone()),
         ("class", CLASS_ACCESS_CONTROL_CREATE.clone()),
+        ("class", CLASS_ACCESS_CONTROL_DELETE.clone()),
         ("class", CLASS_ACCESS_CONTROL_MODIFY.clone()),
         ("class", CLASS_ACCESS_CONTROL_SEARCH.clone()),
         ("name", Value::new_iname("idm_acp_schema_write_classes_priv")),
@@ -1215,15 +1217,18 @@ lazy_static! {
         ("acp_search_attr", Value::new_iutf8("es256_private_key_der")),
         ("acp_search_attr", Value::new_iutf8("fernet_private_key_str")),
         ("acp_search_attr", Value::new_iutf8("cookie_private_key")),
+        ("acp_search_attr", Value::new_iutf8("webauthn_attestation_ca_list")),
         ("acp_modify_removedattr", Value::new_iutf8("domain_display_name")),
         ("acp_modify_removedattr", Value::new_iutf8("domain_ssid")),
         ("acp_modify_removedattr", Value::new_iutf8("domain_ldap_basedn")),
         ("acp_modify_removedattr", Value::new_iutf8("es256_private_key_der")),
         ("acp_modify_removedattr", Value::new_iutf8("cookie_private_key")),
         ("acp_modify_removedattr", Value::new_iutf8("fernet_private_key_str")),
+        ("acp_modify_removedattr", Value::new_iutf8("webauthn_attestation_ca_list")),
         ("acp_modify_presentattr", Value::new_iutf8("domain_display_name")),
         ("acp_modify_presentattr", Value::new_iutf8("domain_ldap_basedn")),
-        ("acp_modify_presentattr", Value::new_iutf8("domain_ssid"))
+        ("acp_modify_presentattr", Value::new_iutf8("domain_ssid")),
+        ("acp_modify_presentattr", Value::new_iutf8("webauthn_attestation_ca_list"))
     );
 }
 
@@ -1450,6 +1455,7 @@ lazy_static! {
         ("acp_search_attr", Value::new_iutf8("oauth2_rs_origin_landing")),
         ("acp_search_attr", Value::new_iutf8("oauth2_rs_scope_map")),
         ("acp_search_attr", Value::new_iutf8("oauth2_rs_sup_scope_map")),
+        ("acp_search_attr", Value::new_iutf8("oauth2_rs_token_exchange_audience")),
         ("acp_search_attr", Value::new_iutf8("oauth2_rs_basic_secret")),
         ("acp_search_attr", Value::new_iutf8("oauth2_rs_token_key")),
         ("acp_search_attr", Value::new_iutf8("es256_private_key_der")),
@@ -1457,6 +1463,12 @@ lazy_static! {
         ("acp_search_attr", Value::new_iutf8("rs256_private_key_der")),
         ("acp_search_attr", Value::new_iutf8("oauth2_jwt_legacy_crypto_enable")),
         ("acp_search_attr", Value::new_iutf8("oauth2_prefer_short_username")),
+        ("acp_search_attr", Value::new_iutf8("oauth2_require_par")),
+        ("acp_search_attr", Value::new_iutf8("oauth2_rs_client_jwk")),
+        ("acp_search_attr", Value::new_iutf8("oauth2_rs_client_jwks_url")),
+        ("acp_search_attr", Value::new_iutf8("oauth2_rs_backchannel_logout_uri")),
+        ("acp_search_attr", Value::new_iutf8("oauth2_rs_registered_by")),
+        ("acp_search_attr", Value::new_iutf8("oauth2_rs_service_account")),
 
         ("acp_modify_removedattr", Value::new_iutf8("description")),
         ("acp_modify_removedattr", Value::new_iutf8("displayname")),
@@ -1465,6 +1477,7 @@ lazy_static! {
         ("acp_modify_removedattr", Value::new_iutf8("oauth2_rs_origin_landing")),
         ("acp_modify_removedattr", Value::new_iutf8("oauth2_rs_scope_map")),
         ("acp_modify_removedattr", Value::new_iutf8("oauth2_rs_sup_scope_map")),
+        ("acp_modify_removedattr", Value::new_iutf8("oauth2_rs_token_exchange_audience")),
         ("acp_modify_removedattr", Value::new_iutf8("oauth2_rs_basic_secret")),
         ("acp_modify_removedattr", Value::new_iutf8("oauth2_rs_token_key")),
         ("acp_modify_removedattr", Value::new_iutf8("es256_private_key_der")),
@@ -1472,6 +1485,12 @@ lazy_static! {
         ("acp_modify_removedattr", Value::new_iutf8("rs256_private_key_der")),
         ("acp_modify_removedattr", Value::new_iutf8("oauth2_jwt_legacy_crypto_enable")),
         ("acp_modify_removedattr", Value::new_iutf8("oauth2_prefer_short_username")),
+        ("acp_modify_removedattr", Value::new_iutf8("oauth2_require_par")),
+        ("acp_modify_removedattr", Value::new_iutf8("oauth2_rs_client_jwk")),
+        ("acp_modify_removedattr", Value::new_iutf8("oauth2_rs_client_jwks_url")),
+        ("acp_modify_removedattr", Value::new_iutf8("oauth2_rs_backchannel_logout_uri")),
+        ("acp_modify_removedattr", Value::new_iutf8("oauth2_rs_registered_by")),
+        ("acp_modify_removedattr", Value::new_iutf8("oauth2_rs_service_account")),
 
 
         ("acp_modify_presentattr", Value::new_iutf8("description")),
@@ -1480,10 +1499,16 @@ lazy_static! {
         ("acp_modify_presentattr", Value::new_iutf8("oauth2_rs_origin")),
         ("acp_modify_presentattr", Value::new_iutf8("oauth2_rs_origin_landing")),
         ("acp_modify_presentattr", Value::new_iutf8("oauth2_rs_sup_scope_map")),
+        ("acp_modify_presentattr", Value::new_iutf8("oauth2_rs_token_exchange_audience")),
         ("acp_modify_presentattr", Value::new_iutf8("oauth2_rs_scope_map")),
         ("acp_modify_presentattr", Value::new_iutf8("oauth2_allow_insecure_client_disable_pkce")),
         ("acp_modify_presentattr", Value::new_iutf8("oauth2_jwt_legacy_crypto_enable")),
         ("acp_modify_presentattr", Value::new_iutf8("oauth2_prefer_short_username")),
+        ("acp_modify_presentattr", Value::new_iutf8("oauth2_require_par")),
+        ("acp_modify_presentattr", Value::new_iutf8("oauth2_rs_client_jwk")),
+        ("acp_modify_presentattr", Value::new_iutf8("oauth2_rs_client_jwks_url")),
+        ("acp_modify_presentattr", Value::new_iutf8("oauth2_rs_backchannel_logout_uri")),
+        ("acp_modify_presentattr", Value::new_iutf8("oauth2_rs_service_account")),
 
         ("acp_create_attr", Value::new_iutf8("class")),
         ("acp_create_attr", Value::new_iutf8("description")),
@@ -1492,10 +1517,16 @@ lazy_static! {
         ("acp_create_attr", Value::new_iutf8("oauth2_rs_origin")),
         ("acp_create_attr", Value::new_iutf8("oauth2_rs_origin_landing")),
         ("acp_create_attr", Value::new_iutf8("oauth2_rs_sup_scope_map")),
+        ("acp_create_attr", Value::new_iutf8("oauth2_rs_token_exchange_audience")),
         ("acp_create_attr", Value::new_iutf8("oauth2_rs_scope_map")),
         ("acp_create_attr", Value::new_iutf8("oauth2_allow_insecure_client_disable_pkce")),
         ("acp_create_attr", Value::new_iutf8("oauth2_jwt_legacy_crypto_enable")),
         ("acp_create_attr", Value::new_iutf8("oauth2_prefer_short_username")),
+        ("acp_create_attr", Value::new_iutf8("oauth2_require_par")),
+        ("acp_create_attr", Value::new_iutf8("oauth2_rs_client_jwk")),
+        ("acp_create_attr", Value::new_iutf8("oauth2_rs_client_jwks_url")),
+        ("acp_create_attr", Value::new_iutf8("oauth2_rs_backchannel_logout_uri")),
+        ("acp_create_attr", Value::new_iutf8("oauth2_rs_service_account")),
 
 
         ("acp_create_class", Value::new_iutf8("object")),
@@ -1504,6 +1535,42 @@ lazy_static! {
     );
 }
 
+lazy_static! {
+    pub static ref E_IDM_HP_ACP_OAUTH2_REGISTRATION_MANAGE_PRIV_V1: EntryInitNew = entry_init!(
+        ("class", CLASS_OBJECT.clone()),
+        ("class", CLASS_ACCESS_CONTROL_PROFILE.clone()),
+        ("class", CLASS_ACCESS_CONTROL_MODIFY.clone()),
+        ("class", CLASS_ACCESS_CONTROL_SEARCH.clone()),
+        ("name", Value::new_iname("idm_acp_hp_oauth2_registration_manage_priv")),
+        ("uuid", Value::Uuid(UUID_IDM_HP_ACP_OAUTH2_REGISTRATION_MANAGE_PRIV_V1)),
+        (
+            "description",
+            Value::new_utf8s("Builtin IDM Control for allowing service accounts to register oauth2 resource servers.")
+        ),
+        (
+            "acp_receiver_group",
+            Value::Refer(UUID_IDM_HP_OAUTH2_MANAGE_PRIV)
+        ),
+        (
+            "acp_targetscope",
+            Value::new_json_filter_s(
+                "{\"and\": [{\"eq\": [\"class\",\"service_account\"]},{\"andnot\": {\"or\": [{\"eq\": [\"class\", \"tombstone\"]}, {\"eq\": [\"class\", \"recycled\"]}]}}]}"
+            )
+                .expect("Invalid JSON filter")
+        ),
+        ("acp_search_attr", Value::new_iutf8("class")),
+        ("acp_search_attr", Value::new_iutf8("name")),
+        ("acp_search_attr", Value::new_iutf8("spn")),
+        ("acp_search_attr", Value::new_iutf8("uuid")),
+        ("acp_search_attr", Value::new_iutf8("oauth2_registration_origin")),
+        ("acp_search_attr", Value::new_iutf8("oauth2_registration_scope_map")),
+        ("acp_modify_removedattr", Value::new_iutf8("oauth2_registration_origin")),
+        ("acp_modify_removedattr", Value::new_iutf8("oauth2_registration_scope_map")),
+        ("acp_modify_presentattr", Value::new_iutf8("oauth2_registration_origin")),
+        ("acp_modify_presentattr", Value::new_iutf8("oauth2_registration_scope_map"))
+    );
+}
+
 lazy_static! {
     pub static ref E_IDM_HP_ACP_SERVICE_ACCOUNT_INTO_PERSON_MIGRATE_V1: EntryInitNew = entry_init!(
         ("class", CLASS_OBJECT.clone()),
@@ -1583,3 +1650,79 @@ lazy_static! {
         ("acp_create_class", Value::new_iutf8("sync_account"))
     );
 }
+
+lazy_static! {
+    pub static ref E_IDM_ACP_ACCOUNT_POLICY_MANAGE_PRIV_V1: EntryInitNew = entry_init!(
+        ("class", CLASS_OBJECT.clone()),
+        ("class", CLASS_ACCESS_CONTROL_PROFILE.clone()),
+        ("class", CLASS_ACCESS_CONTROL_MODIFY.clone()),
+        ("class", CLASS_ACCESS_CONTROL_SEARCH.clone()),
+        ("name", Value::new_iname("idm_acp_account_policy_manage_priv")),
+        ("uuid", Value::Uuid(UUID_IDM_ACP_ACCOUNT_POLICY_MANAGE_PRIV_V1)),
+        (
+            "description",
+            Value::new_utf8s("Builtin IDM Control for managing the account policy of groups.")
+        ),
+        ("acp_receiver_group", Value::Refer(UUID_SYSTEM_ADMINS)),
+        (
+            "acp_targetscope",
+            Value::new_json_filter_s(
+                "{\"and\": [{\"eq\": [\"class\",\"group\"]}, {\"andnot\": {\"or\": [{\"eq\": [\"class\", \"tombstone\"]}, {\"eq\": [\"class\", \"recycled\"]}]}}]}"
+            )
+                .expect("Invalid JSON filter")
+        ),
+        ("acp_search_attr", Value::new_iutf8("class")),
+        ("acp_search_attr", Value::new_iutf8("name")),
+        ("acp_search_attr", Value::new_iutf8("uuid")),
+        ("acp_search_attr", Value::new_iutf8("spn")),
+        ("acp_search_attr", Value::new_iutf8("authsession_expiry")),
+        ("acp_search_attr", Value::new_iutf8("privilege_expiry")),
+        ("acp_search_attr", Value::new_iutf8("auth_password_minimum_length")),
+        ("acp_search_attr", Value::new_iutf8("credential_type_minimum")),
+        ("acp_search_attr", Value::new_iutf8("oauth2_access_token_expiry")),
+        ("acp_modify_removedattr", Value::new_iutf8("class")),
+        ("acp_modify_removedattr", Value::new_iutf8("authsession_expiry")),
+        ("acp_modify_removedattr", Value::new_iutf8("privilege_expiry")),
+        ("acp_modify_removedattr", Value::new_iutf8("auth_password_minimum_length")),
+        ("acp_modify_removedattr", Value::new_iutf8("credential_type_minimum")),
+        ("acp_modify_removedattr", Value::new_iutf8("oauth2_access_token_expiry")),
+        ("acp_modify_presentattr", Value::new_iutf8("class")),
+        ("acp_modify_presentattr", Value::new_iutf8("authsession_expiry")),
+        ("acp_modify_presentattr", Value::new_iutf8("privilege_expiry")),
+        ("acp_modify_presentattr", Value::new_iutf8("auth_password_minimum_length")),
+        ("acp_modify_presentattr", Value::new_iutf8("credential_type_minimum")),
+        ("acp_modify_presentattr", Value::new_iutf8("oauth2_access_token_expiry")),
+        ("acp_modify_class", Value::new_iutf8("account_policy"))
+    );
+}
+
+lazy_static! {
+    pub static ref E_IDM_ACP_MESSAGE_QUEUE_MANAGE_PRIV_V1: EntryInitNew = entry_init!(
+        ("class", CLASS_OBJECT.clone()),
+        ("class", CLASS_ACCESS_CONTROL_PROFILE.clone()),
+        ("class", CLASS_ACCESS_CONTROL_DELETE.clone()),
+        ("class", CLASS_ACCESS_CONTROL_SEARCH.clone()),
+        ("name", Value::new_iname("idm_acp_message_queue_manage_priv")),
+        ("uuid", Value::Uuid(UUID_IDM_ACP_MESSAGE_QUEUE_MANAGE_PRIV_V1)),
+        (
+            "description",
+            Value::new_utf8s("Builtin IDM Control for reviewing and removing outbound messages.")
+        ),
+        ("acp_receiver_group", Value::Refer(UUID_IDM_ADMINS)),
+        (
+            "acp_targetscope",
+            Value::new_json_filter_s(
+                "{\"and\": [{\"eq\": [\"class\",\"outbound_message\"]}, {\"andnot\": {\"or\": [{\"eq\": [\"class\", \"tombstone\"]}, {\"eq\": [\"class\", \"recycled\"]}]}}]}"
+            )
+                .expect("Invalid JSON filter")
+        ),
+        ("acp_search_attr", Value::new_iutf8("class")),
+        ("acp_search_attr", Value::new_iutf8("uuid")),
+        ("acp_search_attr", Value::new_iutf8("mail_destination")),
+        ("acp_search_attr", Value::new_iutf8("message_recipient")),
+        ("acp_search_attr", Value::new_iutf8("send_after")),
+        ("acp_search_attr", Value::new_iutf8("delivered_at")),
+        ("acp_search_attr", Value::new_iutf8("delivery_attempts")),
+        ("acp_search_attr", Value::new_iutf8("delivery_error"))
+    );
+}
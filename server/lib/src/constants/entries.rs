@@ -466,6 +466,19 @@ pub const JSON_IDM_HP_SYNC_ACCOUNT_MANAGE_PRIV: &str = r#"{
     }
 }"#;
 
+/// Members of this group may search the audit log.
+pub const JSON_IDM_AUDIT_READ_PRIV: &str = r#"{
+    "attrs": {
+        "class": ["group", "object"],
+        "name": ["idm_audit_read_priv"],
+        "uuid": ["00000000-0000-0000-0000-000000000040"],
+        "description": ["Builtin IDM Group for granting read access to the audit log"],
+        "member": [
+            "00000000-0000-0000-0000-000000000019"
+        ]
+    }
+}"#;
+
 // == dyn groups
 
 pub const JSON_IDM_ALL_PERSONS: &str = r#"{
@@ -569,6 +582,7 @@ pub const JSON_IDM_HIGH_PRIVILEGE_V1: &str = r#"{
             "00000000-0000-0000-0000-000000000032",
             "00000000-0000-0000-0000-000000000034",
             "00000000-0000-0000-0000-000000000037",
+            "00000000-0000-0000-0000-000000000040",
             "00000000-0000-0000-0000-000000001000"
         ]
     }
@@ -17,7 +17,7 @@ pub use crate::constants::values::*;
 use std::time::Duration;
 
 // Increment this as we add new schema types and values!!!
-pub const SYSTEM_INDEX_VERSION: i64 = 29;
+pub const SYSTEM_INDEX_VERSION: i64 = 32;
 
 /*
  * domain functional levels
@@ -91,3 +91,55 @@ pub const GRACE_WINDOW: Duration = Duration::from_secs(300);
 /// How long access tokens should last. This is NOT the length
 /// of the refresh token, which is bound to the issuing session.
 pub const OAUTH2_ACCESS_TOKEN_EXPIRY: u32 = 15 * 60;
+
+/// How long a device authorisation remains valid for the user to approve it.
+pub const OAUTH2_DEVICE_CODE_EXPIRY: u32 = 10 * 60;
+/// The minimum time in seconds a device must wait between polls of the token endpoint.
+pub const OAUTH2_DEVICE_CODE_INTERVAL: u32 = 5;
+/// The maximum number of outstanding device authorisations a single client may hold.
+pub const OAUTH2_DEVICE_CODE_MAX_PER_CLIENT: usize = 16;
+
+/// How long a pushed authorisation request may be referenced by its request_uri. This
+/// needs to be long enough for the user to login if they have no session.
+pub const OAUTH2_PAR_EXPIRY: u32 = 5 * 60;
+
+/// The longest a client assertion may be valid for. We must remember the jti of each
+/// assertion until it expires, so this bounds how many we hold.
+pub const OAUTH2_CLIENT_ASSERTION_MAX_EXPIRY: u32 = 5 * 60;
+
+/// The longest a signed request object may be valid for, measured from its nbf or iat.
+pub const OAUTH2_REQUEST_OBJECT_MAX_EXPIRY: u32 = 60 * 60;
+
+/// How long we wait on a resource server to accept a back-channel logout token.
+pub const OAUTH2_BACKCHANNEL_LOGOUT_TIMEOUT: u64 = 10;
+
+/// How long we wait on a resource server's jwks url when refreshing its keys.
+pub const OAUTH2_CLIENT_JWKS_FETCH_TIMEOUT: u64 = 10;
+/// The largest jwks document we will read from a resource server's jwks url.
+pub const OAUTH2_CLIENT_JWKS_MAX_SIZE: usize = 64 * 1024;
+
+/// How often queued outbound messages are delivered.
+pub const MESSAGE_DELIVERY_FREQUENCY: u64 = 30;
+
+/// How many times we attempt to deliver an outbound message before giving up on it. Each
+/// failed attempt doubles the delay until the next.
+pub const MESSAGE_DELIVERY_MAX_ATTEMPTS: u32 = 8;
+
+/// How long an outbound message is kept after it was last attempted, in seconds.
+pub const MESSAGE_RETENTION: u64 = 30 * 86400;
+
+/// How many network addresses we remember an account authenticating from. Once this is
+/// exceeded the least recently used address is forgotten, so that the set can't grow without
+/// bound.
+pub const AUTH_SOURCE_IP_MAX: usize = 32;
+
+/// How old the last use of a remembered network address must be, in seconds, before it is
+/// updated. This avoids rewriting the address on every authentication.
+pub const AUTH_SOURCE_IP_REFRESH: u64 = 86400;
+
+/// The most events that a single search of the audit log can return.
+pub const AUDIT_LOG_SEARCH_LIMIT: usize = 1000;
+
+/// The number of audit events that may wait to be recorded. When the queue is full
+/// further events are written to the server log instead of the audit log.
+pub const AUDIT_EVENT_QUEUE_SIZE: usize = 4096;
@@ -424,7 +424,8 @@ pub const JSON_SCHEMA_ATTR_GIDNUMBER: &str = r#"{
         "The groupid (uid) number of a group or account. This is the same value as the UID number on posix accounts for security reasons."
       ],
       "index": [
-        "EQUALITY"
+        "EQUALITY",
+        "ORDERING"
       ],
       "unique": [
         "true"
@@ -516,10 +517,713 @@ pub const JSON_SCHEMA_ATTR_UNIX_PASSWORD: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "A posix users unix login password."
-      ],
-      "index": [
-        "PRESENCE"
+        "A posix users unix login password."
+      ],
+      "index": [
+        "PRESENCE"
+      ],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "attributename": [
+        "unix_password"
+      ],
+      "syntax": [
+        "CREDENTIAL"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000062"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_NSUNIQUEID: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "A unique id compatibility for 389-ds/dsee"
+      ],
+      "index": [
+        "EQUALITY"
+      ],
+      "unique": [
+        "true"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "sync_allowed": [
+        "true"
+      ],
+      "attributename": [
+        "nsuniqueid"
+      ],
+      "syntax": [
+        "NSUNIQUEID"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000067"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_ACCOUNT_EXPIRE: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "The datetime after which this accounnt no longer may authenticate."
+      ],
+      "index": [
+        "ORDERING"
+      ],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "sync_allowed": [
+        "true"
+      ],
+      "attributename": [
+        "account_expire"
+      ],
+      "syntax": [
+        "DATETIME"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000072"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_ACCOUNT_VALID_FROM: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "The datetime after which this account may commence authenticating."
+      ],
+      "index": [],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "sync_allowed": [
+        "true"
+      ],
+      "attributename": [
+        "account_valid_from"
+      ],
+      "syntax": [
+        "DATETIME"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000073"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_NAME: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "The unique name of an external Oauth2 resource"
+      ],
+      "index": [
+        "EQUALITY"
+      ],
+      "unique": [
+        "true"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "attributename": [
+        "oauth2_rs_name"
+      ],
+      "syntax": [
+        "UTF8STRING_INAME"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000080"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_ORIGIN: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "The origin domain of an oauth2 resource server"
+      ],
+      "index": [],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "attributename": [
+        "oauth2_rs_origin"
+      ],
+      "syntax": [
+        "URL"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000081"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_ORIGIN_LANDING: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "The landing page of an RS, that will automatically trigger the auth process."
+      ],
+      "index": [],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "attributename": [
+        "oauth2_rs_origin_landing"
+      ],
+      "syntax": [
+        "URL"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000120"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_SCOPE_MAP: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "A reference to a group mapped to scopes for the associated oauth2 resource server"
+      ],
+      "index": [
+        "EQUALITY"
+      ],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "true"
+      ],
+      "attributename": [
+        "oauth2_rs_scope_map"
+      ],
+      "syntax": [
+        "OAUTH_SCOPE_MAP"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000082"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_TOKEN_EXCHANGE_AUDIENCE: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "A reference to an oauth2 resource server that this resource server may exchange access tokens for"
+      ],
+      "index": [
+        "EQUALITY"
+      ],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "true"
+      ],
+      "attributename": [
+        "oauth2_rs_token_exchange_audience"
+      ],
+      "syntax": [
+        "REFERENCE_UUID"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000133"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_SUP_SCOPE_MAP: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "A reference to a group mapped to scopes for the associated oauth2 resource server"
+      ],
+      "index": [
+        "EQUALITY"
+      ],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "true"
+      ],
+      "attributename": [
+        "oauth2_rs_sup_scope_map"
+      ],
+      "syntax": [
+        "OAUTH_SCOPE_MAP"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000112"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_BASIC_SECRET: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "When using oauth2 basic authentication, the secret string of the resource server"
+      ],
+      "index": [],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "attributename": [
+        "oauth2_rs_basic_secret"
+      ],
+      "syntax": [
+        "SECRET_UTF8STRING"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000083"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_TOKEN_KEY: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "An oauth2 resource servers unique token signing key"
+      ],
+      "index": [],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "attributename": [
+        "oauth2_rs_token_key"
+      ],
+      "syntax": [
+        "SECRET_UTF8STRING"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000084"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_IMPLICIT_SCOPES: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "An oauth2 resource servers scopes that are implicitly granted to all users"
+      ],
+      "index": [],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "true"
+      ],
+      "attributename": [
+        "oauth2_rs_implicit_scopes"
+      ],
+      "syntax": [
+        "OAUTH_SCOPE"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000089"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_OAUTH2_CONSENT_SCOPE_MAP: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "A set of scopes mapped from a relying server to a user, where the user has previously consented to the following. If changed or deleted, consent will be re-sought."
+      ],
+      "index": [
+        "EQUALITY"
+      ],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "true"
+      ],
+      "attributename": [
+        "oauth2_consent_scope_map"
+      ],
+      "syntax": [
+        "OAUTH_SCOPE_MAP"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000097"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_ES256_PRIVATE_KEY_DER: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "An es256 private key"
+      ],
+      "index": [],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "attributename": [
+        "es256_private_key_der"
+      ],
+      "syntax": [
+        "PRIVATE_BINARY"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000090"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_RS256_PRIVATE_KEY_DER: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "An rs256 private key"
+      ],
+      "index": [],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "attributename": [
+        "rs256_private_key_der"
+      ],
+      "syntax": [
+        "PRIVATE_BINARY"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000093"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_JWS_ES256_PRIVATE_KEY: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "An es256 private key for jws"
+      ],
+      "index": [
+        "EQUALITY"
+      ],
+      "unique": [
+        "true"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "attributename": [
+        "jws_es256_private_key"
+      ],
+      "syntax": [
+        "JWS_KEY_ES256"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000110"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_PRIVATE_COOKIE_KEY: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "An private cookie hmac key"
+      ],
+      "index": [],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "attributename": [
+        "private_cookie_key"
+      ],
+      "syntax": [
+        "PRIVATE_BINARY"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000130"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_OAUTH2_ALLOW_INSECURE_CLIENT_DISABLE_PKCE: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "Allows disabling of pkce for insecure oauth2 clients"
+      ],
+      "index": [],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "attributename": [
+        "oauth2_allow_insecure_client_disable_pkce"
+      ],
+      "syntax": [
+        "BOOLEAN"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000091"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_OAUTH2_JWT_LEGACY_CRYPTO_ENABLE: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "Allows enabling legacy jwt cryptograhpy for clients"
+      ],
+      "index": [],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "false"
+      ],
+      "attributename": [
+        "oauth2_jwt_legacy_crypto_enable"
+      ],
+      "syntax": [
+        "BOOLEAN"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000092"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_CREDENTIAL_UPDATE_INTENT_TOKEN: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "The status of a credential update intent token"
+      ],
+      "index": [
+        "EQUALITY"
+      ],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "true"
+      ],
+      "attributename": [
+        "credential_update_intent_token"
+      ],
+      "syntax": [
+        "INTENT_TOKEN"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000096"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_PASSKEYS: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "A set of registered passkeys"
+      ],
+      "index": [
+        "EQUALITY"
+      ],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "true"
+      ],
+      "sync_allowed": [
+        "true"
+      ],
+      "attributename": [
+        "passkeys"
+      ],
+      "syntax": [
+        "PASSKEY"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000099"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_DEVICEKEYS: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "A set of registered device keys"
+      ],
+      "index": [
+        "EQUALITY"
+      ],
+      "unique": [
+        "false"
+      ],
+      "multivalue": [
+        "true"
+      ],
+      "sync_allowed": [
+        "true"
+      ],
+      "attributename": [
+        "devicekeys"
+      ],
+      "syntax": [
+        "DEVICEKEY"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000100"
+      ]
+    }
+}"#;
+
+pub const JSON_SCHEMA_ATTR_DYNGROUP_FILTER: &str = r#"{
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "attributetype"
+      ],
+      "description": [
+        "A filter describing the set of entries to add to a dynamic group"
       ],
       "unique": [
         "false"
@@ -528,18 +1232,18 @@ pub const JSON_SCHEMA_ATTR_UNIX_PASSWORD: &str = r#"{
         "false"
       ],
       "attributename": [
-        "unix_password"
+        "dyngroup_filter"
       ],
       "syntax": [
-        "CREDENTIAL"
+        "JSON_FILTER"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000062"
+        "00000000-0000-0000-0000-ffff00000108"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_NSUNIQUEID: &str = r#"{
+pub const JSON_SCHEMA_ATTR_OAUTH2_REQUIRE_PAR: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -547,33 +1251,28 @@ pub const JSON_SCHEMA_ATTR_NSUNIQUEID: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "A unique id compatibility for 389-ds/dsee"
-      ],
-      "index": [
-        "EQUALITY"
+        "Require that authorisation requests for this resource server are pushed to the par endpoint first"
       ],
+      "index": [],
       "unique": [
-        "true"
+        "false"
       ],
       "multivalue": [
         "false"
       ],
-      "sync_allowed": [
-        "true"
-      ],
       "attributename": [
-        "nsuniqueid"
+        "oauth2_require_par"
       ],
       "syntax": [
-        "NSUNIQUEID"
+        "BOOLEAN"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000067"
+        "00000000-0000-0000-0000-ffff00000134"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_ACCOUNT_EXPIRE: &str = r#"{
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_CLIENT_JWK: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -581,31 +1280,28 @@ pub const JSON_SCHEMA_ATTR_ACCOUNT_EXPIRE: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "The datetime after which this accounnt no longer may authenticate."
+        "A public key in jwk format that the oauth2 resource server signs request objects with"
       ],
       "index": [],
       "unique": [
         "false"
       ],
       "multivalue": [
-        "false"
-      ],
-      "sync_allowed": [
         "true"
       ],
       "attributename": [
-        "account_expire"
+        "oauth2_rs_client_jwk"
       ],
       "syntax": [
-        "DATETIME"
+        "UTF8STRING"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000072"
+        "00000000-0000-0000-0000-ffff00000135"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_ACCOUNT_VALID_FROM: &str = r#"{
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_CLIENT_JWKS_URL: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -613,7 +1309,7 @@ pub const JSON_SCHEMA_ATTR_ACCOUNT_VALID_FROM: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "The datetime after which this account may commence authenticating."
+        "The url of the jwk set of an oauth2 resource server, used to refresh its client jwks"
       ],
       "index": [],
       "unique": [
@@ -622,22 +1318,19 @@ pub const JSON_SCHEMA_ATTR_ACCOUNT_VALID_FROM: &str = r#"{
       "multivalue": [
         "false"
       ],
-      "sync_allowed": [
-        "true"
-      ],
       "attributename": [
-        "account_valid_from"
+        "oauth2_rs_client_jwks_url"
       ],
       "syntax": [
-        "DATETIME"
+        "URL"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000073"
+        "00000000-0000-0000-0000-ffff00000136"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_OAUTH2_RS_NAME: &str = r#"{
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_BACKCHANNEL_LOGOUT_URI: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -645,30 +1338,28 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_NAME: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "The unique name of an external Oauth2 resource"
-      ],
-      "index": [
-        "EQUALITY"
+        "The url that an oauth2 resource server receives back-channel logout tokens at"
       ],
+      "index": [],
       "unique": [
-        "true"
+        "false"
       ],
       "multivalue": [
         "false"
       ],
       "attributename": [
-        "oauth2_rs_name"
+        "oauth2_rs_backchannel_logout_uri"
       ],
       "syntax": [
-        "UTF8STRING_INAME"
+        "URL"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000080"
+        "00000000-0000-0000-0000-ffff00000137"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_OAUTH2_RS_ORIGIN: &str = r#"{
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_SERVICE_ACCOUNT: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -676,9 +1367,11 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_ORIGIN: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "The origin domain of an oauth2 resource server"
+        "The service account that client credential grants of this oauth2 resource server act as"
+      ],
+      "index": [
+        "EQUALITY"
       ],
-      "index": [],
       "unique": [
         "false"
       ],
@@ -686,18 +1379,18 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_ORIGIN: &str = r#"{
         "false"
       ],
       "attributename": [
-        "oauth2_rs_origin"
+        "oauth2_rs_service_account"
       ],
       "syntax": [
-        "URL"
+        "REFERENCE"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000081"
+        "00000000-0000-0000-0000-ffff00000157"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_OAUTH2_RS_ORIGIN_LANDING: &str = r#"{
+pub const JSON_SCHEMA_ATTR_OAUTH2_RS_REGISTERED_BY: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -705,9 +1398,11 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_ORIGIN_LANDING: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "The landing page of an RS, that will automatically trigger the auth process."
+        "The service account that registered this oauth2 resource server"
+      ],
+      "index": [
+        "EQUALITY"
       ],
-      "index": [],
       "unique": [
         "false"
       ],
@@ -715,18 +1410,18 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_ORIGIN_LANDING: &str = r#"{
         "false"
       ],
       "attributename": [
-        "oauth2_rs_origin_landing"
+        "oauth2_rs_registered_by"
       ],
       "syntax": [
-        "URL"
+        "REFERENCE"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000120"
+        "00000000-0000-0000-0000-ffff00000138"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_OAUTH2_RS_SCOPE_MAP: &str = r#"{
+pub const JSON_SCHEMA_ATTR_OAUTH2_REGISTRATION_ORIGIN: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -734,11 +1429,9 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_SCOPE_MAP: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "A reference to a group mapped to scopes for the associated oauth2 resource server"
-      ],
-      "index": [
-        "EQUALITY"
+        "An origin that a service account may register oauth2 resource servers within, including its subdomains"
       ],
+      "index": [],
       "unique": [
         "false"
       ],
@@ -746,18 +1439,18 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_SCOPE_MAP: &str = r#"{
         "true"
       ],
       "attributename": [
-        "oauth2_rs_scope_map"
+        "oauth2_registration_origin"
       ],
       "syntax": [
-        "OAUTH_SCOPE_MAP"
+        "URL"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000082"
+        "00000000-0000-0000-0000-ffff00000139"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_OAUTH2_RS_SUP_SCOPE_MAP: &str = r#"{
+pub const JSON_SCHEMA_ATTR_OAUTH2_REGISTRATION_SCOPE_MAP: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -765,7 +1458,7 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_SUP_SCOPE_MAP: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "A reference to a group mapped to scopes for the associated oauth2 resource server"
+        "A reference to a group mapped to scopes that a service account may grant to the oauth2 resource servers it registers"
       ],
       "index": [
         "EQUALITY"
@@ -777,18 +1470,18 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_SUP_SCOPE_MAP: &str = r#"{
         "true"
       ],
       "attributename": [
-        "oauth2_rs_sup_scope_map"
+        "oauth2_registration_scope_map"
       ],
       "syntax": [
         "OAUTH_SCOPE_MAP"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000112"
+        "00000000-0000-0000-0000-ffff00000140"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_OAUTH2_RS_BASIC_SECRET: &str = r#"{
+pub const JSON_SCHEMA_ATTR_AUTHSESSION_EXPIRY: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -796,7 +1489,7 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_BASIC_SECRET: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "When using oauth2 basic authentication, the secret string of the resource server"
+        "The maximum length in seconds that an authentication session may exist for."
       ],
       "index": [],
       "unique": [
@@ -806,18 +1499,18 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_BASIC_SECRET: &str = r#"{
         "false"
       ],
       "attributename": [
-        "oauth2_rs_basic_secret"
+        "authsession_expiry"
       ],
       "syntax": [
-        "SECRET_UTF8STRING"
+        "UINT32"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000083"
+        "00000000-0000-0000-0000-ffff00000141"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_OAUTH2_RS_TOKEN_KEY: &str = r#"{
+pub const JSON_SCHEMA_ATTR_PRIVILEGE_EXPIRY: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -825,7 +1518,7 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_TOKEN_KEY: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "An oauth2 resource servers unique token signing key"
+        "The maximum length in seconds that privileges will exist after reauthentication for a read write session."
       ],
       "index": [],
       "unique": [
@@ -835,18 +1528,18 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_TOKEN_KEY: &str = r#"{
         "false"
       ],
       "attributename": [
-        "oauth2_rs_token_key"
+        "privilege_expiry"
       ],
       "syntax": [
-        "SECRET_UTF8STRING"
+        "UINT32"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000084"
+        "00000000-0000-0000-0000-ffff00000142"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_OAUTH2_RS_IMPLICIT_SCOPES: &str = r#"{
+pub const JSON_SCHEMA_ATTR_AUTH_PASSWORD_MINIMUM_LENGTH: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -854,28 +1547,28 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_RS_IMPLICIT_SCOPES: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "An oauth2 resource servers scopes that are implicitly granted to all users"
+        "The minimum length of passwords."
       ],
       "index": [],
       "unique": [
         "false"
       ],
       "multivalue": [
-        "true"
+        "false"
       ],
       "attributename": [
-        "oauth2_rs_implicit_scopes"
+        "auth_password_minimum_length"
       ],
       "syntax": [
-        "OAUTH_SCOPE"
+        "UINT32"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000089"
+        "00000000-0000-0000-0000-ffff00000143"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_OAUTH2_CONSENT_SCOPE_MAP: &str = r#"{
+pub const JSON_SCHEMA_ATTR_CREDENTIAL_TYPE_MINIMUM: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -883,30 +1576,28 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_CONSENT_SCOPE_MAP: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "A set of scopes mapped from a relying server to a user, where the user has previously consented to the following. If changed or deleted, consent will be re-sought."
-      ],
-      "index": [
-        "EQUALITY"
+        "The minimum class of credential that is required - one of any, mfa or passkey."
       ],
+      "index": [],
       "unique": [
         "false"
       ],
       "multivalue": [
-        "true"
+        "false"
       ],
       "attributename": [
-        "oauth2_consent_scope_map"
+        "credential_type_minimum"
       ],
       "syntax": [
-        "OAUTH_SCOPE_MAP"
+        "UTF8STRING_INSENSITIVE"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000097"
+        "00000000-0000-0000-0000-ffff00000144"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_ES256_PRIVATE_KEY_DER: &str = r#"{
+pub const JSON_SCHEMA_ATTR_OAUTH2_ACCESS_TOKEN_EXPIRY: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -914,7 +1605,7 @@ pub const JSON_SCHEMA_ATTR_ES256_PRIVATE_KEY_DER: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "An es256 private key"
+        "The maximum length in seconds that an oauth2 access token is valid for."
       ],
       "index": [],
       "unique": [
@@ -924,18 +1615,18 @@ pub const JSON_SCHEMA_ATTR_ES256_PRIVATE_KEY_DER: &str = r#"{
         "false"
       ],
       "attributename": [
-        "es256_private_key_der"
+        "oauth2_access_token_expiry"
       ],
       "syntax": [
-        "PRIVATE_BINARY"
+        "UINT32"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000090"
+        "00000000-0000-0000-0000-ffff00000145"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_RS256_PRIVATE_KEY_DER: &str = r#"{
+pub const JSON_SCHEMA_ATTR_WEBAUTHN_ATTESTATION_CA_LIST: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -943,28 +1634,28 @@ pub const JSON_SCHEMA_ATTR_RS256_PRIVATE_KEY_DER: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "An rs256 private key"
+        "The base64 encoded DER certificates of the CAs that device keys must be attested by."
       ],
       "index": [],
       "unique": [
         "false"
       ],
       "multivalue": [
-        "false"
+        "true"
       ],
       "attributename": [
-        "rs256_private_key_der"
+        "webauthn_attestation_ca_list"
       ],
       "syntax": [
-        "PRIVATE_BINARY"
+        "UTF8STRING"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000093"
+        "00000000-0000-0000-0000-ffff00000147"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_JWS_ES256_PRIVATE_KEY: &str = r#"{
+pub const JSON_SCHEMA_ATTR_MESSAGE_TEMPLATE: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -972,30 +1663,28 @@ pub const JSON_SCHEMA_ATTR_JWS_ES256_PRIVATE_KEY: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "An es256 private key for jws"
-      ],
-      "index": [
-        "EQUALITY"
+        "The template, and the values to render it with, of an outbound message."
       ],
+      "index": [],
       "unique": [
-        "true"
+        "false"
       ],
       "multivalue": [
         "false"
       ],
       "attributename": [
-        "jws_es256_private_key"
+        "message_template"
       ],
       "syntax": [
-        "JWS_KEY_ES256"
+        "UTF8STRING"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000110"
+        "00000000-0000-0000-0000-ffff00000148"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_PRIVATE_COOKIE_KEY: &str = r#"{
+pub const JSON_SCHEMA_ATTR_MAIL_DESTINATION: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -1003,7 +1692,7 @@ pub const JSON_SCHEMA_ATTR_PRIVATE_COOKIE_KEY: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "An private cookie hmac key"
+        "The mail address that an outbound message is delivered to."
       ],
       "index": [],
       "unique": [
@@ -1013,18 +1702,18 @@ pub const JSON_SCHEMA_ATTR_PRIVATE_COOKIE_KEY: &str = r#"{
         "false"
       ],
       "attributename": [
-        "private_cookie_key"
+        "mail_destination"
       ],
       "syntax": [
-        "PRIVATE_BINARY"
+        "EMAIL_ADDRESS"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000130"
+        "00000000-0000-0000-0000-ffff00000149"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_OAUTH2_ALLOW_INSECURE_CLIENT_DISABLE_PKCE: &str = r#"{
+pub const JSON_SCHEMA_ATTR_SEND_AFTER: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -1032,7 +1721,7 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_ALLOW_INSECURE_CLIENT_DISABLE_PKCE: &str = r#"
         "attributetype"
       ],
       "description": [
-        "Allows disabling of pkce for insecure oauth2 clients"
+        "The time after which an outbound message may be delivered."
       ],
       "index": [],
       "unique": [
@@ -1042,18 +1731,18 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_ALLOW_INSECURE_CLIENT_DISABLE_PKCE: &str = r#"
         "false"
       ],
       "attributename": [
-        "oauth2_allow_insecure_client_disable_pkce"
+        "send_after"
       ],
       "syntax": [
-        "BOOLEAN"
+        "DATETIME"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000091"
+        "00000000-0000-0000-0000-ffff00000150"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_OAUTH2_JWT_LEGACY_CRYPTO_ENABLE: &str = r#"{
+pub const JSON_SCHEMA_ATTR_DELIVERED_AT: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -1061,7 +1750,7 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_JWT_LEGACY_CRYPTO_ENABLE: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "Allows enabling legacy jwt cryptograhpy for clients"
+        "The time that an outbound message was delivered."
       ],
       "index": [],
       "unique": [
@@ -1071,18 +1760,18 @@ pub const JSON_SCHEMA_ATTR_OAUTH2_JWT_LEGACY_CRYPTO_ENABLE: &str = r#"{
         "false"
       ],
       "attributename": [
-        "oauth2_jwt_legacy_crypto_enable"
+        "delivered_at"
       ],
       "syntax": [
-        "BOOLEAN"
+        "DATETIME"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000092"
+        "00000000-0000-0000-0000-ffff00000151"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_CREDENTIAL_UPDATE_INTENT_TOKEN: &str = r#"{
+pub const JSON_SCHEMA_ATTR_DELIVERY_ATTEMPTS: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -1090,30 +1779,28 @@ pub const JSON_SCHEMA_ATTR_CREDENTIAL_UPDATE_INTENT_TOKEN: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "The status of a credential update intent token"
-      ],
-      "index": [
-        "EQUALITY"
+        "The number of failed attempts to deliver an outbound message."
       ],
+      "index": [],
       "unique": [
         "false"
       ],
       "multivalue": [
-        "true"
+        "false"
       ],
       "attributename": [
-        "credential_update_intent_token"
+        "delivery_attempts"
       ],
       "syntax": [
-        "INTENT_TOKEN"
+        "UINT32"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000096"
+        "00000000-0000-0000-0000-ffff00000152"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_PASSKEYS: &str = r#"{
+pub const JSON_SCHEMA_ATTR_DELIVERY_ERROR: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -1121,33 +1808,28 @@ pub const JSON_SCHEMA_ATTR_PASSKEYS: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "A set of registered passkeys"
-      ],
-      "index": [
-        "EQUALITY"
+        "The error from the most recent failed attempt to deliver an outbound message."
       ],
+      "index": [],
       "unique": [
         "false"
       ],
       "multivalue": [
-        "true"
-      ],
-      "sync_allowed": [
-        "true"
+        "false"
       ],
       "attributename": [
-        "passkeys"
+        "delivery_error"
       ],
       "syntax": [
-        "PASSKEY"
+        "UTF8STRING"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000099"
+        "00000000-0000-0000-0000-ffff00000153"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_DEVICEKEYS: &str = r#"{
+pub const JSON_SCHEMA_ATTR_MESSAGE_RECIPIENT: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -1155,7 +1837,7 @@ pub const JSON_SCHEMA_ATTR_DEVICEKEYS: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "A set of registered device keys"
+        "The account that an outbound message concerns."
       ],
       "index": [
         "EQUALITY"
@@ -1164,24 +1846,21 @@ pub const JSON_SCHEMA_ATTR_DEVICEKEYS: &str = r#"{
         "false"
       ],
       "multivalue": [
-        "true"
-      ],
-      "sync_allowed": [
-        "true"
+        "false"
       ],
       "attributename": [
-        "devicekeys"
+        "message_recipient"
       ],
       "syntax": [
-        "DEVICEKEY"
+        "REFERENCE_UUID"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000100"
+        "00000000-0000-0000-0000-ffff00000155"
       ]
     }
 }"#;
 
-pub const JSON_SCHEMA_ATTR_DYNGROUP_FILTER: &str = r#"{
+pub const JSON_SCHEMA_ATTR_AUTH_SOURCE_IP: &str = r#"{
     "attrs": {
       "class": [
         "object",
@@ -1189,22 +1868,23 @@ pub const JSON_SCHEMA_ATTR_DYNGROUP_FILTER: &str = r#"{
         "attributetype"
       ],
       "description": [
-        "A filter describing the set of entries to add to a dynamic group"
+        "The network addresses that an account has recently authenticated from."
       ],
+      "index": [],
       "unique": [
         "false"
       ],
       "multivalue": [
-        "false"
+        "true"
       ],
       "attributename": [
-        "dyngroup_filter"
+        "auth_source_ip"
       ],
       "syntax": [
-        "JSON_FILTER"
+        "UTF8STRING_INSENSITIVE"
       ],
       "uuid": [
-        "00000000-0000-0000-0000-ffff00000108"
+        "00000000-0000-0000-0000-ffff00000156"
       ]
     }
 }"#;
@@ -1578,6 +2258,7 @@ pub const JSON_SCHEMA_CLASS_ACCOUNT: &str = r#"
         "oauth2_consent_scope_map",
         "user_auth_token_session",
         "oauth2_session",
+        "auth_source_ip",
         "description"
       ],
       "systemmust": [
@@ -1617,7 +2298,9 @@ pub const JSON_SCHEMA_CLASS_SERVICE_ACCOUNT: &str = r#"
         "mail",
         "primary_credential",
         "jws_es256_private_key",
-        "api_token_session"
+        "api_token_session",
+        "oauth2_registration_origin",
+        "oauth2_registration_scope_map"
       ],
       "uuid": [
         "00000000-0000-0000-0000-ffff00000106"
@@ -1682,7 +2365,8 @@ pub const JSON_SCHEMA_CLASS_DOMAIN_INFO: &str = r#"
       ],
       "systemmay": [
         "domain_ssid",
-        "domain_ldap_basedn"
+        "domain_ldap_basedn",
+        "webauthn_attestation_ca_list"
       ],
       "systemmust": [
         "name",
@@ -1731,6 +2415,69 @@ pub const JSON_SCHEMA_CLASS_POSIXGROUP: &str = r#"
   }
 "#;
 
+pub const JSON_SCHEMA_CLASS_ACCOUNT_POLICY: &str = r#"
+  {
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "classtype"
+      ],
+      "description": [
+        "Policy applied to the accounts that are members of a group"
+      ],
+      "classname": [
+        "account_policy"
+      ],
+      "systemmay": [
+        "authsession_expiry",
+        "privilege_expiry",
+        "auth_password_minimum_length",
+        "credential_type_minimum",
+        "oauth2_access_token_expiry"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000146"
+      ],
+      "systemsupplements": [
+        "group"
+      ]
+    }
+  }
+"#;
+
+pub const JSON_SCHEMA_CLASS_OUTBOUND_MESSAGE: &str = r#"
+  {
+    "attrs": {
+      "class": [
+        "object",
+        "system",
+        "classtype"
+      ],
+      "description": [
+        "A message or notification that is queued for delivery to an account"
+      ],
+      "classname": [
+        "outbound_message"
+      ],
+      "systemmust": [
+        "message_template",
+        "send_after"
+      ],
+      "systemmay": [
+        "mail_destination",
+        "message_recipient",
+        "delivered_at",
+        "delivery_attempts",
+        "delivery_error"
+      ],
+      "uuid": [
+        "00000000-0000-0000-0000-ffff00000154"
+      ]
+    }
+  }
+"#;
+
 pub const JSON_SCHEMA_CLASS_POSIXACCOUNT: &str = r#"
   {
     "attrs": {
@@ -1812,7 +2559,14 @@ pub const JSON_SCHEMA_CLASS_OAUTH2_RS: &str = r#"
         "rs256_private_key_der",
         "oauth2_jwt_legacy_crypto_enable",
         "oauth2_prefer_short_username",
-        "oauth2_rs_origin_landing"
+        "oauth2_rs_origin_landing",
+        "oauth2_rs_token_exchange_audience",
+        "oauth2_require_par",
+        "oauth2_rs_client_jwk",
+        "oauth2_rs_client_jwks_url",
+        "oauth2_rs_backchannel_logout_uri",
+        "oauth2_rs_registered_by",
+        "oauth2_rs_service_account"
       ],
       "systemmust": [
         "oauth2_rs_name",
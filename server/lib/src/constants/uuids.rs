@@ -57,6 +57,7 @@ pub const UUID_IDM_HP_SYNC_ACCOUNT_MANAGE_PRIV: Uuid =
 pub const UUID_IDM_UI_ENABLE_EXPERIMENTAL_FEATURES: Uuid =
     uuid!("00000000-0000-0000-0000-000000000038");
 pub const UUID_IDM_ACCOUNT_MAIL_READ_PRIV: Uuid = uuid!("00000000-0000-0000-0000-000000000039");
+pub const UUID_IDM_AUDIT_READ_PRIV: Uuid = uuid!("00000000-0000-0000-0000-000000000040");
 
 //
 pub const _UUID_IDM_HIGH_PRIVILEGE: Uuid = uuid!("00000000-0000-0000-0000-000000001000");
@@ -227,6 +228,43 @@ pub const UUID_SCHEMA_ATTR_PRIVATE_COOKIE_KEY: Uuid = uuid!("00000000-0000-0000-
 pub const _UUID_SCHEMA_ATTR_DOMAIN_LDAP_BASEDN: Uuid =
     uuid!("00000000-0000-0000-0000-ffff00000131");
 pub const UUID_SCHEMA_ATTR_DYNMEMBER: Uuid = uuid!("00000000-0000-0000-0000-ffff00000132");
+pub const UUID_SCHEMA_ATTR_OAUTH2_RS_TOKEN_EXCHANGE_AUDIENCE: Uuid =
+    uuid!("00000000-0000-0000-0000-ffff00000133");
+pub const UUID_SCHEMA_ATTR_OAUTH2_REQUIRE_PAR: Uuid = uuid!("00000000-0000-0000-0000-ffff00000134");
+pub const UUID_SCHEMA_ATTR_OAUTH2_RS_CLIENT_JWK: Uuid =
+    uuid!("00000000-0000-0000-0000-ffff00000135");
+pub const UUID_SCHEMA_ATTR_OAUTH2_RS_CLIENT_JWKS_URL: Uuid =
+    uuid!("00000000-0000-0000-0000-ffff00000136");
+pub const UUID_SCHEMA_ATTR_OAUTH2_RS_BACKCHANNEL_LOGOUT_URI: Uuid =
+    uuid!("00000000-0000-0000-0000-ffff00000137");
+pub const UUID_SCHEMA_ATTR_OAUTH2_RS_REGISTERED_BY: Uuid =
+    uuid!("00000000-0000-0000-0000-ffff00000138");
+pub const UUID_SCHEMA_ATTR_OAUTH2_REGISTRATION_ORIGIN: Uuid =
+    uuid!("00000000-0000-0000-0000-ffff00000139");
+pub const UUID_SCHEMA_ATTR_OAUTH2_REGISTRATION_SCOPE_MAP: Uuid =
+    uuid!("00000000-0000-0000-0000-ffff00000140");
+pub const UUID_SCHEMA_ATTR_AUTHSESSION_EXPIRY: Uuid = uuid!("00000000-0000-0000-0000-ffff00000141");
+pub const UUID_SCHEMA_ATTR_PRIVILEGE_EXPIRY: Uuid = uuid!("00000000-0000-0000-0000-ffff00000142");
+pub const UUID_SCHEMA_ATTR_AUTH_PASSWORD_MINIMUM_LENGTH: Uuid =
+    uuid!("00000000-0000-0000-0000-ffff00000143");
+pub const UUID_SCHEMA_ATTR_CREDENTIAL_TYPE_MINIMUM: Uuid =
+    uuid!("00000000-0000-0000-0000-ffff00000144");
+pub const UUID_SCHEMA_ATTR_OAUTH2_ACCESS_TOKEN_EXPIRY: Uuid =
+    uuid!("00000000-0000-0000-0000-ffff00000145");
+pub const UUID_SCHEMA_CLASS_ACCOUNT_POLICY: Uuid = uuid!("00000000-0000-0000-0000-ffff00000146");
+pub const UUID_SCHEMA_ATTR_WEBAUTHN_ATTESTATION_CA_LIST: Uuid =
+    uuid!("00000000-0000-0000-0000-ffff00000147");
+pub const UUID_SCHEMA_ATTR_MESSAGE_TEMPLATE: Uuid = uuid!("00000000-0000-0000-0000-ffff00000148");
+pub const UUID_SCHEMA_ATTR_MAIL_DESTINATION: Uuid = uuid!("00000000-0000-0000-0000-ffff00000149");
+pub const UUID_SCHEMA_ATTR_SEND_AFTER: Uuid = uuid!("00000000-0000-0000-0000-ffff00000150");
+pub const UUID_SCHEMA_ATTR_DELIVERED_AT: Uuid = uuid!("00000000-0000-0000-0000-ffff00000151");
+pub const UUID_SCHEMA_ATTR_DELIVERY_ATTEMPTS: Uuid = uuid!("00000000-0000-0000-0000-ffff00000152");
+pub const UUID_SCHEMA_ATTR_DELIVERY_ERROR: Uuid = uuid!("00000000-0000-0000-0000-ffff00000153");
+pub const UUID_SCHEMA_CLASS_OUTBOUND_MESSAGE: Uuid = uuid!("00000000-0000-0000-0000-ffff00000154");
+pub const UUID_SCHEMA_ATTR_MESSAGE_RECIPIENT: Uuid = uuid!("00000000-0000-0000-0000-ffff00000155");
+pub const UUID_SCHEMA_ATTR_AUTH_SOURCE_IP: Uuid = uuid!("00000000-0000-0000-0000-ffff00000156");
+pub const UUID_SCHEMA_ATTR_OAUTH2_RS_SERVICE_ACCOUNT: Uuid =
+    uuid!("00000000-0000-0000-0000-ffff00000157");
 
 // System and domain infos
 // I'd like to strongly criticise william of the past for making poor choices about these allocations.
@@ -305,6 +343,12 @@ pub const UUID_IDM_HP_ACP_SYNC_ACCOUNT_MANAGE_PRIV_V1: Uuid =
     uuid!("00000000-0000-0000-0000-ffffff000044");
 pub const UUID_IDM_ACP_ACCOUNT_MAIL_READ_PRIV_V1: Uuid =
     uuid!("00000000-0000-0000-0000-ffffff000045");
+pub const UUID_IDM_HP_ACP_OAUTH2_REGISTRATION_MANAGE_PRIV_V1: Uuid =
+    uuid!("00000000-0000-0000-0000-ffffff000046");
+pub const UUID_IDM_ACP_ACCOUNT_POLICY_MANAGE_PRIV_V1: Uuid =
+    uuid!("00000000-0000-0000-0000-ffffff000047");
+pub const UUID_IDM_ACP_MESSAGE_QUEUE_MANAGE_PRIV_V1: Uuid =
+    uuid!("00000000-0000-0000-0000-ffffff000048");
 
 // End of system ranges
 pub const UUID_DOES_NOT_EXIST: Uuid = uuid!("00000000-0000-0000-0000-fffffffffffe");
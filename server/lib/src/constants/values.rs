@@ -9,6 +9,7 @@ lazy_static! {
             .expect("Failed to parse oauth2 service documentation url");
     pub static ref PV_FALSE: PartialValue = PartialValue::new_bool(false);
     pub static ref PVCLASS_ACCOUNT: PartialValue = PartialValue::new_class("account");
+    pub static ref PVCLASS_ACCOUNT_POLICY: PartialValue = PartialValue::new_class("account_policy");
     pub static ref PVCLASS_ACS: PartialValue = PartialValue::new_class("access_control_search");
     pub static ref PVCLASS_ACC: PartialValue = PartialValue::new_class("access_control_create");
     pub static ref PVCLASS_ACD: PartialValue = PartialValue::new_class("access_control_delete");
@@ -1,9 +1,13 @@
 use std::convert::TryFrom;
 
+use base64::{engine::general_purpose, Engine as _};
 use hashbrown::{HashMap as Map, HashSet};
 use kanidm_proto::v1::{BackupCodesView, CredentialDetail, CredentialDetailType, OperationError};
+use openssl::x509::X509;
 use uuid::Uuid;
-use webauthn_rs::prelude::{AuthenticationResult, Passkey, SecurityKey};
+use webauthn_rs::prelude::{
+    AttestationCa, AttestationCaList, AuthenticationResult, Passkey, SecurityKey,
+};
 use webauthn_rs_core::proto::{Credential as WebauthnCredential, CredentialV3};
 
 use crate::be::dbvalue::{DbBackupCodeV1, DbCred};
@@ -32,6 +36,30 @@ pub enum Policy {
 
 pub use kanidm_lib_crypto::Password;
 
+/// Parse the base64 encoded DER certificates that make up the domain's attestation CA list.
+pub fn attestation_ca_list_from_b64<'a, I>(cas: I) -> Result<AttestationCaList, OperationError>
+where
+    I: Iterator<Item = &'a str>,
+{
+    cas.map(|ca_b64| {
+        general_purpose::STANDARD
+            .decode(ca_b64)
+            .map_err(|e| {
+                error!(?e, "Invalid base64 in attestation ca");
+                OperationError::InvalidValueState
+            })
+            .and_then(|der| {
+                X509::from_der(&der).map_err(|e| {
+                    error!(?e, "Invalid DER certificate in attestation ca");
+                    OperationError::InvalidValueState
+                })
+            })
+            .map(|ca| AttestationCa { ca })
+    })
+    .collect::<Result<Vec<_>, _>>()
+    .map(|cas| AttestationCaList { cas })
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BackupCodes {
     code_set: HashSet<String>,
@@ -1415,6 +1415,11 @@ impl Entry<EntrySealed, EntryCommitted> {
                                         vec![Err((&ikey.attr, ikey.itype, "_".to_string()))]
                                     }
                                     IndexType::SubString => Vec::new(),
+                                    IndexType::Ordering => vs
+                                        .generate_idx_ord_keys()
+                                        .into_iter()
+                                        .map(|idx_key| Err((&ikey.attr, ikey.itype, idx_key)))
+                                        .collect(),
                                 };
                                 changes
                             }
@@ -1440,6 +1445,11 @@ impl Entry<EntrySealed, EntryCommitted> {
                                         vec![Ok((&ikey.attr, ikey.itype, "_".to_string()))]
                                     }
                                     IndexType::SubString => Vec::new(),
+                                    IndexType::Ordering => vs
+                                        .generate_idx_ord_keys()
+                                        .into_iter()
+                                        .map(|idx_key| Ok((&ikey.attr, ikey.itype, idx_key)))
+                                        .collect(),
                                 };
                                 // For each value
                                 //
@@ -1478,6 +1488,11 @@ impl Entry<EntrySealed, EntryCommitted> {
                                         vec![Err((&ikey.attr, ikey.itype, "_".to_string()))]
                                     }
                                     IndexType::SubString => Vec::new(),
+                                    IndexType::Ordering => pre_vs
+                                        .generate_idx_ord_keys()
+                                        .into_iter()
+                                        .map(|idx_key| Err((&ikey.attr, ikey.itype, idx_key)))
+                                        .collect(),
                                 };
                                 changes
                             }
@@ -1497,15 +1512,28 @@ impl Entry<EntrySealed, EntryCommitted> {
                                         vec![Ok((&ikey.attr, ikey.itype, "_".to_string()))]
                                     }
                                     IndexType::SubString => Vec::new(),
+                                    IndexType::Ordering => post_vs
+                                        .generate_idx_ord_keys()
+                                        .into_iter()
+                                        .map(|idx_key| Ok((&ikey.attr, ikey.itype, idx_key)))
+                                        .collect(),
                                 };
                                 changes
                             }
                             (Some(pre_vs), Some(post_vs)) => {
                                 // it exists in both, we need to work out the difference within the attr.
 
-                                let mut pre_idx_keys = pre_vs.generate_idx_eq_keys();
+                                let (mut pre_idx_keys, mut post_idx_keys) = match ikey.itype {
+                                    IndexType::Ordering => (
+                                        pre_vs.generate_idx_ord_keys(),
+                                        post_vs.generate_idx_ord_keys(),
+                                    ),
+                                    _ => (
+                                        pre_vs.generate_idx_eq_keys(),
+                                        post_vs.generate_idx_eq_keys(),
+                                    ),
+                                };
                                 pre_idx_keys.sort_unstable();
-                                let mut post_idx_keys = post_vs.generate_idx_eq_keys();
                                 post_idx_keys.sort_unstable();
 
                                 let sz = if pre_idx_keys.len() > post_idx_keys.len() {
@@ -1561,7 +1589,7 @@ impl Entry<EntrySealed, EntryCommitted> {
                                     Vec::with_capacity(removed_vs.len() + added_vs.len());
 
                                 match ikey.itype {
-                                    IndexType::Equality => {
+                                    IndexType::Equality | IndexType::Ordering => {
                                         removed_vs
                                             .into_iter()
                                             .map(|idx_key| Err((&ikey.attr, ikey.itype, idx_key)))
@@ -2292,6 +2320,20 @@ impl<VALID, STATE> Entry<VALID, STATE> {
         self.get_ava_set(attr).and_then(|vs| vs.as_iutf8_iter())
     }
 
+    #[inline(always)]
+    /// If possible, return an iterator over the set of values transformed into a `&str`.
+    pub fn get_ava_iter_utf8(&self, attr: &str) -> Option<impl Iterator<Item = &str>> {
+        self.get_ava_set(attr).and_then(|vs| vs.as_utf8_iter())
+    }
+
+    #[inline(always)]
+    /// If possible, return an iterator over the set of values transformed into a `&Url`.
+    pub fn get_ava_iter_url(&self, attr: &str) -> Option<impl Iterator<Item = &Url>> {
+        self.get_ava_set(attr)
+            .and_then(|vs| vs.as_url_set())
+            .map(|set| set.iter())
+    }
+
     #[inline(always)]
     /// If possible, return an iterator over the set of values transformed into a `Uuid`.
     pub fn get_ava_as_refuuid(&self, attr: &str) -> Option<Box<dyn Iterator<Item = Uuid> + '_>> {
@@ -2505,6 +2547,16 @@ impl<VALID, STATE> Entry<VALID, STATE> {
             .unwrap_or(false)
     }
 
+    #[inline(always)]
+    /// Assert if an attribute of this name is present, and one of it's values is greater than
+    /// the following partial value
+    pub fn attribute_greaterthan(&self, attr: &str, subvalue: &PartialValue) -> bool {
+        self.attrs
+            .get(attr)
+            .map(|vset| vset.greaterthan(subvalue))
+            .unwrap_or(false)
+    }
+
     // Since EntryValid/Invalid is just about class adherenece, not Value correctness, we
     // can now apply filters to invalid entries - why? Because even if they aren't class
     // valid, we still have strict typing checks between the filter -> entry to guarantee
@@ -2534,6 +2586,9 @@ impl<VALID, STATE> Entry<VALID, STATE> {
             FilterResolved::LessThan(attr, subvalue, _) => {
                 self.attribute_lessthan(attr.as_str(), subvalue)
             }
+            FilterResolved::GreaterThan(attr, subvalue, _) => {
+                self.attribute_greaterthan(attr.as_str(), subvalue)
+            }
             // Check with ftweedal about or filter zero len correctness.
             FilterResolved::Or(l, _) => l.iter().any(|f| self.entry_match_no_index_inner(f)),
             // Check with ftweedal about and filter zero len correctness.
@@ -3037,6 +3092,30 @@ mod tests {
         assert!(e1.attribute_lessthan("a", &pv15));
     }
 
+    #[test]
+    fn test_entry_greaterthan() {
+        let mut e1: Entry<EntryInit, EntryNew> = Entry::new();
+
+        let pvn5 = PartialValue::new_int64(-5);
+        let pv8 = PartialValue::new_int64(8);
+        let pv10 = PartialValue::new_int64(10);
+        let pv15 = PartialValue::new_int64(15);
+
+        e1.add_ava("a", Value::new_int64(10));
+
+        assert!(e1.attribute_greaterthan("a", &pvn5));
+        assert!(e1.attribute_greaterthan("a", &pv8));
+        assert!(!e1.attribute_greaterthan("a", &pv10));
+        assert!(!e1.attribute_greaterthan("a", &pv15));
+
+        e1.add_ava("a", Value::new_int64(-10));
+
+        assert!(e1.attribute_greaterthan("a", &pvn5));
+        assert!(!e1.attribute_greaterthan("a", &pv10));
+        // Mismatched syntax never matches.
+        assert!(!e1.attribute_greaterthan("a", &PartialValue::new_uint32(1)));
+    }
+
     #[test]
     fn test_entry_apply_modlist() {
         // Test application of changes to an entry.
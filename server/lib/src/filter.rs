@@ -56,6 +56,11 @@ pub fn f_lt(a: &str, v: PartialValue) -> FC {
     FC::LessThan(a, v)
 }
 
+#[allow(dead_code)]
+pub fn f_gt(a: &str, v: PartialValue) -> FC {
+    FC::GreaterThan(a, v)
+}
+
 #[allow(dead_code)]
 pub fn f_or(vs: Vec<FC>) -> FC {
     FC::Or(vs)
@@ -112,6 +117,7 @@ pub enum FC<'a> {
     Sub(&'a str, PartialValue),
     Pres(&'a str),
     LessThan(&'a str, PartialValue),
+    GreaterThan(&'a str, PartialValue),
     Or(Vec<FC<'a>>),
     And(Vec<FC<'a>>),
     Inclusion(Vec<FC<'a>>),
@@ -128,6 +134,7 @@ enum FilterComp {
     Sub(AttrString, PartialValue),
     Pres(AttrString),
     LessThan(AttrString, PartialValue),
+    GreaterThan(AttrString, PartialValue),
     Or(Vec<FilterComp>),
     And(Vec<FilterComp>),
     Inclusion(Vec<FilterComp>),
@@ -154,6 +161,7 @@ pub enum FilterResolved {
     Sub(AttrString, PartialValue, Option<NonZeroU8>),
     Pres(AttrString, Option<NonZeroU8>),
     LessThan(AttrString, PartialValue, Option<NonZeroU8>),
+    GreaterThan(AttrString, PartialValue, Option<NonZeroU8>),
     Or(Vec<FilterResolved>, Option<NonZeroU8>),
     And(Vec<FilterResolved>, Option<NonZeroU8>),
     // All terms must have 1 or more items, or the inclusion is false!
@@ -188,7 +196,12 @@ pub enum FilterPlan {
     PresIndexed(AttrString),
     PresUnindexed(AttrString),
     PresCorrupt(AttrString),
+    LessThanIndexed(AttrString, String),
     LessThanUnindexed(AttrString),
+    LessThanCorrupt(AttrString),
+    GreaterThanIndexed(AttrString, String),
+    GreaterThanUnindexed(AttrString),
+    GreaterThanCorrupt(AttrString),
     OrUnindexed(Vec<FilterPlan>),
     OrIndexed(Vec<FilterPlan>),
     OrPartial(Vec<FilterPlan>),
@@ -543,6 +556,7 @@ impl FilterComp {
             FC::Sub(a, v) => FilterComp::Sub(AttrString::from(a), v),
             FC::Pres(a) => FilterComp::Pres(AttrString::from(a)),
             FC::LessThan(a, v) => FilterComp::LessThan(AttrString::from(a), v),
+            FC::GreaterThan(a, v) => FilterComp::GreaterThan(AttrString::from(a), v),
             FC::Or(v) => FilterComp::Or(v.into_iter().map(FilterComp::new).collect()),
             FC::And(v) => FilterComp::And(v.into_iter().map(FilterComp::new).collect()),
             FC::Inclusion(v) => FilterComp::Inclusion(v.into_iter().map(FilterComp::new).collect()),
@@ -588,7 +602,7 @@ impl FilterComp {
             FilterComp::Pres(attr) => {
                 r_set.insert(attr.as_str());
             }
-            FilterComp::LessThan(attr, _) => {
+            FilterComp::LessThan(attr, _) | FilterComp::GreaterThan(attr, _) => {
                 r_set.insert(attr.as_str());
             }
             FilterComp::Or(vs) => vs.iter().for_each(|f| f.get_attr_set(r_set)),
@@ -670,6 +684,21 @@ impl FilterComp {
                     None => Err(SchemaError::InvalidAttribute(attr_norm.to_string())),
                 }
             }
+            FilterComp::GreaterThan(attr, value) => {
+                // Validate/normalise the attr name.
+                let attr_norm = schema.normalise_attr_name(attr);
+                // Now check it exists
+                match schema_attributes.get(&attr_norm) {
+                    Some(schema_a) => {
+                        schema_a
+                            .validate_partialvalue(attr_norm.as_str(), value)
+                            // Okay, it worked, transform to a filter component
+                            .map(|_| FilterComp::GreaterThan(attr_norm, value.clone()))
+                        // On error, pass the error back out.
+                    }
+                    None => Err(SchemaError::InvalidAttribute(attr_norm.to_string())),
+                }
+            }
             FilterComp::Or(filters) => {
                 // If all filters are okay, return Ok(Filter::Or())
                 // If any is invalid, return the error.
@@ -744,6 +773,16 @@ impl FilterComp {
                 let nk = qs.get_schema().normalise_attr_name(a);
                 FilterComp::Pres(nk)
             }
+            ProtoFilter::LessThan(a, v) => {
+                let nk = qs.get_schema().normalise_attr_name(a);
+                let v = qs.clone_partialvalue(nk.as_str(), v)?;
+                FilterComp::LessThan(nk, v)
+            }
+            ProtoFilter::GreaterThan(a, v) => {
+                let nk = qs.get_schema().normalise_attr_name(a);
+                let v = qs.clone_partialvalue(nk.as_str(), v)?;
+                FilterComp::GreaterThan(nk, v)
+            }
             ProtoFilter::Or(l) => {
                 *elems = (*elems)
                     .checked_sub(l.len())
@@ -796,6 +835,16 @@ impl FilterComp {
                 let nk = qs.get_schema().normalise_attr_name(a);
                 FilterComp::Pres(nk)
             }
+            ProtoFilter::LessThan(a, v) => {
+                let nk = qs.get_schema().normalise_attr_name(a);
+                let v = qs.clone_partialvalue(nk.as_str(), v)?;
+                FilterComp::LessThan(nk, v)
+            }
+            ProtoFilter::GreaterThan(a, v) => {
+                let nk = qs.get_schema().normalise_attr_name(a);
+                let v = qs.clone_partialvalue(nk.as_str(), v)?;
+                FilterComp::GreaterThan(nk, v)
+            }
             ProtoFilter::Or(l) => {
                 *elems = (*elems)
                     .checked_sub(l.len())
@@ -880,13 +929,23 @@ impl FilterComp {
                 admin_error!("Unable to convert ldapsubstringfilter to sub filter");
                 return Err(OperationError::FilterGeneration);
             }
-            LdapFilter::GreaterOrEqual(_, _) => {
-                admin_error!("Unsupported filter operation - greater or equal");
-                return Err(OperationError::FilterGeneration);
+            // Our ordering filters are strict, so ldap's inclusive forms are expressed as
+            // the union with equality.
+            LdapFilter::GreaterOrEqual(a, v) => {
+                let a = ldap_attr_filter_map(a);
+                let v = qs.clone_partialvalue(a.as_str(), v)?;
+                FilterComp::Or(vec![
+                    FilterComp::Eq(a.clone(), v.clone()),
+                    FilterComp::GreaterThan(a, v),
+                ])
             }
-            LdapFilter::LessOrEqual(_, _) => {
-                admin_error!("Unsupported filter operation - less or equal");
-                return Err(OperationError::FilterGeneration);
+            LdapFilter::LessOrEqual(a, v) => {
+                let a = ldap_attr_filter_map(a);
+                let v = qs.clone_partialvalue(a.as_str(), v)?;
+                FilterComp::Or(vec![
+                    FilterComp::Eq(a.clone(), v.clone()),
+                    FilterComp::LessThan(a, v),
+                ])
             }
             LdapFilter::Approx(_, _) => {
                 admin_error!("Unsupported filter operation - approximate");
@@ -940,7 +999,8 @@ impl PartialEq for FilterResolved {
                 a1 == a2 && v1 == v2
             }
             (FilterResolved::Pres(a1, _), FilterResolved::Pres(a2, _)) => a1 == a2,
-            (FilterResolved::LessThan(a1, v1, _), FilterResolved::LessThan(a2, v2, _)) => {
+            (FilterResolved::LessThan(a1, v1, _), FilterResolved::LessThan(a2, v2, _))
+            | (FilterResolved::GreaterThan(a1, v1, _), FilterResolved::GreaterThan(a2, v2, _)) => {
                 a1 == a2 && v1 == v2
             }
             (FilterResolved::And(vs1, _), FilterResolved::And(vs2, _)) => vs1 == vs2,
@@ -981,12 +1041,14 @@ impl Ord for FilterResolved {
             match (self, rhs) {
                 (FilterResolved::Eq(a1, v1, _), FilterResolved::Eq(a2, v2, _))
                 | (FilterResolved::Sub(a1, v1, _), FilterResolved::Sub(a2, v2, _))
-                | (FilterResolved::LessThan(a1, v1, _), FilterResolved::LessThan(a2, v2, _)) => {
-                    match a1.cmp(a2) {
-                        Ordering::Equal => v1.cmp(v2),
-                        o => o,
-                    }
-                }
+                | (FilterResolved::LessThan(a1, v1, _), FilterResolved::LessThan(a2, v2, _))
+                | (
+                    FilterResolved::GreaterThan(a1, v1, _),
+                    FilterResolved::GreaterThan(a2, v2, _),
+                ) => match a1.cmp(a2) {
+                    Ordering::Equal => v1.cmp(v2),
+                    o => o,
+                },
                 (FilterResolved::Pres(a1, _), FilterResolved::Pres(a2, _)) => a1.cmp(a2),
                 // Now sort these into the generally "best" order.
                 (FilterResolved::Eq(_, _, _), _) => Ordering::Less,
@@ -995,6 +1057,8 @@ impl Ord for FilterResolved {
                 (_, FilterResolved::Pres(_, _)) => Ordering::Greater,
                 (FilterResolved::LessThan(_, _, _), _) => Ordering::Less,
                 (_, FilterResolved::LessThan(_, _, _)) => Ordering::Greater,
+                (FilterResolved::GreaterThan(_, _, _), _) => Ordering::Less,
+                (_, FilterResolved::GreaterThan(_, _, _)) => Ordering::Greater,
                 (FilterResolved::Sub(_, _, _), _) => Ordering::Less,
                 (_, FilterResolved::Sub(_, _, _)) => Ordering::Greater,
                 // They can't be re-arranged, they don't move!
@@ -1028,11 +1092,15 @@ impl FilterResolved {
                 FilterResolved::Pres(a, idx)
             }
             FilterComp::LessThan(a, v) => {
-                // let idx = idxmeta.contains(&(&a, &IndexType::ORDERING));
-                // TODO: For now, don't emit ordering indexes.
-                let idx = None;
+                let idx = idxmeta.contains(&(&a, &IndexType::Ordering));
+                let idx = NonZeroU8::new(idx as u8);
                 FilterResolved::LessThan(a, v, idx)
             }
+            FilterComp::GreaterThan(a, v) => {
+                let idx = idxmeta.contains(&(&a, &IndexType::Ordering));
+                let idx = NonZeroU8::new(idx as u8);
+                FilterResolved::GreaterThan(a, v, idx)
+            }
             FilterComp::Or(vs) => FilterResolved::Or(
                 vs.into_iter()
                     .map(|v| FilterResolved::from_invalid(v, idxmeta))
@@ -1105,8 +1173,20 @@ impl FilterResolved {
                 Some(FilterResolved::Pres(a, idx))
             }
             FilterComp::LessThan(a, v) => {
-                // let idx = idxmeta.contains(&(&a, &IndexType::SubString));
-                Some(FilterResolved::LessThan(a, v, None))
+                let idxkref = IdxKeyRef::new(&a, &IndexType::Ordering);
+                let idx = idxmeta
+                    .get(&idxkref as &dyn IdxKeyToRef)
+                    .copied()
+                    .and_then(NonZeroU8::new);
+                Some(FilterResolved::LessThan(a, v, idx))
+            }
+            FilterComp::GreaterThan(a, v) => {
+                let idxkref = IdxKeyRef::new(&a, &IndexType::Ordering);
+                let idx = idxmeta
+                    .get(&idxkref as &dyn IdxKeyToRef)
+                    .copied()
+                    .and_then(NonZeroU8::new);
+                Some(FilterResolved::GreaterThan(a, v, idx))
             }
             // We set the compound filters slope factor to "None" here, because when we do
             // optimise we'll actually fill in the correct slope factors after we sort those
@@ -1167,6 +1247,7 @@ impl FilterResolved {
             FilterComp::Sub(a, v) => Some(FilterResolved::Sub(a, v, None)),
             FilterComp::Pres(a) => Some(FilterResolved::Pres(a, None)),
             FilterComp::LessThan(a, v) => Some(FilterResolved::LessThan(a, v, None)),
+            FilterComp::GreaterThan(a, v) => Some(FilterResolved::GreaterThan(a, v, None)),
             FilterComp::Or(vs) => {
                 let fi: Option<Vec<_>> = vs
                     .into_iter()
@@ -1331,6 +1412,7 @@ impl FilterResolved {
             | FilterResolved::Sub(_, _, sf)
             | FilterResolved::Pres(_, sf)
             | FilterResolved::LessThan(_, _, sf)
+            | FilterResolved::GreaterThan(_, _, sf)
             | FilterResolved::Or(_, sf)
             | FilterResolved::And(_, sf)
             | FilterResolved::Inclusion(_, sf)
@@ -1582,6 +1664,44 @@ mod tests {
         assert!(e.entry_match_no_index(&f_t1c));
     }
 
+    #[test]
+    fn test_greaterthan_entry_filter() {
+        let e = unsafe {
+            entry_init!(
+                ("userid", Value::new_iutf8("william")),
+                (
+                    "uuid",
+                    Value::Uuid(uuid::uuid!("db237e8a-0079-4b8c-8a56-593b22aa44d1"))
+                ),
+                ("gidnumber", Value::Uint32(1000))
+            )
+            .into_sealed_new()
+        };
+
+        let f_t1a = unsafe { filter_resolved!(f_gt("gidnumber", PartialValue::new_uint32(500))) };
+        assert!(e.entry_match_no_index(&f_t1a));
+
+        let f_t1b = unsafe { filter_resolved!(f_gt("gidnumber", PartialValue::new_uint32(1000))) };
+        assert!(!e.entry_match_no_index(&f_t1b));
+
+        // A range is the and of both bounds.
+        let f_t1c = unsafe {
+            filter_resolved!(f_and!([
+                f_gt("gidnumber", PartialValue::new_uint32(999)),
+                f_lt("gidnumber", PartialValue::new_uint32(1001))
+            ]))
+        };
+        assert!(e.entry_match_no_index(&f_t1c));
+
+        let f_t1d = unsafe {
+            filter_resolved!(f_and!([
+                f_gt("gidnumber", PartialValue::new_uint32(1000)),
+                f_lt("gidnumber", PartialValue::new_uint32(2000))
+            ]))
+        };
+        assert!(!e.entry_match_no_index(&f_t1d));
+    }
+
     #[test]
     fn test_or_entry_filter() {
         let e = unsafe {
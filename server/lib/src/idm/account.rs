@@ -2,7 +2,8 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::time::Duration;
 
 use kanidm_proto::v1::{
-    BackupCodesView, CredentialStatus, OperationError, UatPurpose, UatStatus, UiHint, UserAuthToken,
+    BackupCodesView, CredentialStatus, OperationError, PasskeyDetail, UatPurpose, UatStatus,
+    UiHint, UserAuthToken,
 };
 use time::OffsetDateTime;
 use uuid::Uuid;
@@ -15,6 +16,7 @@ use crate::credential::softlock::CredSoftLockPolicy;
 use crate::credential::Credential;
 use crate::entry::{Entry, EntryCommitted, EntryReduced, EntrySealed};
 use crate::event::SearchEvent;
+use crate::idm::accountpolicy::ResolvedAccountPolicy;
 use crate::idm::group::Group;
 use crate::idm::server::{IdmServerProxyReadTransaction, IdmServerProxyWriteTransaction};
 use crate::modify::{ModifyInvalid, ModifyList};
@@ -106,6 +108,10 @@ macro_rules! try_from_entry {
             ui_hints.insert(UiHint::PosixAccount);
         }
 
+        // The strictest policy of all our groups applies.
+        let account_policy =
+            ResolvedAccountPolicy::fold_from(groups.iter().map(|group| &group.account_policy));
+
         Ok(Account {
             uuid,
             name,
@@ -122,6 +128,7 @@ macro_rules! try_from_entry {
             mail_primary,
             mail,
             credential_update_intent_tokens,
+            account_policy,
         })
     }};
 }
@@ -153,6 +160,7 @@ pub struct Account {
     pub mail_primary: Option<String>,
     pub mail: Vec<String>,
     pub credential_update_intent_tokens: BTreeMap<String, IntentTokenState>,
+    pub(crate) account_policy: ResolvedAccountPolicy,
 }
 
 impl Account {
@@ -199,15 +207,17 @@ impl Account {
         scope: SessionScope,
         ct: Duration,
     ) -> Option<UserAuthToken> {
-        // TODO: Apply policy to this expiry time.
         // We have to remove the nanoseconds because when we transmit this / serialise it we drop
         // the nanoseconds, but if we haven't done a serialise on the server our db cache has the
         // ns value which breaks some checks.
         let ct = ct - Duration::from_nanos(ct.subsec_nanos() as u64);
         let issued_at = OffsetDateTime::UNIX_EPOCH + ct;
 
-        let expiry =
-            Some(OffsetDateTime::UNIX_EPOCH + ct + Duration::from_secs(AUTH_SESSION_EXPIRY));
+        let expiry = Some(
+            OffsetDateTime::UNIX_EPOCH
+                + ct
+                + Duration::from_secs(self.account_policy.authsession_expiry(AUTH_SESSION_EXPIRY)),
+        );
 
         let (purpose, expiry) = match scope {
             // Issue an invalid/expired session.
@@ -229,7 +239,11 @@ impl Account {
             {
                 (
                     UatPurpose::ReadWrite { expiry: None },
-                    Some(OffsetDateTime::UNIX_EPOCH + ct + Duration::from_secs(86400)),
+                    Some(
+                        OffsetDateTime::UNIX_EPOCH
+                            + ct
+                            + Duration::from_secs(self.account_policy.authsession_expiry(86400)),
+                    ),
                 )
             }
         };
@@ -249,6 +263,26 @@ impl Account {
         })
     }
 
+    /// Create the user authentication token for a write made with an ldap unix bind. A unix
+    /// bind has no way to re-authenticate, so the privileges of the token last for the privilege
+    /// expiry of the account from the time of the bind, rather than for the session.
+    pub(crate) fn to_unix_bind_userauthtoken(
+        &self,
+        session_id: Uuid,
+        bind_time: Duration,
+        ct: Duration,
+    ) -> Option<UserAuthToken> {
+        let mut uat = self.to_userauthtoken(session_id, SessionScope::ReadWrite, ct)?;
+
+        let privilege_expiry = OffsetDateTime::UNIX_EPOCH
+            + bind_time
+            + Duration::from_secs(self.account_policy.privilege_expiry(AUTH_PRIVILEGE_EXPIRY));
+        uat.purpose = UatPurpose::ReadWrite {
+            expiry: Some(privilege_expiry),
+        };
+        Some(uat)
+    }
+
     /// Given the session_id and other metadata, reissue a user authentication token
     /// that has elevated privileges. In the future we may adapt this to change what
     /// scopes are granted per-reauth.
@@ -273,7 +307,11 @@ impl Account {
             // Return a ReadWrite session with an inner expiry for the privileges
             {
                 let expiry = Some(
-                    OffsetDateTime::UNIX_EPOCH + ct + Duration::from_secs(AUTH_PRIVILEGE_EXPIRY),
+                    OffsetDateTime::UNIX_EPOCH
+                        + ct
+                        + Duration::from_secs(
+                            self.account_policy.privilege_expiry(AUTH_PRIVILEGE_EXPIRY),
+                        ),
                 );
                 (
                     UatPurpose::ReadWrite { expiry },
@@ -424,6 +462,21 @@ impl Account {
             }
         });
 
+        // Or a devicekey?
+        self.devicekeys.iter_mut().for_each(|(u, (t, k))| {
+            if let Some(true) = k.update_credential(auth_result) {
+                ml.push(Modify::Removed(
+                    "devicekeys".into(),
+                    PartialValue::DeviceKey(*u),
+                ));
+
+                ml.push(Modify::Present(
+                    "devicekeys".into(),
+                    Value::DeviceKey(*u, t.clone(), k.clone()),
+                ));
+            }
+        });
+
         if ml.is_empty() {
             Ok(None)
         } else {
@@ -471,13 +524,21 @@ impl Account {
 
     pub(crate) fn to_credentialstatus(&self) -> Result<CredentialStatus, OperationError> {
         // In the future this will need to handle multiple credentials, not just single.
+        if self.primary.is_none() && self.devicekeys.is_empty() {
+            return Err(OperationError::NoMatchingAttributes);
+        }
 
-        self.primary
-            .as_ref()
-            .map(|cred| CredentialStatus {
-                creds: vec![cred.into()],
-            })
-            .ok_or(OperationError::NoMatchingAttributes)
+        Ok(CredentialStatus {
+            creds: self.primary.iter().map(|cred| cred.into()).collect(),
+            devicekeys: self
+                .devicekeys
+                .iter()
+                .map(|(uuid, (tag, _dk))| PasskeyDetail {
+                    tag: tag.clone(),
+                    uuid: *uuid,
+                })
+                .collect(),
+        })
     }
 
     pub(crate) fn to_backupcodesview(&self) -> Result<BackupCodesView, OperationError> {
@@ -0,0 +1,243 @@
+//! Account policy is attached to groups, and constrains the sessions and credentials of the
+//! accounts that are members of those groups. Since an account may be a member of many groups,
+//! the policies of each group are resolved so that the strictest value of each setting applies.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::credential::{Credential, CredentialType};
+use crate::entry::{Entry, EntryCommitted, EntrySealed};
+use crate::prelude::*;
+
+/// The minimum class of credential an account must authenticate with. These are in order
+/// of strength, so that the strictest of two minimums is the greater.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CredentialTypeMinimum {
+    #[default]
+    Any,
+    Mfa,
+    Passkey,
+    /// Only attested, hardware bound device keys.
+    DeviceKey,
+}
+
+impl CredentialTypeMinimum {
+    /// The class of an account's primary credential.
+    pub(crate) fn of_credential(cred: &Credential) -> Self {
+        match &cred.type_ {
+            CredentialType::Password(_) | CredentialType::GeneratedPassword(_) => {
+                CredentialTypeMinimum::Any
+            }
+            CredentialType::PasswordMfa(..) => CredentialTypeMinimum::Mfa,
+            CredentialType::Webauthn(_) => CredentialTypeMinimum::Passkey,
+        }
+    }
+}
+
+impl FromStr for CredentialTypeMinimum {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(CredentialTypeMinimum::Any),
+            "mfa" => Ok(CredentialTypeMinimum::Mfa),
+            "passkey" => Ok(CredentialTypeMinimum::Passkey),
+            "devicekey" => Ok(CredentialTypeMinimum::DeviceKey),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for CredentialTypeMinimum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialTypeMinimum::Any => write!(f, "any"),
+            CredentialTypeMinimum::Mfa => write!(f, "mfa"),
+            CredentialTypeMinimum::Passkey => write!(f, "passkey"),
+            CredentialTypeMinimum::DeviceKey => write!(f, "devicekey"),
+        }
+    }
+}
+
+/// The account policy of a single group.
+#[derive(Debug, Clone, Default)]
+pub struct AccountPolicy {
+    authsession_expiry: Option<u32>,
+    privilege_expiry: Option<u32>,
+    pw_min_length: Option<u32>,
+    credential_type_minimum: Option<CredentialTypeMinimum>,
+    oauth2_access_token_expiry: Option<u32>,
+}
+
+impl AccountPolicy {
+    pub(crate) fn from_entry(value: &Entry<EntrySealed, EntryCommitted>) -> Self {
+        if !value.attribute_equality("class", &PVCLASS_ACCOUNT_POLICY) {
+            return AccountPolicy::default();
+        }
+
+        let credential_type_minimum =
+            value
+                .get_ava_single_iutf8("credential_type_minimum")
+                .map(|s| {
+                    CredentialTypeMinimum::from_str(s).unwrap_or_else(|_| {
+                        // Fail closed, so that a typo can't weaken the policy.
+                        admin_error!(
+                            group = %value.get_uuid(),
+                            credential_type_minimum = %s,
+                            "Invalid credential_type_minimum in account policy, assuming devicekey"
+                        );
+                        CredentialTypeMinimum::DeviceKey
+                    })
+                });
+
+        AccountPolicy {
+            authsession_expiry: value.get_ava_single_uint32("authsession_expiry"),
+            privilege_expiry: value.get_ava_single_uint32("privilege_expiry"),
+            pw_min_length: value.get_ava_single_uint32("auth_password_minimum_length"),
+            credential_type_minimum,
+            oauth2_access_token_expiry: value.get_ava_single_uint32("oauth2_access_token_expiry"),
+        }
+    }
+}
+
+/// The account policy that applies to an account, resolved from all of its groups.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedAccountPolicy {
+    authsession_expiry: Option<u32>,
+    privilege_expiry: Option<u32>,
+    pw_min_length: Option<u32>,
+    credential_type_minimum: CredentialTypeMinimum,
+    oauth2_access_token_expiry: Option<u32>,
+}
+
+impl ResolvedAccountPolicy {
+    pub(crate) fn fold_from<'a, I>(policies: I) -> Self
+    where
+        I: Iterator<Item = &'a AccountPolicy>,
+    {
+        policies.fold(ResolvedAccountPolicy::default(), |acc, policy| {
+            ResolvedAccountPolicy {
+                authsession_expiry: stricter(
+                    acc.authsession_expiry,
+                    policy.authsession_expiry,
+                    std::cmp::min,
+                ),
+                privilege_expiry: stricter(
+                    acc.privilege_expiry,
+                    policy.privilege_expiry,
+                    std::cmp::min,
+                ),
+                pw_min_length: stricter(acc.pw_min_length, policy.pw_min_length, std::cmp::max),
+                credential_type_minimum: std::cmp::max(
+                    acc.credential_type_minimum,
+                    policy.credential_type_minimum.unwrap_or_default(),
+                ),
+                oauth2_access_token_expiry: stricter(
+                    acc.oauth2_access_token_expiry,
+                    policy.oauth2_access_token_expiry,
+                    std::cmp::min,
+                ),
+            }
+        })
+    }
+
+    /// Limit the expiry of a session, in seconds, to the policy.
+    pub(crate) fn authsession_expiry(&self, default: u64) -> u64 {
+        limit(default, self.authsession_expiry)
+    }
+
+    /// Limit the expiry of a session's privileges, in seconds, to the policy.
+    pub(crate) fn privilege_expiry(&self, default: u64) -> u64 {
+        limit(default, self.privilege_expiry)
+    }
+
+    /// Limit the expiry of an oauth2 access token, in seconds, to the policy.
+    pub(crate) fn oauth2_access_token_expiry(&self, default: u32) -> u32 {
+        self.oauth2_access_token_expiry
+            .map(|policy| std::cmp::min(default, policy))
+            .unwrap_or(default)
+    }
+
+    /// The minimum length of a password. A policy can only ever raise this.
+    pub(crate) fn pw_min_length(&self) -> usize {
+        self.pw_min_length
+            .map(|policy| std::cmp::max(PW_MIN_LENGTH, policy as usize))
+            .unwrap_or(PW_MIN_LENGTH)
+    }
+
+    pub(crate) fn credential_type_minimum(&self) -> CredentialTypeMinimum {
+        self.credential_type_minimum
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_credential_type_minimum(&mut self, value: CredentialTypeMinimum) {
+        self.credential_type_minimum = value;
+    }
+}
+
+fn stricter(a: Option<u32>, b: Option<u32>, f: fn(u32, u32) -> u32) -> Option<u32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(f(a, b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn limit(default: u64, policy: Option<u32>) -> u64 {
+    policy
+        .map(|policy| std::cmp::min(default, policy as u64))
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccountPolicy, CredentialTypeMinimum, ResolvedAccountPolicy};
+    use crate::prelude::*;
+
+    #[test]
+    fn test_idm_account_policy_resolve() {
+        let staff = AccountPolicy {
+            authsession_expiry: Some(7200),
+            pw_min_length: Some(12),
+            credential_type_minimum: Some(CredentialTypeMinimum::Mfa),
+            ..Default::default()
+        };
+        let admins = AccountPolicy {
+            authsession_expiry: Some(1800),
+            privilege_expiry: Some(300),
+            pw_min_length: Some(16),
+            credential_type_minimum: Some(CredentialTypeMinimum::Passkey),
+            oauth2_access_token_expiry: Some(300),
+        };
+        let contractors = AccountPolicy {
+            authsession_expiry: Some(3000),
+            pw_min_length: Some(4),
+            credential_type_minimum: Some(CredentialTypeMinimum::Any),
+            ..Default::default()
+        };
+
+        // No groups with a policy means the defaults apply.
+        let policy = ResolvedAccountPolicy::fold_from(std::iter::empty());
+        assert!(policy.authsession_expiry(AUTH_SESSION_EXPIRY) == AUTH_SESSION_EXPIRY);
+        assert!(policy.privilege_expiry(AUTH_PRIVILEGE_EXPIRY) == AUTH_PRIVILEGE_EXPIRY);
+        assert!(policy.pw_min_length() == PW_MIN_LENGTH);
+        assert!(policy.credential_type_minimum() == CredentialTypeMinimum::Any);
+        assert!(
+            policy.oauth2_access_token_expiry(OAUTH2_ACCESS_TOKEN_EXPIRY)
+                == OAUTH2_ACCESS_TOKEN_EXPIRY
+        );
+
+        // A policy can't weaken the defaults.
+        let policy = ResolvedAccountPolicy::fold_from([&contractors].into_iter());
+        assert!(policy.authsession_expiry(AUTH_SESSION_EXPIRY) == 3000);
+        assert!(policy.authsession_expiry(86400) == 3000);
+        assert!(policy.pw_min_length() == PW_MIN_LENGTH);
+
+        // The strictest value of each group wins.
+        let policy = ResolvedAccountPolicy::fold_from([&staff, &admins, &contractors].into_iter());
+        assert!(policy.authsession_expiry(AUTH_SESSION_EXPIRY) == 1800);
+        assert!(policy.privilege_expiry(AUTH_PRIVILEGE_EXPIRY) == 300);
+        assert!(policy.pw_min_length() == 16);
+        assert!(policy.credential_type_minimum() == CredentialTypeMinimum::Passkey);
+        assert!(policy.oauth2_access_token_expiry(OAUTH2_ACCESS_TOKEN_EXPIRY) == 300);
+    }
+}
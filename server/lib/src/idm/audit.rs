@@ -1,12 +1,31 @@
-use crate::prelude::*;
-use serde::{Deserialize, Serialize};
+//! Audit events record security relevant actions, such as authentication and changes to
+//! credentials or access controls. Events are sent to the server core as they happen, which
+//! appends them to an [AuditLog] so that they can be searched later.
+//!
+//! The audit log is deliberately kept outside of the main database. It is append only, is never
+//! replicated, and entries are only ever removed once they are older than the retention period.
+
+use std::fmt;
 use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use kanidm_proto::internal::{AuditLogEntry, AuditLogQuery};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::Sender;
+
+use crate::idm::server::IdmServerProxyReadTransaction;
+use crate::prelude::*;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum AuditSource {
     Internal,
     Https(IpAddr),
+    Ldaps(IpAddr),
 }
 
 impl From<Source> for AuditSource {
@@ -14,11 +33,19 @@ impl From<Source> for AuditSource {
         match value {
             Source::Internal => AuditSource::Internal,
             Source::Https(ip) => AuditSource::Https(ip),
+            Source::Ldaps(ip) => AuditSource::Ldaps(ip),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessControlOperation {
+    Create,
+    Modify,
+    Delete,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum AuditEvent {
     AuthenticationDenied {
         source: AuditSource,
@@ -27,4 +54,518 @@ pub enum AuditEvent {
         #[serde(with = "time::serde::timestamp")]
         time: OffsetDateTime,
     },
+    AuthenticationSucceeded {
+        source: AuditSource,
+        uuid: Uuid,
+        session_id: Uuid,
+        #[serde(with = "time::serde::timestamp")]
+        time: OffsetDateTime,
+    },
+    /// A session was re-authenticated to gain read-write privileges.
+    PrivilegeEscalated {
+        source: AuditSource,
+        uuid: Uuid,
+        spn: String,
+        session_id: Uuid,
+        #[serde(with = "time::serde::timestamp")]
+        time: OffsetDateTime,
+    },
+    CredentialsChanged {
+        /// The account that made the change. This is None when the change was made with a
+        /// credential reset token, since we can't know who held it.
+        actor: Option<Uuid>,
+        uuid: Uuid,
+        changes: Vec<String>,
+        #[serde(with = "time::serde::timestamp")]
+        time: OffsetDateTime,
+    },
+    AccessControlModified {
+        actor: Option<Uuid>,
+        uuid: Uuid,
+        operation: AccessControlOperation,
+        #[serde(with = "time::serde::timestamp")]
+        time: OffsetDateTime,
+    },
+    Oauth2ConsentGranted {
+        uuid: Uuid,
+        resource_server: Uuid,
+        scopes: Vec<String>,
+        #[serde(with = "time::serde::timestamp")]
+        time: OffsetDateTime,
+    },
+}
+
+/// The kind of an [AuditEvent], as it is stored and searched for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEventKind {
+    AuthenticationDenied,
+    AuthenticationSucceeded,
+    PrivilegeEscalated,
+    CredentialsChanged,
+    AccessControlModified,
+    Oauth2ConsentGranted,
+}
+
+impl fmt::Display for AuditEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditEventKind::AuthenticationDenied => write!(f, "authentication_denied"),
+            AuditEventKind::AuthenticationSucceeded => write!(f, "authentication_succeeded"),
+            AuditEventKind::PrivilegeEscalated => write!(f, "privilege_escalated"),
+            AuditEventKind::CredentialsChanged => write!(f, "credentials_changed"),
+            AuditEventKind::AccessControlModified => write!(f, "access_control_modified"),
+            AuditEventKind::Oauth2ConsentGranted => write!(f, "oauth2_consent_granted"),
+        }
+    }
+}
+
+impl FromStr for AuditEventKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "authentication_denied" => Ok(AuditEventKind::AuthenticationDenied),
+            "authentication_succeeded" => Ok(AuditEventKind::AuthenticationSucceeded),
+            "privilege_escalated" => Ok(AuditEventKind::PrivilegeEscalated),
+            "credentials_changed" => Ok(AuditEventKind::CredentialsChanged),
+            "access_control_modified" => Ok(AuditEventKind::AccessControlModified),
+            "oauth2_consent_granted" => Ok(AuditEventKind::Oauth2ConsentGranted),
+            _ => Err(()),
+        }
+    }
+}
+
+impl AuditEvent {
+    pub fn kind(&self) -> AuditEventKind {
+        match self {
+            AuditEvent::AuthenticationDenied { .. } => AuditEventKind::AuthenticationDenied,
+            AuditEvent::AuthenticationSucceeded { .. } => AuditEventKind::AuthenticationSucceeded,
+            AuditEvent::PrivilegeEscalated { .. } => AuditEventKind::PrivilegeEscalated,
+            AuditEvent::CredentialsChanged { .. } => AuditEventKind::CredentialsChanged,
+            AuditEvent::AccessControlModified { .. } => AuditEventKind::AccessControlModified,
+            AuditEvent::Oauth2ConsentGranted { .. } => AuditEventKind::Oauth2ConsentGranted,
+        }
+    }
+
+    pub fn time(&self) -> OffsetDateTime {
+        match self {
+            AuditEvent::AuthenticationDenied { time, .. }
+            | AuditEvent::AuthenticationSucceeded { time, .. }
+            | AuditEvent::PrivilegeEscalated { time, .. }
+            | AuditEvent::CredentialsChanged { time, .. }
+            | AuditEvent::AccessControlModified { time, .. }
+            | AuditEvent::Oauth2ConsentGranted { time, .. } => *time,
+        }
+    }
+
+    /// The account that caused this event, if known.
+    pub fn actor(&self) -> Option<Uuid> {
+        match self {
+            AuditEvent::AuthenticationDenied { uuid, .. }
+            | AuditEvent::AuthenticationSucceeded { uuid, .. }
+            | AuditEvent::PrivilegeEscalated { uuid, .. }
+            | AuditEvent::Oauth2ConsentGranted { uuid, .. } => Some(*uuid),
+            AuditEvent::CredentialsChanged { actor, .. }
+            | AuditEvent::AccessControlModified { actor, .. } => *actor,
+        }
+    }
+
+    /// The entry that this event affected.
+    pub fn target(&self) -> Uuid {
+        match self {
+            AuditEvent::AuthenticationDenied { uuid, .. }
+            | AuditEvent::AuthenticationSucceeded { uuid, .. }
+            | AuditEvent::PrivilegeEscalated { uuid, .. }
+            | AuditEvent::CredentialsChanged { uuid, .. }
+            | AuditEvent::AccessControlModified { uuid, .. } => *uuid,
+            AuditEvent::Oauth2ConsentGranted {
+                resource_server, ..
+            } => *resource_server,
+        }
+    }
+}
+
+/// Queue an event to be recorded in the audit log. This never blocks - if the queue is
+/// full the event is written to the server log instead so that it is not lost.
+pub(crate) fn queue_audit_event(audit_tx: &Sender<AuditEvent>, event: AuditEvent) {
+    match audit_tx.try_send(event) {
+        Ok(()) => {}
+        Err(TrySendError::Full(event)) => {
+            error!(
+                ?event,
+                "Audit event queue is full, unable to record audit event"
+            );
+        }
+        Err(TrySendError::Closed(_)) => {
+            error!("Unable to submit audit event to queue");
+        }
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)] // needs to accept value from `map_err`
+fn sqlite_error(e: rusqlite::Error) -> OperationError {
+    admin_error!(?e, "Audit Log SQLite Error");
+    OperationError::SqliteError
+}
+
+/// An append only store of audit events.
+pub struct AuditLog {
+    conn: Mutex<Connection>,
+}
+
+impl AuditLog {
+    /// Open the audit log at path, creating it if required. An empty path opens an in memory
+    /// log, which is only suitable for testing.
+    pub fn new(path: &str) -> Result<Self, OperationError> {
+        let conn = if path.is_empty() {
+            Connection::open_in_memory()
+        } else {
+            Connection::open(path)
+        }
+        .map_err(sqlite_error)?;
+
+        if !path.is_empty() {
+            conn.pragma_update(None, "journal_mode", "WAL")
+                .map_err(sqlite_error)?;
+        }
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                time INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                actor TEXT,
+                target TEXT NOT NULL,
+                data TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS audit_log_time_idx ON audit_log (time);
+            CREATE INDEX IF NOT EXISTS audit_log_actor_idx ON audit_log (actor);
+            CREATE INDEX IF NOT EXISTS audit_log_target_idx ON audit_log (target);
+            CREATE TRIGGER IF NOT EXISTS audit_log_append_only
+                BEFORE UPDATE ON audit_log
+                BEGIN
+                    SELECT RAISE(ABORT, 'the audit log is append only');
+                END;",
+        )
+        .map_err(sqlite_error)?;
+
+        Ok(AuditLog {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> Result<std::sync::MutexGuard<'_, Connection>, OperationError> {
+        self.conn.lock().map_err(|_| {
+            admin_error!("Audit log lock poisoned");
+            OperationError::InvalidState
+        })
+    }
+
+    pub fn append(&self, event: &AuditEvent) -> Result<(), OperationError> {
+        let data = serde_json::to_string(event).map_err(|e| {
+            admin_error!(?e, "Unable to serialise audit event");
+            OperationError::SerdeJsonError
+        })?;
+
+        self.conn()?
+            .execute(
+                "INSERT INTO audit_log (time, kind, actor, target, data) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    event.time().unix_timestamp(),
+                    event.kind().to_string(),
+                    event.actor().map(|u| u.to_string()),
+                    event.target().to_string(),
+                    data,
+                ],
+            )
+            .map(|_| ())
+            .map_err(sqlite_error)
+    }
+
+    /// Remove events that happened before the given time, returning how many were removed.
+    pub fn purge_before(&self, time: OffsetDateTime) -> Result<usize, OperationError> {
+        self.conn()?
+            .execute(
+                "DELETE FROM audit_log WHERE time < ?1",
+                params![time.unix_timestamp()],
+            )
+            .map_err(sqlite_error)
+    }
+
+    /// Search the log, returning the newest matching events first.
+    pub(crate) fn search(
+        &self,
+        actor: Option<Uuid>,
+        target: Option<Uuid>,
+        query: &AuditLogQuery,
+    ) -> Result<Vec<AuditLogEntry>, OperationError> {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+
+        if let Some(actor) = actor {
+            conditions.push("actor = ?");
+            values.push(SqlValue::Text(actor.to_string()));
+        }
+        if let Some(target) = target {
+            conditions.push("target = ?");
+            values.push(SqlValue::Text(target.to_string()));
+        }
+        if let Some(from) = query.from {
+            conditions.push("time >= ?");
+            values.push(SqlValue::Integer(from.unix_timestamp()));
+        }
+        if let Some(to) = query.to {
+            conditions.push("time < ?");
+            values.push(SqlValue::Integer(to.unix_timestamp()));
+        }
+        if let Some(kind) = &query.kind {
+            let kind = AuditEventKind::from_str(kind).map_err(|_| {
+                request_error!(%kind, "Invalid audit event kind");
+                OperationError::InvalidRequestState
+            })?;
+            conditions.push("kind = ?");
+            values.push(SqlValue::Text(kind.to_string()));
+        }
+
+        let limit = query
+            .limit
+            .map(|l| std::cmp::min(l, AUDIT_LOG_SEARCH_LIMIT))
+            .unwrap_or(AUDIT_LOG_SEARCH_LIMIT);
+
+        let sql = if conditions.is_empty() {
+            format!(
+                "SELECT id, data FROM audit_log ORDER BY id DESC LIMIT {}",
+                limit
+            )
+        } else {
+            format!(
+                "SELECT id, data FROM audit_log WHERE {} ORDER BY id DESC LIMIT {}",
+                conditions.join(" AND "),
+                limit
+            )
+        };
+
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&sql).map_err(sqlite_error)?;
+        let rows = stmt
+            .query_map(params_from_iter(values), |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(sqlite_error)?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let (id, data) = row.map_err(sqlite_error)?;
+            let (event, detail) = serde_json::from_str::<serde_json::Value>(&data)
+                .and_then(|detail| {
+                    serde_json::from_value::<AuditEvent>(detail.clone()).map(|e| (e, detail))
+                })
+                .map_err(|e| {
+                    admin_error!(?e, %id, "Unable to deserialise audit event");
+                    OperationError::SerdeJsonError
+                })?;
+            entries.push(AuditLogEntry {
+                id,
+                time: event.time(),
+                kind: event.kind().to_string(),
+                actor: event.actor(),
+                target: event.target(),
+                event: detail,
+            });
+        }
+        Ok(entries)
+    }
+}
+
+impl<'a> IdmServerProxyReadTransaction<'a> {
+    /// Search the audit log. Only members of idm_audit_read_priv may do this.
+    pub fn audit_log_search(
+        &mut self,
+        ident: &Identity,
+        audit_log: &AuditLog,
+        query: &AuditLogQuery,
+    ) -> Result<Vec<AuditLogEntry>, OperationError> {
+        if !ident.is_memberof(UUID_IDM_AUDIT_READ_PRIV) {
+            security_info!(%ident, "Identity is not permitted to search the audit log");
+            return Err(OperationError::AccessDenied);
+        }
+
+        let actor = query
+            .actor
+            .as_deref()
+            .map(|name| self.qs_read.name_to_uuid(name))
+            .transpose()?;
+        let target = query
+            .target
+            .as_deref()
+            .map(|name| self.qs_read.name_to_uuid(name))
+            .transpose()?;
+
+        audit_log.search(actor, target, query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kanidm_proto::internal::AuditLogQuery;
+    use time::OffsetDateTime;
+
+    use super::{AccessControlOperation, AuditEvent, AuditLog, AuditSource};
+    use crate::prelude::*;
+
+    fn at(secs: u64) -> OffsetDateTime {
+        OffsetDateTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[idm_test]
+    async fn test_idm_audit_log_search(idms: &IdmServer, _idms_delayed: &mut IdmServerDelayed) {
+        let audit_log = AuditLog::new("").expect("Unable to open audit log");
+
+        let acp_uuid = Uuid::new_v4();
+        let events = [
+            AuditEvent::AuthenticationDenied {
+                source: AuditSource::Internal,
+                uuid: UUID_ADMIN,
+                spn: "admin@example.com".to_string(),
+                time: at(100),
+            },
+            AuditEvent::AuthenticationSucceeded {
+                source: AuditSource::Internal,
+                uuid: UUID_ADMIN,
+                session_id: Uuid::new_v4(),
+                time: at(200),
+            },
+            AuditEvent::AccessControlModified {
+                actor: Some(UUID_ADMIN),
+                uuid: acp_uuid,
+                operation: AccessControlOperation::Create,
+                time: at(300),
+            },
+        ];
+        for event in events.iter() {
+            audit_log
+                .append(event)
+                .expect("Unable to append audit event");
+        }
+
+        let mut idms_prox_read = idms.proxy_read().await;
+        let admin = idms_prox_read
+            .qs_read
+            .internal_search_uuid(UUID_ADMIN)
+            .map(Identity::from_impersonate_entry_readonly)
+            .expect("Unable to get admin");
+        let anon = idms_prox_read
+            .qs_read
+            .internal_search_uuid(UUID_ANONYMOUS)
+            .map(Identity::from_impersonate_entry_readonly)
+            .expect("Unable to get anonymous");
+
+        // Only privileged accounts can read the log.
+        assert!(matches!(
+            idms_prox_read.audit_log_search(&anon, &audit_log, &AuditLogQuery::default()),
+            Err(OperationError::AccessDenied)
+        ));
+
+        // Newest first.
+        let r = idms_prox_read
+            .audit_log_search(&admin, &audit_log, &AuditLogQuery::default())
+            .expect("Failed to search");
+        assert!(r.len() == 3);
+        assert!(r[0].kind == "access_control_modified");
+        assert!(r[2].kind == "authentication_denied");
+
+        // Actors and targets can be given by name.
+        let query = AuditLogQuery {
+            actor: Some("admin".to_string()),
+            ..Default::default()
+        };
+        let r = idms_prox_read
+            .audit_log_search(&admin, &audit_log, &query)
+            .expect("Failed to search");
+        assert!(r.len() == 3);
+
+        let query = AuditLogQuery {
+            target: Some("admin".to_string()),
+            kind: Some("authentication_succeeded".to_string()),
+            ..Default::default()
+        };
+        let r = idms_prox_read
+            .audit_log_search(&admin, &audit_log, &query)
+            .expect("Failed to search");
+        assert!(r.len() == 1);
+        assert!(r[0].time == at(200));
+
+        let query = AuditLogQuery {
+            from: Some(at(150)),
+            to: Some(at(300)),
+            ..Default::default()
+        };
+        let r = idms_prox_read
+            .audit_log_search(&admin, &audit_log, &query)
+            .expect("Failed to search");
+        assert!(r.len() == 1);
+        assert!(r[0].kind == "authentication_succeeded");
+
+        let query = AuditLogQuery {
+            kind: Some("not_a_kind".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            idms_prox_read.audit_log_search(&admin, &audit_log, &query),
+            Err(OperationError::InvalidRequestState)
+        ));
+
+        // Events can't be altered, only aged out.
+        assert!(audit_log
+            .conn()
+            .expect("Unable to lock")
+            .execute("UPDATE audit_log SET actor = NULL", [])
+            .is_err());
+        assert!(audit_log.purge_before(at(250)) == Ok(2));
+        let r = idms_prox_read
+            .audit_log_search(&admin, &audit_log, &AuditLogQuery::default())
+            .expect("Failed to search");
+        assert!(r.len() == 1);
+        assert!(r[0].target == acp_uuid);
+    }
+
+    #[idm_test(audit)]
+    async fn test_idm_audit_access_control_modified(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+        idms_audit: &mut IdmServerAudit,
+    ) {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let admin = idms_prox_write
+            .qs_write
+            .internal_search_uuid(UUID_ADMIN)
+            .map(Identity::from_impersonate_entry_readwrite)
+            .expect("Unable to get admin");
+
+        let filter = filter!(f_eq(
+            "uuid",
+            PartialValue::Uuid(UUID_IDM_ACP_PEOPLE_READ_PRIV_V1)
+        ));
+        let modlist = ModifyList::new_purge_and_set("description", Value::new_utf8s("changed"));
+        idms_prox_write
+            .qs_write
+            .impersonate_modify(&filter, &filter, &modlist, &admin)
+            .expect("Failed to modify access control profile");
+
+        // Nothing is reported until the change is committed.
+        assert!(idms_audit.drain().is_empty());
+        idms_prox_write.commit().expect("Failed to commit");
+
+        let events = idms_audit.drain();
+        assert!(matches!(
+            events.as_slice(),
+            [AuditEvent::AccessControlModified {
+                actor: Some(UUID_ADMIN),
+                uuid: UUID_IDM_ACP_PEOPLE_READ_PRIV_V1,
+                operation: AccessControlOperation::Modify,
+                ..
+            }]
+        ));
+    }
 }
@@ -15,20 +15,22 @@ use kanidm_proto::v1::{
     AuthAllowed, AuthCredential, AuthIssueSession, AuthMech, OperationError, UserAuthToken,
 };
 // use crossbeam::channel::Sender;
+use nonempty::{nonempty, NonEmpty};
+use tokio::sync::mpsc;
 use tokio::sync::mpsc::UnboundedSender as Sender;
 use uuid::Uuid;
-// use webauthn_rs::prelude::DeviceKey as DeviceKeyV4;
-use nonempty::{nonempty, NonEmpty};
+use webauthn_rs::prelude::DeviceKey as DeviceKeyV4;
 use webauthn_rs::prelude::Passkey as PasskeyV4;
 use webauthn_rs::prelude::{
-    CredentialID, PasskeyAuthentication, RequestChallengeResponse, SecurityKeyAuthentication,
-    Webauthn,
+    CredentialID, DeviceKeyAuthentication, PasskeyAuthentication, RequestChallengeResponse,
+    SecurityKeyAuthentication, Webauthn,
 };
 
 use crate::credential::totp::Totp;
 use crate::credential::{BackupCodes, Credential, CredentialType, Password};
 use crate::idm::account::Account;
-use crate::idm::audit::AuditEvent;
+use crate::idm::accountpolicy::CredentialTypeMinimum;
+use crate::idm::audit::{queue_audit_event, AuditEvent};
 use crate::idm::delayed::{
     AuthSessionRecord, BackupCodeRemoval, DelayedAction, PasswordUpgrade, WebauthnCounterIncrement,
 };
@@ -50,6 +52,8 @@ const BAD_AUTH_TYPE_MSG: &str = "invalid authentication method in this context";
 const BAD_CREDENTIALS: &str = "invalid credential message";
 const ACCOUNT_EXPIRED: &str = "account expired";
 const PW_BADLIST_MSG: &str = "password is in badlist";
+const CREDENTIAL_POLICY_MSG: &str =
+    "account policy requires a stronger credential than is available for this account";
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub enum AuthType {
@@ -59,6 +63,7 @@ pub enum AuthType {
     GeneratedPassword,
     PasswordMfa,
     Passkey,
+    DeviceKey,
 }
 
 impl fmt::Display for AuthType {
@@ -70,6 +75,7 @@ impl fmt::Display for AuthType {
             AuthType::GeneratedPassword => write!(f, "generatedpassword"),
             AuthType::PasswordMfa => write!(f, "passwordmfa"),
             AuthType::Passkey => write!(f, "passkey"),
+            AuthType::DeviceKey => write!(f, "devicekey"),
         }
     }
 }
@@ -117,6 +123,14 @@ struct CredWebauthn {
     state: CredVerifyState,
 }
 
+#[derive(Clone, Debug)]
+/// The state of a devicekey credential during authentication
+struct CredDeviceKey {
+    chal: RequestChallengeResponse,
+    wan_state: DeviceKeyAuthentication,
+    state: CredVerifyState,
+}
+
 /// The current active handler for this authentication session. This is determined from what credentials
 /// are possible from the account, and what the user selected as the preferred authentication
 /// mechanism.
@@ -138,6 +152,10 @@ enum CredHandler {
         c_wan: CredWebauthn,
         cred_ids: BTreeMap<CredentialID, Uuid>,
     },
+    DeviceKey {
+        c_dk: CredDeviceKey,
+        cred_ids: BTreeMap<CredentialID, Uuid>,
+    },
 }
 
 impl TryFrom<(&Credential, &Webauthn)> for CredHandler {
@@ -292,6 +310,73 @@ impl TryFrom<(Uuid, &PasskeyV4, &Webauthn)> for CredHandler {
     }
 }
 
+impl TryFrom<(&BTreeMap<Uuid, (String, DeviceKeyV4)>, &Webauthn)> for CredHandler {
+    type Error = ();
+
+    /// Given the account's devicekeys, generate the credential handler that will be used
+    /// for this session.
+    fn try_from(
+        (dks, webauthn): (&BTreeMap<Uuid, (String, DeviceKeyV4)>, &Webauthn),
+    ) -> Result<Self, Self::Error> {
+        if dks.is_empty() {
+            security_info!("Account does not have any devicekeys");
+            return Err(());
+        }
+
+        let dk_list: Vec<_> = dks.values().map(|(_, k)| k).cloned().collect();
+        let cred_ids: BTreeMap<_, _> = dks
+            .iter()
+            .map(|(u, (_, k))| (k.cred_id().clone(), *u))
+            .collect();
+
+        webauthn
+            .start_devicekey_authentication(&dk_list)
+            .map(|(chal, wan_state)| CredHandler::DeviceKey {
+                c_dk: CredDeviceKey {
+                    chal,
+                    wan_state,
+                    state: CredVerifyState::Init,
+                },
+                cred_ids,
+            })
+            .map_err(|e| {
+                security_info!(
+                    ?e,
+                    "Unable to create devicekey webauthn authentication challenge"
+                );
+                // maps to unit.
+            })
+    }
+}
+
+impl TryFrom<(Uuid, &DeviceKeyV4, &Webauthn)> for CredHandler {
+    type Error = ();
+    fn try_from(
+        (cred_id, dk, webauthn): (Uuid, &DeviceKeyV4, &Webauthn),
+    ) -> Result<Self, Self::Error> {
+        let cred_ids = btreemap!((dk.cred_id().clone(), cred_id));
+        let dk_list = vec![dk.clone()];
+
+        webauthn
+            .start_devicekey_authentication(dk_list.as_slice())
+            .map(|(chal, wan_state)| CredHandler::DeviceKey {
+                c_dk: CredDeviceKey {
+                    chal,
+                    wan_state,
+                    state: CredVerifyState::Init,
+                },
+                cred_ids,
+            })
+            .map_err(|e| {
+                security_info!(
+                    ?e,
+                    "Unable to create devicekey webauthn authentication challenge"
+                );
+                // maps to unit.
+            })
+    }
+}
+
 impl CredHandler {
     /// Determine if this password factor requires an upgrade of it's cryptographic type. If
     /// so, send an asynchronous event into the queue that will allow the password to have it's
@@ -599,6 +684,71 @@ impl CredHandler {
         }
     }
 
+    /// Validate a devicekey authentication attempt
+    pub fn validate_devicekey(
+        cred: &AuthCredential,
+        cred_ids: &BTreeMap<CredentialID, Uuid>,
+        dk_cred: &mut CredDeviceKey,
+        webauthn: &Webauthn,
+        who: Uuid,
+        async_tx: &Sender<DelayedAction>,
+    ) -> CredState {
+        if dk_cred.state != CredVerifyState::Init {
+            security_error!("Handler::DeviceKey -> Result::Denied - Internal State Already Fail");
+            return CredState::Denied(BAD_WEBAUTHN_MSG);
+        }
+
+        match cred {
+            AuthCredential::DeviceKey(resp) => {
+                match webauthn.finish_devicekey_authentication(resp, &dk_cred.wan_state) {
+                    Ok(auth_result) => {
+                        if let Some(cred_id) = cred_ids.get(auth_result.cred_id()).copied() {
+                            dk_cred.state = CredVerifyState::Success;
+                            // Success. Determine if we need to update the counter
+                            // async from r.
+                            if auth_result.needs_update() {
+                                // Do async
+                                if let Err(_e) =
+                                    async_tx.send(DelayedAction::WebauthnCounterIncrement(
+                                        WebauthnCounterIncrement {
+                                            target_uuid: who,
+                                            auth_result,
+                                        },
+                                    ))
+                                {
+                                    admin_warn!("unable to queue delayed webauthn property update, continuing ... ");
+                                };
+                            };
+
+                            CredState::Success {
+                                auth_type: AuthType::DeviceKey,
+                                cred_id,
+                            }
+                        } else {
+                            dk_cred.state = CredVerifyState::Fail;
+                            security_error!("Handler::DeviceKey -> Result::Denied - devicekey credential id not found");
+                            CredState::Denied(BAD_WEBAUTHN_MSG)
+                        }
+                    }
+                    Err(e) => {
+                        dk_cred.state = CredVerifyState::Fail;
+                        security_error!(
+                            ?e,
+                            "Handler::DeviceKey -> Result::Denied - webauthn error"
+                        );
+                        CredState::Denied(BAD_WEBAUTHN_MSG)
+                    }
+                }
+            }
+            _ => {
+                security_error!(
+                    "Handler::DeviceKey -> Result::Denied - invalid cred type for handler"
+                );
+                CredState::Denied(BAD_AUTH_TYPE_MSG)
+            }
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     /// Given the current handler, proceed to authenticate the attempted credential step.
     pub fn validate(
@@ -642,6 +792,10 @@ impl CredHandler {
                 ref mut c_wan,
                 cred_ids,
             } => Self::validate_webauthn(cred, cred_ids, c_wan, webauthn, who, async_tx),
+            CredHandler::DeviceKey {
+                ref mut c_dk,
+                cred_ids,
+            } => Self::validate_devicekey(cred, cred_ids, c_dk, webauthn, who, async_tx),
         }
     }
 
@@ -667,6 +821,9 @@ impl CredHandler {
                 )
                 .collect(),
             CredHandler::Passkey { c_wan, .. } => vec![AuthAllowed::Passkey(c_wan.chal.clone())],
+            CredHandler::DeviceKey { c_dk, .. } => {
+                vec![AuthAllowed::DeviceKey(c_dk.chal.clone())]
+            }
         }
     }
 
@@ -676,7 +833,8 @@ impl CredHandler {
             (CredHandler::Anonymous { .. }, AuthMech::Anonymous)
             | (CredHandler::Password { .. }, AuthMech::Password)
             | (CredHandler::PasswordMfa { .. }, AuthMech::PasswordMfa)
-            | (CredHandler::Passkey { .. }, AuthMech::Passkey) => true,
+            | (CredHandler::Passkey { .. }, AuthMech::Passkey)
+            | (CredHandler::DeviceKey { .. }, AuthMech::DeviceKey) => true,
             (_, _) => false,
         }
     }
@@ -687,6 +845,7 @@ impl CredHandler {
             CredHandler::Password { .. } => AuthMech::Password,
             CredHandler::PasswordMfa { .. } => AuthMech::PasswordMfa,
             CredHandler::Passkey { .. } => AuthMech::Passkey,
+            CredHandler::DeviceKey { .. } => AuthMech::DeviceKey,
         }
     }
 }
@@ -768,11 +927,21 @@ impl AuthSession {
             } else {
                 // What's valid to use in this context?
                 let mut handlers = Vec::new();
+                // Did account policy remove a credential that would otherwise be usable?
+                let mut policy_denied = false;
+
+                let credential_type_minimum = account.account_policy.credential_type_minimum();
 
+                // TODO: Make it possible to have multiple creds.
+                // Probably means new authsession has to be failable
                 if let Some(cred) = &account.primary {
-                    // TODO: Make it possible to have multiple creds.
-                    // Probably means new authsession has to be failable
-                    if let Ok(ch) = CredHandler::try_from((cred, webauthn)) {
+                    if CredentialTypeMinimum::of_credential(cred) < credential_type_minimum {
+                        security_info!(
+                            %credential_type_minimum,
+                            "primary credential does not meet account policy"
+                        );
+                        policy_denied = true;
+                    } else if let Ok(ch) = CredHandler::try_from((cred, webauthn)) {
                         handlers.push(ch);
                     } else {
                         security_critical!(
@@ -781,12 +950,28 @@ impl AuthSession {
                     }
                 }
 
-                if let Ok(ch) = CredHandler::try_from((&account.passkeys, webauthn)) {
+                if CredentialTypeMinimum::Passkey < credential_type_minimum {
+                    if !account.passkeys.is_empty() {
+                        security_info!(
+                            %credential_type_minimum,
+                            "passkeys do not meet account policy"
+                        );
+                        policy_denied = true;
+                    }
+                } else if let Ok(ch) = CredHandler::try_from((&account.passkeys, webauthn)) {
+                    handlers.push(ch);
+                };
+
+                // Devicekeys always meet the account policy.
+                if let Ok(ch) = CredHandler::try_from((&account.devicekeys, webauthn)) {
                     handlers.push(ch);
                 };
 
                 if let Some(non_empty_handlers) = NonEmpty::collect(handlers.into_iter()) {
                     AuthSessionState::Init(non_empty_handlers)
+                } else if policy_denied {
+                    security_info!("account has no credentials that meet account policy");
+                    AuthSessionState::Denied(CREDENTIAL_POLICY_MSG)
                 } else {
                     security_info!("account has no available credentials");
                     AuthSessionState::Denied("invalid credential state")
@@ -839,6 +1024,7 @@ impl AuthSession {
         enum State {
             Expired,
             NoMatchingCred,
+            PolicyDenied,
             Proceed(CredHandler),
         }
 
@@ -853,10 +1039,17 @@ impl AuthSession {
             // anon cred_id won't ever exist on an account.
 
             let mut cred_handler = None;
+            let mut policy_denied = false;
 
             if let Some(primary) = account.primary.as_ref() {
                 if primary.uuid == cred_id {
-                    if let Ok(ch) = CredHandler::try_from((primary, webauthn)) {
+                    if CredentialTypeMinimum::of_credential(primary)
+                        < account.account_policy.credential_type_minimum()
+                    {
+                        // The policy may have changed since the session was issued.
+                        security_info!("primary credential does not meet account policy");
+                        policy_denied = true;
+                    } else if let Ok(ch) = CredHandler::try_from((primary, webauthn)) {
                         // Update it.
                         debug_assert!(cred_handler.is_none());
                         cred_handler = Some(ch);
@@ -869,7 +1062,11 @@ impl AuthSession {
             }
 
             if let Some(pk) = account.passkeys.get(&cred_id).map(|(_, pk)| pk) {
-                if let Ok(ch) = CredHandler::try_from((cred_id, pk, webauthn)) {
+                if CredentialTypeMinimum::Passkey < account.account_policy.credential_type_minimum()
+                {
+                    security_info!("passkey does not meet account policy");
+                    policy_denied = true;
+                } else if let Ok(ch) = CredHandler::try_from((cred_id, pk, webauthn)) {
                     // Update it.
                     debug_assert!(cred_handler.is_none());
                     cred_handler = Some(ch);
@@ -878,10 +1075,24 @@ impl AuthSession {
                 }
             }
 
+            if let Some(dk) = account.devicekeys.get(&cred_id).map(|(_, dk)| dk) {
+                if let Ok(ch) = CredHandler::try_from((cred_id, dk, webauthn)) {
+                    // Update it.
+                    debug_assert!(cred_handler.is_none());
+                    cred_handler = Some(ch);
+                } else {
+                    security_critical!(
+                        "corrupt credentials, unable to start devicekey credhandler"
+                    );
+                }
+            }
+
             // Did anything get set-up?
 
             if let Some(cred_handler) = cred_handler {
                 State::Proceed(cred_handler)
+            } else if policy_denied {
+                State::PolicyDenied
             } else {
                 State::NoMatchingCred
             }
@@ -914,6 +1125,10 @@ impl AuthSession {
                 security_error!("Unable to select a credential for authentication");
                 (None, AuthState::Denied(BAD_CREDENTIALS.to_string()))
             }
+            State::PolicyDenied => {
+                security_info!("credential does not meet account policy");
+                (None, AuthState::Denied(CREDENTIAL_POLICY_MSG.to_string()))
+            }
         }
     }
 
@@ -925,7 +1140,8 @@ impl AuthSession {
                 Ok(Some(*cred_id))
             }
             AuthSessionState::InProgress(CredHandler::Anonymous { .. })
-            | AuthSessionState::InProgress(CredHandler::Passkey { .. }) => Ok(None),
+            | AuthSessionState::InProgress(CredHandler::Passkey { .. })
+            | AuthSessionState::InProgress(CredHandler::DeviceKey { .. }) => Ok(None),
             _ => Err(OperationError::InvalidState),
         }
     }
@@ -1003,7 +1219,7 @@ impl AuthSession {
         cred: &AuthCredential,
         time: Duration,
         async_tx: &Sender<DelayedAction>,
-        audit_tx: &Sender<AuditEvent>,
+        audit_tx: &mpsc::Sender<AuditEvent>,
         webauthn: &Webauthn,
         pw_badlist_set: Option<&HashSet<String>>,
         uat_jwt_signer: &JwsSigner,
@@ -1026,6 +1242,19 @@ impl AuthSession {
                     CredState::Success { auth_type, cred_id } => {
                         // Issue the uat based on a set of factors.
                         let uat = self.issue_uat(&auth_type, time, async_tx, cred_id)?;
+
+                        if let AuthIntent::Reauth { session_id, .. } = &self.intent {
+                            queue_audit_event(
+                                audit_tx,
+                                AuditEvent::PrivilegeEscalated {
+                                    source: self.source.clone().into(),
+                                    uuid: self.account.uuid,
+                                    spn: self.account.spn.clone(),
+                                    session_id: *session_id,
+                                    time: OffsetDateTime::UNIX_EPOCH + time,
+                                },
+                            );
+                        }
                         let jwt = Jws::new(uat);
 
                         // Now encrypt and prepare the token for return to the client.
@@ -1050,17 +1279,15 @@ impl AuthSession {
                         (None, Ok(AuthState::Continue(allowed.into_iter().collect())))
                     }
                     CredState::Denied(reason) => {
-                        if audit_tx
-                            .send(AuditEvent::AuthenticationDenied {
+                        queue_audit_event(
+                            audit_tx,
+                            AuditEvent::AuthenticationDenied {
                                 source: self.source.clone().into(),
                                 spn: self.account.spn.clone(),
                                 uuid: self.account.uuid,
                                 time: OffsetDateTime::UNIX_EPOCH + time,
-                            })
-                            .is_err()
-                        {
-                            error!("Unable to submit audit event to queue");
-                        }
+                            },
+                        );
                         security_info!(%reason, "Credentials denied");
                         (
                             Some(AuthSessionState::Denied(reason)),
@@ -1106,9 +1333,10 @@ impl AuthSession {
                 let scope = match auth_type {
                     AuthType::UnixPassword | AuthType::Anonymous => SessionScope::ReadOnly,
                     AuthType::GeneratedPassword => SessionScope::ReadWrite,
-                    AuthType::Password | AuthType::PasswordMfa | AuthType::Passkey => {
-                        SessionScope::PrivilegeCapable
-                    }
+                    AuthType::Password
+                    | AuthType::PasswordMfa
+                    | AuthType::Passkey
+                    | AuthType::DeviceKey => SessionScope::PrivilegeCapable,
                 };
 
                 security_info!(
@@ -1141,7 +1369,8 @@ impl AuthSession {
                     AuthType::Password
                     | AuthType::GeneratedPassword
                     | AuthType::PasswordMfa
-                    | AuthType::Passkey => {
+                    | AuthType::Passkey
+                    | AuthType::DeviceKey => {
                         trace!("⚠️   Queued AuthSessionRecord for {}", self.account.uuid);
                         async_tx.send(DelayedAction::AuthSessionRecord(AuthSessionRecord {
                             target_uuid: self.account.uuid,
@@ -1152,6 +1381,7 @@ impl AuthSession {
                             issued_at: uat.issued_at,
                             issued_by: IdentityId::User(self.account.uuid),
                             scope,
+                            source: self.source.clone(),
                         }))
                         .map_err(|e| {
                             debug!(?e, "queue failure");
@@ -1174,9 +1404,10 @@ impl AuthSession {
                         error!("AuthType used in Reauth is not valid for session re-issuance. Rejecting");
                         return Err(OperationError::InvalidState);
                     }
-                    AuthType::Password | AuthType::PasswordMfa | AuthType::Passkey => {
-                        SessionScope::PrivilegeCapable
-                    }
+                    AuthType::Password
+                    | AuthType::PasswordMfa
+                    | AuthType::Passkey
+                    | AuthType::DeviceKey => SessionScope::PrivilegeCapable,
                 };
 
                 let uat = self
@@ -1218,16 +1449,18 @@ mod tests {
     use compact_jwt::JwsSigner;
     use hashbrown::HashSet;
     use kanidm_proto::v1::{AuthAllowed, AuthCredential, AuthIssueSession, AuthMech};
+    use tokio::sync::mpsc;
     use tokio::sync::mpsc::unbounded_channel as unbounded;
     use webauthn_authenticator_rs::softpasskey::SoftPasskey;
     use webauthn_authenticator_rs::WebauthnAuthenticator;
 
     use crate::credential::totp::{Totp, TOTP_DEFAULT_STEP};
     use crate::credential::{BackupCodes, Credential};
+    use crate::idm::accountpolicy::CredentialTypeMinimum;
     use crate::idm::audit::AuditEvent;
     use crate::idm::authsession::{
         AuthSession, BAD_AUTH_TYPE_MSG, BAD_BACKUPCODE_MSG, BAD_PASSWORD_MSG, BAD_TOTP_MSG,
-        BAD_WEBAUTHN_MSG, PW_BADLIST_MSG,
+        BAD_WEBAUTHN_MSG, CREDENTIAL_POLICY_MSG, PW_BADLIST_MSG,
     };
     use crate::idm::delayed::DelayedAction;
     use crate::idm::AuthState;
@@ -1339,7 +1572,7 @@ mod tests {
         account.primary = Some(cred);
 
         let (async_tx, mut async_rx) = unbounded();
-        let (audit_tx, mut audit_rx) = unbounded();
+        let (audit_tx, mut audit_rx) = mpsc::channel(AUDIT_EVENT_QUEUE_SIZE);
 
         // now check
         let (mut session, pw_badlist_cache) =
@@ -1408,7 +1641,7 @@ mod tests {
         account.primary = Some(cred);
 
         let (async_tx, mut async_rx) = unbounded();
-        let (audit_tx, mut audit_rx) = unbounded();
+        let (audit_tx, mut audit_rx) = mpsc::channel(AUDIT_EVENT_QUEUE_SIZE);
 
         // now check, even though the password is correct, Auth should be denied since it is in badlist
         let (mut session, pw_badlist_cache) =
@@ -1530,7 +1763,7 @@ mod tests {
         account.primary = Some(cred);
 
         let (async_tx, mut async_rx) = unbounded();
-        let (audit_tx, mut audit_rx) = unbounded();
+        let (audit_tx, mut audit_rx) = mpsc::channel(AUDIT_EVENT_QUEUE_SIZE);
 
         // now check
 
@@ -1715,7 +1948,7 @@ mod tests {
         account.primary = Some(cred);
 
         let (async_tx, mut async_rx) = unbounded();
-        let (audit_tx, mut audit_rx) = unbounded();
+        let (audit_tx, mut audit_rx) = mpsc::channel(AUDIT_EVENT_QUEUE_SIZE);
 
         // now check
 
@@ -1866,7 +2099,7 @@ mod tests {
     fn test_idm_authsession_webauthn_only_mech() {
         sketching::test_init();
         let (async_tx, mut async_rx) = unbounded();
-        let (audit_tx, mut audit_rx) = unbounded();
+        let (audit_tx, mut audit_rx) = mpsc::channel(AUDIT_EVENT_QUEUE_SIZE);
         let ts = duration_from_epoch_now();
         // create the ent
         let mut account = entry_to_account!(E_ADMIN_V1.clone());
@@ -2025,7 +2258,7 @@ mod tests {
     fn test_idm_authsession_webauthn_password_mech() {
         sketching::test_init();
         let (async_tx, mut async_rx) = unbounded();
-        let (audit_tx, mut audit_rx) = unbounded();
+        let (audit_tx, mut audit_rx) = mpsc::channel(AUDIT_EVENT_QUEUE_SIZE);
         let ts = duration_from_epoch_now();
         // create the ent
         let mut account = entry_to_account!(E_ADMIN_V1);
@@ -2232,7 +2465,7 @@ mod tests {
     fn test_idm_authsession_webauthn_password_totp_mech() {
         sketching::test_init();
         let (async_tx, mut async_rx) = unbounded();
-        let (audit_tx, mut audit_rx) = unbounded();
+        let (audit_tx, mut audit_rx) = mpsc::channel(AUDIT_EVENT_QUEUE_SIZE);
         let ts = duration_from_epoch_now();
         // create the ent
         let mut account = entry_to_account!(E_ADMIN_V1);
@@ -2556,7 +2789,7 @@ mod tests {
         account.primary = Some(cred);
 
         let (async_tx, mut async_rx) = unbounded();
-        let (audit_tx, mut audit_rx) = unbounded();
+        let (audit_tx, mut audit_rx) = mpsc::channel(AUDIT_EVENT_QUEUE_SIZE);
 
         // now check
         // == two step checks
@@ -2737,6 +2970,88 @@ mod tests {
         assert!(audit_rx.blocking_recv().is_none());
     }
 
+    #[test]
+    fn test_idm_authsession_credential_type_minimum() {
+        sketching::test_init();
+        let ts = duration_from_epoch_now();
+        let mut account = entry_to_account!(E_ADMIN_V1.clone());
+
+        let (webauthn, _wa, wan_cred) = setup_webauthn_passkey(account.name.as_str());
+
+        let p = CryptoPolicy::minimum();
+        let pw_cred = Credential::new_password_only(&p, "test_password").unwrap();
+        let mfa_cred = pw_cred
+            .clone()
+            .append_totp("totp".to_string(), Totp::generate_secure(TOTP_DEFAULT_STEP));
+
+        account
+            .account_policy
+            .set_credential_type_minimum(CredentialTypeMinimum::Mfa);
+
+        // A password alone is refused.
+        account.primary = Some(pw_cred.clone());
+        let (session, state) = AuthSession::new(
+            account.clone(),
+            AuthIssueSession::Token,
+            &webauthn,
+            ts,
+            Source::Internal,
+        );
+        assert!(session.is_none());
+        match state {
+            AuthState::Denied(msg) => assert!(msg == CREDENTIAL_POLICY_MSG),
+            _ => panic!("Invalid auth state"),
+        }
+
+        // But if a passkey is present, only it is offered.
+        account.passkeys = btreemap![(Uuid::new_v4(), ("soft".to_string(), wan_cred))];
+        let (session, state) = AuthSession::new(
+            account.clone(),
+            AuthIssueSession::Token,
+            &webauthn,
+            ts,
+            Source::Internal,
+        );
+        assert!(session.is_some());
+        match state {
+            AuthState::Choose(auth_mechs) => assert!(auth_mechs == vec![AuthMech::Passkey]),
+            _ => panic!("Invalid auth state"),
+        }
+
+        // Password and totp meets the policy.
+        account.primary = Some(mfa_cred);
+        let (_session, state) = AuthSession::new(
+            account.clone(),
+            AuthIssueSession::Token,
+            &webauthn,
+            ts,
+            Source::Internal,
+        );
+        match state {
+            AuthState::Choose(auth_mechs) => {
+                assert!(auth_mechs.contains(&AuthMech::PasswordMfa));
+                assert!(auth_mechs.contains(&AuthMech::Passkey));
+            }
+            _ => panic!("Invalid auth state"),
+        }
+
+        // Unless passkeys are required.
+        account
+            .account_policy
+            .set_credential_type_minimum(CredentialTypeMinimum::Passkey);
+        let (_session, state) = AuthSession::new(
+            account,
+            AuthIssueSession::Token,
+            &webauthn,
+            ts,
+            Source::Internal,
+        );
+        match state {
+            AuthState::Choose(auth_mechs) => assert!(auth_mechs == vec![AuthMech::Passkey]),
+            _ => panic!("Invalid auth state"),
+        }
+    }
+
     #[test]
     fn test_idm_authsession_multiple_totp_password_mech() {
         // Slightly different to the other TOTP test, this
@@ -2775,7 +3090,7 @@ mod tests {
         account.primary = Some(cred);
 
         let (async_tx, mut async_rx) = unbounded();
-        let (audit_tx, mut audit_rx) = unbounded();
+        let (audit_tx, mut audit_rx) = mpsc::channel(AUDIT_EVENT_QUEUE_SIZE);
 
         // Test totp_a
         {
@@ -11,13 +11,16 @@ use kanidm_proto::v1::{
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 use webauthn_rs::prelude::{
-    CreationChallengeResponse, DeviceKey as DeviceKeyV4, Passkey as PasskeyV4, PasskeyRegistration,
-    RegisterPublicKeyCredential,
+    CreationChallengeResponse, DeviceKey as DeviceKeyV4, DeviceKeyRegistration,
+    Passkey as PasskeyV4, PasskeyRegistration, RegisterPublicKeyCredential,
 };
 
 use crate::credential::totp::{Totp, TOTP_DEFAULT_STEP};
-use crate::credential::{BackupCodes, Credential};
+use crate::credential::{attestation_ca_list_from_b64, BackupCodes, Credential};
 use crate::idm::account::Account;
+use crate::idm::accountpolicy::CredentialTypeMinimum;
+use crate::idm::audit::AuditEvent;
+use crate::idm::message::{queue_message, queue_notification, MessageTemplate, Notification};
 use crate::idm::server::{IdmServerCredUpdateTransaction, IdmServerProxyWriteTransaction};
 use crate::prelude::*;
 use crate::server::access::Access;
@@ -28,6 +31,11 @@ const MAXIMUM_CRED_UPDATE_TTL: Duration = Duration::from_secs(900);
 const MAXIMUM_INTENT_TTL: Duration = Duration::from_secs(86400);
 const MINIMUM_INTENT_TTL: Duration = MAXIMUM_CRED_UPDATE_TTL;
 
+fn intent_max_ttl(event: &InitCredentialUpdateIntentEvent, ct: Duration) -> Duration {
+    let mttl = event.max_ttl.unwrap_or_else(|| Duration::new(0, 0));
+    ct + mttl.clamp(MINIMUM_INTENT_TTL, MAXIMUM_INTENT_TTL)
+}
+
 #[derive(Debug)]
 pub enum PasswordQuality {
     TooShort(usize),
@@ -60,6 +68,7 @@ enum MfaRegState {
     TotpTryAgain(Totp),
     TotpInvalidSha1(Totp, Totp, String),
     Passkey(Box<CreationChallengeResponse>, PasskeyRegistration),
+    DeviceKey(Box<CreationChallengeResponse>, DeviceKeyRegistration),
 }
 
 impl fmt::Debug for MfaRegState {
@@ -70,6 +79,7 @@ impl fmt::Debug for MfaRegState {
             MfaRegState::TotpTryAgain(_) => "MfaRegState::TotpTryAgain",
             MfaRegState::TotpInvalidSha1(_, _, _) => "MfaRegState::TotpInvalidSha1",
             MfaRegState::Passkey(_, _) => "MfaRegState::Passkey",
+            MfaRegState::DeviceKey(_, _) => "MfaRegState::DeviceKey",
         };
         write!(f, "{t}")
     }
@@ -82,6 +92,8 @@ pub(crate) struct CredentialUpdateSession {
     account: Account,
     // What intent was used to initiate this session.
     intent_token_id: Option<String>,
+    // Who initiated this session, if it was not from an intent.
+    initiated_by: Option<Uuid>,
     // Acc policy
 
     // The pw credential as they are being updated
@@ -90,7 +102,9 @@ pub(crate) struct CredentialUpdateSession {
     // Passkeys that have been configured.
     passkeys: BTreeMap<Uuid, (String, PasskeyV4)>,
     // Devicekeys
-    _devicekeys: BTreeMap<Uuid, (String, DeviceKeyV4)>,
+    devicekeys: BTreeMap<Uuid, (String, DeviceKeyV4)>,
+    // The CAs that devicekeys must be attested by, as configured on the domain.
+    attestation_ca_list: Vec<String>,
 
     // Internal reg state of any inprogress totp or webauthn credentials.
     mfaregstate: MfaRegState,
@@ -112,6 +126,7 @@ impl fmt::Debug for CredentialUpdateSession {
             .field("intent_token_id", &self.intent_token_id)
             .field("primary.detail()", &primary)
             .field("passkeys.list()", &passkeys)
+            .field("devicekeys.len()", &self.devicekeys.len())
             .field("mfaregstate", &self.mfaregstate)
             .finish()
     }
@@ -121,31 +136,78 @@ impl CredentialUpdateSession {
     // In future this should be a Vec of the issues with the current session so that UI's can highlight
     // properly how to proceed.
     fn can_commit(&self) -> bool {
-        // Should be it's own PR and use account policy
+        let credential_type_minimum = self.account.account_policy.credential_type_minimum();
 
-        /*
-        // We'll check policy here in future.
-        let is_primary_valid = match self.primary.as_ref() {
-            Some(Credential {
-                uuid: _,
-                type_: CredentialType::Password(_),
-            }) => {
-                // We refuse password-only auth now.
-                info!("Password only authentication.");
-                false
-            }
-            // So far valid.
-            _ => true,
-        };
+        if credential_type_minimum == CredentialTypeMinimum::Any {
+            return true;
+        }
+
+        // Every credential that remains must meet the account policy, else it could be used
+        // to authenticate below the minimum. There must also be at least one, so the account
+        // is still able to authenticate.
+        let primary_valid = self
+            .primary
+            .as_ref()
+            .map(|primary| CredentialTypeMinimum::of_credential(primary) >= credential_type_minimum)
+            .unwrap_or(true);
 
-        info!("can_commit -> {}", is_primary_valid);
+        let passkeys_valid =
+            self.passkeys.is_empty() || CredentialTypeMinimum::Passkey >= credential_type_minimum;
 
-        // For logic later.
-        is_primary_valid
-        */
+        let any_valid =
+            self.primary.is_some() || !self.passkeys.is_empty() || !self.devicekeys.is_empty();
+
+        let can_commit = primary_valid && passkeys_valid && any_valid;
 
-        true
+        info!(%credential_type_minimum, "can_commit -> {}", can_commit);
+
+        can_commit
     }
+
+    /// Describe how this session changes the credentials of the account, for the notification
+    /// sent to the person once it is committed.
+    fn changes(&self) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        match (&self.account.primary, &self.primary) {
+            (None, Some(_)) => changes.push("A password was set".to_string()),
+            (Some(_), None) => changes.push("The password was removed".to_string()),
+            (Some(prev), Some(next)) if prev != next => changes
+                .push("The password or its multi-factor authentication was changed".to_string()),
+            _ => {}
+        }
+
+        describe_key_changes(
+            "passkey",
+            &self.account.passkeys,
+            &self.passkeys,
+            &mut changes,
+        );
+        describe_key_changes(
+            "device key",
+            &self.account.devicekeys,
+            &self.devicekeys,
+            &mut changes,
+        );
+
+        changes
+    }
+}
+
+fn describe_key_changes<K>(
+    kind: &str,
+    prev: &BTreeMap<Uuid, (String, K)>,
+    next: &BTreeMap<Uuid, (String, K)>,
+    changes: &mut Vec<String>,
+) {
+    next.iter()
+        .filter(|(uuid, _)| !prev.contains_key(*uuid))
+        .for_each(|(_, (tag, _))| changes.push(format!("A {} named '{}' was added", kind, tag)));
+    prev.iter()
+        .filter(|(uuid, _)| !next.contains_key(*uuid))
+        .for_each(|(_, (tag, _))| {
+            changes.push(format!("The {} named '{}' was removed", kind, tag))
+        });
 }
 
 pub enum MfaRegStateStatus {
@@ -156,6 +218,7 @@ pub enum MfaRegStateStatus {
     TotpInvalidSha1,
     BackupCodes(HashSet<String>),
     Passkey(CreationChallengeResponse),
+    DeviceKey(CreationChallengeResponse),
 }
 
 impl fmt::Debug for MfaRegStateStatus {
@@ -167,6 +230,7 @@ impl fmt::Debug for MfaRegStateStatus {
             MfaRegStateStatus::TotpInvalidSha1 => "MfaRegStateStatus::TotpInvalidSha1",
             MfaRegStateStatus::BackupCodes(_) => "MfaRegStateStatus::BackupCodes",
             MfaRegStateStatus::Passkey(_) => "MfaRegStateStatus::Passkey",
+            MfaRegStateStatus::DeviceKey(_) => "MfaRegStateStatus::DeviceKey",
         };
         write!(f, "{t}")
     }
@@ -181,6 +245,7 @@ pub struct CredentialUpdateSessionStatus {
     can_commit: bool,
     primary: Option<CredentialDetail>,
     passkeys: Vec<PasskeyDetail>,
+    devicekeys: Vec<PasskeyDetail>,
     // Any info the client needs about mfareg state.
     mfaregstate: MfaRegStateStatus,
 }
@@ -206,6 +271,7 @@ impl Into<CUStatus> for CredentialUpdateSessionStatus {
             can_commit: self.can_commit,
             primary: self.primary,
             passkeys: self.passkeys,
+            devicekeys: self.devicekeys,
             mfaregstate: match self.mfaregstate {
                 MfaRegStateStatus::None => CURegState::None,
                 MfaRegStateStatus::TotpCheck(c) => CURegState::TotpCheck(c),
@@ -215,6 +281,7 @@ impl Into<CUStatus> for CredentialUpdateSessionStatus {
                     CURegState::BackupCodes(s.into_iter().collect())
                 }
                 MfaRegStateStatus::Passkey(r) => CURegState::Passkey(r),
+                MfaRegStateStatus::DeviceKey(r) => CURegState::DeviceKey(r),
             },
         }
     }
@@ -235,6 +302,14 @@ impl From<&CredentialUpdateSession> for CredentialUpdateSessionStatus {
                     uuid: *uuid,
                 })
                 .collect(),
+            devicekeys: session
+                .devicekeys
+                .iter()
+                .map(|(uuid, (tag, _dk))| PasskeyDetail {
+                    tag: tag.clone(),
+                    uuid: *uuid,
+                })
+                .collect(),
             mfaregstate: match &session.mfaregstate {
                 MfaRegState::None => MfaRegStateStatus::None,
                 MfaRegState::TotpInit(token) => MfaRegStateStatus::TotpCheck(
@@ -243,6 +318,7 @@ impl From<&CredentialUpdateSession> for CredentialUpdateSessionStatus {
                 MfaRegState::TotpTryAgain(_) => MfaRegStateStatus::TotpTryAgain,
                 MfaRegState::TotpInvalidSha1(_, _, _) => MfaRegStateStatus::TotpInvalidSha1,
                 MfaRegState::Passkey(r, _) => MfaRegStateStatus::Passkey(r.as_ref().clone()),
+                MfaRegState::DeviceKey(r, _) => MfaRegStateStatus::DeviceKey(r.as_ref().clone()),
             },
         }
     }
@@ -389,6 +465,7 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         &mut self,
         sessionid: Uuid,
         intent_token_id: Option<String>,
+        initiated_by: Option<Uuid>,
         account: Account,
         ct: Duration,
     ) -> Result<(CredentialUpdateSessionToken, CredentialUpdateSessionStatus), OperationError> {
@@ -398,15 +475,24 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         let devicekeys = account.devicekeys.clone();
         // Stash the issuer for some UI elements
         let issuer = self.qs_write.get_domain_display_name().to_string();
+        // And the cas that any new devicekeys must be attested by.
+        let attestation_ca_list = self
+            .qs_write
+            .internal_search_uuid(UUID_DOMAIN_INFO)?
+            .get_ava_iter_utf8("webauthn_attestation_ca_list")
+            .map(|cas| cas.map(str::to_string).collect())
+            .unwrap_or_default();
 
         // - store account policy (if present)
         let session = CredentialUpdateSession {
             account,
             issuer,
             intent_token_id,
+            initiated_by,
             primary,
             passkeys,
-            _devicekeys: devicekeys,
+            devicekeys,
+            attestation_ca_list,
             mfaregstate: MfaRegState::None,
         };
 
@@ -449,8 +535,7 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         // ==== AUTHORISATION CHECKED ===
 
         // Build the intent token.
-        let mttl = event.max_ttl.unwrap_or_else(|| Duration::new(0, 0));
-        let max_ttl = ct + mttl.clamp(MINIMUM_INTENT_TTL, MAXIMUM_INTENT_TTL);
+        let max_ttl = intent_max_ttl(event, ct);
         // let sessionid = uuid_from_duration(max_ttl, self.sid);
         let intent_id = readable_password_from_random();
 
@@ -519,6 +604,36 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         Ok(CredentialUpdateIntentToken { intent_id })
     }
 
+    /// Create a credential update intent, and queue it to be sent to the primary mail address
+    /// of the account rather than returning it to the caller.
+    pub fn init_credential_update_intent_send(
+        &mut self,
+        event: &InitCredentialUpdateIntentEvent,
+        ct: Duration,
+    ) -> Result<(), OperationError> {
+        let account = self.validate_init_credential_update(event.target, &event.ident)?;
+
+        let mail_destination = account.mail_primary.clone().ok_or_else(|| {
+            admin_warn!(spn = %account.spn, "Unable to send credential update intent, account has no mail address");
+            OperationError::InvalidAccountState("Account has no mail address".to_string())
+        })?;
+
+        let expiry_time = OffsetDateTime::UNIX_EPOCH + intent_max_ttl(event, ct);
+        let CredentialUpdateIntentToken { intent_id } =
+            self.init_credential_update_intent(event, ct)?;
+
+        queue_message(
+            &mut self.qs_write,
+            account.uuid,
+            Some(mail_destination.as_str()),
+            &MessageTemplate::CredentialResetIntent {
+                display_name: account.displayname,
+                intent_id,
+                expiry_time,
+            },
+        )
+    }
+
     pub fn exchange_intent_credential_update(
         &mut self,
         token: CredentialUpdateIntentToken,
@@ -699,7 +814,7 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         // ==========
         // Okay, good to exchange.
 
-        self.create_credupdate_session(session_id, Some(intent_id), account, current_time)
+        self.create_credupdate_session(session_id, Some(intent_id), None, account, current_time)
     }
 
     #[instrument(level = "debug", skip_all)]
@@ -715,7 +830,7 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         let sessionid = uuid_from_duration(ct + MAXIMUM_CRED_UPDATE_TTL, self.sid);
 
         // Build the cred update session.
-        self.create_credupdate_session(sessionid, None, account, ct)
+        self.create_credupdate_session(sessionid, None, event.ident.get_uuid(), account, ct)
     }
 
     #[instrument(level = "trace", skip(self))]
@@ -868,6 +983,13 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
             let v_pk = Value::Passkey(*uuid, tag.clone(), pk.clone());
             modlist.push_mod(Modify::Present(AttrString::from("passkeys"), v_pk));
         });
+
+        // And the same for devicekeys.
+        modlist.push_mod(Modify::Purged(AttrString::from("devicekeys")));
+        session.devicekeys.iter().for_each(|(uuid, (tag, dk))| {
+            let v_dk = Value::DeviceKey(*uuid, tag.clone(), dk.clone());
+            modlist.push_mod(Modify::Present(AttrString::from("devicekeys"), v_dk));
+        });
         // Are any other checks needed?
 
         // Apply to the account!
@@ -882,7 +1004,29 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
             .map_err(|e| {
                 request_error!(error = ?e);
                 e
-            })
+            })?;
+
+        // Let the person know their credentials changed, in case it wasn't them.
+        let changes = session.changes();
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        self.qs_write.audit_event(AuditEvent::CredentialsChanged {
+            actor: session.initiated_by,
+            uuid: session.account.uuid,
+            changes: changes.clone(),
+            time: OffsetDateTime::UNIX_EPOCH + ct,
+        });
+
+        let entry = self.qs_write.internal_search_uuid(session.account.uuid)?;
+        queue_notification(&mut self.qs_write, &entry, |display_name| {
+            Notification::CredentialChanged {
+                display_name,
+                changes,
+                changed_at: OffsetDateTime::UNIX_EPOCH + ct,
+            }
+        })
     }
 
     pub fn cancel_credential_update(
@@ -1012,21 +1156,22 @@ impl<'a> IdmServerCredUpdateTransaction<'a> {
         &self,
         cleartext: &str,
         related_inputs: &[&str],
+        min_length: usize,
     ) -> Result<(), PasswordQuality> {
         // password strength and badlisting is always global, rather than per-pw-policy.
         // pw-policy as check on the account is about requirements for mfa for example.
         //
 
-        // is the password at least 10 char?
-        if cleartext.len() < PW_MIN_LENGTH {
-            return Err(PasswordQuality::TooShort(PW_MIN_LENGTH));
+        // is the password at least the minimum length?
+        if cleartext.len() < min_length {
+            return Err(PasswordQuality::TooShort(min_length));
         }
 
         // does the password pass zxcvbn?
 
         let entropy = zxcvbn::zxcvbn(cleartext, related_inputs).map_err(|e| {
             admin_error!("zxcvbn check failure (password empty?) {:?}", e);
-            PasswordQuality::TooShort(PW_MIN_LENGTH)
+            PasswordQuality::TooShort(min_length)
         })?;
 
         // PW's should always be enforced as strong as possible.
@@ -1040,7 +1185,7 @@ impl<'a> IdmServerCredUpdateTransaction<'a> {
                 .map(|v| v.clone())
                 .map_err(|e| {
                     security_info!("zxcvbn returned no feedback when score < 3 -> {:?}", e);
-                    PasswordQuality::TooShort(PW_MIN_LENGTH)
+                    PasswordQuality::TooShort(min_length)
                 })?;
 
             security_info!(?feedback, "pw quality feedback");
@@ -1164,17 +1309,21 @@ impl<'a> IdmServerCredUpdateTransaction<'a> {
         })?;
         trace!(?session);
 
-        // Check pw quality (future - acc policy applies).
-        self.check_password_quality(pw, session.account.related_inputs().as_slice())
-            .map_err(|e| match e {
-                PasswordQuality::TooShort(sz) => {
-                    OperationError::PasswordQuality(vec![PasswordFeedback::TooShort(sz)])
-                }
-                PasswordQuality::BadListed => {
-                    OperationError::PasswordQuality(vec![PasswordFeedback::BadListed])
-                }
-                PasswordQuality::Feedback(feedback) => OperationError::PasswordQuality(feedback),
-            })?;
+        // Check pw quality, the account policy may require a longer password.
+        self.check_password_quality(
+            pw,
+            session.account.related_inputs().as_slice(),
+            session.account.account_policy.pw_min_length(),
+        )
+        .map_err(|e| match e {
+            PasswordQuality::TooShort(sz) => {
+                OperationError::PasswordQuality(vec![PasswordFeedback::TooShort(sz)])
+            }
+            PasswordQuality::BadListed => {
+                OperationError::PasswordQuality(vec![PasswordFeedback::BadListed])
+            }
+            PasswordQuality::Feedback(feedback) => OperationError::PasswordQuality(feedback),
+        })?;
 
         let ncred = match &session.primary {
             Some(primary) => {
@@ -1509,6 +1658,108 @@ impl<'a> IdmServerCredUpdateTransaction<'a> {
         Ok(session.deref().into())
     }
 
+    pub fn credential_devicekey_init(
+        &self,
+        cust: &CredentialUpdateSessionToken,
+        ct: Duration,
+    ) -> Result<CredentialUpdateSessionStatus, OperationError> {
+        let session_handle = self.get_current_session(cust, ct)?;
+        let mut session = session_handle.try_lock().map_err(|_| {
+            admin_error!("Session already locked, unable to proceed.");
+            OperationError::InvalidState
+        })?;
+        trace!(?session);
+
+        if !matches!(session.mfaregstate, MfaRegState::None) {
+            admin_info!("Invalid Devicekey Init state, another update is in progress");
+            return Err(OperationError::InvalidState);
+        }
+
+        // Devicekeys are only useful if we can trust where they came from, so without any
+        // configured cas we refuse to enrol them.
+        if session.attestation_ca_list.is_empty() {
+            admin_warn!("Unable to enrol devicekey, no attestation cas are configured");
+            return Err(OperationError::InvalidState);
+        }
+
+        let attestation_ca_list =
+            attestation_ca_list_from_b64(session.attestation_ca_list.iter().map(String::as_str))?;
+
+        let (ccr, dk_reg) = self
+            .webauthn
+            .start_devicekey_registration(
+                session.account.uuid,
+                &session.account.spn,
+                &session.account.displayname,
+                session.account.existing_credential_id_list(),
+                attestation_ca_list,
+                None,
+            )
+            .map_err(|e| {
+                error!(eclass=?e, emsg=%e, "Unable to start devicekey registration");
+                OperationError::Webauthn
+            })?;
+
+        session.mfaregstate = MfaRegState::DeviceKey(Box::new(ccr), dk_reg);
+        // Now that it's in the state, it'll be in the status when returned.
+        Ok(session.deref().into())
+    }
+
+    pub fn credential_devicekey_finish(
+        &self,
+        cust: &CredentialUpdateSessionToken,
+        ct: Duration,
+        label: String,
+        reg: &RegisterPublicKeyCredential,
+    ) -> Result<CredentialUpdateSessionStatus, OperationError> {
+        let session_handle = self.get_current_session(cust, ct)?;
+        let mut session = session_handle.try_lock().map_err(|_| {
+            admin_error!("Session already locked, unable to proceed.");
+            OperationError::InvalidState
+        })?;
+        trace!(?session);
+
+        match &session.mfaregstate {
+            MfaRegState::DeviceKey(_ccr, dk_reg) => {
+                // This checks the attestation against the cas we started the registration with.
+                let devicekey = self
+                    .webauthn
+                    .finish_devicekey_registration(reg, dk_reg)
+                    .map_err(|e| {
+                        error!(eclass=?e, emsg=%e, "Unable to finish devicekey registration");
+                        OperationError::Webauthn
+                    })?;
+                let dk_id = Uuid::new_v4();
+                session.devicekeys.insert(dk_id, (label, devicekey));
+
+                // The reg is done.
+                session.mfaregstate = MfaRegState::None;
+
+                Ok(session.deref().into())
+            }
+            _ => Err(OperationError::InvalidRequestState),
+        }
+    }
+
+    pub fn credential_devicekey_remove(
+        &self,
+        cust: &CredentialUpdateSessionToken,
+        ct: Duration,
+        uuid: Uuid,
+    ) -> Result<CredentialUpdateSessionStatus, OperationError> {
+        let session_handle = self.get_current_session(cust, ct)?;
+        let mut session = session_handle.try_lock().map_err(|_| {
+            admin_error!("Session already locked, unable to proceed.");
+            OperationError::InvalidState
+        })?;
+        trace!(?session);
+
+        // No-op if not present
+        session.devicekeys.remove(&uuid);
+
+        Ok(session.deref().into())
+    }
+
     pub fn credential_update_cancel_mfareg(
         &self,
         cust: &CredentialUpdateSessionToken,
@@ -1546,15 +1797,19 @@ impl<'a> IdmServerCredUpdateTransaction<'a> {
 mod tests {
     use std::time::Duration;
 
-    use kanidm_proto::v1::{AuthAllowed, AuthIssueSession, AuthMech, CredentialDetailType};
+    use base64::{engine::general_purpose, Engine as _};
+    use kanidm_proto::v1::{
+        AuthAllowed, AuthIssueSession, AuthMech, CredentialDetailType, PasswordFeedback,
+    };
     use uuid::uuid;
     use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+    use webauthn_authenticator_rs::softtoken::SoftToken;
     use webauthn_authenticator_rs::WebauthnAuthenticator;
 
     use super::{
-        CredentialUpdateSessionStatus, CredentialUpdateSessionToken, InitCredentialUpdateEvent,
-        InitCredentialUpdateIntentEvent, MfaRegStateStatus, MAXIMUM_CRED_UPDATE_TTL,
-        MAXIMUM_INTENT_TTL, MINIMUM_INTENT_TTL,
+        CredentialUpdateIntentToken, CredentialUpdateSessionStatus, CredentialUpdateSessionToken,
+        InitCredentialUpdateEvent, InitCredentialUpdateIntentEvent, MfaRegStateStatus,
+        MAXIMUM_CRED_UPDATE_TTL, MAXIMUM_INTENT_TTL, MINIMUM_INTENT_TTL,
     };
     use crate::credential::totp::Totp;
     use crate::event::CreateEvent;
@@ -1686,6 +1941,81 @@ mod tests {
         idms_prox_write.commit().expect("Failed to commit txn");
     }
 
+    #[idm_test]
+    async fn test_idm_credential_update_intent_send(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        let e1 = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("account")),
+            ("class", Value::new_class("person")),
+            ("name", Value::new_iname("testperson")),
+            ("uuid", Value::Uuid(TESTPERSON_UUID)),
+            ("description", Value::new_utf8s("testperson")),
+            ("displayname", Value::new_utf8s("testperson"))
+        );
+
+        let ce = CreateEvent::new_internal(vec![e1]);
+        let cr = idms_prox_write.qs_write.create(&ce);
+        assert!(cr.is_ok());
+
+        let idm_admin = idms_prox_write
+            .qs_write
+            .internal_search_uuid(UUID_IDM_ADMIN)
+            .expect("failed");
+
+        let event = InitCredentialUpdateIntentEvent::new_impersonate_entry(
+            idm_admin,
+            TESTPERSON_UUID,
+            MINIMUM_INTENT_TTL,
+        );
+
+        // Without a mail address there is nowhere to send it.
+        let cur = idms_prox_write.init_credential_update_intent_send(&event, ct);
+        assert!(matches!(cur, Err(OperationError::InvalidAccountState(_))));
+
+        let modlist = ModifyList::new_purge_and_set(
+            "mail",
+            Value::new_email_address_primary_s("testperson@example.com")
+                .expect("Invalid mail address"),
+        );
+        idms_prox_write
+            .qs_write
+            .internal_modify_uuid(TESTPERSON_UUID, &modlist)
+            .expect("Failed to set mail");
+
+        idms_prox_write
+            .init_credential_update_intent_send(&event, ct)
+            .expect("Failed to send intent token");
+
+        let pending = idms_prox_write
+            .pending_messages(true, ct)
+            .expect("Failed to list pending messages");
+        assert!(pending.len() == 1);
+        assert!(pending[0].mail_destination.as_deref() == Some("testperson@example.com"));
+
+        // The token in the message can be exchanged for a session.
+        let testperson = idms_prox_write
+            .qs_write
+            .internal_search_uuid(TESTPERSON_UUID)
+            .expect("failed");
+        let intent_id = testperson
+            .get_ava_as_intenttokens("credential_update_intent_token")
+            .and_then(|tokens| tokens.keys().next().cloned())
+            .expect("No intent token was created");
+        assert!(pending[0].message.body.contains(&intent_id));
+
+        let cur = idms_prox_write
+            .exchange_intent_credential_update(CredentialUpdateIntentToken { intent_id }, ct);
+        assert!(cur.is_ok());
+
+        idms_prox_write.commit().expect("Failed to commit txn");
+    }
+
     async fn setup_test_session(
         idms: &IdmServer,
         ct: Duration,
@@ -1992,6 +2322,73 @@ mod tests {
         }
     }
 
+    async fn check_testperson_devicekey(
+        idms: &IdmServer,
+        idms_delayed: &mut IdmServerDelayed,
+        wa: &mut WebauthnAuthenticator<SoftToken>,
+        origin: Url,
+        ct: Duration,
+    ) -> Option<String> {
+        let mut idms_auth = idms.auth().await;
+
+        let auth_init = AuthEvent::named_init("testperson");
+
+        let r1 = idms_auth.auth(&auth_init, ct, Source::Internal).await;
+        let ar = r1.unwrap();
+        let AuthResult { sessionid, state } = ar;
+
+        if !matches!(state, AuthState::Choose(_)) {
+            debug!("Can't proceed - {:?}", state);
+            return None;
+        };
+
+        let auth_begin = AuthEvent::begin_mech(sessionid, AuthMech::DeviceKey);
+
+        let r2 = idms_auth.auth(&auth_begin, ct, Source::Internal).await;
+        let ar = r2.unwrap();
+        let AuthResult { sessionid, state } = ar;
+
+        trace!(?state);
+
+        let rcr = match state {
+            AuthState::Continue(mut allowed) => match allowed.pop() {
+                Some(AuthAllowed::DeviceKey(rcr)) => rcr,
+                _ => unreachable!(),
+            },
+            _ => return None,
+        };
+
+        let resp = wa
+            .do_authentication(origin, rcr)
+            .expect("failed to use softtoken to authenticate");
+
+        let devicekey_step = AuthEvent::cred_step_devicekey(sessionid, resp);
+
+        let r3 = idms_auth.auth(&devicekey_step, ct, Source::Internal).await;
+        debug!("r3 ==> {:?}", r3);
+        idms_auth.commit().expect("Must not fail");
+
+        match r3 {
+            Ok(AuthResult {
+                sessionid: _,
+                state: AuthState::Success(token, AuthIssueSession::Token),
+            }) => {
+                // Process the webauthn update
+                let da = idms_delayed.try_recv().expect("invalid");
+                assert!(matches!(da, DelayedAction::WebauthnCounterIncrement(_)));
+                let r = idms.delayed_action(ct, da).await;
+                assert!(r.is_ok());
+
+                // Process the auth session
+                let da = idms_delayed.try_recv().expect("invalid");
+                assert!(matches!(da, DelayedAction::AuthSessionRecord(_)));
+
+                Some(token)
+            }
+            _ => None,
+        }
+    }
+
     #[idm_test]
     async fn test_idm_credential_update_session_cleanup(
         idms: &IdmServer,
@@ -2084,6 +2481,40 @@ mod tests {
             .is_none());
     }
 
+    #[idm_test]
+    async fn test_idm_credential_update_notification(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let test_pw = "fo3EitierohF9AelaNgiem0Ei6vup4equo1Oogeevaetehah8Tobeengae3Ci0ooh0uki";
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+
+        let (cust, _) = setup_test_session(idms, ct).await;
+        let cutxn = idms.cred_update_transaction().await;
+        cutxn
+            .credential_primary_set_password(&cust, ct, test_pw)
+            .expect("Failed to update the primary cred password");
+        drop(cutxn);
+        commit_session(idms, ct, cust).await;
+
+        // Committing without changes doesn't notify.
+        let (cust, _) = renew_test_session(idms, ct).await;
+        commit_session(idms, ct, cust).await;
+
+        let mut idms_prox_read = idms.proxy_read().await;
+        let ident = idms_prox_read
+            .qs_read
+            .internal_search_uuid(TESTPERSON_UUID)
+            .map(Identity::from_impersonate_entry_readonly)
+            .expect("Failed to impersonate identity");
+
+        let notifications = idms_prox_read
+            .list_notifications(&ident)
+            .expect("Failed to list notifications");
+        assert!(notifications.len() == 1);
+        assert!(notifications[0].body.contains("A password was set"));
+    }
+
     // Test set of primary account password
     //    - fail pw quality checks etc
     //    - set correctly.
@@ -2182,6 +2613,79 @@ mod tests {
             .is_some());
     }
 
+    #[idm_test]
+    async fn test_idm_credential_update_account_policy(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let test_pw = "fo3EitierohF9AelaNgiem0Ei6vup4equo1Oogeevaetehah8Tobeengae3Ci0ooh0uki";
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+
+        let (cust, _) = setup_test_session(idms, ct).await;
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        idms_prox_write
+            .cancel_credential_update(&cust, ct)
+            .expect("Failed to cancel credential update");
+
+        // Put testperson in a group with a strict policy.
+        let e: Entry<EntryInit, EntryNew> = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("group")),
+            ("class", Value::new_class("account_policy")),
+            ("name", Value::new_iname("strict_policy")),
+            ("member", Value::Refer(TESTPERSON_UUID)),
+            ("auth_password_minimum_length", Value::new_uint32(80)),
+            ("credential_type_minimum", Value::new_iutf8("mfa"))
+        );
+        assert!(idms_prox_write.qs_write.internal_create(vec![e]).is_ok());
+        idms_prox_write.commit().expect("Failed to commit txn");
+
+        let (cust, c_status) = renew_test_session(idms, ct).await;
+        // There are no credentials yet, so the account couldn't authenticate at all.
+        assert!(!c_status.can_commit);
+
+        let cutxn = idms.cred_update_transaction().await;
+
+        // The password is good, but not long enough for the policy.
+        let err = cutxn
+            .credential_primary_set_password(&cust, ct, test_pw)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            OperationError::PasswordQuality(feedback)
+                if matches!(feedback.as_slice(), [PasswordFeedback::TooShort(80)])
+        ));
+
+        let test_pw = format!("{test_pw}{test_pw}");
+        let c_status = cutxn
+            .credential_primary_set_password(&cust, ct, &test_pw)
+            .expect("Failed to update the primary cred password");
+
+        // A password alone is not enough.
+        assert!(!c_status.can_commit);
+
+        let c_status = cutxn
+            .credential_primary_init_totp(&cust, ct)
+            .expect("Failed to update the primary cred password");
+        let totp_token: Totp = match c_status.mfaregstate {
+            MfaRegStateStatus::TotpCheck(secret) => Some(secret.try_into().unwrap()),
+            _ => None,
+        }
+        .expect("Unable to retrieve totp token, invalid state.");
+        let chal = totp_token
+            .do_totp_duration_from_epoch(&ct)
+            .expect("Failed to perform totp step");
+        let c_status = cutxn
+            .credential_primary_check_totp(&cust, ct, chal, "totp")
+            .expect("Failed to update the primary cred password");
+
+        // With mfa we meet the policy.
+        assert!(c_status.can_commit);
+
+        drop(cutxn);
+        commit_session(idms, ct, cust).await;
+    }
+
     // Check sha1 totp.
     #[idm_test]
     async fn test_idm_credential_update_onboarding_create_new_mfa_totp_sha1(
@@ -2536,12 +3040,103 @@ mod tests {
         );
     }
 
-    // W_ policy, assert can't remove MFA if it's enforced.
+    #[idm_test]
+    async fn test_idm_credential_update_devicekey_requires_attestation_ca(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+
+        let (cust, _) = setup_test_session(idms, ct).await;
+        let cutxn = idms.cred_update_transaction().await;
+
+        // No attestation cas are configured on the domain, so we can't trust any device.
+        let err = cutxn
+            .credential_devicekey_init(&cust, ct)
+            .expect_err("Devicekey registration should be refused");
+        assert!(err == OperationError::InvalidState);
+
+        // The session is still usable afterwards.
+        let c_status = cutxn
+            .credential_update_status(&cust, ct)
+            .expect("Failed to get the current session status.");
+        assert!(matches!(c_status.mfaregstate, MfaRegStateStatus::None));
+        assert!(c_status.devicekeys.is_empty());
+    }
+
+    #[idm_test]
+    async fn test_idm_credential_update_devicekey_attested(
+        idms: &IdmServer,
+        idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+
+        // A soft token, with the ca that signs its attestations.
+        let (soft_token, ca_root) = SoftToken::new().expect("Failed to create soft token");
+        let mut wa = WebauthnAuthenticator::new(soft_token);
+        let ca_b64 = ca_root
+            .to_der()
+            .map(|der| general_purpose::STANDARD.encode(der))
+            .expect("Failed to encode attestation ca");
+
+        let (cust, _) = setup_test_session(idms, ct).await;
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        idms_prox_write
+            .cancel_credential_update(&cust, ct)
+            .expect("Failed to cancel credential update");
+
+        // Trust the soft token's ca on the domain.
+        let modlist =
+            ModifyList::new_purge_and_set("webauthn_attestation_ca_list", Value::new_utf8(ca_b64));
+        assert!(idms_prox_write
+            .qs_write
+            .internal_modify_uuid(UUID_DOMAIN_INFO, &modlist)
+            .is_ok());
+        idms_prox_write.commit().expect("Failed to commit txn");
+
+        let (cust, _) = renew_test_session(idms, ct).await;
+        let cutxn = idms.cred_update_transaction().await;
+        let origin = cutxn.get_origin().clone();
+
+        let c_status = cutxn
+            .credential_devicekey_init(&cust, ct)
+            .expect("Failed to initiate devicekey registration");
+
+        let devicekey_chal = match c_status.mfaregstate {
+            MfaRegStateStatus::DeviceKey(c) => Some(c),
+            _ => None,
+        }
+        .expect("Unable to access devicekey challenge, invalid state");
+
+        let devicekey_resp = wa
+            .do_registration(origin.clone(), devicekey_chal)
+            .expect("Failed to create soft devicekey");
+
+        // The attestation chains to the configured ca, so it's accepted.
+        let c_status = cutxn
+            .credential_devicekey_finish(&cust, ct, "softtoken".to_string(), &devicekey_resp)
+            .expect("Failed to finish devicekey registration");
+
+        assert!(matches!(c_status.mfaregstate, MfaRegStateStatus::None));
+        assert!(c_status.devicekeys.len() == 1);
+
+        drop(cutxn);
+        commit_session(idms, ct, cust).await;
+
+        // And the enrolled devicekey can authenticate.
+        assert!(
+            check_testperson_devicekey(idms, idms_delayed, &mut wa, origin, ct)
+                .await
+                .is_some()
+        );
+    }
 
     // enroll trusted device
     // remove trusted device.
     // trusted device flag changes?
 
+    // W_ policy, assert can't remove MFA if it's enforced.
+
     // Any policy checks we care about?
 
     // Others in the future
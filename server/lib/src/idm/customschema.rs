@@ -0,0 +1,763 @@
+//! Administrators may extend the schema with their own attributes and classes. These must be
+//! named with a reserved prefix so that they can never collide with schema that is added by a
+//! later release. Changes to custom schema are checked against the entries that already use it,
+//! so that a change can't leave entries that no longer validate. Attributes that are still in use
+//! may only be deleted by also purging their values.
+
+use hashbrown::{HashMap, HashSet};
+use kanidm_proto::constants::CUSTOM_SCHEMA_PREFIX;
+use kanidm_proto::internal::{
+    SchemaAttributeCreateRequest, SchemaAttributeUpdateRequest, SchemaClassCreateRequest,
+    SchemaClassUpdateRequest,
+};
+
+use crate::entry::{Entry, EntryCommitted, EntrySealed};
+use crate::idm::server::IdmServerProxyWriteTransaction;
+use crate::prelude::*;
+use crate::schema::{SchemaClass, SchemaTransaction};
+
+fn schema_refused(msg: String) -> OperationError {
+    request_error!("{}", msg);
+    OperationError::InvalidSchemaState(msg)
+}
+
+/// Check that a name is in the custom schema namespace.
+fn check_custom_name(name: &str) -> Result<(), OperationError> {
+    let valid = name
+        .strip_prefix(CUSTOM_SCHEMA_PREFIX)
+        .map(|rest| {
+            !rest.is_empty()
+                && rest
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        })
+        .unwrap_or(false);
+
+    if valid {
+        Ok(())
+    } else {
+        Err(schema_refused(format!(
+            "'{}' is not a valid custom schema name - names must start with {} and contain only lowercase letters, digits and _",
+            name, CUSTOM_SCHEMA_PREFIX
+        )))
+    }
+}
+
+fn parse_indexes(index: &[String]) -> Result<Vec<Value>, OperationError> {
+    index
+        .iter()
+        .map(|i| {
+            Value::new_indexes(i)
+                .ok_or_else(|| schema_refused(format!("'{}' is not a valid index type", i)))
+        })
+        .collect()
+}
+
+/// If one of the other classes of this entry allows it to have the attribute.
+fn allowed_by_other_class(
+    classes: &HashMap<AttrString, SchemaClass>,
+    entry: &Entry<EntrySealed, EntryCommitted>,
+    exclude: &str,
+    attr: &str,
+) -> bool {
+    entry
+        .get_ava_as_iutf8_iter("class")
+        .map(|mut iter| {
+            iter.any(|cname| {
+                cname == "extensibleobject"
+                    || (cname != exclude
+                        && classes
+                            .get(cname)
+                            .map(|c| {
+                                c.systemmay
+                                    .iter()
+                                    .chain(c.may.iter())
+                                    .chain(c.systemmust.iter())
+                                    .chain(c.must.iter())
+                                    .any(|a| a.as_str() == attr)
+                            })
+                            .unwrap_or(false))
+            })
+        })
+        .unwrap_or(false)
+}
+
+impl<'a> IdmServerProxyWriteTransaction<'a> {
+    fn check_attributes_exist(&self, attrs: &[String]) -> Result<(), OperationError> {
+        let schema_attributes = self.qs_write.get_schema().get_attributes();
+        match attrs
+            .iter()
+            .find(|a| !schema_attributes.contains_key(a.as_str()))
+        {
+            Some(a) => Err(schema_refused(format!("attribute '{}' does not exist", a))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn schema_attribute_create(
+        &mut self,
+        ident: &Identity,
+        req: &SchemaAttributeCreateRequest,
+    ) -> Result<(), OperationError> {
+        check_custom_name(&req.name)?;
+
+        if self
+            .qs_write
+            .get_schema()
+            .get_attributes()
+            .contains_key(req.name.as_str())
+        {
+            return Err(schema_refused(format!(
+                "attribute '{}' already exists",
+                req.name
+            )));
+        }
+
+        let syntax = Value::new_syntaxs(&req.syntax)
+            .ok_or_else(|| schema_refused(format!("'{}' is not a valid syntax", req.syntax)))?;
+        let indexes = parse_indexes(&req.index)?;
+
+        let mut entry = entry_init!(
+            ("class", CLASS_OBJECT.clone()),
+            ("class", Value::new_class("attributetype")),
+            ("attributename", Value::new_iutf8(&req.name)),
+            ("description", Value::new_utf8s(&req.description)),
+            ("multivalue", Value::new_bool(req.multivalue)),
+            ("unique", Value::new_bool(req.unique)),
+            ("syntax", syntax)
+        );
+        indexes.into_iter().for_each(|i| entry.add_ava("index", i));
+
+        let ce = CreateEvent {
+            ident: ident.clone(),
+            entries: vec![entry],
+        };
+        self.qs_write.create(&ce)
+    }
+
+    pub fn schema_attribute_update(
+        &mut self,
+        ident: &Identity,
+        name: &str,
+        req: &SchemaAttributeUpdateRequest,
+    ) -> Result<(), OperationError> {
+        check_custom_name(name)?;
+
+        let current = self
+            .qs_write
+            .get_schema()
+            .get_attributes()
+            .get(name)
+            .cloned()
+            .ok_or(OperationError::NoMatchingEntries)?;
+
+        let mut modlist = ModifyList::new();
+
+        if let Some(description) = &req.description {
+            modlist.push_mod(m_purge("description"));
+            modlist.push_mod(m_pres("description", &Value::new_utf8s(description)));
+        }
+
+        let to_single_value = req.multivalue == Some(false) && current.multivalue;
+        if let Some(multivalue) = req.multivalue {
+            modlist.push_mod(m_purge("multivalue"));
+            modlist.push_mod(m_pres("multivalue", &Value::new_bool(multivalue)));
+        }
+
+        let to_unique = req.unique == Some(true) && !current.unique;
+        if let Some(unique) = req.unique {
+            modlist.push_mod(m_purge("unique"));
+            modlist.push_mod(m_pres("unique", &Value::new_bool(unique)));
+        }
+
+        if let Some(index) = &req.index {
+            // Changing the indexes causes the entries to be reindexed as the change commits.
+            modlist.push_mod(m_purge("index"));
+            parse_indexes(index)?
+                .iter()
+                .for_each(|i| modlist.push_mod(m_pres("index", i)));
+        }
+
+        if modlist.is_empty() {
+            return Ok(());
+        }
+
+        // Modify as the caller first, so that nothing about the entries using the attribute
+        // is revealed unless access controls allow the change. The schema is only reloaded
+        // as the transaction commits, so a refusal below aborts the change.
+        self.qs_write.impersonate_modify(
+            // Filter as executed
+            &filter!(f_eq("attributename", PartialValue::new_iutf8(name))),
+            // Filter as intended (acp)
+            &filter_all!(f_eq("attributename", PartialValue::new_iutf8(name))),
+            &modlist,
+            ident,
+        )?;
+
+        if !to_single_value && !to_unique {
+            return Ok(());
+        }
+
+        let entries = self.qs_write.internal_search(filter_all!(f_pres(name)))?;
+
+        if to_single_value
+            && entries
+                .iter()
+                .any(|e| e.get_ava_set(name).map(|vs| vs.len() > 1).unwrap_or(false))
+        {
+            return Err(schema_refused(format!(
+                "attribute '{}' can not be made single valued while entries have more than one value",
+                name
+            )));
+        }
+
+        if to_unique {
+            let mut seen = HashSet::new();
+            let duplicated = entries.iter().any(|e| {
+                e.get_ava_set(name)
+                    .map(|vs| {
+                        vs.generate_idx_eq_keys()
+                            .into_iter()
+                            .any(|k| !seen.insert(k))
+                    })
+                    .unwrap_or(false)
+            });
+            if duplicated {
+                return Err(schema_refused(format!(
+                    "attribute '{}' can not be made unique while values are duplicated",
+                    name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delete a custom attribute. If entries or classes still use the attribute this is refused,
+    /// unless purge is set, in which case the attribute is removed from them as well.
+    pub fn schema_attribute_delete(
+        &mut self,
+        ident: &Identity,
+        name: &str,
+        purge: bool,
+    ) -> Result<(), OperationError> {
+        check_custom_name(name)?;
+
+        let schema = self.qs_write.get_schema();
+        if !schema.get_attributes().contains_key(name) {
+            return Err(OperationError::NoMatchingEntries);
+        }
+
+        let mut referencing_classes: Vec<String> = schema
+            .get_classes()
+            .values()
+            .filter(|c| {
+                c.may
+                    .iter()
+                    .chain(c.must.iter())
+                    .any(|a| a.as_str() == name)
+            })
+            .map(|c| c.name.to_string())
+            .collect();
+        referencing_classes.sort_unstable();
+
+        // Delete as the caller first, so that access controls are checked before the usage of
+        // the attribute is revealed or anything is purged. A refusal aborts the transaction.
+        let de = DeleteEvent::from_parts(
+            ident.clone(),
+            &filter!(f_eq("attributename", PartialValue::new_iutf8(name))),
+            &mut self.qs_write,
+        )?;
+        self.qs_write.delete(&de)?;
+
+        // Recycled entries are included, as they could otherwise be revived with a value
+        // that no longer has a schema.
+        let in_use = self
+            .qs_write
+            .internal_search(filter_all!(f_pres(name)))?
+            .len();
+
+        if !purge && (in_use > 0 || !referencing_classes.is_empty()) {
+            return Err(schema_refused(format!(
+                "attribute '{}' is in use by {} entries and the classes [{}] - it must be purged to be deleted",
+                name,
+                in_use,
+                referencing_classes.join(", ")
+            )));
+        }
+
+        if in_use > 0 {
+            security_info!(%name, %in_use, "Purging values of deleted custom attribute");
+            self.qs_write
+                .internal_modify(&filter_all!(f_pres(name)), &ModifyList::new_purge(name))?;
+        }
+
+        referencing_classes.iter().try_for_each(|cname| {
+            self.qs_write.internal_modify(
+                &filter!(f_eq("classname", PartialValue::new_iutf8(cname))),
+                &ModifyList::new_list(vec![
+                    m_remove("may", &PartialValue::new_iutf8(name)),
+                    m_remove("must", &PartialValue::new_iutf8(name)),
+                ]),
+            )
+        })
+    }
+
+    pub fn schema_class_create(
+        &mut self,
+        ident: &Identity,
+        req: &SchemaClassCreateRequest,
+    ) -> Result<(), OperationError> {
+        check_custom_name(&req.name)?;
+
+        if self
+            .qs_write
+            .get_schema()
+            .get_classes()
+            .contains_key(req.name.as_str())
+        {
+            return Err(schema_refused(format!(
+                "class '{}' already exists",
+                req.name
+            )));
+        }
+
+        self.check_attributes_exist(&req.may)?;
+        self.check_attributes_exist(&req.must)?;
+
+        let mut entry = entry_init!(
+            ("class", CLASS_OBJECT.clone()),
+            ("class", Value::new_class("classtype")),
+            ("classname", Value::new_iutf8(&req.name)),
+            ("description", Value::new_utf8s(&req.description))
+        );
+        req.may
+            .iter()
+            .for_each(|a| entry.add_ava("may", Value::new_iutf8(a)));
+        req.must
+            .iter()
+            .for_each(|a| entry.add_ava("must", Value::new_iutf8(a)));
+
+        let ce = CreateEvent {
+            ident: ident.clone(),
+            entries: vec![entry],
+        };
+        self.qs_write.create(&ce)
+    }
+
+    pub fn schema_class_update(
+        &mut self,
+        ident: &Identity,
+        name: &str,
+        req: &SchemaClassUpdateRequest,
+    ) -> Result<(), OperationError> {
+        check_custom_name(name)?;
+
+        let current = self
+            .qs_write
+            .get_schema()
+            .get_classes()
+            .get(name)
+            .cloned()
+            .ok_or(OperationError::NoMatchingEntries)?;
+
+        let mut modlist = ModifyList::new();
+
+        if let Some(description) = &req.description {
+            modlist.push_mod(m_purge("description"));
+            modlist.push_mod(m_pres("description", &Value::new_utf8s(description)));
+        }
+
+        let attr_change = if req.may.is_some() || req.must.is_some() {
+            let may: Vec<String> = req
+                .may
+                .clone()
+                .unwrap_or_else(|| current.may.iter().map(|a| a.to_string()).collect());
+            let must: Vec<String> = req
+                .must
+                .clone()
+                .unwrap_or_else(|| current.must.iter().map(|a| a.to_string()).collect());
+
+            self.check_attributes_exist(&may)?;
+            self.check_attributes_exist(&must)?;
+
+            let removed: Vec<String> = current
+                .may
+                .iter()
+                .chain(current.must.iter())
+                .map(|a| a.to_string())
+                .filter(|a| !may.iter().chain(must.iter()).any(|n| n == a))
+                .collect();
+
+            modlist.push_mod(m_purge("may"));
+            may.iter()
+                .for_each(|a| modlist.push_mod(m_pres("may", &Value::new_iutf8(a))));
+            modlist.push_mod(m_purge("must"));
+            must.iter()
+                .for_each(|a| modlist.push_mod(m_pres("must", &Value::new_iutf8(a))));
+
+            Some((removed, must))
+        } else {
+            None
+        };
+
+        if modlist.is_empty() {
+            return Ok(());
+        }
+
+        // Modify as the caller first, so that nothing about the entries of the class is
+        // revealed unless access controls allow the change. A refusal below aborts it.
+        self.qs_write.impersonate_modify(
+            // Filter as executed
+            &filter!(f_eq("classname", PartialValue::new_iutf8(name))),
+            // Filter as intended (acp)
+            &filter_all!(f_eq("classname", PartialValue::new_iutf8(name))),
+            &modlist,
+            ident,
+        )?;
+
+        let (removed, must) = match attr_change {
+            Some(change) => change,
+            None => return Ok(()),
+        };
+
+        let entries = self
+            .qs_write
+            .internal_search(filter_all!(f_eq("class", PartialValue::new_class(name))))?;
+        // The schema of this transaction is only reloaded as it commits, so this is still the
+        // definition of the classes from before the change.
+        let classes = self.qs_write.get_schema().get_classes();
+
+        // Entries of this class must still be valid afterwards.
+        if let Some(attr) = removed.iter().find(|attr| {
+            entries
+                .iter()
+                .any(|e| e.attribute_pres(attr) && !allowed_by_other_class(classes, e, name, attr))
+        }) {
+            return Err(schema_refused(format!(
+                "attribute '{}' can not be removed from class '{}' while entries of the class have it",
+                attr, name
+            )));
+        }
+
+        if let Some(attr) = must
+            .iter()
+            .find(|attr| entries.iter().any(|e| !e.attribute_pres(attr)))
+        {
+            return Err(schema_refused(format!(
+                "attribute '{}' can not be required by class '{}' while entries of the class do not have it",
+                attr, name
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Delete a custom class. This is refused while any entry has the class.
+    pub fn schema_class_delete(
+        &mut self,
+        ident: &Identity,
+        name: &str,
+    ) -> Result<(), OperationError> {
+        check_custom_name(name)?;
+
+        if !self.qs_write.get_schema().get_classes().contains_key(name) {
+            return Err(OperationError::NoMatchingEntries);
+        }
+
+        // Delete as the caller first, so the usage of the class is only revealed to those
+        // allowed to delete it. A refusal aborts the transaction.
+        let de = DeleteEvent::from_parts(
+            ident.clone(),
+            &filter!(f_eq("classname", PartialValue::new_iutf8(name))),
+            &mut self.qs_write,
+        )?;
+        self.qs_write.delete(&de)?;
+
+        let in_use = self
+            .qs_write
+            .internal_search(filter_all!(f_eq("class", PartialValue::new_class(name))))?
+            .len();
+        if in_use > 0 {
+            return Err(schema_refused(format!(
+                "class '{}' is in use by {} entries and can not be deleted",
+                name, in_use
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kanidm_proto::internal::{
+        SchemaAttributeCreateRequest, SchemaAttributeUpdateRequest, SchemaClassCreateRequest,
+        SchemaClassUpdateRequest,
+    };
+
+    use crate::prelude::*;
+    use crate::schema::SchemaTransaction;
+
+    fn attribute(name: &str, index: &[&str]) -> SchemaAttributeCreateRequest {
+        SchemaAttributeCreateRequest {
+            name: name.to_string(),
+            description: "Test Attribute".to_string(),
+            syntax: "UTF8STRING".to_string(),
+            multivalue: false,
+            unique: false,
+            index: index.iter().map(|i| i.to_string()).collect(),
+        }
+    }
+
+    fn class(name: &str, may: &[&str]) -> SchemaClassCreateRequest {
+        SchemaClassCreateRequest {
+            name: name.to_string(),
+            description: "Test Class".to_string(),
+            may: may.iter().map(|a| a.to_string()).collect(),
+            must: Vec::new(),
+        }
+    }
+
+    #[idm_test]
+    async fn test_idm_custom_schema_lifecycle(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let admin = idms_prox_write
+            .qs_write
+            .internal_search_uuid(UUID_ADMIN)
+            .map(Identity::from_impersonate_entry_readwrite)
+            .expect("Unable to get admin");
+
+        // Custom schema must be in the reserved namespace.
+        assert!(
+            idms_prox_write.schema_attribute_create(&admin, &attribute("badge", &[]))
+                == Err(OperationError::InvalidSchemaState(String::new()))
+        );
+        assert!(idms_prox_write
+            .schema_attribute_create(&admin, &attribute("ext_Badge", &[]))
+            .is_err());
+
+        idms_prox_write
+            .schema_attribute_create(&admin, &attribute("ext_badge", &[]))
+            .expect("Failed to create attribute");
+        assert!(idms_prox_write.commit().is_ok());
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        // Classes can only refer to attributes that exist.
+        assert!(idms_prox_write
+            .schema_class_create(&admin, &class("ext_badged", &["ext_missing"]))
+            .is_err());
+        idms_prox_write
+            .schema_class_create(&admin, &class("ext_badged", &["ext_badge"]))
+            .expect("Failed to create class");
+        assert!(idms_prox_write.commit().is_ok());
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        idms_prox_write
+            .qs_write
+            .internal_modify_uuid(
+                UUID_ADMIN,
+                &ModifyList::new_list(vec![
+                    m_pres("class", &Value::new_class("ext_badged")),
+                    m_pres("ext_badge", &Value::new_utf8s("1234")),
+                ]),
+            )
+            .expect("Failed to modify admin");
+        assert!(idms_prox_write.commit().is_ok());
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        // Entries of the class don't all have the attribute, so it can't be required.
+        idms_prox_write
+            .qs_write
+            .internal_modify_uuid(
+                UUID_IDM_ADMIN,
+                &ModifyList::new_list(vec![m_pres("class", &Value::new_class("ext_badged"))]),
+            )
+            .expect("Failed to modify idm_admin");
+        assert!(idms_prox_write.commit().is_ok());
+
+        // A refused change leaves the transaction to be aborted, so each is made in its own.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        assert!(idms_prox_write
+            .schema_class_update(
+                &admin,
+                "ext_badged",
+                &SchemaClassUpdateRequest {
+                    must: Some(vec!["ext_badge".to_string()]),
+                    ..Default::default()
+                }
+            )
+            .is_err());
+        drop(idms_prox_write);
+
+        // Nor removed from the class while it's in use.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        assert!(idms_prox_write
+            .schema_class_update(
+                &admin,
+                "ext_badged",
+                &SchemaClassUpdateRequest {
+                    may: Some(Vec::new()),
+                    ..Default::default()
+                }
+            )
+            .is_err());
+        drop(idms_prox_write);
+
+        // Both the class and attribute are in use.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        assert!(idms_prox_write
+            .schema_class_delete(&admin, "ext_badged")
+            .is_err());
+        drop(idms_prox_write);
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        assert!(idms_prox_write
+            .schema_attribute_delete(&admin, "ext_badge", false)
+            .is_err());
+        drop(idms_prox_write);
+
+        // Without access to the schema, the usage of the attribute and class isn't revealed.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let anon = idms_prox_write
+            .qs_write
+            .internal_search_uuid(UUID_ANONYMOUS)
+            .map(Identity::from_impersonate_entry_readwrite)
+            .expect("Unable to get anonymous");
+        let r = idms_prox_write.schema_attribute_delete(&anon, "ext_badge", false);
+        assert!(r.is_err() && !matches!(r, Err(OperationError::InvalidSchemaState(_))));
+        let r = idms_prox_write.schema_class_delete(&anon, "ext_badged");
+        assert!(r.is_err() && !matches!(r, Err(OperationError::InvalidSchemaState(_))));
+        let r = idms_prox_write.schema_attribute_update(
+            &anon,
+            "ext_badge",
+            &SchemaAttributeUpdateRequest {
+                unique: Some(true),
+                ..Default::default()
+            },
+        );
+        assert!(r.is_err() && !matches!(r, Err(OperationError::InvalidSchemaState(_))));
+        drop(idms_prox_write);
+
+        // Purging removes the attribute from the entries and classes that used it.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        idms_prox_write
+            .schema_attribute_delete(&admin, "ext_badge", true)
+            .expect("Failed to purge attribute");
+        assert!(idms_prox_write.commit().is_ok());
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let admin_entry = idms_prox_write
+            .qs_write
+            .internal_search_uuid(UUID_ADMIN)
+            .expect("Unable to get admin");
+        assert!(!admin_entry.attribute_pres("ext_badge"));
+        let schema = idms_prox_write.qs_write.get_schema();
+        assert!(!schema.get_attributes().contains_key("ext_badge"));
+        assert!(schema
+            .get_classes()
+            .get("ext_badged")
+            .map(|c| c.may.is_empty())
+            .unwrap_or(false));
+        assert!(idms_prox_write.commit().is_ok());
+    }
+
+    #[idm_test]
+    async fn test_idm_custom_schema_index_change(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let admin = idms_prox_write
+            .qs_write
+            .internal_search_uuid(UUID_ADMIN)
+            .map(Identity::from_impersonate_entry_readwrite)
+            .expect("Unable to get admin");
+
+        idms_prox_write
+            .schema_attribute_create(&admin, &attribute("ext_code", &[]))
+            .expect("Failed to create attribute");
+        idms_prox_write
+            .schema_class_create(&admin, &class("ext_coded", &["ext_code"]))
+            .expect("Failed to create class");
+        assert!(idms_prox_write.commit().is_ok());
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        idms_prox_write
+            .qs_write
+            .internal_modify_uuid(
+                UUID_ADMIN,
+                &ModifyList::new_list(vec![
+                    m_pres("class", &Value::new_class("ext_coded")),
+                    m_pres("ext_code", &Value::new_utf8s("abc")),
+                ]),
+            )
+            .expect("Failed to modify admin");
+        assert!(idms_prox_write.commit().is_ok());
+
+        // Adding an index reindexes the existing values as the change commits.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        idms_prox_write
+            .schema_attribute_update(
+                &admin,
+                "ext_code",
+                &SchemaAttributeUpdateRequest {
+                    index: Some(vec!["EQUALITY".to_string()]),
+                    ..Default::default()
+                },
+            )
+            .expect("Failed to update attribute");
+        assert!(idms_prox_write.commit().is_ok());
+
+        let mut idms_prox_read = idms.proxy_read().await;
+        let be_txn = idms_prox_read.qs_read.get_be_txn();
+        assert!(be_txn
+            .list_indexes()
+            .expect("Failed to list indexes")
+            .contains(&"idx_eq_ext_code".to_string()));
+        assert!(be_txn
+            .list_index_content("idx_eq_ext_code")
+            .expect("Failed to list index content")
+            .iter()
+            .any(|(k, _)| k == "abc"));
+
+        let entries = idms_prox_read
+            .qs_read
+            .internal_search(filter!(f_eq("ext_code", PartialValue::new_utf8s("abc"))))
+            .expect("Failed to search");
+        assert!(entries.len() == 1);
+        drop(idms_prox_read);
+
+        // Removing the index drops only that index.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        idms_prox_write
+            .schema_attribute_update(
+                &admin,
+                "ext_code",
+                &SchemaAttributeUpdateRequest {
+                    index: Some(Vec::new()),
+                    ..Default::default()
+                },
+            )
+            .expect("Failed to update attribute");
+        assert!(idms_prox_write.commit().is_ok());
+
+        let mut idms_prox_read = idms.proxy_read().await;
+        let be_txn = idms_prox_read.qs_read.get_be_txn();
+        assert!(!be_txn
+            .list_indexes()
+            .expect("Failed to list indexes")
+            .contains(&"idx_eq_ext_code".to_string()));
+        assert!(be_txn
+            .list_index_content("idx_eq_name")
+            .expect("Failed to list index content")
+            .iter()
+            .any(|(k, _)| k == "admin"));
+        assert!(be_txn.verify().is_empty());
+    }
+}
@@ -62,4 +62,6 @@ pub struct AuthSessionRecord {
     pub issued_at: OffsetDateTime,
     pub issued_by: IdentityId,
     pub scope: SessionScope,
+    /// Where the session was created from.
+    pub source: Source,
 }
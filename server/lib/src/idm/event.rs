@@ -391,6 +391,14 @@ impl AuthEventStep {
             cred: AuthCredential::Passkey(Box::new(passkey_response)),
         })
     }
+
+    #[cfg(test)]
+    pub fn cred_step_devicekey(sid: Uuid, devicekey_response: PublicKeyCredential) -> Self {
+        AuthEventStep::Cred(AuthEventStepCred {
+            sessionid: sid,
+            cred: AuthCredential::DeviceKey(Box::new(devicekey_response)),
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -471,6 +479,14 @@ impl AuthEvent {
             step: AuthEventStep::cred_step_passkey(sid, passkey_response),
         }
     }
+
+    #[cfg(test)]
+    pub fn cred_step_devicekey(sid: Uuid, devicekey_response: PublicKeyCredential) -> Self {
+        AuthEvent {
+            ident: None,
+            step: AuthEventStep::cred_step_devicekey(sid, devicekey_response),
+        }
+    }
 }
 
 // Probably should be a struct with the session id present.
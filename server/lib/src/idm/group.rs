@@ -5,6 +5,7 @@ use kanidm_proto::v1::{Group as ProtoGroup, OperationError};
 use uuid::Uuid;
 
 use crate::entry::{Entry, EntryCommitted, EntryReduced, EntrySealed};
+use crate::idm::accountpolicy::AccountPolicy;
 use crate::prelude::*;
 use crate::value::PartialValue;
 
@@ -12,8 +13,9 @@ use crate::value::PartialValue;
 pub struct Group {
     spn: String,
     uuid: Uuid,
-    // We'll probably add policy and claims later to this
+    // We'll probably add claims later to this
     pub ui_hints: BTreeSet<UiHint>,
+    pub account_policy: AccountPolicy,
 }
 
 macro_rules! try_from_account_e {
@@ -41,6 +43,7 @@ macro_rules! try_from_account_e {
             spn,
             uuid,
             ui_hints,
+            account_policy: AccountPolicy::default(),
         };
 
         let mut groups: Vec<Group> = match $value.get_ava_as_refuuid("memberof") {
@@ -126,10 +129,13 @@ impl Group {
             .cloned()
             .unwrap_or_default();
 
+        let account_policy = AccountPolicy::from_entry(value);
+
         Ok(Group {
             spn,
             uuid,
             ui_hints,
+            account_policy,
         })
     }
 
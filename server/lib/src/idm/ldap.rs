@@ -1,19 +1,39 @@
 //! LDAP specific operations handling components. This is where LDAP operations
 //! are sent to for processing.
 
-use std::collections::BTreeSet;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::iter;
 
-use kanidm_proto::v1::{ApiToken, OperationError, UserAuthToken};
+use kanidm_proto::v1::{ApiToken, Entry as ProtoEntry, OperationError, UserAuthToken};
+use ldap3_proto::proto::{
+    LdapAddRequest, LdapControl, LdapExtendedResponse, LdapModify, LdapModifyRequest,
+    LdapModifyType, LdapMsg, LdapOp, LdapPasswordModifyRequest, LdapResult, LdapSortKey,
+};
 use ldap3_proto::simple::*;
 use regex::Regex;
 use tracing::trace;
 use uuid::Uuid;
 
 use crate::event::SearchEvent;
-use crate::idm::event::{LdapAuthEvent, LdapTokenAuthEvent};
+use crate::idm::event::{LdapAuthEvent, LdapTokenAuthEvent, UnixPasswordChangeEvent};
 use crate::idm::server::{IdmServer, IdmServerTransaction};
+use crate::idm::sourcelock::SOURCE_LOCKED_REASON;
 use crate::prelude::*;
+use crate::schema::SchemaTransaction;
+
+/// The RFC 3062 Password Modify extended operation.
+const OID_PASSWORD_MODIFY: &str = "1.3.6.1.4.1.4203.1.11.1";
+/// The RFC 4532 Who Am I extended operation.
+const OID_WHOAMI: &str = "1.3.6.1.4.1.4203.1.11.3";
+/// The RFC 4511 StartTLS extended operation. This is handled by the plaintext listener.
+pub const OID_STARTTLS: &str = "1.3.6.1.4.1.1466.20037";
+/// The RFC 2696 Simple Paged Results control.
+const OID_PAGED_RESULTS: &str = "1.2.840.113556.1.4.319";
+/// The RFC 2891 Server Side Sort request control.
+const OID_SERVER_SIDE_SORT: &str = "1.2.840.113556.1.4.473";
+/// How long an in progress paged search is retained for between pages, in seconds.
+const PAGED_SEARCH_EXPIRY: u64 = 300;
 
 // Clippy doesn't like Bind here. But proto needs unboxed ldapmsg,
 // and ldapboundtoken is moved. Really, it's not too bad, every message here is pretty sucky.
@@ -27,6 +47,112 @@ pub enum LdapResponseState {
     BindMultiPartResponse(LdapBoundToken, Vec<LdapMsg>),
 }
 
+/// LDAP operations that modify the directory. These are not part of [ServerOps] so
+/// we decode them from the raw [LdapMsg] here. If the message is not a write operation
+/// it is handed back so that it can be processed as a [ServerOps].
+#[derive(Debug, Clone)]
+pub enum LdapWriteOps {
+    Modify(i32, LdapModifyRequest),
+    Add(i32, LdapAddRequest),
+    Delete(i32, String),
+    PasswordModify(i32, LdapPasswordModifyRequest),
+}
+
+impl TryFrom<LdapMsg> for LdapWriteOps {
+    type Error = LdapMsg;
+
+    fn try_from(msg: LdapMsg) -> Result<Self, Self::Error> {
+        let LdapMsg { msgid, op, ctrl } = msg;
+        match op {
+            LdapOp::ModifyRequest(mr) => Ok(LdapWriteOps::Modify(msgid, mr)),
+            LdapOp::AddRequest(ar) => Ok(LdapWriteOps::Add(msgid, ar)),
+            LdapOp::DelRequest(dn) => Ok(LdapWriteOps::Delete(msgid, dn)),
+            LdapOp::ExtendedRequest(ler) if ler.name == OID_PASSWORD_MODIFY => {
+                match LdapPasswordModifyRequest::try_from(&ler) {
+                    Ok(pwm) => Ok(LdapWriteOps::PasswordModify(msgid, pwm)),
+                    Err(_) => Err(LdapMsg {
+                        msgid,
+                        op: LdapOp::ExtendedRequest(ler),
+                        ctrl,
+                    }),
+                }
+            }
+            op => Err(LdapMsg { msgid, op, ctrl }),
+        }
+    }
+}
+
+impl LdapWriteOps {
+    fn gen_result(&self, code: LdapResultCode, message: String) -> LdapMsg {
+        let res = LdapResult {
+            code,
+            matcheddn: "".to_string(),
+            message,
+            referral: vec![],
+        };
+        let (msgid, op) = match self {
+            LdapWriteOps::Modify(msgid, _) => (*msgid, LdapOp::ModifyResponse(res)),
+            LdapWriteOps::Add(msgid, _) => (*msgid, LdapOp::AddResponse(res)),
+            LdapWriteOps::Delete(msgid, _) => (*msgid, LdapOp::DelResponse(res)),
+            LdapWriteOps::PasswordModify(msgid, _) => (
+                *msgid,
+                LdapOp::ExtendedResponse(LdapExtendedResponse {
+                    res,
+                    name: None,
+                    value: None,
+                }),
+            ),
+        };
+        LdapMsg {
+            msgid,
+            op,
+            ctrl: vec![],
+        }
+    }
+
+    pub fn gen_success(&self) -> LdapMsg {
+        self.gen_result(LdapResultCode::Success, "".to_string())
+    }
+
+    pub fn gen_error(&self, rc: LdapResultCode, msg: String) -> LdapMsg {
+        self.gen_result(rc, msg)
+    }
+}
+
+/// The state of an in progress paged search (rfc2696). This is held per connection by
+/// the ldap listener, and is provided to each search so that the client can request the
+/// next page with the cookie we issued. Only one paged search exists per connection, it
+/// holds at most `search_max_paged_results` entries, and it is discarded if the client
+/// does not request the next page within `PAGED_SEARCH_EXPIRY`.
+#[derive(Debug, Clone)]
+pub struct LdapPagedSearch {
+    cookie: Vec<u8>,
+    // The request that began this paged search. The client must not change the search
+    // between pages.
+    base: String,
+    scope: LdapSearchScope,
+    filter: LdapFilter,
+    attrs: Vec<String>,
+    page_max: usize,
+    total: usize,
+    expiry: Duration,
+    entries: VecDeque<LdapSearchResultEntry>,
+}
+
+impl LdapPagedSearch {
+    fn is_expired(&self, ct: Duration) -> bool {
+        ct >= self.expiry
+    }
+
+    fn matches(&self, sr: &SearchRequest, cookie: &[u8]) -> bool {
+        self.cookie == cookie
+            && self.base == sr.base
+            && self.scope == sr.scope
+            && self.filter == sr.filter
+            && self.attrs == sr.attrs
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LdapSession {
     // Maps through and provides anon read, but allows us to check the validity
@@ -48,6 +174,9 @@ pub struct LdapBoundToken {
     // In a way, this is a stepping stone to an "ident" but allows us to check
     // the session is still "valid" depending on it's origin.
     pub effective_session: LdapSession,
+    // When the bind occurred. Writes with a unix bind are only allowed within the
+    // privilege expiry of the account from this time.
+    pub bind_time: Duration,
 }
 
 pub struct LdapServer {
@@ -104,7 +233,17 @@ impl LdapServer {
                 },
                 LdapPartialAttribute {
                     atype: "supportedextension".to_string(),
-                    vals: vec!["1.3.6.1.4.1.4203.1.11.3".as_bytes().to_vec()],
+                    vals: vec![
+                        OID_WHOAMI.as_bytes().to_vec(),
+                        OID_PASSWORD_MODIFY.as_bytes().to_vec(),
+                    ],
+                },
+                LdapPartialAttribute {
+                    atype: "supportedcontrol".to_string(),
+                    vals: vec![
+                        OID_PAGED_RESULTS.as_bytes().to_vec(),
+                        OID_SERVER_SIDE_SORT.as_bytes().to_vec(),
+                    ],
                 },
                 LdapPartialAttribute {
                     atype: "supportedfeatures".to_string(),
@@ -125,6 +264,19 @@ impl LdapServer {
         })
     }
 
+    /// Advertise StartTLS in the rootdse. This must only be enabled when the plaintext
+    /// StartTLS listener is configured, as the operation is handled by that listener.
+    pub fn enable_starttls(&mut self) {
+        if let Some(ext) = self
+            .rootdse
+            .attributes
+            .iter_mut()
+            .find(|a| a.atype == "supportedextension")
+        {
+            ext.vals.push(OID_STARTTLS.as_bytes().to_vec());
+        }
+    }
+
     #[instrument(level = "debug", skip_all)]
     async fn do_search(
         &self,
@@ -133,14 +285,152 @@ impl LdapServer {
         uat: &LdapBoundToken,
         // eventid: &Uuid,
     ) -> Result<Vec<LdapMsg>, OperationError> {
+        let (entries, _) = self.search_entries(idms, sr, uat, &[], false).await?;
+
+        Ok(entries
+            .into_iter()
+            .map(|e| sr.gen_result_entry(e))
+            .chain(iter::once(sr.gen_success()))
+            .collect())
+    }
+
+    /// Process a search that may carry the paged results or server side sort request
+    /// controls. The paged search state of the connection is updated as pages are
+    /// consumed, and cleared once the search is complete or abandoned.
+    #[instrument(level = "debug", skip_all)]
+    async fn do_search_ctrl(
+        &self,
+        idms: &IdmServer,
+        sr: &SearchRequest,
+        uat: &LdapBoundToken,
+        ctrl: &[LdapControl],
+        paged_search: &mut Option<LdapPagedSearch>,
+    ) -> Result<Vec<LdapMsg>, OperationError> {
+        let mut page_req = None;
+        let mut sort_keys: &[LdapSortKey] = &[];
+
+        for c in ctrl.iter() {
+            match c {
+                LdapControl::SimplePagedResults { size, cookie } => {
+                    page_req = Some((*size, cookie.as_slice()))
+                }
+                LdapControl::ServerSideSort { keys, .. } => sort_keys = keys.as_slice(),
+                _ => {}
+            }
+        }
+
+        let sort_ctrl = if sort_keys.is_empty() {
+            None
+        } else {
+            Some(LdapControl::ServerSideSortResult {
+                result: LdapResultCode::Success,
+                attribute_type: None,
+            })
+        };
+
+        let (size, cookie) = match page_req {
+            Some(pr) => pr,
+            None => {
+                // Not a paged search, the search limits apply as normal.
+                let (entries, _) = self.search_entries(idms, sr, uat, sort_keys, false).await?;
+                let mut done = sr.gen_success();
+                done.ctrl.extend(sort_ctrl);
+                return Ok(entries
+                    .into_iter()
+                    .map(|e| sr.gen_result_entry(e))
+                    .chain(iter::once(done))
+                    .collect());
+            }
+        };
+
+        let ct = duration_from_epoch_now();
+
+        let mut state = if cookie.is_empty() {
+            // A new paged search replaces any existing one on this connection.
+            if size <= 0 {
+                *paged_search = None;
+                return Ok(vec![sr.gen_success()]);
+            }
+
+            let (entries, page_max) = self.search_entries(idms, sr, uat, sort_keys, true).await?;
+
+            LdapPagedSearch {
+                cookie: Uuid::new_v4().as_bytes().to_vec(),
+                base: sr.base.clone(),
+                scope: sr.scope.clone(),
+                filter: sr.filter.clone(),
+                attrs: sr.attrs.clone(),
+                page_max,
+                total: entries.len(),
+                expiry: ct + Duration::from_secs(PAGED_SEARCH_EXPIRY),
+                entries: entries.into(),
+            }
+        } else {
+            match paged_search.take() {
+                Some(state) if state.matches(sr, cookie) && !state.is_expired(ct) => state,
+                _ => {
+                    request_error!("LDAP Search failure - invalid paged results cookie");
+                    return Err(OperationError::InvalidRequestState);
+                }
+            }
+        };
+
+        // A size of zero with a valid cookie is the client abandoning the search.
+        let page_size = if size <= 0 {
+            0
+        } else {
+            (size as usize).min(state.page_max)
+        };
+
+        let page: Vec<_> = state
+            .entries
+            .drain(..page_size.min(state.entries.len()))
+            .map(|e| sr.gen_result_entry(e))
+            .collect();
+
+        let total = state.total.try_into().unwrap_or(i32::MAX);
+
+        let next_cookie = if state.entries.is_empty() || page_size == 0 {
+            *paged_search = None;
+            Vec::new()
+        } else {
+            let next_cookie = state.cookie.clone();
+            *paged_search = Some(state);
+            next_cookie
+        };
+
+        admin_info!(
+            npage = %page.len(),
+            complete = %next_cookie.is_empty(),
+            "LDAP Paged Search Success -> number of entries in page"
+        );
+
+        let mut done = sr.gen_success();
+        done.ctrl.push(LdapControl::SimplePagedResults {
+            size: total,
+            cookie: next_cookie,
+        });
+        done.ctrl.extend(sort_ctrl);
+
+        Ok(page.into_iter().chain(iter::once(done)).collect())
+    }
+
+    /// Process a search, and return the matching entries in their ldap form. If sort keys are
+    /// provided the entries are ordered by them. When the search is paged the backend will
+    /// allow up to `search_max_paged_results`, and the maximum page size is returned.
+    async fn search_entries(
+        &self,
+        idms: &IdmServer,
+        sr: &SearchRequest,
+        uat: &LdapBoundToken,
+        sort_keys: &[LdapSortKey],
+        paged: bool,
+    ) -> Result<(Vec<LdapSearchResultEntry>, usize), OperationError> {
         admin_info!("Attempt LDAP Search for {}", uat.spn);
         // If the request is "", Base, Present("objectclass"), [], then we want the rootdse.
         if sr.base.is_empty() && sr.scope == LdapSearchScope::Base {
             admin_info!("LDAP Search success - RootDSE");
-            Ok(vec![
-                sr.gen_result_entry(self.rootdse.clone()),
-                sr.gen_success(),
-            ])
+            Ok((vec![self.rootdse.clone()], 1))
         } else {
             // We want something else apparently. Need to do some more work ...
             // Parse the operation and make sure it's sane before we start the txn.
@@ -176,7 +466,7 @@ impl LdapServer {
                 // is a "subtree search excluding base". Because we don't have a tree structure at
                 // all, this is the same as a onelevel (ald children of base excludeing base).
                 (LdapSearchScope::Children, Some(_r)) | (LdapSearchScope::OneLevel, Some(_r)) => {
-                    return Ok(vec![sr.gen_success()])
+                    return Ok((Vec::new(), 1))
                 }
                 (LdapSearchScope::Children, None) | (LdapSearchScope::OneLevel, None) => {
                     // exclude domain_info
@@ -223,7 +513,7 @@ impl LdapServer {
             }
 
             // We need to retain this to know what the client requested.
-            let (k_attrs, l_attrs) = if all_op_attrs {
+            let (mut k_attrs, mut l_attrs) = if all_op_attrs {
                 // We need all attrs, and we do a full v_attr map.
                 (None, ldap_all_vattrs())
             } else if all_attrs {
@@ -275,6 +565,24 @@ impl LdapServer {
                 (Some(mapped_attrs), req_attrs)
             };
 
+            // To sort we need the sort key attributes to be present in the results, even if the
+            // client did not request them. We track what we added so we can remove it after.
+            let mut sort_only_attrs: Vec<String> = Vec::new();
+            if !all_attrs {
+                for sk in sort_keys.iter() {
+                    let a_lower = sk.attribute_type.to_lowercase();
+                    if !l_attrs.contains(&a_lower) {
+                        if let Some(mapped_attrs) = k_attrs.as_mut() {
+                            mapped_attrs.insert(AttrString::from(
+                                ldap_vattr_map(&a_lower).unwrap_or(&a_lower),
+                            ));
+                        }
+                        l_attrs.push(a_lower.clone());
+                        sort_only_attrs.push(a_lower);
+                    }
+                }
+            }
+
             admin_info!(attr = ?l_attrs, "LDAP Search Request LDAP Attrs");
             admin_info!(attr = ?k_attrs, "LDAP Search Request Mapped Attrs");
 
@@ -314,12 +622,19 @@ impl LdapServer {
             // Build the event, with the permissions from effective_session
             //
             // ! Remember, searchEvent wraps to ignore hidden for us.
-            let ident = idm_read
+            let mut ident = idm_read
                 .validate_ldap_session(&uat.effective_session, ct)
                 .map_err(|e| {
                     admin_error!("Invalid identity: {:?}", e);
                     e
                 })?;
+
+            // Paging is how a client is able to retrieve more than the search limit, but each
+            // page is still bounded by it.
+            let page_max = ident.limits.search_max_results;
+            if paged {
+                ident.limits.search_max_results = ident.limits.search_max_paged_results;
+            }
             let se = SearchEvent::new_ext_impersonate_uuid(
                 &mut idm_read.qs_read,
                 ident,
@@ -348,23 +663,31 @@ impl LdapServer {
                         all_attrs,
                         &l_attrs,
                     )
-                    // if okay, wrap in a ldap msg.
-                    .map(|r| sr.gen_result_entry(r))
                 })
-                .chain(iter::once(Ok(sr.gen_success())))
                 .collect();
 
-            let lres = lres.map_err(|e| {
+            let mut lres = lres.map_err(|e| {
                 admin_error!("entry resolve failure {:?}", e);
                 e
             })?;
 
+            if !sort_keys.is_empty() {
+                ldap_sort_entries(&mut lres, sort_keys);
+            }
+
+            if !sort_only_attrs.is_empty() {
+                lres.iter_mut().for_each(|e| {
+                    e.attributes
+                        .retain(|a| !sort_only_attrs.contains(&a.atype.to_lowercase()))
+                });
+            }
+
             admin_info!(
                 nentries = %lres.len(),
                 "LDAP Search Success -> number of entries"
             );
 
-            Ok(lres)
+            Ok((lres, page_max))
         }
     }
 
@@ -452,28 +775,405 @@ impl LdapServer {
         })
     }
 
+    /// Resolve the dn of a write operation to a filter. Only unique attributes (such as
+    /// name, spn or uuid) may be used as the rdn, else a single write could affect
+    /// many entries at once.
+    fn resolve_write_dn(
+        &self,
+        qs: &mut QueryServerWriteTransaction,
+        dn: &str,
+    ) -> Result<(AttrString, PartialValue), OperationError> {
+        let (attr, value) = self
+            .dnre
+            .captures(dn)
+            .and_then(|caps| match (caps.name("attr"), caps.name("val")) {
+                (Some(a), Some(v)) => Some((a.as_str().to_string(), v.as_str().to_string())),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                request_error!(?dn, "LDAP write failure - invalid dn");
+                OperationError::InvalidRequestState
+            })?;
+
+        let attr = ldap_attr_filter_map(&attr);
+
+        let is_unique = qs
+            .get_schema()
+            .get_attributes()
+            .get(attr.as_str())
+            .map(|sa| sa.unique)
+            .unwrap_or(false);
+
+        if !is_unique {
+            request_error!(?attr, "LDAP write failure - rdn attribute is not unique");
+            return Err(OperationError::InvalidRequestState);
+        }
+
+        let pv = qs.clone_partialvalue(attr.as_str(), value.as_str())?;
+        Ok((attr, pv))
+    }
+
+    fn ldap_changes_to_modlist(
+        qs: &mut QueryServerWriteTransaction,
+        changes: &[LdapModify],
+    ) -> Result<ModifyList<ModifyInvalid>, OperationError> {
+        let mut ml = ModifyList::new();
+
+        for change in changes {
+            let attr = ldap_attr_filter_map(&change.modification.atype);
+            let vals = change
+                .modification
+                .vals
+                .iter()
+                .map(|v| {
+                    std::str::from_utf8(v).map_err(|_| {
+                        OperationError::InvalidAttribute(format!(
+                            "{attr} contains a non utf8 value"
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            match change.operation {
+                LdapModifyType::Add => {
+                    for v in vals {
+                        ml.push_mod(Modify::Present(attr.clone(), qs.clone_value(&attr, v)?));
+                    }
+                }
+                LdapModifyType::Delete => {
+                    // Per rfc4511 a delete without values removes the whole attribute.
+                    if vals.is_empty() {
+                        ml.push_mod(Modify::Purged(attr.clone()));
+                    } else {
+                        for v in vals {
+                            ml.push_mod(Modify::Removed(
+                                attr.clone(),
+                                qs.clone_partialvalue(&attr, v)?,
+                            ));
+                        }
+                    }
+                }
+                LdapModifyType::Replace => {
+                    ml.push_mod(Modify::Purged(attr.clone()));
+                    for v in vals {
+                        ml.push_mod(Modify::Present(attr.clone(), qs.clone_value(&attr, v)?));
+                    }
+                }
+            }
+        }
+
+        Ok(ml)
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn do_modify(
+        &self,
+        idms: &IdmServer,
+        mr: &LdapModifyRequest,
+        uat: &LdapBoundToken,
+    ) -> Result<(), OperationError> {
+        admin_info!(dn = %mr.dn, "Attempt LDAP Modify for {}", uat.spn);
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        let ident = idms_prox_write
+            .validate_ldap_write_session(uat, ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                e
+            })?;
+
+        let (attr, pv) = self.resolve_write_dn(&mut idms_prox_write.qs_write, mr.dn.as_str())?;
+        let filter = filter_all!(f_eq(attr.as_str(), pv));
+        let modlist = Self::ldap_changes_to_modlist(&mut idms_prox_write.qs_write, &mr.changes)?;
+
+        let me =
+            ModifyEvent::from_internal_parts(ident, &modlist, &filter, &idms_prox_write.qs_write)
+                .map_err(|e| {
+                admin_error!("failed to create modify event -> {:?}", e);
+                e
+            })?;
+
+        idms_prox_write
+            .qs_write
+            .modify(&me)
+            .and_then(|_| idms_prox_write.commit())
+            .map(|_| {
+                admin_info!("LDAP Modify Success");
+            })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn do_add(
+        &self,
+        idms: &IdmServer,
+        ar: &LdapAddRequest,
+        uat: &LdapBoundToken,
+    ) -> Result<(), OperationError> {
+        admin_info!(dn = %ar.dn, "Attempt LDAP Add for {}", uat.spn);
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        let ident = idms_prox_write
+            .validate_ldap_write_session(uat, ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                e
+            })?;
+
+        let (rdn_attr, _) = self.resolve_write_dn(&mut idms_prox_write.qs_write, ar.dn.as_str())?;
+
+        let mut attrs: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for la in ar.attributes.iter() {
+            let attr = ldap_attr_filter_map(&la.atype);
+            let vals = attrs.entry(attr.to_string()).or_default();
+            for v in la.vals.iter() {
+                let v = std::str::from_utf8(v).map_err(|_| {
+                    OperationError::InvalidAttribute(format!("{attr} contains a non utf8 value"))
+                })?;
+                vals.push(v.to_string());
+            }
+        }
+
+        // The rdn must be part of the entry, else the entry could never be found by the
+        // dn that the client requested.
+        let rdn_value = self
+            .dnre
+            .captures(ar.dn.as_str())
+            .and_then(|caps| caps.name("val"))
+            .map(|v| v.as_str().to_string())
+            .ok_or(OperationError::InvalidRequestState)?;
+
+        let rdn_vals = attrs.entry(rdn_attr.to_string()).or_default();
+        if !rdn_vals.contains(&rdn_value) {
+            if rdn_vals.is_empty() {
+                rdn_vals.push(rdn_value);
+            } else {
+                request_error!("LDAP Add failure - rdn value conflicts with entry attributes");
+                return Err(OperationError::InvalidRequestState);
+            }
+        }
+
+        let proto_entry = ProtoEntry { attrs };
+        let entry = Entry::from_proto_entry(&proto_entry, &mut idms_prox_write.qs_write)?;
+
+        let ce = CreateEvent {
+            ident,
+            entries: vec![entry],
+        };
+
+        idms_prox_write
+            .qs_write
+            .create(&ce)
+            .and_then(|_| idms_prox_write.commit())
+            .map(|_| {
+                admin_info!("LDAP Add Success");
+            })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn do_delete(
+        &self,
+        idms: &IdmServer,
+        dn: &str,
+        uat: &LdapBoundToken,
+    ) -> Result<(), OperationError> {
+        admin_info!(%dn, "Attempt LDAP Delete for {}", uat.spn);
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        let ident = idms_prox_write
+            .validate_ldap_write_session(uat, ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                e
+            })?;
+
+        let (attr, pv) = self.resolve_write_dn(&mut idms_prox_write.qs_write, dn)?;
+        let filter = filter_all!(f_eq(attr.as_str(), pv));
+
+        let de = DeleteEvent::from_parts(ident, &filter, &mut idms_prox_write.qs_write).map_err(
+            |e| {
+                admin_error!("failed to create delete event -> {:?}", e);
+                e
+            },
+        )?;
+
+        idms_prox_write
+            .qs_write
+            .delete(&de)
+            .and_then(|_| idms_prox_write.commit())
+            .map(|_| {
+                admin_info!("LDAP Delete Success");
+            })
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn do_password_modify(
+        &self,
+        idms: &IdmServer,
+        pwm: &LdapPasswordModifyRequest,
+        uat: &LdapBoundToken,
+    ) -> Result<(), OperationError> {
+        security_info!("Attempt LDAP Password Modify for {}", uat.spn);
+
+        let new_password = pwm.new_password.as_ref().ok_or_else(|| {
+            request_error!(
+                "LDAP Password Modify failure - server generated passwords are not supported"
+            );
+            OperationError::InvalidRequestState
+        })?;
+
+        let target = match &pwm.user_identity {
+            Some(user_identity) => {
+                let rdn = self
+                    .binddnre
+                    .captures(user_identity.as_str())
+                    .and_then(|caps| caps.name("val"))
+                    .map(|v| v.as_str().to_string())
+                    .ok_or(OperationError::NoMatchingEntries)?;
+                let mut idms_prox_read = idms.proxy_read().await;
+                idms_prox_read.qs_read.name_to_uuid(rdn.as_str())?
+            }
+            None => match &uat.effective_session {
+                LdapSession::UnixBind(u) => *u,
+                LdapSession::UserAuthToken(u) => u.uuid,
+                LdapSession::ApiToken(apit) => apit.account_id,
+            },
+        };
+
+        // A unix bind is a long lived session, so to change their own password the
+        // account must re-prove their current password as per rfc3062. This re-bind
+        // also starts a new privilege window for the change.
+        let mut reauth = None;
+        if let LdapSession::UnixBind(u) = &uat.effective_session {
+            if *u != UUID_ANONYMOUS && *u == target {
+                let old_password = pwm.old_password.as_ref().ok_or_else(|| {
+                    security_info!("LDAP Password Modify failure - old password is required");
+                    OperationError::NotAuthenticated
+                })?;
+
+                match self.do_bind(idms, uat.spn.as_str(), old_password).await? {
+                    Some(reauth_t) if reauth_t.effective_session == LdapSession::UnixBind(*u) => {
+                        reauth = Some(reauth_t);
+                    }
+                    _ => {
+                        security_info!("LDAP Password Modify failure - old password is incorrect");
+                        return Err(OperationError::NotAuthenticated);
+                    }
+                }
+            }
+        }
+        let uat = reauth.as_ref().unwrap_or(uat);
+
+        let ct = duration_from_epoch_now();
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        let ident = idms_prox_write
+            .validate_ldap_write_session(uat, ct)
+            .map_err(|e| {
+                admin_error!("Invalid identity: {:?}", e);
+                e
+            })?;
+
+        // LDAP binds are validated against the unix password, so that is what we update.
+        let pce = UnixPasswordChangeEvent::from_parts(ident, target, new_password.clone())?;
+
+        idms_prox_write
+            .set_unix_account_password(&pce)
+            .and_then(|_| idms_prox_write.commit())
+            .map(|_| {
+                security_info!("LDAP Password Modify Success");
+            })
+    }
+
+    pub async fn do_write_op(
+        &self,
+        idms: &IdmServer,
+        write_op: LdapWriteOps,
+        uat: Option<LdapBoundToken>,
+        eventid: Uuid,
+    ) -> Result<LdapResponseState, OperationError> {
+        // Writes always require a bind, there is no implicit anonymous write.
+        let uat = match uat {
+            Some(u) => u,
+            None => {
+                return Ok(LdapResponseState::Respond(write_op.gen_error(
+                    LdapResultCode::InsufficentAccessRights,
+                    format!("Unbound Connection {eventid}"),
+                )))
+            }
+        };
+
+        let res = match &write_op {
+            LdapWriteOps::Modify(_, mr) => self.do_modify(idms, mr, &uat).await,
+            LdapWriteOps::Add(_, ar) => self.do_add(idms, ar, &uat).await,
+            LdapWriteOps::Delete(_, dn) => self.do_delete(idms, dn.as_str(), &uat).await,
+            LdapWriteOps::PasswordModify(_, pwm) => self.do_password_modify(idms, pwm, &uat).await,
+        };
+
+        match res {
+            Ok(()) => Ok(LdapResponseState::Respond(write_op.gen_success())),
+            Err(e) => {
+                let (rc, msg) = operationerr_to_ldapresultcode(e);
+                Ok(LdapResponseState::Respond(write_op.gen_error(rc, msg)))
+            }
+        }
+    }
+
     pub async fn do_op(
         &self,
         idms: &IdmServer,
         server_op: ServerOps,
         uat: Option<LdapBoundToken>,
+        ctrl: &[LdapControl],
+        paged_search: &mut Option<LdapPagedSearch>,
         eventid: Uuid,
+        source: Source,
     ) -> Result<LdapResponseState, OperationError> {
+        // Don't retain the entries of an abandoned paged search while the connection
+        // carries on with other operations.
+        if paged_search
+            .as_ref()
+            .map(|ps| ps.is_expired(duration_from_epoch_now()))
+            .unwrap_or(false)
+        {
+            *paged_search = None;
+        }
+
         match server_op {
-            ServerOps::SimpleBind(sbr) => self
-                .do_bind(idms, sbr.dn.as_str(), sbr.pw.as_str())
-                .await
-                .map(|r| match r {
-                    Some(lbt) => LdapResponseState::Bind(lbt, sbr.gen_success()),
-                    None => LdapResponseState::Respond(sbr.gen_invalid_cred()),
-                })
-                .or_else(|e| {
-                    let (rc, msg) = operationerr_to_ldapresultcode(e);
-                    Ok(LdapResponseState::Respond(sbr.gen_error(rc, msg)))
-                }),
+            ServerOps::SimpleBind(sbr) => {
+                let ct = duration_from_epoch_now();
+                let source_locks = idms.source_locks();
+                if source_locks.is_locked(&source, ct) {
+                    security_info!(?source, "Refusing LDAP bind from locked source");
+                    return Ok(LdapResponseState::Respond(sbr.gen_error(
+                        LdapResultCode::UnwillingToPerform,
+                        SOURCE_LOCKED_REASON.to_string(),
+                    )));
+                }
+
+                self.do_bind(idms, sbr.dn.as_str(), sbr.pw.as_str())
+                    .await
+                    .map(|r| match r {
+                        Some(lbt) => LdapResponseState::Bind(lbt, sbr.gen_success()),
+                        None => {
+                            source_locks.record_failure(&source, ct);
+                            LdapResponseState::Respond(sbr.gen_invalid_cred())
+                        }
+                    })
+                    .or_else(|e| {
+                        // Binding as an account that doesn't exist counts as a failure.
+                        if e == OperationError::NoMatchingEntries {
+                            source_locks.record_failure(&source, ct);
+                        }
+                        let (rc, msg) = operationerr_to_ldapresultcode(e);
+                        Ok(LdapResponseState::Respond(sbr.gen_error(rc, msg)))
+                    })
+            }
             ServerOps::Search(sr) => match uat {
                 Some(u) => self
-                    .do_search(idms, &sr, &u)
+                    .do_search_ctrl(idms, &sr, &u, ctrl, paged_search)
                     .await
                     .map(LdapResponseState::MultiPartResponse)
                     .or_else(|e| {
@@ -495,7 +1195,7 @@ impl LdapServer {
                         }
                     };
                     // If okay, do the search.
-                    self.do_search(idms, &sr, &lbt)
+                    self.do_search_ctrl(idms, &sr, &lbt, ctrl, paged_search)
                         .await
                         .map(|r| LdapResponseState::BindMultiPartResponse(lbt, r))
                         .or_else(|e| {
@@ -544,10 +1244,57 @@ fn operationerr_to_ldapresultcode(e: OperationError) -> (LdapResultCode, String)
         OperationError::SchemaViolation(se) => {
             (LdapResultCode::UnwillingToPerform, format!("{se:?}"))
         }
+        OperationError::AccessDenied | OperationError::SystemProtectedObject => {
+            (LdapResultCode::InsufficentAccessRights, "".to_string())
+        }
+        OperationError::NotAuthenticated => (LdapResultCode::InvalidCredentials, "".to_string()),
+        OperationError::NoMatchingEntries => (LdapResultCode::NoSuchObject, "".to_string()),
+        OperationError::PasswordQuality(feedback) => {
+            (LdapResultCode::ConstraintViolation, format!("{feedback:?}"))
+        }
         e => (LdapResultCode::Other, format!("{e:?}")),
     }
 }
 
+/// Order entries by the provided sort keys as per rfc2891. Entries that do not have a value
+/// for a key are ordered after those that do, and multivalued attributes sort by their
+/// smallest value.
+fn ldap_sort_entries(entries: &mut [LdapSearchResultEntry], sort_keys: &[LdapSortKey]) {
+    fn sort_value<'a>(e: &'a LdapSearchResultEntry, key: &str) -> Option<&'a [u8]> {
+        let mapped = ldap_vattr_map(key).unwrap_or(key);
+        e.attributes
+            .iter()
+            .filter(|a| {
+                let atype = a.atype.to_lowercase();
+                atype == key || atype == mapped
+            })
+            .flat_map(|a| a.vals.iter())
+            .map(|v| v.as_slice())
+            .min()
+    }
+
+    let keys: Vec<(String, bool)> = sort_keys
+        .iter()
+        .map(|sk| (sk.attribute_type.to_lowercase(), sk.reverse_order))
+        .collect();
+
+    entries.sort_by(|a, b| {
+        for (key, reverse) in keys.iter() {
+            let ord = match (sort_value(a, key), sort_value(b, key)) {
+                (Some(va), Some(vb)) => va.cmp(vb),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            let ord = if *reverse { ord.reverse() } else { ord };
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+        Ordering::Equal
+    });
+}
+
 #[inline]
 pub(crate) fn ldap_all_vattrs() -> Vec<String> {
     vec![
@@ -606,14 +1353,19 @@ mod tests {
     use compact_jwt::{Jws, JwsUnverified};
     use hashbrown::HashSet;
     use kanidm_proto::v1::ApiToken;
-    use ldap3_proto::proto::{LdapFilter, LdapOp, LdapSearchScope};
+    use ldap3_proto::proto::{
+        LdapAddRequest, LdapAttribute, LdapControl, LdapFilter, LdapModify, LdapModifyRequest,
+        LdapModifyType, LdapMsg, LdapOp, LdapPasswordModifyRequest, LdapSearchScope, LdapSortKey,
+        LdapSubstringFilter,
+    };
     use ldap3_proto::simple::*;
 
-    use super::{LdapServer, LdapSession};
+    use super::{LdapServer, LdapSession, OID_PASSWORD_MODIFY, OID_STARTTLS};
     use crate::idm::event::UnixPasswordChangeEvent;
     use crate::idm::serviceaccount::GenerateApiTokenEvent;
 
     const TEST_PASSWORD: &str = "ntaoeuntnaoeuhraohuercahu😍";
+    const TEST_PASSWORD_INC: &str = "ntaoeuntnaoeuhraohuercahu😍🥺";
 
     #[idm_test]
     async fn test_ldap_simple_bind(idms: &IdmServer, _idms_delayed: &IdmServerDelayed) {
@@ -1249,4 +2001,386 @@ mod tests {
             _ => assert!(false),
         };
     }
+
+    #[idm_test]
+    async fn test_ldap_rootdse_starttls(idms: &IdmServer, _idms_delayed: &IdmServerDelayed) {
+        let mut ldaps = LdapServer::new(idms).await.expect("failed to start ldap");
+
+        let anon_t = ldaps.do_bind(idms, "", "").await.unwrap().unwrap();
+
+        let sr = SearchRequest {
+            msgid: 1,
+            base: "".to_string(),
+            scope: LdapSearchScope::Base,
+            filter: LdapFilter::Present("objectclass".to_string()),
+            attrs: vec!["supportedextension".to_string()],
+        };
+
+        let has_starttls = |r: &[LdapMsg]| match &r[0].op {
+            LdapOp::SearchResultEntry(lsre) => lsre.attributes.iter().any(|a| {
+                a.atype == "supportedextension"
+                    && a.vals.iter().any(|v| v == OID_STARTTLS.as_bytes())
+            }),
+            _ => panic!("rootdse not returned"),
+        };
+
+        // StartTLS is only advertised when the listener is enabled.
+        let r1 = ldaps.do_search(idms, &sr, &anon_t).await.unwrap();
+        assert!(r1.len() == 2);
+        assert!(!has_starttls(&r1));
+
+        ldaps.enable_starttls();
+
+        let r2 = ldaps.do_search(idms, &sr, &anon_t).await.unwrap();
+        assert!(r2.len() == 2);
+        assert!(has_starttls(&r2));
+        match &r2[0].op {
+            LdapOp::SearchResultEntry(lsre) => {
+                assert_entry_contains!(lsre, "", ("supportedextension", OID_PASSWORD_MODIFY));
+            }
+            _ => assert!(false),
+        };
+    }
+
+    #[idm_test]
+    async fn test_ldap_password_modify_self(idms: &IdmServer, _idms_delayed: &IdmServerDelayed) {
+        let ldaps = LdapServer::new(idms).await.expect("failed to start ldap");
+
+        let mut idms_prox_write = idms.proxy_write(duration_from_epoch_now()).await;
+        let me_posix = unsafe {
+            ModifyEvent::new_internal_invalid(
+                filter!(f_eq("name", PartialValue::new_iname("admin"))),
+                ModifyList::new_list(vec![
+                    Modify::Present(AttrString::from("class"), Value::new_class("posixaccount")),
+                    Modify::Present(AttrString::from("gidnumber"), Value::new_uint32(2001)),
+                ]),
+            )
+        };
+        assert!(idms_prox_write.qs_write.modify(&me_posix).is_ok());
+        let pce = UnixPasswordChangeEvent::new_internal(UUID_ADMIN, TEST_PASSWORD);
+        assert!(idms_prox_write.set_unix_account_password(&pce).is_ok());
+        assert!(idms_prox_write.commit().is_ok());
+
+        let admin_t = ldaps
+            .do_bind(idms, "admin", TEST_PASSWORD)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Without the old password this must be refused.
+        let pwm = LdapPasswordModifyRequest {
+            user_identity: None,
+            old_password: None,
+            new_password: Some(TEST_PASSWORD_INC.to_string()),
+        };
+        assert!(
+            ldaps.do_password_modify(idms, &pwm, &admin_t).await
+                == Err(OperationError::NotAuthenticated)
+        );
+
+        // A wrong old password is also refused.
+        let pwm = LdapPasswordModifyRequest {
+            user_identity: None,
+            old_password: Some("wrong".to_string()),
+            new_password: Some(TEST_PASSWORD_INC.to_string()),
+        };
+        assert!(
+            ldaps.do_password_modify(idms, &pwm, &admin_t).await
+                == Err(OperationError::NotAuthenticated)
+        );
+
+        let pwm = LdapPasswordModifyRequest {
+            user_identity: None,
+            old_password: Some(TEST_PASSWORD.to_string()),
+            new_password: Some(TEST_PASSWORD_INC.to_string()),
+        };
+        assert!(ldaps.do_password_modify(idms, &pwm, &admin_t).await.is_ok());
+
+        assert!(ldaps
+            .do_bind(idms, "admin", TEST_PASSWORD_INC)
+            .await
+            .unwrap()
+            .is_some());
+
+        // Anonymous can not change anyones password.
+        let anon_t = ldaps.do_bind(idms, "", "").await.unwrap().unwrap();
+        let pwm = LdapPasswordModifyRequest {
+            user_identity: Some("admin".to_string()),
+            old_password: None,
+            new_password: Some(TEST_PASSWORD.to_string()),
+        };
+        assert!(ldaps.do_password_modify(idms, &pwm, &anon_t).await.is_err());
+    }
+
+    #[idm_test]
+    async fn test_ldap_write_requires_access(idms: &IdmServer, _idms_delayed: &IdmServerDelayed) {
+        let ldaps = LdapServer::new(idms).await.expect("failed to start ldap");
+
+        let anon_t = ldaps.do_bind(idms, "", "").await.unwrap().unwrap();
+
+        let mr = LdapModifyRequest {
+            dn: "name=admin,dc=example,dc=com".to_string(),
+            changes: vec![LdapModify {
+                operation: LdapModifyType::Replace,
+                modification: LdapPartialAttribute {
+                    atype: "displayname".to_string(),
+                    vals: vec!["Clown Shoes".as_bytes().to_vec()],
+                },
+            }],
+        };
+        assert!(ldaps.do_modify(idms, &mr, &anon_t).await == Err(OperationError::AccessDenied));
+
+        // Non-unique rdn's are rejected, as they could target many entries.
+        let mr = LdapModifyRequest {
+            dn: "class=person,dc=example,dc=com".to_string(),
+            changes: vec![],
+        };
+        assert!(
+            ldaps.do_modify(idms, &mr, &anon_t).await == Err(OperationError::InvalidRequestState)
+        );
+
+        assert!(
+            ldaps
+                .do_delete(idms, "name=admin,dc=example,dc=com", &anon_t)
+                .await
+                == Err(OperationError::AccessDenied)
+        );
+    }
+
+    #[idm_test]
+    async fn test_ldap_write_as_bound_account(idms: &IdmServer, _idms_delayed: &IdmServerDelayed) {
+        let ldaps = LdapServer::new(idms).await.expect("failed to start ldap");
+
+        // idm_admin is able to create and modify groups.
+        let mut idms_prox_write = idms.proxy_write(duration_from_epoch_now()).await;
+        let me_posix = unsafe {
+            ModifyEvent::new_internal_invalid(
+                filter!(f_eq("name", PartialValue::new_iname("idm_admin"))),
+                ModifyList::new_list(vec![
+                    Modify::Present(AttrString::from("class"), Value::new_class("posixaccount")),
+                    Modify::Present(AttrString::from("gidnumber"), Value::new_uint32(2002)),
+                ]),
+            )
+        };
+        assert!(idms_prox_write.qs_write.modify(&me_posix).is_ok());
+        let pce = UnixPasswordChangeEvent::new_internal(UUID_IDM_ADMIN, TEST_PASSWORD);
+        assert!(idms_prox_write.set_unix_account_password(&pce).is_ok());
+        assert!(idms_prox_write.commit().is_ok());
+
+        let idm_admin_t = ldaps
+            .do_bind(idms, "idm_admin", TEST_PASSWORD)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(idm_admin_t.effective_session == LdapSession::UnixBind(UUID_IDM_ADMIN));
+
+        let ar = LdapAddRequest {
+            dn: "name=testgroup1,dc=example,dc=com".to_string(),
+            attributes: vec![
+                LdapAttribute {
+                    atype: "objectClass".to_string(),
+                    vals: vec!["group".as_bytes().to_vec(), "object".as_bytes().to_vec()],
+                },
+                LdapAttribute {
+                    atype: "description".to_string(),
+                    vals: vec!["Created over ldap".as_bytes().to_vec()],
+                },
+            ],
+        };
+        assert!(ldaps.do_add(idms, &ar, &idm_admin_t).await.is_ok());
+
+        let mr = LdapModifyRequest {
+            dn: "name=testgroup1,dc=example,dc=com".to_string(),
+            changes: vec![LdapModify {
+                operation: LdapModifyType::Replace,
+                modification: LdapPartialAttribute {
+                    atype: "description".to_string(),
+                    vals: vec!["Modified over ldap".as_bytes().to_vec()],
+                },
+            }],
+        };
+        assert!(ldaps.do_modify(idms, &mr, &idm_admin_t).await.is_ok());
+
+        // Like any other session, the write privileges of a bind end once the privilege
+        // expiry has passed, and the account must bind again.
+        let mut expired_t = idm_admin_t.clone();
+        expired_t.bind_time -= Duration::from_secs(AUTH_PRIVILEGE_EXPIRY + 1);
+        assert!(ldaps.do_modify(idms, &mr, &expired_t).await == Err(OperationError::AccessDenied));
+        assert!(
+            ldaps
+                .do_delete(idms, "name=testgroup1,dc=example,dc=com", &expired_t)
+                .await
+                == Err(OperationError::AccessDenied)
+        );
+
+        let mut idms_prox_read = idms.proxy_read().await;
+        let group = idms_prox_read
+            .qs_read
+            .internal_search(filter!(f_eq("name", PartialValue::new_iname("testgroup1"))))
+            .expect("failed to search")
+            .pop()
+            .expect("group was not created");
+        assert!(group.get_ava_single_utf8("description") == Some("Modified over ldap"));
+        drop(idms_prox_read);
+
+        // The anonymous read identity is never used for writes, so a bound account
+        // is still limited by its own access controls.
+        let mr = LdapModifyRequest {
+            dn: "name=admin,dc=example,dc=com".to_string(),
+            changes: vec![LdapModify {
+                operation: LdapModifyType::Replace,
+                modification: LdapPartialAttribute {
+                    atype: "displayname".to_string(),
+                    vals: vec!["Clown Shoes".as_bytes().to_vec()],
+                },
+            }],
+        };
+        assert!(
+            ldaps.do_modify(idms, &mr, &idm_admin_t).await == Err(OperationError::AccessDenied)
+        );
+    }
+
+    #[idm_test]
+    async fn test_ldap_paged_sorted_search(idms: &IdmServer, _idms_delayed: &IdmServerDelayed) {
+        let ldaps = LdapServer::new(idms).await.expect("failed to start ldap");
+
+        {
+            let entries = ["pagetest_a", "pagetest_b", "pagetest_c"]
+                .into_iter()
+                .map(|name| {
+                    entry_init!(
+                        ("class", Value::new_class("object")),
+                        ("class", Value::new_class("person")),
+                        ("class", Value::new_class("account")),
+                        ("name", Value::new_iname(name)),
+                        ("displayname", Value::new_utf8s(name))
+                    )
+                })
+                .collect();
+
+            let mut server_txn = idms.proxy_write(duration_from_epoch_now()).await;
+            let ce = CreateEvent::new_internal(entries);
+            assert!(server_txn
+                .qs_write
+                .create(&ce)
+                .and_then(|_| server_txn.commit())
+                .is_ok());
+        }
+
+        let anon_t = ldaps.do_bind(idms, "", "").await.unwrap().unwrap();
+
+        let sr = SearchRequest {
+            msgid: 1,
+            base: "dc=example,dc=com".to_string(),
+            scope: LdapSearchScope::Subtree,
+            filter: LdapFilter::Substring(
+                "name".to_string(),
+                LdapSubstringFilter {
+                    initial: Some("pagetest_".to_string()),
+                    any: vec![],
+                    final_: None,
+                },
+            ),
+            attrs: vec!["displayname".to_string()],
+        };
+
+        let sort = LdapControl::ServerSideSort {
+            criticality: false,
+            keys: vec![LdapSortKey {
+                attribute_type: "name".to_string(),
+                ordering_rule: None,
+                reverse_order: true,
+            }],
+        };
+
+        let mut paged_search = None;
+
+        // First page, sorted in reverse.
+        let ctrl = vec![
+            sort.clone(),
+            LdapControl::SimplePagedResults {
+                size: 2,
+                cookie: Vec::new(),
+            },
+        ];
+        let r1 = ldaps
+            .do_search_ctrl(idms, &sr, &anon_t, &ctrl, &mut paged_search)
+            .await
+            .unwrap();
+        assert!(r1.len() == 3);
+        match &r1[0].op {
+            LdapOp::SearchResultEntry(lsre) => {
+                assert_entry_contains!(
+                    lsre,
+                    "spn=pagetest_c@example.com,dc=example,dc=com",
+                    ("displayname", "pagetest_c")
+                );
+                // The sort key was not requested, so it must not be returned.
+                assert!(lsre.attributes.iter().all(|a| a.atype != "name"));
+            }
+            _ => assert!(false),
+        };
+        let cookie = match r1[2].ctrl.iter().find_map(|c| match c {
+            LdapControl::SimplePagedResults { size, cookie } => Some((*size, cookie.clone())),
+            _ => None,
+        }) {
+            Some((size, cookie)) => {
+                assert!(size == 3);
+                cookie
+            }
+            None => panic!("no paged results control"),
+        };
+        assert!(!cookie.is_empty());
+        assert!(paged_search.is_some());
+
+        // An expired paged search can not be continued.
+        let ctrl = vec![LdapControl::SimplePagedResults {
+            size: 2,
+            cookie: cookie.clone(),
+        }];
+        let mut expired_paged_search = paged_search.clone();
+        if let Some(ps) = expired_paged_search.as_mut() {
+            ps.expiry = Duration::ZERO;
+        }
+        assert!(ldaps
+            .do_search_ctrl(idms, &sr, &anon_t, &ctrl, &mut expired_paged_search)
+            .await
+            .is_err());
+        assert!(expired_paged_search.is_none());
+
+        // A bad cookie is rejected.
+        let ctrl = vec![LdapControl::SimplePagedResults {
+            size: 2,
+            cookie: vec![0, 1, 2, 3],
+        }];
+        let mut bad_paged_search = paged_search.clone();
+        assert!(ldaps
+            .do_search_ctrl(idms, &sr, &anon_t, &ctrl, &mut bad_paged_search)
+            .await
+            .is_err());
+
+        // Final page.
+        let ctrl = vec![sort, LdapControl::SimplePagedResults { size: 2, cookie }];
+        let r2 = ldaps
+            .do_search_ctrl(idms, &sr, &anon_t, &ctrl, &mut paged_search)
+            .await
+            .unwrap();
+        assert!(r2.len() == 2);
+        match &r2[0].op {
+            LdapOp::SearchResultEntry(lsre) => {
+                assert_entry_contains!(
+                    lsre,
+                    "spn=pagetest_a@example.com,dc=example,dc=com",
+                    ("displayname", "pagetest_a")
+                );
+            }
+            _ => assert!(false),
+        };
+        // The total is still reported on the final page.
+        assert!(r2[1].ctrl.iter().any(|c| matches!(
+            c,
+            LdapControl::SimplePagedResults { size: 3, cookie } if cookie.is_empty()
+        )));
+        assert!(paged_search.is_none());
+    }
 }
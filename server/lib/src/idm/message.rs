@@ -0,0 +1,724 @@
+//! Outbound messages are sent by Kanidm to the mail address of an account, such as to deliver a
+//! credential reset link. Messages are queued as entries so that delivery survives a restart and
+//! can be retried, and so that administrators can review what was sent and whether it arrived.
+//! The transports that actually deliver messages are provided by the server core.
+//!
+//! Notifications are messages that tell a person about a security relevant event on their
+//! account, such as a new passkey. They are queued even if the account has no mail address, so
+//! that the person can still review them.
+//!
+//! Some messages carry secrets, such as a credential reset token. The content of a message is
+//! only readable by the server itself, and any secrets are redacted once the message has been
+//! delivered. Messages are removed [MESSAGE_RETENTION] seconds after they were last attempted.
+
+use std::time::Duration;
+
+use kanidm_proto::internal::UserNotification;
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use url::Url;
+
+use crate::idm::server::{IdmServerProxyReadTransaction, IdmServerProxyWriteTransaction};
+use crate::prelude::*;
+
+/// A kind of message, and the values needed to render it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum MessageTemplate {
+    CredentialResetIntent {
+        display_name: String,
+        intent_id: String,
+        #[serde(with = "time::serde::timestamp")]
+        expiry_time: OffsetDateTime,
+    },
+    Notification(Notification),
+}
+
+/// A security relevant event on an account, that the owner of the account should know about.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Notification {
+    CredentialChanged {
+        display_name: String,
+        changes: Vec<String>,
+        #[serde(with = "time::serde::timestamp")]
+        changed_at: OffsetDateTime,
+    },
+    ApiTokenIssued {
+        display_name: String,
+        label: String,
+        #[serde(with = "time::serde::timestamp::option")]
+        expiry: Option<OffsetDateTime>,
+        #[serde(with = "time::serde::timestamp")]
+        issued_at: OffsetDateTime,
+    },
+    NewSourceSession {
+        display_name: String,
+        source_ip: String,
+        #[serde(with = "time::serde::timestamp")]
+        issued_at: OffsetDateTime,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedMessage {
+    pub subject: String,
+    pub body: String,
+}
+
+/// A message that is due to be handed to a transport for delivery.
+#[derive(Debug, Clone)]
+pub struct PendingMessage {
+    pub uuid: Uuid,
+    /// The account this message concerns.
+    pub recipient: Option<Uuid>,
+    pub mail_destination: Option<String>,
+    pub message: RenderedMessage,
+}
+
+const CREDENTIAL_RESET_INTENT_SUBJECT: &str = "Set up the credentials of your account";
+
+const CREDENTIAL_RESET_INTENT_BODY: &str = "Hi {{display_name}},
+
+An administrator has allowed you to set up or reset the credentials of your account.
+
+To continue, open this link:
+
+    {{reset_url}}
+
+Or run this command:
+
+    kanidm person credential use-reset-token {{intent_id}}
+
+This expires at {{expiry_time}}. If you were not expecting this message, contact your
+administrator.
+";
+
+const CREDENTIAL_CHANGED_SUBJECT: &str = "The credentials of your account were changed";
+
+const CREDENTIAL_CHANGED_BODY: &str = "Hi {{display_name}},
+
+The credentials of your account were changed at {{changed_at}}:
+
+{{changes}}
+
+If you did not make these changes, contact your administrator immediately.
+";
+
+const API_TOKEN_ISSUED_SUBJECT: &str = "An API token was issued for your account";
+
+const API_TOKEN_ISSUED_BODY: &str = "Hi {{display_name}},
+
+An API token named '{{label}}' was issued for your account at {{issued_at}}. It expires
+{{expiry}}.
+
+If you were not expecting this, contact your administrator immediately.
+";
+
+const NEW_SOURCE_SESSION_SUBJECT: &str = "New sign in to your account";
+
+const NEW_SOURCE_SESSION_BODY: &str = "Hi {{display_name}},
+
+Your account was signed in to from {{source_ip}} at {{issued_at}}. This address has not been
+used with your account recently.
+
+If this was not you, change your credentials and contact your administrator immediately.
+";
+
+/// Replaces any secret in a message once it has been delivered.
+const REDACTED: &str = "redacted";
+
+fn format_time(odt: &OffsetDateTime) -> String {
+    odt.format(&Rfc3339).unwrap_or_else(|_| odt.to_string())
+}
+
+impl MessageTemplate {
+    /// Remove any secret this message carries, returning true if anything was removed.
+    fn redact(&mut self) -> bool {
+        match self {
+            MessageTemplate::CredentialResetIntent { intent_id, .. } if intent_id != REDACTED => {
+                *intent_id = REDACTED.to_string();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn render(&self, origin: &Url) -> RenderedMessage {
+        match self {
+            MessageTemplate::CredentialResetIntent {
+                display_name,
+                intent_id,
+                expiry_time,
+            } => {
+                let mut reset_url = origin.clone();
+                reset_url.set_path("/ui/reset");
+                reset_url
+                    .query_pairs_mut()
+                    .append_pair("token", intent_id.as_str());
+
+                let expiry_time = format_time(expiry_time);
+
+                RenderedMessage {
+                    subject: CREDENTIAL_RESET_INTENT_SUBJECT.to_string(),
+                    body: render_template(
+                        CREDENTIAL_RESET_INTENT_BODY,
+                        &[
+                            ("display_name", display_name.as_str()),
+                            ("reset_url", reset_url.as_str()),
+                            ("intent_id", intent_id.as_str()),
+                            ("expiry_time", expiry_time.as_str()),
+                        ],
+                    ),
+                }
+            }
+            MessageTemplate::Notification(notification) => notification.render(),
+        }
+    }
+}
+
+impl Notification {
+    /// When the event this notification describes happened.
+    pub fn time(&self) -> OffsetDateTime {
+        match self {
+            Notification::CredentialChanged { changed_at, .. } => *changed_at,
+            Notification::ApiTokenIssued { issued_at, .. }
+            | Notification::NewSourceSession { issued_at, .. } => *issued_at,
+        }
+    }
+
+    pub fn render(&self) -> RenderedMessage {
+        match self {
+            Notification::CredentialChanged {
+                display_name,
+                changes,
+                changed_at,
+            } => {
+                let changes = changes
+                    .iter()
+                    .map(|change| format!("  * {}", change))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                RenderedMessage {
+                    subject: CREDENTIAL_CHANGED_SUBJECT.to_string(),
+                    body: render_template(
+                        CREDENTIAL_CHANGED_BODY,
+                        &[
+                            ("display_name", display_name.as_str()),
+                            ("changes", changes.as_str()),
+                            ("changed_at", format_time(changed_at).as_str()),
+                        ],
+                    ),
+                }
+            }
+            Notification::ApiTokenIssued {
+                display_name,
+                label,
+                expiry,
+                issued_at,
+            } => {
+                let expiry = expiry
+                    .map(|expiry| format!("at {}", format_time(&expiry)))
+                    .unwrap_or_else(|| "never".to_string());
+
+                RenderedMessage {
+                    subject: API_TOKEN_ISSUED_SUBJECT.to_string(),
+                    body: render_template(
+                        API_TOKEN_ISSUED_BODY,
+                        &[
+                            ("display_name", display_name.as_str()),
+                            ("label", label.as_str()),
+                            ("expiry", expiry.as_str()),
+                            ("issued_at", format_time(issued_at).as_str()),
+                        ],
+                    ),
+                }
+            }
+            Notification::NewSourceSession {
+                display_name,
+                source_ip,
+                issued_at,
+            } => RenderedMessage {
+                subject: NEW_SOURCE_SESSION_SUBJECT.to_string(),
+                body: render_template(
+                    NEW_SOURCE_SESSION_BODY,
+                    &[
+                        ("display_name", display_name.as_str()),
+                        ("source_ip", source_ip.as_str()),
+                        ("issued_at", format_time(issued_at).as_str()),
+                    ],
+                ),
+            },
+        }
+    }
+}
+
+/// Replace each `{{name}}` in the template with its value. Unknown names are left in place so
+/// that a mistake in a template is visible rather than silently removed.
+fn render_template(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |acc, (name, value)| {
+            acc.replace(&format!("{{{{{}}}}}", name), value)
+        })
+}
+
+/// Queue a message for delivery. This is an internal operation, the caller must have already
+/// checked that the message is permitted to be sent.
+pub(crate) fn queue_message(
+    qs_write: &mut QueryServerWriteTransaction,
+    recipient: Uuid,
+    mail_destination: Option<&str>,
+    template: &MessageTemplate,
+) -> Result<(), OperationError> {
+    let mail_destination = mail_destination
+        .map(|mail_destination| {
+            Value::new_email_address_primary_s(mail_destination).ok_or_else(|| {
+                admin_error!(%mail_destination, "Invalid mail destination for outbound message");
+                OperationError::InvalidAttribute("mail_destination".to_string())
+            })
+        })
+        .transpose()?;
+
+    let template = serde_json::to_string(template).map_err(|e| {
+        admin_error!(err = ?e, "Unable to serialise outbound message template");
+        OperationError::SerdeJsonError
+    })?;
+
+    let mut e = entry_init!(
+        ("class", Value::new_class("object")),
+        ("class", Value::new_class("outbound_message")),
+        ("uuid", Value::Uuid(Uuid::new_v4())),
+        ("message_template", Value::new_utf8(template)),
+        ("message_recipient", Value::Refer(recipient)),
+        (
+            "send_after",
+            Value::new_datetime_epoch(qs_write.get_curtime())
+        )
+    );
+    if let Some(mail_destination) = mail_destination {
+        e.add_ava("mail_destination", mail_destination);
+    }
+
+    qs_write.internal_create(vec![e])
+}
+
+/// Queue a notification to the owner of an account. It is sent to the primary mail address of
+/// the account if it has one.
+pub(crate) fn queue_notification<F>(
+    qs_write: &mut QueryServerWriteTransaction,
+    account: &EntrySealedCommitted,
+    notification: F,
+) -> Result<(), OperationError>
+where
+    F: FnOnce(String) -> Notification,
+{
+    let display_name = account
+        .get_ava_single_utf8("displayname")
+        .or_else(|| account.get_ava_single_iname("name"))
+        .unwrap_or_default()
+        .to_string();
+
+    let template = MessageTemplate::Notification(notification(display_name));
+
+    queue_message(
+        qs_write,
+        account.get_uuid(),
+        account.get_ava_mail_primary("mail"),
+        &template,
+    )
+}
+
+impl<'a> IdmServerProxyWriteTransaction<'a> {
+    /// Render the messages that are due for delivery. Messages without a mail destination are
+    /// only included if the transport can deliver them some other way.
+    pub fn pending_messages(
+        &mut self,
+        require_mail_destination: bool,
+        ct: Duration,
+    ) -> Result<Vec<PendingMessage>, OperationError> {
+        let now = OffsetDateTime::UNIX_EPOCH + ct;
+        let origin = self.get_origin().clone();
+
+        let mut f_pending = vec![
+            f_eq("class", PartialValue::new_class("outbound_message")),
+            f_andnot(f_pres("delivered_at")),
+        ];
+        if require_mail_destination {
+            f_pending.push(f_pres("mail_destination"));
+        }
+
+        let entries = self.qs_write.internal_search(filter!(f_and(f_pending)))?;
+
+        let pending = entries
+            .iter()
+            .filter(|entry| {
+                let attempts = entry.get_ava_single_uint32("delivery_attempts").unwrap_or(0);
+                let send_after = entry.get_ava_single_datetime("send_after");
+                attempts < MESSAGE_DELIVERY_MAX_ATTEMPTS
+                    && send_after.map(|send_after| send_after <= now).unwrap_or(true)
+            })
+            .filter_map(|entry| {
+                let template = entry
+                    .get_ava_single_utf8("message_template")
+                    .and_then(|s| {
+                        serde_json::from_str::<MessageTemplate>(s)
+                            .map_err(|e| {
+                                admin_error!(err = ?e, uuid = %entry.get_uuid(), "Invalid outbound message template");
+                            })
+                            .ok()
+                    })?;
+
+                Some(PendingMessage {
+                    uuid: entry.get_uuid(),
+                    recipient: entry.get_ava_single_refer("message_recipient"),
+                    mail_destination: entry
+                        .get_ava_mail_primary("mail_destination")
+                        .map(str::to_string),
+                    message: template.render(&origin),
+                })
+            })
+            .collect();
+
+        Ok(pending)
+    }
+
+    /// Record the outcome of an attempt to deliver a message. Failed messages are retried with
+    /// an increasing delay until [MESSAGE_DELIVERY_MAX_ATTEMPTS] is reached. Once a message is
+    /// delivered or given up on, any secret it carries is redacted.
+    pub fn record_message_delivery(
+        &mut self,
+        uuid: Uuid,
+        result: Result<(), String>,
+        ct: Duration,
+    ) -> Result<(), OperationError> {
+        let entry = self.qs_write.internal_search_uuid(uuid)?;
+
+        let (mut modlist, finished) = match result {
+            Ok(()) => (
+                ModifyList::new_list(vec![
+                    m_purge("delivered_at"),
+                    m_pres("delivered_at", &Value::new_datetime_epoch(ct)),
+                    m_purge("delivery_error"),
+                ]),
+                true,
+            ),
+            Err(delivery_error) => {
+                let attempts = entry
+                    .get_ava_single_uint32("delivery_attempts")
+                    .unwrap_or(0)
+                    .saturating_add(1);
+
+                let backoff = MESSAGE_DELIVERY_FREQUENCY
+                    .saturating_mul(1 << attempts.min(MESSAGE_DELIVERY_MAX_ATTEMPTS));
+
+                // Errors from a transport can span lines, but we store them as a single line.
+                let delivery_error = delivery_error.lines().collect::<Vec<_>>().join(" ");
+
+                (
+                    ModifyList::new_list(vec![
+                        m_purge("delivery_attempts"),
+                        m_pres("delivery_attempts", &Value::new_uint32(attempts)),
+                        m_purge("delivery_error"),
+                        m_pres("delivery_error", &Value::new_utf8(delivery_error)),
+                        m_purge("send_after"),
+                        m_pres(
+                            "send_after",
+                            &Value::new_datetime_epoch(ct + Duration::from_secs(backoff)),
+                        ),
+                    ]),
+                    attempts >= MESSAGE_DELIVERY_MAX_ATTEMPTS,
+                )
+            }
+        };
+
+        if finished {
+            let template = entry
+                .get_ava_single_utf8("message_template")
+                .and_then(|s| serde_json::from_str::<MessageTemplate>(s).ok());
+
+            if let Some(mut template) = template {
+                if template.redact() {
+                    let template = serde_json::to_string(&template).map_err(|e| {
+                        admin_error!(err = ?e, "Unable to serialise outbound message template");
+                        OperationError::SerdeJsonError
+                    })?;
+                    modlist.push_mod(m_purge("message_template"));
+                    modlist.push_mod(m_pres("message_template", &Value::new_utf8(template)));
+                }
+            }
+        }
+
+        self.qs_write.internal_modify(
+            &filter!(f_and!([
+                f_eq("class", PartialValue::new_class("outbound_message")),
+                f_eq("uuid", PartialValue::Uuid(uuid))
+            ])),
+            &modlist,
+        )
+    }
+
+    /// Remove the messages that were last attempted more than [MESSAGE_RETENTION] seconds ago,
+    /// whether or not they were delivered.
+    pub fn purge_messages(&mut self, ct: Duration) -> Result<(), OperationError> {
+        let purge_before = match ct.checked_sub(Duration::from_secs(MESSAGE_RETENTION)) {
+            Some(purge_before) => purge_before,
+            None => return Ok(()),
+        };
+
+        let filter = filter!(f_and!([
+            f_eq("class", PartialValue::new_class("outbound_message")),
+            f_lt("send_after", PartialValue::new_datetime_epoch(purge_before))
+        ]));
+
+        match self.qs_write.internal_delete(&filter) {
+            Ok(()) | Err(OperationError::NoMatchingEntries) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<'a> IdmServerProxyReadTransaction<'a> {
+    /// List the notifications about the account of this identity, newest first.
+    pub fn list_notifications(
+        &mut self,
+        ident: &Identity,
+    ) -> Result<Vec<UserNotification>, OperationError> {
+        let uuid = ident.get_uuid().ok_or_else(|| {
+            admin_error!("Only accounts can have notifications");
+            OperationError::InvalidState
+        })?;
+
+        let entries = self.qs_read.internal_search(filter!(f_and!([
+            f_eq("class", PartialValue::new_class("outbound_message")),
+            f_eq("message_recipient", PartialValue::Refer(uuid))
+        ])))?;
+
+        let mut notifications: Vec<_> = entries
+            .iter()
+            .filter_map(|entry| {
+                // Other messages, such as reset tokens, are only for the mail address they were
+                // sent to, so they are never shown here.
+                let notification = entry
+                    .get_ava_single_utf8("message_template")
+                    .and_then(|s| serde_json::from_str::<MessageTemplate>(s).ok())
+                    .and_then(|template| match template {
+                        MessageTemplate::Notification(notification) => Some(notification),
+                        _ => None,
+                    })?;
+                let RenderedMessage { subject, body } = notification.render();
+
+                Some(UserNotification {
+                    id: entry.get_uuid(),
+                    subject,
+                    body,
+                    time: notification.time(),
+                    delivered: entry.get_ava_single_datetime("delivered_at").is_some(),
+                })
+            })
+            .collect();
+
+        notifications.sort_unstable_by(|a, b| b.time.cmp(&a.time));
+
+        Ok(notifications)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use time::OffsetDateTime;
+    use url::Url;
+
+    use super::{queue_message, queue_notification, MessageTemplate, Notification};
+    use crate::prelude::*;
+
+    const TEST_CURRENT_TIME: u64 = 6000;
+
+    #[test]
+    fn test_idm_message_render_credential_reset_intent() {
+        let origin = Url::parse("https://idm.example.com").expect("Invalid origin");
+
+        let template = MessageTemplate::CredentialResetIntent {
+            display_name: "Test Person".to_string(),
+            intent_id: "8qDRG-AE1qC-zjjAT-0Fkd6".to_string(),
+            expiry_time: OffsetDateTime::UNIX_EPOCH + Duration::from_secs(86400),
+        };
+
+        let message = template.render(&origin);
+
+        assert!(message.body.starts_with("Hi Test Person,"));
+        assert!(message
+            .body
+            .contains("https://idm.example.com/ui/reset?token=8qDRG-AE1qC-zjjAT-0Fkd6"));
+        assert!(message
+            .body
+            .contains("kanidm person credential use-reset-token 8qDRG-AE1qC-zjjAT-0Fkd6"));
+        assert!(message.body.contains("1970-01-02T00:00:00Z"));
+        assert!(!message.body.contains("{{"));
+    }
+
+    #[idm_test]
+    async fn test_idm_message_queue_delivery(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let template = MessageTemplate::CredentialResetIntent {
+            display_name: "Test Person".to_string(),
+            intent_id: "8qDRG-AE1qC-zjjAT-0Fkd6".to_string(),
+            expiry_time: OffsetDateTime::UNIX_EPOCH + ct + Duration::from_secs(3600),
+        };
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        queue_message(
+            &mut idms_prox_write.qs_write,
+            UUID_ADMIN,
+            Some("testperson@example.com"),
+            &template,
+        )
+        .expect("Failed to queue message");
+
+        let pending = idms_prox_write
+            .pending_messages(true, ct)
+            .expect("Failed to list pending messages");
+        assert!(pending.len() == 1);
+        let message_uuid = pending[0].uuid;
+        assert!(pending[0].recipient == Some(UUID_ADMIN));
+        assert!(pending[0].mail_destination.as_deref() == Some("testperson@example.com"));
+
+        // A failure delays the next attempt.
+        idms_prox_write
+            .record_message_delivery(message_uuid, Err("connection refused".to_string()), ct)
+            .expect("Failed to record delivery");
+        assert!(idms_prox_write
+            .pending_messages(true, ct)
+            .expect("Failed to list pending messages")
+            .is_empty());
+
+        let ct = ct + Duration::from_secs(MESSAGE_DELIVERY_FREQUENCY * 2);
+        assert!(
+            idms_prox_write
+                .pending_messages(true, ct)
+                .expect("Failed to list pending messages")
+                .len()
+                == 1
+        );
+
+        // Once delivered it is never sent again.
+        idms_prox_write
+            .record_message_delivery(message_uuid, Ok(()), ct)
+            .expect("Failed to record delivery");
+        assert!(idms_prox_write
+            .pending_messages(true, ct)
+            .expect("Failed to list pending messages")
+            .is_empty());
+
+        let entry = idms_prox_write
+            .qs_write
+            .internal_search_uuid(message_uuid)
+            .expect("Failed to find message");
+        assert!(entry.get_ava_single_datetime("delivered_at").is_some());
+        assert!(entry.get_ava_single_uint32("delivery_attempts") == Some(1));
+
+        // The reset token is no longer stored once it has been delivered.
+        assert!(!entry
+            .get_ava_single_utf8("message_template")
+            .expect("Missing message template")
+            .contains("8qDRG-AE1qC-zjjAT-0Fkd6"));
+
+        // The message is kept until the retention period has passed.
+        idms_prox_write
+            .purge_messages(ct)
+            .expect("Failed to purge messages");
+        assert!(idms_prox_write
+            .qs_write
+            .internal_search_uuid(message_uuid)
+            .is_ok());
+
+        let ct = ct + Duration::from_secs(MESSAGE_RETENTION);
+        idms_prox_write
+            .purge_messages(ct)
+            .expect("Failed to purge messages");
+        assert!(idms_prox_write
+            .qs_write
+            .internal_search_uuid(message_uuid)
+            .is_err());
+
+        idms_prox_write.commit().expect("Failed to commit txn");
+    }
+
+    #[idm_test]
+    async fn test_idm_message_notification_list(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let issued_at = OffsetDateTime::UNIX_EPOCH + ct;
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        // The admin account has no mail address.
+        let admin = idms_prox_write
+            .qs_write
+            .internal_search_uuid(UUID_ADMIN)
+            .expect("Failed to find admin");
+        queue_notification(&mut idms_prox_write.qs_write, &admin, |display_name| {
+            Notification::NewSourceSession {
+                display_name,
+                source_ip: "192.0.2.1".to_string(),
+                issued_at,
+            }
+        })
+        .expect("Failed to queue notification");
+
+        // A reset token must never be shown as a notification.
+        queue_message(
+            &mut idms_prox_write.qs_write,
+            UUID_ADMIN,
+            Some("admin@example.com"),
+            &MessageTemplate::CredentialResetIntent {
+                display_name: "admin".to_string(),
+                intent_id: "8qDRG-AE1qC-zjjAT-0Fkd6".to_string(),
+                expiry_time: issued_at + Duration::from_secs(3600),
+            },
+        )
+        .expect("Failed to queue message");
+
+        // Only the reset token can be sent by mail, but both can be sent by a transport that
+        // doesn't need a mail address.
+        assert!(
+            idms_prox_write
+                .pending_messages(true, ct)
+                .expect("Failed to list pending messages")
+                .len()
+                == 1
+        );
+        assert!(
+            idms_prox_write
+                .pending_messages(false, ct)
+                .expect("Failed to list pending messages")
+                .len()
+                == 2
+        );
+
+        idms_prox_write.commit().expect("Failed to commit txn");
+
+        let mut idms_prox_read = idms.proxy_read().await;
+        let ident = idms_prox_read
+            .qs_read
+            .internal_search_uuid(UUID_ADMIN)
+            .map(Identity::from_impersonate_entry_readonly)
+            .expect("Failed to impersonate identity");
+
+        let notifications = idms_prox_read
+            .list_notifications(&ident)
+            .expect("Failed to list notifications");
+
+        assert!(notifications.len() == 1);
+        assert!(notifications[0].subject == "New sign in to your account");
+        assert!(notifications[0].body.contains("192.0.2.1"));
+        assert!(!notifications[0].delivered);
+    }
+}
@@ -4,20 +4,24 @@
 //! is implemented.
 
 pub mod account;
+pub mod accountpolicy;
 pub mod applinks;
 pub mod audit;
 pub mod authsession;
 pub mod credupdatesession;
+pub mod customschema;
 pub mod delayed;
 pub mod event;
 pub mod group;
 pub mod ldap;
+pub mod message;
 pub mod oauth2;
 pub mod radius;
 pub mod reauth;
 pub mod scim;
 pub mod server;
 pub mod serviceaccount;
+pub mod sourcelock;
 pub mod unix;
 
 use std::fmt;
@@ -7,25 +7,31 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryFrom;
 use std::fmt;
-use std::sync::Arc;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use base64::{engine::general_purpose, Engine as _};
 
 use base64urlsafedata::Base64UrlSafeData;
+use compact_jwt::{Jwk, Jws, JwsSigner, JwsUnverified, JwsValidator, OidcClaims, OidcSubject};
 pub use compact_jwt::{JwkKeySet, OidcToken};
-use compact_jwt::{JwsSigner, OidcClaims, OidcSubject};
+use concread::bptree::{BptreeMap, BptreeMapReadTxn, BptreeMapWriteTxn};
 use concread::cowcell::*;
 use fernet::Fernet;
 use hashbrown::HashMap;
 pub use kanidm_proto::oauth2::{
     AccessTokenIntrospectRequest, AccessTokenIntrospectResponse, AccessTokenRequest,
-    AccessTokenResponse, AuthorisationRequest, CodeChallengeMethod, ErrorResponse, GrantTypeReq,
-    OidcDiscoveryResponse, TokenRevokeRequest,
+    AccessTokenResponse, AuthorisationRequest, AuthorisationRequestKind,
+    AuthorisationRequestReference, ClientRegistrationRequest, ClientRegistrationResponse,
+    CodeChallengeMethod, DeviceAuthorisationRequest, DeviceAuthorisationResponse,
+    EndSessionRequest, ErrorResponse, GrantTypeReq, OidcDiscoveryResponse,
+    PushedAuthorisationRequest, PushedAuthorisationResponse, TokenRevokeRequest,
 };
 use kanidm_proto::oauth2::{
     ClaimType, DisplayValue, GrantType, IdTokenSignAlg, ResponseMode, ResponseType, SubjectType,
-    TokenEndpointAuthMethod,
+    TokenEndpointAuthMethod, OAUTH2_CLIENT_ASSERTION_TYPE_JWT_BEARER,
+    OAUTH2_PAR_REQUEST_URI_PREFIX, OAUTH2_TOKEN_TYPE_ACCESS_TOKEN,
 };
 use kanidm_proto::v1::UserAuthToken;
 use openssl::sha;
@@ -35,10 +41,12 @@ use tracing::trace;
 use url::{Origin, Url};
 
 use crate::idm::account::Account;
+use crate::idm::audit::AuditEvent;
 use crate::idm::server::{
     IdmServerProxyReadTransaction, IdmServerProxyWriteTransaction, IdmServerTransaction,
 };
 use crate::prelude::*;
+use crate::utils::user_code_from_random;
 use crate::value::{Oauth2Session, OAUTHSCOPE_RE};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -62,6 +70,18 @@ pub enum Oauth2Error {
     InsufficientScope,
     // from https://datatracker.ietf.org/doc/html/rfc7009#section-2.2.1
     UnsupportedTokenType,
+    // from https://datatracker.ietf.org/doc/html/rfc8628#section-3.5
+    AuthorizationPending,
+    SlowDown,
+    ExpiredToken,
+    // from https://datatracker.ietf.org/doc/html/rfc8693#section-2.2.2
+    InvalidTarget,
+    // from https://openid.net/specs/openid-connect-core-1_0.html#AuthError
+    InvalidRequestUri,
+    InvalidRequestObject,
+    // from https://datatracker.ietf.org/doc/html/rfc7591#section-3.2.2
+    InvalidRedirectUri,
+    InvalidClientMetadata,
 }
 
 impl std::fmt::Display for Oauth2Error {
@@ -81,6 +101,14 @@ impl std::fmt::Display for Oauth2Error {
             Oauth2Error::InvalidToken => "invalid_token",
             Oauth2Error::InsufficientScope => "insufficient_scope",
             Oauth2Error::UnsupportedTokenType => "unsupported_token_type",
+            Oauth2Error::AuthorizationPending => "authorization_pending",
+            Oauth2Error::SlowDown => "slow_down",
+            Oauth2Error::ExpiredToken => "expired_token",
+            Oauth2Error::InvalidTarget => "invalid_target",
+            Oauth2Error::InvalidRequestUri => "invalid_request_uri",
+            Oauth2Error::InvalidRequestObject => "invalid_request_object",
+            Oauth2Error::InvalidRedirectUri => "invalid_redirect_uri",
+            Oauth2Error::InvalidClientMetadata => "invalid_client_metadata",
         })
     }
 }
@@ -104,6 +132,8 @@ struct ConsentToken {
     pub scopes: BTreeSet<String>,
     // We stash some details here for oidc.
     pub nonce: Option<String>,
+    // The pushed request this consent was for, which is consumed by the token exchange.
+    pub request_uri: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -119,6 +149,112 @@ struct TokenExchangeCode {
     pub scopes: BTreeSet<String>,
     // We stash some details here for oidc.
     pub nonce: Option<String>,
+    // The pushed request this code was issued for, if any.
+    pub request_uri: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct DeviceConsentToken {
+    pub client_id: String,
+    // Must match the session id of the Uat,
+    pub session_id: Uuid,
+    // So we can ensure that we really match the same uat to prevent confusions.
+    pub ident_id: IdentityId,
+    // The device authorisation this consent applies to.
+    pub user_code: String,
+    // The scopes being granted
+    pub scopes: BTreeSet<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct DeviceCode {
+    // Encrypted with the RS specific key, so this can only be redeemed by the
+    // client that started the device authorisation.
+    pub user_code: String,
+}
+
+// Unlike the other flows, the device flow requires us to hold state between the
+// client polling and the user approving the request on another device. This is
+// kept in memory, so the user and the client must reach the same kanidm instance.
+#[derive(Clone, Debug)]
+enum DeviceAuthorisationState {
+    Pending,
+    Approved {
+        uat: UserAuthToken,
+        scopes: BTreeSet<String>,
+    },
+    Denied,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct DeviceAuthorisation {
+    client_id: String,
+    scopes: BTreeSet<String>,
+    expiry: Duration,
+    interval: u32,
+    last_poll: Option<Duration>,
+    state: DeviceAuthorisationState,
+}
+
+// A pushed authorisation request, held until the client redirects the user to the
+// authorisation endpoint with the matching request_uri. Like device authorisations this
+// is only kept in memory.
+#[derive(Clone, Debug)]
+pub(crate) struct PushedAuthorisation {
+    request: AuthorisationRequest,
+    expiry: Duration,
+}
+
+// The claims of a signed request object. The authorisation parameters are
+// carried as claims alongside the usual jwt ones.
+// https://datatracker.ietf.org/doc/html/rfc9101#section-4
+#[derive(Serialize, Deserialize, Debug)]
+struct RequestObject {
+    iss: String,
+    aud: serde_json::Value,
+    exp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nbf: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    iat: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jti: Option<String>,
+    #[serde(flatten)]
+    request: AuthorisationRequest,
+}
+
+// The claims of a client assertion, used by a client to authenticate to the token
+// endpoint in place of its secret.
+// https://datatracker.ietf.org/doc/html/rfc7523#section-3
+#[derive(Serialize, Deserialize, Debug)]
+struct ClientAssertion {
+    iss: String,
+    sub: String,
+    aud: serde_json::Value,
+    exp: i64,
+    jti: String,
+}
+
+// The claims of a back-channel logout token.
+// https://openid.net/specs/openid-connect-backchannel-1_0.html#LogoutToken
+#[derive(Serialize, Deserialize, Debug)]
+struct LogoutToken {
+    iss: Url,
+    sub: OidcSubject,
+    aud: String,
+    iat: i64,
+    jti: Uuid,
+    events: serde_json::Value,
+    sid: Uuid,
+}
+
+// The claims we check in an id_token_hint. It may have expired, so we only
+// assert it was issued by us to this client, for the user being logged out.
+#[derive(Serialize, Deserialize, Debug)]
+struct IdTokenHint {
+    iss: Url,
+    sub: OidcSubject,
+    aud: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -145,6 +281,16 @@ pub(crate) enum Oauth2TokenType {
         iat: i64,
         nbf: i64,
     },
+    ClientAccess {
+        scopes: BTreeSet<String>,
+        session_id: Uuid,
+        #[serde(with = "time::serde::timestamp")]
+        expiry: time::OffsetDateTime,
+        // The uuid of the service account this was issued for.
+        uuid: Uuid,
+        iat: i64,
+        nbf: i64,
+    },
 }
 
 impl fmt::Display for Oauth2TokenType {
@@ -156,6 +302,9 @@ impl fmt::Display for Oauth2TokenType {
             Oauth2TokenType::Refresh { session_id, .. } => {
                 write!(f, "refresh_token ({session_id}) ")
             }
+            Oauth2TokenType::ClientAccess { session_id, .. } => {
+                write!(f, "client_access_token ({session_id}) ")
+            }
         }
     }
 }
@@ -187,6 +336,174 @@ pub struct AuthorisePermitSuccess {
     pub code: String,
 }
 
+// The service account that presented an initial access token, and the limits on the
+// resource servers that it may register.
+struct Oauth2Registrant {
+    uuid: Uuid,
+    origins: Vec<Url>,
+    scope_maps: BTreeMap<Uuid, BTreeSet<String>>,
+}
+
+impl Oauth2Registrant {
+    fn try_from_ident(ident: &Identity, write: bool) -> Result<Self, Oauth2Error> {
+        let entry = ident
+            .get_user_entry()
+            .filter(|entry| entry.attribute_equality("class", &PVCLASS_SERVICE_ACCOUNT))
+            .ok_or_else(|| {
+                security_info!("Invalid oauth2 registration - not a service account");
+                Oauth2Error::AccessDenied
+            })?;
+
+        if write && ident.access_scope() != AccessScope::ReadWrite {
+            security_info!("Invalid oauth2 registration - the token is not read write");
+            return Err(Oauth2Error::AccessDenied);
+        }
+
+        let origins: Vec<Url> = entry
+            .get_ava_iter_url("oauth2_registration_origin")
+            .map(|iter| iter.cloned().collect())
+            .unwrap_or_default();
+
+        if origins.is_empty() {
+            security_info!(
+                "Invalid oauth2 registration - the service account has no registration origins"
+            );
+            return Err(Oauth2Error::AccessDenied);
+        }
+
+        let scope_maps = entry
+            .get_ava_as_oauthscopemaps("oauth2_registration_scope_map")
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(Oauth2Registrant {
+            uuid: entry.get_uuid(),
+            origins,
+            scope_maps,
+        })
+    }
+
+    // An origin is permitted if it is one of ours, or a subdomain of one of ours.
+    fn permits_origin(&self, origin: &Url) -> bool {
+        self.origins.iter().any(|allowed| {
+            allowed.scheme() == origin.scheme()
+                && allowed.port_or_known_default() == origin.port_or_known_default()
+                && match (allowed.host_str(), origin.host_str()) {
+                    (Some(allowed), Some(host)) => {
+                        host == allowed
+                            || host
+                                .strip_suffix(allowed)
+                                .map(|prefix| prefix.ends_with('.'))
+                                .unwrap_or(false)
+                    }
+                    _ => false,
+                }
+        })
+    }
+
+    // Check the requested metadata is within our limits, returning the origin and scope
+    // maps of the resource server to register.
+    fn check_metadata(
+        &self,
+        reg_req: &ClientRegistrationRequest,
+    ) -> Result<(Url, Vec<Value>), Oauth2Error> {
+        // We validate redirects by origin, so all of the redirect uris must share one.
+        let mut origins = reg_req.redirect_uris.iter().map(|uri| uri.origin());
+        let origin = origins.next().ok_or_else(|| {
+            security_info!("Invalid oauth2 registration - no redirect_uris");
+            Oauth2Error::InvalidRedirectUri
+        })?;
+
+        if origins.any(|other| other != origin) {
+            security_info!("Invalid oauth2 registration - redirect_uris have differing origins");
+            return Err(Oauth2Error::InvalidRedirectUri);
+        }
+
+        // An opaque origin won't parse, so this also rejects those.
+        let origin = Url::parse(&origin.ascii_serialization())
+            .ok()
+            .filter(|origin| self.permits_origin(origin))
+            .ok_or_else(|| {
+                security_info!(
+                    ?origin,
+                    "Invalid oauth2 registration - origin is not permitted"
+                );
+                Oauth2Error::InvalidRedirectUri
+            })?;
+
+        if let Some(backchannel_logout_uri) = reg_req.backchannel_logout_uri.as_ref() {
+            if backchannel_logout_uri.origin() != origin.origin() {
+                security_info!(
+                    "Invalid oauth2 registration - backchannel_logout_uri is not within the origin"
+                );
+                return Err(Oauth2Error::InvalidClientMetadata);
+            }
+        }
+
+        // We only hand out a secret, so the client must be able to use it.
+        match reg_req.token_endpoint_auth_method {
+            None
+            | Some(TokenEndpointAuthMethod::ClientSecretBasic)
+            | Some(TokenEndpointAuthMethod::ClientSecretPost) => {}
+            Some(_) => {
+                security_info!(
+                    "Invalid oauth2 registration - unsupported token_endpoint_auth_method"
+                );
+                return Err(Oauth2Error::InvalidClientMetadata);
+            }
+        }
+
+        // The client can only have the scopes we are able to grant.
+        let scope_maps = match reg_req.scope.as_ref() {
+            Some(scope) => {
+                let requested: BTreeSet<&str> = scope.split_ascii_whitespace().collect();
+                let permitted: BTreeSet<&str> = self
+                    .scope_maps
+                    .values()
+                    .flat_map(|scopes| scopes.iter().map(String::as_str))
+                    .collect();
+
+                if !requested.is_subset(&permitted) {
+                    security_info!(
+                        ?requested,
+                        "Invalid oauth2 registration - scopes requested that may not be granted"
+                    );
+                    return Err(Oauth2Error::InvalidClientMetadata);
+                }
+
+                self.scope_maps
+                    .iter()
+                    .map(|(group, scopes)| {
+                        let scopes: BTreeSet<String> = scopes
+                            .iter()
+                            .filter(|s| requested.contains(s.as_str()))
+                            .cloned()
+                            .collect();
+                        (*group, scopes)
+                    })
+                    .filter(|(_, scopes)| !scopes.is_empty())
+                    .collect()
+            }
+            None => self.scope_maps.clone(),
+        };
+
+        let scope_maps = scope_maps
+            .into_iter()
+            .map(|(group, scopes)| Value::new_oauthscopemap(group, scopes))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(Oauth2Error::InvalidClientMetadata)?;
+
+        Ok((origin, scope_maps))
+    }
+}
+
+/// A logout token to be delivered to a resource server after a user's session ends.
+#[derive(Debug)]
+pub struct Oauth2BackchannelLogout {
+    pub backchannel_logout_uri: Url,
+    pub logout_token: String,
+}
+
 #[derive(Clone)]
 pub struct Oauth2RS {
     name: String,
@@ -210,10 +527,24 @@ pub struct Oauth2RS {
     // For discovery we need to build and keep a number of values.
     authorization_endpoint: Url,
     token_endpoint: Url,
+    device_authorization_endpoint: Url,
     userinfo_endpoint: Url,
     jwks_uri: Url,
     scopes_supported: BTreeSet<String>,
     prefer_short_username: bool,
+    // The resource servers this one may exchange access tokens for.
+    token_exchange_audiences: BTreeSet<Uuid>,
+    // If authorisation requests must be pushed to us before the user is redirected.
+    require_par: bool,
+    par_endpoint: Url,
+    // The keys the client signs request objects with.
+    client_jwks: Vec<Jwk>,
+    end_session_endpoint: Url,
+    registration_endpoint: Url,
+    // Where we tell the client that a user's session has ended.
+    backchannel_logout_uri: Option<Url>,
+    // The service account that client credential grants act as.
+    service_account: Option<Uuid>,
 }
 
 impl std::fmt::Debug for Oauth2RS {
@@ -238,14 +569,27 @@ struct Oauth2RSInner {
 
 pub struct Oauth2ResourceServers {
     inner: CowCell<Oauth2RSInner>,
+    // Pending device authorisations, keyed by their user code.
+    device_auths: BptreeMap<String, DeviceAuthorisation>,
+    // Pushed authorisation requests, keyed by their request_uri.
+    par_requests: BptreeMap<String, PushedAuthorisation>,
+    // The client assertions and pushed request objects we have seen, keyed by client_id
+    // and jti, with their expiry. This is deliberately not transactional - a jti is spent
+    // once it's presented, even if the transaction that checked it is never committed.
+    client_assertion_jtis: Mutex<BTreeMap<String, Duration>>,
 }
 
-pub struct Oauth2ResourceServersReadTransaction {
+pub struct Oauth2ResourceServersReadTransaction<'a> {
     inner: CowCellReadTxn<Oauth2RSInner>,
+    device_auths: BptreeMapReadTxn<'a, String, DeviceAuthorisation>,
+    par_requests: BptreeMapReadTxn<'a, String, PushedAuthorisation>,
 }
 
 pub struct Oauth2ResourceServersWriteTransaction<'a> {
     inner: CowCellWriteTxn<'a, Oauth2RSInner>,
+    device_auths: BptreeMapWriteTxn<'a, String, DeviceAuthorisation>,
+    par_requests: BptreeMapWriteTxn<'a, String, PushedAuthorisation>,
+    client_assertion_jtis: &'a Mutex<BTreeMap<String, Duration>>,
 }
 
 impl TryFrom<(Vec<Arc<EntrySealedCommitted>>, Url)> for Oauth2ResourceServers {
@@ -261,6 +605,9 @@ impl TryFrom<(Vec<Arc<EntrySealedCommitted>>, Url)> for Oauth2ResourceServers {
                 fernet,
                 rs_set: HashMap::new(),
             }),
+            device_auths: BptreeMap::new(),
+            par_requests: BptreeMap::new(),
+            client_assertion_jtis: Mutex::new(BTreeMap::new()),
         };
 
         let mut oauth2rs_wr = oauth2rs.write();
@@ -274,12 +621,17 @@ impl Oauth2ResourceServers {
     pub fn read(&self) -> Oauth2ResourceServersReadTransaction {
         Oauth2ResourceServersReadTransaction {
             inner: self.inner.read(),
+            device_auths: self.device_auths.read(),
+            par_requests: self.par_requests.read(),
         }
     }
 
     pub fn write(&self) -> Oauth2ResourceServersWriteTransaction {
         Oauth2ResourceServersWriteTransaction {
             inner: self.inner.write(),
+            device_auths: self.device_auths.write(),
+            par_requests: self.par_requests.write(),
+            client_assertion_jtis: &self.client_assertion_jtis,
         }
     }
 }
@@ -390,18 +742,61 @@ impl<'a> Oauth2ResourceServersWriteTransaction<'a> {
                         .get_ava_single_bool("oauth2_prefer_short_username")
                         .unwrap_or(false);
 
+                    trace!("token_exchange_audiences");
+                    let token_exchange_audiences = ent
+                        .get_ava_refer("oauth2_rs_token_exchange_audience")
+                        .cloned()
+                        .unwrap_or_default();
+
+                    let require_par = ent
+                        .get_ava_single_bool("oauth2_require_par")
+                        .unwrap_or(false);
+
+                    trace!("client_jwks");
+                    let client_jwks: Vec<Jwk> = ent
+                        .get_ava_iter_utf8("oauth2_rs_client_jwk")
+                        .map(|iter| {
+                            iter.filter_map(|jwk_str| {
+                                serde_json::from_str(jwk_str)
+                                    .map_err(|e| {
+                                        warn!(?e, "{} has a client jwk that could not be parsed, ignoring", name);
+                                    })
+                                    .ok()
+                            })
+                            .collect()
+                        })
+                        .unwrap_or_default();
+
+                    let backchannel_logout_uri = ent
+                        .get_ava_single_url("oauth2_rs_backchannel_logout_uri")
+                        .cloned();
+
+                    let service_account = ent.get_ava_single_refer("oauth2_rs_service_account");
+
                     let mut authorization_endpoint = self.inner.origin.clone();
                     authorization_endpoint.set_path("/ui/oauth2");
 
                     let mut token_endpoint = self.inner.origin.clone();
                     token_endpoint.set_path("/oauth2/token");
 
+                    let mut device_authorization_endpoint = self.inner.origin.clone();
+                    device_authorization_endpoint.set_path("/oauth2/device");
+
+                    let mut par_endpoint = self.inner.origin.clone();
+                    par_endpoint.set_path("/oauth2/par");
+
                     let mut userinfo_endpoint = self.inner.origin.clone();
                     userinfo_endpoint.set_path(&format!("/oauth2/openid/{name}/userinfo"));
 
                     let mut jwks_uri = self.inner.origin.clone();
                     jwks_uri.set_path(&format!("/oauth2/openid/{name}/public_key.jwk"));
 
+                    let mut end_session_endpoint = self.inner.origin.clone();
+                    end_session_endpoint.set_path(&format!("/oauth2/openid/{name}/end_session"));
+
+                    let mut registration_endpoint = self.inner.origin.clone();
+                    registration_endpoint.set_path("/oauth2/register");
+
                     let mut iss = self.inner.origin.clone();
                     iss.set_path(&format!("/oauth2/openid/{name}"));
 
@@ -436,10 +831,19 @@ impl<'a> Oauth2ResourceServersWriteTransaction<'a> {
                         iss,
                         authorization_endpoint,
                         token_endpoint,
+                        device_authorization_endpoint,
                         userinfo_endpoint,
                         jwks_uri,
                         scopes_supported,
                         prefer_short_username,
+                        token_exchange_audiences,
+                        require_par,
+                        par_endpoint,
+                        client_jwks,
+                        end_session_endpoint,
+                        registration_endpoint,
+                        backchannel_logout_uri,
+                        service_account,
                     };
 
                     Ok((client_id, rscfg))
@@ -457,7 +861,31 @@ impl<'a> Oauth2ResourceServersWriteTransaction<'a> {
         })
     }
 
+    // Record that a client has used this jti, returning false if it has been seen before.
+    // It's remembered until the token carrying it expires, after which the token itself
+    // would be rejected.
+    fn record_jti(&self, client_id: &str, jti: &str, exp: Duration, ct: Duration) -> bool {
+        // The map only holds expiry times, so it remains usable even if a holder panicked.
+        let mut jtis = self
+            .client_assertion_jtis
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        // Clean up anything that has expired while we are here.
+        jtis.retain(|_, jti_exp| *jti_exp > ct);
+
+        let jti = format!("{client_id}:{jti}");
+        if jtis.contains_key(&jti) {
+            false
+        } else {
+            jtis.insert(jti, exp);
+            true
+        }
+    }
+
     pub fn commit(self) {
+        self.device_auths.commit();
+        self.par_requests.commit();
         self.inner.commit();
     }
 }
@@ -513,6 +941,12 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
                 expiry,
                 uuid,
                 ..
+            }
+            | Oauth2TokenType::ClientAccess {
+                session_id,
+                expiry,
+                uuid,
+                ..
             } => {
                 // Only submit a revocation if the token is not yet expired.
                 let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
@@ -544,25 +978,90 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         }
     }
 
+    /// Build the logout tokens for each resource server that holds an oauth2 session
+    /// descended from this user session. This must be called before the user session is
+    /// removed, as that also removes the oauth2 sessions we are looking for.
+    pub fn oauth2_backchannel_logouts(
+        &mut self,
+        account_uuid: Uuid,
+        session_id: Uuid,
+        ct: Duration,
+    ) -> Result<Vec<Oauth2BackchannelLogout>, OperationError> {
+        let entry = self.qs_write.internal_search_uuid(account_uuid)?;
+
+        // Sessions created by token exchange are children of other oauth2 sessions, so
+        // follow these too.
+        let mut parents = BTreeSet::from([session_id]);
+        let mut rs_uuids = BTreeSet::new();
+        if let Some(oauth2_sessions) = entry.get_ava_as_oauth2session_map("oauth2_session") {
+            loop {
+                let children: Vec<_> = oauth2_sessions
+                    .iter()
+                    .filter(|(o2_session_id, session)| {
+                        !parents.contains(*o2_session_id) && parents.contains(&session.parent)
+                    })
+                    .map(|(o2_session_id, session)| (*o2_session_id, session.rs_uuid))
+                    .collect();
+
+                if children.is_empty() {
+                    break;
+                }
+
+                for (o2_session_id, rs_uuid) in children {
+                    parents.insert(o2_session_id);
+                    rs_uuids.insert(rs_uuid);
+                }
+            }
+        }
+
+        let iat = ct.as_secs() as i64;
+
+        self.oauth2rs
+            .inner
+            .rs_set
+            .values()
+            .filter(|o2rs| rs_uuids.contains(&o2rs.uuid))
+            .filter_map(|o2rs| {
+                o2rs.backchannel_logout_uri
+                    .as_ref()
+                    .map(|backchannel_logout_uri| (o2rs, backchannel_logout_uri))
+            })
+            .map(|(o2rs, backchannel_logout_uri)| {
+                let logout_token = LogoutToken {
+                    iss: o2rs.iss.clone(),
+                    sub: OidcSubject::U(account_uuid),
+                    aud: o2rs.name.clone(),
+                    iat,
+                    jti: Uuid::new_v4(),
+                    events: serde_json::json!({
+                        "http://schemas.openid.net/event/backchannel-logout": {}
+                    }),
+                    sid: session_id,
+                };
+
+                trace!(?logout_token);
+
+                Jws::new(logout_token)
+                    .sign(&o2rs.jws_signer)
+                    .map(|jws_signed| Oauth2BackchannelLogout {
+                        backchannel_logout_uri: backchannel_logout_uri.clone(),
+                        logout_token: jws_signed.to_string(),
+                    })
+                    .map_err(|e| {
+                        admin_error!(err = ?e, "Unable to sign logout token");
+                        OperationError::CryptographyError
+                    })
+            })
+            .collect()
+    }
+
     pub fn check_oauth2_token_exchange(
         &mut self,
         client_authz: Option<&str>,
         token_req: &AccessTokenRequest,
         ct: Duration,
     ) -> Result<AccessTokenResponse, Oauth2Error> {
-        let (client_id, secret) = if let Some(client_authz) = client_authz {
-            parse_basic_authz(client_authz)?
-        } else {
-            match (&token_req.client_id, &token_req.client_secret) {
-                (Some(a), Some(b)) => (a.clone(), b.clone()),
-                _ => {
-                    security_info!(
-                        "Invalid oauth2 authentication - no basic auth or missing auth post data"
-                    );
-                    return Err(Oauth2Error::AuthenticationRequired);
-                }
-            }
-        };
+        let client_id = self.check_oauth2_token_client_auth(client_authz, token_req, ct)?;
 
         // DANGER: Why do we have to do this? During the use of qs for internal search
         // and other operations we need qs to be mut. But when we borrow oauth2rs here we
@@ -578,12 +1077,6 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
             &*(s as *const _)
         };
 
-        // check the secret.
-        if o2rs.authz_secret != secret {
-            security_info!("Invalid oauth2 client_id secret");
-            return Err(Oauth2Error::AuthenticationRequired);
-        }
-
         // We are authenticated! Yay! Now we can actually check things ...
 
         // TODO: add refresh token grant type.
@@ -605,1511 +1098,4075 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
                 refresh_token,
                 scope,
             } => self.check_oauth2_token_refresh(o2rs, refresh_token, scope.as_ref(), ct),
+            GrantTypeReq::ClientCredentials { scope } => {
+                self.check_oauth2_token_client_credentials(o2rs, scope.as_deref(), ct)
+            }
+            GrantTypeReq::DeviceCode { device_code } => {
+                self.check_oauth2_token_device_code(o2rs, device_code, ct)
+            }
+            GrantTypeReq::TokenExchange {
+                subject_token,
+                subject_token_type,
+                audience,
+                scope,
+                requested_token_type,
+            } => self.check_oauth2_token_exchange_access_token(
+                o2rs,
+                subject_token,
+                subject_token_type,
+                audience,
+                scope.as_deref(),
+                requested_token_type.as_deref(),
+                ct,
+            ),
         }
     }
 
-    pub fn check_oauth2_authorise_permit(
+    /// Authenticate the client at the token endpoint, returning its client_id.
+    fn check_oauth2_token_client_auth(
         &mut self,
-        ident: &Identity,
-        uat: &UserAuthToken,
-        consent_token: &str,
+        client_authz: Option<&str>,
+        token_req: &AccessTokenRequest,
         ct: Duration,
-    ) -> Result<AuthorisePermitSuccess, OperationError> {
-        // Decode the consent req with our system fernet key. Use a ttl of 5 minutes.
-        let consent_req: ConsentToken = self
-            .oauth2rs
-            .inner
-            .fernet
-            .decrypt_at_time(consent_token, Some(300), ct.as_secs())
-            .map_err(|_| {
-                admin_error!("Failed to decrypt consent request");
-                OperationError::CryptographyError
-            })
-            .and_then(|data| {
-                serde_json::from_slice(&data).map_err(|e| {
-                    admin_error!(err = ?e, "Failed to deserialise consent request");
-                    OperationError::SerdeJsonError
-                })
-            })?;
-
-        // Validate that the ident_id matches our current ident.
-        if consent_req.ident_id != ident.get_event_origin_id() {
-            security_info!("consent request ident id does not match the identity of our UAT.");
-            return Err(OperationError::InvalidSessionState);
-        }
+    ) -> Result<String, Oauth2Error> {
+        match (
+            token_req.client_assertion_type.as_deref(),
+            token_req.client_assertion.as_deref(),
+        ) {
+            (None, None) => {
+                let (client_id, secret) = parse_client_authz(
+                    client_authz,
+                    token_req.client_id.as_deref(),
+                    token_req.client_secret.as_deref(),
+                )?;
+
+                let o2rs = self.oauth2rs.inner.rs_set.get(&client_id).ok_or_else(|| {
+                    admin_warn!("Invalid oauth2 client_id");
+                    Oauth2Error::AuthenticationRequired
+                })?;
+
+                // check the secret.
+                if o2rs.authz_secret != secret {
+                    security_info!("Invalid oauth2 client_id secret");
+                    return Err(Oauth2Error::AuthenticationRequired);
+                }
 
-        // Validate that the session id matches our uat.
-        if consent_req.session_id != uat.session_id {
-            security_info!("consent request session id does not match the session id of our UAT.");
-            return Err(OperationError::InvalidSessionState);
+                Ok(client_id)
+            }
+            (Some(OAUTH2_CLIENT_ASSERTION_TYPE_JWT_BEARER), Some(client_assertion)) => {
+                // https://datatracker.ietf.org/doc/html/rfc6749#section-2.3
+                // Only one authentication method may be used.
+                if client_authz.is_some() || token_req.client_secret.is_some() {
+                    admin_warn!("Invalid oauth2 client authentication - client_assertion can not be combined with a secret");
+                    return Err(Oauth2Error::InvalidRequest);
+                }
+                self.check_oauth2_client_assertion(
+                    token_req.client_id.as_deref(),
+                    client_assertion,
+                    ct,
+                )
+            }
+            _ => {
+                admin_warn!("Invalid oauth2 client authentication - unsupported or incomplete client_assertion");
+                Err(Oauth2Error::InvalidRequest)
+            }
         }
+    }
 
-        // Get the resource server config based on this client_id.
-        let o2rs = self
-            .oauth2rs
-            .inner
-            .rs_set
-            .get(&consent_req.client_id)
-            .ok_or_else(|| {
-                admin_error!("Invalid consent request oauth2 client_id");
-                OperationError::InvalidRequestState
-            })?;
+    // Handles both private_key_jwt, where the assertion is signed by one of the client's
+    // jwks, and client_secret_jwt, where it is signed with the client secret.
+    // https://openid.net/specs/openid-connect-core-1_0.html#ClientAuthentication
+    fn check_oauth2_client_assertion(
+        &mut self,
+        client_id: Option<&str>,
+        client_assertion: &str,
+        ct: Duration,
+    ) -> Result<String, Oauth2Error> {
+        let jwsu = JwsUnverified::from_str(client_assertion).map_err(|e| {
+            admin_warn!(?e, "Invalid oauth2 client assertion - unable to decode");
+            Oauth2Error::AuthenticationRequired
+        })?;
 
-        // Extract the state, code challenge, redirect_uri
-        let xchg_code = TokenExchangeCode {
-            uat: uat.clone(),
-            code_challenge: consent_req.code_challenge,
-            redirect_uri: consent_req.redirect_uri.clone(),
-            scopes: consent_req.scopes.clone(),
-            nonce: consent_req.nonce,
+        // client_id is optional with an assertion, so we may need to find the client from the
+        // unverified issuer. This only selects the keys to validate with, and is checked again
+        // against the validated claims.
+        let client_id = match client_id {
+            Some(client_id) => client_id.to_string(),
+            None => unverified_jwt_issuer(client_assertion)?,
         };
 
-        // Encrypt the exchange token with the fernet key of the client resource server
-        let code_data = serde_json::to_vec(&xchg_code).map_err(|e| {
-            admin_error!(err = ?e, "Unable to encode xchg_code data");
-            OperationError::SerdeJsonError
+        let o2rs = self.oauth2rs.inner.rs_set.get(&client_id).ok_or_else(|| {
+            admin_warn!("Invalid oauth2 client_id");
+            Oauth2Error::AuthenticationRequired
         })?;
 
-        let code = o2rs.token_fernet.encrypt_at_time(&code_data, ct.as_secs());
+        let secret_validator = JwsSigner::from_hs256_raw(o2rs.authz_secret.as_bytes())
+            .and_then(|signer| signer.get_validator())
+            .map_err(|e| {
+                admin_error!(?e, "Unable to create client secret validator");
+            })
+            .ok();
 
-        // Everything is DONE! Now submit that it's all happy and the user consented correctly.
-        // this will let them bypass consent steps in the future.
-        // Submit that we consented to the delayed action queue
+        let assertion = o2rs
+            .client_jwks
+            .iter()
+            .filter_map(|jwk| JwsValidator::try_from(jwk).ok())
+            .chain(secret_validator)
+            .find_map(|jws_validator| jwsu.validate(&jws_validator).ok())
+            .map(|jws: Jws<ClientAssertion>| jws.into_inner())
+            .ok_or_else(|| {
+                security_info!(?o2rs.name, "Invalid oauth2 client assertion - signature not valid");
+                Oauth2Error::AuthenticationRequired
+            })?;
 
-        let modlist = ModifyList::new_list(vec![
-            Modify::Removed(
-                AttrString::from("oauth2_consent_scope_map"),
-                PartialValue::Refer(o2rs.uuid),
-            ),
-            Modify::Present(
-                AttrString::from("oauth2_consent_scope_map"),
-                Value::OauthScopeMap(o2rs.uuid, consent_req.scopes.iter().cloned().collect()),
-            ),
-        ]);
+        if assertion.iss != client_id || assertion.sub != client_id {
+            security_info!("Invalid oauth2 client assertion - iss and sub must be the client_id");
+            return Err(Oauth2Error::AuthenticationRequired);
+        }
 
-        self.qs_write.internal_modify(
-            &filter_all!(f_eq("uuid", PartialValue::Uuid(uat.uuid))),
-            &modlist,
-        )?;
+        if !aud_contains(
+            &assertion.aud,
+            &[&o2rs.token_endpoint, &o2rs.iss, &self.oauth2rs.inner.origin],
+        ) {
+            security_info!("Invalid oauth2 client assertion - aud is not this server");
+            return Err(Oauth2Error::AuthenticationRequired);
+        }
 
-        Ok(AuthorisePermitSuccess {
-            redirect_uri: consent_req.redirect_uri,
-            state: consent_req.state,
-            code,
-        })
+        let exp = Duration::from_secs(assertion.exp.max(0) as u64);
+        if exp <= ct {
+            security_info!("Invalid oauth2 client assertion - expired");
+            return Err(Oauth2Error::AuthenticationRequired);
+        }
+
+        if exp > ct + Duration::from_secs(OAUTH2_CLIENT_ASSERTION_MAX_EXPIRY as u64) {
+            security_info!("Invalid oauth2 client assertion - expiry is too far in the future");
+            return Err(Oauth2Error::AuthenticationRequired);
+        }
+
+        // Each assertion may only be used once.
+        if !self
+            .oauth2rs
+            .record_jti(&client_id, &assertion.jti, exp, ct)
+        {
+            security_info!(
+                ?client_id,
+                "Invalid oauth2 client assertion - jti has been used before"
+            );
+            return Err(Oauth2Error::AuthenticationRequired);
+        }
+
+        Ok(client_id)
     }
 
-    fn check_oauth2_token_exchange_authorization_code(
+    pub fn check_oauth2_device_authorise(
         &mut self,
-        o2rs: &Oauth2RS,
-        token_req_code: &str,
-        token_req_redirect_uri: &Url,
-        token_req_code_verifier: Option<&str>,
+        client_authz: Option<&str>,
+        device_req: &DeviceAuthorisationRequest,
         ct: Duration,
-    ) -> Result<AccessTokenResponse, Oauth2Error> {
-        // Check the token_req is within the valid time, and correctly signed for
-        // this client.
+    ) -> Result<DeviceAuthorisationResponse, Oauth2Error> {
+        let (client_id, secret) = parse_client_authz(
+            client_authz,
+            device_req.client_id.as_deref(),
+            device_req.client_secret.as_deref(),
+        )?;
 
-        let code_xchg: TokenExchangeCode = o2rs
-            .token_fernet
-            .decrypt_at_time(token_req_code, Some(60), ct.as_secs())
-            .map_err(|_| {
-                admin_error!("Failed to decrypt token exchange request");
-                Oauth2Error::InvalidRequest
-            })
-            .and_then(|data| {
-                serde_json::from_slice(&data).map_err(|e| {
-                    admin_error!("Failed to deserialise token exchange code - {:?}", e);
-                    Oauth2Error::InvalidRequest
-                })
-            })?;
+        let o2rs = self.oauth2rs.inner.rs_set.get(&client_id).ok_or_else(|| {
+            admin_warn!("Invalid oauth2 client_id");
+            Oauth2Error::AuthenticationRequired
+        })?;
 
-        // If we have a verifier present, we MUST assert that a code challenge is present!
-        // It is worth noting here that code_xchg is *server issued* and encrypted, with
-        // a short validity period. The client controlled value is in token_req.code_verifier
-        if let Some(code_challenge) = code_xchg.code_challenge {
-            // Validate the code_verifier
-            let code_verifier = token_req_code_verifier
-                    .ok_or_else(|| {
-                        security_info!("PKCE code verification failed - code challenge is present, but no verifier was provided");
-                        Oauth2Error::InvalidRequest
-                    })?;
-            let mut hasher = sha::Sha256::new();
-            hasher.update(code_verifier.as_bytes());
-            let code_verifier_hash: Vec<u8> = hasher.finish().to_vec();
+        // check the secret.
+        if o2rs.authz_secret != secret {
+            security_info!("Invalid oauth2 client_id secret");
+            return Err(Oauth2Error::AuthenticationRequired);
+        }
 
-            if code_challenge.0 != code_verifier_hash {
-                security_info!(
-                    "PKCE code verification failed - this may indicate malicious activity"
-                );
-                return Err(Oauth2Error::InvalidRequest);
-            }
-        } else if o2rs.enable_pkce {
-            security_info!(
-                "PKCE code verification failed - no code challenge present in PKCE enforced mode"
-            );
+        let req_scopes: BTreeSet<String> = device_req
+            .scope
+            .split_ascii_whitespace()
+            .map(str::to_string)
+            .collect();
+        if req_scopes.is_empty() {
+            admin_error!("Invalid oauth2 request - must contain at least one requested scope");
             return Err(Oauth2Error::InvalidRequest);
-        } else if token_req_code_verifier.is_some() {
-            security_info!(
-                "PKCE code verification failed - a code verifier is present, but no code challenge in exchange"
+        }
+
+        if !req_scopes.iter().all(|s| OAUTHSCOPE_RE.is_match(s)) {
+            admin_error!(
+                "Invalid oauth2 request - requested scopes failed to pass validation rules"
             );
-            return Err(Oauth2Error::InvalidRequest);
+            return Err(Oauth2Error::InvalidScope);
         }
 
-        // Validate the redirect_uri is the same as the original.
-        if token_req_redirect_uri != &code_xchg.redirect_uri {
-            security_info!("Invalid oauth2 redirect_uri (differs from original request uri)");
-            return Err(Oauth2Error::InvalidOrigin);
+        // Clean up anything that has expired while we are here.
+        self.prune_oauth2_device_auths(ct);
+
+        // Limit how many outstanding device codes a single client may hold, so that
+        // a misbehaving client can't grow this set without bound.
+        let outstanding = self
+            .oauth2rs
+            .device_auths
+            .values()
+            .filter(|dev_auth| dev_auth.client_id == client_id)
+            .count();
+        if outstanding >= OAUTH2_DEVICE_CODE_MAX_PER_CLIENT {
+            security_info!(?client_id, "Too many outstanding device authorisations");
+            return Err(Oauth2Error::SlowDown);
         }
 
-        // Check that the UAT we are issuing for still is valid.
-        let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
-        if let Some(expiry) = code_xchg.uat.expiry {
-            if expiry <= odt_ct {
-                security_info!(
-                    "User Auth Token has expired before we could publish the oauth2 response"
-                );
-                return Err(Oauth2Error::AccessDenied);
+        let user_code = loop {
+            let user_code = user_code_from_random();
+            if self.oauth2rs.device_auths.get(&user_code).is_none() {
+                break user_code;
             }
-        }
+        };
 
-        // ==== We are now GOOD TO GO! ====
-        // Grant the access token response.
-        let parent_session_id = code_xchg.uat.session_id;
-        let session_id = Uuid::new_v4();
+        let code_data = serde_json::to_vec(&DeviceCode {
+            user_code: user_code.clone(),
+        })
+        .map_err(|e| {
+            admin_error!(err = ?e, "Unable to encode device code data");
+            Oauth2Error::ServerError(OperationError::SerdeJsonError)
+        })?;
 
-        let scopes = code_xchg.scopes;
-        let account_uuid = code_xchg.uat.uuid;
-        let nonce = code_xchg.nonce;
+        let device_code = o2rs.token_fernet.encrypt_at_time(&code_data, ct.as_secs());
 
-        self.generate_access_token_response(
-            o2rs,
-            ct,
-            scopes,
-            account_uuid,
-            parent_session_id,
-            session_id,
-            nonce,
-        )
+        let mut verification_uri = self.oauth2rs.inner.origin.clone();
+        verification_uri.set_path("/ui/oauth2/device");
+
+        let mut verification_uri_complete = verification_uri.clone();
+        verification_uri_complete
+            .query_pairs_mut()
+            .append_pair("user_code", &user_code);
+
+        security_info!(?client_id, "oauth2 device authorisation started");
+
+        self.oauth2rs.device_auths.insert(
+            user_code.clone(),
+            DeviceAuthorisation {
+                client_id,
+                scopes: req_scopes,
+                expiry: ct + Duration::from_secs(OAUTH2_DEVICE_CODE_EXPIRY as u64),
+                interval: OAUTH2_DEVICE_CODE_INTERVAL,
+                last_poll: None,
+                state: DeviceAuthorisationState::Pending,
+            },
+        );
+
+        Ok(DeviceAuthorisationResponse {
+            device_code,
+            user_code,
+            verification_uri,
+            verification_uri_complete,
+            expires_in: OAUTH2_DEVICE_CODE_EXPIRY,
+            interval: OAUTH2_DEVICE_CODE_INTERVAL,
+        })
     }
 
-    fn check_oauth2_token_refresh(
+    pub fn check_oauth2_pushed_authorisation(
         &mut self,
-        o2rs: &Oauth2RS,
-        refresh_token: &str,
-        req_scopes: Option<&BTreeSet<String>>,
+        client_authz: Option<&str>,
+        par_req: &PushedAuthorisationRequest,
         ct: Duration,
-    ) -> Result<AccessTokenResponse, Oauth2Error> {
-        // Validate the refresh token decrypts and it's expiry is within the valid window.
-        let token: Oauth2TokenType = o2rs
-            .token_fernet
-            .decrypt(refresh_token)
-            .map_err(|_| {
-                admin_error!("Failed to decrypt refresh token request");
-                Oauth2Error::InvalidRequest
-            })
-            .and_then(|data| {
-                serde_json::from_slice(&data).map_err(|e| {
-                    admin_error!("Failed to deserialise token - {:?}", e);
-                    Oauth2Error::InvalidRequest
-                })
-            })?;
+    ) -> Result<PushedAuthorisationResponse, Oauth2Error> {
+        let (req_client_id, request_uri, request) = match &par_req.request {
+            AuthorisationRequestKind::Request(auth_req) => (
+                auth_req.client_id.as_str(),
+                auth_req
+                    .unknown_keys
+                    .get("request_uri")
+                    .and_then(|v| v.as_str()),
+                auth_req
+                    .unknown_keys
+                    .get("request")
+                    .and_then(|v| v.as_str()),
+            ),
+            AuthorisationRequestKind::Reference(auth_ref) => (
+                auth_ref.client_id.as_str(),
+                auth_ref.request_uri.as_deref(),
+                auth_ref.request.as_deref(),
+            ),
+        };
 
-        match token {
-            Oauth2TokenType::Access { .. } => {
-                admin_error!("attempt to refresh with access token");
-                Err(Oauth2Error::InvalidToken)
-            }
-            Oauth2TokenType::Refresh {
-                scopes,
-                parent_session_id,
-                session_id,
-                expiry,
-                uuid,
-                iat,
-                nbf: _,
-            } => {
-                // Get the current time in odt
-                let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
-                if expiry <= odt_ct {
-                    security_info!(?uuid, "refresh token has expired, ");
-                    return Err(Oauth2Error::InvalidToken);
-                }
+        let (client_id, secret) = parse_client_authz(
+            client_authz,
+            Some(req_client_id),
+            par_req.client_secret.as_deref(),
+        )?;
 
-                // Check the session is still valid. This call checks the parent session
-                // and the oauth2 session.
-                let valid = self
-                    .check_oauth2_account_uuid_valid(uuid, session_id, parent_session_id, iat, ct)
-                    .map_err(|_| admin_error!("Account is not valid"));
+        let o2rs = self.oauth2rs.inner.rs_set.get(&client_id).ok_or_else(|| {
+            admin_warn!("Invalid oauth2 client_id");
+            Oauth2Error::AuthenticationRequired
+        })?;
 
-                let entry = match valid {
-                    Ok(Some(entry)) => entry,
-                    _ => {
-                        security_info!(
-                            ?uuid,
-                            "access token has no account not valid, returning inactive"
-                        );
-                        return Err(Oauth2Error::InvalidToken);
-                    }
-                };
+        // check the secret.
+        if o2rs.authz_secret != secret {
+            security_info!("Invalid oauth2 client_id secret");
+            return Err(Oauth2Error::AuthenticationRequired);
+        }
 
-                // Check the not issued before of the session relative to this refresh iat
-                let oauth2_session = entry
-                    .get_ava_as_oauth2session_map("oauth2_session")
-                    .and_then(|map| map.get(&session_id))
-                    .ok_or_else(|| {
-                        security_info!(
-                            ?session_id,
-                            "No oauth2 session found, unable to proceed with refresh"
-                        );
-                        Oauth2Error::InvalidToken
-                    })?;
+        // https://datatracker.ietf.org/doc/html/rfc9126#section-2.1
+        // A pushed request can't itself refer to an earlier pushed request.
+        if request_uri.is_some() {
+            admin_warn!("Invalid oauth2 pushed request - request_uri is not permitted");
+            return Err(Oauth2Error::InvalidRequest);
+        }
 
-                // If the refresh token was issued previous to the time listed in our oauth2_session
-                // this indicates session desync / replay. We must nuke the session at this point.
-                //
-                // Need to think about how to handle this nicely give transactions.
-                if iat < oauth2_session.issued_at.unix_timestamp() {
+        let mut auth_req = match (request, &par_req.request) {
+            (Some(request), _) => {
+                let request_object =
+                    verify_request_object(o2rs, &self.oauth2rs.inner.origin, request, ct)?;
+
+                // Unlike a request object sent directly, which the user agent may need to
+                // resubmit after login, a pushed one can only be pushed once.
+                let jti = request_object.jti.as_deref().ok_or_else(|| {
+                    security_info!("Invalid oauth2 pushed request object - jti is required");
+                    Oauth2Error::InvalidRequestObject
+                })?;
+                let exp = Duration::from_secs(request_object.exp.max(0) as u64);
+                if !self.oauth2rs.record_jti(&client_id, jti, exp, ct) {
                     security_info!(
-                        ?session_id,
-                        "Attempt to reuse a refresh token detected, destroying session"
+                        ?client_id,
+                        "Invalid oauth2 pushed request object - jti has been used before"
                     );
+                    return Err(Oauth2Error::InvalidRequestObject);
+                }
 
-                    // Revoke it
-                    let modlist = ModifyList::new_list(vec![Modify::Removed(
-                        AttrString::from("oauth2_session"),
-                        PartialValue::Refer(session_id),
-                    )]);
+                request_object.request
+            }
+            (None, AuthorisationRequestKind::Request(auth_req)) => auth_req.clone(),
+            (None, AuthorisationRequestKind::Reference(_)) => {
+                admin_warn!("Invalid oauth2 pushed request - no authorisation parameters provided");
+                return Err(Oauth2Error::InvalidRequest);
+            }
+        };
+        auth_req.unknown_keys.remove("request");
 
-                    self.qs_write
-                        .internal_modify(&filter!(f_eq("uuid", PartialValue::Uuid(uuid))), &modlist)
-                        .map_err(|e| {
-                            admin_error!("Failed to modify - revoke oauth2 session {:?}", e);
-                            Oauth2Error::ServerError(e)
-                        })?;
+        if auth_req.client_id != client_id {
+            security_info!(
+                "Invalid oauth2 pushed request - client_id does not match the authenticated client"
+            );
+            return Err(Oauth2Error::InvalidRequest);
+        }
 
-                    return Err(Oauth2Error::InvalidGrant);
-                }
+        // Reject what we can now, so the client finds out before the user is redirected. The
+        // remaining checks need the user and happen at the authorisation endpoint.
+        if auth_req.response_type != "code" {
+            admin_warn!("Invalid oauth2 response_type (should be 'code')");
+            return Err(Oauth2Error::UnsupportedResponseType);
+        }
 
-                // Check the scopes are identical, or None.
-                if let Some(req_scopes) = req_scopes {
-                    if &scopes != req_scopes {
-                        warn!("oauth2 scopes requested, invalid.");
-                        return Err(Oauth2Error::InvalidScope);
-                    } else {
-                        debug!("oauth2 scopes requested, checked as valid.");
-                    }
-                } else {
-                    debug!("No oauth2 scopes requested, this is valid.");
-                };
+        if auth_req.redirect_uri.origin() != o2rs.origin {
+            admin_warn!(
+                origin = ?o2rs.origin,
+                "Invalid oauth2 redirect_uri (must be related to origin {:?}) - got {:?}",
+                o2rs.origin,
+                auth_req.redirect_uri.origin()
+            );
+            return Err(Oauth2Error::InvalidRequest);
+        }
 
-                // ----------
-                // good to go
+        // Clean up anything that has expired while we are here.
+        let expired: Vec<String> = self
+            .oauth2rs
+            .par_requests
+            .iter()
+            .filter(|(_, par)| par.expiry <= ct)
+            .map(|(request_uri, _)| request_uri.clone())
+            .collect();
+        expired.iter().for_each(|request_uri| {
+            self.oauth2rs.par_requests.remove(request_uri);
+        });
 
-                let account_uuid = uuid;
-                // Not present now. We could preserve it in the refresh token but
-                // I don't think it is worth much since the nonce exists to protect
-                // the initial auth-code exchange, not a refresh token exchange.
-                let nonce = None;
+        let request_uri = format!("{}{}", OAUTH2_PAR_REQUEST_URI_PREFIX, Uuid::new_v4());
 
-                self.generate_access_token_response(
-                    o2rs,
-                    ct,
-                    scopes,
-                    account_uuid,
-                    parent_session_id,
-                    session_id,
-                    nonce,
-                )
-            }
-        }
+        security_info!(?client_id, "oauth2 authorisation request pushed");
+
+        self.oauth2rs.par_requests.insert(
+            request_uri.clone(),
+            PushedAuthorisation {
+                request: auth_req,
+                expiry: ct + Duration::from_secs(OAUTH2_PAR_EXPIRY as u64),
+            },
+        );
+
+        Ok(PushedAuthorisationResponse {
+            request_uri,
+            expires_in: OAUTH2_PAR_EXPIRY,
+        })
     }
 
-    fn generate_access_token_response(
+    pub fn check_oauth2_device_permit(
         &mut self,
-        o2rs: &Oauth2RS,
+        ident: &Identity,
+        uat: &UserAuthToken,
+        consent_token: &str,
         ct: Duration,
-        //
-        scopes: BTreeSet<String>,
-        account_uuid: Uuid,
-        parent_session_id: Uuid,
-        session_id: Uuid,
-        nonce: Option<String>,
-    ) -> Result<AccessTokenResponse, Oauth2Error> {
-        let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
-        let iat = ct.as_secs() as i64;
-
-        // TODO: Configurable from the oauth2rs configuration?
+    ) -> Result<(), OperationError> {
+        let consent_req = self.decrypt_device_consent_token(ident, uat, consent_token, ct)?;
 
-        // Disclaimer: It may seem odd here that we are ignoring our session when it comes to expiry
-        // times. However, this actually is valid because when we take the initial code exchange
-        // path we have already validate the expiry of the account/session in that process. For the
-        // refresh path, we validate the session expiry and validity before we call this. So these
-        // expiries are *purely* for the tokens we issue and are *not related* to the expiries of the
-        // the session - these are enforced as above!
+        let dev_auth = self
+            .oauth2rs
+            .device_auths
+            .get(&consent_req.user_code)
+            .filter(|dev_auth| {
+                dev_auth.client_id == consent_req.client_id
+                    && dev_auth.expiry > ct
+                    && matches!(dev_auth.state, DeviceAuthorisationState::Pending)
+            })
+            .cloned()
+            .ok_or_else(|| {
+                admin_error!("Device authorisation is no longer pending");
+                OperationError::InvalidRequestState
+            })?;
 
-        let expiry = odt_ct + Duration::from_secs(OAUTH2_ACCESS_TOKEN_EXPIRY as u64);
-        let expires_in = OAUTH2_ACCESS_TOKEN_EXPIRY;
-        let refresh_expiry = odt_ct + Duration::from_secs(OAUTH_REFRESH_TOKEN_EXPIRY);
+        security_info!(client_id = ?consent_req.client_id, "oauth2 device authorisation approved");
 
-        let scope = if scopes.is_empty() {
-            None
-        } else {
-            Some(str_join(&scopes))
-        };
+        self.oauth2rs.device_auths.insert(
+            consent_req.user_code,
+            DeviceAuthorisation {
+                state: DeviceAuthorisationState::Approved {
+                    uat: uat.clone(),
+                    scopes: consent_req.scopes,
+                },
+                ..dev_auth
+            },
+        );
 
-        let id_token = if scopes.contains(&"openid".to_string()) {
-            // TODO: Scopes map to claims:
-            //
-            // * profile - (name, family\_name, given\_name, middle\_name, nickname, preferred\_username, profile, picture, website, gender, birthdate, zoneinfo, locale, and updated\_at)
-            // * email - (email, email\_verified)
-            // * address - (address)
-            // * phone - (phone\_number, phone\_number\_verified)
-            //
-            // https://openid.net/specs/openid-connect-basic-1_0.html#StandardClaims
+        Ok(())
+    }
 
-            // TODO: Can the user consent to which claims are released? Today as we don't support most
-            // of them anyway, no, but in the future, we can stash these to the consent req.
+    pub fn check_oauth2_device_reject(
+        &mut self,
+        ident: &Identity,
+        uat: &UserAuthToken,
+        consent_token: &str,
+        ct: Duration,
+    ) -> Result<(), OperationError> {
+        let consent_req = self.decrypt_device_consent_token(ident, uat, consent_token, ct)?;
 
-            // TODO: If max_age was requested in the request, we MUST provide auth_time.
+        let dev_auth = self
+            .oauth2rs
+            .device_auths
+            .get(&consent_req.user_code)
+            .filter(|dev_auth| dev_auth.client_id == consent_req.client_id)
+            .cloned()
+            .ok_or_else(|| {
+                admin_error!("Device authorisation is no longer pending");
+                OperationError::InvalidRequestState
+            })?;
 
-            // amr == auth method
-            // We removed this from uat, and I think that it's okay here. AMR is a bit useless anyway
-            // since there is no standard for what it should look like wrt to cred strength.
-            let amr = None;
+        self.oauth2rs.device_auths.insert(
+            consent_req.user_code,
+            DeviceAuthorisation {
+                state: DeviceAuthorisationState::Denied,
+                ..dev_auth
+            },
+        );
 
-            let iss = o2rs.iss.clone();
+        Ok(())
+    }
 
-            // Just reflect the access token expiry.
-            let exp = expiry.unix_timestamp();
+    fn decrypt_device_consent_token(
+        &self,
+        ident: &Identity,
+        uat: &UserAuthToken,
+        consent_token: &str,
+        ct: Duration,
+    ) -> Result<DeviceConsentToken, OperationError> {
+        // Decode the consent req with our system fernet key. Use a ttl of 5 minutes.
+        let consent_req: DeviceConsentToken = self
+            .oauth2rs
+            .inner
+            .fernet
+            .decrypt_at_time(consent_token, Some(300), ct.as_secs())
+            .map_err(|_| {
+                admin_error!("Failed to decrypt device consent request");
+                OperationError::CryptographyError
+            })
+            .and_then(|data| {
+                serde_json::from_slice(&data).map_err(|e| {
+                    admin_error!(err = ?e, "Failed to deserialise device consent request");
+                    OperationError::SerdeJsonError
+                })
+            })?;
 
-            let entry = match self.qs_write.internal_search_uuid(account_uuid) {
-                Ok(entry) => entry,
-                Err(err) => return Err(Oauth2Error::ServerError(err)),
-            };
+        // Validate that the ident_id matches our current ident.
+        if consent_req.ident_id != ident.get_event_origin_id() {
+            security_info!("consent request ident id does not match the identity of our UAT.");
+            return Err(OperationError::InvalidSessionState);
+        }
 
-            let account = match Account::try_from_entry_rw(&entry, &mut self.qs_write) {
-                Ok(account) => account,
-                Err(err) => return Err(Oauth2Error::ServerError(err)),
-            };
+        // Validate that the session id matches our uat.
+        if consent_req.session_id != uat.session_id {
+            security_info!("consent request session id does not match the session id of our UAT.");
+            return Err(OperationError::InvalidSessionState);
+        }
 
-            let s_claims = s_claims_for_account(o2rs, &account, &scopes);
-            let extra_claims = extra_claims_for_account(&account, &scopes);
+        Ok(consent_req)
+    }
 
-            let oidc = OidcToken {
-                iss,
-                sub: OidcSubject::U(account_uuid),
-                aud: o2rs.name.clone(),
-                iat,
-                nbf: Some(iat),
-                exp,
-                auth_time: None,
-                nonce,
-                at_hash: None,
-                acr: None,
-                amr,
-                azp: Some(o2rs.name.clone()),
-                jti: None,
-                s_claims,
-                claims: extra_claims,
-            };
+    pub fn check_oauth2_authorise_permit(
+        &mut self,
+        ident: &Identity,
+        uat: &UserAuthToken,
+        consent_token: &str,
+        ct: Duration,
+    ) -> Result<AuthorisePermitSuccess, OperationError> {
+        // Decode the consent req with our system fernet key. Use a ttl of 5 minutes.
+        let consent_req: ConsentToken = self
+            .oauth2rs
+            .inner
+            .fernet
+            .decrypt_at_time(consent_token, Some(300), ct.as_secs())
+            .map_err(|_| {
+                admin_error!("Failed to decrypt consent request");
+                OperationError::CryptographyError
+            })
+            .and_then(|data| {
+                serde_json::from_slice(&data).map_err(|e| {
+                    admin_error!(err = ?e, "Failed to deserialise consent request");
+                    OperationError::SerdeJsonError
+                })
+            })?;
 
-            trace!(?oidc);
+        // Validate that the ident_id matches our current ident.
+        if consent_req.ident_id != ident.get_event_origin_id() {
+            security_info!("consent request ident id does not match the identity of our UAT.");
+            return Err(OperationError::InvalidSessionState);
+        }
 
-            Some(
-                oidc.sign(&o2rs.jws_signer)
-                    .map(|jwt_signed| jwt_signed.to_string())
-                    .map_err(|e| {
-                        admin_error!(err = ?e, "Unable to encode uat data");
-                        Oauth2Error::ServerError(OperationError::InvalidState)
-                    })?,
-            )
-        } else {
-            // id_token is not required in non-openid flows.
-            None
-        };
+        // Validate that the session id matches our uat.
+        if consent_req.session_id != uat.session_id {
+            security_info!("consent request session id does not match the session id of our UAT.");
+            return Err(OperationError::InvalidSessionState);
+        }
 
-        // We need to record this into the record? Delayed action?
+        // Get the resource server config based on this client_id.
+        let o2rs = self
+            .oauth2rs
+            .inner
+            .rs_set
+            .get(&consent_req.client_id)
+            .ok_or_else(|| {
+                admin_error!("Invalid consent request oauth2 client_id");
+                OperationError::InvalidRequestState
+            })?;
 
-        let access_token_raw = Oauth2TokenType::Access {
-            scopes: scopes.clone(),
-            parent_session_id,
-            session_id,
-            expiry,
-            uuid: account_uuid,
-            iat,
-            nbf: iat,
-            auth_time: None,
+        // Extract the state, code challenge, redirect_uri
+        let xchg_code = TokenExchangeCode {
+            uat: uat.clone(),
+            code_challenge: consent_req.code_challenge,
+            redirect_uri: consent_req.redirect_uri.clone(),
+            scopes: consent_req.scopes.clone(),
+            nonce: consent_req.nonce,
+            request_uri: consent_req.request_uri,
         };
 
-        let access_token_data = serde_json::to_vec(&access_token_raw).map_err(|e| {
-            admin_error!(err = ?e, "Unable to encode token data");
-            Oauth2Error::ServerError(OperationError::SerdeJsonError)
-        })?;
-
-        let access_token = o2rs
-            .token_fernet
-            .encrypt_at_time(&access_token_data, ct.as_secs());
-
-        let refresh_token_raw = Oauth2TokenType::Refresh {
-            scopes,
-            parent_session_id,
-            session_id,
-            expiry: refresh_expiry,
-            uuid: account_uuid,
-            iat,
-            nbf: iat,
-        };
-
-        let refresh_token_data = serde_json::to_vec(&refresh_token_raw).map_err(|e| {
-            admin_error!(err = ?e, "Unable to encode token data");
-            Oauth2Error::ServerError(OperationError::SerdeJsonError)
+        // Encrypt the exchange token with the fernet key of the client resource server
+        let code_data = serde_json::to_vec(&xchg_code).map_err(|e| {
+            admin_error!(err = ?e, "Unable to encode xchg_code data");
+            OperationError::SerdeJsonError
         })?;
 
-        let refresh_token = o2rs
-            .token_fernet
-            .encrypt_at_time(&refresh_token_data, ct.as_secs());
+        let code = o2rs.token_fernet.encrypt_at_time(&code_data, ct.as_secs());
 
-        // Write the session to the db even with the refresh path, we need to do
-        // this to update the "not issued before" time.
-        let session = Value::Oauth2Session(
-            session_id,
-            Oauth2Session {
-                parent: parent_session_id,
-                expiry: Some(refresh_expiry),
-                issued_at: odt_ct,
-                rs_uuid: o2rs.uuid,
-            },
-        );
+        // Everything is DONE! Now submit that it's all happy and the user consented correctly.
+        // this will let them bypass consent steps in the future.
+        // Submit that we consented to the delayed action queue
 
-        // We need to update (replace) this session id if present.
         let modlist = ModifyList::new_list(vec![
-            Modify::Removed("oauth2_session".into(), PartialValue::Refer(session_id)),
-            Modify::Present("oauth2_session".into(), session),
+            Modify::Removed(
+                AttrString::from("oauth2_consent_scope_map"),
+                PartialValue::Refer(o2rs.uuid),
+            ),
+            Modify::Present(
+                AttrString::from("oauth2_consent_scope_map"),
+                Value::OauthScopeMap(o2rs.uuid, consent_req.scopes.iter().cloned().collect()),
+            ),
         ]);
 
-        self.qs_write
-            .internal_modify(
-                &filter!(f_eq("uuid", PartialValue::Uuid(account_uuid))),
-                &modlist,
-            )
-            .map_err(|e| {
-                admin_error!("Failed to persist oauth2 session record {:?}", e);
-                Oauth2Error::ServerError(e)
-            })?;
+        self.qs_write.internal_modify(
+            &filter_all!(f_eq("uuid", PartialValue::Uuid(uat.uuid))),
+            &modlist,
+        )?;
 
-        Ok(AccessTokenResponse {
-            access_token,
-            token_type: "bearer".to_string(),
-            expires_in,
-            refresh_token: Some(refresh_token),
-            scope,
-            id_token,
+        self.qs_write.audit_event(AuditEvent::Oauth2ConsentGranted {
+            uuid: uat.uuid,
+            resource_server: o2rs.uuid,
+            scopes: consent_req.scopes.iter().cloned().collect(),
+            time: OffsetDateTime::UNIX_EPOCH + ct,
+        });
+
+        Ok(AuthorisePermitSuccess {
+            redirect_uri: consent_req.redirect_uri,
+            state: consent_req.state,
+            code,
         })
     }
 
-    #[cfg(test)]
-    fn reflect_oauth2_token(
+    fn check_oauth2_token_exchange_authorization_code(
         &mut self,
-        client_authz: &str,
-        token: &str,
-    ) -> Result<Oauth2TokenType, OperationError> {
-        let (client_id, secret) = parse_basic_authz(client_authz).map_err(|_| {
-            admin_warn!("Invalid client_authz base64");
-            OperationError::InvalidSessionState
-        })?;
-
-        // Get the o2rs for the handle.
-        let o2rs = self.oauth2rs.inner.rs_set.get(&client_id).ok_or_else(|| {
-            admin_warn!("Invalid oauth2 client_id");
-            OperationError::InvalidSessionState
-        })?;
-
-        // check the secret.
-        if o2rs.authz_secret != secret {
-            security_info!("Invalid oauth2 client_id secret");
-            return Err(OperationError::InvalidSessionState);
-        }
+        o2rs: &Oauth2RS,
+        token_req_code: &str,
+        token_req_redirect_uri: &Url,
+        token_req_code_verifier: Option<&str>,
+        ct: Duration,
+    ) -> Result<AccessTokenResponse, Oauth2Error> {
+        // Check the token_req is within the valid time, and correctly signed for
+        // this client.
 
-        o2rs.token_fernet
-            .decrypt(token)
+        let code_xchg: TokenExchangeCode = o2rs
+            .token_fernet
+            .decrypt_at_time(token_req_code, Some(60), ct.as_secs())
             .map_err(|_| {
-                admin_error!("Failed to decrypt token introspection request");
-                OperationError::CryptographyError
+                admin_error!("Failed to decrypt token exchange request");
+                Oauth2Error::InvalidRequest
             })
             .and_then(|data| {
                 serde_json::from_slice(&data).map_err(|e| {
                     admin_error!("Failed to deserialise token exchange code - {:?}", e);
-                    OperationError::SerdeJsonError
+                    Oauth2Error::InvalidRequest
                 })
-            })
-    }
-}
-
-impl<'a> IdmServerProxyReadTransaction<'a> {
-    pub fn check_oauth2_authorisation(
-        &self,
-        ident: &Identity,
-        uat: &UserAuthToken,
-        auth_req: &AuthorisationRequest,
-        ct: Duration,
-    ) -> Result<AuthoriseResponse, Oauth2Error> {
-        // due to identity processing we already know that:
-        // * the session must be authenticated, and valid
-        // * is within it's valid time window.
-        trace!(?auth_req);
-
-        if auth_req.response_type != "code" {
-            admin_warn!("Invalid oauth2 response_type (should be 'code')");
-            return Err(Oauth2Error::UnsupportedResponseType);
-        }
+            })?;
 
-        /*
-         * 4.1.2.1.  Error Response
-         *
-         * If the request fails due to a missing, invalid, or mismatching
-         * redirection URI, or if the client identifier is missing or invalid,
-         * the authorization server SHOULD inform the resource owner of the
-         * error and MUST NOT automatically redirect the user-agent to the
-         * invalid redirection URI.
-         */
+        // If we have a verifier present, we MUST assert that a code challenge is present!
+        // It is worth noting here that code_xchg is *server issued* and encrypted, with
+        // a short validity period. The client controlled value is in token_req.code_verifier
+        if let Some(code_challenge) = code_xchg.code_challenge {
+            // Validate the code_verifier
+            let code_verifier = token_req_code_verifier
+                    .ok_or_else(|| {
+                        security_info!("PKCE code verification failed - code challenge is present, but no verifier was provided");
+                        Oauth2Error::InvalidRequest
+                    })?;
+            let mut hasher = sha::Sha256::new();
+            hasher.update(code_verifier.as_bytes());
+            let code_verifier_hash: Vec<u8> = hasher.finish().to_vec();
 
-        //
-        let o2rs = self
-            .oauth2rs
-            .inner
-            .rs_set
-            .get(&auth_req.client_id)
-            .ok_or_else(|| {
-                admin_warn!(
-                    "Invalid oauth2 client_id ({}) Have you configured the oauth2 resource server?",
-                    &auth_req.client_id
+            if code_challenge.0 != code_verifier_hash {
+                security_info!(
+                    "PKCE code verification failed - this may indicate malicious activity"
                 );
-                Oauth2Error::InvalidClientId
-            })?;
-
-        // redirect_uri must be part of the client_id origin.
-        if auth_req.redirect_uri.origin() != o2rs.origin {
-            admin_warn!(
-                origin = ?o2rs.origin,
-                "Invalid oauth2 redirect_uri (must be related to origin {:?}) - got {:?}",
-                o2rs.origin,
-                auth_req.redirect_uri.origin()
+                return Err(Oauth2Error::InvalidRequest);
+            }
+        } else if o2rs.enable_pkce {
+            security_info!(
+                "PKCE code verification failed - no code challenge present in PKCE enforced mode"
             );
-            return Err(Oauth2Error::InvalidOrigin);
+            return Err(Oauth2Error::InvalidRequest);
+        } else if token_req_code_verifier.is_some() {
+            security_info!(
+                "PKCE code verification failed - a code verifier is present, but no code challenge in exchange"
+            );
+            return Err(Oauth2Error::InvalidRequest);
         }
 
-        if o2rs.origin_https && auth_req.redirect_uri.scheme() != "https" {
-            admin_warn!(
-                origin = ?o2rs.origin,
-                "Invalid oauth2 redirect_uri (must be https for secure origin) - got {:?}", auth_req.redirect_uri.scheme()
-            );
+        // Validate the redirect_uri is the same as the original.
+        if token_req_redirect_uri != &code_xchg.redirect_uri {
+            security_info!("Invalid oauth2 redirect_uri (differs from original request uri)");
             return Err(Oauth2Error::InvalidOrigin);
         }
 
-        let code_challenge = if let Some(pkce_request) = &auth_req.pkce_request {
-            if !o2rs.enable_pkce {
-                security_info!(?o2rs.name, "Insecure rs configuration - pkce is not enforced, but rs is requesting it!");
+        // A pushed request is single use. It's kept until now so that the user can reload
+        // the authorisation page or login, but only one code issued from it can be redeemed.
+        if let Some(request_uri) = &code_xchg.request_uri {
+            if self.oauth2rs.par_requests.remove(request_uri).is_none() {
+                security_info!("Invalid oauth2 request_uri - pushed request was already used");
+                return Err(Oauth2Error::InvalidGrant);
             }
-            // CodeChallengeMethod must be S256
-            if pkce_request.code_challenge_method != CodeChallengeMethod::S256 {
-                admin_warn!("Invalid oauth2 code_challenge_method (must be 'S256')");
-                return Err(Oauth2Error::InvalidRequest);
+        }
+
+        // Check that the UAT we are issuing for still is valid.
+        let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
+        if let Some(expiry) = code_xchg.uat.expiry {
+            if expiry <= odt_ct {
+                security_info!(
+                    "User Auth Token has expired before we could publish the oauth2 response"
+                );
+                return Err(Oauth2Error::AccessDenied);
             }
-            Some(pkce_request.code_challenge.clone())
-        } else if o2rs.enable_pkce {
-            security_error!(?o2rs.name, "No PKCE code challenge was provided with client in enforced PKCE mode.");
-            return Err(Oauth2Error::InvalidRequest);
-        } else {
-            security_info!(?o2rs.name, "Insecure client configuration - pkce is not enforced.");
-            None
-        };
+        }
 
-        // TODO: https://openid.net/specs/openid-connect-basic-1_0.html#RequestParameters
-        // Are we going to provide the functions for these? Most of these can be "later".
-        // IF CHANGED: Update OidcDiscoveryResponse!!!
+        // ==== We are now GOOD TO GO! ====
+        // Grant the access token response.
+        let parent_session_id = code_xchg.uat.session_id;
+        let session_id = Uuid::new_v4();
 
-        // TODO: https://openid.net/specs/openid-connect-basic-1_0.html#RequestParameters
-        // prompt - if set to login, we need to force a re-auth. But we don't want to
-        // if the user "only just" logged in, that's annoying. So we need a time window for
-        // this, to detect when we should force it to the consent req.
+        let scopes = code_xchg.scopes;
+        let account_uuid = code_xchg.uat.uuid;
+        let nonce = code_xchg.nonce;
 
-        // TODO: display = popup vs touch vs wap etc.
+        self.generate_access_token_response(
+            o2rs,
+            ct,
+            scopes,
+            account_uuid,
+            parent_session_id,
+            session_id,
+            nonce,
+        )
+    }
 
-        // TODO: max_age, pass through with consent req. If 0, force login.
-        // Otherwise force a login re the uat timeout.
+    fn check_oauth2_token_refresh(
+        &mut self,
+        o2rs: &Oauth2RS,
+        refresh_token: &str,
+        req_scopes: Option<&BTreeSet<String>>,
+        ct: Duration,
+    ) -> Result<AccessTokenResponse, Oauth2Error> {
+        // Validate the refresh token decrypts and it's expiry is within the valid window.
+        let token: Oauth2TokenType = o2rs
+            .token_fernet
+            .decrypt(refresh_token)
+            .map_err(|_| {
+                admin_error!("Failed to decrypt refresh token request");
+                Oauth2Error::InvalidRequest
+            })
+            .and_then(|data| {
+                serde_json::from_slice(&data).map_err(|e| {
+                    admin_error!("Failed to deserialise token - {:?}", e);
+                    Oauth2Error::InvalidRequest
+                })
+            })?;
 
-        // TODO: ui_locales / claims_locales for the ui. Only if we don't have a Uat that
-        // would provide this.
+        match token {
+            Oauth2TokenType::Access { .. } | Oauth2TokenType::ClientAccess { .. } => {
+                admin_error!("attempt to refresh with access token");
+                Err(Oauth2Error::InvalidToken)
+            }
+            Oauth2TokenType::Refresh {
+                scopes,
+                parent_session_id,
+                session_id,
+                expiry,
+                uuid,
+                iat,
+                nbf: _,
+            } => {
+                // Get the current time in odt
+                let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
+                if expiry <= odt_ct {
+                    security_info!(?uuid, "refresh token has expired, ");
+                    return Err(Oauth2Error::InvalidToken);
+                }
 
-        // TODO: id_token_hint - a past token which can be used as a hint.
+                // Check the session is still valid. This call checks the parent session
+                // and the oauth2 session.
+                let valid = self
+                    .check_oauth2_account_uuid_valid(uuid, session_id, parent_session_id, iat, ct)
+                    .map_err(|_| admin_error!("Account is not valid"));
 
-        // NOTE: login_hint is handled in the UI code, not here.
+                let entry = match valid {
+                    Ok(Some(entry)) => entry,
+                    _ => {
+                        security_info!(
+                            ?uuid,
+                            "access token has no account not valid, returning inactive"
+                        );
+                        return Err(Oauth2Error::InvalidToken);
+                    }
+                };
 
-        // Deny anonymous access to oauth2
-        if uat.uuid == UUID_ANONYMOUS {
-            admin_error!(
-                "Invalid oauth2 request - refusing to allow user that authenticated with anonymous"
-            );
-            return Err(Oauth2Error::AccessDenied);
+                // Check the not issued before of the session relative to this refresh iat
+                let oauth2_session = entry
+                    .get_ava_as_oauth2session_map("oauth2_session")
+                    .and_then(|map| map.get(&session_id))
+                    .ok_or_else(|| {
+                        security_info!(
+                            ?session_id,
+                            "No oauth2 session found, unable to proceed with refresh"
+                        );
+                        Oauth2Error::InvalidToken
+                    })?;
+
+                // If the refresh token was issued previous to the time listed in our oauth2_session
+                // this indicates session desync / replay. We must nuke the session at this point.
+                //
+                // Need to think about how to handle this nicely give transactions.
+                if iat < oauth2_session.issued_at.unix_timestamp() {
+                    security_info!(
+                        ?session_id,
+                        "Attempt to reuse a refresh token detected, destroying session"
+                    );
+
+                    // Revoke it
+                    let modlist = ModifyList::new_list(vec![Modify::Removed(
+                        AttrString::from("oauth2_session"),
+                        PartialValue::Refer(session_id),
+                    )]);
+
+                    self.qs_write
+                        .internal_modify(&filter!(f_eq("uuid", PartialValue::Uuid(uuid))), &modlist)
+                        .map_err(|e| {
+                            admin_error!("Failed to modify - revoke oauth2 session {:?}", e);
+                            Oauth2Error::ServerError(e)
+                        })?;
+
+                    return Err(Oauth2Error::InvalidGrant);
+                }
+
+                // Check the scopes are identical, or None.
+                if let Some(req_scopes) = req_scopes {
+                    if &scopes != req_scopes {
+                        warn!("oauth2 scopes requested, invalid.");
+                        return Err(Oauth2Error::InvalidScope);
+                    } else {
+                        debug!("oauth2 scopes requested, checked as valid.");
+                    }
+                } else {
+                    debug!("No oauth2 scopes requested, this is valid.");
+                };
+
+                // ----------
+                // good to go
+
+                let account_uuid = uuid;
+                // Not present now. We could preserve it in the refresh token but
+                // I don't think it is worth much since the nonce exists to protect
+                // the initial auth-code exchange, not a refresh token exchange.
+                let nonce = None;
+
+                self.generate_access_token_response(
+                    o2rs,
+                    ct,
+                    scopes,
+                    account_uuid,
+                    parent_session_id,
+                    session_id,
+                    nonce,
+                )
+            }
         }
+    }
 
-        // scopes - you need to have every requested scope or this auth_req is denied.
-        let req_scopes: BTreeSet<String> = auth_req
-            .scope
-            .split_ascii_whitespace()
-            .map(str::to_string)
+    fn prune_oauth2_device_auths(&mut self, ct: Duration) {
+        let expired: Vec<String> = self
+            .oauth2rs
+            .device_auths
+            .iter()
+            .filter(|(_, dev_auth)| dev_auth.expiry <= ct)
+            .map(|(user_code, _)| user_code.clone())
             .collect();
-        if req_scopes.is_empty() {
-            admin_error!("Invalid oauth2 request - must contain at least one requested scope");
-            return Err(Oauth2Error::InvalidRequest);
+        expired.iter().for_each(|user_code| {
+            self.oauth2rs.device_auths.remove(user_code);
+        });
+    }
+
+    fn check_oauth2_token_device_code(
+        &mut self,
+        o2rs: &Oauth2RS,
+        device_code: &str,
+        ct: Duration,
+    ) -> Result<AccessTokenResponse, Oauth2Error> {
+        // The expiry is enforced by the device authorisation below, so that we can
+        // report expired_token rather than invalid_grant to the client.
+        let device_code: DeviceCode = o2rs
+            .token_fernet
+            .decrypt(device_code)
+            .map_err(|_| {
+                admin_error!("Failed to decrypt device code");
+                Oauth2Error::InvalidGrant
+            })
+            .and_then(|data| {
+                serde_json::from_slice(&data).map_err(|e| {
+                    admin_error!("Failed to deserialise device code - {:?}", e);
+                    Oauth2Error::InvalidGrant
+                })
+            })?;
+
+        // Devices that never return would otherwise leave their authorisations behind
+        // until the next device request, so prune here too.
+        self.prune_oauth2_device_auths(ct);
+
+        let mut dev_auth = self
+            .oauth2rs
+            .device_auths
+            .get(&device_code.user_code)
+            .filter(|dev_auth| dev_auth.client_id == o2rs.name)
+            .cloned()
+            .ok_or_else(|| {
+                security_info!("Device authorisation was not found, it may have already been used");
+                Oauth2Error::ExpiredToken
+            })?;
+
+        // Enforce the polling interval. Each time the client polls too quickly the
+        // interval is increased by 5 seconds as per rfc8628 3.5
+        if let Some(last_poll) = dev_auth.last_poll {
+            if ct < last_poll + Duration::from_secs(dev_auth.interval as u64) {
+                security_info!(?o2rs.name, "Device is polling too quickly");
+                dev_auth.interval += OAUTH2_DEVICE_CODE_INTERVAL;
+                dev_auth.last_poll = Some(ct);
+                self.oauth2rs
+                    .device_auths
+                    .insert(device_code.user_code, dev_auth);
+                return Err(Oauth2Error::SlowDown);
+            }
         }
 
-        // Check the scopes by our scope regex validation rules.
-        if !req_scopes.iter().all(|s| OAUTHSCOPE_RE.is_match(s)) {
-            admin_error!(
-                "Invalid oauth2 request - requested scopes failed to pass validation rules"
+        match dev_auth.state {
+            DeviceAuthorisationState::Pending => {
+                dev_auth.last_poll = Some(ct);
+                self.oauth2rs
+                    .device_auths
+                    .insert(device_code.user_code, dev_auth);
+                Err(Oauth2Error::AuthorizationPending)
+            }
+            DeviceAuthorisationState::Denied => {
+                self.oauth2rs.device_auths.remove(&device_code.user_code);
+                Err(Oauth2Error::AccessDenied)
+            }
+            DeviceAuthorisationState::Approved { uat, scopes } => {
+                // This is single use.
+                self.oauth2rs.device_auths.remove(&device_code.user_code);
+
+                // Check that the UAT we are issuing for still is valid.
+                let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
+                if let Some(expiry) = uat.expiry {
+                    if expiry <= odt_ct {
+                        security_info!(
+                            "User Auth Token has expired before we could publish the oauth2 response"
+                        );
+                        return Err(Oauth2Error::AccessDenied);
+                    }
+                }
+
+                let parent_session_id = uat.session_id;
+                let session_id = Uuid::new_v4();
+
+                self.generate_access_token_response(
+                    o2rs,
+                    ct,
+                    scopes,
+                    uat.uuid,
+                    parent_session_id,
+                    session_id,
+                    None,
+                )
+            }
+        }
+    }
+
+    fn check_oauth2_token_client_credentials(
+        &mut self,
+        o2rs: &Oauth2RS,
+        req_scope: Option<&str>,
+        ct: Duration,
+    ) -> Result<AccessTokenResponse, Oauth2Error> {
+        // The client has already authenticated with its secret, and acts as the service
+        // account linked to it. The scopes it may be granted are those of the scope maps
+        // for groups that the service account is a member of.
+        let sa_uuid = o2rs.service_account.ok_or_else(|| {
+            security_info!(
+                ?o2rs.name,
+                "oauth2 resource server has no linked service account, denying client credentials grant"
             );
-            return Err(Oauth2Error::InvalidScope);
+            Oauth2Error::UnauthorizedClient
+        })?;
+
+        let sa_entry = self.qs_write.internal_search_uuid(sa_uuid).map_err(|e| {
+            admin_error!(?e, "Unable to retrieve linked service account entry");
+            Oauth2Error::ServerError(e)
+        })?;
+
+        if !sa_entry.attribute_equality("class", &PVCLASS_SERVICE_ACCOUNT) {
+            security_info!(
+                ?o2rs.name,
+                ?sa_uuid,
+                "oauth2 resource server is linked to an entry that is not a service account, denying client credentials grant"
+            );
+            return Err(Oauth2Error::UnauthorizedClient);
         }
 
-        let uat_scopes: BTreeSet<String> = o2rs
+        let within_valid_window = Account::check_within_valid_time(
+            ct,
+            sa_entry
+                .get_ava_single_datetime("account_valid_from")
+                .as_ref(),
+            sa_entry.get_ava_single_datetime("account_expire").as_ref(),
+        );
+
+        if !within_valid_window {
+            security_info!(
+                ?sa_uuid,
+                "linked service account has expired or is not yet valid, denying client credentials grant"
+            );
+            return Err(Oauth2Error::UnauthorizedClient);
+        }
+
+        let is_memberof =
+            |u: &Uuid| sa_entry.attribute_equality("memberof", &PartialValue::Refer(*u));
+
+        let rs_scopes: BTreeSet<String> = o2rs
             .scope_maps
             .iter()
-            .filter_map(|(u, m)| {
-                if ident.is_memberof(*u) {
-                    Some(m.iter())
-                } else {
-                    None
-                }
-            })
+            .filter_map(|(u, m)| if is_memberof(u) { Some(m.iter()) } else { None })
             .flatten()
             .cloned()
             .collect();
 
-        // Needs to use s.to_string due to &&str which can't use the str::to_string
-        let avail_scopes: Vec<String> = req_scopes
-            .intersection(&uat_scopes)
-            .map(|s| s.to_string())
-            .collect();
+        if rs_scopes.is_empty() {
+            security_info!(
+                ?o2rs.name,
+                ?sa_uuid,
+                "linked service account is not a member of any scope mapped group, denying client credentials grant"
+            );
+            return Err(Oauth2Error::UnauthorizedClient);
+        }
 
-        debug!(?o2rs.scope_maps);
+        let req_scopes: BTreeSet<String> = match req_scope {
+            Some(req_scope) => req_scope
+                .split_ascii_whitespace()
+                .map(str::to_string)
+                .collect(),
+            None => BTreeSet::new(),
+        };
 
-        // Due to the intersection above, this is correct because the equal len can only
-        // occur if all terms were satisfied - effectively this check is that avail_scopes
-        // and req_scopes are identical after intersection with the scopes defined by uat_scopes
-        if avail_scopes.len() != req_scopes.len() {
+        let mut scopes = if req_scopes.is_empty() {
+            // No scopes requested, grant everything the resource server has access to.
+            rs_scopes
+        } else if !req_scopes.iter().all(|s| OAUTHSCOPE_RE.is_match(s)) {
+            admin_error!(
+                "Invalid oauth2 request - requested scopes failed to pass validation rules"
+            );
+            return Err(Oauth2Error::InvalidScope);
+        } else if req_scopes.is_subset(&rs_scopes) {
+            req_scopes
+        } else {
             admin_warn!(
-                %ident,
                 requested_scopes = ?req_scopes,
-                available_scopes = ?uat_scopes,
-                "Identity does not have access to the requested scopes"
+                available_scopes = ?rs_scopes,
+                "Linked service account does not have access to the requested scopes"
             );
-            return Err(Oauth2Error::AccessDenied);
-        }
+            return Err(Oauth2Error::InvalidScope);
+        };
 
-        drop(avail_scopes);
+        // As with authorisation, supplemental scopes are only added *after* the
+        // requested scopes were checked.
+        o2rs.sup_scope_maps
+            .iter()
+            .filter_map(|(u, m)| if is_memberof(u) { Some(m.iter()) } else { None })
+            .flatten()
+            .for_each(|s| {
+                scopes.insert(s.clone());
+            });
 
-        // ⚠️  At this point, per scopes we are *authorised*
+        // There is no end user in this flow, so we never issue an id_token
+        // or a refresh token (rfc6749 4.4.3). The client can just repeat this
+        // request when the access token expires.
+        let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
+        let iat = ct.as_secs() as i64;
+        let expiry = odt_ct + Duration::from_secs(OAUTH2_ACCESS_TOKEN_EXPIRY as u64);
+        let expires_in = OAUTH2_ACCESS_TOKEN_EXPIRY;
 
-        // We now access the supplemental scopes that will be granted to this session. It is important
-        // we DO NOT do this prior to the requested scope check, just in case we accidentally
-        // confuse the two!
-
-        // The set of scopes that are being granted during this auth_request. This is a combination
-        // of the scopes that were requested, and the scopes we supplement.
-
-        // MICRO OPTIMISATION = flag if we have openid first, so we can into_iter here rather than
-        // cloning.
-        let openid_requested = req_scopes.contains("openid");
-
-        let granted_scopes: BTreeSet<String> = o2rs
-            .sup_scope_maps
-            .iter()
-            .filter_map(|(u, m)| {
-                if ident.is_memberof(*u) {
-                    Some(m.iter())
-                } else {
-                    None
-                }
-            })
-            .flatten()
-            .cloned()
-            .chain(req_scopes.into_iter())
-            .collect();
-
-        let consent_previously_granted =
-            if let Some(consent_scopes) = ident.get_oauth2_consent_scopes(o2rs.uuid) {
-                granted_scopes.eq(consent_scopes)
-            } else {
-                false
-            };
-
-        if consent_previously_granted {
-            admin_info!(
-                "User has previously consented, permitting. {:?}",
-                granted_scopes
-            );
-
-            // Setup for the permit success
-            let xchg_code = TokenExchangeCode {
-                uat: uat.clone(),
-                code_challenge,
-                redirect_uri: auth_req.redirect_uri.clone(),
-                scopes: granted_scopes.into_iter().collect(),
-                nonce: auth_req.nonce.clone(),
-            };
-
-            // Encrypt the exchange token with the fernet key of the client resource server
-            let code_data = serde_json::to_vec(&xchg_code).map_err(|e| {
-                admin_error!(err = ?e, "Unable to encode xchg_code data");
-                Oauth2Error::ServerError(OperationError::SerdeJsonError)
-            })?;
-
-            let code = o2rs.token_fernet.encrypt_at_time(&code_data, ct.as_secs());
-
-            Ok(AuthoriseResponse::Permitted(AuthorisePermitSuccess {
-                redirect_uri: auth_req.redirect_uri.clone(),
-                state: auth_req.state.clone(),
-                code,
-            }))
+        let scope = if scopes.is_empty() {
+            None
         } else {
-            //  Check that the scopes are the same as a previous consent (if any)
-            // If oidc, what PII is visible?
-            // TODO: Scopes map to claims:
-            //
-            // * profile - (name, family\_name, given\_name, middle\_name, nickname, preferred\_username, profile, picture, website, gender, birthdate, zoneinfo, locale, and updated\_at)
-            // * email - (email, email\_verified)
-            // * address - (address)
-            // * phone - (phone\_number, phone\_number\_verified)
-            //
-            // https://openid.net/specs/openid-connect-basic-1_0.html#StandardClaims
+            Some(str_join(&scopes))
+        };
 
-            // IMPORTANT DISTINCTION - Here req scopes must contain openid, but the PII can be supplemented
-            // be the servers scopes!
-            let mut pii_scopes = BTreeSet::default();
-            if openid_requested {
-                // Only mutate if things were requested under openid
-                if granted_scopes.contains("email") {
-                    pii_scopes.insert("email".to_string());
-                    pii_scopes.insert("email_verified".to_string());
-                }
-            };
+        let session_id = Uuid::new_v4();
 
-            // Subsequent we then return an encrypted session handle which allows
-            // the user to indicate their consent to this authorisation.
-            //
-            // This session handle is what we use in "permit" to generate the redirect.
+        let access_token_raw = Oauth2TokenType::ClientAccess {
+            scopes,
+            session_id,
+            expiry,
+            uuid: sa_uuid,
+            iat,
+            nbf: iat,
+        };
 
-            let consent_req = ConsentToken {
-                client_id: auth_req.client_id.clone(),
-                ident_id: ident.get_event_origin_id(),
-                session_id: uat.session_id,
-                state: auth_req.state.clone(),
-                code_challenge,
-                redirect_uri: auth_req.redirect_uri.clone(),
-                scopes: granted_scopes.iter().cloned().collect(),
-                nonce: auth_req.nonce.clone(),
-            };
+        let access_token_data = serde_json::to_vec(&access_token_raw).map_err(|e| {
+            admin_error!(err = ?e, "Unable to encode token data");
+            Oauth2Error::ServerError(OperationError::SerdeJsonError)
+        })?;
 
-            let consent_data = serde_json::to_vec(&consent_req).map_err(|e| {
-                admin_error!(err = ?e, "Unable to encode consent data");
-                Oauth2Error::ServerError(OperationError::SerdeJsonError)
-            })?;
+        let access_token = o2rs
+            .token_fernet
+            .encrypt_at_time(&access_token_data, ct.as_secs());
 
-            let consent_token = self
-                .oauth2rs
-                .inner
-                .fernet
-                .encrypt_at_time(&consent_data, ct.as_secs());
+        // There is no user session for this to descend from, so the session is its own
+        // parent. It ends when it expires, or is revoked.
+        let session = Value::Oauth2Session(
+            session_id,
+            Oauth2Session {
+                parent: session_id,
+                expiry: Some(expiry),
+                issued_at: odt_ct,
+                rs_uuid: o2rs.uuid,
+            },
+        );
 
-            Ok(AuthoriseResponse::ConsentRequested {
-                client_name: o2rs.displayname.clone(),
-                scopes: granted_scopes.into_iter().collect(),
-                pii_scopes,
-                consent_token,
-            })
-        }
-    }
+        let modlist = ModifyList::new_list(vec![Modify::Present("oauth2_session".into(), session)]);
 
-    pub fn check_oauth2_authorise_reject(
-        &self,
-        ident: &Identity,
-        uat: &UserAuthToken,
-        consent_token: &str,
-        ct: Duration,
-    ) -> Result<Url, OperationError> {
-        // Decode the consent req with our system fernet key. Use a ttl of 5 minutes.
-        let consent_req: ConsentToken = self
-            .oauth2rs
-            .inner
-            .fernet
-            .decrypt_at_time(consent_token, Some(300), ct.as_secs())
-            .map_err(|_| {
-                admin_error!("Failed to decrypt consent request");
-                OperationError::CryptographyError
-            })
-            .and_then(|data| {
-                serde_json::from_slice(&data).map_err(|e| {
-                    admin_error!(err = ?e, "Failed to deserialise consent request");
-                    OperationError::SerdeJsonError
-                })
+        self.qs_write
+            .internal_modify(
+                &filter!(f_eq("uuid", PartialValue::Uuid(sa_uuid))),
+                &modlist,
+            )
+            .map_err(|e| {
+                admin_error!("Failed to persist oauth2 session record {:?}", e);
+                Oauth2Error::ServerError(e)
             })?;
 
-        // Validate that the ident_id matches our current ident.
-        if consent_req.ident_id != ident.get_event_origin_id() {
-            security_info!("consent request ident id does not match the identity of our UAT.");
-            return Err(OperationError::InvalidSessionState);
-        }
-
-        // Validate that the session id matches our uat.
-        if consent_req.session_id != uat.session_id {
-            security_info!("consent request sessien id does not match the session id of our UAT.");
-            return Err(OperationError::InvalidSessionState);
-        }
-
-        // Get the resource server config based on this client_id.
-        let _o2rs = self
-            .oauth2rs
-            .inner
-            .rs_set
-            .get(&consent_req.client_id)
-            .ok_or_else(|| {
-                admin_error!("Invalid consent request oauth2 client_id");
-                OperationError::InvalidRequestState
-            })?;
+        security_info!(?sa_uuid, ?o2rs.name, "oauth2 client credentials granted");
 
-        // All good, now confirm the rejection to the client application.
-        Ok(consent_req.redirect_uri)
+        Ok(AccessTokenResponse {
+            access_token,
+            token_type: "bearer".to_string(),
+            expires_in,
+            refresh_token: None,
+            scope,
+            id_token: None,
+            issued_token_type: None,
+        })
     }
 
-    pub fn check_oauth2_token_introspect(
+    #[allow(clippy::too_many_arguments)]
+    fn check_oauth2_token_exchange_access_token(
         &mut self,
-        client_authz: &str,
-        intr_req: &AccessTokenIntrospectRequest,
+        o2rs: &Oauth2RS,
+        subject_token: &str,
+        subject_token_type: &str,
+        audience: &str,
+        req_scope: Option<&str>,
+        requested_token_type: Option<&str>,
         ct: Duration,
-    ) -> Result<AccessTokenIntrospectResponse, Oauth2Error> {
-        let (client_id, secret) = parse_basic_authz(client_authz)?;
+    ) -> Result<AccessTokenResponse, Oauth2Error> {
+        // We only exchange access tokens, for access tokens.
+        if subject_token_type != OAUTH2_TOKEN_TYPE_ACCESS_TOKEN
+            || requested_token_type
+                .map(|t| t != OAUTH2_TOKEN_TYPE_ACCESS_TOKEN)
+                .unwrap_or(false)
+        {
+            admin_warn!(
+                ?subject_token_type,
+                ?requested_token_type,
+                "Unsupported token type in token exchange"
+            );
+            return Err(Oauth2Error::InvalidRequest);
+        }
 
-        // Get the o2rs for the handle.
-        let o2rs = self.oauth2rs.inner.rs_set.get(&client_id).ok_or_else(|| {
-            admin_warn!("Invalid oauth2 client_id");
-            Oauth2Error::AuthenticationRequired
-        })?;
+        // DANGER: See check_oauth2_token_exchange for why this is safe.
+        let target_rs: &Oauth2RS = unsafe {
+            let s = self.oauth2rs.inner.rs_set.get(audience).ok_or_else(|| {
+                admin_warn!(?audience, "Invalid token exchange audience");
+                Oauth2Error::InvalidTarget
+            })?;
+            &*(s as *const _)
+        };
 
-        // check the secret.
-        if o2rs.authz_secret != secret {
-            security_info!("Invalid oauth2 client_id secret");
-            return Err(Oauth2Error::AuthenticationRequired);
+        if !o2rs.token_exchange_audiences.contains(&target_rs.uuid) {
+            security_info!(
+                ?o2rs.name,
+                ?target_rs.name,
+                "oauth2 resource server is not permitted to exchange tokens for this audience"
+            );
+            return Err(Oauth2Error::UnauthorizedClient);
         }
-        // We are authenticated! Yay! Now we can actually check things ...
 
+        // The subject token must have been issued to the requesting resource server, which
+        // we know as it is encrypted with that server's key.
         let token: Oauth2TokenType = o2rs
             .token_fernet
-            .decrypt(&intr_req.token)
+            .decrypt(subject_token)
             .map_err(|_| {
-                admin_error!("Failed to decrypt token introspection request");
-                Oauth2Error::InvalidRequest
+                admin_error!("Failed to decrypt token exchange subject token");
+                Oauth2Error::InvalidGrant
             })
             .and_then(|data| {
                 serde_json::from_slice(&data).map_err(|e| {
                     admin_error!("Failed to deserialise token - {:?}", e);
-                    Oauth2Error::InvalidRequest
+                    Oauth2Error::InvalidGrant
                 })
             })?;
 
-        match token {
+        let (parent_session_id, subject_session_id, uuid, subject_iat, subject_expiry) = match token
+        {
             Oauth2TokenType::Access {
-                scopes,
                 parent_session_id,
                 session_id,
                 expiry,
                 uuid,
                 iat,
-                nbf,
-                auth_time: _,
-            } => {
-                // Has this token expired?
-                let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
-                if expiry <= odt_ct {
-                    security_info!(?uuid, "access token has expired, returning inactive");
-                    return Ok(AccessTokenIntrospectResponse::inactive());
-                }
+                ..
+            } => (parent_session_id, session_id, uuid, iat, expiry),
+            _ => {
+                admin_warn!("Token exchange subject token is not an access token");
+                return Err(Oauth2Error::InvalidGrant);
+            }
+        };
 
-                // Is the user expired, or the oauth2 session invalid?
-                let valid = self
-                    .check_oauth2_account_uuid_valid(uuid, session_id, parent_session_id, iat, ct)
-                    .map_err(|_| admin_error!("Account is not valid"));
+        let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
+        if subject_expiry <= odt_ct {
+            security_info!(?uuid, "subject token has expired, denying token exchange");
+            return Err(Oauth2Error::InvalidGrant);
+        }
 
-                let entry = match valid {
-                    Ok(Some(entry)) => entry,
-                    _ => {
-                        security_info!(
-                            ?uuid,
-                            "access token has no account not valid, returning inactive"
-                        );
-                        return Ok(AccessTokenIntrospectResponse::inactive());
-                    }
-                };
-
-                let account = match Account::try_from_entry_no_groups(&entry) {
-                    Ok(account) => account,
-                    Err(err) => return Err(Oauth2Error::ServerError(err)),
-                };
-
-                // ==== good to generate response ====
+        let entry = match self.check_oauth2_account_uuid_valid(
+            uuid,
+            subject_session_id,
+            parent_session_id,
+            subject_iat,
+            ct,
+        ) {
+            Ok(Some(entry)) => entry,
+            Ok(None) => {
+                security_info!(?uuid, "subject token session is no longer valid");
+                return Err(Oauth2Error::InvalidGrant);
+            }
+            Err(err) => return Err(Oauth2Error::ServerError(err)),
+        };
 
-                let scope = if scopes.is_empty() {
-                    None
-                } else {
-                    Some(str_join(&scopes))
-                };
+        // The scopes of the new token are what the account would be granted by the
+        // audience, not what the subject token carried.
+        let is_memberof = |u: &Uuid| entry.attribute_equality("memberof", &PartialValue::Refer(*u));
 
-                let exp = expiry.unix_timestamp();
+        let available_scopes: BTreeSet<String> = target_rs
+            .scope_maps
+            .iter()
+            .filter_map(|(u, m)| if is_memberof(u) { Some(m.iter()) } else { None })
+            .flatten()
+            .cloned()
+            .collect();
 
-                let token_type = Some("access_token".to_string());
-                Ok(AccessTokenIntrospectResponse {
-                    active: true,
-                    scope,
-                    client_id: Some(client_id.clone()),
-                    username: Some(account.spn),
-                    token_type,
-                    iat: Some(iat),
-                    exp: Some(exp),
-                    nbf: Some(nbf),
-                    sub: Some(uuid.to_string()),
-                    aud: Some(client_id),
-                    iss: None,
-                    jti: None,
-                })
-            }
-            Oauth2TokenType::Refresh { .. } => Ok(AccessTokenIntrospectResponse::inactive()),
+        if available_scopes.is_empty() {
+            security_info!(
+                ?uuid,
+                ?target_rs.name,
+                "account has no scopes for the token exchange audience, denying"
+            );
+            return Err(Oauth2Error::AccessDenied);
         }
-    }
 
-    pub fn oauth2_openid_userinfo(
-        &mut self,
-        client_id: &str,
-        client_authz: &str,
-        ct: Duration,
-    ) -> Result<OidcToken, Oauth2Error> {
-        // DANGER: Why do we have to do this? During the use of qs for internal search
-        // and other operations we need qs to be mut. But when we borrow oauth2rs here we
-        // cause multiple borrows to occur on struct members that freaks rust out. This *IS*
-        // safe however because no element of the search or write process calls the oauth2rs
-        // excepting for this idm layer within a single thread, meaning that stripping the
-        // lifetime here is safe since we are the sole accessor.
-        let o2rs: &Oauth2RS = unsafe {
-            let s = self.oauth2rs.inner.rs_set.get(client_id).ok_or_else(|| {
-                admin_warn!(
-                    "Invalid oauth2 client_id (have you configured the oauth2 resource server?)"
-                );
-                Oauth2Error::InvalidClientId
-            })?;
-            &*(s as *const _)
+        let req_scopes: BTreeSet<String> = req_scope
+            .map(|req_scope| {
+                req_scope
+                    .split_ascii_whitespace()
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut scopes = if req_scopes.is_empty() {
+            available_scopes
+        } else if !req_scopes.iter().all(|s| OAUTHSCOPE_RE.is_match(s)) {
+            admin_error!(
+                "Invalid oauth2 request - requested scopes failed to pass validation rules"
+            );
+            return Err(Oauth2Error::InvalidScope);
+        } else if req_scopes.is_subset(&available_scopes) {
+            req_scopes
+        } else {
+            admin_warn!(
+                requested_scopes = ?req_scopes,
+                available_scopes = ?available_scopes,
+                "Account does not have access to the requested scopes on the audience"
+            );
+            return Err(Oauth2Error::InvalidScope);
         };
 
-        let token: Oauth2TokenType = o2rs
-            .token_fernet
-            .decrypt(client_authz)
-            .map_err(|_| {
-                admin_error!("Failed to decrypt token introspection request");
-                Oauth2Error::InvalidRequest
-            })
-            .and_then(|data| {
-                serde_json::from_slice(&data).map_err(|e| {
-                    admin_error!("Failed to deserialise token exchange code - {:?}", e);
-                    Oauth2Error::InvalidRequest
-                })
-            })?;
+        target_rs
+            .sup_scope_maps
+            .iter()
+            .filter_map(|(u, m)| if is_memberof(u) { Some(m.iter()) } else { None })
+            .flatten()
+            .for_each(|s| {
+                scopes.insert(s.clone());
+            });
 
-        match token {
-            Oauth2TokenType::Access {
-                scopes,
-                parent_session_id,
-                session_id,
-                expiry,
-                uuid,
-                iat,
-                nbf,
-                auth_time: _,
-            } => {
-                // Has this token expired?
-                let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
-                if expiry <= odt_ct {
-                    security_info!(?uuid, "access token has expired, returning inactive");
-                    return Err(Oauth2Error::InvalidToken);
-                }
+        // The exchanged token can never outlive the token it was exchanged from.
+        let iat = ct.as_secs() as i64;
+        let expiry = std::cmp::min(
+            subject_expiry,
+            odt_ct + Duration::from_secs(OAUTH2_ACCESS_TOKEN_EXPIRY as u64),
+        );
+        let expires_in = (expiry - odt_ct).whole_seconds() as u32;
 
-                // Is the user expired, or the oauth2 session invalid?
-                let valid = self
-                    .check_oauth2_account_uuid_valid(uuid, session_id, parent_session_id, iat, ct)
-                    .map_err(|_| admin_error!("Account is not valid"));
+        let scope = if scopes.is_empty() {
+            None
+        } else {
+            Some(str_join(&scopes))
+        };
 
-                let entry = match valid {
-                    Ok(Some(entry)) => entry,
-                    _ => {
-                        security_info!(
-                            ?uuid,
-                            "access token has account not valid, returning inactive"
-                        );
-                        return Err(Oauth2Error::InvalidToken);
-                    }
-                };
+        // The new session is bound to the session of the subject token, so that revoking
+        // the subject token also revokes this one.
+        let session_id = Uuid::new_v4();
 
-                let account = match Account::try_from_entry_ro(&entry, &mut self.qs_read) {
-                    Ok(account) => account,
-                    Err(err) => return Err(Oauth2Error::ServerError(err)),
-                };
+        let access_token_raw = Oauth2TokenType::Access {
+            scopes,
+            parent_session_id,
+            session_id,
+            expiry,
+            uuid,
+            iat,
+            nbf: iat,
+            auth_time: None,
+        };
 
-                let amr = None;
+        let access_token_data = serde_json::to_vec(&access_token_raw).map_err(|e| {
+            admin_error!(err = ?e, "Unable to encode token data");
+            Oauth2Error::ServerError(OperationError::SerdeJsonError)
+        })?;
 
-                let iss = o2rs.iss.clone();
+        let access_token = target_rs
+            .token_fernet
+            .encrypt_at_time(&access_token_data, ct.as_secs());
 
-                let s_claims = s_claims_for_account(o2rs, &account, &scopes);
-                let extra_claims = extra_claims_for_account(&account, &scopes);
-                let exp = expiry.unix_timestamp();
+        let session = Value::Oauth2Session(
+            session_id,
+            Oauth2Session {
+                parent: subject_session_id,
+                expiry: Some(expiry),
+                issued_at: odt_ct,
+                rs_uuid: target_rs.uuid,
+            },
+        );
 
-                // ==== good to generate response ====
+        let modlist = ModifyList::new_list(vec![Modify::Present("oauth2_session".into(), session)]);
 
-                Ok(OidcToken {
-                    iss,
-                    sub: OidcSubject::U(uuid),
-                    aud: client_id.to_string(),
-                    iat,
-                    nbf: Some(nbf),
-                    exp,
-                    auth_time: None,
-                    nonce: None,
-                    at_hash: None,
-                    acr: None,
-                    amr,
-                    azp: Some(client_id.to_string()),
-                    jti: None,
-                    s_claims,
-                    claims: extra_claims,
-                })
-            }
-            // https://openid.net/specs/openid-connect-basic-1_0.html#UserInfoErrorResponse
-            Oauth2TokenType::Refresh { .. } => Err(Oauth2Error::InvalidToken),
-        }
+        self.qs_write
+            .internal_modify(&filter!(f_eq("uuid", PartialValue::Uuid(uuid))), &modlist)
+            .map_err(|e| {
+                admin_error!("Failed to persist oauth2 session record {:?}", e);
+                Oauth2Error::ServerError(e)
+            })?;
+
+        security_info!(
+            ?uuid,
+            ?o2rs.name,
+            ?target_rs.name,
+            "oauth2 token exchanged"
+        );
+
+        // As with client credentials, there is no refresh token. The client should
+        // exchange its (possibly refreshed) token again when this expires.
+        Ok(AccessTokenResponse {
+            access_token,
+            token_type: "bearer".to_string(),
+            expires_in,
+            refresh_token: None,
+            scope,
+            id_token: None,
+            issued_token_type: Some(OAUTH2_TOKEN_TYPE_ACCESS_TOKEN.to_string()),
+        })
     }
 
-    pub fn oauth2_openid_discovery(
-        &self,
-        client_id: &str,
-    ) -> Result<OidcDiscoveryResponse, OperationError> {
-        let o2rs = self.oauth2rs.inner.rs_set.get(client_id).ok_or_else(|| {
-            admin_warn!(
-                "Invalid oauth2 client_id (have you configured the oauth2 resource server?)"
-            );
-            OperationError::NoMatchingEntries
-        })?;
+    fn generate_access_token_response(
+        &mut self,
+        o2rs: &Oauth2RS,
+        ct: Duration,
+        //
+        scopes: BTreeSet<String>,
+        account_uuid: Uuid,
+        parent_session_id: Uuid,
+        session_id: Uuid,
+        nonce: Option<String>,
+    ) -> Result<AccessTokenResponse, Oauth2Error> {
+        let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
+        let iat = ct.as_secs() as i64;
 
-        let issuer = o2rs.iss.clone();
+        // TODO: Configurable from the oauth2rs configuration?
 
-        let authorization_endpoint = o2rs.authorization_endpoint.clone();
-        let token_endpoint = o2rs.token_endpoint.clone();
-        let userinfo_endpoint = Some(o2rs.userinfo_endpoint.clone());
-        let jwks_uri = o2rs.jwks_uri.clone();
-        let scopes_supported = Some(o2rs.scopes_supported.iter().cloned().collect());
-        let response_types_supported = vec![ResponseType::Code];
-        let response_modes_supported = vec![ResponseMode::Query];
-        let grant_types_supported = vec![GrantType::AuthorisationCode];
-        let subject_types_supported = vec![SubjectType::Public];
+        // Disclaimer: It may seem odd here that we are ignoring our session when it comes to expiry
+        // times. However, this actually is valid because when we take the initial code exchange
+        // path we have already validate the expiry of the account/session in that process. For the
+        // refresh path, we validate the session expiry and validity before we call this. So these
+        // expiries are *purely* for the tokens we issue and are *not related* to the expiries of the
+        // the session - these are enforced as above!
 
-        let id_token_signing_alg_values_supported = match &o2rs.jws_signer {
-            JwsSigner::ES256 { .. } => vec![IdTokenSignAlg::ES256],
-            JwsSigner::RS256 { .. } => vec![IdTokenSignAlg::RS256],
-            JwsSigner::HS256 { .. } => {
-                admin_warn!("Invalid oauth2 configuration - HS256 is not supported!");
-                vec![]
-            }
+        // The account policy may require a shorter lived access token.
+        let expires_in = self
+            .qs_write
+            .internal_search_uuid(account_uuid)
+            .and_then(|entry| Account::try_from_entry_rw(&entry, &mut self.qs_write))
+            .map(|account| {
+                account
+                    .account_policy
+                    .oauth2_access_token_expiry(OAUTH2_ACCESS_TOKEN_EXPIRY)
+            })
+            .map_err(|e| {
+                admin_error!(?e, "Unable to resolve the account policy");
+                Oauth2Error::ServerError(e)
+            })?;
+        let expiry = odt_ct + Duration::from_secs(expires_in as u64);
+        let refresh_expiry = odt_ct + Duration::from_secs(OAUTH_REFRESH_TOKEN_EXPIRY);
+
+        let scope = if scopes.is_empty() {
+            None
+        } else {
+            Some(str_join(&scopes))
         };
 
-        let userinfo_signing_alg_values_supported = None;
-        let token_endpoint_auth_methods_supported = vec![
-            TokenEndpointAuthMethod::ClientSecretBasic,
-            TokenEndpointAuthMethod::ClientSecretPost,
-        ];
-        let display_values_supported = Some(vec![DisplayValue::Page]);
-        let claim_types_supported = vec![ClaimType::Normal];
-        // What claims can we offer?
-        let claims_supported = None;
-        let service_documentation = Some(URL_SERVICE_DOCUMENTATION.clone());
+        let id_token = if scopes.contains(&"openid".to_string()) {
+            // TODO: Scopes map to claims:
+            //
+            // * profile - (name, family\_name, given\_name, middle\_name, nickname, preferred\_username, profile, picture, website, gender, birthdate, zoneinfo, locale, and updated\_at)
+            // * email - (email, email\_verified)
+            // * address - (address)
+            // * phone - (phone\_number, phone\_number\_verified)
+            //
+            // https://openid.net/specs/openid-connect-basic-1_0.html#StandardClaims
 
-        Ok(OidcDiscoveryResponse {
-            issuer,
-            authorization_endpoint,
-            token_endpoint,
-            userinfo_endpoint,
-            jwks_uri,
-            registration_endpoint: None,
-            scopes_supported,
-            response_types_supported,
-            response_modes_supported,
-            grant_types_supported,
-            acr_values_supported: None,
-            subject_types_supported,
-            id_token_signing_alg_values_supported,
-            id_token_encryption_alg_values_supported: None,
-            id_token_encryption_enc_values_supported: None,
-            userinfo_signing_alg_values_supported,
-            userinfo_encryption_alg_values_supported: None,
-            userinfo_encryption_enc_values_supported: None,
-            request_object_signing_alg_values_supported: None,
-            request_object_encryption_alg_values_supported: None,
-            request_object_encryption_enc_values_supported: None,
-            token_endpoint_auth_methods_supported,
-            token_endpoint_auth_signing_alg_values_supported: None,
-            display_values_supported,
-            claim_types_supported,
-            claims_supported,
-            service_documentation,
-            claims_locales_supported: None,
-            ui_locales_supported: None,
-            claims_parameter_supported: false,
-            // I think?
-            request_parameter_supported: true,
-            request_uri_parameter_supported: false,
-            require_request_uri_registration: false,
-            op_policy_uri: None,
-            op_tos_uri: None,
-        })
-    }
+            // TODO: Can the user consent to which claims are released? Today as we don't support most
+            // of them anyway, no, but in the future, we can stash these to the consent req.
 
-    pub fn oauth2_openid_publickey(&self, client_id: &str) -> Result<JwkKeySet, OperationError> {
-        let o2rs = self.oauth2rs.inner.rs_set.get(client_id).ok_or_else(|| {
-            admin_warn!(
-                "Invalid oauth2 client_id (have you configured the oauth2 resource server?)"
+            // TODO: If max_age was requested in the request, we MUST provide auth_time.
+
+            // amr == auth method
+            // We removed this from uat, and I think that it's okay here. AMR is a bit useless anyway
+            // since there is no standard for what it should look like wrt to cred strength.
+            let amr = None;
+
+            let iss = o2rs.iss.clone();
+
+            // Just reflect the access token expiry.
+            let exp = expiry.unix_timestamp();
+
+            let entry = match self.qs_write.internal_search_uuid(account_uuid) {
+                Ok(entry) => entry,
+                Err(err) => return Err(Oauth2Error::ServerError(err)),
+            };
+
+            let account = match Account::try_from_entry_rw(&entry, &mut self.qs_write) {
+                Ok(account) => account,
+                Err(err) => return Err(Oauth2Error::ServerError(err)),
+            };
+
+            let s_claims = s_claims_for_account(o2rs, &account, &scopes);
+            let mut extra_claims = extra_claims_for_account(&account, &scopes);
+            // The session this token descends from, so that the client can match our
+            // back-channel logout tokens to it.
+            extra_claims.insert(
+                "sid".to_string(),
+                serde_json::Value::String(parent_session_id.to_string()),
             );
-            OperationError::NoMatchingEntries
+
+            let oidc = OidcToken {
+                iss,
+                sub: OidcSubject::U(account_uuid),
+                aud: o2rs.name.clone(),
+                iat,
+                nbf: Some(iat),
+                exp,
+                auth_time: None,
+                nonce,
+                at_hash: None,
+                acr: None,
+                amr,
+                azp: Some(o2rs.name.clone()),
+                jti: None,
+                s_claims,
+                claims: extra_claims,
+            };
+
+            trace!(?oidc);
+
+            Some(
+                oidc.sign(&o2rs.jws_signer)
+                    .map(|jwt_signed| jwt_signed.to_string())
+                    .map_err(|e| {
+                        admin_error!(err = ?e, "Unable to encode uat data");
+                        Oauth2Error::ServerError(OperationError::InvalidState)
+                    })?,
+            )
+        } else {
+            // id_token is not required in non-openid flows.
+            None
+        };
+
+        // We need to record this into the record? Delayed action?
+
+        let access_token_raw = Oauth2TokenType::Access {
+            scopes: scopes.clone(),
+            parent_session_id,
+            session_id,
+            expiry,
+            uuid: account_uuid,
+            iat,
+            nbf: iat,
+            auth_time: None,
+        };
+
+        let access_token_data = serde_json::to_vec(&access_token_raw).map_err(|e| {
+            admin_error!(err = ?e, "Unable to encode token data");
+            Oauth2Error::ServerError(OperationError::SerdeJsonError)
         })?;
 
-        o2rs.jws_signer
-            .public_key_as_jwk()
+        let access_token = o2rs
+            .token_fernet
+            .encrypt_at_time(&access_token_data, ct.as_secs());
+
+        let refresh_token_raw = Oauth2TokenType::Refresh {
+            scopes,
+            parent_session_id,
+            session_id,
+            expiry: refresh_expiry,
+            uuid: account_uuid,
+            iat,
+            nbf: iat,
+        };
+
+        let refresh_token_data = serde_json::to_vec(&refresh_token_raw).map_err(|e| {
+            admin_error!(err = ?e, "Unable to encode token data");
+            Oauth2Error::ServerError(OperationError::SerdeJsonError)
+        })?;
+
+        let refresh_token = o2rs
+            .token_fernet
+            .encrypt_at_time(&refresh_token_data, ct.as_secs());
+
+        // Write the session to the db even with the refresh path, we need to do
+        // this to update the "not issued before" time.
+        let session = Value::Oauth2Session(
+            session_id,
+            Oauth2Session {
+                parent: parent_session_id,
+                expiry: Some(refresh_expiry),
+                issued_at: odt_ct,
+                rs_uuid: o2rs.uuid,
+            },
+        );
+
+        // We need to update (replace) this session id if present.
+        let modlist = ModifyList::new_list(vec![
+            Modify::Removed("oauth2_session".into(), PartialValue::Refer(session_id)),
+            Modify::Present("oauth2_session".into(), session),
+        ]);
+
+        self.qs_write
+            .internal_modify(
+                &filter!(f_eq("uuid", PartialValue::Uuid(account_uuid))),
+                &modlist,
+            )
             .map_err(|e| {
-                admin_error!("Unable to retrieve public key for {} - {:?}", o2rs.name, e);
-                OperationError::InvalidState
-            })
-            .map(|jwk| JwkKeySet { keys: vec![jwk] })
-    }
-}
+                admin_error!("Failed to persist oauth2 session record {:?}", e);
+                Oauth2Error::ServerError(e)
+            })?;
 
-fn parse_basic_authz(client_authz: &str) -> Result<(String, String), Oauth2Error> {
-    // Check the client_authz
-    let authz = general_purpose::STANDARD
-        .decode(client_authz)
-        .map_err(|_| {
-            admin_error!("Basic authz invalid base64");
-            Oauth2Error::AuthenticationRequired
+        Ok(AccessTokenResponse {
+            access_token,
+            token_type: "bearer".to_string(),
+            expires_in,
+            refresh_token: Some(refresh_token),
+            scope,
+            id_token,
+            issued_token_type: None,
         })
-        .and_then(|data| {
-            String::from_utf8(data).map_err(|_| {
-                admin_error!("Basic authz invalid utf8");
-                Oauth2Error::AuthenticationRequired
-            })
+    }
+
+    #[cfg(test)]
+    fn reflect_oauth2_token(
+        &mut self,
+        client_authz: &str,
+        token: &str,
+    ) -> Result<Oauth2TokenType, OperationError> {
+        let (client_id, secret) = parse_basic_authz(client_authz).map_err(|_| {
+            admin_warn!("Invalid client_authz base64");
+            OperationError::InvalidSessionState
         })?;
 
-    // Get the first :, it should be our delim.
-    //
-    let mut split_iter = authz.split(':');
+        // Get the o2rs for the handle.
+        let o2rs = self.oauth2rs.inner.rs_set.get(&client_id).ok_or_else(|| {
+            admin_warn!("Invalid oauth2 client_id");
+            OperationError::InvalidSessionState
+        })?;
 
-    let client_id = split_iter.next().ok_or_else(|| {
-        admin_error!("Basic authz invalid format (corrupt input?)");
-        Oauth2Error::AuthenticationRequired
-    })?;
-    let secret = split_iter.next().ok_or_else(|| {
-        admin_error!("Basic authz invalid format (missing ':' separator?)");
-        Oauth2Error::AuthenticationRequired
-    })?;
+        // check the secret.
+        if o2rs.authz_secret != secret {
+            security_info!("Invalid oauth2 client_id secret");
+            return Err(OperationError::InvalidSessionState);
+        }
 
-    Ok((client_id.to_string(), secret.to_string()))
-}
+        o2rs.token_fernet
+            .decrypt(token)
+            .map_err(|_| {
+                admin_error!("Failed to decrypt token introspection request");
+                OperationError::CryptographyError
+            })
+            .and_then(|data| {
+                serde_json::from_slice(&data).map_err(|e| {
+                    admin_error!("Failed to deserialise token exchange code - {:?}", e);
+                    OperationError::SerdeJsonError
+                })
+            })
+    }
 
-fn s_claims_for_account(
-    o2rs: &Oauth2RS,
-    account: &Account,
-    scopes: &BTreeSet<String>,
-) -> OidcClaims {
-    let preferred_username = if o2rs.prefer_short_username {
-        Some(account.name.clone())
-    } else {
-        Some(account.spn.clone())
-    };
+    /// Register a new resource server on behalf of a service account with registration
+    /// origins. The service account is then able to read, update and delete it.
+    pub fn oauth2_client_register(
+        &mut self,
+        ident: &Identity,
+        reg_req: &ClientRegistrationRequest,
+    ) -> Result<ClientRegistrationResponse, Oauth2Error> {
+        let registrant = Oauth2Registrant::try_from_ident(ident, true)?;
+        let (origin, scope_maps) = registrant.check_metadata(reg_req)?;
 
-    let (email, email_verified) = if scopes.contains(&"email".to_string()) {
-        if let Some(mp) = &account.mail_primary {
-            (Some(mp.clone()), Some(true))
+        let uuid = Uuid::new_v4();
+        let client_id = format!("dyn_{}", uuid.simple());
+        let displayname = reg_req
+            .client_name
+            .clone()
+            .unwrap_or_else(|| client_id.clone());
+
+        let mut e: Entry<EntryInit, EntryNew> = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("oauth2_resource_server")),
+            ("class", Value::new_class("oauth2_resource_server_basic")),
+            ("uuid", Value::Uuid(uuid)),
+            ("oauth2_rs_name", Value::new_iname(&client_id)),
+            ("displayname", Value::new_utf8(displayname)),
+            ("oauth2_rs_origin", Value::Url(origin)),
+            ("oauth2_rs_registered_by", Value::Refer(registrant.uuid))
+        );
+
+        scope_maps
+            .into_iter()
+            .for_each(|scope_map| e.add_ava("oauth2_rs_scope_map", scope_map));
+
+        if let Some(backchannel_logout_uri) = reg_req.backchannel_logout_uri.clone() {
+            e.add_ava(
+                "oauth2_rs_backchannel_logout_uri",
+                Value::Url(backchannel_logout_uri),
+            );
+        }
+
+        self.qs_write.internal_create(vec![e]).map_err(|e| {
+            admin_error!(?e, "Failed to register oauth2 client");
+            Oauth2Error::ServerError(e)
+        })?;
+
+        security_info!(%client_id, registrant = %registrant.uuid, "Registered oauth2 client");
+
+        let entry = self
+            .qs_write
+            .internal_search_uuid(uuid)
+            .map_err(Oauth2Error::ServerError)?;
+
+        client_registration_response(&entry, &self.oauth2rs.inner.origin)
+    }
+
+    /// Replace the metadata of a resource server, as its registrant.
+    /// <https://datatracker.ietf.org/doc/html/rfc7592#section-2.2>
+    pub fn oauth2_client_registration_update(
+        &mut self,
+        ident: &Identity,
+        client_id: &str,
+        reg_req: &ClientRegistrationRequest,
+    ) -> Result<ClientRegistrationResponse, Oauth2Error> {
+        let registrant = Oauth2Registrant::try_from_ident(ident, true)?;
+
+        let uuid = self
+            .qs_write
+            .internal_search(registered_client_filter(client_id, registrant.uuid))
+            .map_err(Oauth2Error::ServerError)?
+            .pop()
+            .map(|entry| entry.get_uuid())
+            .ok_or_else(|| {
+                security_info!(%client_id, "Invalid oauth2 registration - client was not registered by this service account");
+                Oauth2Error::InvalidToken
+            })?;
+
+        let (origin, scope_maps) = registrant.check_metadata(reg_req)?;
+        let displayname = reg_req
+            .client_name
+            .clone()
+            .unwrap_or_else(|| client_id.to_string());
+
+        let mut mods = vec![
+            Modify::Purged("displayname".into()),
+            Modify::Present("displayname".into(), Value::new_utf8(displayname)),
+            Modify::Purged("oauth2_rs_origin".into()),
+            Modify::Present("oauth2_rs_origin".into(), Value::Url(origin)),
+            Modify::Purged("oauth2_rs_scope_map".into()),
+            Modify::Purged("oauth2_rs_backchannel_logout_uri".into()),
+        ];
+
+        mods.extend(
+            scope_maps
+                .into_iter()
+                .map(|scope_map| Modify::Present("oauth2_rs_scope_map".into(), scope_map)),
+        );
+
+        if let Some(backchannel_logout_uri) = reg_req.backchannel_logout_uri.clone() {
+            mods.push(Modify::Present(
+                "oauth2_rs_backchannel_logout_uri".into(),
+                Value::Url(backchannel_logout_uri),
+            ));
+        }
+
+        self.qs_write
+            .internal_modify(
+                &filter!(f_eq("uuid", PartialValue::Uuid(uuid))),
+                &ModifyList::new_list(mods),
+            )
+            .map_err(|e| {
+                admin_error!(?e, "Failed to update oauth2 client registration");
+                Oauth2Error::ServerError(e)
+            })?;
+
+        let entry = self
+            .qs_write
+            .internal_search_uuid(uuid)
+            .map_err(Oauth2Error::ServerError)?;
+
+        client_registration_response(&entry, &self.oauth2rs.inner.origin)
+    }
+
+    /// Delete a resource server, as its registrant.
+    /// <https://datatracker.ietf.org/doc/html/rfc7592#section-2.3>
+    pub fn oauth2_client_registration_delete(
+        &mut self,
+        ident: &Identity,
+        client_id: &str,
+    ) -> Result<(), Oauth2Error> {
+        let registrant = Oauth2Registrant::try_from_ident(ident, true)?;
+        let filter = registered_client_filter(client_id, registrant.uuid);
+
+        // Unknown clients are reported in the same way as a read does.
+        if self
+            .qs_write
+            .internal_search(filter.clone())
+            .map_err(Oauth2Error::ServerError)?
+            .is_empty()
+        {
+            security_info!(%client_id, "Invalid oauth2 registration - client was not registered by this service account");
+            return Err(Oauth2Error::InvalidToken);
+        }
+
+        self.qs_write.internal_delete(&filter).map_err(|e| {
+            admin_error!(?e, "Failed to delete oauth2 client registration");
+            Oauth2Error::ServerError(e)
+        })?;
+
+        security_info!(%client_id, registrant = %registrant.uuid, "Deleted oauth2 client");
+        Ok(())
+    }
+}
+
+impl<'a> IdmServerProxyReadTransaction<'a> {
+    pub fn check_oauth2_authorisation(
+        &self,
+        ident: &Identity,
+        uat: &UserAuthToken,
+        auth_req: &AuthorisationRequest,
+        ct: Duration,
+    ) -> Result<AuthoriseResponse, Oauth2Error> {
+        // If the client sent a reference along with plain parameters, the reference
+        // is authoritative and the plain parameters are ignored.
+        let request_uri = auth_req
+            .unknown_keys
+            .get("request_uri")
+            .and_then(|v| v.as_str());
+        let request = auth_req
+            .unknown_keys
+            .get("request")
+            .and_then(|v| v.as_str());
+
+        if request_uri.is_some() || request.is_some() {
+            let auth_ref = AuthorisationRequestReference {
+                client_id: auth_req.client_id.clone(),
+                request_uri: request_uri.map(str::to_string),
+                request: request.map(str::to_string),
+            };
+            return self.check_oauth2_authorisation_reference(ident, uat, &auth_ref, ct);
+        }
+
+        self.check_oauth2_authorisation_inner(ident, uat, auth_req, None, ct)
+    }
+
+    pub fn check_oauth2_authorisation_reference(
+        &self,
+        ident: &Identity,
+        uat: &UserAuthToken,
+        auth_ref: &AuthorisationRequestReference,
+        ct: Duration,
+    ) -> Result<AuthoriseResponse, Oauth2Error> {
+        trace!(?auth_ref);
+
+        match (&auth_ref.request_uri, &auth_ref.request) {
+            (Some(request_uri), None) => {
+                // We don't remove the pushed request here, since the user may need to reload
+                // the page or login first. It's consumed when the resulting code is exchanged.
+                let par = self.oauth2rs.par_requests.get(request_uri).ok_or_else(|| {
+                    admin_warn!("Invalid oauth2 request_uri - no such pushed request");
+                    Oauth2Error::InvalidRequestUri
+                })?;
+
+                if par.expiry <= ct {
+                    admin_warn!("Invalid oauth2 request_uri - pushed request has expired");
+                    return Err(Oauth2Error::InvalidRequestUri);
+                }
+
+                if par.request.client_id != auth_ref.client_id {
+                    security_info!(
+                        "Invalid oauth2 request_uri - client_id does not match the pushed request"
+                    );
+                    return Err(Oauth2Error::InvalidRequestUri);
+                }
+
+                self.check_oauth2_authorisation_inner(
+                    ident,
+                    uat,
+                    &par.request,
+                    Some(request_uri),
+                    ct,
+                )
+            }
+            (None, Some(request)) => {
+                let o2rs = self
+                    .oauth2rs
+                    .inner
+                    .rs_set
+                    .get(&auth_ref.client_id)
+                    .ok_or_else(|| {
+                        admin_warn!(
+                            "Invalid oauth2 client_id ({}) Have you configured the oauth2 resource server?",
+                            &auth_ref.client_id
+                        );
+                        Oauth2Error::InvalidClientId
+                    })?;
+
+                let auth_req =
+                    verify_request_object(o2rs, &self.oauth2rs.inner.origin, request, ct)?.request;
+
+                if auth_req.client_id != auth_ref.client_id {
+                    security_info!("Invalid oauth2 request object - client_id does not match");
+                    return Err(Oauth2Error::InvalidRequestObject);
+                }
+
+                self.check_oauth2_authorisation_inner(ident, uat, &auth_req, None, ct)
+            }
+            _ => {
+                admin_warn!(
+                    "Invalid oauth2 request - exactly one of request or request_uri is required"
+                );
+                Err(Oauth2Error::InvalidRequest)
+            }
+        }
+    }
+
+    fn check_oauth2_authorisation_inner(
+        &self,
+        ident: &Identity,
+        uat: &UserAuthToken,
+        auth_req: &AuthorisationRequest,
+        request_uri: Option<&str>,
+        ct: Duration,
+    ) -> Result<AuthoriseResponse, Oauth2Error> {
+        // due to identity processing we already know that:
+        // * the session must be authenticated, and valid
+        // * is within it's valid time window.
+        trace!(?auth_req);
+
+        if auth_req.response_type != "code" {
+            admin_warn!("Invalid oauth2 response_type (should be 'code')");
+            return Err(Oauth2Error::UnsupportedResponseType);
+        }
+
+        /*
+         * 4.1.2.1.  Error Response
+         *
+         * If the request fails due to a missing, invalid, or mismatching
+         * redirection URI, or if the client identifier is missing or invalid,
+         * the authorization server SHOULD inform the resource owner of the
+         * error and MUST NOT automatically redirect the user-agent to the
+         * invalid redirection URI.
+         */
+
+        //
+        let o2rs = self
+            .oauth2rs
+            .inner
+            .rs_set
+            .get(&auth_req.client_id)
+            .ok_or_else(|| {
+                admin_warn!(
+                    "Invalid oauth2 client_id ({}) Have you configured the oauth2 resource server?",
+                    &auth_req.client_id
+                );
+                Oauth2Error::InvalidClientId
+            })?;
+
+        if o2rs.require_par && request_uri.is_none() {
+            admin_warn!(
+                ?o2rs.name,
+                "Invalid oauth2 request - this resource server requires pushed authorisation requests"
+            );
+            return Err(Oauth2Error::InvalidRequest);
+        }
+
+        // redirect_uri must be part of the client_id origin.
+        if auth_req.redirect_uri.origin() != o2rs.origin {
+            admin_warn!(
+                origin = ?o2rs.origin,
+                "Invalid oauth2 redirect_uri (must be related to origin {:?}) - got {:?}",
+                o2rs.origin,
+                auth_req.redirect_uri.origin()
+            );
+            return Err(Oauth2Error::InvalidOrigin);
+        }
+
+        if o2rs.origin_https && auth_req.redirect_uri.scheme() != "https" {
+            admin_warn!(
+                origin = ?o2rs.origin,
+                "Invalid oauth2 redirect_uri (must be https for secure origin) - got {:?}", auth_req.redirect_uri.scheme()
+            );
+            return Err(Oauth2Error::InvalidOrigin);
+        }
+
+        let code_challenge = if let Some(pkce_request) = &auth_req.pkce_request {
+            if !o2rs.enable_pkce {
+                security_info!(?o2rs.name, "Insecure rs configuration - pkce is not enforced, but rs is requesting it!");
+            }
+            // CodeChallengeMethod must be S256
+            if pkce_request.code_challenge_method != CodeChallengeMethod::S256 {
+                admin_warn!("Invalid oauth2 code_challenge_method (must be 'S256')");
+                return Err(Oauth2Error::InvalidRequest);
+            }
+            Some(pkce_request.code_challenge.clone())
+        } else if o2rs.enable_pkce {
+            security_error!(?o2rs.name, "No PKCE code challenge was provided with client in enforced PKCE mode.");
+            return Err(Oauth2Error::InvalidRequest);
         } else {
-            (None, None)
+            security_info!(?o2rs.name, "Insecure client configuration - pkce is not enforced.");
+            None
+        };
+
+        // TODO: https://openid.net/specs/openid-connect-basic-1_0.html#RequestParameters
+        // Are we going to provide the functions for these? Most of these can be "later".
+        // IF CHANGED: Update OidcDiscoveryResponse!!!
+
+        // TODO: https://openid.net/specs/openid-connect-basic-1_0.html#RequestParameters
+        // prompt - if set to login, we need to force a re-auth. But we don't want to
+        // if the user "only just" logged in, that's annoying. So we need a time window for
+        // this, to detect when we should force it to the consent req.
+
+        // TODO: display = popup vs touch vs wap etc.
+
+        // TODO: max_age, pass through with consent req. If 0, force login.
+        // Otherwise force a login re the uat timeout.
+
+        // TODO: ui_locales / claims_locales for the ui. Only if we don't have a Uat that
+        // would provide this.
+
+        // TODO: id_token_hint - a past token which can be used as a hint.
+
+        // NOTE: login_hint is handled in the UI code, not here.
+
+        // Deny anonymous access to oauth2
+        if uat.uuid == UUID_ANONYMOUS {
+            admin_error!(
+                "Invalid oauth2 request - refusing to allow user that authenticated with anonymous"
+            );
+            return Err(Oauth2Error::AccessDenied);
+        }
+
+        // scopes - you need to have every requested scope or this auth_req is denied.
+        let req_scopes: BTreeSet<String> = auth_req
+            .scope
+            .split_ascii_whitespace()
+            .map(str::to_string)
+            .collect();
+        if req_scopes.is_empty() {
+            admin_error!("Invalid oauth2 request - must contain at least one requested scope");
+            return Err(Oauth2Error::InvalidRequest);
+        }
+
+        // Check the scopes by our scope regex validation rules.
+        if !req_scopes.iter().all(|s| OAUTHSCOPE_RE.is_match(s)) {
+            admin_error!(
+                "Invalid oauth2 request - requested scopes failed to pass validation rules"
+            );
+            return Err(Oauth2Error::InvalidScope);
+        }
+
+        let uat_scopes: BTreeSet<String> = o2rs
+            .scope_maps
+            .iter()
+            .filter_map(|(u, m)| {
+                if ident.is_memberof(*u) {
+                    Some(m.iter())
+                } else {
+                    None
+                }
+            })
+            .flatten()
+            .cloned()
+            .collect();
+
+        // Needs to use s.to_string due to &&str which can't use the str::to_string
+        let avail_scopes: Vec<String> = req_scopes
+            .intersection(&uat_scopes)
+            .map(|s| s.to_string())
+            .collect();
+
+        debug!(?o2rs.scope_maps);
+
+        // Due to the intersection above, this is correct because the equal len can only
+        // occur if all terms were satisfied - effectively this check is that avail_scopes
+        // and req_scopes are identical after intersection with the scopes defined by uat_scopes
+        if avail_scopes.len() != req_scopes.len() {
+            admin_warn!(
+                %ident,
+                requested_scopes = ?req_scopes,
+                available_scopes = ?uat_scopes,
+                "Identity does not have access to the requested scopes"
+            );
+            return Err(Oauth2Error::AccessDenied);
+        }
+
+        drop(avail_scopes);
+
+        // ⚠️  At this point, per scopes we are *authorised*
+
+        // We now access the supplemental scopes that will be granted to this session. It is important
+        // we DO NOT do this prior to the requested scope check, just in case we accidentally
+        // confuse the two!
+
+        // The set of scopes that are being granted during this auth_request. This is a combination
+        // of the scopes that were requested, and the scopes we supplement.
+
+        // MICRO OPTIMISATION = flag if we have openid first, so we can into_iter here rather than
+        // cloning.
+        let openid_requested = req_scopes.contains("openid");
+
+        let granted_scopes: BTreeSet<String> = o2rs
+            .sup_scope_maps
+            .iter()
+            .filter_map(|(u, m)| {
+                if ident.is_memberof(*u) {
+                    Some(m.iter())
+                } else {
+                    None
+                }
+            })
+            .flatten()
+            .cloned()
+            .chain(req_scopes.into_iter())
+            .collect();
+
+        let consent_previously_granted =
+            if let Some(consent_scopes) = ident.get_oauth2_consent_scopes(o2rs.uuid) {
+                granted_scopes.eq(consent_scopes)
+            } else {
+                false
+            };
+
+        if consent_previously_granted {
+            admin_info!(
+                "User has previously consented, permitting. {:?}",
+                granted_scopes
+            );
+
+            // Setup for the permit success
+            let xchg_code = TokenExchangeCode {
+                uat: uat.clone(),
+                code_challenge,
+                redirect_uri: auth_req.redirect_uri.clone(),
+                scopes: granted_scopes.into_iter().collect(),
+                nonce: auth_req.nonce.clone(),
+                request_uri: request_uri.map(str::to_string),
+            };
+
+            // Encrypt the exchange token with the fernet key of the client resource server
+            let code_data = serde_json::to_vec(&xchg_code).map_err(|e| {
+                admin_error!(err = ?e, "Unable to encode xchg_code data");
+                Oauth2Error::ServerError(OperationError::SerdeJsonError)
+            })?;
+
+            let code = o2rs.token_fernet.encrypt_at_time(&code_data, ct.as_secs());
+
+            Ok(AuthoriseResponse::Permitted(AuthorisePermitSuccess {
+                redirect_uri: auth_req.redirect_uri.clone(),
+                state: auth_req.state.clone(),
+                code,
+            }))
+        } else {
+            //  Check that the scopes are the same as a previous consent (if any)
+            // If oidc, what PII is visible?
+            // TODO: Scopes map to claims:
+            //
+            // * profile - (name, family\_name, given\_name, middle\_name, nickname, preferred\_username, profile, picture, website, gender, birthdate, zoneinfo, locale, and updated\_at)
+            // * email - (email, email\_verified)
+            // * address - (address)
+            // * phone - (phone\_number, phone\_number\_verified)
+            //
+            // https://openid.net/specs/openid-connect-basic-1_0.html#StandardClaims
+
+            // IMPORTANT DISTINCTION - Here req scopes must contain openid, but the PII can be supplemented
+            // be the servers scopes!
+            let mut pii_scopes = BTreeSet::default();
+            if openid_requested {
+                // Only mutate if things were requested under openid
+                if granted_scopes.contains("email") {
+                    pii_scopes.insert("email".to_string());
+                    pii_scopes.insert("email_verified".to_string());
+                }
+            };
+
+            // Subsequent we then return an encrypted session handle which allows
+            // the user to indicate their consent to this authorisation.
+            //
+            // This session handle is what we use in "permit" to generate the redirect.
+
+            let consent_req = ConsentToken {
+                client_id: auth_req.client_id.clone(),
+                ident_id: ident.get_event_origin_id(),
+                session_id: uat.session_id,
+                state: auth_req.state.clone(),
+                code_challenge,
+                redirect_uri: auth_req.redirect_uri.clone(),
+                scopes: granted_scopes.iter().cloned().collect(),
+                nonce: auth_req.nonce.clone(),
+                request_uri: request_uri.map(str::to_string),
+            };
+
+            let consent_data = serde_json::to_vec(&consent_req).map_err(|e| {
+                admin_error!(err = ?e, "Unable to encode consent data");
+                Oauth2Error::ServerError(OperationError::SerdeJsonError)
+            })?;
+
+            let consent_token = self
+                .oauth2rs
+                .inner
+                .fernet
+                .encrypt_at_time(&consent_data, ct.as_secs());
+
+            Ok(AuthoriseResponse::ConsentRequested {
+                client_name: o2rs.displayname.clone(),
+                scopes: granted_scopes.into_iter().collect(),
+                pii_scopes,
+                consent_token,
+            })
+        }
+    }
+
+    pub fn check_oauth2_authorise_reject(
+        &self,
+        ident: &Identity,
+        uat: &UserAuthToken,
+        consent_token: &str,
+        ct: Duration,
+    ) -> Result<Url, OperationError> {
+        // Decode the consent req with our system fernet key. Use a ttl of 5 minutes.
+        let consent_req: ConsentToken = self
+            .oauth2rs
+            .inner
+            .fernet
+            .decrypt_at_time(consent_token, Some(300), ct.as_secs())
+            .map_err(|_| {
+                admin_error!("Failed to decrypt consent request");
+                OperationError::CryptographyError
+            })
+            .and_then(|data| {
+                serde_json::from_slice(&data).map_err(|e| {
+                    admin_error!(err = ?e, "Failed to deserialise consent request");
+                    OperationError::SerdeJsonError
+                })
+            })?;
+
+        // Validate that the ident_id matches our current ident.
+        if consent_req.ident_id != ident.get_event_origin_id() {
+            security_info!("consent request ident id does not match the identity of our UAT.");
+            return Err(OperationError::InvalidSessionState);
+        }
+
+        // Validate that the session id matches our uat.
+        if consent_req.session_id != uat.session_id {
+            security_info!("consent request sessien id does not match the session id of our UAT.");
+            return Err(OperationError::InvalidSessionState);
+        }
+
+        // Get the resource server config based on this client_id.
+        let _o2rs = self
+            .oauth2rs
+            .inner
+            .rs_set
+            .get(&consent_req.client_id)
+            .ok_or_else(|| {
+                admin_error!("Invalid consent request oauth2 client_id");
+                OperationError::InvalidRequestState
+            })?;
+
+        // All good, now confirm the rejection to the client application.
+        Ok(consent_req.redirect_uri)
+    }
+
+    pub fn check_oauth2_device_authorisation(
+        &self,
+        ident: &Identity,
+        uat: &UserAuthToken,
+        user_code: &str,
+        ct: Duration,
+    ) -> Result<AuthoriseResponse, Oauth2Error> {
+        let user_code = normalise_user_code(user_code);
+
+        let dev_auth = self
+            .oauth2rs
+            .device_auths
+            .get(&user_code)
+            .filter(|dev_auth| {
+                dev_auth.expiry > ct && matches!(dev_auth.state, DeviceAuthorisationState::Pending)
+            })
+            .ok_or_else(|| {
+                security_info!("Invalid or expired device user code");
+                Oauth2Error::InvalidRequest
+            })?;
+
+        let o2rs = self
+            .oauth2rs
+            .inner
+            .rs_set
+            .get(&dev_auth.client_id)
+            .ok_or_else(|| {
+                admin_warn!("Invalid oauth2 client_id for device authorisation");
+                Oauth2Error::InvalidClientId
+            })?;
+
+        // Deny anonymous access to oauth2
+        if uat.uuid == UUID_ANONYMOUS {
+            admin_error!(
+                "Invalid oauth2 request - refusing to allow user that authenticated with anonymous"
+            );
+            return Err(Oauth2Error::AccessDenied);
+        }
+
+        // As with the authorisation code flow, you need to have every requested scope.
+        let uat_scopes: BTreeSet<String> = o2rs
+            .scope_maps
+            .iter()
+            .filter_map(|(u, m)| {
+                if ident.is_memberof(*u) {
+                    Some(m.iter())
+                } else {
+                    None
+                }
+            })
+            .flatten()
+            .cloned()
+            .collect();
+
+        if !dev_auth.scopes.is_subset(&uat_scopes) {
+            admin_warn!(
+                %ident,
+                requested_scopes = ?dev_auth.scopes,
+                available_scopes = ?uat_scopes,
+                "Identity does not have access to the requested scopes"
+            );
+            return Err(Oauth2Error::AccessDenied);
+        }
+
+        let openid_requested = dev_auth.scopes.contains("openid");
+
+        let granted_scopes: BTreeSet<String> = o2rs
+            .sup_scope_maps
+            .iter()
+            .filter_map(|(u, m)| {
+                if ident.is_memberof(*u) {
+                    Some(m.iter())
+                } else {
+                    None
+                }
+            })
+            .flatten()
+            .chain(dev_auth.scopes.iter())
+            .cloned()
+            .collect();
+
+        let mut pii_scopes = BTreeSet::default();
+        if openid_requested && granted_scopes.contains("email") {
+            pii_scopes.insert("email".to_string());
+            pii_scopes.insert("email_verified".to_string());
+        };
+
+        // We always request consent here, even if it was previously granted. The user
+        // must confirm that the device they are looking at is the one they expect.
+        let consent_req = DeviceConsentToken {
+            client_id: dev_auth.client_id.clone(),
+            ident_id: ident.get_event_origin_id(),
+            session_id: uat.session_id,
+            user_code,
+            scopes: granted_scopes.clone(),
+        };
+
+        let consent_data = serde_json::to_vec(&consent_req).map_err(|e| {
+            admin_error!(err = ?e, "Unable to encode consent data");
+            Oauth2Error::ServerError(OperationError::SerdeJsonError)
+        })?;
+
+        let consent_token = self
+            .oauth2rs
+            .inner
+            .fernet
+            .encrypt_at_time(&consent_data, ct.as_secs());
+
+        Ok(AuthoriseResponse::ConsentRequested {
+            client_name: o2rs.displayname.clone(),
+            scopes: granted_scopes,
+            pii_scopes,
+            consent_token,
+        })
+    }
+
+    pub fn check_oauth2_token_introspect(
+        &mut self,
+        client_authz: &str,
+        intr_req: &AccessTokenIntrospectRequest,
+        ct: Duration,
+    ) -> Result<AccessTokenIntrospectResponse, Oauth2Error> {
+        let (client_id, secret) = parse_basic_authz(client_authz)?;
+
+        // Get the o2rs for the handle.
+        let o2rs = self.oauth2rs.inner.rs_set.get(&client_id).ok_or_else(|| {
+            admin_warn!("Invalid oauth2 client_id");
+            Oauth2Error::AuthenticationRequired
+        })?;
+
+        // check the secret.
+        if o2rs.authz_secret != secret {
+            security_info!("Invalid oauth2 client_id secret");
+            return Err(Oauth2Error::AuthenticationRequired);
+        }
+        // We are authenticated! Yay! Now we can actually check things ...
+
+        let token: Oauth2TokenType = o2rs
+            .token_fernet
+            .decrypt(&intr_req.token)
+            .map_err(|_| {
+                admin_error!("Failed to decrypt token introspection request");
+                Oauth2Error::InvalidRequest
+            })
+            .and_then(|data| {
+                serde_json::from_slice(&data).map_err(|e| {
+                    admin_error!("Failed to deserialise token - {:?}", e);
+                    Oauth2Error::InvalidRequest
+                })
+            })?;
+
+        let rs_uuid = o2rs.uuid;
+
+        match token {
+            Oauth2TokenType::Access {
+                scopes,
+                parent_session_id,
+                session_id,
+                expiry,
+                uuid,
+                iat,
+                nbf,
+                auth_time: _,
+            } => {
+                // Has this token expired?
+                let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
+                if expiry <= odt_ct {
+                    security_info!(?uuid, "access token has expired, returning inactive");
+                    return Ok(AccessTokenIntrospectResponse::inactive());
+                }
+
+                // Is the user expired, or the oauth2 session invalid?
+                let valid = self
+                    .check_oauth2_account_uuid_valid(uuid, session_id, parent_session_id, iat, ct)
+                    .map_err(|_| admin_error!("Account is not valid"));
+
+                let entry = match valid {
+                    Ok(Some(entry)) => entry,
+                    _ => {
+                        security_info!(
+                            ?uuid,
+                            "access token has no account not valid, returning inactive"
+                        );
+                        return Ok(AccessTokenIntrospectResponse::inactive());
+                    }
+                };
+
+                let account = match Account::try_from_entry_no_groups(&entry) {
+                    Ok(account) => account,
+                    Err(err) => return Err(Oauth2Error::ServerError(err)),
+                };
+
+                // ==== good to generate response ====
+
+                let scope = if scopes.is_empty() {
+                    None
+                } else {
+                    Some(str_join(&scopes))
+                };
+
+                let exp = expiry.unix_timestamp();
+
+                let token_type = Some("access_token".to_string());
+                Ok(AccessTokenIntrospectResponse {
+                    active: true,
+                    scope,
+                    client_id: Some(client_id.clone()),
+                    username: Some(account.spn),
+                    token_type,
+                    iat: Some(iat),
+                    exp: Some(exp),
+                    nbf: Some(nbf),
+                    sub: Some(uuid.to_string()),
+                    aud: Some(client_id),
+                    iss: None,
+                    jti: None,
+                })
+            }
+            Oauth2TokenType::ClientAccess {
+                scopes,
+                session_id,
+                expiry,
+                uuid,
+                iat,
+                nbf,
+            } => {
+                let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
+                if expiry <= odt_ct {
+                    security_info!(?uuid, "access token has expired, returning inactive");
+                    return Ok(AccessTokenIntrospectResponse::inactive());
+                }
+
+                // Is the service account expired, or the session revoked?
+                let valid = self
+                    .check_oauth2_client_session_valid(rs_uuid, uuid, session_id, iat, ct)
+                    .map_err(|_| admin_error!("Service account is not valid"));
+
+                let entry = match valid {
+                    Ok(Some(entry)) => entry,
+                    _ => {
+                        security_info!(
+                            ?uuid,
+                            "client access token has no valid session, returning inactive"
+                        );
+                        return Ok(AccessTokenIntrospectResponse::inactive());
+                    }
+                };
+
+                let account = match Account::try_from_entry_no_groups(&entry) {
+                    Ok(account) => account,
+                    Err(err) => return Err(Oauth2Error::ServerError(err)),
+                };
+
+                let scope = if scopes.is_empty() {
+                    None
+                } else {
+                    Some(str_join(&scopes))
+                };
+
+                let exp = expiry.unix_timestamp();
+
+                let token_type = Some("access_token".to_string());
+                Ok(AccessTokenIntrospectResponse {
+                    active: true,
+                    scope,
+                    client_id: Some(client_id.clone()),
+                    username: Some(account.spn),
+                    token_type,
+                    iat: Some(iat),
+                    exp: Some(exp),
+                    nbf: Some(nbf),
+                    sub: Some(uuid.to_string()),
+                    aud: Some(client_id),
+                    iss: None,
+                    jti: None,
+                })
+            }
+            Oauth2TokenType::Refresh { .. } => Ok(AccessTokenIntrospectResponse::inactive()),
+        }
+    }
+
+    pub fn oauth2_openid_userinfo(
+        &mut self,
+        client_id: &str,
+        client_authz: &str,
+        ct: Duration,
+    ) -> Result<OidcToken, Oauth2Error> {
+        // DANGER: Why do we have to do this? During the use of qs for internal search
+        // and other operations we need qs to be mut. But when we borrow oauth2rs here we
+        // cause multiple borrows to occur on struct members that freaks rust out. This *IS*
+        // safe however because no element of the search or write process calls the oauth2rs
+        // excepting for this idm layer within a single thread, meaning that stripping the
+        // lifetime here is safe since we are the sole accessor.
+        let o2rs: &Oauth2RS = unsafe {
+            let s = self.oauth2rs.inner.rs_set.get(client_id).ok_or_else(|| {
+                admin_warn!(
+                    "Invalid oauth2 client_id (have you configured the oauth2 resource server?)"
+                );
+                Oauth2Error::InvalidClientId
+            })?;
+            &*(s as *const _)
+        };
+
+        let token: Oauth2TokenType = o2rs
+            .token_fernet
+            .decrypt(client_authz)
+            .map_err(|_| {
+                admin_error!("Failed to decrypt token introspection request");
+                Oauth2Error::InvalidRequest
+            })
+            .and_then(|data| {
+                serde_json::from_slice(&data).map_err(|e| {
+                    admin_error!("Failed to deserialise token exchange code - {:?}", e);
+                    Oauth2Error::InvalidRequest
+                })
+            })?;
+
+        match token {
+            Oauth2TokenType::Access {
+                scopes,
+                parent_session_id,
+                session_id,
+                expiry,
+                uuid,
+                iat,
+                nbf,
+                auth_time: _,
+            } => {
+                // Has this token expired?
+                let odt_ct = OffsetDateTime::UNIX_EPOCH + ct;
+                if expiry <= odt_ct {
+                    security_info!(?uuid, "access token has expired, returning inactive");
+                    return Err(Oauth2Error::InvalidToken);
+                }
+
+                // Is the user expired, or the oauth2 session invalid?
+                let valid = self
+                    .check_oauth2_account_uuid_valid(uuid, session_id, parent_session_id, iat, ct)
+                    .map_err(|_| admin_error!("Account is not valid"));
+
+                let entry = match valid {
+                    Ok(Some(entry)) => entry,
+                    _ => {
+                        security_info!(
+                            ?uuid,
+                            "access token has account not valid, returning inactive"
+                        );
+                        return Err(Oauth2Error::InvalidToken);
+                    }
+                };
+
+                let account = match Account::try_from_entry_ro(&entry, &mut self.qs_read) {
+                    Ok(account) => account,
+                    Err(err) => return Err(Oauth2Error::ServerError(err)),
+                };
+
+                let amr = None;
+
+                let iss = o2rs.iss.clone();
+
+                let s_claims = s_claims_for_account(o2rs, &account, &scopes);
+                let extra_claims = extra_claims_for_account(&account, &scopes);
+                let exp = expiry.unix_timestamp();
+
+                // ==== good to generate response ====
+
+                Ok(OidcToken {
+                    iss,
+                    sub: OidcSubject::U(uuid),
+                    aud: client_id.to_string(),
+                    iat,
+                    nbf: Some(nbf),
+                    exp,
+                    auth_time: None,
+                    nonce: None,
+                    at_hash: None,
+                    acr: None,
+                    amr,
+                    azp: Some(client_id.to_string()),
+                    jti: None,
+                    s_claims,
+                    claims: extra_claims,
+                })
+            }
+            // https://openid.net/specs/openid-connect-basic-1_0.html#UserInfoErrorResponse
+            // Client credential tokens have no end user to provide information about.
+            Oauth2TokenType::Refresh { .. } | Oauth2TokenType::ClientAccess { .. } => {
+                Err(Oauth2Error::InvalidToken)
+            }
+        }
+    }
+
+    /// Read a resource server, as its registrant.
+    /// <https://datatracker.ietf.org/doc/html/rfc7592#section-2.1>
+    pub fn oauth2_client_registration_get(
+        &mut self,
+        ident: &Identity,
+        client_id: &str,
+    ) -> Result<ClientRegistrationResponse, Oauth2Error> {
+        let registrant = Oauth2Registrant::try_from_ident(ident, false)?;
+
+        let entry = self
+            .qs_read
+            .internal_search(registered_client_filter(client_id, registrant.uuid))
+            .map_err(Oauth2Error::ServerError)?
+            .pop()
+            .ok_or_else(|| {
+                security_info!(%client_id, "Invalid oauth2 registration - client was not registered by this service account");
+                Oauth2Error::InvalidToken
+            })?;
+
+        client_registration_response(&entry, &self.oauth2rs.inner.origin)
+    }
+
+    /// Check a request from a resource server to end the user's session, returning where
+    /// the user should be sent afterwards if the resource server asked for this.
+    ///
+    /// Since this may arrive as a plain cross-site GET, the request must carry an
+    /// id_token_hint for the user of the session being ended, so that another site can't
+    /// log the user out.
+    pub fn check_oauth2_end_session(
+        &self,
+        client_id: &str,
+        end_session_req: &EndSessionRequest,
+        uat: Option<&UserAuthToken>,
+    ) -> Result<Option<Url>, Oauth2Error> {
+        let o2rs = self.oauth2rs.inner.rs_set.get(client_id).ok_or_else(|| {
+            admin_warn!(
+                "Invalid oauth2 client_id (have you configured the oauth2 resource server?)"
+            );
+            Oauth2Error::InvalidClientId
+        })?;
+
+        if end_session_req
+            .client_id
+            .as_ref()
+            .map(|req_client_id| req_client_id != client_id)
+            .unwrap_or(false)
+        {
+            security_info!("Invalid oauth2 end session request - client_id does not match");
+            return Err(Oauth2Error::InvalidRequest);
+        }
+
+        let id_token_hint = end_session_req.id_token_hint.as_ref().ok_or_else(|| {
+            security_info!("Invalid oauth2 end session request - id_token_hint is required");
+            Oauth2Error::InvalidRequest
+        })?;
+
+        let jws_validator = o2rs.jws_signer.get_validator().map_err(|e| {
+            admin_error!(err = ?e, "Unable to load JwsValidator from JwsSigner");
+            Oauth2Error::ServerError(OperationError::CryptographyError)
+        })?;
+
+        let id_token_hint = JwsUnverified::from_str(id_token_hint)
+            .and_then(|jwsu| jwsu.validate(&jws_validator))
+            .map(|jws: Jws<IdTokenHint>| jws.into_inner())
+            .map_err(|e| {
+                security_info!(
+                    ?e,
+                    "Invalid oauth2 end session request - id_token_hint is not valid"
+                );
+                Oauth2Error::InvalidRequest
+            })?;
+
+        if id_token_hint.iss != o2rs.iss || id_token_hint.aud != o2rs.name {
+            security_info!(
+                "Invalid oauth2 end session request - id_token_hint was not issued to this client"
+            );
+            return Err(Oauth2Error::InvalidRequest);
+        }
+
+        // If the user no longer has a session there is nothing to end, but we still send
+        // them on to the resource server.
+        if let Some(uat) = uat {
+            if id_token_hint.sub != OidcSubject::U(uat.uuid) {
+                security_info!(
+                    "Invalid oauth2 end session request - id_token_hint is for a different user"
+                );
+                return Err(Oauth2Error::InvalidRequest);
+            }
+        }
+
+        // As with authorisation, we only send the user back to the resource server's origin.
+        match end_session_req.post_logout_redirect_uri.as_ref() {
+            Some(post_logout_redirect_uri) if post_logout_redirect_uri.origin() != o2rs.origin => {
+                security_info!(
+                    origin = ?o2rs.origin,
+                    "Invalid oauth2 end session request - post_logout_redirect_uri is not within the origin"
+                );
+                Err(Oauth2Error::InvalidOrigin)
+            }
+            Some(post_logout_redirect_uri) => {
+                let mut post_logout_redirect_uri = post_logout_redirect_uri.clone();
+                if let Some(state) = end_session_req.state.as_ref() {
+                    post_logout_redirect_uri
+                        .query_pairs_mut()
+                        .append_pair("state", state);
+                }
+                Ok(Some(post_logout_redirect_uri))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn oauth2_openid_discovery(
+        &self,
+        client_id: &str,
+    ) -> Result<OidcDiscoveryResponse, OperationError> {
+        let o2rs = self.oauth2rs.inner.rs_set.get(client_id).ok_or_else(|| {
+            admin_warn!(
+                "Invalid oauth2 client_id (have you configured the oauth2 resource server?)"
+            );
+            OperationError::NoMatchingEntries
+        })?;
+
+        let issuer = o2rs.iss.clone();
+
+        let authorization_endpoint = o2rs.authorization_endpoint.clone();
+        let token_endpoint = o2rs.token_endpoint.clone();
+        let device_authorization_endpoint = Some(o2rs.device_authorization_endpoint.clone());
+        let userinfo_endpoint = Some(o2rs.userinfo_endpoint.clone());
+        let jwks_uri = o2rs.jwks_uri.clone();
+        let scopes_supported = Some(o2rs.scopes_supported.iter().cloned().collect());
+        let response_types_supported = vec![ResponseType::Code];
+        let response_modes_supported = vec![ResponseMode::Query];
+        let grant_types_supported = vec![
+            GrantType::AuthorisationCode,
+            GrantType::ClientCredentials,
+            GrantType::DeviceCode,
+            GrantType::TokenExchange,
+        ];
+        let subject_types_supported = vec![SubjectType::Public];
+
+        let id_token_signing_alg_values_supported = match &o2rs.jws_signer {
+            JwsSigner::ES256 { .. } => vec![IdTokenSignAlg::ES256],
+            JwsSigner::RS256 { .. } => vec![IdTokenSignAlg::RS256],
+            JwsSigner::HS256 { .. } => {
+                admin_warn!("Invalid oauth2 configuration - HS256 is not supported!");
+                vec![]
+            }
+        };
+
+        let userinfo_signing_alg_values_supported = None;
+        let token_endpoint_auth_methods_supported = vec![
+            TokenEndpointAuthMethod::ClientSecretBasic,
+            TokenEndpointAuthMethod::ClientSecretPost,
+            TokenEndpointAuthMethod::ClientSecretJwt,
+            TokenEndpointAuthMethod::PrivateKeyJwt,
+        ];
+        let token_endpoint_auth_signing_alg_values_supported = Some(vec![
+            "ES256".to_string(),
+            "RS256".to_string(),
+            "HS256".to_string(),
+        ]);
+        let request_object_signing_alg_values_supported =
+            Some(vec!["ES256".to_string(), "RS256".to_string()]);
+        let pushed_authorization_request_endpoint = Some(o2rs.par_endpoint.clone());
+        let display_values_supported = Some(vec![DisplayValue::Page]);
+        let claim_types_supported = vec![ClaimType::Normal];
+        // What claims can we offer?
+        let claims_supported = None;
+        let service_documentation = Some(URL_SERVICE_DOCUMENTATION.clone());
+
+        Ok(OidcDiscoveryResponse {
+            issuer,
+            authorization_endpoint,
+            token_endpoint,
+            userinfo_endpoint,
+            jwks_uri,
+            registration_endpoint: Some(o2rs.registration_endpoint.clone()),
+            device_authorization_endpoint,
+            pushed_authorization_request_endpoint,
+            require_pushed_authorization_requests: o2rs.require_par,
+            end_session_endpoint: Some(o2rs.end_session_endpoint.clone()),
+            backchannel_logout_supported: true,
+            backchannel_logout_session_supported: true,
+            scopes_supported,
+            response_types_supported,
+            response_modes_supported,
+            grant_types_supported,
+            acr_values_supported: None,
+            subject_types_supported,
+            id_token_signing_alg_values_supported,
+            id_token_encryption_alg_values_supported: None,
+            id_token_encryption_enc_values_supported: None,
+            userinfo_signing_alg_values_supported,
+            userinfo_encryption_alg_values_supported: None,
+            userinfo_encryption_enc_values_supported: None,
+            request_object_signing_alg_values_supported,
+            request_object_encryption_alg_values_supported: None,
+            request_object_encryption_enc_values_supported: None,
+            token_endpoint_auth_methods_supported,
+            token_endpoint_auth_signing_alg_values_supported,
+            display_values_supported,
+            claim_types_supported,
+            claims_supported,
+            service_documentation,
+            claims_locales_supported: None,
+            ui_locales_supported: None,
+            claims_parameter_supported: false,
+            // I think?
+            request_parameter_supported: true,
+            request_uri_parameter_supported: false,
+            require_request_uri_registration: false,
+            op_policy_uri: None,
+            op_tos_uri: None,
+        })
+    }
+
+    pub fn oauth2_openid_publickey(&self, client_id: &str) -> Result<JwkKeySet, OperationError> {
+        let o2rs = self.oauth2rs.inner.rs_set.get(client_id).ok_or_else(|| {
+            admin_warn!(
+                "Invalid oauth2 client_id (have you configured the oauth2 resource server?)"
+            );
+            OperationError::NoMatchingEntries
+        })?;
+
+        o2rs.jws_signer
+            .public_key_as_jwk()
+            .map_err(|e| {
+                admin_error!("Unable to retrieve public key for {} - {:?}", o2rs.name, e);
+                OperationError::InvalidState
+            })
+            .map(|jwk| JwkKeySet { keys: vec![jwk] })
+    }
+}
+
+// The resource server with this client_id, if it was registered by this service account.
+fn registered_client_filter(client_id: &str, registrant: Uuid) -> Filter<FilterInvalid> {
+    filter!(f_and!([
+        f_eq("class", PVCLASS_OAUTH2_BASIC.clone()),
+        f_eq("oauth2_rs_name", PartialValue::new_iname(client_id)),
+        f_eq("oauth2_rs_registered_by", PartialValue::Refer(registrant))
+    ]))
+}
+
+fn client_registration_response(
+    entry: &EntrySealedCommitted,
+    origin: &Url,
+) -> Result<ClientRegistrationResponse, Oauth2Error> {
+    let client_id = entry
+        .get_ava_single_iname("oauth2_rs_name")
+        .map(str::to_string)
+        .ok_or(Oauth2Error::ServerError(OperationError::InvalidValueState))?;
+    let client_secret = entry
+        .get_ava_single_secret("oauth2_rs_basic_secret")
+        .map(str::to_string)
+        .ok_or(Oauth2Error::ServerError(OperationError::InvalidValueState))?;
+    let rs_origin = entry
+        .get_ava_single_url("oauth2_rs_origin")
+        .cloned()
+        .ok_or(Oauth2Error::ServerError(OperationError::InvalidValueState))?;
+
+    let scopes: BTreeSet<String> = entry
+        .get_ava_as_oauthscopemaps("oauth2_rs_scope_map")
+        .map(|scope_maps| scope_maps.values().flatten().cloned().collect())
+        .unwrap_or_default();
+
+    let mut registration_client_uri = origin.clone();
+    registration_client_uri.set_path(&format!("/oauth2/register/{client_id}"));
+
+    Ok(ClientRegistrationResponse {
+        client_id,
+        client_secret,
+        client_secret_expires_at: 0,
+        registration_client_uri,
+        metadata: ClientRegistrationRequest {
+            // We only keep the origin, which the redirect uris are all within.
+            redirect_uris: vec![rs_origin],
+            client_name: entry.get_ava_single_utf8("displayname").map(str::to_string),
+            scope: if scopes.is_empty() {
+                None
+            } else {
+                Some(str_join(&scopes))
+            },
+            token_endpoint_auth_method: Some(TokenEndpointAuthMethod::ClientSecretBasic),
+            backchannel_logout_uri: entry
+                .get_ava_single_url("oauth2_rs_backchannel_logout_uri")
+                .cloned(),
+        },
+    })
+}
+
+// The aud claim may be a single value or a list. It must contain one of the expected values.
+fn aud_contains(aud: &serde_json::Value, expected: &[&Url]) -> bool {
+    let is_expected = |aud: &str| {
+        let aud = aud.trim_end_matches('/');
+        expected
+            .iter()
+            .any(|url| url.as_str().trim_end_matches('/') == aud)
+    };
+    match aud {
+        serde_json::Value::String(aud) => is_expected(aud),
+        serde_json::Value::Array(auds) => auds.iter().filter_map(|v| v.as_str()).any(is_expected),
+        _ => false,
+    }
+}
+
+// Read the iss claim of a jwt without validating it.
+fn unverified_jwt_issuer(jwt: &str) -> Result<String, Oauth2Error> {
+    #[derive(Deserialize)]
+    struct Issuer {
+        iss: String,
+    }
+
+    jwt.split('.')
+        .nth(1)
+        .and_then(|payload| general_purpose::URL_SAFE_NO_PAD.decode(payload).ok())
+        .and_then(|payload| serde_json::from_slice::<Issuer>(&payload).ok())
+        .map(|claims| claims.iss)
+        .ok_or_else(|| {
+            admin_warn!("Invalid oauth2 client assertion - unable to determine issuer");
+            Oauth2Error::AuthenticationRequired
+        })
+}
+
+// https://datatracker.ietf.org/doc/html/rfc9101#section-6
+fn verify_request_object(
+    o2rs: &Oauth2RS,
+    origin: &Url,
+    request: &str,
+    ct: Duration,
+) -> Result<RequestObject, Oauth2Error> {
+    if o2rs.client_jwks.is_empty() {
+        admin_warn!(?o2rs.name, "Invalid oauth2 request object - no client jwk is configured");
+        return Err(Oauth2Error::InvalidRequestObject);
+    }
+
+    let jwsu = JwsUnverified::from_str(request).map_err(|e| {
+        admin_warn!(?e, "Invalid oauth2 request object - unable to decode");
+        Oauth2Error::InvalidRequestObject
+    })?;
+
+    // The client may have more than one key during a rotation, so accept any of them.
+    let request_object = o2rs
+        .client_jwks
+        .iter()
+        .filter_map(|jwk| JwsValidator::try_from(jwk).ok())
+        .find_map(|jws_validator| jwsu.validate(&jws_validator).ok())
+        .map(|jws: Jws<RequestObject>| jws.into_inner())
+        .ok_or_else(|| {
+            security_info!(?o2rs.name, "Invalid oauth2 request object - signature not valid for any client jwk");
+            Oauth2Error::InvalidRequestObject
+        })?;
+
+    if request_object.iss != o2rs.name {
+        security_info!("Invalid oauth2 request object - iss does not match client_id");
+        return Err(Oauth2Error::InvalidRequestObject);
+    }
+
+    // We accept either the issuer of this resource server, or our origin.
+    if !aud_contains(&request_object.aud, &[&o2rs.iss, origin]) {
+        security_info!("Invalid oauth2 request object - aud is not this server");
+        return Err(Oauth2Error::InvalidRequestObject);
+    }
+
+    let now = ct.as_secs() as i64;
+    if request_object.exp <= now {
+        security_info!("Invalid oauth2 request object - expired");
+        return Err(Oauth2Error::InvalidRequestObject);
+    }
+
+    if request_object.nbf.map(|nbf| nbf > now).unwrap_or(false) {
+        security_info!("Invalid oauth2 request object - not yet valid");
+        return Err(Oauth2Error::InvalidRequestObject);
+    }
+
+    if request_object.iat.map(|iat| iat > now).unwrap_or(false) {
+        security_info!("Invalid oauth2 request object - issued in the future");
+        return Err(Oauth2Error::InvalidRequestObject);
+    }
+
+    // Limit how long a request object can be valid for, from when it was issued or when
+    // we received it, whichever is earlier.
+    let valid_from = request_object
+        .nbf
+        .or(request_object.iat)
+        .unwrap_or(now)
+        .min(now);
+    if request_object.exp - valid_from > OAUTH2_REQUEST_OBJECT_MAX_EXPIRY as i64 {
+        security_info!("Invalid oauth2 request object - lifetime is too long");
+        return Err(Oauth2Error::InvalidRequestObject);
+    }
+
+    Ok(request_object)
+}
+
+fn parse_basic_authz(client_authz: &str) -> Result<(String, String), Oauth2Error> {
+    // Check the client_authz
+    let authz = general_purpose::STANDARD
+        .decode(client_authz)
+        .map_err(|_| {
+            admin_error!("Basic authz invalid base64");
+            Oauth2Error::AuthenticationRequired
+        })
+        .and_then(|data| {
+            String::from_utf8(data).map_err(|_| {
+                admin_error!("Basic authz invalid utf8");
+                Oauth2Error::AuthenticationRequired
+            })
+        })?;
+
+    // Get the first :, it should be our delim.
+    //
+    let mut split_iter = authz.split(':');
+
+    let client_id = split_iter.next().ok_or_else(|| {
+        admin_error!("Basic authz invalid format (corrupt input?)");
+        Oauth2Error::AuthenticationRequired
+    })?;
+    let secret = split_iter.next().ok_or_else(|| {
+        admin_error!("Basic authz invalid format (missing ':' separator?)");
+        Oauth2Error::AuthenticationRequired
+    })?;
+
+    Ok((client_id.to_string(), secret.to_string()))
+}
+
+fn parse_client_authz(
+    client_authz: Option<&str>,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<(String, String), Oauth2Error> {
+    if let Some(client_authz) = client_authz {
+        parse_basic_authz(client_authz)
+    } else {
+        match (client_id, client_secret) {
+            (Some(a), Some(b)) => Ok((a.to_string(), b.to_string())),
+            _ => {
+                security_info!(
+                    "Invalid oauth2 authentication - no basic auth or missing auth post data"
+                );
+                Err(Oauth2Error::AuthenticationRequired)
+            }
+        }
+    }
+}
+
+// People will type these in, so be forgiving of case and separators.
+fn normalise_user_code(user_code: &str) -> String {
+    let mut code: String = user_code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if code.len() > 4 {
+        code.insert(4, '-');
+    }
+    code
+}
+
+fn s_claims_for_account(
+    o2rs: &Oauth2RS,
+    account: &Account,
+    scopes: &BTreeSet<String>,
+) -> OidcClaims {
+    let preferred_username = if o2rs.prefer_short_username {
+        Some(account.name.clone())
+    } else {
+        Some(account.spn.clone())
+    };
+
+    let (email, email_verified) = if scopes.contains(&"email".to_string()) {
+        if let Some(mp) = &account.mail_primary {
+            (Some(mp.clone()), Some(true))
+        } else {
+            (None, None)
+        }
+    } else {
+        (None, None)
+    };
+
+    OidcClaims {
+        // Map from displayname
+        name: Some(account.displayname.clone()),
+        scopes: scopes.iter().cloned().collect(),
+        preferred_username,
+        email,
+        email_verified,
+        ..Default::default()
+    }
+}
+fn extra_claims_for_account(
+    account: &Account,
+    scopes: &BTreeSet<String>,
+) -> BTreeMap<String, serde_json::Value> {
+    let mut extra_claims = BTreeMap::new();
+    if scopes.contains(&"groups".to_string()) {
+        extra_claims.insert(
+            "groups".to_string(),
+            account.groups.iter().map(|x| x.to_proto().uuid).collect(),
+        );
+    }
+    extra_claims
+}
+
+fn str_join(set: &BTreeSet<String>) -> String {
+    let alloc_len = set.iter().fold(0, |acc, s| acc + s.len() + 1);
+    let mut buf = String::with_capacity(alloc_len);
+    set.iter().for_each(|s| {
+        buf.push_str(s);
+        buf.push(' ');
+    });
+
+    // Remove the excess trailing space.
+    let _ = buf.pop();
+
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose, Engine as _};
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use base64urlsafedata::Base64UrlSafeData;
+    use compact_jwt::{
+        JwaAlg, Jwk, JwkUse, Jws, JwsSigner, JwsUnverified, JwsValidator, OidcSubject,
+        OidcUnverified,
+    };
+    use kanidm_proto::oauth2::*;
+    use kanidm_proto::v1::UserAuthToken;
+    use openssl::sha;
+
+    use crate::idm::oauth2::{AuthoriseResponse, Oauth2Error};
+    use crate::idm::server::{IdmServer, IdmServerTransaction};
+    use crate::prelude::*;
+
+    use crate::credential::Credential;
+    use kanidm_lib_crypto::CryptoPolicy;
+
+    use super::{LogoutToken, Oauth2TokenType};
+    use crate::idm::account::DestroySessionTokenEvent;
+
+    const TEST_CURRENT_TIME: u64 = 6000;
+    const UAT_EXPIRE: u64 = 5;
+    const TOKEN_EXPIRE: u64 = 900;
+
+    macro_rules! create_code_verifier {
+        ($key:expr) => {{
+            let code_verifier = $key.to_string();
+            let mut hasher = sha::Sha256::new();
+            hasher.update(code_verifier.as_bytes());
+            let code_challenge: Vec<u8> = hasher.finish().iter().copied().collect();
+            (Some(code_verifier), code_challenge)
+        }};
+    }
+
+    macro_rules! good_authorisation_request {
+        (
+            $idms_prox_read:expr,
+            $ident:expr,
+            $uat:expr,
+            $ct:expr,
+            $code_challenge:expr,
+            $scope:expr
+        ) => {{
+            let auth_req = AuthorisationRequest {
+                response_type: "code".to_string(),
+                client_id: "test_resource_server".to_string(),
+                state: "123".to_string(),
+                pkce_request: Some(PkceRequest {
+                    code_challenge: Base64UrlSafeData($code_challenge),
+                    code_challenge_method: CodeChallengeMethod::S256,
+                }),
+                redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+                scope: $scope,
+                nonce: Some("abcdef".to_string()),
+                oidc_ext: Default::default(),
+                unknown_keys: Default::default(),
+            };
+
+            $idms_prox_read
+                .check_oauth2_authorisation($ident, $uat, &auth_req, $ct)
+                .expect("Oauth2 authorisation failed")
+        }};
+    }
+
+    // setup an oauth2 instance.
+    async fn setup_oauth2_resource_server(
+        idms: &IdmServer,
+        ct: Duration,
+        enable_pkce: bool,
+        enable_legacy_crypto: bool,
+        prefer_short_username: bool,
+    ) -> (String, UserAuthToken, Identity, Uuid) {
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        let uuid = Uuid::new_v4();
+
+        let e: Entry<EntryInit, EntryNew> = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("oauth2_resource_server")),
+            ("class", Value::new_class("oauth2_resource_server_basic")),
+            ("uuid", Value::Uuid(uuid)),
+            ("oauth2_rs_name", Value::new_iname("test_resource_server")),
+            ("displayname", Value::new_utf8s("test_resource_server")),
+            (
+                "oauth2_rs_origin",
+                Value::new_url_s("https://demo.example.com").unwrap()
+            ),
+            // System admins
+            (
+                "oauth2_rs_scope_map",
+                Value::new_oauthscopemap(UUID_SYSTEM_ADMINS, btreeset!["groups".to_string()])
+                    .expect("invalid oauthscope")
+            ),
+            (
+                "oauth2_rs_scope_map",
+                Value::new_oauthscopemap(UUID_IDM_ALL_ACCOUNTS, btreeset!["openid".to_string()])
+                    .expect("invalid oauthscope")
+            ),
+            (
+                "oauth2_rs_sup_scope_map",
+                Value::new_oauthscopemap(
+                    UUID_IDM_ALL_ACCOUNTS,
+                    btreeset!["supplement".to_string()]
+                )
+                .expect("invalid oauthscope")
+            ),
+            (
+                "oauth2_allow_insecure_client_disable_pkce",
+                Value::new_bool(!enable_pkce)
+            ),
+            (
+                "oauth2_jwt_legacy_crypto_enable",
+                Value::new_bool(enable_legacy_crypto)
+            ),
+            (
+                "oauth2_prefer_short_username",
+                Value::new_bool(prefer_short_username)
+            )
+        );
+        let ce = CreateEvent::new_internal(vec![e]);
+        assert!(idms_prox_write.qs_write.create(&ce).is_ok());
+
+        let entry = idms_prox_write
+            .qs_write
+            .internal_search_uuid(uuid)
+            .expect("Failed to retrieve oauth2 resource entry ");
+        let secret = entry
+            .get_ava_single_secret("oauth2_rs_basic_secret")
+            .map(str::to_string)
+            .expect("No oauth2_rs_basic_secret found");
+
+        // Setup the uat we'll be using - note for these tests they *require*
+        // the parent session to be valid and present!
+
+        let session_id = uuid::Uuid::new_v4();
+
+        let account = idms_prox_write
+            .target_to_account(UUID_ADMIN)
+            .expect("account must exist");
+        let uat = account
+            .to_userauthtoken(session_id, SessionScope::ReadWrite, ct)
+            .expect("Unable to create uat");
+
+        // Need the uat first for expiry.
+        let expiry = uat.expiry;
+
+        let p = CryptoPolicy::minimum();
+        let cred = Credential::new_password_only(&p, "test_password").unwrap();
+        let cred_id = cred.uuid;
+
+        let session = Value::Session(
+            session_id,
+            crate::value::Session {
+                label: "label".to_string(),
+                expiry,
+                issued_at: time::OffsetDateTime::UNIX_EPOCH + ct,
+                issued_by: IdentityId::Internal,
+                cred_id,
+                scope: SessionScope::ReadWrite,
+            },
+        );
+
+        // Mod the user
+        let modlist = ModifyList::new_list(vec![
+            Modify::Present("user_auth_token_session".into(), session),
+            Modify::Present(
+                "primary_credential".into(),
+                Value::Cred("primary".to_string(), cred),
+            ),
+        ]);
+
+        idms_prox_write
+            .qs_write
+            .internal_modify(
+                &filter!(f_eq("uuid", PartialValue::Uuid(UUID_ADMIN))),
+                &modlist,
+            )
+            .expect("Failed to modify user");
+
+        let ident = idms_prox_write
+            .process_uat_to_identity(&uat, ct)
+            .expect("Unable to process uat");
+
+        idms_prox_write.commit().expect("failed to commit");
+
+        (secret, uat, ident, uuid)
+    }
+
+    async fn setup_idm_admin(idms: &IdmServer, ct: Duration) -> (UserAuthToken, Identity) {
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let account = idms_prox_write
+            .target_to_account(UUID_IDM_ADMIN)
+            .expect("account must exist");
+        let session_id = uuid::Uuid::new_v4();
+        let uat = account
+            .to_userauthtoken(session_id, SessionScope::ReadWrite, ct)
+            .expect("Unable to create uat");
+        let ident = idms_prox_write
+            .process_uat_to_identity(&uat, ct)
+            .expect("Unable to process uat");
+
+        idms_prox_write.commit().expect("failed to commit");
+
+        (uat, ident)
+    }
+
+    #[idm_test]
+    async fn test_idm_oauth2_basic_function(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (secret, uat, ident, _) =
+            setup_oauth2_resource_server(idms, ct, true, false, false).await;
+
+        let idms_prox_read = idms.proxy_read().await;
+
+        // Get an ident/uat for now.
+
+        // == Setup the authorisation request
+        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+
+        let consent_request = good_authorisation_request!(
+            idms_prox_read,
+            &ident,
+            &uat,
+            ct,
+            code_challenge,
+            "openid".to_string()
+        );
+
+        // Should be in the consent phase;
+        let consent_token =
+            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
+                consent_token
+            } else {
+                unreachable!();
+            };
+
+        // == Manually submit the consent token to the permit for the permit_success
+        drop(idms_prox_read);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        let permit_success = idms_prox_write
+            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
+            .expect("Failed to perform oauth2 permit");
+
+        // Check we are reflecting the CSRF properly.
+        assert!(permit_success.state == "123");
+
+        // == Submit the token exchange code.
+
+        let token_req = AccessTokenRequest {
+            grant_type: GrantTypeReq::AuthorizationCode {
+                code: permit_success.code,
+                redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+                // From the first step.
+                code_verifier,
+            },
+            client_id: Some("test_resource_server".to_string()),
+            client_secret: Some(secret),
+            client_assertion_type: None,
+            client_assertion: None,
+        };
+
+        let token_response = idms_prox_write
+            .check_oauth2_token_exchange(None, &token_req, ct)
+            .expect("Failed to perform oauth2 token exchange");
+
+        // 🎉 We got a token! In the future we can then check introspection from this point.
+        assert!(token_response.token_type == "bearer");
+
+        assert!(idms_prox_write.commit().is_ok());
+    }
+
+    #[idm_test]
+    async fn test_idm_oauth2_invalid_authorisation_requests(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        // Test invalid oauth2 authorisation states/requests.
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (_secret, uat, ident, _) =
+            setup_oauth2_resource_server(idms, ct, true, false, false).await;
+
+        let (anon_uat, anon_ident) = setup_idm_admin(idms, ct).await;
+        let (idm_admin_uat, idm_admin_ident) = setup_idm_admin(idms, ct).await;
+
+        // Need a uat from a user not in the group. Probs anonymous.
+        let idms_prox_read = idms.proxy_read().await;
+
+        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+
+        let pkce_request = Some(PkceRequest {
+            code_challenge: Base64UrlSafeData(code_challenge),
+            code_challenge_method: CodeChallengeMethod::S256,
+        });
+
+        //  * response type != code.
+        let auth_req = AuthorisationRequest {
+            response_type: "NOTCODE".to_string(),
+            client_id: "test_resource_server".to_string(),
+            state: "123".to_string(),
+            pkce_request: pkce_request.clone(),
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            scope: "openid".to_string(),
+            nonce: None,
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
+
+        assert!(
+            idms_prox_read
+                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+                .unwrap_err()
+                == Oauth2Error::UnsupportedResponseType
+        );
+
+        // * No pkce in pkce enforced mode.
+        let auth_req = AuthorisationRequest {
+            response_type: "code".to_string(),
+            client_id: "test_resource_server".to_string(),
+            state: "123".to_string(),
+            pkce_request: None,
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            scope: "openid".to_string(),
+            nonce: None,
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
+
+        assert!(
+            idms_prox_read
+                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
+        );
+
+        //  * invalid rs name
+        let auth_req = AuthorisationRequest {
+            response_type: "code".to_string(),
+            client_id: "NOT A REAL RESOURCE SERVER".to_string(),
+            state: "123".to_string(),
+            pkce_request: pkce_request.clone(),
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            scope: "openid".to_string(),
+            nonce: None,
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
+
+        assert!(
+            idms_prox_read
+                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidClientId
+        );
+
+        //  * mis match origin in the redirect.
+        let auth_req = AuthorisationRequest {
+            response_type: "code".to_string(),
+            client_id: "test_resource_server".to_string(),
+            state: "123".to_string(),
+            pkce_request: pkce_request.clone(),
+            redirect_uri: Url::parse("https://totes.not.sus.org/oauth2/result").unwrap(),
+            scope: "openid".to_string(),
+            nonce: None,
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
+
+        assert!(
+            idms_prox_read
+                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidOrigin
+        );
+
+        // Requested scope is not available
+        let auth_req = AuthorisationRequest {
+            response_type: "code".to_string(),
+            client_id: "test_resource_server".to_string(),
+            state: "123".to_string(),
+            pkce_request: pkce_request.clone(),
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            scope: "invalid_scope read".to_string(),
+            nonce: None,
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
+
+        assert!(
+            idms_prox_read
+                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AccessDenied
+        );
+
+        // Not a member of the group.
+        let auth_req = AuthorisationRequest {
+            response_type: "code".to_string(),
+            client_id: "test_resource_server".to_string(),
+            state: "123".to_string(),
+            pkce_request: pkce_request.clone(),
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            scope: "read openid".to_string(),
+            nonce: None,
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
+
+        assert!(
+            idms_prox_read
+                .check_oauth2_authorisation(&idm_admin_ident, &idm_admin_uat, &auth_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AccessDenied
+        );
+
+        // Deny Anonymous auth methods
+        let auth_req = AuthorisationRequest {
+            response_type: "code".to_string(),
+            client_id: "test_resource_server".to_string(),
+            state: "123".to_string(),
+            pkce_request,
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            scope: "read openid".to_string(),
+            nonce: None,
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
+
+        assert!(
+            idms_prox_read
+                .check_oauth2_authorisation(&anon_ident, &anon_uat, &auth_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AccessDenied
+        );
+    }
+
+    #[idm_test]
+    async fn test_idm_oauth2_invalid_authorisation_permit_requests(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        // Test invalid oauth2 authorisation states/requests.
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (_secret, uat, ident, _) =
+            setup_oauth2_resource_server(idms, ct, true, false, false).await;
+
+        let (uat2, ident2) = {
+            let mut idms_prox_write = idms.proxy_write(ct).await;
+            let account = idms_prox_write
+                .target_to_account(UUID_IDM_ADMIN)
+                .expect("account must exist");
+            let session_id = uuid::Uuid::new_v4();
+            let uat2 = account
+                .to_userauthtoken(session_id, SessionScope::ReadWrite, ct)
+                .expect("Unable to create uat");
+            let ident2 = idms_prox_write
+                .process_uat_to_identity(&uat2, ct)
+                .expect("Unable to process uat");
+            (uat2, ident2)
+        };
+
+        let idms_prox_read = idms.proxy_read().await;
+
+        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+
+        let consent_request = good_authorisation_request!(
+            idms_prox_read,
+            &ident,
+            &uat,
+            ct,
+            code_challenge,
+            "openid".to_string()
+        );
+
+        let consent_token =
+            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
+                consent_token
+            } else {
+                unreachable!();
+            };
+
+        drop(idms_prox_read);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        // Invalid permits
+        //  * expired token, aka past ttl.
+        assert!(
+            idms_prox_write
+                .check_oauth2_authorise_permit(
+                    &ident,
+                    &uat,
+                    &consent_token,
+                    ct + Duration::from_secs(TOKEN_EXPIRE),
+                )
+                .unwrap_err()
+                == OperationError::CryptographyError
+        );
+
+        //  * incorrect ident
+        // We get another uat, but for a different user, and we'll introduce these
+        // inconsistently to cause confusion.
+
+        assert!(
+            idms_prox_write
+                .check_oauth2_authorise_permit(&ident2, &uat, &consent_token, ct,)
+                .unwrap_err()
+                == OperationError::InvalidSessionState
+        );
+
+        //  * incorrect session id
+        assert!(
+            idms_prox_write
+                .check_oauth2_authorise_permit(&ident, &uat2, &consent_token, ct,)
+                .unwrap_err()
+                == OperationError::InvalidSessionState
+        );
+
+        assert!(idms_prox_write.commit().is_ok());
+    }
+
+    #[idm_test]
+    async fn test_idm_oauth2_invalid_token_exchange_requests(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (secret, mut uat, ident, _) =
+            setup_oauth2_resource_server(idms, ct, true, false, false).await;
+
+        // ⚠️  We set the uat expiry time to 5 seconds from TEST_CURRENT_TIME. This
+        // allows all our other tests to pass, but it means when we specifically put the
+        // clock forward a fraction, the fernet tokens are still valid, but the uat
+        // is not.
+        // IE
+        //   |---------------------|------------------|
+        //   TEST_CURRENT_TIME     UAT_EXPIRE         TOKEN_EXPIRE
+        //
+        // This lets us check a variety of time based cases.
+        uat.expiry = Some(
+            time::OffsetDateTime::UNIX_EPOCH
+                + Duration::from_secs(TEST_CURRENT_TIME + UAT_EXPIRE - 1),
+        );
+
+        let idms_prox_read = idms.proxy_read().await;
+
+        // == Setup the authorisation request
+        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        let consent_request = good_authorisation_request!(
+            idms_prox_read,
+            &ident,
+            &uat,
+            ct,
+            code_challenge,
+            "openid".to_string()
+        );
+
+        let consent_token =
+            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
+                consent_token
+            } else {
+                unreachable!();
+            };
+
+        drop(idms_prox_read);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        // == Manually submit the consent token to the permit for the permit_success
+        let permit_success = idms_prox_write
+            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
+            .expect("Failed to perform oauth2 permit");
+
+        // == Submit the token exchange code.
+
+        // Invalid token exchange
+        //  * invalid client_authz (not base64)
+        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
+            code: permit_success.code.clone(),
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            code_verifier: code_verifier.clone(),
+        }
+        .into();
+
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(Some("not base64"), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AuthenticationRequired
+        );
+
+        //  * doesn't have :
+        let client_authz =
+            Some(general_purpose::STANDARD.encode(format!("test_resource_server {secret}")));
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AuthenticationRequired
+        );
+
+        //  * invalid client_id
+        let client_authz =
+            Some(general_purpose::STANDARD.encode(format!("NOT A REAL SERVER:{secret}")));
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AuthenticationRequired
+        );
+
+        //  * valid client_id, but invalid secret
+        let client_authz = Some(general_purpose::STANDARD.encode("test_resource_server:12345"));
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AuthenticationRequired
+        );
+
+        // ✅ Now the valid client_authz is in place.
+        let client_authz =
+            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
+        //  * expired exchange code (took too long)
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(
+                    client_authz.as_deref(),
+                    &token_req,
+                    ct + Duration::from_secs(TOKEN_EXPIRE)
+                )
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
+        );
+
+        //  * Uat has expired!
+        // NOTE: This is setup EARLY in the test, by manipulation of the UAT expiry.
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(
+                    client_authz.as_deref(),
+                    &token_req,
+                    ct + Duration::from_secs(UAT_EXPIRE)
+                )
+                .unwrap_err()
+                == Oauth2Error::AccessDenied
+        );
+
+        /*
+        //  * incorrect grant_type
+        // No longer possible due to changes in json api
+        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
+            grant_type: "INCORRECT GRANT TYPE".to_string(),
+            code: permit_success.code.clone(),
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            client_id: None,
+            client_secret: None,
+            code_verifier: code_verifier.clone(),
+        };
+        assert!(
+            idms_prox_read
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
+        );
+        */
+
+        //  * Incorrect redirect uri
+        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
+            code: permit_success.code.clone(),
+            redirect_uri: Url::parse("https://totes.not.sus.org/oauth2/result").unwrap(),
+            code_verifier,
         }
-    } else {
-        (None, None)
-    };
+        .into();
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidOrigin
+        );
 
-    OidcClaims {
-        // Map from displayname
-        name: Some(account.displayname.clone()),
-        scopes: scopes.iter().cloned().collect(),
-        preferred_username,
-        email,
-        email_verified,
-        ..Default::default()
-    }
-}
-fn extra_claims_for_account(
-    account: &Account,
-    scopes: &BTreeSet<String>,
-) -> BTreeMap<String, serde_json::Value> {
-    let mut extra_claims = BTreeMap::new();
-    if scopes.contains(&"groups".to_string()) {
-        extra_claims.insert(
-            "groups".to_string(),
-            account.groups.iter().map(|x| x.to_proto().uuid).collect(),
+        //  * code verifier incorrect
+        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
+            code: permit_success.code,
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            code_verifier: Some("12345".to_string()),
+        }
+        .into();
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
         );
+
+        assert!(idms_prox_write.commit().is_ok());
     }
-    extra_claims
-}
 
-fn str_join(set: &BTreeSet<String>) -> String {
-    let alloc_len = set.iter().fold(0, |acc, s| acc + s.len() + 1);
-    let mut buf = String::with_capacity(alloc_len);
-    set.iter().for_each(|s| {
-        buf.push_str(s);
-        buf.push(' ');
-    });
+    #[idm_test]
+    async fn test_idm_oauth2_token_introspect(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (secret, uat, ident, _) =
+            setup_oauth2_resource_server(idms, ct, true, false, false).await;
+        let client_authz =
+            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
-    // Remove the excess trailing space.
-    let _ = buf.pop();
+        let idms_prox_read = idms.proxy_read().await;
 
-    buf
-}
+        // == Setup the authorisation request
+        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        let consent_request = good_authorisation_request!(
+            idms_prox_read,
+            &ident,
+            &uat,
+            ct,
+            code_challenge,
+            "openid".to_string()
+        );
 
-#[cfg(test)]
-mod tests {
-    use base64::{engine::general_purpose, Engine as _};
-    use std::convert::TryFrom;
-    use std::str::FromStr;
-    use std::time::Duration;
+        let consent_token =
+            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
+                consent_token
+            } else {
+                unreachable!();
+            };
 
-    use base64urlsafedata::Base64UrlSafeData;
-    use compact_jwt::{JwaAlg, Jwk, JwkUse, JwsValidator, OidcSubject, OidcUnverified};
-    use kanidm_proto::oauth2::*;
-    use kanidm_proto::v1::UserAuthToken;
-    use openssl::sha;
+        // == Manually submit the consent token to the permit for the permit_success
+        drop(idms_prox_read);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-    use crate::idm::oauth2::{AuthoriseResponse, Oauth2Error};
-    use crate::idm::server::{IdmServer, IdmServerTransaction};
-    use crate::prelude::*;
+        let permit_success = idms_prox_write
+            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
+            .expect("Failed to perform oauth2 permit");
 
-    use crate::credential::Credential;
-    use kanidm_lib_crypto::CryptoPolicy;
+        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
+            code: permit_success.code,
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            code_verifier,
+        }
+        .into();
+        let oauth2_token = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Unable to exchange for oauth2 token");
 
-    use super::Oauth2TokenType;
+        assert!(idms_prox_write.commit().is_ok());
 
-    const TEST_CURRENT_TIME: u64 = 6000;
-    const UAT_EXPIRE: u64 = 5;
-    const TOKEN_EXPIRE: u64 = 900;
+        // Okay, now we have the token, we can check it works with introspect.
+        let mut idms_prox_read = idms.proxy_read().await;
 
-    macro_rules! create_code_verifier {
-        ($key:expr) => {{
-            let code_verifier = $key.to_string();
-            let mut hasher = sha::Sha256::new();
-            hasher.update(code_verifier.as_bytes());
-            let code_challenge: Vec<u8> = hasher.finish().iter().copied().collect();
-            (Some(code_verifier), code_challenge)
-        }};
-    }
+        let intr_request = AccessTokenIntrospectRequest {
+            token: oauth2_token.access_token,
+            token_type_hint: None,
+        };
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
+            .expect("Failed to inspect token");
 
-    macro_rules! good_authorisation_request {
-        (
-            $idms_prox_read:expr,
-            $ident:expr,
-            $uat:expr,
-            $ct:expr,
-            $code_challenge:expr,
-            $scope:expr
-        ) => {{
-            let auth_req = AuthorisationRequest {
-                response_type: "code".to_string(),
-                client_id: "test_resource_server".to_string(),
-                state: "123".to_string(),
-                pkce_request: Some(PkceRequest {
-                    code_challenge: Base64UrlSafeData($code_challenge),
-                    code_challenge_method: CodeChallengeMethod::S256,
-                }),
-                redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-                scope: $scope,
-                nonce: Some("abcdef".to_string()),
-                oidc_ext: Default::default(),
-                unknown_keys: Default::default(),
-            };
+        eprintln!("👉  {intr_response:?}");
+        assert!(intr_response.active);
+        assert!(intr_response.scope.as_deref() == Some("openid supplement"));
+        assert!(intr_response.client_id.as_deref() == Some("test_resource_server"));
+        assert!(intr_response.username.as_deref() == Some("admin@example.com"));
+        assert!(intr_response.token_type.as_deref() == Some("access_token"));
+        assert!(intr_response.iat == Some(ct.as_secs() as i64));
+        assert!(intr_response.nbf == Some(ct.as_secs() as i64));
 
-            $idms_prox_read
-                .check_oauth2_authorisation($ident, $uat, &auth_req, $ct)
-                .expect("Oauth2 authorisation failed")
-        }};
-    }
+        drop(idms_prox_read);
+        // start a write,
 
-    // setup an oauth2 instance.
-    async fn setup_oauth2_resource_server(
-        idms: &IdmServer,
-        ct: Duration,
-        enable_pkce: bool,
-        enable_legacy_crypto: bool,
-        prefer_short_username: bool,
-    ) -> (String, UserAuthToken, Identity, Uuid) {
         let mut idms_prox_write = idms.proxy_write(ct).await;
-
-        let uuid = Uuid::new_v4();
-
-        let e: Entry<EntryInit, EntryNew> = entry_init!(
-            ("class", Value::new_class("object")),
-            ("class", Value::new_class("oauth2_resource_server")),
-            ("class", Value::new_class("oauth2_resource_server_basic")),
-            ("uuid", Value::Uuid(uuid)),
-            ("oauth2_rs_name", Value::new_iname("test_resource_server")),
-            ("displayname", Value::new_utf8s("test_resource_server")),
-            (
-                "oauth2_rs_origin",
-                Value::new_url_s("https://demo.example.com").unwrap()
-            ),
-            // System admins
-            (
-                "oauth2_rs_scope_map",
-                Value::new_oauthscopemap(UUID_SYSTEM_ADMINS, btreeset!["groups".to_string()])
-                    .expect("invalid oauthscope")
-            ),
-            (
-                "oauth2_rs_scope_map",
-                Value::new_oauthscopemap(UUID_IDM_ALL_ACCOUNTS, btreeset!["openid".to_string()])
-                    .expect("invalid oauthscope")
-            ),
-            (
-                "oauth2_rs_sup_scope_map",
-                Value::new_oauthscopemap(
-                    UUID_IDM_ALL_ACCOUNTS,
-                    btreeset!["supplement".to_string()]
-                )
-                .expect("invalid oauthscope")
-            ),
-            (
-                "oauth2_allow_insecure_client_disable_pkce",
-                Value::new_bool(!enable_pkce)
-            ),
-            (
-                "oauth2_jwt_legacy_crypto_enable",
-                Value::new_bool(enable_legacy_crypto)
-            ),
-            (
-                "oauth2_prefer_short_username",
-                Value::new_bool(prefer_short_username)
+        // Expire the account, should cause introspect to return inactive.
+        let v_expire = Value::new_datetime_epoch(Duration::from_secs(TEST_CURRENT_TIME - 1));
+        let me_inv_m = unsafe {
+            ModifyEvent::new_internal_invalid(
+                filter!(f_eq("name", PartialValue::new_iname("admin"))),
+                ModifyList::new_list(vec![Modify::Present(
+                    AttrString::from("account_expire"),
+                    v_expire,
+                )]),
             )
-        );
-        let ce = CreateEvent::new_internal(vec![e]);
-        assert!(idms_prox_write.qs_write.create(&ce).is_ok());
+        };
+        // go!
+        assert!(idms_prox_write.qs_write.modify(&me_inv_m).is_ok());
+        assert!(idms_prox_write.commit().is_ok());
+
+        // start a new read
+        // check again.
+        let mut idms_prox_read = idms.proxy_read().await;
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(&client_authz.unwrap(), &intr_request, ct)
+            .expect("Failed to inspect token");
 
-        let entry = idms_prox_write
-            .qs_write
-            .internal_search_uuid(uuid)
-            .expect("Failed to retrieve oauth2 resource entry ");
-        let secret = entry
-            .get_ava_single_secret("oauth2_rs_basic_secret")
-            .map(str::to_string)
-            .expect("No oauth2_rs_basic_secret found");
+        assert!(!intr_response.active);
+    }
 
-        // Setup the uat we'll be using - note for these tests they *require*
-        // the parent session to be valid and present!
+    #[idm_test]
+    async fn test_idm_oauth2_token_revoke(idms: &IdmServer, _idms_delayed: &mut IdmServerDelayed) {
+        // First, setup to get a token.
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (secret, uat, ident, _) =
+            setup_oauth2_resource_server(idms, ct, true, false, false).await;
+        let client_authz =
+            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
-        let session_id = uuid::Uuid::new_v4();
+        let idms_prox_read = idms.proxy_read().await;
 
-        let account = idms_prox_write
-            .target_to_account(UUID_ADMIN)
-            .expect("account must exist");
-        let uat = account
-            .to_userauthtoken(session_id, SessionScope::ReadWrite, ct)
-            .expect("Unable to create uat");
+        // == Setup the authorisation request
+        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        let consent_request = good_authorisation_request!(
+            idms_prox_read,
+            &ident,
+            &uat,
+            ct,
+            code_challenge,
+            "openid".to_string()
+        );
 
-        // Need the uat first for expiry.
-        let expiry = uat.expiry;
+        let consent_token =
+            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
+                consent_token
+            } else {
+                unreachable!();
+            };
 
-        let p = CryptoPolicy::minimum();
-        let cred = Credential::new_password_only(&p, "test_password").unwrap();
-        let cred_id = cred.uuid;
+        // == Manually submit the consent token to the permit for the permit_success
+        drop(idms_prox_read);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let session = Value::Session(
-            session_id,
-            crate::value::Session {
-                label: "label".to_string(),
-                expiry,
-                issued_at: time::OffsetDateTime::UNIX_EPOCH + ct,
-                issued_by: IdentityId::Internal,
-                cred_id,
-                scope: SessionScope::ReadWrite,
-            },
-        );
+        let permit_success = idms_prox_write
+            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
+            .expect("Failed to perform oauth2 permit");
 
-        // Mod the user
-        let modlist = ModifyList::new_list(vec![
-            Modify::Present("user_auth_token_session".into(), session),
-            Modify::Present(
-                "primary_credential".into(),
-                Value::Cred("primary".to_string(), cred),
-            ),
-        ]);
+        // Assert that the consent was submitted
+        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
+            code: permit_success.code,
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            code_verifier,
+        }
+        .into();
+        let oauth2_token = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Unable to exchange for oauth2 token");
 
-        idms_prox_write
-            .qs_write
-            .internal_modify(
-                &filter!(f_eq("uuid", PartialValue::Uuid(UUID_ADMIN))),
-                &modlist,
-            )
-            .expect("Failed to modify user");
+        assert!(idms_prox_write.commit().is_ok());
 
-        let ident = idms_prox_write
-            .process_uat_to_identity(&uat, ct)
-            .expect("Unable to process uat");
+        // Okay, now we have the token, we can check behaviours with the revoke interface.
 
-        idms_prox_write.commit().expect("failed to commit");
+        // First, assert it is valid, similar to the introspect api.
+        let mut idms_prox_read = idms.proxy_read().await;
+        let intr_request = AccessTokenIntrospectRequest {
+            token: oauth2_token.access_token.clone(),
+            token_type_hint: None,
+        };
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
+            .expect("Failed to inspect token");
+        eprintln!("👉  {intr_response:?}");
+        assert!(intr_response.active);
+        drop(idms_prox_read);
 
-        (secret, uat, ident, uuid)
-    }
+        // First, the revoke needs basic auth. Provide incorrect auth, and we fail.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-    async fn setup_idm_admin(idms: &IdmServer, ct: Duration) -> (UserAuthToken, Identity) {
+        let bad_client_authz = Some(general_purpose::STANDARD.encode("test_resource_server:12345"));
+        let revoke_request = TokenRevokeRequest {
+            token: oauth2_token.access_token.clone(),
+            token_type_hint: None,
+        };
+        let e = idms_prox_write
+            .oauth2_token_revoke(bad_client_authz.as_deref().unwrap(), &revoke_request, ct)
+            .unwrap_err();
+        assert!(matches!(e, Oauth2Error::AuthenticationRequired));
+        assert!(idms_prox_write.commit().is_ok());
+
+        // Now submit a non-existent/invalid token. Does not affect our tokens validity.
         let mut idms_prox_write = idms.proxy_write(ct).await;
-        let account = idms_prox_write
-            .target_to_account(UUID_IDM_ADMIN)
-            .expect("account must exist");
-        let session_id = uuid::Uuid::new_v4();
-        let uat = account
-            .to_userauthtoken(session_id, SessionScope::ReadWrite, ct)
-            .expect("Unable to create uat");
-        let ident = idms_prox_write
-            .process_uat_to_identity(&uat, ct)
-            .expect("Unable to process uat");
+        let revoke_request = TokenRevokeRequest {
+            token: "this is an invalid token, nothing will happen!".to_string(),
+            token_type_hint: None,
+        };
+        let e = idms_prox_write
+            .oauth2_token_revoke(client_authz.as_deref().unwrap(), &revoke_request, ct)
+            .unwrap_err();
+        assert!(matches!(e, Oauth2Error::InvalidRequest));
+        assert!(idms_prox_write.commit().is_ok());
 
-        idms_prox_write.commit().expect("failed to commit");
+        // Check our token is still valid.
+        let mut idms_prox_read = idms.proxy_read().await;
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
+            .expect("Failed to inspect token");
+        assert!(intr_response.active);
+        drop(idms_prox_read);
 
-        (uat, ident)
+        // Finally revoke it.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let revoke_request = TokenRevokeRequest {
+            token: oauth2_token.access_token.clone(),
+            token_type_hint: None,
+        };
+        assert!(idms_prox_write
+            .oauth2_token_revoke(client_authz.as_deref().unwrap(), &revoke_request, ct,)
+            .is_ok());
+        assert!(idms_prox_write.commit().is_ok());
+
+        // Check it is still valid - this is because we are still in the GRACE window.
+        let mut idms_prox_read = idms.proxy_read().await;
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
+            .expect("Failed to inspect token");
+
+        assert!(intr_response.active);
+        drop(idms_prox_read);
+
+        // Check after the grace window, it will be invalid.
+        let ct = ct + GRACE_WINDOW;
+
+        // Assert it is now invalid.
+        let mut idms_prox_read = idms.proxy_read().await;
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
+            .expect("Failed to inspect token");
+
+        assert!(!intr_response.active);
+        drop(idms_prox_read);
+
+        // A second invalidation of the token "does nothing".
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let revoke_request = TokenRevokeRequest {
+            token: oauth2_token.access_token,
+            token_type_hint: None,
+        };
+        assert!(idms_prox_write
+            .oauth2_token_revoke(client_authz.as_deref().unwrap(), &revoke_request, ct,)
+            .is_ok());
+        assert!(idms_prox_write.commit().is_ok());
     }
 
     #[idm_test]
-    async fn test_idm_oauth2_basic_function(
+    async fn test_idm_oauth2_session_cleanup_post_rs_delete(
         idms: &IdmServer,
         _idms_delayed: &mut IdmServerDelayed,
     ) {
+        // First, setup to get a token.
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
         let (secret, uat, ident, _) =
             setup_oauth2_resource_server(idms, ct, true, false, false).await;
+        let client_authz =
+            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
         let idms_prox_read = idms.proxy_read().await;
 
-        // Get an ident/uat for now.
-
         // == Setup the authorisation request
         let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
-
         let consent_request = good_authorisation_request!(
             idms_prox_read,
             &ident,
@@ -2119,7 +5176,6 @@ mod tests {
             "openid".to_string()
         );
 
-        // Should be in the consent phase;
         let consent_token =
             if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
                 consent_token
@@ -2135,307 +5191,343 @@ mod tests {
             .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
             .expect("Failed to perform oauth2 permit");
 
-        // Check we are reflecting the CSRF properly.
-        assert!(permit_success.state == "123");
+        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
+            code: permit_success.code,
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            code_verifier,
+        }
+        .into();
 
-        // == Submit the token exchange code.
+        let oauth2_token = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Unable to exchange for oauth2 token");
 
-        let token_req = AccessTokenRequest {
-            grant_type: GrantTypeReq::AuthorizationCode {
-                code: permit_success.code,
-                redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-                // From the first step.
-                code_verifier,
-            },
-            client_id: Some("test_resource_server".to_string()),
-            client_secret: Some(secret),
+        let reflected_token = idms_prox_write
+            .reflect_oauth2_token(client_authz.as_ref().unwrap(), &oauth2_token.access_token)
+            .expect("Failed to access internals of the refresh token");
+
+        let session_id = match reflected_token {
+            Oauth2TokenType::Refresh { session_id, .. } => session_id,
+            Oauth2TokenType::Access { session_id, .. } => session_id,
+            Oauth2TokenType::ClientAccess { .. } => unreachable!(),
+        };
+
+        assert!(idms_prox_write.commit().is_ok());
+
+        // Process it to ensure the record exists.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        // Check it is now there
+        let entry = idms_prox_write
+            .qs_write
+            .internal_search_uuid(UUID_ADMIN)
+            .expect("failed");
+        let valid = entry
+            .get_ava_as_oauth2session_map("oauth2_session")
+            .map(|map| map.get(&session_id).is_some())
+            .unwrap_or(false);
+        assert!(valid);
+
+        // Delete the resource server.
+
+        let de = unsafe {
+            DeleteEvent::new_internal_invalid(filter!(f_eq(
+                "oauth2_rs_name",
+                PartialValue::new_iname("test_resource_server")
+            )))
         };
 
-        let token_response = idms_prox_write
-            .check_oauth2_token_exchange(None, &token_req, ct)
-            .expect("Failed to perform oauth2 token exchange");
+        assert!(idms_prox_write.qs_write.delete(&de).is_ok());
 
-        // 🎉 We got a token! In the future we can then check introspection from this point.
-        assert!(token_response.token_type == "bearer");
+        // Assert the session is gone. This is cleaned up as an artifact of the referential
+        // integrity plugin.
+        let entry = idms_prox_write
+            .qs_write
+            .internal_search_uuid(UUID_ADMIN)
+            .expect("failed");
+        let valid = entry
+            .get_ava_as_oauth2session_map("oauth2_session")
+            .map(|map| map.get(&session_id).is_some())
+            .unwrap_or(false);
+        assert!(!valid);
 
         assert!(idms_prox_write.commit().is_ok());
     }
 
     #[idm_test]
-    async fn test_idm_oauth2_invalid_authorisation_requests(
+    async fn test_idm_oauth2_authorisation_reject(
         idms: &IdmServer,
         _idms_delayed: &mut IdmServerDelayed,
     ) {
-        // Test invalid oauth2 authorisation states/requests.
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
         let (_secret, uat, ident, _) =
             setup_oauth2_resource_server(idms, ct, true, false, false).await;
 
-        let (anon_uat, anon_ident) = setup_idm_admin(idms, ct).await;
-        let (idm_admin_uat, idm_admin_ident) = setup_idm_admin(idms, ct).await;
+        let (uat2, ident2) = {
+            let mut idms_prox_write = idms.proxy_write(ct).await;
+            let account = idms_prox_write
+                .target_to_account(UUID_IDM_ADMIN)
+                .expect("account must exist");
+            let session_id = uuid::Uuid::new_v4();
+            let uat2 = account
+                .to_userauthtoken(session_id, SessionScope::ReadWrite, ct)
+                .expect("Unable to create uat");
+            let ident2 = idms_prox_write
+                .process_uat_to_identity(&uat2, ct)
+                .expect("Unable to process uat");
+            (uat2, ident2)
+        };
 
-        // Need a uat from a user not in the group. Probs anonymous.
         let idms_prox_read = idms.proxy_read().await;
-
+        let redirect_uri = Url::parse("https://demo.example.com/oauth2/result").unwrap();
         let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
 
-        let pkce_request = Some(PkceRequest {
-            code_challenge: Base64UrlSafeData(code_challenge),
-            code_challenge_method: CodeChallengeMethod::S256,
-        });
+        // Check reject behaviour
+        let consent_request = good_authorisation_request!(
+            idms_prox_read,
+            &ident,
+            &uat,
+            ct,
+            code_challenge,
+            "openid".to_string()
+        );
 
-        //  * response type != code.
-        let auth_req = AuthorisationRequest {
-            response_type: "NOTCODE".to_string(),
-            client_id: "test_resource_server".to_string(),
-            state: "123".to_string(),
-            pkce_request: pkce_request.clone(),
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            scope: "openid".to_string(),
-            nonce: None,
-            oidc_ext: Default::default(),
-            unknown_keys: Default::default(),
-        };
+        let consent_token =
+            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
+                consent_token
+            } else {
+                unreachable!();
+            };
 
-        assert!(
-            idms_prox_read
-                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
-                .unwrap_err()
-                == Oauth2Error::UnsupportedResponseType
-        );
+        let reject_success = idms_prox_read
+            .check_oauth2_authorise_reject(&ident, &uat, &consent_token, ct)
+            .expect("Failed to perform oauth2 reject");
 
-        // * No pkce in pkce enforced mode.
-        let auth_req = AuthorisationRequest {
-            response_type: "code".to_string(),
-            client_id: "test_resource_server".to_string(),
-            state: "123".to_string(),
-            pkce_request: None,
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            scope: "openid".to_string(),
-            nonce: None,
-            oidc_ext: Default::default(),
-            unknown_keys: Default::default(),
-        };
+        assert!(reject_success == redirect_uri);
 
+        // Too much time past to reject
+        let past_ct = Duration::from_secs(TEST_CURRENT_TIME + 301);
         assert!(
             idms_prox_read
-                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+                .check_oauth2_authorise_reject(&ident, &uat, &consent_token, past_ct)
                 .unwrap_err()
-                == Oauth2Error::InvalidRequest
+                == OperationError::CryptographyError
         );
 
-        //  * invalid rs name
-        let auth_req = AuthorisationRequest {
-            response_type: "code".to_string(),
-            client_id: "NOT A REAL RESOURCE SERVER".to_string(),
-            state: "123".to_string(),
-            pkce_request: pkce_request.clone(),
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            scope: "openid".to_string(),
-            nonce: None,
-            oidc_ext: Default::default(),
-            unknown_keys: Default::default(),
-        };
-
+        // Invalid consent token
         assert!(
             idms_prox_read
-                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+                .check_oauth2_authorise_reject(&ident, &uat, "not a token", ct)
                 .unwrap_err()
-                == Oauth2Error::InvalidClientId
+                == OperationError::CryptographyError
         );
 
-        //  * mis match origin in the redirect.
-        let auth_req = AuthorisationRequest {
-            response_type: "code".to_string(),
-            client_id: "test_resource_server".to_string(),
-            state: "123".to_string(),
-            pkce_request: pkce_request.clone(),
-            redirect_uri: Url::parse("https://totes.not.sus.org/oauth2/result").unwrap(),
-            scope: "openid".to_string(),
-            nonce: None,
-            oidc_ext: Default::default(),
-            unknown_keys: Default::default(),
-        };
-
+        // Wrong UAT
         assert!(
             idms_prox_read
-                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+                .check_oauth2_authorise_reject(&ident, &uat2, &consent_token, ct)
                 .unwrap_err()
-                == Oauth2Error::InvalidOrigin
+                == OperationError::InvalidSessionState
         );
-
-        // Requested scope is not available
-        let auth_req = AuthorisationRequest {
-            response_type: "code".to_string(),
-            client_id: "test_resource_server".to_string(),
-            state: "123".to_string(),
-            pkce_request: pkce_request.clone(),
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            scope: "invalid_scope read".to_string(),
-            nonce: None,
-            oidc_ext: Default::default(),
-            unknown_keys: Default::default(),
-        };
-
+        // Wrong ident
         assert!(
             idms_prox_read
-                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+                .check_oauth2_authorise_reject(&ident2, &uat, &consent_token, ct)
                 .unwrap_err()
-                == Oauth2Error::AccessDenied
+                == OperationError::InvalidSessionState
         );
+    }
 
-        // Not a member of the group.
-        let auth_req = AuthorisationRequest {
-            response_type: "code".to_string(),
-            client_id: "test_resource_server".to_string(),
-            state: "123".to_string(),
-            pkce_request: pkce_request.clone(),
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            scope: "read openid".to_string(),
-            nonce: None,
-            oidc_ext: Default::default(),
-            unknown_keys: Default::default(),
-        };
+    #[idm_test]
+    async fn test_idm_oauth2_openid_discovery(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (_secret, _uat, _ident, _) =
+            setup_oauth2_resource_server(idms, ct, true, false, false).await;
 
+        let idms_prox_read = idms.proxy_read().await;
+
+        // check the discovery end point works as we expect
         assert!(
             idms_prox_read
-                .check_oauth2_authorisation(&idm_admin_ident, &idm_admin_uat, &auth_req, ct)
+                .oauth2_openid_discovery("nosuchclient")
                 .unwrap_err()
-                == Oauth2Error::AccessDenied
+                == OperationError::NoMatchingEntries
         );
 
-        // Deny Anonymous auth methods
-        let auth_req = AuthorisationRequest {
-            response_type: "code".to_string(),
-            client_id: "test_resource_server".to_string(),
-            state: "123".to_string(),
-            pkce_request,
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            scope: "read openid".to_string(),
-            nonce: None,
-            oidc_ext: Default::default(),
-            unknown_keys: Default::default(),
-        };
-
         assert!(
             idms_prox_read
-                .check_oauth2_authorisation(&anon_ident, &anon_uat, &auth_req, ct)
+                .oauth2_openid_publickey("nosuchclient")
                 .unwrap_err()
-                == Oauth2Error::AccessDenied
+                == OperationError::NoMatchingEntries
         );
-    }
 
-    #[idm_test]
-    async fn test_idm_oauth2_invalid_authorisation_permit_requests(
-        idms: &IdmServer,
-        _idms_delayed: &mut IdmServerDelayed,
-    ) {
-        // Test invalid oauth2 authorisation states/requests.
-        let ct = Duration::from_secs(TEST_CURRENT_TIME);
-        let (_secret, uat, ident, _) =
-            setup_oauth2_resource_server(idms, ct, true, false, false).await;
+        let discovery = idms_prox_read
+            .oauth2_openid_discovery("test_resource_server")
+            .expect("Failed to get discovery");
 
-        let (uat2, ident2) = {
-            let mut idms_prox_write = idms.proxy_write(ct).await;
-            let account = idms_prox_write
-                .target_to_account(UUID_IDM_ADMIN)
-                .expect("account must exist");
-            let session_id = uuid::Uuid::new_v4();
-            let uat2 = account
-                .to_userauthtoken(session_id, SessionScope::ReadWrite, ct)
-                .expect("Unable to create uat");
-            let ident2 = idms_prox_write
-                .process_uat_to_identity(&uat2, ct)
-                .expect("Unable to process uat");
-            (uat2, ident2)
-        };
+        let mut jwkset = idms_prox_read
+            .oauth2_openid_publickey("test_resource_server")
+            .expect("Failed to get public key");
 
-        let idms_prox_read = idms.proxy_read().await;
+        let jwk = jwkset.keys.pop().expect("no such jwk");
 
-        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        match jwk {
+            Jwk::EC { alg, use_, kid, .. } => {
+                match (
+                    alg.unwrap(),
+                    &discovery.id_token_signing_alg_values_supported[0],
+                ) {
+                    (JwaAlg::ES256, IdTokenSignAlg::ES256) => {}
+                    _ => panic!(),
+                };
+                assert!(use_.unwrap() == JwkUse::Sig);
+                assert!(kid.is_some())
+            }
+            _ => panic!(),
+        };
 
-        let consent_request = good_authorisation_request!(
-            idms_prox_read,
-            &ident,
-            &uat,
-            ct,
-            code_challenge,
-            "openid".to_string()
+        assert!(
+            discovery.issuer
+                == Url::parse("https://idm.example.com/oauth2/openid/test_resource_server")
+                    .unwrap()
         );
 
-        let consent_token =
-            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
-                consent_token
-            } else {
-                unreachable!();
-            };
+        assert!(
+            discovery.authorization_endpoint
+                == Url::parse("https://idm.example.com/ui/oauth2").unwrap()
+        );
 
-        drop(idms_prox_read);
-        let mut idms_prox_write = idms.proxy_write(ct).await;
+        assert!(
+            discovery.token_endpoint == Url::parse("https://idm.example.com/oauth2/token").unwrap()
+        );
 
-        // Invalid permits
-        //  * expired token, aka past ttl.
         assert!(
-            idms_prox_write
-                .check_oauth2_authorise_permit(
-                    &ident,
-                    &uat,
-                    &consent_token,
-                    ct + Duration::from_secs(TOKEN_EXPIRE),
+            discovery.userinfo_endpoint
+                == Some(
+                    Url::parse(
+                        "https://idm.example.com/oauth2/openid/test_resource_server/userinfo"
+                    )
+                    .unwrap()
                 )
-                .unwrap_err()
-                == OperationError::CryptographyError
         );
 
-        //  * incorrect ident
-        // We get another uat, but for a different user, and we'll introduce these
-        // inconsistently to cause confusion.
+        assert!(
+            discovery.jwks_uri
+                == Url::parse(
+                    "https://idm.example.com/oauth2/openid/test_resource_server/public_key.jwk"
+                )
+                .unwrap()
+        );
 
+        eprintln!("{:?}", discovery.scopes_supported);
         assert!(
-            idms_prox_write
-                .check_oauth2_authorise_permit(&ident2, &uat, &consent_token, ct,)
-                .unwrap_err()
-                == OperationError::InvalidSessionState
+            discovery.scopes_supported
+                == Some(vec![
+                    "groups".to_string(),
+                    "openid".to_string(),
+                    "supplement".to_string(),
+                ])
         );
 
-        //  * incorrect session id
+        assert!(discovery.response_types_supported == vec![ResponseType::Code]);
+        assert!(discovery.response_modes_supported == vec![ResponseMode::Query]);
         assert!(
-            idms_prox_write
-                .check_oauth2_authorise_permit(&ident, &uat2, &consent_token, ct,)
-                .unwrap_err()
-                == OperationError::InvalidSessionState
+            discovery.grant_types_supported
+                == vec![
+                    GrantType::AuthorisationCode,
+                    GrantType::ClientCredentials,
+                    GrantType::DeviceCode,
+                    GrantType::TokenExchange
+                ]
+        );
+        assert!(discovery.subject_types_supported == vec![SubjectType::Public]);
+        assert!(discovery.id_token_signing_alg_values_supported == vec![IdTokenSignAlg::ES256]);
+        assert!(discovery.userinfo_signing_alg_values_supported.is_none());
+        assert!(
+            discovery.token_endpoint_auth_methods_supported
+                == vec![
+                    TokenEndpointAuthMethod::ClientSecretBasic,
+                    TokenEndpointAuthMethod::ClientSecretPost,
+                    TokenEndpointAuthMethod::ClientSecretJwt,
+                    TokenEndpointAuthMethod::PrivateKeyJwt
+                ]
         );
+        assert!(discovery.display_values_supported == Some(vec![DisplayValue::Page]));
+        assert!(discovery.claim_types_supported == vec![ClaimType::Normal]);
+        assert!(discovery.claims_supported.is_none());
+        assert!(discovery.service_documentation.is_some());
 
-        assert!(idms_prox_write.commit().is_ok());
+        assert!(
+            discovery.registration_endpoint
+                == Some(Url::parse("https://idm.example.com/oauth2/register").unwrap())
+        );
+        assert!(discovery.acr_values_supported.is_none());
+        assert!(discovery.id_token_encryption_alg_values_supported.is_none());
+        assert!(discovery.id_token_encryption_enc_values_supported.is_none());
+        assert!(discovery.userinfo_encryption_alg_values_supported.is_none());
+        assert!(discovery.userinfo_encryption_enc_values_supported.is_none());
+        assert!(
+            discovery.request_object_signing_alg_values_supported
+                == Some(vec!["ES256".to_string(), "RS256".to_string()])
+        );
+        assert!(discovery
+            .request_object_encryption_alg_values_supported
+            .is_none());
+        assert!(discovery
+            .request_object_encryption_enc_values_supported
+            .is_none());
+        assert!(
+            discovery.token_endpoint_auth_signing_alg_values_supported
+                == Some(vec![
+                    "ES256".to_string(),
+                    "RS256".to_string(),
+                    "HS256".to_string()
+                ])
+        );
+        assert!(discovery.claims_locales_supported.is_none());
+        assert!(discovery.ui_locales_supported.is_none());
+        assert!(discovery.op_policy_uri.is_none());
+        assert!(discovery.op_tos_uri.is_none());
+        assert!(!discovery.claims_parameter_supported);
+        assert!(!discovery.request_uri_parameter_supported);
+        assert!(!discovery.require_request_uri_registration);
+        assert!(discovery.request_parameter_supported);
+        assert!(
+            discovery.pushed_authorization_request_endpoint
+                == Some(Url::parse("https://idm.example.com/oauth2/par").unwrap())
+        );
+        assert!(!discovery.require_pushed_authorization_requests);
+        assert!(
+            discovery.end_session_endpoint
+                == Some(
+                    Url::parse(
+                        "https://idm.example.com/oauth2/openid/test_resource_server/end_session"
+                    )
+                    .unwrap()
+                )
+        );
+        assert!(discovery.backchannel_logout_supported);
+        assert!(discovery.backchannel_logout_session_supported);
     }
 
     #[idm_test]
-    async fn test_idm_oauth2_invalid_token_exchange_requests(
+    async fn test_idm_oauth2_openid_extensions(
         idms: &IdmServer,
         _idms_delayed: &mut IdmServerDelayed,
     ) {
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
-        let (secret, mut uat, ident, _) =
+        let (secret, uat, ident, _) =
             setup_oauth2_resource_server(idms, ct, true, false, false).await;
-
-        // ⚠️  We set the uat expiry time to 5 seconds from TEST_CURRENT_TIME. This
-        // allows all our other tests to pass, but it means when we specifically put the
-        // clock forward a fraction, the fernet tokens are still valid, but the uat
-        // is not.
-        // IE
-        //   |---------------------|------------------|
-        //   TEST_CURRENT_TIME     UAT_EXPIRE         TOKEN_EXPIRE
-        //
-        // This lets us check a variety of time based cases.
-        uat.expiry = Some(
-            time::OffsetDateTime::UNIX_EPOCH
-                + Duration::from_secs(TEST_CURRENT_TIME + UAT_EXPIRE - 1),
-        );
+        let client_authz =
+            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
         let idms_prox_read = idms.proxy_read().await;
 
-        // == Setup the authorisation request
         let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+
         let consent_request = good_authorisation_request!(
             idms_prox_read,
             &ident,
@@ -2452,161 +5544,212 @@ mod tests {
                 unreachable!();
             };
 
+        // == Manually submit the consent token to the permit for the permit_success
         drop(idms_prox_read);
         let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        // == Manually submit the consent token to the permit for the permit_success
         let permit_success = idms_prox_write
             .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
             .expect("Failed to perform oauth2 permit");
 
         // == Submit the token exchange code.
-
-        // Invalid token exchange
-        //  * invalid client_authz (not base64)
         let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
-            code: permit_success.code.clone(),
+            code: permit_success.code,
             redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            code_verifier: code_verifier.clone(),
+            // From the first step.
+            code_verifier,
         }
         .into();
 
-        assert!(
-            idms_prox_write
-                .check_oauth2_token_exchange(Some("not base64"), &token_req, ct)
-                .unwrap_err()
-                == Oauth2Error::AuthenticationRequired
-        );
+        let token_response = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Failed to perform oauth2 token exchange");
 
-        //  * doesn't have :
-        let client_authz =
-            Some(general_purpose::STANDARD.encode(format!("test_resource_server {secret}")));
-        assert!(
-            idms_prox_write
-                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-                .unwrap_err()
-                == Oauth2Error::AuthenticationRequired
-        );
+        // 🎉 We got a token!
+        assert!(token_response.token_type == "bearer");
 
-        //  * invalid client_id
-        let client_authz =
-            Some(general_purpose::STANDARD.encode(format!("NOT A REAL SERVER:{secret}")));
-        assert!(
-            idms_prox_write
-                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-                .unwrap_err()
-                == Oauth2Error::AuthenticationRequired
-        );
+        let id_token = token_response.id_token.expect("No id_token in response!");
+        let access_token = token_response.access_token;
 
-        //  * valid client_id, but invalid secret
-        let client_authz = Some(general_purpose::STANDARD.encode("test_resource_server:12345"));
+        // Get the read txn for inspecting the tokens
+        assert!(idms_prox_write.commit().is_ok());
+        let mut idms_prox_read = idms.proxy_read().await;
+
+        let mut jwkset = idms_prox_read
+            .oauth2_openid_publickey("test_resource_server")
+            .expect("Failed to get public key");
+        let public_jwk = jwkset.keys.pop().expect("no such jwk");
+
+        let jws_validator = JwsValidator::try_from(&public_jwk).expect("failed to build validator");
+
+        let oidc_unverified =
+            OidcUnverified::from_str(&id_token).expect("Failed to parse id_token");
+
+        let iat = ct.as_secs() as i64;
+
+        let oidc = oidc_unverified
+            .validate(&jws_validator, iat)
+            .expect("Failed to verify oidc");
+
+        // Are the id_token values what we expect?
         assert!(
-            idms_prox_write
-                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-                .unwrap_err()
-                == Oauth2Error::AuthenticationRequired
+            oidc.iss
+                == Url::parse("https://idm.example.com/oauth2/openid/test_resource_server")
+                    .unwrap()
         );
+        assert!(oidc.sub == OidcSubject::U(UUID_ADMIN));
+        assert!(oidc.aud == "test_resource_server");
+        assert!(oidc.iat == iat);
+        assert!(oidc.nbf == Some(iat));
+        // Previously this was the auth session but it's now inline with the access token expiry.
+        assert!(oidc.exp == iat + (OAUTH2_ACCESS_TOKEN_EXPIRY as i64));
+        assert!(oidc.auth_time.is_none());
+        // Is nonce correctly passed through?
+        assert!(oidc.nonce == Some("abcdef".to_string()));
+        assert!(oidc.at_hash.is_none());
+        assert!(oidc.acr.is_none());
+        assert!(oidc.amr.is_none());
+        assert!(oidc.azp == Some("test_resource_server".to_string()));
+        assert!(oidc.jti.is_none());
+        assert!(oidc.s_claims.name == Some("System Administrator".to_string()));
+        assert!(oidc.s_claims.preferred_username == Some("admin@example.com".to_string()));
+        assert!(oidc.s_claims.scopes == vec!["openid".to_string(), "supplement".to_string()]);
+        // Only the session id is present, for back-channel logout.
+        assert!(oidc.claims.len() == 1);
+        assert!(oidc.claims.contains_key("sid"));
+        // Does our access token work with the userinfo endpoint?
+        // Do the id_token details line up to the userinfo?
+        let userinfo = idms_prox_read
+            .oauth2_openid_userinfo("test_resource_server", &access_token, ct)
+            .expect("failed to get userinfo");
 
-        // ✅ Now the valid client_authz is in place.
+        assert!(oidc.iss == userinfo.iss);
+        assert!(oidc.sub == userinfo.sub);
+        assert!(oidc.aud == userinfo.aud);
+        assert!(oidc.iat == userinfo.iat);
+        assert!(oidc.nbf == userinfo.nbf);
+        assert!(oidc.exp == userinfo.exp);
+        assert!(userinfo.auth_time.is_none());
+        assert!(userinfo.nonce.is_none());
+        assert!(userinfo.at_hash.is_none());
+        assert!(userinfo.acr.is_none());
+        assert!(oidc.amr == userinfo.amr);
+        assert!(oidc.azp == userinfo.azp);
+        assert!(userinfo.jti.is_none());
+        assert!(oidc.s_claims == userinfo.s_claims);
+        assert!(userinfo.claims.is_empty());
+    }
+
+    #[idm_test]
+    async fn test_idm_oauth2_openid_short_username(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        // we run the same test as test_idm_oauth2_openid_extensions()
+        // but change the preferred_username setting on the RS
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (secret, uat, ident, _) =
+            setup_oauth2_resource_server(idms, ct, true, false, true).await;
         let client_authz =
             Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
-        //  * expired exchange code (took too long)
-        assert!(
-            idms_prox_write
-                .check_oauth2_token_exchange(
-                    client_authz.as_deref(),
-                    &token_req,
-                    ct + Duration::from_secs(TOKEN_EXPIRE)
-                )
-                .unwrap_err()
-                == Oauth2Error::InvalidRequest
-        );
 
-        //  * Uat has expired!
-        // NOTE: This is setup EARLY in the test, by manipulation of the UAT expiry.
-        assert!(
-            idms_prox_write
-                .check_oauth2_token_exchange(
-                    client_authz.as_deref(),
-                    &token_req,
-                    ct + Duration::from_secs(UAT_EXPIRE)
-                )
-                .unwrap_err()
-                == Oauth2Error::AccessDenied
-        );
+        let idms_prox_read = idms.proxy_read().await;
 
-        /*
-        //  * incorrect grant_type
-        // No longer possible due to changes in json api
-        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
-            grant_type: "INCORRECT GRANT TYPE".to_string(),
-            code: permit_success.code.clone(),
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            client_id: None,
-            client_secret: None,
-            code_verifier: code_verifier.clone(),
-        };
-        assert!(
-            idms_prox_read
-                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-                .unwrap_err()
-                == Oauth2Error::InvalidRequest
-        );
-        */
+        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
 
-        //  * Incorrect redirect uri
-        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
-            code: permit_success.code.clone(),
-            redirect_uri: Url::parse("https://totes.not.sus.org/oauth2/result").unwrap(),
-            code_verifier,
-        }
-        .into();
-        assert!(
-            idms_prox_write
-                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-                .unwrap_err()
-                == Oauth2Error::InvalidOrigin
+        let consent_request = good_authorisation_request!(
+            idms_prox_read,
+            &ident,
+            &uat,
+            ct,
+            code_challenge,
+            "openid".to_string()
         );
 
-        //  * code verifier incorrect
+        let consent_token =
+            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
+                consent_token
+            } else {
+                unreachable!();
+            };
+
+        // == Manually submit the consent token to the permit for the permit_success
+        drop(idms_prox_read);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        let permit_success = idms_prox_write
+            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
+            .expect("Failed to perform oauth2 permit");
+
+        // == Submit the token exchange code.
         let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
             code: permit_success.code,
             redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            code_verifier: Some("12345".to_string()),
+            // From the first step.
+            code_verifier,
         }
         .into();
-        assert!(
-            idms_prox_write
-                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-                .unwrap_err()
-                == Oauth2Error::InvalidRequest
-        );
+
+        let token_response = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Failed to perform oauth2 token exchange");
+
+        let id_token = token_response.id_token.expect("No id_token in response!");
+        let access_token = token_response.access_token;
 
         assert!(idms_prox_write.commit().is_ok());
+        let mut idms_prox_read = idms.proxy_read().await;
+
+        let mut jwkset = idms_prox_read
+            .oauth2_openid_publickey("test_resource_server")
+            .expect("Failed to get public key");
+        let public_jwk = jwkset.keys.pop().expect("no such jwk");
+
+        let jws_validator = JwsValidator::try_from(&public_jwk).expect("failed to build validator");
+
+        let oidc_unverified =
+            OidcUnverified::from_str(&id_token).expect("Failed to parse id_token");
+
+        let iat = ct.as_secs() as i64;
+
+        let oidc = oidc_unverified
+            .validate(&jws_validator, iat)
+            .expect("Failed to verify oidc");
+
+        // Do we have the short username in the token claims?
+        assert!(oidc.s_claims.preferred_username == Some("admin".to_string()));
+        // Do the id_token details line up to the userinfo?
+        let userinfo = idms_prox_read
+            .oauth2_openid_userinfo("test_resource_server", &access_token, ct)
+            .expect("failed to get userinfo");
+
+        assert!(oidc.s_claims == userinfo.s_claims);
     }
 
     #[idm_test]
-    async fn test_idm_oauth2_token_introspect(
+    async fn test_idm_oauth2_openid_group_claims(
         idms: &IdmServer,
         _idms_delayed: &mut IdmServerDelayed,
     ) {
+        // we run the same test as test_idm_oauth2_openid_extensions()
+        // but change the preferred_username setting on the RS
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
         let (secret, uat, ident, _) =
-            setup_oauth2_resource_server(idms, ct, true, false, false).await;
+            setup_oauth2_resource_server(idms, ct, true, false, true).await;
         let client_authz =
             Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
         let idms_prox_read = idms.proxy_read().await;
 
-        // == Setup the authorisation request
         let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+
         let consent_request = good_authorisation_request!(
             idms_prox_read,
             &ident,
             &uat,
             ct,
             code_challenge,
-            "openid".to_string()
+            "openid groups".to_string()
         );
 
         let consent_token =
@@ -2624,80 +5767,143 @@ mod tests {
             .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
             .expect("Failed to perform oauth2 permit");
 
+        // == Submit the token exchange code.
         let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
             code: permit_success.code,
             redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            // From the first step.
             code_verifier,
         }
         .into();
-        let oauth2_token = idms_prox_write
+
+        let token_response = idms_prox_write
             .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .expect("Unable to exchange for oauth2 token");
+            .expect("Failed to perform oauth2 token exchange");
 
-        assert!(idms_prox_write.commit().is_ok());
+        let id_token = token_response.id_token.expect("No id_token in response!");
+        let access_token = token_response.access_token;
 
-        // Okay, now we have the token, we can check it works with introspect.
+        assert!(idms_prox_write.commit().is_ok());
         let mut idms_prox_read = idms.proxy_read().await;
 
-        let intr_request = AccessTokenIntrospectRequest {
-            token: oauth2_token.access_token,
-            token_type_hint: None,
-        };
-        let intr_response = idms_prox_read
-            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
-            .expect("Failed to inspect token");
+        let mut jwkset = idms_prox_read
+            .oauth2_openid_publickey("test_resource_server")
+            .expect("Failed to get public key");
+        let public_jwk = jwkset.keys.pop().expect("no such jwk");
 
-        eprintln!("👉  {intr_response:?}");
-        assert!(intr_response.active);
-        assert!(intr_response.scope.as_deref() == Some("openid supplement"));
-        assert!(intr_response.client_id.as_deref() == Some("test_resource_server"));
-        assert!(intr_response.username.as_deref() == Some("admin@example.com"));
-        assert!(intr_response.token_type.as_deref() == Some("access_token"));
-        assert!(intr_response.iat == Some(ct.as_secs() as i64));
-        assert!(intr_response.nbf == Some(ct.as_secs() as i64));
+        let jws_validator = JwsValidator::try_from(&public_jwk).expect("failed to build validator");
 
-        drop(idms_prox_read);
-        // start a write,
+        let oidc_unverified =
+            OidcUnverified::from_str(&id_token).expect("Failed to parse id_token");
 
-        let mut idms_prox_write = idms.proxy_write(ct).await;
-        // Expire the account, should cause introspect to return inactive.
-        let v_expire = Value::new_datetime_epoch(Duration::from_secs(TEST_CURRENT_TIME - 1));
-        let me_inv_m = unsafe {
-            ModifyEvent::new_internal_invalid(
-                filter!(f_eq("name", PartialValue::new_iname("admin"))),
-                ModifyList::new_list(vec![Modify::Present(
-                    AttrString::from("account_expire"),
-                    v_expire,
-                )]),
-            )
-        };
-        // go!
-        assert!(idms_prox_write.qs_write.modify(&me_inv_m).is_ok());
-        assert!(idms_prox_write.commit().is_ok());
+        let iat = ct.as_secs() as i64;
 
-        // start a new read
-        // check again.
-        let mut idms_prox_read = idms.proxy_read().await;
-        let intr_response = idms_prox_read
-            .check_oauth2_token_introspect(&client_authz.unwrap(), &intr_request, ct)
-            .expect("Failed to inspect token");
+        let oidc = oidc_unverified
+            .validate(&jws_validator, iat)
+            .expect("Failed to verify oidc");
 
-        assert!(!intr_response.active);
+        // does our id_token contain the expected groups?
+        assert!(oidc.claims.contains_key(&"groups".to_string()));
+
+        assert!(oidc
+            .claims
+            .get(&"groups".to_string())
+            .expect("unable to find key")
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!(STR_UUID_IDM_ALL_ACCOUNTS)));
+
+        // Do the id_token details line up to the userinfo?
+        let userinfo = idms_prox_read
+            .oauth2_openid_userinfo("test_resource_server", &access_token, ct)
+            .expect("failed to get userinfo");
+
+        // does the userinfo endpoint provide the same groups?
+        assert!(
+            oidc.claims.get(&"groups".to_string()) == userinfo.claims.get(&"groups".to_string())
+        );
     }
 
+    //  Check insecure pkce behaviour.
     #[idm_test]
-    async fn test_idm_oauth2_token_revoke(idms: &IdmServer, _idms_delayed: &mut IdmServerDelayed) {
-        // First, setup to get a token.
+    async fn test_idm_oauth2_insecure_pkce(idms: &IdmServer, _idms_delayed: &mut IdmServerDelayed) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (_secret, uat, ident, _) =
+            setup_oauth2_resource_server(idms, ct, false, false, false).await;
+
+        let idms_prox_read = idms.proxy_read().await;
+
+        // == Setup the authorisation request
+        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+
+        // Even in disable pkce mode, we will allow pkce
+        let _consent_request = good_authorisation_request!(
+            idms_prox_read,
+            &ident,
+            &uat,
+            ct,
+            code_challenge,
+            "openid".to_string()
+        );
+
+        // Check we allow none.
+        let auth_req = AuthorisationRequest {
+            response_type: "code".to_string(),
+            client_id: "test_resource_server".to_string(),
+            state: "123".to_string(),
+            pkce_request: None,
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            scope: "openid".to_string(),
+            nonce: Some("abcdef".to_string()),
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
+
+        idms_prox_read
+            .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+            .expect("Oauth2 authorisation failed");
+    }
+
+    #[idm_test]
+    async fn test_idm_oauth2_openid_legacy_crypto(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
         let (secret, uat, ident, _) =
-            setup_oauth2_resource_server(idms, ct, true, false, false).await;
-        let client_authz =
-            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
-
+            setup_oauth2_resource_server(idms, ct, false, true, false).await;
         let idms_prox_read = idms.proxy_read().await;
+        // The public key url should offer an rs key
+        // discovery should offer RS256
+        let discovery = idms_prox_read
+            .oauth2_openid_discovery("test_resource_server")
+            .expect("Failed to get discovery");
 
-        // == Setup the authorisation request
+        let mut jwkset = idms_prox_read
+            .oauth2_openid_publickey("test_resource_server")
+            .expect("Failed to get public key");
+
+        let jwk = jwkset.keys.pop().expect("no such jwk");
+        let public_jwk = jwk.clone();
+
+        match jwk {
+            Jwk::RSA { alg, use_, kid, .. } => {
+                match (
+                    alg.unwrap(),
+                    &discovery.id_token_signing_alg_values_supported[0],
+                ) {
+                    (JwaAlg::RS256, IdTokenSignAlg::RS256) => {}
+                    _ => panic!(),
+                };
+                assert!(use_.unwrap() == JwkUse::Sig);
+                assert!(kid.is_some());
+            }
+            _ => panic!(),
+        };
+
+        // Check that the id_token is signed with the correct key.
         let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+
         let consent_request = good_authorisation_request!(
             idms_prox_read,
             &ident,
@@ -2722,128 +5928,56 @@ mod tests {
             .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
             .expect("Failed to perform oauth2 permit");
 
-        // Assert that the consent was submitted
-        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
-            code: permit_success.code,
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            code_verifier,
-        }
-        .into();
-        let oauth2_token = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .expect("Unable to exchange for oauth2 token");
-
-        assert!(idms_prox_write.commit().is_ok());
-
-        // Okay, now we have the token, we can check behaviours with the revoke interface.
-
-        // First, assert it is valid, similar to the introspect api.
-        let mut idms_prox_read = idms.proxy_read().await;
-        let intr_request = AccessTokenIntrospectRequest {
-            token: oauth2_token.access_token.clone(),
-            token_type_hint: None,
-        };
-        let intr_response = idms_prox_read
-            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
-            .expect("Failed to inspect token");
-        eprintln!("👉  {intr_response:?}");
-        assert!(intr_response.active);
-        drop(idms_prox_read);
-
-        // First, the revoke needs basic auth. Provide incorrect auth, and we fail.
-        let mut idms_prox_write = idms.proxy_write(ct).await;
-
-        let bad_client_authz = Some(general_purpose::STANDARD.encode("test_resource_server:12345"));
-        let revoke_request = TokenRevokeRequest {
-            token: oauth2_token.access_token.clone(),
-            token_type_hint: None,
-        };
-        let e = idms_prox_write
-            .oauth2_token_revoke(bad_client_authz.as_deref().unwrap(), &revoke_request, ct)
-            .unwrap_err();
-        assert!(matches!(e, Oauth2Error::AuthenticationRequired));
-        assert!(idms_prox_write.commit().is_ok());
-
-        // Now submit a non-existent/invalid token. Does not affect our tokens validity.
-        let mut idms_prox_write = idms.proxy_write(ct).await;
-        let revoke_request = TokenRevokeRequest {
-            token: "this is an invalid token, nothing will happen!".to_string(),
-            token_type_hint: None,
+        // == Submit the token exchange code.
+        let token_req = AccessTokenRequest {
+            grant_type: GrantTypeReq::AuthorizationCode {
+                code: permit_success.code,
+                redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+                // From the first step.
+                code_verifier,
+            },
+            client_id: Some("test_resource_server".to_string()),
+            client_secret: Some(secret),
+            client_assertion_type: None,
+            client_assertion: None,
         };
-        let e = idms_prox_write
-            .oauth2_token_revoke(client_authz.as_deref().unwrap(), &revoke_request, ct)
-            .unwrap_err();
-        assert!(matches!(e, Oauth2Error::InvalidRequest));
-        assert!(idms_prox_write.commit().is_ok());
 
-        // Check our token is still valid.
-        let mut idms_prox_read = idms.proxy_read().await;
-        let intr_response = idms_prox_read
-            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
-            .expect("Failed to inspect token");
-        assert!(intr_response.active);
-        drop(idms_prox_read);
+        let token_response = idms_prox_write
+            .check_oauth2_token_exchange(None, &token_req, ct)
+            .expect("Failed to perform oauth2 token exchange");
 
-        // Finally revoke it.
-        let mut idms_prox_write = idms.proxy_write(ct).await;
-        let revoke_request = TokenRevokeRequest {
-            token: oauth2_token.access_token.clone(),
-            token_type_hint: None,
-        };
-        assert!(idms_prox_write
-            .oauth2_token_revoke(client_authz.as_deref().unwrap(), &revoke_request, ct,)
-            .is_ok());
-        assert!(idms_prox_write.commit().is_ok());
+        // 🎉 We got a token!
+        assert!(token_response.token_type == "bearer");
+        let id_token = token_response.id_token.expect("No id_token in response!");
 
-        // Check it is still valid - this is because we are still in the GRACE window.
-        let mut idms_prox_read = idms.proxy_read().await;
-        let intr_response = idms_prox_read
-            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
-            .expect("Failed to inspect token");
+        let jws_validator = JwsValidator::try_from(&public_jwk).expect("failed to build validator");
 
-        assert!(intr_response.active);
-        drop(idms_prox_read);
+        let oidc_unverified =
+            OidcUnverified::from_str(&id_token).expect("Failed to parse id_token");
 
-        // Check after the grace window, it will be invalid.
-        let ct = ct + GRACE_WINDOW;
+        let iat = ct.as_secs() as i64;
 
-        // Assert it is now invalid.
-        let mut idms_prox_read = idms.proxy_read().await;
-        let intr_response = idms_prox_read
-            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
-            .expect("Failed to inspect token");
+        let oidc = oidc_unverified
+            .validate(&jws_validator, iat)
+            .expect("Failed to verify oidc");
 
-        assert!(!intr_response.active);
-        drop(idms_prox_read);
+        assert!(oidc.sub == OidcSubject::U(UUID_ADMIN));
 
-        // A second invalidation of the token "does nothing".
-        let mut idms_prox_write = idms.proxy_write(ct).await;
-        let revoke_request = TokenRevokeRequest {
-            token: oauth2_token.access_token,
-            token_type_hint: None,
-        };
-        assert!(idms_prox_write
-            .oauth2_token_revoke(client_authz.as_deref().unwrap(), &revoke_request, ct,)
-            .is_ok());
         assert!(idms_prox_write.commit().is_ok());
     }
 
     #[idm_test]
-    async fn test_idm_oauth2_session_cleanup_post_rs_delete(
+    async fn test_idm_oauth2_consent_granted_and_changed_workflow(
         idms: &IdmServer,
         _idms_delayed: &mut IdmServerDelayed,
     ) {
-        // First, setup to get a token.
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
-        let (secret, uat, ident, _) =
+        let (_secret, uat, ident, _) =
             setup_oauth2_resource_server(idms, ct, true, false, false).await;
-        let client_authz =
-            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
         let idms_prox_read = idms.proxy_read().await;
 
-        // == Setup the authorisation request
-        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
         let consent_request = good_authorisation_request!(
             idms_prox_read,
             &ident,
@@ -2853,6 +5987,7 @@ mod tests {
             "openid".to_string()
         );
 
+        // Should be in the consent phase;
         let consent_token =
             if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
                 consent_token
@@ -2864,101 +5999,21 @@ mod tests {
         drop(idms_prox_read);
         let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let permit_success = idms_prox_write
-            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
-            .expect("Failed to perform oauth2 permit");
-
-        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
-            code: permit_success.code,
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            code_verifier,
-        }
-        .into();
-
-        let oauth2_token = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .expect("Unable to exchange for oauth2 token");
-
-        let reflected_token = idms_prox_write
-            .reflect_oauth2_token(client_authz.as_ref().unwrap(), &oauth2_token.access_token)
-            .expect("Failed to access internals of the refresh token");
-
-        let session_id = match reflected_token {
-            Oauth2TokenType::Refresh { session_id, .. } => session_id,
-            Oauth2TokenType::Access { session_id, .. } => session_id,
-        };
-
-        assert!(idms_prox_write.commit().is_ok());
-
-        // Process it to ensure the record exists.
-        let mut idms_prox_write = idms.proxy_write(ct).await;
-
-        // Check it is now there
-        let entry = idms_prox_write
-            .qs_write
-            .internal_search_uuid(UUID_ADMIN)
-            .expect("failed");
-        let valid = entry
-            .get_ava_as_oauth2session_map("oauth2_session")
-            .map(|map| map.get(&session_id).is_some())
-            .unwrap_or(false);
-        assert!(valid);
-
-        // Delete the resource server.
-
-        let de = unsafe {
-            DeleteEvent::new_internal_invalid(filter!(f_eq(
-                "oauth2_rs_name",
-                PartialValue::new_iname("test_resource_server")
-            )))
-        };
-
-        assert!(idms_prox_write.qs_write.delete(&de).is_ok());
-
-        // Assert the session is gone. This is cleaned up as an artifact of the referential
-        // integrity plugin.
-        let entry = idms_prox_write
-            .qs_write
-            .internal_search_uuid(UUID_ADMIN)
-            .expect("failed");
-        let valid = entry
-            .get_ava_as_oauth2session_map("oauth2_session")
-            .map(|map| map.get(&session_id).is_some())
-            .unwrap_or(false);
-        assert!(!valid);
-
-        assert!(idms_prox_write.commit().is_ok());
-    }
-
-    #[idm_test]
-    async fn test_idm_oauth2_authorisation_reject(
-        idms: &IdmServer,
-        _idms_delayed: &mut IdmServerDelayed,
-    ) {
-        let ct = Duration::from_secs(TEST_CURRENT_TIME);
-        let (_secret, uat, ident, _) =
-            setup_oauth2_resource_server(idms, ct, true, false, false).await;
-
-        let (uat2, ident2) = {
-            let mut idms_prox_write = idms.proxy_write(ct).await;
-            let account = idms_prox_write
-                .target_to_account(UUID_IDM_ADMIN)
-                .expect("account must exist");
-            let session_id = uuid::Uuid::new_v4();
-            let uat2 = account
-                .to_userauthtoken(session_id, SessionScope::ReadWrite, ct)
-                .expect("Unable to create uat");
-            let ident2 = idms_prox_write
-                .process_uat_to_identity(&uat2, ct)
-                .expect("Unable to process uat");
-            (uat2, ident2)
-        };
+        let _permit_success = idms_prox_write
+            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
+            .expect("Failed to perform oauth2 permit");
 
-        let idms_prox_read = idms.proxy_read().await;
-        let redirect_uri = Url::parse("https://demo.example.com/oauth2/result").unwrap();
-        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        assert!(idms_prox_write.commit().is_ok());
 
-        // Check reject behaviour
+        // == Now try the authorise again, should be in the permitted state.
+        let mut idms_prox_read = idms.proxy_read().await;
+
+        // We need to reload our identity
+        let ident = idms_prox_read
+            .process_uat_to_identity(&uat, ct)
+            .expect("Unable to process uat");
+
+        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
         let consent_request = good_authorisation_request!(
             idms_prox_read,
             &ident,
@@ -2968,207 +6023,164 @@ mod tests {
             "openid".to_string()
         );
 
-        let consent_token =
-            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
-                consent_token
-            } else {
-                unreachable!();
-            };
+        // Should be in the consent phase;
+        let _permit_success = if let AuthoriseResponse::Permitted(permit_success) = consent_request
+        {
+            permit_success
+        } else {
+            unreachable!();
+        };
 
-        let reject_success = idms_prox_read
-            .check_oauth2_authorise_reject(&ident, &uat, &consent_token, ct)
-            .expect("Failed to perform oauth2 reject");
+        drop(idms_prox_read);
 
-        assert!(reject_success == redirect_uri);
+        // Great! Now change the scopes on the oauth2 instance, this revokes the permit.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        // Too much time past to reject
-        let past_ct = Duration::from_secs(TEST_CURRENT_TIME + 301);
-        assert!(
-            idms_prox_read
-                .check_oauth2_authorise_reject(&ident, &uat, &consent_token, past_ct)
-                .unwrap_err()
-                == OperationError::CryptographyError
-        );
+        let me_extend_scopes = unsafe {
+            ModifyEvent::new_internal_invalid(
+                filter!(f_eq(
+                    "oauth2_rs_name",
+                    PartialValue::new_iname("test_resource_server")
+                )),
+                ModifyList::new_list(vec![Modify::Present(
+                    AttrString::from("oauth2_rs_scope_map"),
+                    Value::new_oauthscopemap(
+                        UUID_IDM_ALL_ACCOUNTS,
+                        btreeset!["email".to_string(), "openid".to_string()],
+                    )
+                    .expect("invalid oauthscope"),
+                )]),
+            )
+        };
 
-        // Invalid consent token
-        assert!(
-            idms_prox_read
-                .check_oauth2_authorise_reject(&ident, &uat, "not a token", ct)
-                .unwrap_err()
-                == OperationError::CryptographyError
-        );
+        assert!(idms_prox_write.qs_write.modify(&me_extend_scopes).is_ok());
+        assert!(idms_prox_write.commit().is_ok());
 
-        // Wrong UAT
-        assert!(
-            idms_prox_read
-                .check_oauth2_authorise_reject(&ident, &uat2, &consent_token, ct)
-                .unwrap_err()
-                == OperationError::InvalidSessionState
-        );
-        // Wrong ident
-        assert!(
-            idms_prox_read
-                .check_oauth2_authorise_reject(&ident2, &uat, &consent_token, ct)
-                .unwrap_err()
-                == OperationError::InvalidSessionState
-        );
-    }
+        // And do the workflow once more to see if we need to consent again.
 
-    #[idm_test]
-    async fn test_idm_oauth2_openid_discovery(
-        idms: &IdmServer,
-        _idms_delayed: &mut IdmServerDelayed,
-    ) {
-        let ct = Duration::from_secs(TEST_CURRENT_TIME);
-        let (_secret, _uat, _ident, _) =
-            setup_oauth2_resource_server(idms, ct, true, false, false).await;
+        let mut idms_prox_read = idms.proxy_read().await;
 
-        let idms_prox_read = idms.proxy_read().await;
+        // We need to reload our identity
+        let ident = idms_prox_read
+            .process_uat_to_identity(&uat, ct)
+            .expect("Unable to process uat");
 
-        // check the discovery end point works as we expect
-        assert!(
-            idms_prox_read
-                .oauth2_openid_discovery("nosuchclient")
-                .unwrap_err()
-                == OperationError::NoMatchingEntries
-        );
+        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
 
-        assert!(
-            idms_prox_read
-                .oauth2_openid_publickey("nosuchclient")
-                .unwrap_err()
-                == OperationError::NoMatchingEntries
-        );
+        let auth_req = AuthorisationRequest {
+            response_type: "code".to_string(),
+            client_id: "test_resource_server".to_string(),
+            state: "123".to_string(),
+            pkce_request: Some(PkceRequest {
+                code_challenge: Base64UrlSafeData(code_challenge),
+                code_challenge_method: CodeChallengeMethod::S256,
+            }),
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            scope: "openid email".to_string(),
+            nonce: Some("abcdef".to_string()),
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
 
-        let discovery = idms_prox_read
-            .oauth2_openid_discovery("test_resource_server")
-            .expect("Failed to get discovery");
+        let consent_request = idms_prox_read
+            .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+            .expect("Oauth2 authorisation failed");
 
-        let mut jwkset = idms_prox_read
-            .oauth2_openid_publickey("test_resource_server")
-            .expect("Failed to get public key");
+        // Should be in the consent phase;
+        let _consent_token =
+            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
+                consent_token
+            } else {
+                unreachable!();
+            };
 
-        let jwk = jwkset.keys.pop().expect("no such jwk");
+        drop(idms_prox_read);
 
-        match jwk {
-            Jwk::EC { alg, use_, kid, .. } => {
-                match (
-                    alg.unwrap(),
-                    &discovery.id_token_signing_alg_values_supported[0],
-                ) {
-                    (JwaAlg::ES256, IdTokenSignAlg::ES256) => {}
-                    _ => panic!(),
-                };
-                assert!(use_.unwrap() == JwkUse::Sig);
-                assert!(kid.is_some())
-            }
-            _ => panic!(),
+        // Success! We had to consent again due to the change :)
+
+        // Now change the supplemental scopes on the oauth2 instance, this revokes the permit.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        let me_extend_scopes = unsafe {
+            ModifyEvent::new_internal_invalid(
+                filter!(f_eq(
+                    "oauth2_rs_name",
+                    PartialValue::new_iname("test_resource_server")
+                )),
+                ModifyList::new_list(vec![Modify::Present(
+                    AttrString::from("oauth2_rs_sup_scope_map"),
+                    Value::new_oauthscopemap(
+                        UUID_IDM_ALL_ACCOUNTS,
+                        btreeset!["newscope".to_string()],
+                    )
+                    .expect("invalid oauthscope"),
+                )]),
+            )
         };
 
-        assert!(
-            discovery.issuer
-                == Url::parse("https://idm.example.com/oauth2/openid/test_resource_server")
-                    .unwrap()
-        );
+        assert!(idms_prox_write.qs_write.modify(&me_extend_scopes).is_ok());
+        assert!(idms_prox_write.commit().is_ok());
 
-        assert!(
-            discovery.authorization_endpoint
-                == Url::parse("https://idm.example.com/ui/oauth2").unwrap()
-        );
+        // And do the workflow once more to see if we need to consent again.
 
-        assert!(
-            discovery.token_endpoint == Url::parse("https://idm.example.com/oauth2/token").unwrap()
-        );
+        let mut idms_prox_read = idms.proxy_read().await;
 
-        assert!(
-            discovery.userinfo_endpoint
-                == Some(
-                    Url::parse(
-                        "https://idm.example.com/oauth2/openid/test_resource_server/userinfo"
-                    )
-                    .unwrap()
-                )
-        );
+        // We need to reload our identity
+        let ident = idms_prox_read
+            .process_uat_to_identity(&uat, ct)
+            .expect("Unable to process uat");
 
-        assert!(
-            discovery.jwks_uri
-                == Url::parse(
-                    "https://idm.example.com/oauth2/openid/test_resource_server/public_key.jwk"
-                )
-                .unwrap()
-        );
+        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
 
-        eprintln!("{:?}", discovery.scopes_supported);
-        assert!(
-            discovery.scopes_supported
-                == Some(vec![
-                    "groups".to_string(),
-                    "openid".to_string(),
-                    "supplement".to_string(),
-                ])
-        );
+        let auth_req = AuthorisationRequest {
+            response_type: "code".to_string(),
+            client_id: "test_resource_server".to_string(),
+            state: "123".to_string(),
+            pkce_request: Some(PkceRequest {
+                code_challenge: Base64UrlSafeData(code_challenge),
+                code_challenge_method: CodeChallengeMethod::S256,
+            }),
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            // Note the scope isn't requested here!
+            scope: "openid email".to_string(),
+            nonce: Some("abcdef".to_string()),
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
 
-        assert!(discovery.response_types_supported == vec![ResponseType::Code]);
-        assert!(discovery.response_modes_supported == vec![ResponseMode::Query]);
-        assert!(discovery.grant_types_supported == vec![GrantType::AuthorisationCode]);
-        assert!(discovery.subject_types_supported == vec![SubjectType::Public]);
-        assert!(discovery.id_token_signing_alg_values_supported == vec![IdTokenSignAlg::ES256]);
-        assert!(discovery.userinfo_signing_alg_values_supported.is_none());
-        assert!(
-            discovery.token_endpoint_auth_methods_supported
-                == vec![
-                    TokenEndpointAuthMethod::ClientSecretBasic,
-                    TokenEndpointAuthMethod::ClientSecretPost
-                ]
-        );
-        assert!(discovery.display_values_supported == Some(vec![DisplayValue::Page]));
-        assert!(discovery.claim_types_supported == vec![ClaimType::Normal]);
-        assert!(discovery.claims_supported.is_none());
-        assert!(discovery.service_documentation.is_some());
+        let consent_request = idms_prox_read
+            .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+            .expect("Oauth2 authorisation failed");
 
-        assert!(discovery.registration_endpoint.is_none());
-        assert!(discovery.acr_values_supported.is_none());
-        assert!(discovery.id_token_encryption_alg_values_supported.is_none());
-        assert!(discovery.id_token_encryption_enc_values_supported.is_none());
-        assert!(discovery.userinfo_encryption_alg_values_supported.is_none());
-        assert!(discovery.userinfo_encryption_enc_values_supported.is_none());
-        assert!(discovery
-            .request_object_signing_alg_values_supported
-            .is_none());
-        assert!(discovery
-            .request_object_encryption_alg_values_supported
-            .is_none());
-        assert!(discovery
-            .request_object_encryption_enc_values_supported
-            .is_none());
-        assert!(discovery
-            .token_endpoint_auth_signing_alg_values_supported
-            .is_none());
-        assert!(discovery.claims_locales_supported.is_none());
-        assert!(discovery.ui_locales_supported.is_none());
-        assert!(discovery.op_policy_uri.is_none());
-        assert!(discovery.op_tos_uri.is_none());
-        assert!(!discovery.claims_parameter_supported);
-        assert!(!discovery.request_uri_parameter_supported);
-        assert!(!discovery.require_request_uri_registration);
-        assert!(discovery.request_parameter_supported);
+        // Should be present in the consent phase however!
+        let _consent_token = if let AuthoriseResponse::ConsentRequested {
+            consent_token,
+            scopes,
+            ..
+        } = consent_request
+        {
+            assert!(scopes.contains(&"newscope".to_string()));
+            consent_token
+        } else {
+            unreachable!();
+        };
     }
 
     #[idm_test]
-    async fn test_idm_oauth2_openid_extensions(
+    async fn test_idm_oauth2_consent_granted_refint_cleanup_on_delete(
         idms: &IdmServer,
         _idms_delayed: &mut IdmServerDelayed,
     ) {
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
-        let (secret, uat, ident, _) =
+        let (_secret, uat, ident, o2rs_uuid) =
             setup_oauth2_resource_server(idms, ct, true, false, false).await;
-        let client_authz =
-            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
-        let idms_prox_read = idms.proxy_read().await;
+        // Assert there are no consent maps yet.
+        assert!(ident.get_oauth2_consent_scopes(o2rs_uuid).is_none());
 
-        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        let idms_prox_read = idms.proxy_read().await;
 
+        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
         let consent_request = good_authorisation_request!(
             idms_prox_read,
             &ident,
@@ -3178,6 +6190,7 @@ mod tests {
             "openid".to_string()
         );
 
+        // Should be in the consent phase;
         let consent_token =
             if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
                 consent_token
@@ -3189,113 +6202,178 @@ mod tests {
         drop(idms_prox_read);
         let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let permit_success = idms_prox_write
+        let _permit_success = idms_prox_write
             .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
             .expect("Failed to perform oauth2 permit");
 
-        // == Submit the token exchange code.
-        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
-            code: permit_success.code,
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            // From the first step.
-            code_verifier,
-        }
-        .into();
+        let ident = idms_prox_write
+            .process_uat_to_identity(&uat, ct)
+            .expect("Unable to process uat");
 
-        let token_response = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .expect("Failed to perform oauth2 token exchange");
+        // Assert that the ident now has the consents.
+        assert!(
+            ident.get_oauth2_consent_scopes(o2rs_uuid)
+                == Some(&btreeset!["openid".to_string(), "supplement".to_string()])
+        );
 
-        // 🎉 We got a token!
-        assert!(token_response.token_type == "bearer");
+        // Now trigger the delete of the RS
+        let de = unsafe {
+            DeleteEvent::new_internal_invalid(filter!(f_eq(
+                "oauth2_rs_name",
+                PartialValue::new_iname("test_resource_server")
+            )))
+        };
 
-        let id_token = token_response.id_token.expect("No id_token in response!");
-        let access_token = token_response.access_token;
+        assert!(idms_prox_write.qs_write.delete(&de).is_ok());
+        // Assert the consent maps are gone.
+        let ident = idms_prox_write
+            .process_uat_to_identity(&uat, ct)
+            .expect("Unable to process uat");
+        assert!(ident.get_oauth2_consent_scopes(o2rs_uuid).is_none());
 
-        // Get the read txn for inspecting the tokens
         assert!(idms_prox_write.commit().is_ok());
-        let mut idms_prox_read = idms.proxy_read().await;
+    }
 
-        let mut jwkset = idms_prox_read
-            .oauth2_openid_publickey("test_resource_server")
-            .expect("Failed to get public key");
-        let public_jwk = jwkset.keys.pop().expect("no such jwk");
+    #[idm_test]
+    // https://datatracker.ietf.org/doc/html/draft-ietf-oauth-security-topics#section-4.8
+    //
+    // It was reported we were vulnerable to this attack, but that isn't the case. First
+    // this attack relies on stripping the *code_challenge* from the internals of the returned
+    // code exchange token. This isn't possible due to our use of encryption of the code exchange
+    // token. If that code challenge *could* be removed, then the attacker could use the code exchange
+    // with no verifier or an incorrect verifier.
+    //
+    // Due to the logic in our server, if a code exchange contains a code challenge we always enforce
+    // it is correctly used!
+    //
+    // This left a single odd case where if a client did an authorisation request without a pkce
+    // verifier, but then a verifier was submitted during the code exchange, that the server would
+    // *ignore* the verifier parameter. In this case, no stripping of the code challenge was done,
+    // and the client could have simply also submitted *no* verifier anyway. It could be that
+    // an attacker could gain a code exchange with no code challenge and then force a victim to
+    // exchange that code exchange with out the verifier, but I'm not sure what damage that would
+    // lead to? Regardless, we test for and close off that possible hole in this test.
+    //
+    async fn test_idm_oauth2_1076_pkce_downgrade(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        // Enable pkce is set to FALSE
+        let (secret, uat, ident, _) =
+            setup_oauth2_resource_server(idms, ct, false, false, false).await;
 
-        let jws_validator = JwsValidator::try_from(&public_jwk).expect("failed to build validator");
+        let idms_prox_read = idms.proxy_read().await;
 
-        let oidc_unverified =
-            OidcUnverified::from_str(&id_token).expect("Failed to parse id_token");
+        // Get an ident/uat for now.
 
-        let iat = ct.as_secs() as i64;
+        // == Setup the authorisation request
+        // We attempt pkce even though the rs is set to not support pkce.
+        let (code_verifier, _code_challenge) = create_code_verifier!("Whar Garble");
 
-        let oidc = oidc_unverified
-            .validate(&jws_validator, iat)
-            .expect("Failed to verify oidc");
+        // First, the user does not request pkce in their exchange.
+        let auth_req = AuthorisationRequest {
+            response_type: "code".to_string(),
+            client_id: "test_resource_server".to_string(),
+            state: "123".to_string(),
+            pkce_request: None,
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            scope: "openid".to_string(),
+            nonce: None,
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
 
-        // Are the id_token values what we expect?
-        assert!(
-            oidc.iss
-                == Url::parse("https://idm.example.com/oauth2/openid/test_resource_server")
-                    .unwrap()
-        );
-        assert!(oidc.sub == OidcSubject::U(UUID_ADMIN));
-        assert!(oidc.aud == "test_resource_server");
-        assert!(oidc.iat == iat);
-        assert!(oidc.nbf == Some(iat));
-        // Previously this was the auth session but it's now inline with the access token expiry.
-        assert!(oidc.exp == iat + (OAUTH2_ACCESS_TOKEN_EXPIRY as i64));
-        assert!(oidc.auth_time.is_none());
-        // Is nonce correctly passed through?
-        assert!(oidc.nonce == Some("abcdef".to_string()));
-        assert!(oidc.at_hash.is_none());
-        assert!(oidc.acr.is_none());
-        assert!(oidc.amr.is_none());
-        assert!(oidc.azp == Some("test_resource_server".to_string()));
-        assert!(oidc.jti.is_none());
-        assert!(oidc.s_claims.name == Some("System Administrator".to_string()));
-        assert!(oidc.s_claims.preferred_username == Some("admin@example.com".to_string()));
-        assert!(oidc.s_claims.scopes == vec!["openid".to_string(), "supplement".to_string()]);
-        assert!(oidc.claims.is_empty());
-        // Does our access token work with the userinfo endpoint?
-        // Do the id_token details line up to the userinfo?
-        let userinfo = idms_prox_read
-            .oauth2_openid_userinfo("test_resource_server", &access_token, ct)
-            .expect("failed to get userinfo");
+        let consent_request = idms_prox_read
+            .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+            .expect("Failed to perform oauth2 authorisation request.");
 
-        assert!(oidc.iss == userinfo.iss);
-        assert!(oidc.sub == userinfo.sub);
-        assert!(oidc.aud == userinfo.aud);
-        assert!(oidc.iat == userinfo.iat);
-        assert!(oidc.nbf == userinfo.nbf);
-        assert!(oidc.exp == userinfo.exp);
-        assert!(userinfo.auth_time.is_none());
-        assert!(userinfo.nonce.is_none());
-        assert!(userinfo.at_hash.is_none());
-        assert!(userinfo.acr.is_none());
-        assert!(oidc.amr == userinfo.amr);
-        assert!(oidc.azp == userinfo.azp);
-        assert!(userinfo.jti.is_none());
-        assert!(oidc.s_claims == userinfo.s_claims);
-        assert!(userinfo.claims.is_empty());
+        // Should be in the consent phase;
+        let consent_token =
+            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
+                consent_token
+            } else {
+                unreachable!();
+            };
+
+        // == Manually submit the consent token to the permit for the permit_success
+        drop(idms_prox_read);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        let permit_success = idms_prox_write
+            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
+            .expect("Failed to perform oauth2 permit");
+
+        // == Submit the token exchange code.
+        // This exchange failed because we submitted a verifier when the code exchange
+        // has NO code challenge present.
+        let token_req = AccessTokenRequest {
+            grant_type: GrantTypeReq::AuthorizationCode {
+                code: permit_success.code,
+                redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+                // Note the code verifier is set to "something else"
+                code_verifier,
+            },
+            client_id: Some("test_resource_server".to_string()),
+            client_secret: Some(secret),
+            client_assertion_type: None,
+            client_assertion: None,
+        };
+
+        // Assert the exchange fails.
+        assert!(matches!(
+            idms_prox_write.check_oauth2_token_exchange(None, &token_req, ct),
+            Err(Oauth2Error::InvalidRequest)
+        ));
+
+        assert!(idms_prox_write.commit().is_ok());
     }
 
     #[idm_test]
-    async fn test_idm_oauth2_openid_short_username(
+    // https://datatracker.ietf.org/doc/html/draft-ietf-oauth-security-topics#section-2.1
+    //
+    // If the origin configured is https, do not allow downgrading to http on redirect
+    async fn test_idm_oauth2_redir_http_downgrade(
         idms: &IdmServer,
         _idms_delayed: &mut IdmServerDelayed,
     ) {
-        // we run the same test as test_idm_oauth2_openid_extensions()
-        // but change the preferred_username setting on the RS
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        // Enable pkce is set to FALSE
         let (secret, uat, ident, _) =
-            setup_oauth2_resource_server(idms, ct, true, false, true).await;
-        let client_authz =
-            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
+            setup_oauth2_resource_server(idms, ct, false, false, false).await;
 
         let idms_prox_read = idms.proxy_read().await;
 
-        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        // Get an ident/uat for now.
+
+        // == Setup the authorisation request
+        // We attempt pkce even though the rs is set to not support pkce.
+        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+
+        // First, NOTE the lack of https on the redir uri.
+        let auth_req = AuthorisationRequest {
+            response_type: "code".to_string(),
+            client_id: "test_resource_server".to_string(),
+            state: "123".to_string(),
+            pkce_request: Some(PkceRequest {
+                code_challenge: Base64UrlSafeData(code_challenge.clone()),
+                code_challenge_method: CodeChallengeMethod::S256,
+            }),
+            redirect_uri: Url::parse("http://demo.example.com/oauth2/result").unwrap(),
+            scope: "openid".to_string(),
+            nonce: None,
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
+
+        assert!(
+            idms_prox_read
+                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidOrigin
+        );
 
+        // This does have https
         let consent_request = good_authorisation_request!(
             idms_prox_read,
             &ident,
@@ -3305,6 +6383,7 @@ mod tests {
             "openid".to_string()
         );
 
+        // Should be in the consent phase;
         let consent_token =
             if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
                 consent_token
@@ -3321,74 +6400,51 @@ mod tests {
             .expect("Failed to perform oauth2 permit");
 
         // == Submit the token exchange code.
-        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
-            code: permit_success.code,
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            // From the first step.
-            code_verifier,
-        }
-        .into();
-
-        let token_response = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .expect("Failed to perform oauth2 token exchange");
+        // NOTE the url is http again
+        let token_req = AccessTokenRequest {
+            grant_type: GrantTypeReq::AuthorizationCode {
+                code: permit_success.code,
+                redirect_uri: Url::parse("http://demo.example.com/oauth2/result").unwrap(),
+                // Note the code verifier is set to "something else"
+                code_verifier,
+            },
+            client_id: Some("test_resource_server".to_string()),
+            client_secret: Some(secret),
+            client_assertion_type: None,
+            client_assertion: None,
+        };
 
-        let id_token = token_response.id_token.expect("No id_token in response!");
-        let access_token = token_response.access_token;
+        // Assert the exchange fails.
+        assert!(matches!(
+            idms_prox_write.check_oauth2_token_exchange(None, &token_req, ct),
+            Err(Oauth2Error::InvalidOrigin)
+        ));
 
         assert!(idms_prox_write.commit().is_ok());
-        let mut idms_prox_read = idms.proxy_read().await;
-
-        let mut jwkset = idms_prox_read
-            .oauth2_openid_publickey("test_resource_server")
-            .expect("Failed to get public key");
-        let public_jwk = jwkset.keys.pop().expect("no such jwk");
-
-        let jws_validator = JwsValidator::try_from(&public_jwk).expect("failed to build validator");
-
-        let oidc_unverified =
-            OidcUnverified::from_str(&id_token).expect("Failed to parse id_token");
-
-        let iat = ct.as_secs() as i64;
-
-        let oidc = oidc_unverified
-            .validate(&jws_validator, iat)
-            .expect("Failed to verify oidc");
-
-        // Do we have the short username in the token claims?
-        assert!(oidc.s_claims.preferred_username == Some("admin".to_string()));
-        // Do the id_token details line up to the userinfo?
-        let userinfo = idms_prox_read
-            .oauth2_openid_userinfo("test_resource_server", &access_token, ct)
-            .expect("failed to get userinfo");
-
-        assert!(oidc.s_claims == userinfo.s_claims);
     }
 
-    #[idm_test]
-    async fn test_idm_oauth2_openid_group_claims(
+    async fn setup_refresh_token(
         idms: &IdmServer,
         _idms_delayed: &mut IdmServerDelayed,
-    ) {
-        // we run the same test as test_idm_oauth2_openid_extensions()
-        // but change the preferred_username setting on the RS
-        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        ct: Duration,
+    ) -> (AccessTokenResponse, Option<String>) {
+        // First, setup to get a token.
         let (secret, uat, ident, _) =
-            setup_oauth2_resource_server(idms, ct, true, false, true).await;
+            setup_oauth2_resource_server(idms, ct, true, false, false).await;
         let client_authz =
             Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
         let idms_prox_read = idms.proxy_read().await;
 
+        // == Setup the authorisation request
         let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
-
         let consent_request = good_authorisation_request!(
             idms_prox_read,
             &ident,
             &uat,
             ct,
             code_challenge,
-            "openid groups".to_string()
+            "openid".to_string()
         );
 
         let consent_token =
@@ -3406,670 +6462,871 @@ mod tests {
             .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
             .expect("Failed to perform oauth2 permit");
 
-        // == Submit the token exchange code.
         let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
             code: permit_success.code,
             redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            // From the first step.
             code_verifier,
         }
         .into();
-
-        let token_response = idms_prox_write
+        let access_token_response_1 = idms_prox_write
             .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .expect("Failed to perform oauth2 token exchange");
+            .expect("Unable to exchange for oauth2 token");
 
-        let id_token = token_response.id_token.expect("No id_token in response!");
-        let access_token = token_response.access_token;
+        assert!(idms_prox_write.commit().is_ok());
+
+        trace!(?access_token_response_1);
+
+        (access_token_response_1, client_authz)
+    }
+
+    #[idm_test]
+    async fn test_idm_oauth2_refresh_token_basic(
+        idms: &IdmServer,
+        idms_delayed: &mut IdmServerDelayed,
+    ) {
+        // First, setup to get a token.
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+
+        let (access_token_response_1, client_authz) =
+            setup_refresh_token(idms, idms_delayed, ct).await;
+
+        // ============================================
+        // test basic refresh while access still valid.
+
+        let ct = Duration::from_secs(TEST_CURRENT_TIME + 10);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+
+        let refresh_token = access_token_response_1
+            .refresh_token
+            .as_ref()
+            .expect("no refresh token was issued")
+            .clone();
+
+        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
+            refresh_token,
+            scope: None,
+        }
+        .into();
+        let access_token_response_2 = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Unable to exchange for oauth2 token");
 
         assert!(idms_prox_write.commit().is_ok());
-        let mut idms_prox_read = idms.proxy_read().await;
 
-        let mut jwkset = idms_prox_read
-            .oauth2_openid_publickey("test_resource_server")
-            .expect("Failed to get public key");
-        let public_jwk = jwkset.keys.pop().expect("no such jwk");
+        trace!(?access_token_response_2);
 
-        let jws_validator = JwsValidator::try_from(&public_jwk).expect("failed to build validator");
+        assert!(access_token_response_1.access_token != access_token_response_2.access_token);
+        assert!(access_token_response_1.refresh_token != access_token_response_2.refresh_token);
+        assert!(access_token_response_1.id_token != access_token_response_2.id_token);
 
-        let oidc_unverified =
-            OidcUnverified::from_str(&id_token).expect("Failed to parse id_token");
+        // ============================================
+        // test basic refresh after access exp
+        let ct =
+            Duration::from_secs(TEST_CURRENT_TIME + 20 + access_token_response_2.expires_in as u64);
 
-        let iat = ct.as_secs() as i64;
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let oidc = oidc_unverified
-            .validate(&jws_validator, iat)
-            .expect("Failed to verify oidc");
+        let refresh_token = access_token_response_2
+            .refresh_token
+            .as_ref()
+            .expect("no refresh token was issued")
+            .clone();
 
-        // does our id_token contain the expected groups?
-        assert!(oidc.claims.contains_key(&"groups".to_string()));
+        // get the refresh token expiry now before we use it.
+        let reflected_token = idms_prox_write
+            .reflect_oauth2_token(client_authz.as_ref().unwrap(), &refresh_token)
+            .expect("Failed to access internals of the refresh token");
 
-        assert!(oidc
-            .claims
-            .get(&"groups".to_string())
-            .expect("unable to find key")
-            .as_array()
-            .unwrap()
-            .contains(&serde_json::json!(STR_UUID_IDM_ALL_ACCOUNTS)));
+        let refresh_exp = match reflected_token {
+            Oauth2TokenType::Refresh { expiry, .. } => expiry.unix_timestamp(),
+            Oauth2TokenType::Access { .. } | Oauth2TokenType::ClientAccess { .. } => {
+                unreachable!()
+            }
+        };
 
-        // Do the id_token details line up to the userinfo?
-        let userinfo = idms_prox_read
-            .oauth2_openid_userinfo("test_resource_server", &access_token, ct)
-            .expect("failed to get userinfo");
+        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
+            refresh_token,
+            scope: None,
+        }
+        .into();
+        let access_token_response_3 = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Unable to exchange for oauth2 token");
 
-        // does the userinfo endpoint provide the same groups?
-        assert!(
-            oidc.claims.get(&"groups".to_string()) == userinfo.claims.get(&"groups".to_string())
-        );
-    }
+        assert!(idms_prox_write.commit().is_ok());
 
-    //  Check insecure pkce behaviour.
-    #[idm_test]
-    async fn test_idm_oauth2_insecure_pkce(idms: &IdmServer, _idms_delayed: &mut IdmServerDelayed) {
-        let ct = Duration::from_secs(TEST_CURRENT_TIME);
-        let (_secret, uat, ident, _) =
-            setup_oauth2_resource_server(idms, ct, false, false, false).await;
+        trace!(?access_token_response_3);
 
-        let idms_prox_read = idms.proxy_read().await;
+        assert!(access_token_response_3.access_token != access_token_response_2.access_token);
+        assert!(access_token_response_3.refresh_token != access_token_response_2.refresh_token);
+        assert!(access_token_response_3.id_token != access_token_response_2.id_token);
 
-        // == Setup the authorisation request
-        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        // refresh after refresh has expired.
+        // Refresh tokens have a max time limit - the session time limit still bounds it though, but
+        // so does the refresh token limit. We check both, but the refresh time is checked first so
+        // we can guarantee this in this test.
 
-        // Even in disable pkce mode, we will allow pkce
-        let _consent_request = good_authorisation_request!(
-            idms_prox_read,
-            &ident,
-            &uat,
-            ct,
-            code_challenge,
-            "openid".to_string()
+        let ct = Duration::from_secs(
+            TEST_CURRENT_TIME + refresh_exp as u64 + access_token_response_3.expires_in as u64,
         );
 
-        // Check we allow none.
-        let auth_req = AuthorisationRequest {
-            response_type: "code".to_string(),
-            client_id: "test_resource_server".to_string(),
-            state: "123".to_string(),
-            pkce_request: None,
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            scope: "openid".to_string(),
-            nonce: Some("abcdef".to_string()),
-            oidc_ext: Default::default(),
-            unknown_keys: Default::default(),
-        };
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        idms_prox_read
-            .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
-            .expect("Oauth2 authorisation failed");
+        let refresh_token = access_token_response_3
+            .refresh_token
+            .as_ref()
+            .expect("no refresh token was issued")
+            .clone();
+
+        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
+            refresh_token,
+            scope: None,
+        }
+        .into();
+        let access_token_response_4 = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .unwrap_err();
+
+        assert!(access_token_response_4 == Oauth2Error::InvalidToken);
+
+        assert!(idms_prox_write.commit().is_ok());
     }
 
+    // refresh when oauth2 parent session exp / missing.
     #[idm_test]
-    async fn test_idm_oauth2_openid_legacy_crypto(
+    async fn test_idm_oauth2_refresh_token_oauth2_session_expired(
         idms: &IdmServer,
-        _idms_delayed: &mut IdmServerDelayed,
+        idms_delayed: &mut IdmServerDelayed,
     ) {
+        // First, setup to get a token.
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
-        let (secret, uat, ident, _) =
-            setup_oauth2_resource_server(idms, ct, false, true, false).await;
-        let idms_prox_read = idms.proxy_read().await;
-        // The public key url should offer an rs key
-        // discovery should offer RS256
-        let discovery = idms_prox_read
-            .oauth2_openid_discovery("test_resource_server")
-            .expect("Failed to get discovery");
 
-        let mut jwkset = idms_prox_read
-            .oauth2_openid_publickey("test_resource_server")
-            .expect("Failed to get public key");
+        let (access_token_response_1, client_authz) =
+            setup_refresh_token(idms, idms_delayed, ct).await;
 
-        let jwk = jwkset.keys.pop().expect("no such jwk");
-        let public_jwk = jwk.clone();
+        // ============================================
+        // Revoke the oauth2 session
 
-        match jwk {
-            Jwk::RSA { alg, use_, kid, .. } => {
-                match (
-                    alg.unwrap(),
-                    &discovery.id_token_signing_alg_values_supported[0],
-                ) {
-                    (JwaAlg::RS256, IdTokenSignAlg::RS256) => {}
-                    _ => panic!(),
-                };
-                assert!(use_.unwrap() == JwkUse::Sig);
-                assert!(kid.is_some());
-            }
-            _ => panic!(),
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let revoke_request = TokenRevokeRequest {
+            token: access_token_response_1.access_token.clone(),
+            token_type_hint: None,
         };
+        assert!(idms_prox_write
+            .oauth2_token_revoke(client_authz.as_deref().unwrap(), &revoke_request, ct,)
+            .is_ok());
+        assert!(idms_prox_write.commit().is_ok());
 
-        // Check that the id_token is signed with the correct key.
-        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        // ============================================
+        // then attempt a refresh.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let consent_request = good_authorisation_request!(
-            idms_prox_read,
-            &ident,
-            &uat,
-            ct,
-            code_challenge,
-            "openid".to_string()
-        );
+        let refresh_token = access_token_response_1
+            .refresh_token
+            .as_ref()
+            .expect("no refresh token was issued")
+            .clone();
 
-        let consent_token =
-            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
-                consent_token
-            } else {
-                unreachable!();
-            };
+        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
+            refresh_token,
+            scope: None,
+        }
+        .into();
+        let access_token_response_2 = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            // Should be unable to exchange.
+            .unwrap_err();
 
-        // == Manually submit the consent token to the permit for the permit_success
-        drop(idms_prox_read);
-        let mut idms_prox_write = idms.proxy_write(ct).await;
+        assert!(access_token_response_2 == Oauth2Error::InvalidToken);
 
-        let permit_success = idms_prox_write
-            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
-            .expect("Failed to perform oauth2 permit");
+        assert!(idms_prox_write.commit().is_ok());
+    }
 
-        // == Submit the token exchange code.
-        let token_req = AccessTokenRequest {
-            grant_type: GrantTypeReq::AuthorizationCode {
-                code: permit_success.code,
-                redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-                // From the first step.
-                code_verifier,
-            },
-            client_id: Some("test_resource_server".to_string()),
-            client_secret: Some(secret),
-        };
+    // refresh with wrong client id/authz
+    #[idm_test]
+    async fn test_idm_oauth2_refresh_token_invalid_client_authz(
+        idms: &IdmServer,
+        idms_delayed: &mut IdmServerDelayed,
+    ) {
+        // First, setup to get a token.
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
 
-        let token_response = idms_prox_write
-            .check_oauth2_token_exchange(None, &token_req, ct)
-            .expect("Failed to perform oauth2 token exchange");
+        let (access_token_response_1, mut client_authz) =
+            setup_refresh_token(idms, idms_delayed, ct).await;
 
-        // 🎉 We got a token!
-        assert!(token_response.token_type == "bearer");
-        let id_token = token_response.id_token.expect("No id_token in response!");
+        if let Some(s) = client_authz.as_mut() {
+            s.push_str("invalid")
+        }
 
-        let jws_validator = JwsValidator::try_from(&public_jwk).expect("failed to build validator");
+        // ============================================
+        // Refresh with invalid client authz
 
-        let oidc_unverified =
-            OidcUnverified::from_str(&id_token).expect("Failed to parse id_token");
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let iat = ct.as_secs() as i64;
+        let refresh_token = access_token_response_1
+            .refresh_token
+            .as_ref()
+            .expect("no refresh token was issued")
+            .clone();
 
-        let oidc = oidc_unverified
-            .validate(&jws_validator, iat)
-            .expect("Failed to verify oidc");
+        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
+            refresh_token,
+            scope: None,
+        }
+        .into();
+        let access_token_response_2 = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .unwrap_err();
 
-        assert!(oidc.sub == OidcSubject::U(UUID_ADMIN));
+        assert!(access_token_response_2 == Oauth2Error::AuthenticationRequired);
 
         assert!(idms_prox_write.commit().is_ok());
     }
 
+    // Incorrect scopes re-requested
     #[idm_test]
-    async fn test_idm_oauth2_consent_granted_and_changed_workflow(
+    async fn test_idm_oauth2_refresh_token_inconsistent_scopes(
         idms: &IdmServer,
-        _idms_delayed: &mut IdmServerDelayed,
+        idms_delayed: &mut IdmServerDelayed,
     ) {
+        // First, setup to get a token.
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
-        let (_secret, uat, ident, _) =
-            setup_oauth2_resource_server(idms, ct, true, false, false).await;
-
-        let idms_prox_read = idms.proxy_read().await;
 
-        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
-        let consent_request = good_authorisation_request!(
-            idms_prox_read,
-            &ident,
-            &uat,
-            ct,
-            code_challenge,
-            "openid".to_string()
-        );
+        let (access_token_response_1, client_authz) =
+            setup_refresh_token(idms, idms_delayed, ct).await;
 
-        // Should be in the consent phase;
-        let consent_token =
-            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
-                consent_token
-            } else {
-                unreachable!();
-            };
+        // ============================================
+        // Refresh with different scopes
 
-        // == Manually submit the consent token to the permit for the permit_success
-        drop(idms_prox_read);
         let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let _permit_success = idms_prox_write
-            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
-            .expect("Failed to perform oauth2 permit");
-
-        assert!(idms_prox_write.commit().is_ok());
+        let refresh_token = access_token_response_1
+            .refresh_token
+            .as_ref()
+            .expect("no refresh token was issued")
+            .clone();
 
-        // == Now try the authorise again, should be in the permitted state.
-        let mut idms_prox_read = idms.proxy_read().await;
+        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
+            refresh_token,
+            scope: Some(btreeset!["invalid_scope".to_string()]),
+        }
+        .into();
+        let access_token_response_2 = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .unwrap_err();
 
-        // We need to reload our identity
-        let ident = idms_prox_read
-            .process_uat_to_identity(&uat, ct)
-            .expect("Unable to process uat");
+        assert!(access_token_response_2 == Oauth2Error::InvalidScope);
 
-        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
-        let consent_request = good_authorisation_request!(
-            idms_prox_read,
-            &ident,
-            &uat,
-            ct,
-            code_challenge,
-            "openid".to_string()
-        );
+        assert!(idms_prox_write.commit().is_ok());
+    }
 
-        // Should be in the consent phase;
-        let _permit_success = if let AuthoriseResponse::Permitted(permit_success) = consent_request
-        {
-            permit_success
-        } else {
-            unreachable!();
-        };
+    // Test that re-use of a refresh token is denied + terminates the session.
+    //
+    // https://www.ietf.org/archive/id/draft-ietf-oauth-security-topics-18.html#refresh_token_protection
+    #[idm_test]
+    async fn test_idm_oauth2_refresh_token_reuse_invalidates_session(
+        idms: &IdmServer,
+        idms_delayed: &mut IdmServerDelayed,
+    ) {
+        // First, setup to get a token.
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
 
-        drop(idms_prox_read);
+        let (access_token_response_1, client_authz) =
+            setup_refresh_token(idms, idms_delayed, ct).await;
 
-        // Great! Now change the scopes on the oauth2 instance, this revokes the permit.
+        // ============================================
+        // Use the refresh token once
+        let ct = Duration::from_secs(TEST_CURRENT_TIME + 1);
         let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let me_extend_scopes = unsafe {
-            ModifyEvent::new_internal_invalid(
-                filter!(f_eq(
-                    "oauth2_rs_name",
-                    PartialValue::new_iname("test_resource_server")
-                )),
-                ModifyList::new_list(vec![Modify::Present(
-                    AttrString::from("oauth2_rs_scope_map"),
-                    Value::new_oauthscopemap(
-                        UUID_IDM_ALL_ACCOUNTS,
-                        btreeset!["email".to_string(), "openid".to_string()],
-                    )
-                    .expect("invalid oauthscope"),
-                )]),
-            )
-        };
+        let refresh_token = access_token_response_1
+            .refresh_token
+            .as_ref()
+            .expect("no refresh token was issued")
+            .clone();
+
+        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
+            refresh_token,
+            scope: None,
+        }
+        .into();
+
+        let _access_token_response_2 = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Unable to exchange for oauth2 token");
 
-        assert!(idms_prox_write.qs_write.modify(&me_extend_scopes).is_ok());
         assert!(idms_prox_write.commit().is_ok());
 
-        // And do the workflow once more to see if we need to consent again.
+        // Now use it again. - this will cause an error and the session to be terminated.
+        let ct = Duration::from_secs(TEST_CURRENT_TIME + 2);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let mut idms_prox_read = idms.proxy_read().await;
+        let refresh_token = access_token_response_1
+            .refresh_token
+            .as_ref()
+            .expect("no refresh token was issued")
+            .clone();
 
-        // We need to reload our identity
-        let ident = idms_prox_read
-            .process_uat_to_identity(&uat, ct)
-            .expect("Unable to process uat");
+        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
+            refresh_token,
+            scope: None,
+        }
+        .into();
 
-        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        let access_token_response_3 = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .unwrap_err();
 
-        let auth_req = AuthorisationRequest {
-            response_type: "code".to_string(),
-            client_id: "test_resource_server".to_string(),
-            state: "123".to_string(),
-            pkce_request: Some(PkceRequest {
-                code_challenge: Base64UrlSafeData(code_challenge),
-                code_challenge_method: CodeChallengeMethod::S256,
-            }),
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            scope: "openid email".to_string(),
-            nonce: Some("abcdef".to_string()),
-            oidc_ext: Default::default(),
-            unknown_keys: Default::default(),
-        };
+        assert!(access_token_response_3 == Oauth2Error::InvalidGrant);
 
-        let consent_request = idms_prox_read
-            .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
-            .expect("Oauth2 authorisation failed");
+        let entry = idms_prox_write
+            .qs_write
+            .internal_search_uuid(UUID_ADMIN)
+            .expect("failed");
+        let valid = entry
+            .get_ava_as_oauth2session_map("oauth2_session")
+            .map(|map| {
+                trace!(?map);
+                map.is_empty()
+            })
+            // If there is no map, it must be empty
+            .unwrap_or(true);
+        assert!(valid);
 
-        // Should be in the consent phase;
-        let _consent_token =
-            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
-                consent_token
-            } else {
-                unreachable!();
-            };
+        assert!(idms_prox_write.commit().is_ok());
+    }
 
-        drop(idms_prox_read);
+    // Test session divergence. This means that we have to:
+    // access + refresh 1
+    // use refresh 1 -> access + refresh 2 // don't commit this txn.
+    // use refresh 2 -> access + refresh 3
+    //    check the session state.
 
-        // Success! We had to consent again due to the change :)
+    #[idm_test]
+    async fn test_idm_oauth2_refresh_token_divergence(
+        idms: &IdmServer,
+        idms_delayed: &mut IdmServerDelayed,
+    ) {
+        // First, setup to get a token.
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
 
-        // Now change the supplemental scopes on the oauth2 instance, this revokes the permit.
+        let (access_token_response_1, client_authz) =
+            setup_refresh_token(idms, idms_delayed, ct).await;
+
+        // ============================================
+        // Use the refresh token once
+        let ct = Duration::from_secs(TEST_CURRENT_TIME + 1);
         let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let me_extend_scopes = unsafe {
-            ModifyEvent::new_internal_invalid(
-                filter!(f_eq(
-                    "oauth2_rs_name",
-                    PartialValue::new_iname("test_resource_server")
-                )),
-                ModifyList::new_list(vec![Modify::Present(
-                    AttrString::from("oauth2_rs_sup_scope_map"),
-                    Value::new_oauthscopemap(
-                        UUID_IDM_ALL_ACCOUNTS,
-                        btreeset!["newscope".to_string()],
-                    )
-                    .expect("invalid oauthscope"),
-                )]),
-            )
-        };
+        let refresh_token = access_token_response_1
+            .refresh_token
+            .as_ref()
+            .expect("no refresh token was issued")
+            .clone();
 
-        assert!(idms_prox_write.qs_write.modify(&me_extend_scopes).is_ok());
-        assert!(idms_prox_write.commit().is_ok());
+        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
+            refresh_token,
+            scope: None,
+        }
+        .into();
 
-        // And do the workflow once more to see if we need to consent again.
+        let access_token_response_2 = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Unable to exchange for oauth2 token");
 
-        let mut idms_prox_read = idms.proxy_read().await;
+        // DO NOT COMMIT HERE - this is what forces the session issued_at
+        // time to stay at the original time!
+        drop(idms_prox_write);
 
-        // We need to reload our identity
-        let ident = idms_prox_read
-            .process_uat_to_identity(&uat, ct)
-            .expect("Unable to process uat");
+        // ============================================
+        let ct = Duration::from_secs(TEST_CURRENT_TIME + 2);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        let refresh_token = access_token_response_2
+            .refresh_token
+            .as_ref()
+            .expect("no refresh token was issued")
+            .clone();
 
-        let auth_req = AuthorisationRequest {
-            response_type: "code".to_string(),
-            client_id: "test_resource_server".to_string(),
-            state: "123".to_string(),
-            pkce_request: Some(PkceRequest {
-                code_challenge: Base64UrlSafeData(code_challenge),
-                code_challenge_method: CodeChallengeMethod::S256,
-            }),
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            // Note the scope isn't requested here!
-            scope: "openid email".to_string(),
-            nonce: Some("abcdef".to_string()),
-            oidc_ext: Default::default(),
-            unknown_keys: Default::default(),
-        };
+        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
+            refresh_token,
+            scope: None,
+        }
+        .into();
 
-        let consent_request = idms_prox_read
-            .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
-            .expect("Oauth2 authorisation failed");
+        let _access_token_response_3 = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Unable to exchange for oauth2 token");
 
-        // Should be present in the consent phase however!
-        let _consent_token = if let AuthoriseResponse::ConsentRequested {
-            consent_token,
-            scopes,
-            ..
-        } = consent_request
-        {
-            assert!(scopes.contains(&"newscope".to_string()));
-            consent_token
-        } else {
-            unreachable!();
-        };
+        assert!(idms_prox_write.commit().is_ok());
+
+        // Success!
     }
 
     #[idm_test]
-    async fn test_idm_oauth2_consent_granted_refint_cleanup_on_delete(
+    async fn test_idm_oauth2_client_credentials(
         idms: &IdmServer,
         _idms_delayed: &mut IdmServerDelayed,
     ) {
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
-        let (_secret, uat, ident, o2rs_uuid) =
+        let (secret, _uat, _ident, rs_uuid) =
             setup_oauth2_resource_server(idms, ct, true, false, false).await;
+        let client_authz =
+            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
-        // Assert there are no consent maps yet.
-        assert!(ident.get_oauth2_consent_scopes(o2rs_uuid).is_none());
-
-        let idms_prox_read = idms.proxy_read().await;
+        let token_req: AccessTokenRequest = GrantTypeReq::ClientCredentials { scope: None }.into();
 
-        let (_code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
-        let consent_request = good_authorisation_request!(
-            idms_prox_read,
-            &ident,
-            &uat,
-            ct,
-            code_challenge,
-            "openid".to_string()
+        // The resource server has no linked service account yet.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::UnauthorizedClient
         );
 
-        // Should be in the consent phase;
-        let consent_token =
-            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
-                consent_token
-            } else {
-                unreachable!();
-            };
-
-        // == Manually submit the consent token to the permit for the permit_success
-        drop(idms_prox_read);
-        let mut idms_prox_write = idms.proxy_write(ct).await;
+        // Wrong secret is rejected before we get that far.
+        let bad_authz = general_purpose::STANDARD.encode("test_resource_server:12345");
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(Some(bad_authz.as_str()), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AuthenticationRequired
+        );
 
-        let _permit_success = idms_prox_write
-            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
-            .expect("Failed to perform oauth2 permit");
+        // Link a service account that is not a member of any scope mapped group.
+        let sa_uuid = Uuid::new_v4();
+        let e_sa: Entry<EntryInit, EntryNew> = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("account")),
+            ("class", Value::new_class("service_account")),
+            ("uuid", Value::Uuid(sa_uuid)),
+            ("name", Value::new_iname("test_client_sa")),
+            ("displayname", Value::new_utf8s("test_client_sa"))
+        );
+        let ce = CreateEvent::new_internal(vec![e_sa]);
+        assert!(idms_prox_write.qs_write.create(&ce).is_ok());
 
-        let ident = idms_prox_write
-            .process_uat_to_identity(&uat, ct)
-            .expect("Unable to process uat");
+        let me_link = unsafe {
+            ModifyEvent::new_internal_invalid(
+                filter!(f_eq("uuid", PartialValue::Uuid(rs_uuid))),
+                ModifyList::new_purge_and_set("oauth2_rs_service_account", Value::Refer(sa_uuid)),
+            )
+        };
+        assert!(idms_prox_write.qs_write.modify(&me_link).is_ok());
+        assert!(idms_prox_write.commit().is_ok());
 
-        // Assert that the ident now has the consents.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
         assert!(
-            ident.get_oauth2_consent_scopes(o2rs_uuid)
-                == Some(&btreeset!["openid".to_string(), "supplement".to_string()])
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::UnauthorizedClient
         );
 
-        // Now trigger the delete of the RS
-        let de = unsafe {
-            DeleteEvent::new_internal_invalid(filter!(f_eq(
-                "oauth2_rs_name",
-                PartialValue::new_iname("test_resource_server")
-            )))
+        // Put the service account in a group, and map scopes to that group.
+        let group_uuid = Uuid::new_v4();
+        let e_group: Entry<EntryInit, EntryNew> = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("group")),
+            ("name", Value::new_iname("test_rs_service_group")),
+            ("uuid", Value::Uuid(group_uuid)),
+            ("member", Value::Refer(sa_uuid))
+        );
+        let ce = CreateEvent::new_internal(vec![e_group]);
+        assert!(idms_prox_write.qs_write.create(&ce).is_ok());
+
+        let me_scopes = unsafe {
+            ModifyEvent::new_internal_invalid(
+                filter!(f_eq("uuid", PartialValue::Uuid(rs_uuid))),
+                ModifyList::new_list(vec![Modify::Present(
+                    AttrString::from("oauth2_rs_scope_map"),
+                    Value::new_oauthscopemap(
+                        group_uuid,
+                        btreeset!["read".to_string(), "write".to_string()],
+                    )
+                    .expect("invalid oauthscope"),
+                )]),
+            )
         };
+        assert!(idms_prox_write.qs_write.modify(&me_scopes).is_ok());
+        assert!(idms_prox_write.commit().is_ok());
 
-        assert!(idms_prox_write.qs_write.delete(&de).is_ok());
-        // Assert the consent maps are gone.
-        let ident = idms_prox_write
-            .process_uat_to_identity(&uat, ct)
-            .expect("Unable to process uat");
-        assert!(ident.get_oauth2_consent_scopes(o2rs_uuid).is_none());
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        assert!(idms_prox_write.commit().is_ok());
-    }
+        // No scopes requested grants everything that is mapped.
+        let oauth2_token_all = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Unable to exchange for oauth2 token");
+        assert!(oauth2_token_all.scope.as_deref() == Some("read write"));
+        assert!(oauth2_token_all.refresh_token.is_none());
+        assert!(oauth2_token_all.id_token.is_none());
 
-    #[idm_test]
-    // https://datatracker.ietf.org/doc/html/draft-ietf-oauth-security-topics#section-4.8
-    //
-    // It was reported we were vulnerable to this attack, but that isn't the case. First
-    // this attack relies on stripping the *code_challenge* from the internals of the returned
-    // code exchange token. This isn't possible due to our use of encryption of the code exchange
-    // token. If that code challenge *could* be removed, then the attacker could use the code exchange
-    // with no verifier or an incorrect verifier.
-    //
-    // Due to the logic in our server, if a code exchange contains a code challenge we always enforce
-    // it is correctly used!
-    //
-    // This left a single odd case where if a client did an authorisation request without a pkce
-    // verifier, but then a verifier was submitted during the code exchange, that the server would
-    // *ignore* the verifier parameter. In this case, no stripping of the code challenge was done,
-    // and the client could have simply also submitted *no* verifier anyway. It could be that
-    // an attacker could gain a code exchange with no code challenge and then force a victim to
-    // exchange that code exchange with out the verifier, but I'm not sure what damage that would
-    // lead to? Regardless, we test for and close off that possible hole in this test.
-    //
-    async fn test_idm_oauth2_1076_pkce_downgrade(
-        idms: &IdmServer,
-        _idms_delayed: &mut IdmServerDelayed,
-    ) {
-        let ct = Duration::from_secs(TEST_CURRENT_TIME);
-        // Enable pkce is set to FALSE
-        let (secret, uat, ident, _) =
-            setup_oauth2_resource_server(idms, ct, false, false, false).await;
+        // A subset can be requested.
+        let token_req: AccessTokenRequest = GrantTypeReq::ClientCredentials {
+            scope: Some("read".to_string()),
+        }
+        .into();
+        let oauth2_token = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Unable to exchange for oauth2 token");
+        assert!(oauth2_token.scope.as_deref() == Some("read"));
 
-        let idms_prox_read = idms.proxy_read().await;
+        // Scopes that are mapped to other groups are denied.
+        let token_req: AccessTokenRequest = GrantTypeReq::ClientCredentials {
+            scope: Some("read groups".to_string()),
+        }
+        .into();
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidScope
+        );
 
-        // Get an ident/uat for now.
+        assert!(idms_prox_write.commit().is_ok());
 
-        // == Setup the authorisation request
-        // We attempt pkce even though the rs is set to not support pkce.
-        let (code_verifier, _code_challenge) = create_code_verifier!("Whar Garble");
+        // Each grant recorded a session on the service account.
+        let mut idms_prox_read = idms.proxy_read().await;
+        let sa_entry = idms_prox_read
+            .qs_read
+            .internal_search_uuid(sa_uuid)
+            .expect("failed to get service account");
+        let sessions = sa_entry
+            .get_ava_as_oauth2session_map("oauth2_session")
+            .expect("no oauth2 sessions");
+        assert!(sessions.len() == 2);
+        assert!(sessions.values().all(|s| s.rs_uuid == rs_uuid));
 
-        // First, the user does not request pkce in their exchange.
-        let auth_req = AuthorisationRequest {
-            response_type: "code".to_string(),
-            client_id: "test_resource_server".to_string(),
-            state: "123".to_string(),
-            pkce_request: None,
-            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-            scope: "openid".to_string(),
-            nonce: None,
-            oidc_ext: Default::default(),
-            unknown_keys: Default::default(),
+        // The token introspects as the service account.
+        let intr_request = AccessTokenIntrospectRequest {
+            token: oauth2_token.access_token.clone(),
+            token_type_hint: None,
         };
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
+            .expect("Failed to inspect token");
 
-        let consent_request = idms_prox_read
-            .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
-            .expect("Failed to perform oauth2 authorisation request.");
+        assert!(intr_response.active);
+        assert!(intr_response.scope.as_deref() == Some("read"));
+        assert!(intr_response.client_id.as_deref() == Some("test_resource_server"));
+        assert!(intr_response.username.as_deref() == Some("test_client_sa@example.com"));
+        assert!(intr_response.sub == Some(sa_uuid.to_string()));
 
-        // Should be in the consent phase;
-        let consent_token =
-            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
-                consent_token
-            } else {
-                unreachable!();
-            };
+        // There is no user to provide info about.
+        assert!(
+            idms_prox_read
+                .oauth2_openid_userinfo("test_resource_server", &oauth2_token.access_token, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidToken
+        );
 
-        // == Manually submit the consent token to the permit for the permit_success
+        // And it expires as usual.
+        let ct_expired = ct + Duration::from_secs(TOKEN_EXPIRE + 1);
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(
+                client_authz.as_deref().unwrap(),
+                &intr_request,
+                ct_expired,
+            )
+            .expect("Failed to inspect token");
+        assert!(!intr_response.active);
         drop(idms_prox_read);
+
+        // The token can be revoked, which only affects that session.
         let mut idms_prox_write = idms.proxy_write(ct).await;
+        let revoke_request = TokenRevokeRequest {
+            token: oauth2_token.access_token.clone(),
+            token_type_hint: None,
+        };
+        assert!(idms_prox_write
+            .oauth2_token_revoke(client_authz.as_deref().unwrap(), &revoke_request, ct)
+            .is_ok());
+        assert!(idms_prox_write.commit().is_ok());
 
-        let permit_success = idms_prox_write
-            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
-            .expect("Failed to perform oauth2 permit");
+        let ct_grace = ct + GRACE_WINDOW;
+        let mut idms_prox_read = idms.proxy_read().await;
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(
+                client_authz.as_deref().unwrap(),
+                &intr_request,
+                ct_grace,
+            )
+            .expect("Failed to inspect token");
+        assert!(!intr_response.active);
 
-        // == Submit the token exchange code.
-        // This exchange failed because we submitted a verifier when the code exchange
-        // has NO code challenge present.
-        let token_req = AccessTokenRequest {
-            grant_type: GrantTypeReq::AuthorizationCode {
-                code: permit_success.code,
-                redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
-                // Note the code verifier is set to "something else"
-                code_verifier,
-            },
-            client_id: Some("test_resource_server".to_string()),
-            client_secret: Some(secret),
+        let intr_request = AccessTokenIntrospectRequest {
+            token: oauth2_token_all.access_token.clone(),
+            token_type_hint: None,
         };
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(
+                client_authz.as_deref().unwrap(),
+                &intr_request,
+                ct_grace,
+            )
+            .expect("Failed to inspect token");
+        assert!(intr_response.active);
+        drop(idms_prox_read);
 
-        // Assert the exchange fails.
-        assert!(matches!(
-            idms_prox_write.check_oauth2_token_exchange(None, &token_req, ct),
-            Err(Oauth2Error::InvalidRequest)
-        ));
-
+        // Expiring the service account invalidates its tokens.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let me_expire = unsafe {
+            ModifyEvent::new_internal_invalid(
+                filter!(f_eq("uuid", PartialValue::Uuid(sa_uuid))),
+                ModifyList::new_purge_and_set(
+                    "account_expire",
+                    Value::new_datetime_epoch(ct - Duration::from_secs(1)),
+                ),
+            )
+        };
+        assert!(idms_prox_write.qs_write.modify(&me_expire).is_ok());
         assert!(idms_prox_write.commit().is_ok());
+
+        let mut idms_prox_read = idms.proxy_read().await;
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
+            .expect("Failed to inspect token");
+        assert!(!intr_response.active);
+        drop(idms_prox_read);
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let token_req: AccessTokenRequest = GrantTypeReq::ClientCredentials { scope: None }.into();
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::UnauthorizedClient
+        );
     }
 
     #[idm_test]
-    // https://datatracker.ietf.org/doc/html/draft-ietf-oauth-security-topics#section-2.1
-    //
-    // If the origin configured is https, do not allow downgrading to http on redirect
-    async fn test_idm_oauth2_redir_http_downgrade(
+    async fn test_idm_oauth2_device_authorisation(
         idms: &IdmServer,
         _idms_delayed: &mut IdmServerDelayed,
     ) {
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
-        // Enable pkce is set to FALSE
         let (secret, uat, ident, _) =
-            setup_oauth2_resource_server(idms, ct, false, false, false).await;
+            setup_oauth2_resource_server(idms, ct, true, false, false).await;
+        let client_authz =
+            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
-        let idms_prox_read = idms.proxy_read().await;
+        let device_req = DeviceAuthorisationRequest {
+            scope: "openid".to_string(),
+            client_id: None,
+            client_secret: None,
+        };
 
-        // Get an ident/uat for now.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        // == Setup the authorisation request
-        // We attempt pkce even though the rs is set to not support pkce.
-        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        // Must be authenticated to start.
+        assert!(
+            idms_prox_write
+                .check_oauth2_device_authorise(None, &device_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AuthenticationRequired
+        );
 
-        // First, NOTE the lack of https on the redir uri.
-        let auth_req = AuthorisationRequest {
-            response_type: "code".to_string(),
-            client_id: "test_resource_server".to_string(),
-            state: "123".to_string(),
-            pkce_request: Some(PkceRequest {
-                code_challenge: Base64UrlSafeData(code_challenge.clone()),
-                code_challenge_method: CodeChallengeMethod::S256,
-            }),
-            redirect_uri: Url::parse("http://demo.example.com/oauth2/result").unwrap(),
-            scope: "openid".to_string(),
-            nonce: None,
-            oidc_ext: Default::default(),
-            unknown_keys: Default::default(),
-        };
+        let device_resp = idms_prox_write
+            .check_oauth2_device_authorise(client_authz.as_deref(), &device_req, ct)
+            .expect("Failed to start device authorisation");
 
+        assert!(device_resp.verification_uri.path() == "/ui/oauth2/device");
+        assert!(device_resp.interval == OAUTH2_DEVICE_CODE_INTERVAL);
+
+        let token_req: AccessTokenRequest = GrantTypeReq::DeviceCode {
+            device_code: device_resp.device_code.clone(),
+        }
+        .into();
+
+        // The user has not yet approved.
         assert!(
-            idms_prox_read
-                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
                 .unwrap_err()
-                == Oauth2Error::InvalidOrigin
+                == Oauth2Error::AuthorizationPending
         );
 
-        // This does have https
-        let consent_request = good_authorisation_request!(
-            idms_prox_read,
-            &ident,
-            &uat,
-            ct,
-            code_challenge,
-            "openid".to_string()
+        // Polling too quickly is slowed down.
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::SlowDown
         );
 
-        // Should be in the consent phase;
-        let consent_token =
-            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
+        assert!(idms_prox_write.commit().is_ok());
+
+        // The user enters the code, we are lenient on the format.
+        let idms_prox_read = idms.proxy_read().await;
+        let user_code = device_resp.user_code.replace('-', "").to_lowercase();
+
+        assert!(
+            idms_prox_read
+                .check_oauth2_device_authorisation(&ident, &uat, "ABCD-EFGH", ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
+        );
+
+        let consent_token = match idms_prox_read
+            .check_oauth2_device_authorisation(&ident, &uat, &user_code, ct)
+            .expect("Failed to authorise device")
+        {
+            AuthoriseResponse::ConsentRequested {
+                consent_token,
+                scopes,
+                ..
+            } => {
+                assert!(scopes.contains("openid"));
                 consent_token
-            } else {
-                unreachable!();
-            };
+            }
+            _ => unreachable!(),
+        };
+        drop(idms_prox_read);
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        idms_prox_write
+            .check_oauth2_device_permit(&ident, &uat, &consent_token, ct)
+            .expect("Failed to permit device");
+        assert!(idms_prox_write.commit().is_ok());
+
+        // Now the device can get its token, once the (increased) interval has passed.
+        let ct = ct + Duration::from_secs(15);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let oauth2_token = idms_prox_write
+            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+            .expect("Unable to exchange for oauth2 token");
+
+        assert!(oauth2_token.id_token.is_some());
+        assert!(oauth2_token.refresh_token.is_some());
+
+        // It's single use.
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::ExpiredToken
+        );
 
-        // == Manually submit the consent token to the permit for the permit_success
+        // A rejected request is reported to the device.
+        let device_resp = idms_prox_write
+            .check_oauth2_device_authorise(client_authz.as_deref(), &device_req, ct)
+            .expect("Failed to start device authorisation");
+        assert!(idms_prox_write.commit().is_ok());
+
+        let idms_prox_read = idms.proxy_read().await;
+        let consent_token = match idms_prox_read
+            .check_oauth2_device_authorisation(&ident, &uat, &device_resp.user_code, ct)
+            .expect("Failed to authorise device")
+        {
+            AuthoriseResponse::ConsentRequested { consent_token, .. } => consent_token,
+            _ => unreachable!(),
+        };
         drop(idms_prox_read);
+
         let mut idms_prox_write = idms.proxy_write(ct).await;
+        idms_prox_write
+            .check_oauth2_device_reject(&ident, &uat, &consent_token, ct)
+            .expect("Failed to reject device");
 
-        let permit_success = idms_prox_write
-            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
-            .expect("Failed to perform oauth2 permit");
+        let token_req: AccessTokenRequest = GrantTypeReq::DeviceCode {
+            device_code: device_resp.device_code,
+        }
+        .into();
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AccessDenied
+        );
 
-        // == Submit the token exchange code.
-        // NOTE the url is http again
-        let token_req = AccessTokenRequest {
-            grant_type: GrantTypeReq::AuthorizationCode {
-                code: permit_success.code,
-                redirect_uri: Url::parse("http://demo.example.com/oauth2/result").unwrap(),
-                // Note the code verifier is set to "something else"
-                code_verifier,
-            },
-            client_id: Some("test_resource_server".to_string()),
-            client_secret: Some(secret),
-        };
+        // And an unapproved request expires.
+        let device_resp = idms_prox_write
+            .check_oauth2_device_authorise(client_authz.as_deref(), &device_req, ct)
+            .expect("Failed to start device authorisation");
+        let token_req: AccessTokenRequest = GrantTypeReq::DeviceCode {
+            device_code: device_resp.device_code,
+        }
+        .into();
 
-        // Assert the exchange fails.
-        assert!(matches!(
-            idms_prox_write.check_oauth2_token_exchange(None, &token_req, ct),
-            Err(Oauth2Error::InvalidOrigin)
-        ));
+        let ct = ct + Duration::from_secs(OAUTH2_DEVICE_CODE_EXPIRY as u64);
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::ExpiredToken
+        );
 
-        assert!(idms_prox_write.commit().is_ok());
+        // Polling removed every expired authorisation, not just the one polled for.
+        assert!(idms_prox_write.oauth2rs.device_auths.is_empty());
+
+        // A client can only hold a limited number of outstanding device codes.
+        for _ in 0..OAUTH2_DEVICE_CODE_MAX_PER_CLIENT {
+            idms_prox_write
+                .check_oauth2_device_authorise(client_authz.as_deref(), &device_req, ct)
+                .expect("Failed to start device authorisation");
+        }
+        assert!(
+            idms_prox_write
+                .check_oauth2_device_authorise(client_authz.as_deref(), &device_req, ct)
+                .unwrap_err()
+                == Oauth2Error::SlowDown
+        );
+
+        // Once those expire the client can start again.
+        let ct = ct + Duration::from_secs(OAUTH2_DEVICE_CODE_EXPIRY as u64);
+        assert!(idms_prox_write
+            .check_oauth2_device_authorise(client_authz.as_deref(), &device_req, ct)
+            .is_ok());
     }
 
-    async fn setup_refresh_token(
+    #[idm_test]
+    async fn test_idm_oauth2_token_exchange_delegation(
         idms: &IdmServer,
         _idms_delayed: &mut IdmServerDelayed,
-        ct: Duration,
-    ) -> (AccessTokenResponse, Option<String>) {
-        // First, setup to get a token.
-        let (secret, uat, ident, _) =
+    ) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (secret, uat, ident, rs_uuid) =
             setup_oauth2_resource_server(idms, ct, true, false, false).await;
         let client_authz =
             Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
-        let idms_prox_read = idms.proxy_read().await;
+        // Setup the downstream resource server that tokens will be exchanged for.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let downstream_uuid = Uuid::new_v4();
+        let e: Entry<EntryInit, EntryNew> = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("oauth2_resource_server")),
+            ("class", Value::new_class("oauth2_resource_server_basic")),
+            ("uuid", Value::Uuid(downstream_uuid)),
+            ("oauth2_rs_name", Value::new_iname("test_downstream_server")),
+            ("displayname", Value::new_utf8s("test_downstream_server")),
+            (
+                "oauth2_rs_origin",
+                Value::new_url_s("https://downstream.example.com").unwrap()
+            ),
+            (
+                "oauth2_rs_scope_map",
+                Value::new_oauthscopemap(UUID_IDM_ALL_ACCOUNTS, btreeset!["read".to_string()])
+                    .expect("invalid oauthscope")
+            )
+        );
+        let ce = CreateEvent::new_internal(vec![e]);
+        assert!(idms_prox_write.qs_write.create(&ce).is_ok());
 
-        // == Setup the authorisation request
+        let downstream_secret = idms_prox_write
+            .qs_write
+            .internal_search_uuid(downstream_uuid)
+            .expect("Failed to retrieve oauth2 resource entry ")
+            .get_ava_single_secret("oauth2_rs_basic_secret")
+            .map(str::to_string)
+            .expect("No oauth2_rs_basic_secret found");
+        let downstream_authz =
+            general_purpose::STANDARD.encode(format!("test_downstream_server:{downstream_secret}"));
+        assert!(idms_prox_write.commit().is_ok());
+
+        // Get an access token for the upstream resource server.
+        let idms_prox_read = idms.proxy_read().await;
         let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
         let consent_request = good_authorisation_request!(
             idms_prox_read,
@@ -4079,418 +7336,974 @@ mod tests {
             code_challenge,
             "openid".to_string()
         );
-
         let consent_token =
             if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
                 consent_token
             } else {
                 unreachable!();
             };
-
-        // == Manually submit the consent token to the permit for the permit_success
         drop(idms_prox_read);
-        let mut idms_prox_write = idms.proxy_write(ct).await;
 
+        let mut idms_prox_write = idms.proxy_write(ct).await;
         let permit_success = idms_prox_write
             .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
             .expect("Failed to perform oauth2 permit");
-
         let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
             code: permit_success.code,
             redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
             code_verifier,
         }
         .into();
-        let access_token_response_1 = idms_prox_write
+        let oauth2_token = idms_prox_write
             .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
             .expect("Unable to exchange for oauth2 token");
-
         assert!(idms_prox_write.commit().is_ok());
 
-        trace!(?access_token_response_1);
-
-        (access_token_response_1, client_authz)
-    }
-
-    #[idm_test]
-    async fn test_idm_oauth2_refresh_token_basic(
-        idms: &IdmServer,
-        idms_delayed: &mut IdmServerDelayed,
-    ) {
-        // First, setup to get a token.
-        let ct = Duration::from_secs(TEST_CURRENT_TIME);
-
-        let (access_token_response_1, client_authz) =
-            setup_refresh_token(idms, idms_delayed, ct).await;
-
-        // ============================================
-        // test basic refresh while access still valid.
+        let exchange_req = |audience: &str, subject_token_type: &str, scope: Option<&str>| {
+            AccessTokenRequest::from(GrantTypeReq::TokenExchange {
+                subject_token: oauth2_token.access_token.clone(),
+                subject_token_type: subject_token_type.to_string(),
+                audience: audience.to_string(),
+                scope: scope.map(str::to_string),
+                requested_token_type: None,
+            })
+        };
 
-        let ct = Duration::from_secs(TEST_CURRENT_TIME + 10);
+        // Without permission, the exchange is denied.
         let mut idms_prox_write = idms.proxy_write(ct).await;
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(
+                    client_authz.as_deref(),
+                    &exchange_req(
+                        "test_downstream_server",
+                        OAUTH2_TOKEN_TYPE_ACCESS_TOKEN,
+                        None
+                    ),
+                    ct
+                )
+                .unwrap_err()
+                == Oauth2Error::UnauthorizedClient
+        );
 
-        let refresh_token = access_token_response_1
-            .refresh_token
-            .as_ref()
-            .expect("no refresh token was issued")
-            .clone();
-
-        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
-            refresh_token,
-            scope: None,
-        }
-        .into();
-        let access_token_response_2 = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .expect("Unable to exchange for oauth2 token");
+        // An unknown audience is an invalid target.
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(
+                    client_authz.as_deref(),
+                    &exchange_req("nonexistent", OAUTH2_TOKEN_TYPE_ACCESS_TOKEN, None),
+                    ct
+                )
+                .unwrap_err()
+                == Oauth2Error::InvalidTarget
+        );
 
+        // Allow the upstream resource server to exchange for the downstream.
+        let modlist = ModifyList::new_append(
+            "oauth2_rs_token_exchange_audience",
+            Value::Refer(downstream_uuid),
+        );
+        assert!(idms_prox_write
+            .qs_write
+            .internal_modify(
+                &filter!(f_eq("uuid", PartialValue::Uuid(rs_uuid))),
+                &modlist
+            )
+            .is_ok());
         assert!(idms_prox_write.commit().is_ok());
 
-        trace!(?access_token_response_2);
-
-        assert!(access_token_response_1.access_token != access_token_response_2.access_token);
-        assert!(access_token_response_1.refresh_token != access_token_response_2.refresh_token);
-        assert!(access_token_response_1.id_token != access_token_response_2.id_token);
-
-        // ============================================
-        // test basic refresh after access exp
-        let ct =
-            Duration::from_secs(TEST_CURRENT_TIME + 20 + access_token_response_2.expires_in as u64);
-
         let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let refresh_token = access_token_response_2
-            .refresh_token
-            .as_ref()
-            .expect("no refresh token was issued")
-            .clone();
-
-        // get the refresh token expiry now before we use it.
-        let reflected_token = idms_prox_write
-            .reflect_oauth2_token(client_authz.as_ref().unwrap(), &refresh_token)
-            .expect("Failed to access internals of the refresh token");
+        // Only access tokens can be exchanged.
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(
+                    client_authz.as_deref(),
+                    &exchange_req(
+                        "test_downstream_server",
+                        "urn:ietf:params:oauth:token-type:id_token",
+                        None
+                    ),
+                    ct
+                )
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
+        );
 
-        let refresh_exp = match reflected_token {
-            Oauth2TokenType::Refresh { expiry, .. } => expiry.unix_timestamp(),
-            Oauth2TokenType::Access { .. } => unreachable!(),
-        };
+        // Scopes are from the downstream scope maps, not the subject token.
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(
+                    client_authz.as_deref(),
+                    &exchange_req(
+                        "test_downstream_server",
+                        OAUTH2_TOKEN_TYPE_ACCESS_TOKEN,
+                        Some("openid")
+                    ),
+                    ct
+                )
+                .unwrap_err()
+                == Oauth2Error::InvalidScope
+        );
 
-        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
-            refresh_token,
-            scope: None,
-        }
-        .into();
-        let access_token_response_3 = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .expect("Unable to exchange for oauth2 token");
+        // The permission is only granted to the upstream resource server.
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(
+                    Some(downstream_authz.as_str()),
+                    &exchange_req(
+                        "test_downstream_server",
+                        OAUTH2_TOKEN_TYPE_ACCESS_TOKEN,
+                        None
+                    ),
+                    ct
+                )
+                .unwrap_err()
+                == Oauth2Error::UnauthorizedClient
+        );
 
+        let exchanged_token = idms_prox_write
+            .check_oauth2_token_exchange(
+                client_authz.as_deref(),
+                &exchange_req(
+                    "test_downstream_server",
+                    OAUTH2_TOKEN_TYPE_ACCESS_TOKEN,
+                    None,
+                ),
+                ct,
+            )
+            .expect("Unable to exchange token");
         assert!(idms_prox_write.commit().is_ok());
 
-        trace!(?access_token_response_3);
-
-        assert!(access_token_response_3.access_token != access_token_response_2.access_token);
-        assert!(access_token_response_3.refresh_token != access_token_response_2.refresh_token);
-        assert!(access_token_response_3.id_token != access_token_response_2.id_token);
-
-        // refresh after refresh has expired.
-        // Refresh tokens have a max time limit - the session time limit still bounds it though, but
-        // so does the refresh token limit. We check both, but the refresh time is checked first so
-        // we can guarantee this in this test.
-
-        let ct = Duration::from_secs(
-            TEST_CURRENT_TIME + refresh_exp as u64 + access_token_response_3.expires_in as u64,
+        assert!(exchanged_token.scope.as_deref() == Some("read"));
+        assert!(exchanged_token.refresh_token.is_none());
+        assert!(exchanged_token.id_token.is_none());
+        assert!(
+            exchanged_token.issued_token_type.as_deref() == Some(OAUTH2_TOKEN_TYPE_ACCESS_TOKEN)
         );
 
-        let mut idms_prox_write = idms.proxy_write(ct).await;
-
-        let refresh_token = access_token_response_3
-            .refresh_token
-            .as_ref()
-            .expect("no refresh token was issued")
-            .clone();
-
-        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
-            refresh_token,
-            scope: None,
-        }
-        .into();
-        let access_token_response_4 = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .unwrap_err();
+        // The new token is only valid for the audience.
+        let intr_request = AccessTokenIntrospectRequest {
+            token: exchanged_token.access_token.clone(),
+            token_type_hint: None,
+        };
+        let mut idms_prox_read = idms.proxy_read().await;
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(&downstream_authz, &intr_request, ct)
+            .expect("Failed to inspect token");
+        assert!(intr_response.active);
+        assert!(intr_response.scope.as_deref() == Some("read"));
+        assert!(intr_response.sub == Some(UUID_ADMIN.to_string()));
 
-        assert!(access_token_response_4 == Oauth2Error::InvalidToken);
+        assert!(idms_prox_read
+            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
+            .is_err());
+        drop(idms_prox_read);
 
+        // Revoking the subject token revokes the exchanged token.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let revoke_request = TokenRevokeRequest {
+            token: oauth2_token.access_token.clone(),
+            token_type_hint: None,
+        };
+        assert!(idms_prox_write
+            .oauth2_token_revoke(client_authz.as_deref().unwrap(), &revoke_request, ct)
+            .is_ok());
         assert!(idms_prox_write.commit().is_ok());
+
+        // Past the grace window it is invalid.
+        let ct = ct + GRACE_WINDOW;
+        let mut idms_prox_read = idms.proxy_read().await;
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(&downstream_authz, &intr_request, ct)
+            .expect("Failed to inspect token");
+        assert!(!intr_response.active);
     }
 
-    // refresh when oauth2 parent session exp / missing.
     #[idm_test]
-    async fn test_idm_oauth2_refresh_token_oauth2_session_expired(
+    async fn test_idm_oauth2_pushed_authorisation_request(
         idms: &IdmServer,
-        idms_delayed: &mut IdmServerDelayed,
+        _idms_delayed: &mut IdmServerDelayed,
     ) {
-        // First, setup to get a token.
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (secret, uat, ident, rs_uuid) =
+            setup_oauth2_resource_server(idms, ct, true, false, false).await;
+        let client_authz =
+            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
-        let (access_token_response_1, client_authz) =
-            setup_refresh_token(idms, idms_delayed, ct).await;
-
-        // ============================================
-        // Revoke the oauth2 session
+        // Require par, and give the client a key to sign request objects with.
+        let client_signer = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let client_jwk = client_signer
+            .public_key_as_jwk()
+            .expect("failed to get public key");
 
         let mut idms_prox_write = idms.proxy_write(ct).await;
-        let revoke_request = TokenRevokeRequest {
-            token: access_token_response_1.access_token.clone(),
-            token_type_hint: None,
-        };
+        let modlist = ModifyList::new_list(vec![
+            Modify::Present("oauth2_require_par".into(), Value::new_bool(true)),
+            Modify::Present(
+                "oauth2_rs_client_jwk".into(),
+                Value::new_utf8(serde_json::to_string(&client_jwk).expect("invalid jwk")),
+            ),
+        ]);
         assert!(idms_prox_write
-            .oauth2_token_revoke(client_authz.as_deref().unwrap(), &revoke_request, ct,)
+            .qs_write
+            .internal_modify(
+                &filter!(f_eq("uuid", PartialValue::Uuid(rs_uuid))),
+                &modlist
+            )
             .is_ok());
         assert!(idms_prox_write.commit().is_ok());
 
-        // ============================================
-        // then attempt a refresh.
-        let mut idms_prox_write = idms.proxy_write(ct).await;
-
-        let refresh_token = access_token_response_1
-            .refresh_token
-            .as_ref()
-            .expect("no refresh token was issued")
-            .clone();
+        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        let auth_req = AuthorisationRequest {
+            response_type: "code".to_string(),
+            client_id: "test_resource_server".to_string(),
+            state: "123".to_string(),
+            pkce_request: Some(PkceRequest {
+                code_challenge: Base64UrlSafeData(code_challenge),
+                code_challenge_method: CodeChallengeMethod::S256,
+            }),
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            scope: "openid".to_string(),
+            nonce: Some("abcdef".to_string()),
+            oidc_ext: Default::default(),
+            unknown_keys: Default::default(),
+        };
 
-        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
-            refresh_token,
-            scope: None,
-        }
-        .into();
-        let access_token_response_2 = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            // Should be unable to exchange.
-            .unwrap_err();
+        // The request is rejected when it isn't pushed first.
+        let idms_prox_read = idms.proxy_read().await;
+        assert!(
+            idms_prox_read
+                .check_oauth2_authorisation(&ident, &uat, &auth_req, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
+        );
+        drop(idms_prox_read);
 
-        assert!(access_token_response_2 == Oauth2Error::InvalidToken);
+        // Pushing requires the client to authenticate.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let par_req = PushedAuthorisationRequest {
+            client_secret: None,
+            request: AuthorisationRequestKind::Request(auth_req.clone()),
+        };
+        let bad_authz = Some(general_purpose::STANDARD.encode("test_resource_server:12345"));
+        assert!(
+            idms_prox_write
+                .check_oauth2_pushed_authorisation(bad_authz.as_deref(), &par_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AuthenticationRequired
+        );
 
+        let par = idms_prox_write
+            .check_oauth2_pushed_authorisation(client_authz.as_deref(), &par_req, ct)
+            .expect("Failed to push authorisation request");
+        assert!(par.expires_in == OAUTH2_PAR_EXPIRY);
+        assert!(par.request_uri.starts_with(OAUTH2_PAR_REQUEST_URI_PREFIX));
+
+        // A request object signed by the client can be pushed too.
+        let request_object = super::RequestObject {
+            iss: "test_resource_server".to_string(),
+            aud: serde_json::Value::String(
+                "https://idm.example.com/oauth2/openid/test_resource_server".to_string(),
+            ),
+            exp: ct.as_secs() as i64 + 60,
+            nbf: None,
+            iat: Some(ct.as_secs() as i64),
+            jti: Some("request-1".to_string()),
+            request: auth_req.clone(),
+        };
+        let request = Jws::new(request_object)
+            .sign(&client_signer)
+            .map(|jws| jws.to_string())
+            .expect("Failed to sign request object");
+        let par_req = PushedAuthorisationRequest {
+            client_secret: Some(secret.clone()),
+            request: AuthorisationRequestKind::Reference(AuthorisationRequestReference {
+                client_id: "test_resource_server".to_string(),
+                request_uri: None,
+                request: Some(request.clone()),
+            }),
+        };
+        let signed_par = idms_prox_write
+            .check_oauth2_pushed_authorisation(None, &par_req, ct)
+            .expect("Failed to push signed authorisation request");
         assert!(idms_prox_write.commit().is_ok());
-    }
 
-    // refresh with wrong client id/authz
-    #[idm_test]
-    async fn test_idm_oauth2_refresh_token_invalid_client_authz(
-        idms: &IdmServer,
-        idms_delayed: &mut IdmServerDelayed,
-    ) {
-        // First, setup to get a token.
-        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        // Now the pushed requests are accepted by reference.
+        let idms_prox_read = idms.proxy_read().await;
+        for request_uri in [&par.request_uri, &signed_par.request_uri] {
+            let auth_ref = AuthorisationRequestReference {
+                client_id: "test_resource_server".to_string(),
+                request_uri: Some(request_uri.clone()),
+                request: None,
+            };
+            assert!(matches!(
+                idms_prox_read
+                    .check_oauth2_authorisation_reference(&ident, &uat, &auth_ref, ct)
+                    .expect("Oauth2 authorisation failed"),
+                AuthoriseResponse::ConsentRequested { .. }
+            ));
+        }
 
-        let (access_token_response_1, mut client_authz) =
-            setup_refresh_token(idms, idms_delayed, ct).await;
+        // A request object sent directly is still not pushed.
+        let auth_ref = AuthorisationRequestReference {
+            client_id: "test_resource_server".to_string(),
+            request_uri: None,
+            request: Some(request.clone()),
+        };
+        assert!(
+            idms_prox_read
+                .check_oauth2_authorisation_reference(&ident, &uat, &auth_ref, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
+        );
 
-        if let Some(s) = client_authz.as_mut() {
-            s.push_str("invalid")
-        }
+        // The reference must belong to the same client.
+        let auth_ref = AuthorisationRequestReference {
+            client_id: "other_resource_server".to_string(),
+            request_uri: Some(par.request_uri.clone()),
+            request: None,
+        };
+        assert!(
+            idms_prox_read
+                .check_oauth2_authorisation_reference(&ident, &uat, &auth_ref, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidRequestUri
+        );
 
-        // ============================================
-        // Refresh with invalid client authz
+        // And it expires.
+        let auth_ref = AuthorisationRequestReference {
+            client_id: "test_resource_server".to_string(),
+            request_uri: Some(par.request_uri.clone()),
+            request: None,
+        };
+        assert!(
+            idms_prox_read
+                .check_oauth2_authorisation_reference(
+                    &ident,
+                    &uat,
+                    &auth_ref,
+                    ct + Duration::from_secs(OAUTH2_PAR_EXPIRY as u64)
+                )
+                .unwrap_err()
+                == Oauth2Error::InvalidRequestUri
+        );
+        drop(idms_prox_read);
 
+        // A request object signed by another key is rejected.
+        let other_signer = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let request_object = super::RequestObject {
+            iss: "test_resource_server".to_string(),
+            aud: serde_json::Value::String(
+                "https://idm.example.com/oauth2/openid/test_resource_server".to_string(),
+            ),
+            exp: ct.as_secs() as i64 + 60,
+            nbf: None,
+            iat: None,
+            jti: Some("request-2".to_string()),
+            request: auth_req.clone(),
+        };
+        let request = Jws::new(request_object)
+            .sign(&other_signer)
+            .map(|jws| jws.to_string())
+            .expect("Failed to sign request object");
+        let signed_par_req = |request: String| PushedAuthorisationRequest {
+            client_secret: Some(secret.clone()),
+            request: AuthorisationRequestKind::Reference(AuthorisationRequestReference {
+                client_id: "test_resource_server".to_string(),
+                request_uri: None,
+                request: Some(request),
+            }),
+        };
         let mut idms_prox_write = idms.proxy_write(ct).await;
+        assert!(
+            idms_prox_write
+                .check_oauth2_pushed_authorisation(None, &signed_par_req(request), ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidRequestObject
+        );
 
-        let refresh_token = access_token_response_1
-            .refresh_token
-            .as_ref()
-            .expect("no refresh token was issued")
-            .clone();
+        // A pushed request object can't be replayed.
+        assert!(
+            idms_prox_write
+                .check_oauth2_pushed_authorisation(None, &par_req, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidRequestObject
+        );
 
-        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
-            refresh_token,
-            scope: None,
+        // Request objects without a jti, that are not yet valid, or that are valid for too
+        // long are rejected.
+        let sign_request = |nbf: Option<i64>, exp: i64, jti: Option<&str>| {
+            let request_object = super::RequestObject {
+                iss: "test_resource_server".to_string(),
+                aud: serde_json::Value::String(
+                    "https://idm.example.com/oauth2/openid/test_resource_server".to_string(),
+                ),
+                exp,
+                nbf,
+                iat: None,
+                jti: jti.map(str::to_string),
+                request: auth_req.clone(),
+            };
+            Jws::new(request_object)
+                .sign(&client_signer)
+                .map(|jws| jws.to_string())
+                .expect("Failed to sign request object")
+        };
+        let now = ct.as_secs() as i64;
+        for request in [
+            sign_request(None, now + 60, None),
+            sign_request(Some(now + 30), now + 60, Some("request-3")),
+            sign_request(
+                None,
+                now + OAUTH2_REQUEST_OBJECT_MAX_EXPIRY as i64 + 1,
+                Some("request-4"),
+            ),
+            sign_request(
+                Some(now - 60),
+                now + OAUTH2_REQUEST_OBJECT_MAX_EXPIRY as i64,
+                Some("request-5"),
+            ),
+        ] {
+            assert!(
+                idms_prox_write
+                    .check_oauth2_pushed_authorisation(None, &signed_par_req(request), ct)
+                    .unwrap_err()
+                    == Oauth2Error::InvalidRequestObject
+            );
         }
-        .into();
-        let access_token_response_2 = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .unwrap_err();
+        drop(idms_prox_write);
 
-        assert!(access_token_response_2 == Oauth2Error::AuthenticationRequired);
+        // The request_uri can only be used to obtain one token.
+        let idms_prox_read = idms.proxy_read().await;
+        let auth_ref = AuthorisationRequestReference {
+            client_id: "test_resource_server".to_string(),
+            request_uri: Some(par.request_uri.clone()),
+            request: None,
+        };
+        let consent_token = match idms_prox_read
+            .check_oauth2_authorisation_reference(&ident, &uat, &auth_ref, ct)
+            .expect("Oauth2 authorisation failed")
+        {
+            AuthoriseResponse::ConsentRequested { consent_token, .. } => consent_token,
+            _ => unreachable!(),
+        };
+        drop(idms_prox_read);
 
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let permit_success = idms_prox_write
+            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
+            .expect("Failed to perform oauth2 permit");
         assert!(idms_prox_write.commit().is_ok());
+
+        // Consent is now remembered, so authorising again issues a second code directly.
+        let mut idms_prox_read = idms.proxy_read().await;
+        let ident = idms_prox_read
+            .process_uat_to_identity(&uat, ct)
+            .expect("Unable to process uat");
+        let second_code = match idms_prox_read
+            .check_oauth2_authorisation_reference(&ident, &uat, &auth_ref, ct)
+            .expect("Oauth2 authorisation failed")
+        {
+            AuthoriseResponse::Permitted(permit_success) => permit_success.code,
+            _ => unreachable!(),
+        };
+        drop(idms_prox_read);
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        for (code, expected) in [
+            (permit_success.code, Ok(())),
+            (second_code, Err(Oauth2Error::InvalidGrant)),
+        ] {
+            let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
+                code,
+                redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+                code_verifier: code_verifier.clone(),
+            }
+            .into();
+            assert!(
+                idms_prox_write
+                    .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
+                    .map(|_| ())
+                    == expected
+            );
+        }
     }
 
-    // Incorrect scopes re-requested
     #[idm_test]
-    async fn test_idm_oauth2_refresh_token_inconsistent_scopes(
+    async fn test_idm_oauth2_token_client_assertion(
         idms: &IdmServer,
-        idms_delayed: &mut IdmServerDelayed,
+        _idms_delayed: &mut IdmServerDelayed,
     ) {
-        // First, setup to get a token.
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (secret, _uat, _ident, rs_uuid) =
+            setup_oauth2_resource_server(idms, ct, true, false, false).await;
 
-        let (access_token_response_1, client_authz) =
-            setup_refresh_token(idms, idms_delayed, ct).await;
+        let client_signer = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let client_jwk = client_signer
+            .public_key_as_jwk()
+            .expect("failed to get public key");
 
-        // ============================================
-        // Refresh with different scopes
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let modlist = ModifyList::new_list(vec![Modify::Present(
+            "oauth2_rs_client_jwk".into(),
+            Value::new_utf8(serde_json::to_string(&client_jwk).expect("invalid jwk")),
+        )]);
+        assert!(idms_prox_write
+            .qs_write
+            .internal_modify(
+                &filter!(f_eq("uuid", PartialValue::Uuid(rs_uuid))),
+                &modlist
+            )
+            .is_ok());
+        assert!(idms_prox_write.commit().is_ok());
 
+        let assertion_req = |signer: &JwsSigner, jti: &str, exp: i64| {
+            let assertion = super::ClientAssertion {
+                iss: "test_resource_server".to_string(),
+                sub: "test_resource_server".to_string(),
+                aud: serde_json::Value::String("https://idm.example.com/oauth2/token".to_string()),
+                exp,
+                jti: jti.to_string(),
+            };
+            let client_assertion = Jws::new(assertion)
+                .sign(signer)
+                .map(|jws| jws.to_string())
+                .expect("Failed to sign client assertion");
+            let mut token_req: AccessTokenRequest =
+                GrantTypeReq::ClientCredentials { scope: None }.into();
+            token_req.client_assertion_type =
+                Some(OAUTH2_CLIENT_ASSERTION_TYPE_JWT_BEARER.to_string());
+            token_req.client_assertion = Some(client_assertion);
+            token_req
+        };
+
+        let exp = ct.as_secs() as i64 + 60;
         let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let refresh_token = access_token_response_1
-            .refresh_token
-            .as_ref()
-            .expect("no refresh token was issued")
-            .clone();
+        // private_key_jwt. The resource server has no scopes to be granted, so once it is
+        // authenticated it is unauthorised.
+        let token_req = assertion_req(&client_signer, "jti_1", exp);
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(None, &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::UnauthorizedClient
+        );
 
-        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
-            refresh_token,
-            scope: Some(btreeset!["invalid_scope".to_string()]),
-        }
-        .into();
-        let access_token_response_2 = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .unwrap_err();
+        // The same assertion can't be replayed, even though that failed exchange was
+        // never committed.
+        drop(idms_prox_write);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(None, &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AuthenticationRequired
+        );
 
-        assert!(access_token_response_2 == Oauth2Error::InvalidScope);
+        // client_secret_jwt
+        let secret_signer =
+            JwsSigner::from_hs256_raw(secret.as_bytes()).expect("failed to construct signer.");
+        let token_req = assertion_req(&secret_signer, "jti_2", exp);
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(None, &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::UnauthorizedClient
+        );
 
-        assert!(idms_prox_write.commit().is_ok());
+        // An assertion can't be combined with the secret.
+        let token_req = assertion_req(&client_signer, "jti_3", exp);
+        let client_authz =
+            general_purpose::STANDARD.encode(format!("test_resource_server:{secret}"));
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(Some(client_authz.as_str()), &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
+        );
+
+        // Signed by an unknown key.
+        let other_signer = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let token_req = assertion_req(&other_signer, "jti_4", exp);
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(None, &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AuthenticationRequired
+        );
+
+        // Expired, or valid for too long.
+        let token_req = assertion_req(&client_signer, "jti_5", ct.as_secs() as i64);
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(None, &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AuthenticationRequired
+        );
+        let token_req = assertion_req(
+            &client_signer,
+            "jti_6",
+            ct.as_secs() as i64 + OAUTH2_CLIENT_ASSERTION_MAX_EXPIRY as i64 + 1,
+        );
+        assert!(
+            idms_prox_write
+                .check_oauth2_token_exchange(None, &token_req, ct)
+                .unwrap_err()
+                == Oauth2Error::AuthenticationRequired
+        );
     }
 
-    // Test that re-use of a refresh token is denied + terminates the session.
-    //
-    // https://www.ietf.org/archive/id/draft-ietf-oauth-security-topics-18.html#refresh_token_protection
     #[idm_test]
-    async fn test_idm_oauth2_refresh_token_reuse_invalidates_session(
+    async fn test_idm_oauth2_end_session_and_backchannel_logout(
         idms: &IdmServer,
-        idms_delayed: &mut IdmServerDelayed,
+        _idms_delayed: &mut IdmServerDelayed,
     ) {
-        // First, setup to get a token.
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let (secret, uat, ident, rs_uuid) =
+            setup_oauth2_resource_server(idms, ct, true, false, false).await;
+        let client_authz =
+            Some(general_purpose::STANDARD.encode(format!("test_resource_server:{secret}")));
 
-        let (access_token_response_1, client_authz) =
-            setup_refresh_token(idms, idms_delayed, ct).await;
+        let backchannel_logout_uri = Url::parse("https://demo.example.com/backchannel").unwrap();
 
-        // ============================================
-        // Use the refresh token once
-        let ct = Duration::from_secs(TEST_CURRENT_TIME + 1);
         let mut idms_prox_write = idms.proxy_write(ct).await;
+        let modlist = ModifyList::new_list(vec![Modify::Present(
+            "oauth2_rs_backchannel_logout_uri".into(),
+            Value::Url(backchannel_logout_uri.clone()),
+        )]);
+        assert!(idms_prox_write
+            .qs_write
+            .internal_modify(
+                &filter!(f_eq("uuid", PartialValue::Uuid(rs_uuid))),
+                &modlist
+            )
+            .is_ok());
+        assert!(idms_prox_write.commit().is_ok());
 
-        let refresh_token = access_token_response_1
-            .refresh_token
-            .as_ref()
-            .expect("no refresh token was issued")
-            .clone();
+        // Get an id_token, which creates an oauth2 session under the user's session.
+        let idms_prox_read = idms.proxy_read().await;
+        let (code_verifier, code_challenge) = create_code_verifier!("Whar Garble");
+        let consent_request = good_authorisation_request!(
+            idms_prox_read,
+            &ident,
+            &uat,
+            ct,
+            code_challenge,
+            "openid".to_string()
+        );
+        let consent_token =
+            if let AuthoriseResponse::ConsentRequested { consent_token, .. } = consent_request {
+                consent_token
+            } else {
+                unreachable!();
+            };
+        drop(idms_prox_read);
 
-        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
-            refresh_token,
-            scope: None,
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let permit_success = idms_prox_write
+            .check_oauth2_authorise_permit(&ident, &uat, &consent_token, ct)
+            .expect("Failed to perform oauth2 permit");
+        let token_req: AccessTokenRequest = GrantTypeReq::AuthorizationCode {
+            code: permit_success.code,
+            redirect_uri: Url::parse("https://demo.example.com/oauth2/result").unwrap(),
+            code_verifier,
         }
         .into();
-
-        let _access_token_response_2 = idms_prox_write
+        let oauth2_token = idms_prox_write
             .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
             .expect("Unable to exchange for oauth2 token");
-
         assert!(idms_prox_write.commit().is_ok());
 
-        // Now use it again. - this will cause an error and the session to be terminated.
-        let ct = Duration::from_secs(TEST_CURRENT_TIME + 2);
-        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let id_token = oauth2_token.id_token.clone().expect("no id_token");
 
-        let refresh_token = access_token_response_1
-            .refresh_token
-            .as_ref()
-            .expect("no refresh token was issued")
-            .clone();
+        // The resource server can end the session, and have the user sent back to it.
+        let idms_prox_read = idms.proxy_read().await;
+        let mut end_session_req = EndSessionRequest {
+            id_token_hint: Some(id_token.clone()),
+            client_id: None,
+            post_logout_redirect_uri: Some(
+                Url::parse("https://demo.example.com/logged_out").unwrap(),
+            ),
+            state: Some("abcdef".to_string()),
+        };
+        let post_logout_redirect_uri = idms_prox_read
+            .check_oauth2_end_session("test_resource_server", &end_session_req, Some(&uat))
+            .expect("Failed to check end session request")
+            .expect("No post logout redirect uri");
+        assert!(
+            post_logout_redirect_uri.as_str() == "https://demo.example.com/logged_out?state=abcdef"
+        );
 
-        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
-            refresh_token,
-            scope: None,
-        }
-        .into();
+        // But not anywhere else.
+        end_session_req.post_logout_redirect_uri =
+            Some(Url::parse("https://evil.example.com/logged_out").unwrap());
+        assert!(
+            idms_prox_read
+                .check_oauth2_end_session("test_resource_server", &end_session_req, Some(&uat))
+                .unwrap_err()
+                == Oauth2Error::InvalidOrigin
+        );
+        end_session_req.post_logout_redirect_uri = None;
 
-        let access_token_response_3 = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .unwrap_err();
+        // The id_token_hint is required, so that another site can't log the user out.
+        end_session_req.id_token_hint = None;
+        assert!(
+            idms_prox_read
+                .check_oauth2_end_session("test_resource_server", &end_session_req, Some(&uat))
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
+        );
 
-        assert!(access_token_response_3 == Oauth2Error::InvalidGrant);
+        // It must be for the user of the session being ended.
+        end_session_req.id_token_hint = Some(id_token.clone());
+        let mut other_uat = uat.clone();
+        other_uat.uuid = UUID_IDM_ADMIN;
+        assert!(
+            idms_prox_read
+                .check_oauth2_end_session(
+                    "test_resource_server",
+                    &end_session_req,
+                    Some(&other_uat)
+                )
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
+        );
 
-        let entry = idms_prox_write
-            .qs_write
-            .internal_search_uuid(UUID_ADMIN)
-            .expect("failed");
-        let valid = entry
-            .get_ava_as_oauth2session_map("oauth2_session")
-            .map(|map| {
-                trace!(?map);
-                map.is_empty()
-            })
-            // If there is no map, it must be empty
-            .unwrap_or(true);
-        assert!(valid);
+        // Though if the session has already gone, there is nothing to check it against.
+        assert!(idms_prox_read
+            .check_oauth2_end_session("test_resource_server", &end_session_req, None)
+            .is_ok());
+
+        // The id_token_hint must be one we issued, to this client.
+        end_session_req.id_token_hint = Some("not a token".to_string());
+        assert!(
+            idms_prox_read
+                .check_oauth2_end_session("test_resource_server", &end_session_req, Some(&uat))
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
+        );
+        end_session_req.id_token_hint = Some(id_token);
+        end_session_req.client_id = Some("other_resource_server".to_string());
+        assert!(
+            idms_prox_read
+                .check_oauth2_end_session("test_resource_server", &end_session_req, Some(&uat))
+                .unwrap_err()
+                == Oauth2Error::InvalidRequest
+        );
+
+        let mut jwkset = idms_prox_read
+            .oauth2_openid_publickey("test_resource_server")
+            .expect("Failed to get public key");
+        let public_jwk = jwkset.keys.pop().expect("no such jwk");
+        let jws_validator = JwsValidator::try_from(&public_jwk).expect("failed to build validator");
+        drop(idms_prox_read);
 
+        // Now logout. The resource server is sent a logout token for the session.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let logouts = idms_prox_write
+            .oauth2_backchannel_logouts(UUID_ADMIN, uat.session_id, ct)
+            .expect("Failed to create logout tokens");
+        assert!(logouts.len() == 1);
+        assert!(logouts[0].backchannel_logout_uri == backchannel_logout_uri);
+
+        let logout_token: LogoutToken = JwsUnverified::from_str(&logouts[0].logout_token)
+            .and_then(|jwsu| jwsu.validate(&jws_validator))
+            .map(|jws: Jws<LogoutToken>| jws.into_inner())
+            .expect("Failed to verify logout token");
+        assert!(logout_token.sub == OidcSubject::U(UUID_ADMIN));
+        assert!(logout_token.aud == "test_resource_server");
+        assert!(logout_token.sid == uat.session_id);
+        assert!(logout_token
+            .events
+            .get("http://schemas.openid.net/event/backchannel-logout")
+            .is_some());
+
+        let dte = DestroySessionTokenEvent {
+            ident: ident.clone(),
+            target: UUID_ADMIN,
+            token_id: uat.session_id,
+        };
+        assert!(idms_prox_write.account_destroy_session_token(&dte).is_ok());
         assert!(idms_prox_write.commit().is_ok());
-    }
 
-    // Test session divergence. This means that we have to:
-    // access + refresh 1
-    // use refresh 1 -> access + refresh 2 // don't commit this txn.
-    // use refresh 2 -> access + refresh 3
-    //    check the session state.
+        // The oauth2 session is revoked with it, even though we are within the grace window.
+        let mut idms_prox_read = idms.proxy_read().await;
+        let intr_request = AccessTokenIntrospectRequest {
+            token: oauth2_token.access_token,
+            token_type_hint: None,
+        };
+        let intr_response = idms_prox_read
+            .check_oauth2_token_introspect(client_authz.as_deref().unwrap(), &intr_request, ct)
+            .expect("Failed to inspect token");
+        assert!(!intr_response.active);
+        drop(idms_prox_read);
+
+        // And there is nothing left to send.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        assert!(idms_prox_write
+            .oauth2_backchannel_logouts(UUID_ADMIN, uat.session_id, ct)
+            .expect("Failed to create logout tokens")
+            .is_empty());
+    }
 
     #[idm_test]
-    async fn test_idm_oauth2_refresh_token_divergence(
+    async fn test_idm_oauth2_client_registration(
         idms: &IdmServer,
-        idms_delayed: &mut IdmServerDelayed,
+        _idms_delayed: &mut IdmServerDelayed,
     ) {
-        // First, setup to get a token.
         let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let mut idms_prox_write = idms.proxy_write(ct).await;
 
-        let (access_token_response_1, client_authz) =
-            setup_refresh_token(idms, idms_delayed, ct).await;
+        let registrant_uuid = Uuid::new_v4();
+        let other_uuid = Uuid::new_v4();
 
-        // ============================================
-        // Use the refresh token once
-        let ct = Duration::from_secs(TEST_CURRENT_TIME + 1);
-        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let registrant: Entry<EntryInit, EntryNew> = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("account")),
+            ("class", Value::new_class("service_account")),
+            ("uuid", Value::Uuid(registrant_uuid)),
+            ("name", Value::new_iname("test_registrant")),
+            ("displayname", Value::new_utf8s("test_registrant")),
+            (
+                "oauth2_registration_origin",
+                Value::new_url_s("https://review.example.com").unwrap()
+            ),
+            (
+                "oauth2_registration_scope_map",
+                Value::new_oauthscopemap(
+                    UUID_IDM_ALL_ACCOUNTS,
+                    btreeset!["openid".to_string(), "email".to_string()]
+                )
+                .expect("invalid oauthscope")
+            )
+        );
+        let other: Entry<EntryInit, EntryNew> = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("account")),
+            ("class", Value::new_class("service_account")),
+            ("uuid", Value::Uuid(other_uuid)),
+            ("name", Value::new_iname("test_other_registrant")),
+            ("displayname", Value::new_utf8s("test_other_registrant")),
+            (
+                "oauth2_registration_origin",
+                Value::new_url_s("https://review.example.com").unwrap()
+            )
+        );
+        assert!(idms_prox_write
+            .qs_write
+            .internal_create(vec![registrant, other])
+            .is_ok());
 
-        let refresh_token = access_token_response_1
-            .refresh_token
-            .as_ref()
-            .expect("no refresh token was issued")
-            .clone();
+        let ident = Identity::from_impersonate_entry_readwrite(
+            idms_prox_write
+                .qs_write
+                .internal_search_uuid(registrant_uuid)
+                .expect("Failed to find registrant"),
+        );
+        let other_ident = Identity::from_impersonate_entry_readwrite(
+            idms_prox_write
+                .qs_write
+                .internal_search_uuid(other_uuid)
+                .expect("Failed to find registrant"),
+        );
 
-        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
-            refresh_token,
-            scope: None,
-        }
-        .into();
+        // Origins outside of the registration origins are rejected, as are scopes we can't grant.
+        let reg_req = ClientRegistrationRequest {
+            redirect_uris: vec![Url::parse("https://evil.example.com/oauth2/result").unwrap()],
+            ..Default::default()
+        };
+        assert!(
+            idms_prox_write.oauth2_client_register(&ident, &reg_req)
+                == Err(Oauth2Error::InvalidRedirectUri)
+        );
 
-        let access_token_response_2 = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .expect("Unable to exchange for oauth2 token");
+        let reg_req = ClientRegistrationRequest {
+            redirect_uris: vec![Url::parse("https://pr1.review.example.com/oauth2/result").unwrap()],
+            scope: Some("openid groups".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            idms_prox_write.oauth2_client_register(&ident, &reg_req)
+                == Err(Oauth2Error::InvalidClientMetadata)
+        );
 
-        // DO NOT COMMIT HERE - this is what forces the session issued_at
-        // time to stay at the original time!
-        drop(idms_prox_write);
+        // A subdomain of a registration origin is allowed.
+        let reg_req = ClientRegistrationRequest {
+            redirect_uris: vec![Url::parse("https://pr1.review.example.com/oauth2/result").unwrap()],
+            client_name: Some("Review PR1".to_string()),
+            scope: Some("openid".to_string()),
+            ..Default::default()
+        };
+        let reg_resp = idms_prox_write
+            .oauth2_client_register(&ident, &reg_req)
+            .expect("Failed to register client");
+        assert!(reg_resp.client_id.starts_with("dyn_"));
+        assert!(!reg_resp.client_secret.is_empty());
+        assert!(reg_resp.metadata.scope.as_deref() == Some("openid"));
+        assert!(
+            reg_resp.metadata.redirect_uris
+                == vec![Url::parse("https://pr1.review.example.com").unwrap()]
+        );
+        assert!(idms_prox_write.commit().is_ok());
 
-        // ============================================
-        let ct = Duration::from_secs(TEST_CURRENT_TIME + 2);
-        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let client_id = reg_resp.client_id.clone();
 
-        let refresh_token = access_token_response_2
-            .refresh_token
-            .as_ref()
-            .expect("no refresh token was issued")
-            .clone();
+        // The client is a usable resource server.
+        let mut idms_prox_read = idms.proxy_read().await;
+        assert!(idms_prox_read
+            .oauth2rs
+            .inner
+            .rs_set
+            .contains_key(&client_id));
 
-        let token_req: AccessTokenRequest = GrantTypeReq::RefreshToken {
-            refresh_token,
-            scope: None,
-        }
-        .into();
+        // Only the registrant can see it.
+        let get_resp = idms_prox_read
+            .oauth2_client_registration_get(&ident, &client_id)
+            .expect("Failed to read client registration");
+        assert!(get_resp == reg_resp);
+        assert!(
+            idms_prox_read.oauth2_client_registration_get(&other_ident, &client_id)
+                == Err(Oauth2Error::InvalidToken)
+        );
+        drop(idms_prox_read);
 
-        let _access_token_response_3 = idms_prox_write
-            .check_oauth2_token_exchange(client_authz.as_deref(), &token_req, ct)
-            .expect("Unable to exchange for oauth2 token");
+        // Update the client.
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let reg_req = ClientRegistrationRequest {
+            redirect_uris: vec![Url::parse("https://pr2.review.example.com/oauth2/result").unwrap()],
+            client_name: Some("Review PR2".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            idms_prox_write.oauth2_client_registration_update(&other_ident, &client_id, &reg_req)
+                == Err(Oauth2Error::InvalidToken)
+        );
+        let update_resp = idms_prox_write
+            .oauth2_client_registration_update(&ident, &client_id, &reg_req)
+            .expect("Failed to update client registration");
+        assert!(update_resp.client_id == client_id);
+        assert!(update_resp.client_secret == reg_resp.client_secret);
+        assert!(update_resp.metadata.client_name.as_deref() == Some("Review PR2"));
+        assert!(
+            update_resp.metadata.redirect_uris
+                == vec![Url::parse("https://pr2.review.example.com").unwrap()]
+        );
+        assert!(update_resp.metadata.scope.as_deref() == Some("email openid"));
 
+        // And delete it.
+        assert!(
+            idms_prox_write.oauth2_client_registration_delete(&other_ident, &client_id)
+                == Err(Oauth2Error::InvalidToken)
+        );
+        assert!(idms_prox_write
+            .oauth2_client_registration_delete(&ident, &client_id)
+            .is_ok());
+        assert!(
+            idms_prox_write.oauth2_client_registration_delete(&ident, &client_id)
+                == Err(Oauth2Error::InvalidToken)
+        );
         assert!(idms_prox_write.commit().is_ok());
-
-        // Success!
     }
 }
@@ -591,8 +591,12 @@ mod tests {
         }
     }
 
-    #[idm_test]
-    async fn test_idm_reauth_passkey(idms: &IdmServer, idms_delayed: &mut IdmServerDelayed) {
+    #[idm_test(audit)]
+    async fn test_idm_reauth_passkey(
+        idms: &IdmServer,
+        idms_delayed: &mut IdmServerDelayed,
+        idms_audit: &mut IdmServerAudit,
+    ) {
         let ct = duration_from_epoch_now();
 
         // Setup the test account
@@ -629,6 +633,18 @@ mod tests {
         // They now have the entitlement.
         debug!(?ident);
         assert!(matches!(ident.access_scope(), AccessScope::ReadWrite));
+
+        // And each step was audited.
+        let events = idms_audit.drain();
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, AuditEvent::CredentialsChanged { .. })));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, AuditEvent::AuthenticationSucceeded { .. })));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, AuditEvent::PrivilegeEscalated { .. })));
     }
 
     #[idm_test(audit)]
@@ -676,10 +692,10 @@ mod tests {
         .is_none());
 
         // There should be a queued audit event
-        match idms_audit.audit_rx().try_recv() {
-            Ok(AuditEvent::AuthenticationDenied { .. }) => {}
-            _ => assert!(false),
-        }
+        assert!(idms_audit
+            .drain()
+            .iter()
+            .any(|e| matches!(e, AuditEvent::AuthenticationDenied { .. })));
 
         // Start the re-auth - MUST FAIL!
         assert!(
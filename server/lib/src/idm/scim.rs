@@ -833,6 +833,51 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
                 })
                 .map(|value| vec![Value::Uint32(value)]),
 
+            (
+                SyntaxType::Int64,
+                false,
+                ScimAttr::SingleSimple(ScimSimpleAttr::Number(js_value)),
+            ) => js_value
+                .as_i64()
+                .ok_or_else(|| {
+                    error!("Invalid value - not a valid signed 64 bit integer");
+                    OperationError::InvalidAttribute(format!(
+                        "Invalid signed integer - {scim_attr_name}"
+                    ))
+                })
+                .map(|value| vec![Value::Int64(value)]),
+
+            // Decimals may be sent as strings to avoid precision loss in json floats.
+            (
+                SyntaxType::Decimal,
+                false,
+                ScimAttr::SingleSimple(ScimSimpleAttr::Number(js_value)),
+            ) => Value::new_decimal_str(&js_value.to_string())
+                .ok_or_else(|| {
+                    error!("Invalid value - not a valid decimal");
+                    OperationError::InvalidAttribute(format!("Invalid decimal - {scim_attr_name}"))
+                })
+                .map(|value| vec![value]),
+            (SyntaxType::Decimal, false, ScimAttr::SingleSimple(ScimSimpleAttr::String(value))) => {
+                Value::new_decimal_str(value)
+                    .ok_or_else(|| {
+                        error!("Invalid value - not a valid decimal");
+                        OperationError::InvalidAttribute(format!(
+                            "Invalid decimal - {scim_attr_name}"
+                        ))
+                    })
+                    .map(|value| vec![value])
+            }
+
+            (SyntaxType::Date, false, ScimAttr::SingleSimple(ScimSimpleAttr::String(value))) => {
+                Value::new_date_s(value)
+                    .ok_or_else(|| {
+                        error!("Invalid value - not a valid YYYY-MM-DD date");
+                        OperationError::InvalidAttribute(format!("Invalid date - {scim_attr_name}"))
+                    })
+                    .map(|value| vec![value])
+            }
+
             (SyntaxType::ReferenceUuid, true, ScimAttr::MultiComplex(values)) => {
                 // In this case, because it's a reference uuid only, despite the multicomplex structure, it's a list of
                 // "external_id" to external_ids. These *might* also be uuids. So we need to use sync_external_id_to_uuid
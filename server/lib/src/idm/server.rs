@@ -12,13 +12,14 @@ use concread::hashmap::HashMap;
 use concread::CowCell;
 use fernet::Fernet;
 use hashbrown::HashSet;
+use kanidm_proto::internal::PasswordKdfReport;
 use kanidm_proto::v1::{
     ApiToken, BackupCodesView, CredentialStatus, PasswordFeedback, RadiusAuthToken, UatPurpose,
     UnixGroupToken, UnixUserToken, UserAuthToken,
 };
 use rand::prelude::*;
 use tokio::sync::mpsc::{
-    unbounded_channel as unbounded, UnboundedReceiver as Receiver, UnboundedSender as Sender,
+    self, unbounded_channel as unbounded, UnboundedReceiver as Receiver, UnboundedSender as Sender,
 };
 use tokio::sync::{Mutex, Semaphore};
 use tracing::trace;
@@ -29,7 +30,7 @@ use super::event::ReadBackupCodeEvent;
 use super::ldap::{LdapBoundToken, LdapSession};
 use crate::credential::{softlock::CredSoftLock, Credential};
 use crate::idm::account::Account;
-use crate::idm::audit::AuditEvent;
+use crate::idm::audit::{queue_audit_event, AuditEvent};
 use crate::idm::authsession::AuthSession;
 use crate::idm::credupdatesession::CredentialUpdateSessionMutex;
 use crate::idm::delayed::{
@@ -44,6 +45,7 @@ use crate::idm::event::{
     RegenerateRadiusSecretEvent, UnixGroupTokenEvent, UnixPasswordChangeEvent, UnixUserAuthEvent,
     UnixUserTokenEvent,
 };
+use crate::idm::message::{queue_notification, Notification};
 use crate::idm::oauth2::{
     Oauth2ResourceServers, Oauth2ResourceServersReadTransaction,
     Oauth2ResourceServersWriteTransaction,
@@ -51,6 +53,7 @@ use crate::idm::oauth2::{
 use crate::idm::radius::RadiusAccount;
 use crate::idm::scim::{ScimSyncToken, SyncAccount};
 use crate::idm::serviceaccount::ServiceAccount;
+use crate::idm::sourcelock::{SourceLockPolicy, SourceLocks, SOURCE_LOCKED_REASON};
 use crate::idm::unix::{UnixGroup, UnixUserAccount};
 use crate::idm::AuthState;
 use crate::prelude::*;
@@ -76,6 +79,8 @@ pub struct IdmServer {
     session_ticket: Semaphore,
     sessions: BptreeMap<Uuid, AuthSessionMutex>,
     softlocks: HashMap<Uuid, CredSoftLockMutex>,
+    /// Failed authentications by client address, to slow down attacks across many accounts.
+    source_locks: SourceLocks,
     /// A set of in progress credential registrations
     cred_update_sessions: BptreeMap<Uuid, CredentialUpdateSessionMutex>,
     /// Reference to the query server.
@@ -83,7 +88,7 @@ pub struct IdmServer {
     /// The configured crypto policy for the IDM server. Later this could be transactional and loaded from the db similar to access. But today it's just to allow dynamic pbkdf2rounds
     crypto_policy: CryptoPolicy,
     async_tx: Sender<DelayedAction>,
-    audit_tx: Sender<AuditEvent>,
+    audit_tx: mpsc::Sender<AuditEvent>,
     /// [Webauthn] verifier/config
     webauthn: Webauthn,
     pw_badlist_cache: Arc<CowCell<HashSet<String>>>,
@@ -96,13 +101,14 @@ pub struct IdmServerAuthTransaction<'a> {
     pub(crate) session_ticket: &'a Semaphore,
     pub(crate) sessions: &'a BptreeMap<Uuid, AuthSessionMutex>,
     pub(crate) softlocks: &'a HashMap<Uuid, CredSoftLockMutex>,
+    pub(crate) source_locks: &'a SourceLocks,
 
     pub qs_read: QueryServerReadTransaction<'a>,
     /// Thread/Server ID
     pub(crate) sid: Sid,
     // For flagging eventual actions.
     pub(crate) async_tx: Sender<DelayedAction>,
-    pub(crate) audit_tx: Sender<AuditEvent>,
+    pub(crate) audit_tx: mpsc::Sender<AuditEvent>,
     pub(crate) webauthn: &'a Webauthn,
     pub(crate) pw_badlist_cache: CowCellReadTxn<HashSet<String>>,
     pub(crate) domain_keys: CowCellReadTxn<DomainKeys>,
@@ -122,7 +128,7 @@ pub struct IdmServerCredUpdateTransaction<'a> {
 pub struct IdmServerProxyReadTransaction<'a> {
     pub qs_read: QueryServerReadTransaction<'a>,
     pub(crate) domain_keys: CowCellReadTxn<DomainKeys>,
-    pub(crate) oauth2rs: Oauth2ResourceServersReadTransaction,
+    pub(crate) oauth2rs: Oauth2ResourceServersReadTransaction<'a>,
 }
 
 pub struct IdmServerProxyWriteTransaction<'a> {
@@ -132,6 +138,7 @@ pub struct IdmServerProxyWriteTransaction<'a> {
     /// Associate to an event origin ID, which has a TS and a UUID instead
     pub(crate) cred_update_sessions: BptreeMapWriteTxn<'a, Uuid, CredentialUpdateSessionMutex>,
     pub(crate) sid: Sid,
+    audit_tx: mpsc::Sender<AuditEvent>,
     crypto_policy: &'a CryptoPolicy,
     webauthn: &'a Webauthn,
     pw_badlist_cache: CowCellWriteTxn<'a, HashSet<String>>,
@@ -144,13 +151,14 @@ pub struct IdmServerDelayed {
 }
 
 pub struct IdmServerAudit {
-    pub(crate) audit_rx: Receiver<AuditEvent>,
+    pub(crate) audit_rx: mpsc::Receiver<AuditEvent>,
 }
 
 impl IdmServer {
     pub async fn new(
         qs: QueryServer,
         origin: &str,
+        source_lock_policy: SourceLockPolicy,
     ) -> Result<(IdmServer, IdmServerDelayed, IdmServerAudit), OperationError> {
         // This is calculated back from:
         //  500 auths / thread -> 0.002 sec per op
@@ -161,7 +169,7 @@ impl IdmServer {
         // improves.
         let crypto_policy = CryptoPolicy::time_target(Duration::from_millis(1));
         let (async_tx, async_rx) = unbounded();
-        let (audit_tx, audit_rx) = unbounded();
+        let (audit_tx, audit_rx) = mpsc::channel(AUDIT_EVENT_QUEUE_SIZE);
 
         // Get the domain name, as the relying party id.
         let (
@@ -251,6 +259,7 @@ impl IdmServer {
                 session_ticket: Semaphore::new(1),
                 sessions: BptreeMap::new(),
                 softlocks: HashMap::new(),
+                source_locks: SourceLocks::new(source_lock_policy),
                 cred_update_sessions: BptreeMap::new(),
                 qs,
                 crypto_policy,
@@ -266,6 +275,10 @@ impl IdmServer {
         ))
     }
 
+    pub fn source_locks(&self) -> &SourceLocks {
+        &self.source_locks
+    }
+
     pub fn get_cookie_key(&self) -> [u8; 32] {
         self.domain_keys.read().cookie_key
     }
@@ -282,6 +295,7 @@ impl IdmServer {
             session_ticket: &self.session_ticket,
             sessions: &self.sessions,
             softlocks: &self.softlocks,
+            source_locks: &self.source_locks,
             qs_read,
             sid,
             async_tx: self.async_tx.clone(),
@@ -315,6 +329,7 @@ impl IdmServer {
             cred_update_sessions: self.cred_update_sessions.write(),
             qs_write,
             sid,
+            audit_tx: self.audit_tx.clone(),
             crypto_policy: &self.crypto_policy,
             webauthn: &self.webauthn,
             pw_badlist_cache: self.pw_badlist_cache.write(),
@@ -349,23 +364,19 @@ impl IdmServer {
 }
 
 impl IdmServerAudit {
+    /// Remove all queued audit events. Most operations are audited, so tests use this to
+    /// check for the events they expect.
     #[cfg(test)]
-    pub(crate) fn check_is_empty_or_panic(&mut self) {
-        use tokio::sync::mpsc::error::TryRecvError;
-
-        match self.audit_rx.try_recv() {
-            Err(TryRecvError::Empty) => {}
-            Err(TryRecvError::Disconnected) => {
-                panic!("Task queue disconnected");
-            }
-            Ok(m) => {
-                trace!(?m);
-                panic!("Task queue not empty");
-            }
+    pub(crate) fn drain(&mut self) -> Vec<AuditEvent> {
+        let mut events = Vec::new();
+        while let Ok(event) = self.audit_rx.try_recv() {
+            trace!(?event);
+            events.push(event);
         }
+        events
     }
 
-    pub fn audit_rx(&mut self) -> &mut Receiver<AuditEvent> {
+    pub fn audit_rx(&mut self) -> &mut mpsc::Receiver<AuditEvent> {
         &mut self.audit_rx
     }
 }
@@ -625,9 +636,24 @@ pub trait IdmServerTransaction<'a> {
             // We are past the grace window. Enforce session presence.
             // We enforce both sessions are present in case of inconsistency
             // that may occur with replication.
+            //
+            // A session from a token exchange has the oauth2 session it was exchanged from
+            // as its parent, so walk up until we reach the uat session. If any session in
+            // that chain was revoked, this session is no longer valid.
             let oauth2_session_valid = entry
                 .get_ava_as_oauth2session_map("oauth2_session")
-                .map(|map| map.get(&session_id).is_some())
+                .map(|map| {
+                    let mut current = session_id;
+                    // Bounded by the number of sessions, so a malformed chain can't loop.
+                    for _ in 0..map.len() {
+                        match map.get(&current) {
+                            Some(session) if session.parent == parent_session_id => return true,
+                            Some(session) => current = session.parent,
+                            None => return false,
+                        }
+                    }
+                    false
+                })
                 .unwrap_or(false);
             let uat_session_valid = entry
                 .get_ava_as_session_map("user_auth_token_session")
@@ -647,6 +673,54 @@ pub trait IdmServerTransaction<'a> {
         Ok(Some(entry))
     }
 
+    /// Check a client credentials session on the service account it was issued for. These
+    /// have no user session, so only the oauth2 session itself must be present.
+    fn check_oauth2_client_session_valid(
+        &mut self,
+        rs_uuid: Uuid,
+        uuid: Uuid,
+        session_id: Uuid,
+        iat: i64,
+        ct: Duration,
+    ) -> Result<Option<Arc<Entry<EntrySealed, EntryCommitted>>>, OperationError> {
+        let entry = self.get_qs_txn().internal_search_uuid(uuid).map_err(|e| {
+            admin_error!(?e, "check_oauth2_client_session_valid failed");
+            e
+        })?;
+
+        let within_valid_window = Account::check_within_valid_time(
+            ct,
+            entry.get_ava_single_datetime("account_valid_from").as_ref(),
+            entry.get_ava_single_datetime("account_expire").as_ref(),
+        );
+
+        if !within_valid_window {
+            security_info!("Account has expired or is not yet valid, not allowing to proceed");
+            return Ok(None);
+        }
+
+        if ct >= Duration::from_secs(iat as u64) + GRACE_WINDOW {
+            let oauth2_session_valid = entry
+                .get_ava_as_oauth2session_map("oauth2_session")
+                .and_then(|map| map.get(&session_id))
+                .map(|session| session.rs_uuid == rs_uuid)
+                .unwrap_or(false);
+
+            if oauth2_session_valid {
+                security_info!("A valid session value exists for this token");
+            } else {
+                security_info!(
+                    "The token grace window has passed and no session exists. Assuming invalid."
+                );
+                return Ok(None);
+            }
+        } else {
+            security_info!("The token grace window is in effect. Assuming valid.");
+        };
+
+        Ok(Some(entry))
+    }
+
     /// For any event/operation to proceed, we need to attach an identity to the
     /// event for security and access processing. When that event is externally
     /// triggered via one of our various api layers, we process some type of
@@ -941,6 +1015,19 @@ impl<'a> IdmServerAuthTransaction<'a> {
                 // Allocate a session id, based on current time.
                 let sessionid = uuid_from_duration(ct, self.sid);
 
+                // Refuse addresses that have failed too often before doing any work for them.
+                if self.source_locks.is_locked(&source, ct) {
+                    security_info!(
+                        username = %init.username,
+                        ?source,
+                        "Refusing authentication from locked source",
+                    );
+                    return Ok(AuthResult {
+                        sessionid,
+                        state: AuthState::Denied(SOURCE_LOCKED_REASON.to_string()),
+                    });
+                }
+
                 // Begin the auth procedure!
                 // Start a read
                 //
@@ -956,7 +1043,14 @@ impl<'a> IdmServerAuthTransaction<'a> {
                 //
                 // Check anything needed? Get the current auth-session-id from request
                 // because it associates to the nonce's etc which were all cached.
-                let euuid = self.qs_read.name_to_uuid(init.username.as_str())?;
+                let euuid = self
+                    .qs_read
+                    .name_to_uuid(init.username.as_str())
+                    .map_err(|e| {
+                        // Guessing account names counts against the source too.
+                        self.source_locks.record_failure(&source, ct);
+                        e
+                    })?;
 
                 // Get the first / single entry we expect here ....
                 let entry = self.qs_read.internal_search_uuid(euuid)?;
@@ -1120,7 +1214,9 @@ impl<'a> IdmServerAuthTransaction<'a> {
                     true
                 };
 
-                if is_valid {
+                let source_locked = self.source_locks.is_locked(&source, ct);
+
+                if is_valid && !source_locked {
                     // Process the credentials here as required.
                     // Basically throw them at the auth_session and see what
                     // falls out.
@@ -1143,9 +1239,12 @@ impl<'a> IdmServerAuthTransaction<'a> {
                                 if let Some(ref mut slock) = maybe_slock {
                                     slock.record_failure(ct);
                                 }
+                                self.source_locks.record_failure(&source, ct);
                             };
                             aus
                         })
+                } else if source_locked {
+                    auth_session.end_session(SOURCE_LOCKED_REASON)
                 } else {
                     // Fail the session
                     auth_session.end_session("Account is temporarily locked")
@@ -1250,6 +1349,7 @@ impl<'a> IdmServerAuthTransaction<'a> {
                     session_id: uat.session_id,
                     spn,
                     effective_session: LdapSession::UserAuthToken(uat),
+                    bind_time: ct,
                 }))
             }
             Token::ApiToken(apit, entry) => {
@@ -1261,6 +1361,7 @@ impl<'a> IdmServerAuthTransaction<'a> {
                     session_id: apit.token_id,
                     spn,
                     effective_session: LdapSession::ApiToken(apit),
+                    bind_time: ct,
                 }))
             }
         }
@@ -1298,6 +1399,7 @@ impl<'a> IdmServerAuthTransaction<'a> {
                 session_id,
                 spn: account.spn,
                 effective_session: LdapSession::UnixBind(UUID_ANONYMOUS),
+                bind_time: ct,
             }))
         } else {
             let account =
@@ -1364,6 +1466,7 @@ impl<'a> IdmServerAuthTransaction<'a> {
                             spn: account.spn,
                             session_id,
                             effective_session: LdapSession::UnixBind(account.uuid),
+                            bind_time: ct,
                         }))
                     } else {
                         // PW failure, update softlock.
@@ -1494,6 +1597,46 @@ impl<'a> IdmServerProxyReadTransaction<'a> {
 
         account.to_backupcodesview()
     }
+
+    /// Count how account passwords are hashed, so that administrators can see how many
+    /// accounts are still using a legacy kdf. Only system admins may do this.
+    pub fn password_kdf_report(
+        &mut self,
+        ident: &Identity,
+    ) -> Result<PasswordKdfReport, OperationError> {
+        if !ident.is_memberof(UUID_SYSTEM_ADMINS) {
+            security_info!(%ident, "Identity is not permitted to view the password kdf report");
+            return Err(OperationError::AccessDenied);
+        }
+
+        let entries = self.qs_read.internal_search(filter!(f_and!([
+            f_eq("class", PVCLASS_ACCOUNT.clone()),
+            f_or!([f_pres("primary_credential"), f_pres("unix_password")])
+        ])))?;
+
+        let mut report = PasswordKdfReport::default();
+        for entry in entries.iter() {
+            let passwords: Vec<_> = ["primary_credential", "unix_password"]
+                .iter()
+                .filter_map(|attr| entry.get_ava_single_credential(attr))
+                .filter_map(|cred| cred.password_ref().ok())
+                .collect();
+
+            if passwords.is_empty() {
+                continue;
+            }
+
+            report.accounts += 1;
+            if passwords.iter().any(|pw| pw.is_legacy()) {
+                report.legacy_accounts += 1;
+            }
+            for pw in passwords {
+                *report.kdfs.entry(pw.kdf_name().to_string()).or_default() += 1;
+            }
+        }
+
+        Ok(report)
+    }
 }
 
 impl<'a> IdmServerTransaction<'a> for IdmServerProxyWriteTransaction<'a> {
@@ -1513,6 +1656,37 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         self.crypto_policy
     }
 
+    /// Unix binds search as anonymous, but writes must be performed as the bound account
+    /// so that access controls apply to it. The write identity is derived from a read-write
+    /// uat for the account, so the account validity and session scope are checked by the
+    /// same path as any other authenticated session. As with any other session, write
+    /// privileges only last for the privilege expiry of the account from the time of the bind.
+    #[instrument(level = "debug", skip_all)]
+    pub(crate) fn validate_ldap_write_session(
+        &mut self,
+        lbt: &LdapBoundToken,
+        ct: Duration,
+    ) -> Result<Identity, OperationError> {
+        match &lbt.effective_session {
+            LdapSession::UnixBind(uuid) if *uuid != UUID_ANONYMOUS => {
+                let entry = self.qs_write.internal_search_uuid(*uuid).map_err(|e| {
+                    admin_error!("Failed to validate ldap session -> {:?}", e);
+                    e
+                })?;
+
+                // The groups are needed to resolve the account policy privilege expiry.
+                let account = Account::try_from_entry_rw(entry.as_ref(), &mut self.qs_write)?;
+
+                let uat = account
+                    .to_unix_bind_userauthtoken(lbt.session_id, lbt.bind_time, ct)
+                    .ok_or(OperationError::InvalidState)?;
+
+                self.process_uat_to_identity(&uat, ct)
+            }
+            session => self.validate_ldap_session(session, ct),
+        }
+    }
+
     pub fn get_origin(&self) -> &Url {
         #[allow(clippy::unwrap_used)]
         self.webauthn.get_allowed_origins().get(0).unwrap()
@@ -1522,15 +1696,16 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         &mut self,
         cleartext: &str,
         related_inputs: &[&str],
+        min_length: usize,
     ) -> Result<(), OperationError> {
         // password strength and badlisting is always global, rather than per-pw-policy.
         // pw-policy as check on the account is about requirements for mfa for example.
         //
 
-        // is the password at least 10 char?
-        if cleartext.len() < PW_MIN_LENGTH {
+        // is the password at least the minimum length?
+        if cleartext.len() < min_length {
             return Err(OperationError::PasswordQuality(vec![
-                PasswordFeedback::TooShort(PW_MIN_LENGTH),
+                PasswordFeedback::TooShort(min_length),
             ]));
         }
 
@@ -1538,7 +1713,7 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
 
         let entropy = zxcvbn::zxcvbn(cleartext, related_inputs).map_err(|e| {
             admin_error!("zxcvbn check failure (password empty?) {:?}", e);
-            OperationError::PasswordQuality(vec![PasswordFeedback::TooShort(PW_MIN_LENGTH)])
+            OperationError::PasswordQuality(vec![PasswordFeedback::TooShort(min_length)])
         })?;
 
         // Unix PW's are a single factor, so we enforce good pws
@@ -1649,11 +1824,15 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         // Check the password quality.
         // Ask if tis all good - this step checks pwpolicy and such
 
-        self.check_password_quality(pce.cleartext.as_str(), account.related_inputs().as_slice())
-            .map_err(|e| {
-                request_error!(err = ?e, "check_password_quality");
-                e
-            })?;
+        self.check_password_quality(
+            pce.cleartext.as_str(),
+            account.related_inputs().as_slice(),
+            account.account_policy.pw_min_length(),
+        )
+        .map_err(|e| {
+            request_error!(err = ?e, "check_password_quality");
+            e
+        })?;
 
         // And actually really apply it now.
         self.qs_write.modify_apply(mp).map_err(|e| {
@@ -1669,12 +1848,17 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         pce: &UnixPasswordChangeEvent,
     ) -> Result<(), OperationError> {
         // Get the account
-        let account = self
+        let (account, account_policy) = self
             .qs_write
             .internal_search_uuid(pce.target)
             .and_then(|account_entry| {
                 // Assert the account is unix and valid.
-                UnixUserAccount::try_from_entry_rw(&account_entry, &mut self.qs_write)
+                let account =
+                    UnixUserAccount::try_from_entry_rw(&account_entry, &mut self.qs_write)?;
+                // The unix password is held to the same policy as the primary password.
+                let account_policy =
+                    Account::try_from_entry_rw(&account_entry, &mut self.qs_write)?.account_policy;
+                Ok((account, account_policy))
             })
             .map_err(|e| {
                 admin_error!("Failed to start set unix account password {:?}", e);
@@ -1725,11 +1909,15 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         // If we got here, then pre-apply succeeded, and that means access control
         // passed. Now we can do the extra checks.
 
-        self.check_password_quality(pce.cleartext.as_str(), account.related_inputs().as_slice())
-            .map_err(|e| {
-                admin_error!(?e, "Failed to checked password quality");
-                e
-            })?;
+        self.check_password_quality(
+            pce.cleartext.as_str(),
+            account.related_inputs().as_slice(),
+            account_policy.pw_min_length(),
+        )
+        .map_err(|e| {
+            admin_error!(?e, "Failed to checked password quality");
+            e
+        })?;
 
         // And actually really apply it now.
         self.qs_write.modify_apply(mp).map_err(|e| {
@@ -1959,7 +2147,15 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         info!(session_id = %asr.session_id, "Persisting auth session");
 
         // modify the account to put the session onto it.
-        let modlist = ModifyList::new_append("user_auth_token_session", session);
+        let mut modlist = ModifyList::new_append("user_auth_token_session", session);
+
+        // Remembering where the session came from is part of the same write.
+        let new_source = match &asr.source {
+            Source::Https(ip_addr) | Source::Ldaps(ip_addr) => {
+                self.process_auth_source_ip(asr, ip_addr.to_string(), &mut modlist)?
+            }
+            Source::Internal => None,
+        };
 
         self.qs_write
             .internal_modify(
@@ -1969,10 +2165,90 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
             .map_err(|e| {
                 admin_error!("Failed to persist user auth token {:?}", e);
                 e
-            })
+            })?;
+
+        self.qs_write
+            .audit_event(AuditEvent::AuthenticationSucceeded {
+                source: asr.source.clone().into(),
+                uuid: asr.target_uuid,
+                session_id: asr.session_id,
+                time: asr.issued_at,
+            });
+
+        match new_source {
+            Some((entry, source_ip)) => {
+                security_info!(uuid = %asr.target_uuid, %source_ip, "Session created from a new address");
+                queue_notification(&mut self.qs_write, &entry, |display_name| {
+                    Notification::NewSourceSession {
+                        display_name,
+                        source_ip,
+                        issued_at: asr.issued_at,
+                    }
+                })
+            }
+            None => Ok(()),
+        }
         // Done!
     }
 
+    /// Remember the address a session was created from. Each address is stored with when it was
+    /// last used, which is only refreshed once it is [AUTH_SOURCE_IP_REFRESH] seconds old, and the
+    /// least recently used address is forgotten once [AUTH_SOURCE_IP_MAX] are known. Returns the
+    /// entry if the person should be notified, because their account hasn't been used from this
+    /// address recently.
+    fn process_auth_source_ip(
+        &mut self,
+        asr: &AuthSessionRecord,
+        source_ip: String,
+        modlist: &mut ModifyList<ModifyInvalid>,
+    ) -> Result<Option<(Arc<EntrySealedCommitted>, String)>, OperationError> {
+        let entry = self.qs_write.internal_search_uuid(asr.target_uuid)?;
+        let last_used = asr.issued_at.unix_timestamp();
+
+        let known: Vec<(&str, &str, i64)> = entry
+            .get_ava_as_iutf8("auth_source_ip")
+            .map(|known| {
+                known
+                    .iter()
+                    .map(|value| {
+                        // Addresses remembered without a time are the least recently used.
+                        let (ip, used) = value
+                            .split_once(' ')
+                            .and_then(|(ip, used)| used.parse::<i64>().ok().map(|used| (ip, used)))
+                            .unwrap_or((value.as_str(), 0));
+                        (value.as_str(), ip, used)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let remember = Value::new_iutf8(&format!("{} {}", source_ip, last_used));
+
+        if let Some((value, _, used)) = known.iter().find(|(_, ip, _)| *ip == source_ip) {
+            if last_used.saturating_sub(*used) >= AUTH_SOURCE_IP_REFRESH as i64 {
+                modlist.push_mod(m_remove("auth_source_ip", &PartialValue::new_iutf8(value)));
+                modlist.push_mod(m_pres("auth_source_ip", &remember));
+            }
+            return Ok(None);
+        }
+
+        if known.len() >= AUTH_SOURCE_IP_MAX {
+            let mut by_use: Vec<_> = known.iter().collect();
+            by_use.sort_unstable_by_key(|(_, _, used)| *used);
+            for (value, _, _) in by_use.iter().take(known.len() + 1 - AUTH_SOURCE_IP_MAX) {
+                modlist.push_mod(m_remove("auth_source_ip", &PartialValue::new_iutf8(value)));
+            }
+        }
+        modlist.push_mod(m_pres("auth_source_ip", &remember));
+
+        // The first address an account is used from isn't news to anyone.
+        if known.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some((entry, source_ip)))
+        }
+    }
+
     pub fn process_delayedaction(
         &mut self,
         da: DelayedAction,
@@ -2047,7 +2323,13 @@ impl<'a> IdmServerProxyWriteTransaction<'a> {
         self.pw_badlist_cache.commit();
         self.cred_update_sessions.commit();
         trace!("cred_update_session.commit");
-        self.qs_write.commit()
+        let audit_events = self.qs_write.take_audit_events();
+        self.qs_write.commit()?;
+        // Only once the changes are durable do we report them.
+        audit_events
+            .into_iter()
+            .for_each(|event| queue_audit_event(&self.audit_tx, event));
+        Ok(())
     }
 
     fn reload_password_badlist(&mut self) -> Result<(), OperationError> {
@@ -2083,6 +2365,7 @@ mod tests {
         UnixGroupTokenEvent, UnixPasswordChangeEvent, UnixUserAuthEvent, UnixUserTokenEvent,
     };
     use crate::idm::server::{IdmServer, IdmServerTransaction};
+    use crate::idm::sourcelock::SOURCE_LOCKED_REASON;
     use crate::idm::AuthState;
     use crate::modify::{Modify, ModifyList};
     use crate::prelude::*;
@@ -2750,6 +3033,51 @@ mod tests {
         assert!(idms_auth.commit().is_ok());
     }
 
+    #[idm_test]
+    async fn test_idm_unix_password_account_policy(
+        idms: &IdmServer,
+        _idms_delayed: &IdmServerDelayed,
+    ) {
+        let mut idms_prox_write = idms.proxy_write(duration_from_epoch_now()).await;
+        // make the admin a valid posix account, in a group with a strict policy.
+        let me_posix = unsafe {
+            ModifyEvent::new_internal_invalid(
+                filter!(f_eq("name", PartialValue::new_iname("admin"))),
+                ModifyList::new_list(vec![
+                    Modify::Present(AttrString::from("class"), Value::new_class("posixaccount")),
+                    Modify::Present(AttrString::from("gidnumber"), Value::new_uint32(2001)),
+                ]),
+            )
+        };
+        assert!(idms_prox_write.qs_write.modify(&me_posix).is_ok());
+
+        let e: Entry<EntryInit, EntryNew> = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("group")),
+            ("class", Value::new_class("account_policy")),
+            ("name", Value::new_iname("strict_policy")),
+            ("member", Value::Refer(UUID_ADMIN)),
+            ("auth_password_minimum_length", Value::new_uint32(80))
+        );
+        assert!(idms_prox_write.qs_write.internal_create(vec![e]).is_ok());
+
+        // The password is good, but not long enough for the policy.
+        let pce = UnixPasswordChangeEvent::new_internal(UUID_ADMIN, TEST_PASSWORD);
+        let err = idms_prox_write.set_unix_account_password(&pce).unwrap_err();
+        assert!(matches!(
+            err,
+            OperationError::PasswordQuality(feedback)
+                if matches!(feedback.as_slice(), [PasswordFeedback::TooShort(80)])
+        ));
+
+        let test_pw = format!(
+            "{TEST_PASSWORD}fo3EitierohF9AelaNgiem0Ei6vup4equo1Oogeevaetehah8Tobeengae3Ci0"
+        );
+        let pce = UnixPasswordChangeEvent::new_internal(UUID_ADMIN, &test_pw);
+        assert!(idms_prox_write.set_unix_account_password(&pce).is_ok());
+        assert!(idms_prox_write.commit().is_ok());
+    }
+
     #[idm_test]
     async fn test_idm_simple_password_upgrade(
         idms: &IdmServer,
@@ -2776,6 +3104,20 @@ mod tests {
         }
         // Still empty
         idms_delayed.check_is_empty_or_panic();
+
+        // The imported hash shows as legacy.
+        let report = {
+            let mut idms_prox_read = idms.proxy_read().await;
+            let admin = idms_prox_read
+                .qs_read
+                .internal_search_uuid(UUID_ADMIN)
+                .expect("failed");
+            let admin = Identity::from_impersonate_entry_readonly(admin);
+            idms_prox_read.password_kdf_report(&admin).expect("failed")
+        };
+        assert!(report.legacy_accounts == 1);
+        assert!(report.kdfs.get("ssha512") == Some(&1));
+
         // Do an auth, this will trigger the action to send.
         check_admin_password(idms, "password").await;
 
@@ -2789,6 +3131,20 @@ mod tests {
         assert!(matches!(da, DelayedAction::AuthSessionRecord(_)));
         assert!(Ok(true) == r);
 
+        // And is now argon2id.
+        let report = {
+            let mut idms_prox_read = idms.proxy_read().await;
+            let admin = idms_prox_read
+                .qs_read
+                .internal_search_uuid(UUID_ADMIN)
+                .expect("failed");
+            let admin = Identity::from_impersonate_entry_readonly(admin);
+            idms_prox_read.password_kdf_report(&admin).expect("failed")
+        };
+        assert!(report.legacy_accounts == 0);
+        assert!(report.kdfs.get("ssha512").is_none());
+        assert!(report.kdfs.get("argon2id") >= Some(&1));
+
         // Check the admin pw still matches
         check_admin_password(idms, "password").await;
         // Clear the next auth session record
@@ -2799,6 +3155,70 @@ mod tests {
         idms_delayed.check_is_empty_or_panic();
     }
 
+    #[idm_test]
+    async fn test_idm_imported_argon2id_password_upgrade(
+        idms: &IdmServer,
+        idms_delayed: &mut IdmServerDelayed,
+    ) {
+        idms_delayed.check_is_empty_or_panic();
+        // Even though this is argon2id, the parameters were chosen elsewhere.
+        {
+            let mut idms_prox_write = idms.proxy_write(duration_from_epoch_now()).await;
+            let me_inv_m = unsafe {
+                ModifyEvent::new_internal_invalid(
+                        filter!(f_eq("name", PartialValue::new_iname("admin"))),
+                        ModifyList::new_list(vec![Modify::Present(
+                            AttrString::from("password_import"),
+                            Value::from("$argon2id$v=19$m=65536,t=2,p=1$IyTQMsvzB2JHDiWx8fq7Ew$VhYOA7AL0kbRXI5g2kOyyp8St1epkNj7WZyUY4pAIQQ")
+                        )]),
+                    )
+            };
+            assert!(idms_prox_write.qs_write.modify(&me_inv_m).is_ok());
+            assert!(idms_prox_write.commit().is_ok());
+        }
+        idms_delayed.check_is_empty_or_panic();
+
+        let report = {
+            let mut idms_prox_read = idms.proxy_read().await;
+            let admin = idms_prox_read
+                .qs_read
+                .internal_search_uuid(UUID_ADMIN)
+                .expect("failed");
+            let admin = Identity::from_impersonate_entry_readonly(admin);
+            idms_prox_read.password_kdf_report(&admin).expect("failed")
+        };
+        assert!(report.legacy_accounts == 1);
+        assert!(report.kdfs.get("argon2_imported") == Some(&1));
+
+        // The imported hash verifies, and is upgraded.
+        check_admin_password(idms, "password").await;
+        let da = idms_delayed.try_recv().expect("invalid");
+        assert!(matches!(da, DelayedAction::PwUpgrade(_)));
+        let r = idms.delayed_action(duration_from_epoch_now(), da).await;
+        let da = idms_delayed.try_recv().expect("invalid");
+        assert!(matches!(da, DelayedAction::AuthSessionRecord(_)));
+        assert!(Ok(true) == r);
+
+        let report = {
+            let mut idms_prox_read = idms.proxy_read().await;
+            let admin = idms_prox_read
+                .qs_read
+                .internal_search_uuid(UUID_ADMIN)
+                .expect("failed");
+            let admin = Identity::from_impersonate_entry_readonly(admin);
+            idms_prox_read.password_kdf_report(&admin).expect("failed")
+        };
+        assert!(report.legacy_accounts == 0);
+        assert!(report.kdfs.get("argon2_imported").is_none());
+        assert!(report.kdfs.get("argon2id") >= Some(&1));
+
+        // The upgraded password still works, and isn't upgraded again.
+        check_admin_password(idms, "password").await;
+        let da = idms_delayed.try_recv().expect("invalid");
+        assert!(matches!(da, DelayedAction::AuthSessionRecord(_)));
+        idms_delayed.check_is_empty_or_panic();
+    }
+
     #[idm_test]
     async fn test_idm_unix_password_upgrade(idms: &IdmServer, idms_delayed: &mut IdmServerDelayed) {
         // Assert the delayed action queue is empty
@@ -3214,6 +3634,67 @@ mod tests {
         // Tested in the softlock state machine.
     }
 
+    #[idm_test]
+    async fn test_idm_source_locking(idms: &IdmServer, _idms_delayed: &mut IdmServerDelayed) {
+        init_admin_w_password(idms, TEST_PASSWORD)
+            .await
+            .expect("Failed to setup admin account");
+
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+        let attacker = Source::Https("192.0.2.1".parse().expect("Invalid address"));
+        let other = Source::Https("198.51.100.1".parse().expect("Invalid address"));
+
+        // Spraying account names from one address counts against it, even though no
+        // account is ever softlocked.
+        let mut idms_auth = idms.auth().await;
+        for i in 0..10 {
+            let init = AuthEvent::named_init(&format!("no_such_account_{}", i));
+            assert!(idms_auth.auth(&init, ct, attacker.clone()).await.is_err());
+        }
+
+        let admin_init = AuthEvent::named_init("admin");
+        let r1 = idms_auth
+            .auth(&admin_init, ct, attacker.clone())
+            .await
+            .expect("Failed to init auth");
+        match r1.state {
+            AuthState::Denied(reason) => assert!(reason == SOURCE_LOCKED_REASON),
+            _ => panic!("Source was not locked"),
+        }
+
+        // Other sources may still authenticate.
+        let r1 = idms_auth
+            .auth(&admin_init, ct, other.clone())
+            .await
+            .expect("Failed to init auth");
+        assert!(matches!(r1.state, AuthState::Choose(_)));
+
+        // A wrong password from the other address counts against it too.
+        let admin_begin = AuthEvent::begin_mech(r1.sessionid, AuthMech::Password);
+        idms_auth
+            .auth(&admin_begin, ct, other.clone())
+            .await
+            .expect("Failed to begin auth");
+        let admin_cred = AuthEvent::cred_step_password(r1.sessionid, TEST_PASSWORD_INC);
+        let r2 = idms_auth
+            .auth(&admin_cred, ct, other.clone())
+            .await
+            .expect("Failed to step auth");
+        assert!(matches!(r2.state, AuthState::Denied(_)));
+        assert!(!idms.source_locks().is_locked(&other, ct));
+        idms_auth.commit().expect("Must not fail");
+
+        // Once the delay passes the attacker may try again.
+        let ct = ct + Duration::from_secs(2);
+        let mut idms_auth = idms.auth().await;
+        let r1 = idms_auth
+            .auth(&admin_init, ct, attacker)
+            .await
+            .expect("Failed to init auth");
+        assert!(matches!(r1.state, AuthState::Choose(_)));
+        idms_auth.commit().expect("Must not fail");
+    }
+
     #[idm_test(audit)]
     async fn test_idm_account_softlocking_interleaved(
         idms: &IdmServer,
@@ -3436,6 +3917,7 @@ mod tests {
             issued_at: OffsetDateTime::UNIX_EPOCH + ct,
             issued_by: IdentityId::User(UUID_ADMIN),
             scope: SessionScope::ReadOnly,
+            source: Source::Internal,
         });
         // Persist it.
         let r = idms.delayed_action(ct, da).await;
@@ -3471,6 +3953,7 @@ mod tests {
             issued_at: OffsetDateTime::UNIX_EPOCH + ct,
             issued_by: IdentityId::User(UUID_ADMIN),
             scope: SessionScope::ReadOnly,
+            source: Source::Internal,
         });
         // Persist it.
         let r = idms.delayed_action(expiry_a, da).await;
@@ -3496,6 +3979,101 @@ mod tests {
         assert!(session_id_a != session_id_b);
     }
 
+    #[idm_test]
+    async fn test_idm_auth_session_new_source_notification(
+        idms: &IdmServer,
+        _idms_delayed: &mut IdmServerDelayed,
+    ) {
+        let ct = Duration::from_secs(TEST_CURRENT_TIME);
+
+        let cred_id = init_admin_w_password(idms, TEST_PASSWORD)
+            .await
+            .expect("Failed to setup admin account");
+
+        let session_at = |source_ip: &str, ct: Duration| {
+            DelayedAction::AuthSessionRecord(AuthSessionRecord {
+                target_uuid: UUID_ADMIN,
+                session_id: Uuid::new_v4(),
+                cred_id,
+                label: "Test Session".to_string(),
+                expiry: Some(OffsetDateTime::UNIX_EPOCH + ct + Duration::from_secs(3600)),
+                issued_at: OffsetDateTime::UNIX_EPOCH + ct,
+                issued_by: IdentityId::User(UUID_ADMIN),
+                scope: SessionScope::ReadOnly,
+                source: Source::Https(source_ip.parse().expect("Invalid address")),
+            })
+        };
+        let session_from = |source_ip: &str| session_at(source_ip, ct);
+
+        // The first address, and any repeat of it, are not notified.
+        for source_ip in ["192.0.2.1", "192.0.2.1"] {
+            let r = idms.delayed_action(ct, session_from(source_ip)).await;
+            assert!(Ok(true) == r);
+        }
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        assert!(idms_prox_write
+            .pending_messages(false, ct)
+            .expect("Failed to list pending messages")
+            .is_empty());
+        drop(idms_prox_write);
+
+        let r = idms.delayed_action(ct, session_from("198.51.100.1")).await;
+        assert!(Ok(true) == r);
+
+        let mut idms_prox_write = idms.proxy_write(ct).await;
+        let pending = idms_prox_write
+            .pending_messages(false, ct)
+            .expect("Failed to list pending messages");
+        assert!(pending.len() == 1);
+        assert!(pending[0].recipient == Some(UUID_ADMIN));
+        assert!(pending[0].message.body.contains("198.51.100.1"));
+
+        let admin = idms_prox_write
+            .qs_write
+            .internal_search_uuid(UUID_ADMIN)
+            .expect("failed");
+        assert!(
+            admin
+                .get_ava_as_iutf8("auth_source_ip")
+                .map(|known| known.len())
+                == Some(2)
+        );
+        drop(idms_prox_write);
+
+        // Using an address again after a while refreshes it, so that once the set is full the
+        // least recently used address is the one forgotten.
+        let later = ct + Duration::from_secs(AUTH_SOURCE_IP_REFRESH * 2);
+        let r = idms
+            .delayed_action(later, session_at("192.0.2.1", later))
+            .await;
+        assert!(Ok(true) == r);
+
+        for i in 0..AUTH_SOURCE_IP_MAX - 1 {
+            let source_ip = format!("203.0.113.{}", i);
+            let r = idms
+                .delayed_action(later, session_at(&source_ip, later))
+                .await;
+            assert!(Ok(true) == r);
+        }
+
+        let mut idms_prox_write = idms.proxy_write(later).await;
+        let admin = idms_prox_write
+            .qs_write
+            .internal_search_uuid(UUID_ADMIN)
+            .expect("failed");
+        let known: Vec<_> = admin
+            .get_ava_as_iutf8("auth_source_ip")
+            .expect("No known addresses")
+            .iter()
+            .filter_map(|value| value.split_once(' ').map(|(ip, _)| ip.to_string()))
+            .collect();
+        assert!(known.len() == AUTH_SOURCE_IP_MAX);
+        assert!(known.iter().any(|ip| ip == "192.0.2.1"));
+        assert!(!known.iter().any(|ip| ip == "198.51.100.1"));
+        assert!(known.iter().any(|ip| ip == "203.0.113.30"));
+    }
+
     #[idm_test]
     async fn test_idm_account_session_validation(
         idms: &IdmServer,
@@ -457,6 +457,14 @@ mod tests {
 
         trace!(?api_token);
 
+        // The owner of the account is told a token was issued.
+        let pending = idms_prox_write
+            .pending_messages(false, ct)
+            .expect("Failed to list pending messages");
+        assert!(pending.len() == 1);
+        assert!(pending[0].recipient == Some(testaccount_uuid));
+        assert!(pending[0].message.body.contains("TestToken"));
+
         // Deserialise it.
         let apitoken_unverified =
             JwsUnverified::from_str(&api_token).expect("Failed to parse apitoken");
@@ -0,0 +1,569 @@
+//! Source locks slow down and then ban client addresses that repeatedly fail to authenticate.
+//! Credential softlocks protect a single account, but an attacker that tries one password
+//! against many accounts never triggers them. Failures are counted for each address, and for the
+//! subnet that the address is in, so that spreading an attack over a range of addresses doesn't
+//! avoid the limit either.
+//!
+//! Once a source exceeds its threshold each further failure locks it for twice as long as the
+//! previous one, up to a maximum delay. If failures continue the source is banned. Like
+//! softlocks, this state is in memory and per server.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use kanidm_proto::internal::SourceLockStatus;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::prelude::*;
+
+/// The reason given when authentication is refused because of the source it came from.
+pub(crate) const SOURCE_LOCKED_REASON: &str =
+    "Too many failed authentication attempts from this address, try again later";
+
+/// How often expired source locks are removed.
+const SOURCE_LOCK_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// An address range in cidr notation, such as `192.0.2.0/24`. A plain address is a network of
+/// only that address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    fn new(addr: IpAddr, prefix: u8) -> Self {
+        IpNetwork {
+            addr: mask(addr, prefix),
+            prefix,
+        }
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        addr.is_ipv4() == self.addr.is_ipv4() && mask(addr, self.prefix) == self.addr
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = IpAddr::from_str(addr).map_err(|_| format!("invalid address '{}'", s))?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max_prefix)
+                .ok_or_else(|| format!("invalid prefix length in '{}'", s))?,
+            None => max_prefix,
+        };
+        Ok(IpNetwork::new(addr, prefix))
+    }
+}
+
+impl TryFrom<String> for IpNetwork {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        IpNetwork::from_str(&value)
+    }
+}
+
+impl From<IpNetwork> for String {
+    fn from(value: IpNetwork) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+fn mask(addr: IpAddr, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let bits = u32::from(v4);
+            let masked = match prefix.min(32) {
+                0 => 0,
+                p => bits & (u32::MAX << (32 - u32::from(p))),
+            };
+            IpAddr::V4(Ipv4Addr::from(masked))
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::from(v6);
+            let masked = match prefix.min(128) {
+                0 => 0,
+                p => bits & (u128::MAX << (128 - u32::from(p))),
+            };
+            IpAddr::V6(Ipv6Addr::from(masked))
+        }
+    }
+}
+
+fn default_address_threshold() -> u32 {
+    10
+}
+
+fn default_subnet_threshold() -> u32 {
+    50
+}
+
+fn default_ipv4_subnet_prefix() -> u8 {
+    24
+}
+
+fn default_ipv6_subnet_prefix() -> u8 {
+    64
+}
+
+fn default_max_delay() -> u64 {
+    300
+}
+
+fn default_ban_after() -> u32 {
+    10
+}
+
+fn default_ban_duration() -> u64 {
+    3600
+}
+
+fn default_window() -> u64 {
+    3600
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceLockPolicy {
+    /// How many failures from one address are allowed before it is delayed.
+    #[serde(default = "default_address_threshold")]
+    pub address_threshold: u32,
+    /// How many failures from one subnet are allowed before it is delayed.
+    #[serde(default = "default_subnet_threshold")]
+    pub subnet_threshold: u32,
+    #[serde(default = "default_ipv4_subnet_prefix")]
+    pub ipv4_subnet_prefix: u8,
+    #[serde(default = "default_ipv6_subnet_prefix")]
+    pub ipv6_subnet_prefix: u8,
+    /// The longest a source is delayed for, in seconds, before it is banned.
+    #[serde(default = "default_max_delay")]
+    pub max_delay: u64,
+    /// How many failures past the threshold cause a source to be banned.
+    #[serde(default = "default_ban_after")]
+    pub ban_after: u32,
+    /// How long a ban lasts, in seconds.
+    #[serde(default = "default_ban_duration")]
+    pub ban_duration: u64,
+    /// How long, in seconds, without a failure before a source's failures are forgotten.
+    #[serde(default = "default_window")]
+    pub window: u64,
+    /// Networks that are never locked, such as a reverse proxy or a trusted office.
+    #[serde(default)]
+    pub allowlist: Vec<IpNetwork>,
+}
+
+impl Default for SourceLockPolicy {
+    fn default() -> Self {
+        SourceLockPolicy {
+            address_threshold: default_address_threshold(),
+            subnet_threshold: default_subnet_threshold(),
+            ipv4_subnet_prefix: default_ipv4_subnet_prefix(),
+            ipv6_subnet_prefix: default_ipv6_subnet_prefix(),
+            max_delay: default_max_delay(),
+            ban_after: default_ban_after(),
+            ban_duration: default_ban_duration(),
+            window: default_window(),
+            allowlist: Vec::new(),
+        }
+    }
+}
+
+impl SourceLockPolicy {
+    /// The sources that a failure from this address counts against, and their thresholds.
+    fn sources(&self, addr: IpAddr) -> [(IpNetwork, u32); 2] {
+        let (full, subnet) = if addr.is_ipv4() {
+            (32, self.ipv4_subnet_prefix)
+        } else {
+            (128, self.ipv6_subnet_prefix)
+        };
+        [
+            (IpNetwork::new(addr, full), self.address_threshold),
+            (IpNetwork::new(addr, subnet), self.subnet_threshold),
+        ]
+    }
+
+    fn is_allowed(&self, addr: IpAddr) -> bool {
+        self.allowlist.iter().any(|net| net.contains(addr))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SourceLockState {
+    failures: u32,
+    last_failure: Duration,
+    locked_until: Duration,
+    banned: bool,
+}
+
+impl SourceLockState {
+    fn is_locked(&self, ct: Duration) -> bool {
+        ct < self.locked_until
+    }
+
+    fn is_expired(&self, ct: Duration, window: Duration) -> bool {
+        !self.is_locked(ct) && ct >= self.last_failure + window
+    }
+}
+
+struct SourceLocksInner {
+    sources: HashMap<IpNetwork, SourceLockState>,
+    next_prune: Duration,
+}
+
+pub struct SourceLocks {
+    policy: SourceLockPolicy,
+    inner: Mutex<SourceLocksInner>,
+}
+
+impl SourceLocks {
+    pub fn new(policy: SourceLockPolicy) -> Self {
+        SourceLocks {
+            policy,
+            inner: Mutex::new(SourceLocksInner {
+                sources: HashMap::new(),
+                next_prune: Duration::ZERO,
+            }),
+        }
+    }
+
+    fn inner(&self) -> std::sync::MutexGuard<'_, SourceLocksInner> {
+        // The state is only counters, so it remains usable even if a holder panicked.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// If authentication from this source must be refused.
+    pub(crate) fn is_locked(&self, source: &Source, ct: Duration) -> bool {
+        let addr = match source {
+            Source::Internal => return false,
+            Source::Https(addr) | Source::Ldaps(addr) => *addr,
+        };
+
+        if self.policy.is_allowed(addr) {
+            return false;
+        }
+
+        let inner = self.inner();
+        self.policy.sources(addr).iter().any(|(net, _)| {
+            inner
+                .sources
+                .get(net)
+                .map(|state| state.is_locked(ct))
+                .unwrap_or(false)
+        })
+    }
+
+    /// Count an authentication failure against this source, locking it if it has failed too
+    /// often.
+    pub(crate) fn record_failure(&self, source: &Source, ct: Duration) {
+        let addr = match source {
+            Source::Internal => return,
+            Source::Https(addr) | Source::Ldaps(addr) => *addr,
+        };
+
+        if self.policy.is_allowed(addr) {
+            return;
+        }
+
+        let window = Duration::from_secs(self.policy.window);
+        let mut inner = self.inner();
+
+        if ct >= inner.next_prune {
+            inner
+                .sources
+                .retain(|_, state| !state.is_expired(ct, window));
+            inner.next_prune = ct + SOURCE_LOCK_PRUNE_INTERVAL;
+        }
+
+        for (net, threshold) in self.policy.sources(addr) {
+            let state = inner.sources.entry(net).or_insert(SourceLockState {
+                failures: 0,
+                last_failure: ct,
+                locked_until: Duration::ZERO,
+                banned: false,
+            });
+
+            if state.is_expired(ct, window) {
+                state.failures = 0;
+                state.banned = false;
+            }
+
+            state.failures = state.failures.saturating_add(1);
+            state.last_failure = ct;
+
+            if state.failures >= threshold.saturating_add(self.policy.ban_after) {
+                if !state.banned {
+                    security_info!(source = %net, failures = %state.failures, "Banning source after repeated authentication failures");
+                }
+                state.banned = true;
+                state.locked_until = ct + Duration::from_secs(self.policy.ban_duration);
+            } else if state.failures >= threshold {
+                // Double the delay for each failure past the threshold.
+                let exponent = state.failures - threshold;
+                let delay = 1u64
+                    .checked_shl(exponent)
+                    .unwrap_or(u64::MAX)
+                    .min(self.policy.max_delay);
+                security_info!(source = %net, failures = %state.failures, %delay, "Delaying source after repeated authentication failures");
+                state.locked_until = state.locked_until.max(ct + Duration::from_secs(delay));
+            }
+        }
+    }
+
+    /// The sources that have failed to authenticate recently, most failures first.
+    pub fn list(
+        &self,
+        ident: &Identity,
+        ct: Duration,
+    ) -> Result<Vec<SourceLockStatus>, OperationError> {
+        if !ident.is_memberof(UUID_SYSTEM_ADMINS) {
+            security_info!(%ident, "Identity is not permitted to view source locks");
+            return Err(OperationError::AccessDenied);
+        }
+
+        let window = Duration::from_secs(self.policy.window);
+        let inner = self.inner();
+        let mut status: Vec<_> = inner
+            .sources
+            .iter()
+            .filter(|(_, state)| !state.is_expired(ct, window))
+            .map(|(net, state)| SourceLockStatus {
+                source: net.to_string(),
+                failures: state.failures,
+                locked_until: if state.is_locked(ct) {
+                    Some(OffsetDateTime::UNIX_EPOCH + state.locked_until)
+                } else {
+                    None
+                },
+                banned: state.banned && state.is_locked(ct),
+            })
+            .collect();
+
+        status.sort_unstable_by(|a, b| {
+            b.failures
+                .cmp(&a.failures)
+                .then_with(|| a.source.cmp(&b.source))
+        });
+        Ok(status)
+    }
+
+    /// Forget the failures of a source, lifting any lock or ban on it.
+    pub fn clear(&self, ident: &Identity, source: &str) -> Result<(), OperationError> {
+        if !ident.is_memberof(UUID_SYSTEM_ADMINS) {
+            security_info!(%ident, "Identity is not permitted to clear source locks");
+            return Err(OperationError::AccessDenied);
+        }
+
+        let net = IpNetwork::from_str(source).map_err(|e| {
+            request_error!(%source, "Invalid source - {}", e);
+            OperationError::InvalidRequestState
+        })?;
+
+        match self.inner().sources.remove(&net) {
+            Some(_) => {
+                security_info!(%ident, source = %net, "Cleared source lock");
+                Ok(())
+            }
+            None => Err(OperationError::NoMatchingEntries),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{IpNetwork, SourceLockPolicy, SourceLocks};
+    use crate::prelude::*;
+
+    fn https(addr: &str) -> Source {
+        Source::Https(addr.parse().expect("Invalid address"))
+    }
+
+    #[test]
+    fn test_idm_source_lock_network() {
+        let net = IpNetwork::from_str("192.0.2.77/24").expect("Invalid network");
+        assert!(net.to_string() == "192.0.2.0/24");
+        assert!(net.contains("192.0.2.1".parse().unwrap()));
+        assert!(!net.contains("192.0.3.1".parse().unwrap()));
+        assert!(!net.contains("::ffff:192.0.2.1".parse().unwrap()));
+
+        let net = IpNetwork::from_str("2001:db8::1").expect("Invalid network");
+        assert!(net.to_string() == "2001:db8::1/128");
+        assert!(net.contains("2001:db8::1".parse().unwrap()));
+        assert!(!net.contains("2001:db8::2".parse().unwrap()));
+
+        assert!(IpNetwork::from_str("0.0.0.0/0")
+            .expect("Invalid network")
+            .contains("203.0.113.1".parse().unwrap()));
+        assert!(IpNetwork::from_str("192.0.2.0/33").is_err());
+        assert!(IpNetwork::from_str("example.com").is_err());
+    }
+
+    #[test]
+    fn test_idm_source_lock_backoff_and_ban() {
+        let policy = SourceLockPolicy {
+            address_threshold: 3,
+            subnet_threshold: 100,
+            max_delay: 4,
+            ban_after: 5,
+            ban_duration: 600,
+            ..Default::default()
+        };
+        let locks = SourceLocks::new(policy);
+        let source = https("192.0.2.1");
+        let ct = Duration::from_secs(1000);
+
+        // Below the threshold, failures are only counted.
+        locks.record_failure(&source, ct);
+        locks.record_failure(&source, ct);
+        assert!(!locks.is_locked(&source, ct));
+
+        // At the threshold the delay starts at one second, and then doubles.
+        locks.record_failure(&source, ct);
+        assert!(locks.is_locked(&source, ct));
+        assert!(!locks.is_locked(&source, ct + Duration::from_secs(1)));
+
+        let ct = ct + Duration::from_secs(1);
+        locks.record_failure(&source, ct);
+        assert!(locks.is_locked(&source, ct + Duration::from_secs(1)));
+        assert!(!locks.is_locked(&source, ct + Duration::from_secs(2)));
+
+        // The delay is capped.
+        let ct = ct + Duration::from_secs(2);
+        locks.record_failure(&source, ct);
+        locks.record_failure(&source, ct);
+        assert!(locks.is_locked(&source, ct + Duration::from_secs(3)));
+        assert!(!locks.is_locked(&source, ct + Duration::from_secs(4)));
+
+        // Other addresses, and internal sources are not affected.
+        assert!(!locks.is_locked(&https("192.0.2.2"), ct));
+        assert!(!locks.is_locked(&Source::Internal, ct));
+
+        // Past the threshold and ban_after, the source is banned.
+        locks.record_failure(&source, ct);
+        locks.record_failure(&source, ct);
+        assert!(locks.is_locked(&source, ct + Duration::from_secs(599)));
+        assert!(!locks.is_locked(&source, ct + Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn test_idm_source_lock_subnet() {
+        let policy = SourceLockPolicy {
+            address_threshold: 100,
+            subnet_threshold: 4,
+            ..Default::default()
+        };
+        let locks = SourceLocks::new(policy);
+        let ct = Duration::from_secs(1000);
+
+        // Each address fails once, but the subnet is locked.
+        for addr in ["192.0.2.1", "192.0.2.2", "192.0.2.3", "192.0.2.4"] {
+            locks.record_failure(&https(addr), ct);
+        }
+        assert!(locks.is_locked(&https("192.0.2.200"), ct));
+        assert!(!locks.is_locked(&https("198.51.100.1"), ct));
+
+        // Ipv6 subnets are /64 by default.
+        for n in 1..=4 {
+            locks.record_failure(&https(&format!("2001:db8::{}", n)), ct);
+        }
+        assert!(locks.is_locked(&https("2001:db8::ffff"), ct));
+        assert!(!locks.is_locked(&https("2001:db8:0:1::1"), ct));
+    }
+
+    #[test]
+    fn test_idm_source_lock_allowlist_and_window() {
+        let policy = SourceLockPolicy {
+            address_threshold: 2,
+            window: 60,
+            allowlist: vec![IpNetwork::from_str("10.0.0.0/8").expect("Invalid network")],
+            ..Default::default()
+        };
+        let locks = SourceLocks::new(policy);
+        let ct = Duration::from_secs(1000);
+
+        // Allowed networks are never locked.
+        let trusted = https("10.1.2.3");
+        for _ in 0..10 {
+            locks.record_failure(&trusted, ct);
+        }
+        assert!(!locks.is_locked(&trusted, ct));
+
+        // Failures are forgotten once the window passes without one.
+        let source = https("192.0.2.1");
+        locks.record_failure(&source, ct);
+        let ct = ct + Duration::from_secs(60);
+        locks.record_failure(&source, ct);
+        assert!(!locks.is_locked(&source, ct));
+        locks.record_failure(&source, ct);
+        assert!(locks.is_locked(&source, ct));
+    }
+
+    #[idm_test]
+    async fn test_idm_source_lock_admin(idms: &IdmServer, _idms_delayed: &mut IdmServerDelayed) {
+        let ct = duration_from_epoch_now();
+        let source = https("192.0.2.1");
+        // Enough to ban both the address and its subnet.
+        for _ in 0..60 {
+            idms.source_locks().record_failure(&source, ct);
+        }
+
+        let admin = {
+            let mut idms_prox_read = idms.proxy_read().await;
+            let admin = idms_prox_read
+                .qs_read
+                .internal_search_uuid(UUID_ADMIN)
+                .expect("failed");
+            Identity::from_impersonate_entry_readonly(admin)
+        };
+        let anon = {
+            let mut idms_prox_read = idms.proxy_read().await;
+            let anon = idms_prox_read
+                .qs_read
+                .internal_search_uuid(UUID_ANONYMOUS)
+                .expect("failed");
+            Identity::from_impersonate_entry_readonly(anon)
+        };
+
+        assert!(idms.source_locks().list(&anon, ct) == Err(OperationError::AccessDenied));
+        assert!(idms.source_locks().clear(&anon, "192.0.2.1") == Err(OperationError::AccessDenied));
+
+        let status = idms.source_locks().list(&admin, ct).expect("failed");
+        assert!(status.len() == 2);
+        assert!(status[0].source == "192.0.2.0/24" || status[0].source == "192.0.2.1/32");
+        assert!(status
+            .iter()
+            .all(|s| s.failures == 60 && s.locked_until.is_some()));
+
+        // Clearing the address leaves the subnet lock in place.
+        assert!(idms.source_locks().clear(&admin, "192.0.2.1").is_ok());
+        assert!(
+            idms.source_locks().clear(&admin, "192.0.2.1")
+                == Err(OperationError::NoMatchingEntries)
+        );
+        assert!(idms.source_locks().is_locked(&source, ct));
+        assert!(idms.source_locks().clear(&admin, "192.0.2.0/24").is_ok());
+        assert!(!idms.source_locks().is_locked(&source, ct));
+    }
+}
@@ -75,8 +75,8 @@ pub mod prelude {
     };
     pub use crate::event::{CreateEvent, DeleteEvent, ExistsEvent, ModifyEvent, SearchEvent};
     pub use crate::filter::{
-        f_and, f_andnot, f_eq, f_id, f_inc, f_lt, f_or, f_pres, f_self, f_spn_name, f_sub, Filter,
-        FilterInvalid, FilterValid, FC,
+        f_and, f_andnot, f_eq, f_gt, f_id, f_inc, f_lt, f_or, f_pres, f_self, f_spn_name, f_sub,
+        Filter, FilterInvalid, FilterValid, FC,
     };
     pub use crate::idm::server::{IdmServer, IdmServerAudit, IdmServerDelayed};
     pub use crate::modify::{
@@ -96,9 +96,9 @@ pub mod prelude {
         ApiTokenScope, IndexType, PartialValue, SessionScope, SyntaxType, Value,
     };
     pub use crate::valueset::{
-        ValueSet, ValueSetBool, ValueSetCid, ValueSetIndex, ValueSetIutf8, ValueSetRefer,
-        ValueSetSecret, ValueSetSpn, ValueSetSyntax, ValueSetT, ValueSetUint32, ValueSetUtf8,
-        ValueSetUuid,
+        ValueSet, ValueSetBool, ValueSetCid, ValueSetDate, ValueSetDecimal, ValueSetIndex,
+        ValueSetInt64, ValueSetIutf8, ValueSetRefer, ValueSetSecret, ValueSetSpn, ValueSetSyntax,
+        ValueSetT, ValueSetUint32, ValueSetUtf8, ValueSetUuid,
     };
 
     #[cfg(test)]
@@ -68,7 +68,8 @@ impl CredImport {
                 // convert the import_password_string to a password
                 let pw = Password::try_from(im_pw).map_err(|_| {
                     OperationError::Plugin(PluginError::CredImport(
-                        "password_import was unable to convert hash format".to_string(),
+                        "password_import was unable to convert hash format, or its cost is too high"
+                            .to_string(),
                     ))
                 })?;
 
@@ -224,6 +225,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_modify_password_import_crypt() {
+        let euuid = Uuid::new_v4();
+
+        for im_pw in [
+            "$2b$05$abcdefghijklmnopqrstuuWG29KuyeAicPCJODk1zjyGvyQUU2awu",
+            "$5$saltsalt$gOjOtoMpVhru2uyjeJSEc/JaLQWOXMNmlOnj6T4AtC.",
+            "$6$saltsalt$qFmFH.bQmmtXzyBY0s9v7Oicd2z4XSIecDzlB5KiA2/jctKu9YterLp8wwnSq.qc.eoxqOmSuNp2xS0ktL3nh/",
+            "$argon2id$v=19$m=65536,t=2,p=1$IyTQMsvzB2JHDiWx8fq7Ew$VhYOA7AL0kbRXI5g2kOyyp8St1epkNj7WZyUY4pAIQQ",
+        ] {
+            let ea = entry_init!(
+                ("class", Value::new_class("account")),
+                ("class", Value::new_class("person")),
+                ("name", Value::new_iname("testperson")),
+                ("description", Value::Utf8("testperson".to_string())),
+                ("displayname", Value::Utf8("testperson".to_string())),
+                ("uuid", Value::Uuid(euuid))
+            );
+
+            let preload = vec![ea];
+
+            run_modify_test!(
+                Ok(()),
+                preload,
+                filter!(f_eq("name", PartialValue::new_iutf8("testperson"))),
+                ModifyList::new_list(vec![Modify::Present(
+                    AttrString::from("password_import"),
+                    Value::Utf8(im_pw.to_string())
+                )]),
+                None,
+                |_| {},
+                |qs: &mut QueryServerWriteTransaction| {
+                    let e = qs.internal_search_uuid(euuid).expect("failed to get entry");
+                    let pw = e
+                        .get_ava_single_credential("primary_credential")
+                        .and_then(|c| c.password_ref().ok())
+                        .expect("failed to get primary password.");
+                    // Imported hashes are always rehashed on the next login.
+                    assert!(pw.requires_upgrade());
+                    assert!(pw.verify("password") == Ok(true));
+                }
+            );
+        }
+    }
+
     #[test]
     fn test_modify_password_import_3_totp() {
         // Add another uuid to a type
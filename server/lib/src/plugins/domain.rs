@@ -12,6 +12,7 @@ use rand::prelude::*;
 use regex::Regex;
 use tracing::trace;
 
+use crate::credential::attestation_ca_list_from_b64;
 use crate::event::{CreateEvent, ModifyEvent};
 use crate::plugins::Plugin;
 use crate::prelude::*;
@@ -78,6 +79,11 @@ impl Domain {
                     }
                 }
 
+                // Validate that the attestation cas are certificates.
+                if let Some(cas) = e.get_ava_iter_utf8("webauthn_attestation_ca_list") {
+                    attestation_ca_list_from_b64(cas)?;
+                }
+
                 // We always set this, because the DB uuid is authoritative.
                 let u = Value::Uuid(qs.get_domain_uuid());
                 e.set_ava("domain_uuid", once(u));
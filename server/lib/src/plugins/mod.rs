@@ -256,6 +256,7 @@ impl Plugins {
         refint::ReferentialIntegrity::post_modify(qs, pre_cand, cand, me)
             .and_then(|_| spn::Spn::post_modify(qs, pre_cand, cand, me))
             .and_then(|_| memberof::MemberOf::post_modify(qs, pre_cand, cand, me))
+            .and_then(|_| session::SessionConsistency::post_modify(qs, pre_cand, cand, me))
     }
 
     #[instrument(level = "debug", name = "plugins::run_pre_batch_modify", skip_all)]
@@ -286,6 +287,7 @@ impl Plugins {
         refint::ReferentialIntegrity::post_batch_modify(qs, pre_cand, cand, me)
             .and_then(|_| spn::Spn::post_batch_modify(qs, pre_cand, cand, me))
             .and_then(|_| memberof::MemberOf::post_batch_modify(qs, pre_cand, cand, me))
+            .and_then(|_| session::SessionConsistency::post_batch_modify(qs, pre_cand, cand, me))
     }
 
     #[instrument(level = "debug", name = "plugins::run_pre_delete", skip_all)]
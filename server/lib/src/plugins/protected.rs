@@ -2,6 +2,7 @@
 // may only have certain modifications performed.
 
 use hashbrown::HashSet;
+use kanidm_proto::constants::CUSTOM_SCHEMA_PREFIX;
 
 use crate::event::{CreateEvent, DeleteEvent, ModifyEvent};
 use crate::modify::Modify;
@@ -29,6 +30,7 @@ lazy_static! {
         m.insert("es256_private_key_der");
         m.insert("badlist_password");
         m.insert("domain_display_name");
+        m.insert("webauthn_attestation_ca_list");
         m
     };
 }
@@ -63,6 +65,29 @@ impl Plugin for Protected {
             } else {
                 Ok(())
             }
+        })?;
+
+        // Schema defined by administrators must be in the custom namespace so that it can
+        // never collide with schema that is added by a later release.
+        cand.iter().try_fold((), |(), cand| {
+            let name = if cand.attribute_equality("class", &PVCLASS_ATTRIBUTETYPE) {
+                cand.get_ava_single_iutf8("attributename")
+            } else if cand.attribute_equality("class", &PVCLASS_CLASSTYPE) {
+                cand.get_ava_single_iutf8("classname")
+            } else {
+                None
+            };
+
+            match name {
+                Some(name) if !name.starts_with(CUSTOM_SCHEMA_PREFIX) => {
+                    request_error!(%name, "Custom schema must be named with the {} prefix", CUSTOM_SCHEMA_PREFIX);
+                    Err(OperationError::InvalidSchemaState(format!(
+                        "custom schema names must start with {}",
+                        CUSTOM_SCHEMA_PREFIX
+                    )))
+                }
+                _ => Ok(()),
+            }
         })
     }
 
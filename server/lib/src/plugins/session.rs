@@ -6,11 +6,20 @@
 //!
 //! This plugin is also responsible for invaliding old sessions that are past
 //! their expiry.
+//!
+//! When a user auth session is explicitly removed, such as by a logout, it is
+//! treated as revoked. The oauth2 sessions descending from it are removed
+//! immediately, rather than once the grace window has passed.
+//!
+//! When an api token is issued to an account, the owner of the account is
+//! notified.
 
 use crate::event::ModifyEvent;
+use crate::idm::message::{queue_notification, Notification};
 use crate::plugins::Plugin;
 use crate::prelude::*;
 use std::collections::BTreeSet;
+use std::sync::Arc;
 use time::OffsetDateTime;
 
 pub struct SessionConsistency {}
@@ -24,25 +33,93 @@ impl Plugin for SessionConsistency {
     fn pre_modify(
         qs: &mut QueryServerWriteTransaction,
         cand: &mut Vec<Entry<EntryInvalid, EntryCommitted>>,
-        _me: &ModifyEvent,
+        me: &ModifyEvent,
     ) -> Result<(), OperationError> {
-        Self::modify_inner(qs, cand)
+        let revoked = revoked_sessions(me.modlist.iter());
+        Self::modify_inner(qs, cand, &revoked)
     }
 
     #[instrument(level = "debug", name = "session_consistency", skip_all)]
     fn pre_batch_modify(
         qs: &mut QueryServerWriteTransaction,
         cand: &mut Vec<Entry<EntryInvalid, EntryCommitted>>,
+        me: &BatchModifyEvent,
+    ) -> Result<(), OperationError> {
+        let revoked = revoked_sessions(me.modset.values().flat_map(|modlist| modlist.iter()));
+        Self::modify_inner(qs, cand, &revoked)
+    }
+
+    #[instrument(level = "debug", name = "session_consistency", skip_all)]
+    fn post_modify(
+        qs: &mut QueryServerWriteTransaction,
+        pre_cand: &[Arc<EntrySealedCommitted>],
+        cand: &[EntrySealedCommitted],
+        _me: &ModifyEvent,
+    ) -> Result<(), OperationError> {
+        Self::notify_api_tokens(qs, pre_cand, cand)
+    }
+
+    #[instrument(level = "debug", name = "session_consistency", skip_all)]
+    fn post_batch_modify(
+        qs: &mut QueryServerWriteTransaction,
+        pre_cand: &[Arc<EntrySealedCommitted>],
+        cand: &[EntrySealedCommitted],
         _me: &BatchModifyEvent,
     ) -> Result<(), OperationError> {
-        Self::modify_inner(qs, cand)
+        Self::notify_api_tokens(qs, pre_cand, cand)
     }
 }
 
+// The user auth sessions that this modification explicitly removes.
+fn revoked_sessions<'a>(mods: impl Iterator<Item = &'a Modify>) -> BTreeSet<Uuid> {
+    mods.filter_map(|m| match m {
+        Modify::Removed(attr, PartialValue::Refer(session_id))
+            if attr.as_str() == "user_auth_token_session" =>
+        {
+            Some(*session_id)
+        }
+        _ => None,
+    })
+    .collect()
+}
+
 impl SessionConsistency {
+    fn notify_api_tokens(
+        qs: &mut QueryServerWriteTransaction,
+        pre_cand: &[Arc<EntrySealedCommitted>],
+        cand: &[EntrySealedCommitted],
+    ) -> Result<(), OperationError> {
+        cand.iter().zip(pre_cand.iter()).try_for_each(|(post, pre)| {
+            let issued: Vec<_> = match post.get_ava_as_apitoken_map("api_token_session") {
+                Some(tokens) => {
+                    let pre_tokens = pre.get_ava_as_apitoken_map("api_token_session");
+                    tokens
+                        .iter()
+                        .filter(|(token_id, _)| {
+                            !pre_tokens.map(|t| t.contains_key(*token_id)).unwrap_or(false)
+                        })
+                        .map(|(_, token)| token.clone())
+                        .collect()
+                }
+                None => return Ok(()),
+            };
+
+            issued.into_iter().try_for_each(|token| {
+                info!(uuid = %post.get_uuid(), label = %token.label, "Notifying of issued api token");
+                queue_notification(qs, post, |display_name| Notification::ApiTokenIssued {
+                    display_name,
+                    label: token.label,
+                    expiry: token.expiry,
+                    issued_at: token.issued_at,
+                })
+            })
+        })
+    }
+
     fn modify_inner<T: Clone + std::fmt::Debug>(
         qs: &mut QueryServerWriteTransaction,
         cand: &mut [Entry<EntryInvalid, T>],
+        revoked: &BTreeSet<Uuid>,
     ) -> Result<(), OperationError> {
         let curtime = qs.get_curtime();
         let curtime_odt = OffsetDateTime::UNIX_EPOCH + curtime;
@@ -101,36 +178,71 @@ impl SessionConsistency {
 
             // * If an oauth2 session is past it's expiry, remove it.
             // * If an oauth2 session is past the grace window, and no parent session exists, remove it.
+            // * If an oauth2 session was created by a token exchange, its parent is the oauth2
+            //   session it was exchanged from. If that parent is removed, remove it too.
+            // * If an oauth2 session's parent was revoked, remove it regardless of the grace window.
+            // * A client credentials session is its own parent, so it is only removed by expiry.
             let oauth2_remove: Option<BTreeSet<_>> = entry.get_ava_as_oauth2session_map("oauth2_session").map(|oauth2_sessions| {
                 // If we have oauth2 sessions, we need to be able to lookup if sessions exist in the uat.
                 let sessions = entry.get_ava_as_session_map("user_auth_token_session");
 
-                oauth2_sessions.iter().filter_map(|(o2_session_id, session)| {
+                let mut remove: BTreeSet<Uuid> = oauth2_sessions.iter().filter_map(|(o2_session_id, session)| {
                     match &session.expiry {
                         Some(exp) if exp <= &curtime_odt => {
                             info!(%o2_session_id, "Removing expired oauth2 session");
-                            Some(PartialValue::Refer(*o2_session_id))
+                            Some(*o2_session_id)
+                        }
+                        _ => None,
+                    }
+                })
+                .collect();
+
+                // Removing a session may unbind others that were exchanged from it, so repeat
+                // until nothing changes.
+                loop {
+                    let unbound: Vec<Uuid> = oauth2_sessions.iter().filter_map(|(o2_session_id, session)| {
+                        if remove.contains(o2_session_id) {
+                            return None;
                         }
-                        _ => {
-                            // Okay, now check the issued / grace time for parent enforcement.
-                            if session.issued_at + GRACE_WINDOW <= curtime_odt {
-                                if sessions.map(|s| s.contains_key(&session.parent)).unwrap_or(false) {
-                                    // The parent exists, go ahead
-                                    None
-                                } else {
-                                    info!(%o2_session_id, parent_id = %session.parent, "Removing unbound oauth2 session");
-                                    Some(PartialValue::Refer(*o2_session_id))
-                                }
-                            } else {
-                                // Grace window is still in effect
-                                None
-                            }
 
+                        if sessions.map(|s| s.contains_key(&session.parent)).unwrap_or(false) {
+                            // The parent exists, go ahead
+                            return None;
+                        }
+
+                        if remove.contains(&session.parent) {
+                            info!(%o2_session_id, parent_id = %session.parent, "Removing oauth2 session exchanged from a removed session");
+                            return Some(*o2_session_id);
+                        }
+
+                        if oauth2_sessions.contains_key(&session.parent) {
+                            // Exchanged from an oauth2 session that still exists.
+                            return None;
+                        }
+
+                        if revoked.contains(&session.parent) {
+                            info!(%o2_session_id, parent_id = %session.parent, "Removing oauth2 session whose parent session was revoked");
+                            return Some(*o2_session_id);
+                        }
+
+                        // Okay, now check the issued / grace time for parent enforcement.
+                        if session.issued_at + GRACE_WINDOW <= curtime_odt {
+                            info!(%o2_session_id, parent_id = %session.parent, "Removing unbound oauth2 session");
+                            Some(*o2_session_id)
+                        } else {
+                            // Grace window is still in effect
+                            None
                         }
+                    })
+                    .collect();
+
+                    if unbound.is_empty() {
+                        break;
                     }
+                    remove.extend(unbound);
+                }
 
-                })
-                .collect()
+                remove.into_iter().map(PartialValue::Refer).collect()
             });
 
             if let Some(oauth2_remove) = oauth2_remove.as_ref() {
@@ -525,6 +637,283 @@ mod tests {
         assert!(server_txn.commit().is_ok());
     }
 
+    // test that revoking a session removes related oauth2 sessions, even within the grace window.
+    #[qs_test]
+    async fn test_session_consistency_oauth2_revoked_in_grace_window(server: &QueryServer) {
+        let curtime = duration_from_epoch_now();
+        let curtime_odt = OffsetDateTime::UNIX_EPOCH + curtime;
+
+        let p = CryptoPolicy::minimum();
+        let cred = Credential::new_password_only(&p, "test_password").unwrap();
+        let cred_id = cred.uuid;
+
+        // Create a user
+        let mut server_txn = server.write(curtime).await;
+
+        let tuuid = uuid!("cc8e95b4-c24f-4d68-ba54-8bed76f63930");
+        let rs_uuid = Uuid::new_v4();
+
+        let e1 = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("person")),
+            ("class", Value::new_class("account")),
+            ("name", Value::new_iname("testperson1")),
+            ("uuid", Value::Uuid(tuuid)),
+            ("description", Value::new_utf8s("testperson1")),
+            ("displayname", Value::new_utf8s("testperson1")),
+            (
+                "primary_credential",
+                Value::Cred("primary".to_string(), cred.clone())
+            )
+        );
+
+        let e2 = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("oauth2_resource_server")),
+            ("class", Value::new_class("oauth2_resource_server_basic")),
+            ("uuid", Value::Uuid(rs_uuid)),
+            ("oauth2_rs_name", Value::new_iname("test_resource_server")),
+            ("displayname", Value::new_utf8s("test_resource_server")),
+            (
+                "oauth2_rs_origin",
+                Value::new_url_s("https://demo.example.com").unwrap()
+            ),
+            // System admins
+            (
+                "oauth2_rs_scope_map",
+                Value::new_oauthscopemap(UUID_IDM_ALL_ACCOUNTS, btreeset!["openid".to_string()])
+                    .expect("invalid oauthscope")
+            )
+        );
+
+        let ce = CreateEvent::new_internal(vec![e1, e2]);
+        assert!(server_txn.create(&ce).is_ok());
+
+        // Create a fake session and oauth2 session.
+
+        let session_id = Uuid::new_v4();
+        let pv_session_id = PartialValue::Refer(session_id);
+
+        let parent = Uuid::new_v4();
+        let pv_parent_id = PartialValue::Refer(parent);
+        let issued_at = curtime_odt;
+        let issued_by = IdentityId::User(tuuid);
+        let scope = SessionScope::ReadOnly;
+
+        // Mod the user
+        let modlist = modlist!([
+            Modify::Present(
+                "oauth2_session".into(),
+                Value::Oauth2Session(
+                    session_id,
+                    Oauth2Session {
+                        parent,
+                        // Note we set the exp to None so we are not removing based on exp
+                        expiry: None,
+                        issued_at,
+                        rs_uuid,
+                    },
+                )
+            ),
+            Modify::Present(
+                "user_auth_token_session".into(),
+                Value::Session(
+                    parent,
+                    Session {
+                        label: "label".to_string(),
+                        // Note we set the exp to None so we are not removing based on removal of the parent.
+                        expiry: None,
+                        // Need the other inner bits?
+                        // for the gracewindow.
+                        issued_at,
+                        // Who actually created this?
+                        issued_by,
+                        cred_id,
+                        // What is the access scope of this session? This is
+                        // for auditing purposes.
+                        scope,
+                    },
+                )
+            ),
+        ]);
+
+        server_txn
+            .internal_modify(&filter!(f_eq("uuid", PartialValue::Uuid(tuuid))), &modlist)
+            .expect("Failed to modify user");
+
+        // Still there
+
+        let entry = server_txn.internal_search_uuid(tuuid).expect("failed");
+
+        assert!(entry.attribute_equality("user_auth_token_session", &pv_parent_id));
+        assert!(entry.attribute_equality("oauth2_session", &pv_session_id));
+
+        // Still within the grace window, remove the parent session, as a logout does.
+        let modlist = ModifyList::new_remove("user_auth_token_session", pv_parent_id.clone());
+
+        server_txn
+            .internal_modify(&filter!(f_eq("uuid", PartialValue::Uuid(tuuid))), &modlist)
+            .expect("Failed to modify user");
+
+        // Session gone.
+        let entry = server_txn.internal_search_uuid(tuuid).expect("failed");
+
+        // Note the uat is removed
+        assert!(!entry.attribute_equality("user_auth_token_session", &pv_parent_id));
+        // The oauth2 session is also removed, despite the grace window.
+        assert!(!entry.attribute_equality("oauth2_session", &pv_session_id));
+
+        assert!(server_txn.commit().is_ok());
+    }
+
+    // test that an oauth2 session from a token exchange is kept while the session it was
+    // exchanged from exists, and is removed with it.
+    #[qs_test]
+    async fn test_session_consistency_oauth2_exchanged_removed_by_parent(server: &QueryServer) {
+        let curtime = duration_from_epoch_now();
+        let curtime_odt = OffsetDateTime::UNIX_EPOCH + curtime;
+        let exp_curtime = curtime + GRACE_WINDOW;
+
+        let p = CryptoPolicy::minimum();
+        let cred = Credential::new_password_only(&p, "test_password").unwrap();
+        let cred_id = cred.uuid;
+
+        // Create a user
+        let mut server_txn = server.write(curtime).await;
+
+        let tuuid = uuid!("cc8e95b4-c24f-4d68-ba54-8bed76f63930");
+        let rs_uuid = Uuid::new_v4();
+
+        let e1 = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("person")),
+            ("class", Value::new_class("account")),
+            ("name", Value::new_iname("testperson1")),
+            ("uuid", Value::Uuid(tuuid)),
+            ("description", Value::new_utf8s("testperson1")),
+            ("displayname", Value::new_utf8s("testperson1")),
+            (
+                "primary_credential",
+                Value::Cred("primary".to_string(), cred.clone())
+            )
+        );
+
+        let e2 = entry_init!(
+            ("class", Value::new_class("object")),
+            ("class", Value::new_class("oauth2_resource_server")),
+            ("class", Value::new_class("oauth2_resource_server_basic")),
+            ("uuid", Value::Uuid(rs_uuid)),
+            ("oauth2_rs_name", Value::new_iname("test_resource_server")),
+            ("displayname", Value::new_utf8s("test_resource_server")),
+            (
+                "oauth2_rs_origin",
+                Value::new_url_s("https://demo.example.com").unwrap()
+            ),
+            // System admins
+            (
+                "oauth2_rs_scope_map",
+                Value::new_oauthscopemap(UUID_IDM_ALL_ACCOUNTS, btreeset!["openid".to_string()])
+                    .expect("invalid oauthscope")
+            )
+        );
+
+        let ce = CreateEvent::new_internal(vec![e1, e2]);
+        assert!(server_txn.create(&ce).is_ok());
+
+        // Create a fake session, an oauth2 session, and a session exchanged from that.
+        let session_id = Uuid::new_v4();
+        let pv_session_id = PartialValue::Refer(session_id);
+
+        let exchanged_session_id = Uuid::new_v4();
+        let pv_exchanged_session_id = PartialValue::Refer(exchanged_session_id);
+
+        let parent = Uuid::new_v4();
+        let pv_parent_id = PartialValue::Refer(parent);
+        let issued_at = curtime_odt;
+        let issued_by = IdentityId::User(tuuid);
+        let scope = SessionScope::ReadOnly;
+
+        // Mod the user
+        let modlist = modlist!([
+            Modify::Present(
+                "oauth2_session".into(),
+                Value::Oauth2Session(
+                    session_id,
+                    Oauth2Session {
+                        parent,
+                        expiry: None,
+                        issued_at,
+                        rs_uuid,
+                    },
+                )
+            ),
+            Modify::Present(
+                "oauth2_session".into(),
+                Value::Oauth2Session(
+                    exchanged_session_id,
+                    Oauth2Session {
+                        parent: session_id,
+                        expiry: None,
+                        issued_at,
+                        rs_uuid,
+                    },
+                )
+            ),
+            Modify::Present(
+                "user_auth_token_session".into(),
+                Value::Session(
+                    parent,
+                    Session {
+                        label: "label".to_string(),
+                        expiry: None,
+                        issued_at,
+                        issued_by,
+                        cred_id,
+                        scope,
+                    },
+                )
+            ),
+        ]);
+
+        server_txn
+            .internal_modify(&filter!(f_eq("uuid", PartialValue::Uuid(tuuid))), &modlist)
+            .expect("Failed to modify user");
+
+        assert!(server_txn.commit().is_ok());
+
+        // Past the grace window, the exchanged session is still bound via the oauth2 session.
+        let mut server_txn = server.write(exp_curtime).await;
+
+        let modlist =
+            ModifyList::new_purge_and_set("description", Value::new_utf8s("test person 1 change"));
+
+        server_txn
+            .internal_modify(&filter!(f_eq("uuid", PartialValue::Uuid(tuuid))), &modlist)
+            .expect("Failed to modify user");
+
+        let entry = server_txn.internal_search_uuid(tuuid).expect("failed");
+
+        assert!(entry.attribute_equality("user_auth_token_session", &pv_parent_id));
+        assert!(entry.attribute_equality("oauth2_session", &pv_session_id));
+        assert!(entry.attribute_equality("oauth2_session", &pv_exchanged_session_id));
+
+        // Remove the oauth2 session, as a revoke would.
+        let modlist = ModifyList::new_remove("oauth2_session", pv_session_id.clone());
+
+        server_txn
+            .internal_modify(&filter!(f_eq("uuid", PartialValue::Uuid(tuuid))), &modlist)
+            .expect("Failed to modify user");
+
+        let entry = server_txn.internal_search_uuid(tuuid).expect("failed");
+
+        // The uat remains, but the exchanged session went with its parent.
+        assert!(entry.attribute_equality("user_auth_token_session", &pv_parent_id));
+        assert!(!entry.attribute_equality("oauth2_session", &pv_session_id));
+        assert!(!entry.attribute_equality("oauth2_session", &pv_exchanged_session_id));
+
+        assert!(server_txn.commit().is_ok());
+    }
+
     // Test if an oauth2 session exists, the grace window passes and it's UAT doesn't exist.
     #[qs_test]
     async fn test_session_consistency_oauth2_grace_window_past(server: &QueryServer) {
@@ -303,6 +303,9 @@ pub enum ReplAttrV1 {
     DateTime {
         set: Vec<String>,
     },
+    Date {
+        set: Vec<String>,
+    },
     Iname {
         set: Vec<String>,
     },
@@ -333,6 +336,12 @@ pub enum ReplAttrV1 {
     Uint32 {
         set: Vec<u32>,
     },
+    Int64 {
+        set: Vec<i64>,
+    },
+    Decimal {
+        set: Vec<String>,
+    },
     Url {
         set: Vec<Url>,
     },
@@ -158,6 +158,19 @@ impl SchemaAttribute {
             admin_error!("missing syntax - {}", name);
             OperationError::InvalidSchemaState("missing syntax".to_string())
         })?;
+        // Only these syntaxes generate ordering keys, on any other the index would be empty.
+        // Decimals are excluded as their precision can't be held in a fixed width key.
+        if index.contains(&IndexType::Ordering)
+            && !matches!(
+                syntax,
+                SyntaxType::Uint32 | SyntaxType::Int64 | SyntaxType::DateTime | SyntaxType::Date
+            )
+        {
+            admin_error!("ordering index not supported by syntax - {}", name);
+            return Err(OperationError::InvalidSchemaState(
+                "ordering index not supported by syntax".to_string(),
+            ));
+        }
 
         Ok(SchemaAttribute {
             name,
@@ -192,9 +205,12 @@ impl SchemaAttribute {
             SyntaxType::SshKey => matches!(v, PartialValue::SshKey(_)),
             SyntaxType::SecurityPrincipalName => matches!(v, PartialValue::Spn(_, _)),
             SyntaxType::Uint32 => matches!(v, PartialValue::Uint32(_)),
+            SyntaxType::Int64 => matches!(v, PartialValue::Int64(_)),
+            SyntaxType::Decimal => matches!(v, PartialValue::Decimal(_)),
             SyntaxType::Cid => matches!(v, PartialValue::Cid(_)),
             SyntaxType::NsUniqueId => matches!(v, PartialValue::Nsuniqueid(_)),
             SyntaxType::DateTime => matches!(v, PartialValue::DateTime(_)),
+            SyntaxType::Date => matches!(v, PartialValue::Date(_)),
             SyntaxType::EmailAddress => matches!(v, PartialValue::EmailAddress(_)),
             SyntaxType::Url => matches!(v, PartialValue::Url(_)),
             SyntaxType::OauthScope => matches!(v, PartialValue::OauthScope(_)),
@@ -244,9 +260,12 @@ impl SchemaAttribute {
                 SyntaxType::SshKey => matches!(v, Value::SshKey(_, _)),
                 SyntaxType::SecurityPrincipalName => matches!(v, Value::Spn(_, _)),
                 SyntaxType::Uint32 => matches!(v, Value::Uint32(_)),
+                SyntaxType::Int64 => matches!(v, Value::Int64(_)),
+                SyntaxType::Decimal => matches!(v, Value::Decimal(_)),
                 SyntaxType::Cid => matches!(v, Value::Cid(_)),
                 SyntaxType::NsUniqueId => matches!(v, Value::Nsuniqueid(_)),
                 SyntaxType::DateTime => matches!(v, Value::DateTime(_)),
+                SyntaxType::Date => matches!(v, Value::Date(_)),
                 SyntaxType::EmailAddress => matches!(v, Value::EmailAddress(_, _)),
                 SyntaxType::Url => matches!(v, Value::Url(_)),
                 SyntaxType::OauthScope => matches!(v, Value::OauthScope(_)),
@@ -2050,6 +2069,71 @@ mod tests {
             SchemaAttribute
         );
 
+        sch_from_entry_err!(
+            r#"{
+                    "attrs": {
+                        "class": ["object", "attributetype"],
+                        "attributename": ["schema_attr_test"],
+                        "uuid": ["66c68b2f-d02c-4243-8013-7946e40fe321"],
+                        "description": ["Test attr parsing"],
+                        "multivalue": ["false"],
+                        "unique": ["false"],
+                        "index": ["ORDERING"],
+                        "syntax": ["UTF8STRING"]
+                    }
+                }"#,
+            SchemaAttribute
+        );
+
+        sch_from_entry_err!(
+            r#"{
+                    "attrs": {
+                        "class": ["object", "attributetype"],
+                        "attributename": ["schema_attr_test"],
+                        "uuid": ["66c68b2f-d02c-4243-8013-7946e40fe321"],
+                        "description": ["Test attr parsing"],
+                        "multivalue": ["false"],
+                        "unique": ["false"],
+                        "index": ["ORDERING"],
+                        "syntax": ["DECIMAL"]
+                    }
+                }"#,
+            SchemaAttribute
+        );
+
+        // Ordering is allowed on syntaxes with ordering keys
+        sch_from_entry_ok!(
+            r#"{
+                    "attrs": {
+                        "class": ["object", "attributetype"],
+                        "attributename": ["schema_attr_test"],
+                        "uuid": ["66c68b2f-d02c-4243-8013-7946e40fe321"],
+                        "description": ["Test attr parsing"],
+                        "multivalue": ["false"],
+                        "unique": ["false"],
+                        "index": ["ORDERING"],
+                        "syntax": ["INT64"]
+                    }
+                }"#,
+            SchemaAttribute
+        );
+
+        sch_from_entry_ok!(
+            r#"{
+                    "attrs": {
+                        "class": ["object", "attributetype"],
+                        "attributename": ["schema_attr_test"],
+                        "uuid": ["66c68b2f-d02c-4243-8013-7946e40fe321"],
+                        "description": ["Test attr parsing"],
+                        "multivalue": ["false"],
+                        "unique": ["false"],
+                        "index": ["ORDERING"],
+                        "syntax": ["DATE"]
+                    }
+                }"#,
+            SchemaAttribute
+        );
+
         sch_from_entry_err!(
             r#"{
                     "attrs": {
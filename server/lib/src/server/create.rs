@@ -1,3 +1,4 @@
+use crate::idm::audit::AccessControlOperation;
 use crate::prelude::*;
 use crate::server::CreateEvent;
 use crate::server::Plugins;
@@ -116,6 +117,14 @@ impl<'a> QueryServerWriteTransaction<'a> {
                 .iter()
                 .any(|e| e.attribute_equality("class", &PVCLASS_ACP));
         }
+        self.audit_access_control_change(
+            &ce.ident,
+            commit_cand
+                .iter()
+                .filter(|e| e.attribute_equality("class", &PVCLASS_ACP))
+                .map(|e| e.get_uuid()),
+            AccessControlOperation::Create,
+        );
         if !self.changed_oauth2 {
             self.changed_oauth2 = commit_cand
                 .iter()
@@ -1,3 +1,4 @@
+use crate::idm::audit::AccessControlOperation;
 use crate::plugins::Plugins;
 use crate::prelude::*;
 use crate::server::DeleteEvent;
@@ -107,6 +108,14 @@ impl<'a> QueryServerWriteTransaction<'a> {
                 .iter()
                 .any(|e| e.attribute_equality("class", &PVCLASS_ACP));
         }
+        self.audit_access_control_change(
+            &de.ident,
+            del_cand
+                .iter()
+                .filter(|e| e.attribute_equality("class", &PVCLASS_ACP))
+                .map(|e| e.get_uuid()),
+            AccessControlOperation::Delete,
+        );
         if !self.changed_oauth2 {
             self.changed_oauth2 = del_cand
                 .iter()
@@ -21,7 +21,7 @@ use crate::value::Session;
 pub enum Source {
     Internal,
     Https(IpAddr),
-    // Ldaps,
+    Ldaps(IpAddr),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
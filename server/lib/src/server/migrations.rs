@@ -457,6 +457,29 @@ impl<'a> QueryServerWriteTransaction<'a> {
             JSON_SCHEMA_ATTR_JWS_ES256_PRIVATE_KEY,
             JSON_SCHEMA_ATTR_API_TOKEN_SESSION,
             JSON_SCHEMA_ATTR_OAUTH2_RS_SUP_SCOPE_MAP,
+            JSON_SCHEMA_ATTR_OAUTH2_RS_TOKEN_EXCHANGE_AUDIENCE,
+            JSON_SCHEMA_ATTR_OAUTH2_REQUIRE_PAR,
+            JSON_SCHEMA_ATTR_OAUTH2_RS_CLIENT_JWK,
+            JSON_SCHEMA_ATTR_OAUTH2_RS_CLIENT_JWKS_URL,
+            JSON_SCHEMA_ATTR_OAUTH2_RS_BACKCHANNEL_LOGOUT_URI,
+            JSON_SCHEMA_ATTR_OAUTH2_RS_REGISTERED_BY,
+            JSON_SCHEMA_ATTR_OAUTH2_RS_SERVICE_ACCOUNT,
+            JSON_SCHEMA_ATTR_OAUTH2_REGISTRATION_ORIGIN,
+            JSON_SCHEMA_ATTR_OAUTH2_REGISTRATION_SCOPE_MAP,
+            JSON_SCHEMA_ATTR_AUTHSESSION_EXPIRY,
+            JSON_SCHEMA_ATTR_PRIVILEGE_EXPIRY,
+            JSON_SCHEMA_ATTR_AUTH_PASSWORD_MINIMUM_LENGTH,
+            JSON_SCHEMA_ATTR_CREDENTIAL_TYPE_MINIMUM,
+            JSON_SCHEMA_ATTR_OAUTH2_ACCESS_TOKEN_EXPIRY,
+            JSON_SCHEMA_ATTR_WEBAUTHN_ATTESTATION_CA_LIST,
+            JSON_SCHEMA_ATTR_MESSAGE_TEMPLATE,
+            JSON_SCHEMA_ATTR_MAIL_DESTINATION,
+            JSON_SCHEMA_ATTR_SEND_AFTER,
+            JSON_SCHEMA_ATTR_DELIVERED_AT,
+            JSON_SCHEMA_ATTR_DELIVERY_ATTEMPTS,
+            JSON_SCHEMA_ATTR_DELIVERY_ERROR,
+            JSON_SCHEMA_ATTR_MESSAGE_RECIPIENT,
+            JSON_SCHEMA_ATTR_AUTH_SOURCE_IP,
             JSON_SCHEMA_ATTR_USER_AUTH_TOKEN_SESSION,
             JSON_SCHEMA_ATTR_OAUTH2_SESSION,
             JSON_SCHEMA_ATTR_NSUNIQUEID,
@@ -475,6 +498,8 @@ impl<'a> QueryServerWriteTransaction<'a> {
             JSON_SCHEMA_CLASS_DOMAIN_INFO,
             JSON_SCHEMA_CLASS_POSIXACCOUNT,
             JSON_SCHEMA_CLASS_POSIXGROUP,
+            JSON_SCHEMA_CLASS_ACCOUNT_POLICY,
+            JSON_SCHEMA_CLASS_OUTBOUND_MESSAGE,
             JSON_SCHEMA_CLASS_SYSTEM_CONFIG,
             JSON_SCHEMA_CLASS_OAUTH2_RS,
             JSON_SCHEMA_CLASS_OAUTH2_RS_BASIC,
@@ -576,6 +601,7 @@ impl<'a> QueryServerWriteTransaction<'a> {
             JSON_IDM_HP_OAUTH2_MANAGE_PRIV_V1,
             JSON_IDM_HP_SERVICE_ACCOUNT_INTO_PERSON_MIGRATE_PRIV,
             JSON_IDM_HP_SYNC_ACCOUNT_MANAGE_PRIV,
+            JSON_IDM_AUDIT_READ_PRIV,
             // All members must exist before we write HP
             JSON_IDM_HIGH_PRIVILEGE_V1,
         ];
@@ -628,6 +654,9 @@ impl<'a> QueryServerWriteTransaction<'a> {
             E_IDM_ACP_GROUP_UNIX_EXTEND_PRIV_V1.clone(),
             E_IDM_HP_ACP_GROUP_UNIX_EXTEND_PRIV_V1.clone(),
             E_IDM_HP_ACP_OAUTH2_MANAGE_PRIV_V1.clone(),
+            E_IDM_HP_ACP_OAUTH2_REGISTRATION_MANAGE_PRIV_V1.clone(),
+            E_IDM_ACP_ACCOUNT_POLICY_MANAGE_PRIV_V1.clone(),
+            E_IDM_ACP_MESSAGE_QUEUE_MANAGE_PRIV_V1.clone(),
             E_IDM_ACP_RADIUS_SECRET_READ_PRIV_V1.clone(),
             E_IDM_ACP_RADIUS_SECRET_WRITE_PRIV_V1.clone(),
             E_IDM_HP_ACP_SERVICE_ACCOUNT_INTO_PERSON_MIGRATE_V1.clone(),
@@ -10,6 +10,7 @@ use concread::arcache::{ARCache, ARCacheBuilder, ARCacheReadTxn};
 use concread::cowcell::*;
 use hashbrown::HashSet;
 use kanidm_proto::v1::{ConsistencyError, UiHint};
+use time::OffsetDateTime;
 use tokio::sync::{Semaphore, SemaphorePermit};
 use tracing::trace;
 
@@ -24,6 +25,7 @@ use self::access::{
 use crate::be::{Backend, BackendReadTransaction, BackendTransaction, BackendWriteTransaction};
 // We use so many, we just import them all ...
 use crate::filter::{Filter, FilterInvalid, FilterValid, FilterValidResolved};
+use crate::idm::audit::{AccessControlOperation, AuditEvent};
 use crate::plugins::dyngroup::{DynGroup, DynGroupCache};
 use crate::plugins::Plugins;
 use crate::repl::cid::Cid;
@@ -115,6 +117,8 @@ pub struct QueryServerWriteTransaction<'a> {
     pub(crate) changed_domain: bool,
     // Store the list of changed uuids for other invalidation needs?
     pub(crate) changed_uuid: HashSet<Uuid>,
+    // Audit events caused by this transaction, which are only sent if it commits.
+    audit_events: Vec<AuditEvent>,
     _db_ticket: SemaphorePermit<'a>,
     _write_ticket: SemaphorePermit<'a>,
     resolve_filter_cache:
@@ -518,11 +522,17 @@ pub trait QueryServerTransaction<'a> {
                     SyntaxType::SecurityPrincipalName => Err(OperationError::InvalidAttribute("SPNs are generated and not able to be set.".to_string())),
                     SyntaxType::Uint32 => Value::new_uint32_str(value)
                         .ok_or_else(|| OperationError::InvalidAttribute("Invalid uint32 syntax".to_string())),
+                    SyntaxType::Int64 => Value::new_int64_str(value)
+                        .ok_or_else(|| OperationError::InvalidAttribute("Invalid int64 syntax".to_string())),
+                    SyntaxType::Decimal => Value::new_decimal_str(value)
+                        .ok_or_else(|| OperationError::InvalidAttribute("Invalid decimal syntax".to_string())),
                     SyntaxType::Cid => Err(OperationError::InvalidAttribute("CIDs are generated and not able to be set.".to_string())),
                     SyntaxType::NsUniqueId => Value::new_nsuniqueid_s(value)
                         .ok_or_else(|| OperationError::InvalidAttribute("Invalid NsUniqueId syntax".to_string())),
                     SyntaxType::DateTime => Value::new_datetime_s(value)
                         .ok_or_else(|| OperationError::InvalidAttribute("Invalid DateTime (rfc3339) syntax".to_string())),
+                    SyntaxType::Date => Value::new_date_s(value)
+                        .ok_or_else(|| OperationError::InvalidAttribute("Invalid Date (YYYY-MM-DD) syntax".to_string())),
                     SyntaxType::EmailAddress => Value::new_email_address_s(value)
                         .ok_or_else(|| OperationError::InvalidAttribute("Invalid Email Address syntax".to_string())),
                     SyntaxType::Url => Value::new_url_s(value)
@@ -611,6 +621,12 @@ pub trait QueryServerTransaction<'a> {
                     SyntaxType::Uint32 => PartialValue::new_uint32_str(value).ok_or_else(|| {
                         OperationError::InvalidAttribute("Invalid uint32 syntax".to_string())
                     }),
+                    SyntaxType::Int64 => PartialValue::new_int64_str(value).ok_or_else(|| {
+                        OperationError::InvalidAttribute("Invalid int64 syntax".to_string())
+                    }),
+                    SyntaxType::Decimal => PartialValue::new_decimal_str(value).ok_or_else(|| {
+                        OperationError::InvalidAttribute("Invalid decimal syntax".to_string())
+                    }),
                     SyntaxType::Cid => PartialValue::new_cid_s(value).ok_or_else(|| {
                         OperationError::InvalidAttribute("Invalid cid syntax".to_string())
                     }),
@@ -620,6 +636,11 @@ pub trait QueryServerTransaction<'a> {
                             "Invalid DateTime (rfc3339) syntax".to_string(),
                         )
                     }),
+                    SyntaxType::Date => PartialValue::new_date_s(value).ok_or_else(|| {
+                        OperationError::InvalidAttribute(
+                            "Invalid Date (YYYY-MM-DD) syntax".to_string(),
+                        )
+                    }),
                     SyntaxType::EmailAddress => Ok(PartialValue::new_email_address_s(value)),
                     SyntaxType::Url => PartialValue::new_url_s(value).ok_or_else(|| {
                         OperationError::InvalidAttribute(
@@ -1148,6 +1169,7 @@ impl QueryServer {
             changed_oauth2: false,
             changed_domain: false,
             changed_uuid: HashSet::new(),
+            audit_events: Vec::new(),
             _db_ticket: db_ticket,
             _write_ticket: write_ticket,
             resolve_filter_cache: self.resolve_filter_cache.read(),
@@ -1172,6 +1194,9 @@ impl<'a> QueryServerWriteTransaction<'a> {
 
     #[instrument(level = "debug", name = "reload_schema", skip(self))]
     pub(crate) fn reload_schema(&mut self) -> Result<(), OperationError> {
+        // Remember what was indexed, so that we can tell if the indexes changed.
+        let prev_idxkeys: HashSet<_> = self.schema.reload_idxmeta().into_iter().collect();
+
         // supply entries to the writable schema to reload from.
         // find all attributes.
         let filt = filter!(f_eq("class", PVCLASS_ATTRIBUTETYPE.clone()));
@@ -1217,12 +1242,32 @@ impl<'a> QueryServerWriteTransaction<'a> {
         if valid_r.is_empty() {
             // Now use this to reload the backend idxmeta
             trace!("Reloading idxmeta ...");
-            self.be_txn
-                .update_idxmeta(self.schema.reload_idxmeta())
-                .map_err(|e| {
-                    admin_error!("reload schema update idxmeta {:?}", e);
+            let idxkeys = self.schema.reload_idxmeta();
+            let next_idxkeys: HashSet<_> = idxkeys.iter().cloned().collect();
+            let added: Vec<_> = next_idxkeys.difference(&prev_idxkeys).cloned().collect();
+            let removed: Vec<_> = prev_idxkeys.difference(&next_idxkeys).cloned().collect();
+
+            self.be_txn.update_idxmeta(idxkeys).map_err(|e| {
+                admin_error!("reload schema update idxmeta {:?}", e);
+                e
+            })?;
+
+            // During startup the migrations reindex as required, but once running an
+            // index change (such as to a custom attribute) must be applied online. Only the
+            // indexes that changed are touched.
+            if (!added.is_empty() || !removed.is_empty()) && *self.phase >= ServerPhase::Running {
+                admin_info!(
+                    ?added,
+                    ?removed,
+                    "Index definitions changed, updating indexes"
+                );
+                self.be_txn.reindex_changed(&added, &removed).map_err(|e| {
+                    admin_error!("reload schema reindex {:?}", e);
                     e
                 })
+            } else {
+                Ok(())
+            }
         } else {
             // Log the failures?
             admin_error!("Schema reload failed -> {:?}", valid_r);
@@ -1472,6 +1517,39 @@ impl<'a> QueryServerWriteTransaction<'a> {
         &self.changed_uuid
     }
 
+    /// Record an audit event, to be sent once this transaction commits.
+    pub(crate) fn audit_event(&mut self, event: AuditEvent) {
+        self.audit_events.push(event)
+    }
+
+    pub(crate) fn take_audit_events(&mut self) -> Vec<AuditEvent> {
+        std::mem::take(&mut self.audit_events)
+    }
+
+    /// Record that an identity changed access control profiles. Internal changes, such as
+    /// migrations, are not audited.
+    pub(crate) fn audit_access_control_change<I>(
+        &mut self,
+        ident: &Identity,
+        uuids: I,
+        operation: AccessControlOperation,
+    ) where
+        I: Iterator<Item = Uuid>,
+    {
+        if ident.is_internal() {
+            return;
+        }
+        let actor = ident.get_uuid();
+        let time = OffsetDateTime::UNIX_EPOCH + self.curtime;
+        self.audit_events
+            .extend(uuids.map(|uuid| AuditEvent::AccessControlModified {
+                actor,
+                uuid,
+                operation,
+                time,
+            }));
+    }
+
     pub fn get_changed_ouath2(&self) -> bool {
         self.changed_oauth2
     }
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use crate::idm::audit::AccessControlOperation;
 use crate::plugins::Plugins;
 use crate::prelude::*;
 
@@ -199,6 +200,18 @@ impl<'a> QueryServerWriteTransaction<'a> {
                 .chain(pre_candidates.iter().map(|e| e.as_ref()))
                 .any(|e| e.attribute_equality("class", &PVCLASS_ACP))
         }
+        self.audit_access_control_change(
+            &me.ident,
+            norm_cand
+                .iter()
+                .zip(pre_candidates.iter())
+                .filter(|(e, pre)| {
+                    e.attribute_equality("class", &PVCLASS_ACP)
+                        || pre.attribute_equality("class", &PVCLASS_ACP)
+                })
+                .map(|(e, _)| e.get_uuid()),
+            AccessControlOperation::Modify,
+        );
         if !self.changed_oauth2 {
             self.changed_oauth2 = norm_cand
                 .iter()
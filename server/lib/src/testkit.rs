@@ -1,4 +1,5 @@
 use crate::be::{Backend, BackendConfig};
+use crate::idm::sourcelock::SourceLockPolicy;
 use crate::prelude::*;
 use crate::schema::Schema;
 #[allow(unused_imports)]
@@ -63,7 +64,7 @@ pub async fn setup_idm_test() -> (IdmServer, IdmServerDelayed, IdmServerAudit) {
     qs.initialise_helper(duration_from_epoch_now())
         .await
         .expect("init failed!");
-    IdmServer::new(qs, "https://idm.example.com")
+    IdmServer::new(qs, "https://idm.example.com", SourceLockPolicy::default())
         .await
         .expect("Failed to setup idms")
 }
@@ -80,6 +80,19 @@ pub fn readable_password_from_random() -> String {
     )
 }
 
+/// A short code for a person to type on a second device, such as for the oauth2
+/// device authorisation grant. Only upper case consonants are used to avoid
+/// ambiguous characters and forming words, as recommended by rfc8628 6.1.
+pub fn user_code_from_random() -> String {
+    const CHARSET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+    let mut trng = thread_rng();
+    let mut code: String = (0..8)
+        .map(|_| CHARSET[trng.gen_range(0..CHARSET.len())] as char)
+        .collect();
+    code.insert(4, '-');
+    code
+}
+
 pub fn duration_from_epoch_now() -> Duration {
     #[allow(clippy::expect_used)]
     SystemTime::now()
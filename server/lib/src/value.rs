@@ -12,7 +12,10 @@ use std::fmt::Formatter;
 use std::str::FromStr;
 use std::time::Duration;
 
-use crate::valueset::uuid_to_proto_string;
+use crate::valueset::{
+    date_from_str, date_to_idx_ord_key, date_to_string, datetime_to_idx_ord_key,
+    int64_to_idx_ord_key, uint32_to_idx_ord_key, uuid_to_proto_string,
+};
 #[cfg(test)]
 use base64::{engine::general_purpose, Engine as _};
 use compact_jwt::JwsSigner;
@@ -23,9 +26,10 @@ use kanidm_proto::v1::UatPurposeStatus;
 use kanidm_proto::v1::UiHint;
 use num_enum::TryFromPrimitive;
 use regex::Regex;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use sshkeys::PublicKey as SshPublicKey;
-use time::OffsetDateTime;
+use time::{Date, OffsetDateTime};
 use url::Url;
 use uuid::Uuid;
 use webauthn_rs::prelude::{DeviceKey as DeviceKeyV4, Passkey as PasskeyV4};
@@ -154,6 +158,7 @@ pub enum IndexType {
     Equality,
     Presence,
     SubString,
+    Ordering,
 }
 
 impl TryFrom<&str> for IndexType {
@@ -165,6 +170,7 @@ impl TryFrom<&str> for IndexType {
             "EQUALITY" => Ok(IndexType::Equality),
             "PRESENCE" => Ok(IndexType::Presence),
             "SUBSTRING" => Ok(IndexType::SubString),
+            "ORDERING" => Ok(IndexType::Ordering),
             // UUID map?
             // UUID rev map?
             _ => Err(()),
@@ -178,6 +184,7 @@ impl IndexType {
             IndexType::Equality => "eq",
             IndexType::Presence => "pres",
             IndexType::SubString => "sub",
+            IndexType::Ordering => "ord",
         }
     }
 }
@@ -191,6 +198,7 @@ impl fmt::Display for IndexType {
                 IndexType::Equality => "EQUALITY",
                 IndexType::Presence => "PRESENCE",
                 IndexType::SubString => "SUBSTRING",
+                IndexType::Ordering => "ORDERING",
             }
         )
     }
@@ -246,6 +254,9 @@ pub enum SyntaxType {
     UiHint = 29,
     TotpSecret = 30,
     ApiToken = 31,
+    Int64 = 32,
+    Decimal = 33,
+    Date = 34,
 }
 
 impl TryFrom<&str> for SyntaxType {
@@ -287,6 +298,9 @@ impl TryFrom<&str> for SyntaxType {
             "UIHINT" => Ok(SyntaxType::UiHint),
             "TOTPSECRET" => Ok(SyntaxType::TotpSecret),
             "APITOKEN" => Ok(SyntaxType::ApiToken),
+            "INT64" => Ok(SyntaxType::Int64),
+            "DECIMAL" => Ok(SyntaxType::Decimal),
+            "DATE" => Ok(SyntaxType::Date),
             _ => Err(()),
         }
     }
@@ -327,6 +341,9 @@ impl fmt::Display for SyntaxType {
             SyntaxType::UiHint => "UIHINT",
             SyntaxType::TotpSecret => "TOTPSECRET",
             SyntaxType::ApiToken => "APITOKEN",
+            SyntaxType::Int64 => "INT64",
+            SyntaxType::Decimal => "DECIMAL",
+            SyntaxType::Date => "DATE",
         })
     }
 }
@@ -356,9 +373,12 @@ pub enum PartialValue {
     SecretValue,
     Spn(String, String),
     Uint32(u32),
+    Int64(i64),
+    Decimal(Decimal),
     Cid(Cid),
     Nsuniqueid(String),
     DateTime(OffsetDateTime),
+    Date(Date),
     EmailAddress(String),
     PhoneNumber(String),
     Address(String),
@@ -414,6 +434,24 @@ impl From<u32> for PartialValue {
     }
 }
 
+impl From<i64> for PartialValue {
+    fn from(i: i64) -> Self {
+        PartialValue::Int64(i)
+    }
+}
+
+impl From<Decimal> for PartialValue {
+    fn from(d: Decimal) -> Self {
+        PartialValue::Decimal(d)
+    }
+}
+
+impl From<Date> for PartialValue {
+    fn from(d: Date) -> Self {
+        PartialValue::Date(d)
+    }
+}
+
 impl From<OffsetDateTime> for PartialValue {
     fn from(i: OffsetDateTime) -> Self {
         PartialValue::DateTime(i)
@@ -582,6 +620,30 @@ impl PartialValue {
         matches!(self, PartialValue::Uint32(_))
     }
 
+    pub fn new_int64(i: i64) -> Self {
+        PartialValue::Int64(i)
+    }
+
+    pub fn new_int64_str(i: &str) -> Option<Self> {
+        i.parse::<i64>().ok().map(PartialValue::Int64)
+    }
+
+    pub fn is_int64(&self) -> bool {
+        matches!(self, PartialValue::Int64(_))
+    }
+
+    pub fn new_decimal(d: Decimal) -> Self {
+        PartialValue::Decimal(d)
+    }
+
+    pub fn new_decimal_str(d: &str) -> Option<Self> {
+        Decimal::from_str_exact(d).ok().map(PartialValue::Decimal)
+    }
+
+    pub fn is_decimal(&self) -> bool {
+        matches!(self, PartialValue::Decimal(_))
+    }
+
     pub fn new_cid(c: Cid) -> Self {
         PartialValue::Cid(c)
     }
@@ -617,6 +679,18 @@ impl PartialValue {
         matches!(self, PartialValue::DateTime(_))
     }
 
+    pub fn new_date(d: Date) -> Self {
+        PartialValue::Date(d)
+    }
+
+    pub fn new_date_s(s: &str) -> Option<Self> {
+        date_from_str(s).map(PartialValue::Date)
+    }
+
+    pub fn is_date(&self) -> bool {
+        matches!(self, PartialValue::Date(_))
+    }
+
     pub fn new_email_address_s(s: &str) -> Self {
         PartialValue::EmailAddress(s.to_string())
     }
@@ -733,6 +807,9 @@ impl PartialValue {
             PartialValue::SecretValue | PartialValue::PrivateBinary => "_".to_string(),
             PartialValue::Spn(name, realm) => format!("{name}@{realm}"),
             PartialValue::Uint32(u) => u.to_string(),
+            PartialValue::Int64(i) => i.to_string(),
+            // Normalise so that 1.50 and 1.5 share an index key, matching their equality.
+            PartialValue::Decimal(d) => d.normalize().to_string(),
             // This will never work, we don't allow equality searching on Cid's
             PartialValue::Cid(_) => "_".to_string(),
             PartialValue::DateTime(odt) => {
@@ -741,6 +818,7 @@ impl PartialValue {
                 odt.format(&Rfc3339)
                     .expect("Failed to format timestamp into RFC3339")
             }
+            PartialValue::Date(d) => date_to_string(d),
             PartialValue::Url(u) => u.to_string(),
             PartialValue::OauthScope(u) => u.to_string(),
             PartialValue::Address(a) => a.to_string(),
@@ -754,6 +832,18 @@ impl PartialValue {
     pub fn get_idx_sub_key(&self) -> String {
         unimplemented!();
     }
+
+    /// The key of this value in an ordering index, if its syntax can be ordered. These keys
+    /// sort in the same order as the values they were generated from.
+    pub fn get_idx_ord_key(&self) -> Option<String> {
+        match self {
+            PartialValue::Uint32(u) => Some(uint32_to_idx_ord_key(u)),
+            PartialValue::Int64(i) => Some(int64_to_idx_ord_key(i)),
+            PartialValue::DateTime(odt) => Some(datetime_to_idx_ord_key(odt)),
+            PartialValue::Date(d) => Some(date_to_idx_ord_key(d)),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -867,9 +957,12 @@ pub enum Value {
     SecretValue(String),
     Spn(String, String),
     Uint32(u32),
+    Int64(i64),
+    Decimal(Decimal),
     Cid(Cid),
     Nsuniqueid(String),
     DateTime(OffsetDateTime),
+    Date(Date),
     EmailAddress(String, bool),
     PhoneNumber(String, bool),
     Address(Address),
@@ -923,10 +1016,16 @@ impl PartialEq for Value {
             (Value::JsonFilt(a), Value::JsonFilt(b)) => a.eq(b),
             // Uint32
             (Value::Uint32(a), Value::Uint32(b)) => a.eq(b),
+            // Int64
+            (Value::Int64(a), Value::Int64(b)) => a.eq(b),
+            // Decimal
+            (Value::Decimal(a), Value::Decimal(b)) => a.eq(b),
             // Cid
             (Value::Cid(a), Value::Cid(b)) => a.eq(b),
             // DateTime
             (Value::DateTime(a), Value::DateTime(b)) => a.eq(b),
+            // Date
+            (Value::Date(a), Value::Date(b)) => a.eq(b),
             // Url
             (Value::Url(a), Value::Url(b)) => a.eq(b),
             // OauthScopeMap
@@ -994,6 +1093,24 @@ impl From<u32> for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int64(i)
+    }
+}
+
+impl From<Decimal> for Value {
+    fn from(d: Decimal) -> Self {
+        Value::Decimal(d)
+    }
+}
+
+impl From<Date> for Value {
+    fn from(d: Date) -> Self {
+        Value::Date(d)
+    }
+}
+
 impl From<Url> for Value {
     fn from(i: Url) -> Self {
         Value::Url(i)
@@ -1235,6 +1352,42 @@ impl Value {
         matches!(&self, Value::Uint32(_))
     }
 
+    pub fn new_int64(i: i64) -> Self {
+        Value::Int64(i)
+    }
+
+    pub fn new_int64_str(i: &str) -> Option<Self> {
+        i.parse::<i64>().ok().map(Value::Int64)
+    }
+
+    pub fn is_int64(&self) -> bool {
+        matches!(&self, Value::Int64(_))
+    }
+
+    pub fn new_decimal(d: Decimal) -> Self {
+        Value::Decimal(d)
+    }
+
+    pub fn new_decimal_str(d: &str) -> Option<Self> {
+        Decimal::from_str_exact(d).ok().map(Value::Decimal)
+    }
+
+    pub fn is_decimal(&self) -> bool {
+        matches!(&self, Value::Decimal(_))
+    }
+
+    pub fn new_date(d: Date) -> Self {
+        Value::Date(d)
+    }
+
+    pub fn new_date_s(s: &str) -> Option<Self> {
+        date_from_str(s).map(Value::Date)
+    }
+
+    pub fn is_date(&self) -> bool {
+        matches!(&self, Value::Date(_))
+    }
+
     pub fn new_cid(c: Cid) -> Self {
         Value::Cid(c)
     }
@@ -1464,6 +1617,27 @@ impl Value {
         }
     }
 
+    pub fn to_int64(&self) -> Option<i64> {
+        match &self {
+            Value::Int64(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn to_decimal(&self) -> Option<Decimal> {
+        match &self {
+            Value::Decimal(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn to_date(&self) -> Option<Date> {
+        match &self {
+            Value::Date(v) => Some(*v),
+            _ => None,
+        }
+    }
+
     pub fn to_utf8(self) -> Option<String> {
         match self {
             Value::Utf8(s) => Some(s),
@@ -1679,6 +1853,9 @@ impl Value {
             | Value::JsonFilt(_)
             | Value::SecretValue(_)
             | Value::Uint32(_)
+            | Value::Int64(_)
+            | Value::Decimal(_)
+            | Value::Date(_)
             | Value::Url(_)
             | Value::Cid(_)
             | Value::PrivateBinary(_)
@@ -1751,8 +1928,11 @@ mod tests {
         let r3 = IndexType::try_from("SUBSTRING");
         assert_eq!(r3, Ok(IndexType::SubString));
 
-        let r4 = IndexType::try_from("thaoeusaneuh");
-        assert_eq!(r4, Err(()));
+        let r4 = IndexType::try_from("ORDERING");
+        assert_eq!(r4, Ok(IndexType::Ordering));
+
+        let r5 = IndexType::try_from("thaoeusaneuh");
+        assert_eq!(r5, Err(()));
     }
 
     #[test]
@@ -1939,6 +2119,53 @@ mod tests {
         assert!(val3.validate());
     }
 
+    #[test]
+    fn test_value_int64() {
+        assert!(Value::new_int64_str("test").is_none());
+        assert!(Value::new_int64_str("9223372036854775808").is_none());
+
+        let i64v = Value::new_int64_str("-9223372036854775808").unwrap();
+        assert!(i64v.validate());
+        assert!(i64v.to_int64() == Some(i64::MIN));
+
+        let i64pv = PartialValue::new_int64_str("-42").unwrap();
+        assert!(i64pv.get_idx_eq_key() == "-42");
+    }
+
+    #[test]
+    fn test_value_decimal() {
+        assert!(Value::new_decimal_str("test").is_none());
+        assert!(Value::new_decimal_str("1,5").is_none());
+
+        let dv1 = Value::new_decimal_str("-12.3400").unwrap();
+        let dv2 = Value::new_decimal_str("-12.34").unwrap();
+        assert!(dv1.validate());
+        // Equal values compare equal regardless of scale, and share an index key.
+        assert!(dv1 == dv2);
+        assert!(
+            PartialValue::new_decimal_str("-12.3400")
+                .unwrap()
+                .get_idx_eq_key()
+                == PartialValue::new_decimal_str("-12.34")
+                    .unwrap()
+                    .get_idx_eq_key()
+        );
+    }
+
+    #[test]
+    fn test_value_date() {
+        // Dates are strictly YYYY-MM-DD, with no time or offset component.
+        let val1 = Value::new_date_s("2020-09-25").expect("Must be valid");
+        assert!(val1.validate());
+        assert!(Value::new_date_s("2020-09-25T01:22:02+00:00").is_none());
+        assert!(Value::new_date_s("2020-9-25").is_none());
+        assert!(Value::new_date_s("2020-02-30").is_none());
+        assert!(Value::new_date_s("25/09/2020").is_none());
+
+        let pv1 = PartialValue::new_date_s("2020-09-25").expect("Must be valid");
+        assert!(pv1.get_idx_eq_key() == "2020-09-25");
+    }
+
     #[test]
     fn test_value_email_address() {
         // https://html.spec.whatwg.org/multipage/forms.html#valid-e-mail-address
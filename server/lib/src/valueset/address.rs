@@ -133,6 +133,10 @@ impl ValueSetT for ValueSetAddress {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.set.len()
     }
@@ -356,6 +360,10 @@ impl ValueSetT for ValueSetEmailAddress {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.set.len()
     }
@@ -93,6 +93,13 @@ impl ValueSetT for ValueSetCid {
         }
     }
 
+    fn greaterthan(&self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Cid(c2) => self.set.iter().any(|c1| c1 > c2),
+            _ => false,
+        }
+    }
+
     fn len(&self) -> usize {
         self.set.len()
     }
@@ -104,6 +104,10 @@ impl ValueSetT for ValueSetCredential {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.map.len()
     }
@@ -320,6 +324,10 @@ impl ValueSetT for ValueSetIntentToken {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.map.len()
     }
@@ -524,6 +532,10 @@ impl ValueSetT for ValueSetPasskey {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.map.len()
     }
@@ -708,6 +720,10 @@ impl ValueSetT for ValueSetDeviceKey {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.map.len()
     }
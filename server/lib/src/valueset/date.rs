@@ -0,0 +1,254 @@
+use smolset::SmolSet;
+use time::{Date, Month};
+
+use crate::prelude::*;
+use crate::repl::proto::ReplAttrV1;
+use crate::schema::SchemaAttribute;
+use crate::valueset::{DbValueSetV2, ValueSet};
+
+/// Parse a calendar date in the strict `YYYY-MM-DD` form. Unlike datetimes there is no
+/// timezone component - a date is the same calendar day everywhere.
+pub fn date_from_str(s: &str) -> Option<Date> {
+    let mut parts = s.splitn(3, '-');
+    let (y, m, d) = (parts.next()?, parts.next()?, parts.next()?);
+
+    if y.len() != 4 || m.len() != 2 || d.len() != 2 {
+        return None;
+    }
+
+    if !(y.chars().chain(m.chars()).chain(d.chars())).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let year = y.parse::<i32>().ok()?;
+    let month = m.parse::<u8>().ok().and_then(|m| Month::try_from(m).ok())?;
+    let day = d.parse::<u8>().ok()?;
+
+    Date::from_calendar_date(year, month, day).ok()
+}
+
+pub fn date_to_string(d: &Date) -> String {
+    format!("{:04}-{:02}-{:02}", d.year(), d.month() as u8, d.day())
+}
+
+/// Ordering keys are the julian day with the sign bit flipped, as fixed width hex. This sorts
+/// the same as the dates, including those before the start of the julian period.
+pub fn date_to_idx_ord_key(d: &Date) -> String {
+    let day = d.to_julian_day() as u32 ^ (1 << 31);
+    format!("{day:08x}")
+}
+
+#[derive(Debug, Clone)]
+pub struct ValueSetDate {
+    set: SmolSet<[Date; 1]>,
+}
+
+impl ValueSetDate {
+    pub fn new(d: Date) -> Box<Self> {
+        let mut set = SmolSet::new();
+        set.insert(d);
+        Box::new(ValueSetDate { set })
+    }
+
+    pub fn push(&mut self, d: Date) -> bool {
+        self.set.insert(d)
+    }
+
+    pub fn from_dbvs2(data: Vec<String>) -> Result<ValueSet, OperationError> {
+        let set = data
+            .iter()
+            .map(|s| date_from_str(s).ok_or(OperationError::InvalidValueState))
+            .collect::<Result<_, _>>()?;
+        Ok(Box::new(ValueSetDate { set }))
+    }
+
+    pub fn from_repl_v1(data: &[String]) -> Result<ValueSet, OperationError> {
+        let set = data
+            .iter()
+            .map(|s| date_from_str(s).ok_or(OperationError::InvalidValueState))
+            .collect::<Result<_, _>>()?;
+        Ok(Box::new(ValueSetDate { set }))
+    }
+
+    // We need to allow this, because rust doesn't allow us to impl FromIterator on foreign
+    // types, and date is foreign.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter<T>(iter: T) -> Option<Box<Self>>
+    where
+        T: IntoIterator<Item = Date>,
+    {
+        let set = iter.into_iter().collect();
+        Some(Box::new(ValueSetDate { set }))
+    }
+}
+
+impl ValueSetT for ValueSetDate {
+    fn insert_checked(&mut self, value: Value) -> Result<bool, OperationError> {
+        match value {
+            Value::Date(d) => Ok(self.set.insert(d)),
+            _ => {
+                debug_assert!(false);
+                Err(OperationError::InvalidValueState)
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.set.clear();
+    }
+
+    fn remove(&mut self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Date(d) => self.set.remove(d),
+            _ => false,
+        }
+    }
+
+    fn contains(&self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Date(d) => self.set.contains(d),
+            _ => false,
+        }
+    }
+
+    fn substring(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
+    fn lessthan(&self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Date(d) => self.set.iter().any(|i| i < d),
+            _ => false,
+        }
+    }
+
+    fn greaterthan(&self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Date(d) => self.set.iter().any(|i| i > d),
+            _ => false,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    fn generate_idx_eq_keys(&self) -> Vec<String> {
+        self.set.iter().map(date_to_string).collect()
+    }
+
+    fn generate_idx_ord_keys(&self) -> Vec<String> {
+        self.set.iter().map(date_to_idx_ord_key).collect()
+    }
+
+    fn syntax(&self) -> SyntaxType {
+        SyntaxType::Date
+    }
+
+    fn validate(&self, _schema_attr: &SchemaAttribute) -> bool {
+        true
+    }
+
+    fn to_proto_string_clone_iter(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        Box::new(self.set.iter().map(date_to_string))
+    }
+
+    fn to_db_valueset_v2(&self) -> DbValueSetV2 {
+        DbValueSetV2::Date(self.set.iter().map(date_to_string).collect())
+    }
+
+    fn to_repl_v1(&self) -> ReplAttrV1 {
+        ReplAttrV1::Date {
+            set: self.set.iter().map(date_to_string).collect(),
+        }
+    }
+
+    fn to_partialvalue_iter(&self) -> Box<dyn Iterator<Item = PartialValue> + '_> {
+        Box::new(self.set.iter().copied().map(PartialValue::Date))
+    }
+
+    fn to_value_iter(&self) -> Box<dyn Iterator<Item = Value> + '_> {
+        Box::new(self.set.iter().copied().map(Value::Date))
+    }
+
+    fn equal(&self, other: &ValueSet) -> bool {
+        if let Some(other) = other.as_date_set() {
+            &self.set == other
+        } else {
+            debug_assert!(false);
+            false
+        }
+    }
+
+    fn merge(&mut self, other: &ValueSet) -> Result<(), OperationError> {
+        if let Some(b) = other.as_date_set() {
+            mergesets!(self.set, b)
+        } else {
+            debug_assert!(false);
+            Err(OperationError::InvalidValueState)
+        }
+    }
+
+    fn as_date_set(&self) -> Option<&SmolSet<[Date; 1]>> {
+        Some(&self.set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Date, Month};
+
+    use super::{date_from_str, date_to_idx_ord_key, date_to_string};
+    use crate::prelude::*;
+
+    #[test]
+    fn test_valueset_basic() {
+        let d = date_from_str("2023-06-01").unwrap();
+        let mut vs = ValueSetDate::new(d);
+        assert!(vs.insert_checked(Value::new_date_s("2023-06-01").unwrap()) == Ok(false));
+        assert!(vs.insert_checked(Value::new_date_s("1999-12-31").unwrap()) == Ok(true));
+
+        assert!(vs.lessthan(&PartialValue::new_date_s("2000-01-01").unwrap()));
+        assert!(!vs.lessthan(&PartialValue::new_date_s("1999-12-31").unwrap()));
+        assert!(vs.greaterthan(&PartialValue::new_date_s("2023-05-31").unwrap()));
+        assert!(!vs.greaterthan(&PartialValue::new_date_s("2023-06-01").unwrap()));
+    }
+
+    #[test]
+    fn test_valueset_date_parse() {
+        let d = date_from_str("0987-01-09").unwrap();
+        assert!(date_to_string(&d) == "0987-01-09");
+
+        assert!(date_from_str("2023-02-29").is_none());
+        assert!(date_from_str("2024-02-29").is_some());
+        assert!(date_from_str("2023-13-01").is_none());
+        assert!(date_from_str("2023-1-01").is_none());
+        assert!(date_from_str("+2023-01-01").is_none());
+        assert!(date_from_str("2023-01-01T00:00:00Z").is_none());
+    }
+
+    #[test]
+    fn test_valueset_date_ord_keys() {
+        // Julian day 0 is -4713-11-24, so this covers negative days and years.
+        let dates = [
+            (-9999, Month::January, 1),
+            (-4713, Month::November, 23),
+            (-4713, Month::November, 24),
+            (987, Month::January, 9),
+            (1969, Month::December, 31),
+            (2023, Month::June, 1),
+        ];
+        let keys: Vec<_> = dates
+            .iter()
+            .map(|(y, m, d)| {
+                let d = Date::from_calendar_date(*y, *m, *d).expect("invalid date");
+                date_to_idx_ord_key(&d)
+            })
+            .collect();
+
+        let mut sorted = keys.clone();
+        sorted.sort_unstable();
+        assert_eq!(keys, sorted);
+        assert!(keys.iter().all(|k| k.len() == 8));
+    }
+}
@@ -6,6 +6,13 @@ use crate::repl::proto::ReplAttrV1;
 use crate::schema::SchemaAttribute;
 use crate::valueset::{DbValueSetV2, ValueSet};
 
+/// Ordering keys are the nanoseconds since the epoch with the sign bit flipped, as fixed width
+/// hex. This sorts the same as the timestamps, including those before 1970.
+pub fn datetime_to_idx_ord_key(odt: &OffsetDateTime) -> String {
+    let nanos = odt.unix_timestamp_nanos() as u128 ^ (1 << 127);
+    format!("{nanos:032x}")
+}
+
 #[derive(Debug, Clone)]
 pub struct ValueSetDateTime {
     set: SmolSet<[OffsetDateTime; 1]>,
@@ -91,8 +98,18 @@ impl ValueSetT for ValueSetDateTime {
         false
     }
 
-    fn lessthan(&self, _pv: &PartialValue) -> bool {
-        false
+    fn lessthan(&self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::DateTime(u) => self.set.iter().any(|odt| odt < u),
+            _ => false,
+        }
+    }
+
+    fn greaterthan(&self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::DateTime(u) => self.set.iter().any(|odt| odt > u),
+            _ => false,
+        }
     }
 
     fn len(&self) -> usize {
@@ -111,6 +128,10 @@ impl ValueSetT for ValueSetDateTime {
             .collect()
     }
 
+    fn generate_idx_ord_keys(&self) -> Vec<String> {
+        self.set.iter().map(datetime_to_idx_ord_key).collect()
+    }
+
     fn syntax(&self) -> SyntaxType {
         SyntaxType::DateTime
     }
@@ -195,3 +216,27 @@ impl ValueSetT for ValueSetDateTime {
         Some(&self.set)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::datetime_to_idx_ord_key;
+
+    #[test]
+    fn test_valueset_datetime_ord_keys() {
+        let times = [-86400 * 365 * 100, -1, 0, 1, 86400 * 365 * 50];
+        let keys: Vec<_> = times
+            .iter()
+            .map(|t| {
+                let odt = OffsetDateTime::from_unix_timestamp(*t).expect("invalid timestamp");
+                datetime_to_idx_ord_key(&odt)
+            })
+            .collect();
+
+        let mut sorted = keys.clone();
+        sorted.sort_unstable();
+        assert_eq!(keys, sorted);
+        assert!(keys.iter().all(|k| k.len() == 32));
+    }
+}
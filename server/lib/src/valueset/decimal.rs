@@ -0,0 +1,201 @@
+use std::collections::BTreeSet;
+
+use rust_decimal::Decimal;
+
+use crate::prelude::*;
+use crate::repl::proto::ReplAttrV1;
+use crate::schema::SchemaAttribute;
+use crate::valueset::{DbValueSetV2, ValueSet};
+
+#[derive(Debug, Clone)]
+pub struct ValueSetDecimal {
+    set: BTreeSet<Decimal>,
+}
+
+impl ValueSetDecimal {
+    pub fn new(d: Decimal) -> Box<Self> {
+        let mut set = BTreeSet::new();
+        set.insert(d);
+        Box::new(ValueSetDecimal { set })
+    }
+
+    pub fn push(&mut self, d: Decimal) -> bool {
+        self.set.insert(d)
+    }
+
+    pub fn from_dbvs2(data: Vec<String>) -> Result<ValueSet, OperationError> {
+        let set = data
+            .iter()
+            .map(|s| Decimal::from_str_exact(s).map_err(|_| OperationError::InvalidValueState))
+            .collect::<Result<_, _>>()?;
+        Ok(Box::new(ValueSetDecimal { set }))
+    }
+
+    pub fn from_repl_v1(data: &[String]) -> Result<ValueSet, OperationError> {
+        let set = data
+            .iter()
+            .map(|s| Decimal::from_str_exact(s).map_err(|_| OperationError::InvalidValueState))
+            .collect::<Result<_, _>>()?;
+        Ok(Box::new(ValueSetDecimal { set }))
+    }
+
+    // We need to allow this, because rust doesn't allow us to impl FromIterator on foreign
+    // types, and decimal is foreign.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter<T>(iter: T) -> Option<Box<Self>>
+    where
+        T: IntoIterator<Item = Decimal>,
+    {
+        let set = iter.into_iter().collect();
+        Some(Box::new(ValueSetDecimal { set }))
+    }
+}
+
+impl ValueSetT for ValueSetDecimal {
+    fn insert_checked(&mut self, value: Value) -> Result<bool, OperationError> {
+        match value {
+            Value::Decimal(d) => Ok(self.set.insert(d)),
+            _ => {
+                debug_assert!(false);
+                Err(OperationError::InvalidValueState)
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.set.clear();
+    }
+
+    fn remove(&mut self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Decimal(d) => self.set.remove(d),
+            _ => {
+                debug_assert!(false);
+                true
+            }
+        }
+    }
+
+    fn contains(&self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Decimal(d) => self.set.contains(d),
+            _ => false,
+        }
+    }
+
+    fn substring(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
+    fn lessthan(&self, pv: &PartialValue) -> bool {
+        match pv {
+            // The set is ordered, so only the smallest value needs to be checked.
+            PartialValue::Decimal(d) => self.set.first().map(|i| i < d).unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    fn greaterthan(&self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Decimal(d) => self.set.last().map(|i| i > d).unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    fn generate_idx_eq_keys(&self) -> Vec<String> {
+        // Normalise so that equal values with differing scales (1.5, 1.50) share a key.
+        self.set.iter().map(|d| d.normalize().to_string()).collect()
+    }
+
+    fn syntax(&self) -> SyntaxType {
+        SyntaxType::Decimal
+    }
+
+    fn validate(&self, _schema_attr: &SchemaAttribute) -> bool {
+        true
+    }
+
+    fn to_proto_string_clone_iter(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        Box::new(self.set.iter().map(|d| d.to_string()))
+    }
+
+    fn to_db_valueset_v2(&self) -> DbValueSetV2 {
+        DbValueSetV2::Decimal(self.set.iter().map(|d| d.to_string()).collect())
+    }
+
+    fn to_repl_v1(&self) -> ReplAttrV1 {
+        ReplAttrV1::Decimal {
+            set: self.set.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    fn to_partialvalue_iter(&self) -> Box<dyn Iterator<Item = PartialValue> + '_> {
+        Box::new(self.set.iter().copied().map(PartialValue::new_decimal))
+    }
+
+    fn to_value_iter(&self) -> Box<dyn Iterator<Item = Value> + '_> {
+        Box::new(self.set.iter().copied().map(Value::new_decimal))
+    }
+
+    fn equal(&self, other: &ValueSet) -> bool {
+        if let Some(other) = other.as_decimal_set() {
+            &self.set == other
+        } else {
+            debug_assert!(false);
+            false
+        }
+    }
+
+    fn merge(&mut self, other: &ValueSet) -> Result<(), OperationError> {
+        if let Some(b) = other.as_decimal_set() {
+            mergesets!(self.set, b)
+        } else {
+            debug_assert!(false);
+            Err(OperationError::InvalidValueState)
+        }
+    }
+
+    fn as_decimal_set(&self) -> Option<&BTreeSet<Decimal>> {
+        Some(&self.set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use crate::prelude::*;
+
+    #[test]
+    fn test_valueset_basic() {
+        let mut vs = ValueSetDecimal::new(Decimal::new(15, 1));
+        // Scale does not affect equality.
+        assert!(vs.insert_checked(Value::new_decimal_str("1.50").unwrap()) == Ok(false));
+        assert!(vs.insert_checked(Value::new_decimal_str("-20.125").unwrap()) == Ok(true));
+        assert!(vs.contains(&PartialValue::new_decimal_str("1.500").unwrap()));
+
+        assert!(vs.lessthan(&PartialValue::new_decimal_str("0").unwrap()));
+        assert!(!vs.lessthan(&PartialValue::new_decimal_str("-20.125").unwrap()));
+        assert!(vs.greaterthan(&PartialValue::new_decimal_str("1.49").unwrap()));
+        assert!(!vs.greaterthan(&PartialValue::new_decimal_str("1.5").unwrap()));
+
+        assert!(Value::new_decimal_str("1.5e3").is_none());
+        assert!(Value::new_decimal_str("abc").is_none());
+    }
+
+    #[test]
+    fn test_valueset_decimal_idx_key() {
+        let vs = ValueSetDecimal::new(Decimal::new(10250, 3));
+        assert!(vs.generate_idx_eq_keys() == vec!["10.25".to_string()]);
+        assert!(
+            PartialValue::new_decimal_str("10.25")
+                .unwrap()
+                .get_idx_eq_key()
+                == "10.25".to_string()
+        );
+    }
+}
@@ -89,6 +89,10 @@ impl ValueSetT for ValueSetIname {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.set.len()
     }
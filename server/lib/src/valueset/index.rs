@@ -7,7 +7,7 @@ use crate::valueset::{DbValueSetV2, ValueSet};
 
 #[derive(Debug, Clone)]
 pub struct ValueSetIndex {
-    set: SmolSet<[IndexType; 3]>,
+    set: SmolSet<[IndexType; 4]>,
 }
 
 impl ValueSetIndex {
@@ -84,6 +84,10 @@ impl ValueSetT for ValueSetIndex {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.set.len()
     }
@@ -144,7 +148,7 @@ impl ValueSetT for ValueSetIndex {
         Some(Box::new(self.set.iter().copied()))
     }
 
-    fn as_index_set(&self) -> Option<&SmolSet<[IndexType; 3]>> {
+    fn as_index_set(&self) -> Option<&SmolSet<[IndexType; 4]>> {
         Some(&self.set)
     }
 }
@@ -0,0 +1,207 @@
+use smolset::SmolSet;
+
+use crate::prelude::*;
+use crate::repl::proto::ReplAttrV1;
+use crate::schema::SchemaAttribute;
+use crate::valueset::{DbValueSetV2, ValueSet};
+
+/// Ordering keys are the integers with the sign bit flipped, as fixed width hex. This sorts the
+/// same as the integers, including negative ones.
+pub fn int64_to_idx_ord_key(i: &i64) -> String {
+    let u = *i as u64 ^ (1 << 63);
+    format!("{u:016x}")
+}
+
+#[derive(Debug, Clone)]
+pub struct ValueSetInt64 {
+    set: SmolSet<[i64; 1]>,
+}
+
+impl ValueSetInt64 {
+    pub fn new(b: i64) -> Box<Self> {
+        let mut set = SmolSet::new();
+        set.insert(b);
+        Box::new(ValueSetInt64 { set })
+    }
+
+    pub fn push(&mut self, b: i64) -> bool {
+        self.set.insert(b)
+    }
+
+    pub fn from_dbvs2(data: Vec<i64>) -> Result<ValueSet, OperationError> {
+        let set = data.into_iter().collect();
+        Ok(Box::new(ValueSetInt64 { set }))
+    }
+
+    pub fn from_repl_v1(data: &[i64]) -> Result<ValueSet, OperationError> {
+        let set = data.iter().copied().collect();
+        Ok(Box::new(ValueSetInt64 { set }))
+    }
+
+    // We need to allow this, because rust doesn't allow us to impl FromIterator on foreign
+    // types, and i64 is foreign.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter<T>(iter: T) -> Option<Box<Self>>
+    where
+        T: IntoIterator<Item = i64>,
+    {
+        let set = iter.into_iter().collect();
+        Some(Box::new(ValueSetInt64 { set }))
+    }
+}
+
+impl ValueSetT for ValueSetInt64 {
+    fn insert_checked(&mut self, value: Value) -> Result<bool, OperationError> {
+        match value {
+            Value::Int64(u) => Ok(self.set.insert(u)),
+            _ => {
+                debug_assert!(false);
+                Err(OperationError::InvalidValueState)
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.set.clear();
+    }
+
+    fn remove(&mut self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Int64(u) => self.set.remove(u),
+            _ => {
+                debug_assert!(false);
+                true
+            }
+        }
+    }
+
+    fn contains(&self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Int64(u) => self.set.contains(u),
+            _ => false,
+        }
+    }
+
+    fn substring(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
+    fn lessthan(&self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Int64(u) => self.set.iter().any(|i| i < u),
+            _ => false,
+        }
+    }
+
+    fn greaterthan(&self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Int64(u) => self.set.iter().any(|i| i > u),
+            _ => false,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    fn generate_idx_eq_keys(&self) -> Vec<String> {
+        self.set.iter().map(|b| b.to_string()).collect()
+    }
+
+    fn generate_idx_ord_keys(&self) -> Vec<String> {
+        self.set.iter().map(int64_to_idx_ord_key).collect()
+    }
+
+    fn syntax(&self) -> SyntaxType {
+        SyntaxType::Int64
+    }
+
+    fn validate(&self, _schema_attr: &SchemaAttribute) -> bool {
+        true
+    }
+
+    fn to_proto_string_clone_iter(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        Box::new(self.set.iter().map(|b| b.to_string()))
+    }
+
+    fn to_db_valueset_v2(&self) -> DbValueSetV2 {
+        DbValueSetV2::Int64(self.set.iter().cloned().collect())
+    }
+
+    fn to_repl_v1(&self) -> ReplAttrV1 {
+        ReplAttrV1::Int64 {
+            set: self.set.iter().cloned().collect(),
+        }
+    }
+
+    fn to_partialvalue_iter(&self) -> Box<dyn Iterator<Item = PartialValue> + '_> {
+        Box::new(self.set.iter().copied().map(PartialValue::new_int64))
+    }
+
+    fn to_value_iter(&self) -> Box<dyn Iterator<Item = Value> + '_> {
+        Box::new(self.set.iter().copied().map(Value::new_int64))
+    }
+
+    fn equal(&self, other: &ValueSet) -> bool {
+        if let Some(other) = other.as_int64_set() {
+            &self.set == other
+        } else {
+            debug_assert!(false);
+            false
+        }
+    }
+
+    fn merge(&mut self, other: &ValueSet) -> Result<(), OperationError> {
+        if let Some(b) = other.as_int64_set() {
+            mergesets!(self.set, b)
+        } else {
+            debug_assert!(false);
+            Err(OperationError::InvalidValueState)
+        }
+    }
+
+    fn as_int64_set(&self) -> Option<&SmolSet<[i64; 1]>> {
+        Some(&self.set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn test_valueset_basic() {
+        let mut vs = ValueSetInt64::new(-1);
+        assert!(vs.insert_checked(Value::new_int64(-1)) == Ok(false));
+        assert!(vs.insert_checked(Value::new_int64(i64::MAX)) == Ok(true));
+        assert!(vs.insert_checked(Value::new_int64(i64::MAX)) == Ok(false));
+
+        assert!(vs.lessthan(&PartialValue::new_int64(0)));
+        assert!(!vs.lessthan(&PartialValue::new_int64(-1)));
+        assert!(vs.greaterthan(&PartialValue::new_int64(0)));
+        assert!(!vs.greaterthan(&PartialValue::new_int64(i64::MAX)));
+    }
+
+    #[test]
+    fn test_valueset_int64_ord_keys() {
+        let mut vs = ValueSetInt64::new(0);
+        vs.insert_checked(Value::new_int64(-1))
+            .expect("insert failed");
+        vs.insert_checked(Value::new_int64(i64::MIN))
+            .expect("insert failed");
+        vs.insert_checked(Value::new_int64(i64::MAX))
+            .expect("insert failed");
+
+        let mut keys = vs.generate_idx_ord_keys();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            vec![
+                "0000000000000000",
+                "7fffffffffffffff",
+                "8000000000000000",
+                "ffffffffffffffff"
+            ]
+        );
+    }
+}
@@ -102,6 +102,10 @@ impl ValueSetT for ValueSetJwsKeyEs256 {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.set.len()
     }
@@ -283,6 +287,10 @@ impl ValueSetT for ValueSetJwsKeyRs256 {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.set.len()
     }
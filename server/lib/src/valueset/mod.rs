@@ -5,8 +5,9 @@ use dyn_clone::DynClone;
 use hashbrown::HashSet;
 use kanidm_proto::v1::Filter as ProtoFilter;
 use kanidm_proto::v1::UiHint;
+use rust_decimal::Decimal;
 use smolset::SmolSet;
-use time::OffsetDateTime;
+use time::{Date, OffsetDateTime};
 // use std::fmt::Debug;
 use webauthn_rs::prelude::DeviceKey as DeviceKeyV4;
 use webauthn_rs::prelude::Passkey as PasskeyV4;
@@ -23,9 +24,12 @@ mod binary;
 mod bool;
 mod cid;
 mod cred;
+mod date;
 mod datetime;
+mod decimal;
 mod iname;
 mod index;
+mod int64;
 mod iutf8;
 mod json;
 mod jws;
@@ -49,9 +53,12 @@ pub use self::binary::{ValueSetPrivateBinary, ValueSetPublicBinary};
 pub use self::bool::ValueSetBool;
 pub use self::cid::ValueSetCid;
 pub use self::cred::{ValueSetCredential, ValueSetDeviceKey, ValueSetIntentToken, ValueSetPasskey};
-pub use self::datetime::ValueSetDateTime;
+pub use self::date::{date_from_str, date_to_idx_ord_key, date_to_string, ValueSetDate};
+pub use self::datetime::{datetime_to_idx_ord_key, ValueSetDateTime};
+pub use self::decimal::ValueSetDecimal;
 pub use self::iname::ValueSetIname;
 pub use self::index::ValueSetIndex;
+pub use self::int64::{int64_to_idx_ord_key, ValueSetInt64};
 pub use self::iutf8::ValueSetIutf8;
 pub use self::json::ValueSetJsonFilter;
 pub use self::jws::{ValueSetJwsKeyEs256, ValueSetJwsKeyRs256};
@@ -65,7 +72,7 @@ pub use self::ssh::ValueSetSshKey;
 pub use self::syntax::ValueSetSyntax;
 pub use self::totp::ValueSetTotpSecret;
 pub use self::uihint::ValueSetUiHint;
-pub use self::uint32::ValueSetUint32;
+pub use self::uint32::{uint32_to_idx_ord_key, ValueSetUint32};
 pub use self::url::ValueSetUrl;
 pub use self::utf8::ValueSetUtf8;
 pub use self::uuid::{ValueSetRefer, ValueSetUuid};
@@ -95,10 +102,18 @@ pub trait ValueSetT: std::fmt::Debug + DynClone {
 
     fn lessthan(&self, pv: &PartialValue) -> bool;
 
+    fn greaterthan(&self, pv: &PartialValue) -> bool;
+
     fn len(&self) -> usize;
 
     fn generate_idx_eq_keys(&self) -> Vec<String>;
 
+    /// Keys for an ordering index. These must sort in the same order as the values they
+    /// are generated from, so only syntaxes that can be ordered provide them.
+    fn generate_idx_ord_keys(&self) -> Vec<String> {
+        Vec::new()
+    }
+
     fn syntax(&self) -> SyntaxType;
 
     fn validate(&self, schema_attr: &SchemaAttribute) -> bool;
@@ -222,12 +237,22 @@ pub trait ValueSetT: std::fmt::Debug + DynClone {
         None
     }
 
+    fn as_int64_set(&self) -> Option<&SmolSet<[i64; 1]>> {
+        debug_assert!(false);
+        None
+    }
+
+    fn as_decimal_set(&self) -> Option<&BTreeSet<Decimal>> {
+        debug_assert!(false);
+        None
+    }
+
     fn as_syntax_set(&self) -> Option<&SmolSet<[SyntaxType; 1]>> {
         debug_assert!(false);
         None
     }
 
-    fn as_index_set(&self) -> Option<&SmolSet<[IndexType; 3]>> {
+    fn as_index_set(&self) -> Option<&SmolSet<[IndexType; 4]>> {
         debug_assert!(false);
         None
     }
@@ -272,6 +297,11 @@ pub trait ValueSetT: std::fmt::Debug + DynClone {
         None
     }
 
+    fn as_date_set(&self) -> Option<&SmolSet<[Date; 1]>> {
+        debug_assert!(false);
+        None
+    }
+
     fn as_private_binary_set(&self) -> Option<&SmolSet<[Vec<u8>; 1]>> {
         debug_assert!(false);
         None
@@ -587,6 +617,8 @@ pub fn from_result_value_iter(
         Value::Refer(u) => ValueSetRefer::new(u),
         Value::Bool(u) => ValueSetBool::new(u),
         Value::Uint32(u) => ValueSetUint32::new(u),
+        Value::Int64(i) => ValueSetInt64::new(i),
+        Value::Decimal(d) => ValueSetDecimal::new(d),
         Value::Syntax(u) => ValueSetSyntax::new(u),
         Value::Index(u) => ValueSetIndex::new(u),
         Value::SecretValue(u) => ValueSetSecret::new(u),
@@ -597,6 +629,7 @@ pub fn from_result_value_iter(
         Value::Nsuniqueid(u) => ValueSetNsUniqueId::new(u),
         Value::Url(u) => ValueSetUrl::new(u),
         Value::DateTime(u) => ValueSetDateTime::new(u),
+        Value::Date(d) => ValueSetDate::new(d),
         Value::PrivateBinary(u) => ValueSetPrivateBinary::new(u),
         Value::OauthScope(u) => ValueSetOauthScope::new(u),
         Value::Address(u) => ValueSetAddress::new(u),
@@ -645,6 +678,8 @@ pub fn from_value_iter(mut iter: impl Iterator<Item = Value>) -> Result<ValueSet
         Value::Refer(u) => ValueSetRefer::new(u),
         Value::Bool(u) => ValueSetBool::new(u),
         Value::Uint32(u) => ValueSetUint32::new(u),
+        Value::Int64(i) => ValueSetInt64::new(i),
+        Value::Decimal(d) => ValueSetDecimal::new(d),
         Value::Syntax(u) => ValueSetSyntax::new(u),
         Value::Index(u) => ValueSetIndex::new(u),
         Value::SecretValue(u) => ValueSetSecret::new(u),
@@ -655,6 +690,7 @@ pub fn from_value_iter(mut iter: impl Iterator<Item = Value>) -> Result<ValueSet
         Value::Nsuniqueid(u) => ValueSetNsUniqueId::new(u),
         Value::Url(u) => ValueSetUrl::new(u),
         Value::DateTime(u) => ValueSetDateTime::new(u),
+        Value::Date(d) => ValueSetDate::new(d),
         Value::PrivateBinary(u) => ValueSetPrivateBinary::new(u),
         Value::OauthScope(u) => ValueSetOauthScope::new(u),
         Value::Address(u) => ValueSetAddress::new(u),
@@ -694,6 +730,8 @@ pub fn from_db_valueset_v2(dbvs: DbValueSetV2) -> Result<ValueSet, OperationErro
         DbValueSetV2::Reference(set) => ValueSetRefer::from_dbvs2(set),
         DbValueSetV2::Bool(set) => ValueSetBool::from_dbvs2(set),
         DbValueSetV2::Uint32(set) => ValueSetUint32::from_dbvs2(set),
+        DbValueSetV2::Int64(set) => ValueSetInt64::from_dbvs2(set),
+        DbValueSetV2::Decimal(set) => ValueSetDecimal::from_dbvs2(set),
         DbValueSetV2::SyntaxType(set) => ValueSetSyntax::from_dbvs2(set),
         DbValueSetV2::IndexType(set) => ValueSetIndex::from_dbvs2(set),
         DbValueSetV2::SecretValue(set) => ValueSetSecret::from_dbvs2(set),
@@ -704,6 +742,7 @@ pub fn from_db_valueset_v2(dbvs: DbValueSetV2) -> Result<ValueSet, OperationErro
         DbValueSetV2::NsUniqueId(set) => ValueSetNsUniqueId::from_dbvs2(set),
         DbValueSetV2::Url(set) => ValueSetUrl::from_dbvs2(set),
         DbValueSetV2::DateTime(set) => ValueSetDateTime::from_dbvs2(set),
+        DbValueSetV2::Date(set) => ValueSetDate::from_dbvs2(set),
         DbValueSetV2::PrivateBinary(set) => ValueSetPrivateBinary::from_dbvs2(set),
         DbValueSetV2::OauthScope(set) => ValueSetOauthScope::from_dbvs2(set),
         DbValueSetV2::Address(set) => ValueSetAddress::from_dbvs2(set),
@@ -739,6 +778,8 @@ pub fn from_repl_v1(rv1: &ReplAttrV1) -> Result<ValueSet, OperationError> {
         ReplAttrV1::Bool { set } => ValueSetBool::from_repl_v1(set),
         ReplAttrV1::Uuid { set } => ValueSetUuid::from_repl_v1(set),
         ReplAttrV1::Uint32 { set } => ValueSetUint32::from_repl_v1(set),
+        ReplAttrV1::Int64 { set } => ValueSetInt64::from_repl_v1(set),
+        ReplAttrV1::Decimal { set } => ValueSetDecimal::from_repl_v1(set),
         ReplAttrV1::Iname { set } => ValueSetIname::from_repl_v1(set),
         ReplAttrV1::PrivateBinary { set } => ValueSetPrivateBinary::from_repl_v1(set),
         ReplAttrV1::SecretValue { set } => ValueSetSecret::from_repl_v1(set),
@@ -758,6 +799,7 @@ pub fn from_repl_v1(rv1: &ReplAttrV1) -> Result<ValueSet, OperationError> {
         ReplAttrV1::Passkey { set } => ValueSetPasskey::from_repl_v1(set),
         ReplAttrV1::DeviceKey { set } => ValueSetDeviceKey::from_repl_v1(set),
         ReplAttrV1::DateTime { set } => ValueSetDateTime::from_repl_v1(set),
+        ReplAttrV1::Date { set } => ValueSetDate::from_repl_v1(set),
         ReplAttrV1::Url { set } => ValueSetUrl::from_repl_v1(set),
         ReplAttrV1::NsUniqueId { set } => ValueSetNsUniqueId::from_repl_v1(set),
         ReplAttrV1::RestrictedString { set } => ValueSetRestricted::from_repl_v1(set),
@@ -74,6 +74,10 @@ impl ValueSetT for ValueSetSecret {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.set.len()
     }
@@ -324,6 +324,10 @@ impl ValueSetT for ValueSetSession {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.map.len()
     }
@@ -741,6 +745,10 @@ impl ValueSetT for ValueSetOauth2Session {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.map.len()
     }
@@ -1099,6 +1107,10 @@ impl ValueSetT for ValueSetApiToken {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.map.len()
     }
@@ -102,6 +102,10 @@ impl ValueSetT for ValueSetTotpSecret {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.map.len()
     }
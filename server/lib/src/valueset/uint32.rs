@@ -5,6 +5,11 @@ use crate::repl::proto::ReplAttrV1;
 use crate::schema::SchemaAttribute;
 use crate::valueset::{DbValueSetV2, ValueSet};
 
+/// Ordering keys are fixed width hex so that they sort the same as the integers.
+pub fn uint32_to_idx_ord_key(u: &u32) -> String {
+    format!("{u:08x}")
+}
+
 #[derive(Debug, Clone)]
 pub struct ValueSetUint32 {
     set: SmolSet<[u32; 1]>,
@@ -86,6 +91,13 @@ impl ValueSetT for ValueSetUint32 {
         }
     }
 
+    fn greaterthan(&self, pv: &PartialValue) -> bool {
+        match pv {
+            PartialValue::Uint32(u) => self.set.iter().any(|i| i > u),
+            _ => false,
+        }
+    }
+
     fn len(&self) -> usize {
         self.set.len()
     }
@@ -94,6 +106,10 @@ impl ValueSetT for ValueSetUint32 {
         self.set.iter().map(|b| b.to_string()).collect()
     }
 
+    fn generate_idx_ord_keys(&self) -> Vec<String> {
+        self.set.iter().map(uint32_to_idx_ord_key).collect()
+    }
+
     fn syntax(&self) -> SyntaxType {
         SyntaxType::Uint32
     }
@@ -166,4 +182,17 @@ mod tests {
         assert!(vs.insert_checked(Value::new_uint32(1)) == Ok(true));
         assert!(vs.insert_checked(Value::new_uint32(1)) == Ok(false));
     }
+
+    #[test]
+    fn test_valueset_uint32_ord_keys() {
+        let mut vs = ValueSetUint32::new(10);
+        vs.insert_checked(Value::new_uint32(9))
+            .expect("insert failed");
+        vs.insert_checked(Value::new_uint32(u32::MAX))
+            .expect("insert failed");
+
+        let mut keys = vs.generate_idx_ord_keys();
+        keys.sort_unstable();
+        assert_eq!(keys, vec!["00000009", "0000000a", "ffffffff"]);
+    }
 }
@@ -75,6 +75,10 @@ impl ValueSetT for ValueSetUtf8 {
         false
     }
 
+    fn greaterthan(&self, _pv: &PartialValue) -> bool {
+        false
+    }
+
     fn len(&self) -> usize {
         self.set.len()
     }
@@ -6,13 +6,14 @@ use wasm_bindgen_futures::JsFuture;
 use web_sys::{Request, RequestInit, RequestMode, Response};
 use yew::prelude::*;
 
-use super::reset::{EventBusMsg, ModalProps};
+use super::reset::{EventBusMsg, PasskeyClass, PasskeyModalProps};
 use crate::error::*;
 use crate::utils;
 
 pub struct PasskeyModalApp {
     state: State,
     label_val: String,
+    class: PasskeyClass,
 }
 
 pub enum State {
@@ -47,11 +48,18 @@ impl From<FetchError> for Msg {
 
 impl PasskeyModalApp {
     fn reset_and_hide(&mut self) {
-        utils::modal_hide_by_id("staticPasskeyCreate");
+        utils::modal_hide_by_id(&format!("static{}Create", self.class.id_prefix()));
         self.state = State::Init;
         self.label_val = "".to_string();
     }
 
+    fn label_id(&self) -> String {
+        match self.class {
+            PasskeyClass::Passkey => "passkey-label".to_string(),
+            PasskeyClass::DeviceKey => "devicekey-label".to_string(),
+        }
+    }
+
     async fn submit_passkey_update(
         token: CUSessionToken,
         req: CURequest,
@@ -98,7 +106,9 @@ impl PasskeyModalApp {
                     emsg: "Invalid Passkey reg state response".to_string(),
                     kopid,
                 },
-                CURegState::Passkey(challenge) => Msg::ChallengeReady(challenge),
+                CURegState::Passkey(challenge) | CURegState::DeviceKey(challenge) => {
+                    Msg::ChallengeReady(challenge)
+                }
                 CURegState::None => Msg::Success,
             })
         } else {
@@ -111,14 +121,15 @@ impl PasskeyModalApp {
 
 impl Component for PasskeyModalApp {
     type Message = Msg;
-    type Properties = ModalProps;
+    type Properties = PasskeyModalProps;
 
-    fn create(_ctx: &Context<Self>) -> Self {
+    fn create(ctx: &Context<Self>) -> Self {
         console::debug!("passkey modal create");
 
         PasskeyModalApp {
             state: State::Init,
             label_val: "".to_string(),
+            class: ctx.props().class,
         }
     }
 
@@ -132,7 +143,7 @@ impl Component for PasskeyModalApp {
         let cb = ctx.props().cb.clone();
         match msg {
             Msg::LabelCheck => {
-                let label = utils::get_value_from_element_id("passkey-label")
+                let label = utils::get_value_from_element_id(&self.label_id())
                     // Default is empty string.
                     .unwrap_or_default();
 
@@ -144,15 +155,13 @@ impl Component for PasskeyModalApp {
                     let label = self.label_val.clone();
                     // Init a fetch to get the challenge.
                     let token_c = ctx.props().token.clone();
+                    let req = match self.class {
+                        PasskeyClass::Passkey => CURequest::PasskeyFinish(label, rpkc),
+                        PasskeyClass::DeviceKey => CURequest::DeviceKeyFinish(label, rpkc),
+                    };
 
                     ctx.link().send_future(async {
-                        match Self::submit_passkey_update(
-                            token_c,
-                            CURequest::PasskeyFinish(label, rpkc),
-                            cb,
-                        )
-                        .await
-                        {
+                        match Self::submit_passkey_update(token_c, req, cb).await {
                             Ok(v) => v,
                             Err(v) => v.into(),
                         }
@@ -168,9 +177,13 @@ impl Component for PasskeyModalApp {
             Msg::Generate => {
                 // Init a fetch to get the challenge.
                 let token_c = ctx.props().token.clone();
+                let req = match self.class {
+                    PasskeyClass::Passkey => CURequest::PasskeyInit,
+                    PasskeyClass::DeviceKey => CURequest::DeviceKeyInit,
+                };
 
                 ctx.link().send_future(async {
-                    match Self::submit_passkey_update(token_c, CURequest::PasskeyInit, cb).await {
+                    match Self::submit_passkey_update(token_c, req, cb).await {
                         Ok(v) => v,
                         Err(v) => v.into(),
                     }
@@ -258,6 +271,8 @@ impl Component for PasskeyModalApp {
         console::debug!("passkey modal::view");
 
         let label_val = self.label_val.clone();
+        let label_id = self.label_id();
+        let class = self.class;
 
         let passkey_state = match &self.state {
             State::Init => {
@@ -271,7 +286,7 @@ impl Component for PasskeyModalApp {
                         }
                     >
                     // TODO: start the session once the modal is popped up
-                    { "Start Creating a New Passkey" }</button>
+                    { format!("Start Creating a New {}", class) }</button>
                 }
             }
             State::Submitting | State::FetchingChallenge => {
@@ -296,7 +311,7 @@ impl Component for PasskeyModalApp {
             }
             State::CredentialReady(_) => {
                 html! {
-                    <h3>{ "Passkey Created!" }</h3>
+                    <h3>{ format!("{} Created!", class) }</h3>
                 }
             }
         };
@@ -320,11 +335,11 @@ impl Component for PasskeyModalApp {
                             }
                         } ) }
                     >
-                      <label for="passkey-label" class="form-label">{ "Please name this Passkey" }</label>
+                      <label for={ label_id.clone() } class="form-label">{ format!("Please name this {}", class) }</label>
                       <input
                         type="text"
                         class="form-control"
-                        id="passkey-label"
+                        id={ label_id }
                         placeholder=""
                         value={ label_val }
                         required=true
@@ -362,12 +377,15 @@ impl Component for PasskeyModalApp {
             }
         };
 
+        let modal_id = format!("static{}Create", class.id_prefix());
+        let modal_label = format!("static{}Label", class.id_prefix());
+
         html! {
-            <div class="modal fade" id="staticPasskeyCreate" data-bs-backdrop="static" data-bs-keyboard="false" tabindex="-1" aria-labelledby="staticPasskeyLabel" aria-hidden="true">
+            <div class="modal fade" id={ modal_id } data-bs-backdrop="static" data-bs-keyboard="false" tabindex="-1" aria-labelledby={ modal_label.clone() } aria-hidden="true">
               <div class="modal-dialog">
                 <div class="modal-content">
                   <div class="modal-header">
-                    <h5 class="modal-title" id="staticPasskeyLabel">{ "Add a New Passkey" }</h5>
+                    <h5 class="modal-title" id={ modal_label }>{ format!("Add a New {}", class) }</h5>
                     <button type="button" class="btn-close" aria-label="Close"
                         onclick={
                             ctx.link()
@@ -7,7 +7,7 @@ use wasm_bindgen_futures::JsFuture;
 use web_sys::{Request, RequestInit, RequestMode, Response};
 use yew::prelude::*;
 
-use super::reset::{EventBusMsg, PasskeyRemoveModalProps};
+use super::reset::{EventBusMsg, PasskeyClass, PasskeyRemoveModalProps};
 use crate::error::*;
 use crate::utils;
 
@@ -16,6 +16,7 @@ pub struct PasskeyRemoveModalApp {
     target: String,
     tag: String,
     uuid: Uuid,
+    class: PasskeyClass,
 }
 
 pub enum State {
@@ -40,8 +41,8 @@ impl From<FetchError> for Msg {
 }
 
 impl PasskeyRemoveModalApp {
-    pub fn render_button(tag: &str, uuid: Uuid) -> Html {
-        let remove_tgt = format!("#staticPasskeyRemove-{}", uuid);
+    pub fn render_button(tag: &str, uuid: Uuid, class: PasskeyClass) -> Html {
+        let remove_tgt = format!("#static{}Remove-{}", class.id_prefix(), uuid);
         let tag = tag.to_string();
 
         html! {
@@ -104,6 +105,7 @@ impl PasskeyRemoveModalApp {
                 | CURegState::TotpTryAgain
                 | CURegState::TotpInvalidSha1
                 | CURegState::Passkey(_)
+                | CURegState::DeviceKey(_)
                 | CURegState::BackupCodes(_) => Msg::Error {
                     emsg: "Invalid Passkey reg state response".to_string(),
                     kopid,
@@ -128,13 +130,15 @@ impl Component for PasskeyRemoveModalApp {
 
         let tag = ctx.props().tag.clone();
         let uuid = ctx.props().uuid;
-        let target = format!("staticPasskeyRemove-{}", uuid);
+        let class = ctx.props().class;
+        let target = format!("static{}Remove-{}", class.id_prefix(), uuid);
 
         PasskeyRemoveModalApp {
             state: State::Init,
             tag,
             uuid,
             target,
+            class,
         }
     }
 
@@ -154,12 +158,13 @@ impl Component for PasskeyRemoveModalApp {
 
                 // Do the call back.
                 let token_c = ctx.props().token.clone();
-                let uuid = self.uuid;
+                let req = match self.class {
+                    PasskeyClass::Passkey => CURequest::PasskeyRemove(self.uuid),
+                    PasskeyClass::DeviceKey => CURequest::DeviceKeyRemove(self.uuid),
+                };
 
                 ctx.link().send_future(async move {
-                    match Self::submit_passkey_update(token_c, CURequest::PasskeyRemove(uuid), cb)
-                        .await
-                    {
+                    match Self::submit_passkey_update(token_c, req, cb).await {
                         Ok(v) => v,
                         Err(v) => v.into(),
                     }
@@ -194,10 +199,12 @@ impl Component for PasskeyRemoveModalApp {
         console::debug!("passkey remove modal::view");
 
         let remove_tgt = self.target.clone();
-        let remove_id = format!("staticPasskeyRemove-{}", self.uuid);
-        let remove_label = format!("staticPasskeyRemoveLabel-{}", self.uuid);
+        let prefix = self.class.id_prefix();
+        let remove_id = format!("static{}Remove-{}", prefix, self.uuid);
+        let remove_label = format!("static{}RemoveLabel-{}", prefix, self.uuid);
 
-        let msg = format!("Delete the Passkey named '{}'?", self.tag);
+        let msg = format!("Delete the {} named '{}'?", self.class, self.tag);
+        let title = format!("Delete {}", self.class);
 
         let submit_enabled = matches!(self.state, State::Init);
 
@@ -206,7 +213,7 @@ impl Component for PasskeyRemoveModalApp {
               <div class="modal-dialog">
                 <div class="modal-content">
                   <div class="modal-header">
-                    <h5 class="modal-title" id={ remove_label }>{ "Delete Passkey" }</h5>
+                    <h5 class="modal-title" id={ remove_label }>{ title }</h5>
                     <button type="button" class="btn-close" aria-label="Close"
                         onclick={
                             ctx.link()
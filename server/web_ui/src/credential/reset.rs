@@ -33,6 +33,39 @@ pub struct ModalProps {
     pub cb: Callback<EventBusMsg>,
 }
 
+/// Passkeys and device keys share the same modals, and only differ in the requests they
+/// make and how they are labelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasskeyClass {
+    Passkey,
+    DeviceKey,
+}
+
+impl PasskeyClass {
+    pub fn id_prefix(&self) -> &'static str {
+        match self {
+            PasskeyClass::Passkey => "Passkey",
+            PasskeyClass::DeviceKey => "DeviceKey",
+        }
+    }
+}
+
+impl std::fmt::Display for PasskeyClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PasskeyClass::Passkey => write!(f, "Passkey"),
+            PasskeyClass::DeviceKey => write!(f, "Device Key"),
+        }
+    }
+}
+
+#[derive(PartialEq, Properties)]
+pub struct PasskeyModalProps {
+    pub token: CUSessionToken,
+    pub cb: Callback<EventBusMsg>,
+    pub class: PasskeyClass,
+}
+
 #[derive(PartialEq, Properties)]
 pub struct TotpRemoveProps {
     pub token: CUSessionToken,
@@ -46,6 +79,7 @@ pub struct PasskeyRemoveModalProps {
     pub tag: String,
     pub uuid: Uuid,
     pub cb: Callback<EventBusMsg>,
+    pub class: PasskeyClass,
 }
 
 pub enum Msg {
@@ -471,7 +505,23 @@ impl CredentialResetApp {
                 <>
                 { for status.passkeys.iter()
                     .map(|detail|
-                        PasskeyRemoveModalApp::render_button(&detail.tag, detail.uuid)
+                        PasskeyRemoveModalApp::render_button(&detail.tag, detail.uuid, PasskeyClass::Passkey)
+                    )
+                }
+                </>
+            }
+        };
+
+        let devicekey_html = if status.devicekeys.is_empty() {
+            html! {
+                <p>{ "No Device Keys Registered" }</p>
+            }
+        } else {
+            html! {
+                <>
+                { for status.devicekeys.iter()
+                    .map(|detail|
+                        PasskeyRemoveModalApp::render_button(&detail.tag, detail.uuid, PasskeyClass::DeviceKey)
                     )
                 }
                 </>
@@ -482,7 +532,12 @@ impl CredentialResetApp {
             <>
                 { for status.passkeys.iter()
                     .map(|detail|
-                        html! { <PasskeyRemoveModalApp token={ token.clone() } tag={ detail.tag.clone() } uuid={ detail.uuid } cb={ cb.clone() } /> }
+                        html! { <PasskeyRemoveModalApp token={ token.clone() } tag={ detail.tag.clone() } uuid={ detail.uuid } cb={ cb.clone() } class={ PasskeyClass::Passkey } /> }
+                    )
+                }
+                { for status.devicekeys.iter()
+                    .map(|detail|
+                        html! { <PasskeyRemoveModalApp token={ token.clone() } tag={ detail.tag.clone() } uuid={ detail.uuid } cb={ cb.clone() } class={ PasskeyClass::DeviceKey } /> }
                     )
                 }
             </>
@@ -510,6 +565,16 @@ impl CredentialResetApp {
                       { "Add Passkey" }
                     </button>
 
+                    <hr class="my-4" />
+                    <h4>{"Device Keys"}</h4>
+                    <p>{ "Hardware security keys that your organisation has approved. These can only be enrolled if your administrator has configured which devices are trusted." }</p>
+
+                    { devicekey_html }
+
+                    <button type="button" class="btn btn-primary" data-bs-toggle="modal" data-bs-target="#staticDeviceKeyCreate">
+                      { "Add Device Key" }
+                    </button>
+
                     <hr class="my-4" />
 
                     <h4>{"Password / TOTP"}</h4>
@@ -543,7 +608,9 @@ impl CredentialResetApp {
               </div>
             </main>
 
-            <PasskeyModalApp token={ token.clone() } cb={ cb.clone() } />
+            <PasskeyModalApp token={ token.clone() } cb={ cb.clone() } class={ PasskeyClass::Passkey } />
+
+            <PasskeyModalApp token={ token.clone() } cb={ cb.clone() } class={ PasskeyClass::DeviceKey } />
 
             <PwModalApp token={ token.clone() } cb={ cb.clone() } />
 
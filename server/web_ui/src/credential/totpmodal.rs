@@ -104,10 +104,12 @@ impl TotpModalApp {
             });
 
             Ok(match status.mfaregstate {
-                CURegState::Passkey(_) | CURegState::BackupCodes(_) => Msg::Error {
-                    emsg: "Invalid TOTP mfa reg state response".to_string(),
-                    kopid,
-                },
+                CURegState::Passkey(_) | CURegState::DeviceKey(_) | CURegState::BackupCodes(_) => {
+                    Msg::Error {
+                        emsg: "Invalid TOTP mfa reg state response".to_string(),
+                        kopid,
+                    }
+                }
                 CURegState::None => Msg::TotpSuccess,
                 CURegState::TotpCheck(secret) => Msg::TotpSecretReady(secret),
                 CURegState::TotpTryAgain => Msg::TotpTryAgain,
@@ -23,6 +23,7 @@ mod login;
 mod manager;
 mod models;
 mod oauth2;
+mod oauth2_device;
 mod utils;
 mod views;
 
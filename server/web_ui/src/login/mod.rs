@@ -54,6 +54,7 @@ enum LoginState {
     BackupCode(bool),
     Totp(TotpState),
     Passkey(CredentialRequestOptions),
+    DeviceKey(CredentialRequestOptions),
     SecurityKey(CredentialRequestOptions),
     // Error, state handling.
     Error { emsg: String, kopid: Option<String> },
@@ -70,6 +71,7 @@ pub enum LoginAppMsg {
     BackupCodeSubmit,
     TotpSubmit,
     PasskeySubmit(PublicKeyCredential),
+    DeviceKeySubmit(PublicKeyCredential),
     SecurityKeySubmit(PublicKeyCredential),
     Start(AuthResponse),
     Next(AuthResponse),
@@ -556,7 +558,8 @@ impl LoginApp {
                     </div>
                 }
             }
-            LoginState::Passkey(challenge) => {
+            LoginState::Passkey(challenge) | LoginState::DeviceKey(challenge) => {
+                let is_devicekey = matches!(self.state, LoginState::DeviceKey(_));
                 // Start the navigator parts.
                 if let Some(win) = web_sys::window() {
                     let promise = win
@@ -573,7 +576,11 @@ impl LoginApp {
                                 let data = PublicKeyCredential::from(
                                     web_sys::PublicKeyCredential::from(data),
                                 );
-                                linkc.send_message(LoginAppMsg::PasskeySubmit(data));
+                                if is_devicekey {
+                                    linkc.send_message(LoginAppMsg::DeviceKeySubmit(data));
+                                } else {
+                                    linkc.send_message(LoginAppMsg::PasskeySubmit(data));
+                                }
                             }
                             Err(e) => {
                                 linkc.send_message(LoginAppMsg::Error {
@@ -593,7 +600,13 @@ impl LoginApp {
                 html! {
                     <div class="container text-center">
                         <p>
-                        {"Prompting for Passkey authentication..."}
+                        {
+                            if is_devicekey {
+                                "Prompting for Device Key authentication..."
+                            } else {
+                                "Prompting for Passkey authentication..."
+                            }
+                        }
                         </p>
                     </div>
                 }
@@ -869,6 +882,21 @@ impl Component for LoginApp {
                 // Do not submit here, we need to wait for the next ui transition.
                 false
             }
+            LoginAppMsg::DeviceKeySubmit(resp) => {
+                #[cfg(debug_assertions)]
+                console::debug!("At devicekey step".to_string());
+                let authreq = AuthRequest {
+                    step: AuthStep::Cred(AuthCredential::DeviceKey(Box::new(resp))),
+                };
+                ctx.link().send_future(async {
+                    match Self::auth_step(authreq).await {
+                        Ok(v) => v,
+                        Err(v) => v.into(),
+                    }
+                });
+                // Do not submit here, we need to wait for the next ui transition.
+                false
+            }
             LoginAppMsg::Start(resp) => {
                 // Clear any leftover input
                 self.inputvalue = "".to_string();
@@ -986,6 +1014,9 @@ impl Component for LoginApp {
                                 AuthAllowed::Passkey(challenge) => {
                                     self.state = LoginState::Passkey(challenge.into())
                                 }
+                                AuthAllowed::DeviceKey(challenge) => {
+                                    self.state = LoginState::DeviceKey(challenge.into())
+                                }
                             }
                         } else {
                             // Else, present the options in a choice.
@@ -1045,6 +1076,9 @@ impl Component for LoginApp {
                             Some(AuthAllowed::Passkey(challenge)) => {
                                 self.state = LoginState::Passkey(challenge.clone().into())
                             }
+                            Some(AuthAllowed::DeviceKey(challenge)) => {
+                                self.state = LoginState::DeviceKey(challenge.clone().into())
+                            }
                             None => {
                                 console::error!("invalid allowed mech idx".to_string());
                                 self.state = LoginState::Error {
@@ -14,6 +14,7 @@ use yew_router::prelude::*;
 use crate::credential::reset::CredentialResetApp;
 use crate::login::{LoginApp, LoginWorkflow};
 use crate::oauth2::Oauth2App;
+use crate::oauth2_device::Oauth2DeviceApp;
 use crate::views::{ViewRoute, ViewsApp};
 
 // router to decide on state.
@@ -34,6 +35,9 @@ pub enum Route {
     #[at("/ui/oauth2")]
     Oauth2,
 
+    #[at("/ui/oauth2/device")]
+    Oauth2Device,
+
     #[at("/ui/reset")]
     CredentialReset,
 
@@ -65,6 +69,7 @@ fn switch(route: Route) -> Html {
         Route::Reauth => html! { <LoginApp workflow={ LoginWorkflow::Reauth } /> },
         #[allow(clippy::let_unit_value)]
         Route::Oauth2 => html! { <Oauth2App /> },
+        Route::Oauth2Device => html! { <Oauth2DeviceApp /> },
         #[allow(clippy::let_unit_value)]
         Route::Views => html! { <ViewsApp /> },
         #[allow(clippy::let_unit_value)]
@@ -1,7 +1,7 @@
 // use anyhow::Error;
 use gloo::console;
 pub use kanidm_proto::oauth2::{
-    AccessTokenRequest, AccessTokenResponse, AuthorisationRequest, AuthorisationResponse,
+    AccessTokenRequest, AccessTokenResponse, AuthorisationRequestKind, AuthorisationResponse,
     CodeChallengeMethod, ErrorResponse,
 };
 use wasm_bindgen::{JsCast, JsValue, UnwrapThrowExt};
@@ -100,7 +100,7 @@ impl Oauth2App {
         }
     }
 
-    async fn fetch_authreq(authreq: AuthorisationRequest) -> Result<Oauth2Msg, FetchError> {
+    async fn fetch_authreq(authreq: AuthorisationRequestKind) -> Result<Oauth2Msg, FetchError> {
         let authreq_jsvalue = serde_json::to_string(&authreq)
             .map(|s| JsValue::from(&s))
             .expect_throw("Failed to serialise authreq");
@@ -223,7 +223,7 @@ impl Component for Oauth2App {
             .location()
             .expect_throw("Can't access browser current location");
 
-        let query: Option<AuthorisationRequest> = location
+        let query: Option<AuthorisationRequestKind> = location
             .query()
             .map_err(|e| {
                 let e_msg = format!(
@@ -254,9 +254,12 @@ impl Component for Oauth2App {
         // In the query, if this is openid there MAY be a hint
         // as to the users name.
         // See: https://openid.net/specs/openid-connect-basic-1_0.html#RequestParameters
-        // specifically, login_hint
-        if let Some(login_hint) = query.oidc_ext.login_hint.clone() {
-            models::push_login_hint(login_hint)
+        // specifically, login_hint. If the request was pushed or signed we
+        // can't see it here.
+        if let AuthorisationRequestKind::Request(auth_req) = &query {
+            if let Some(login_hint) = auth_req.oidc_ext.login_hint.clone() {
+                models::push_login_hint(login_hint)
+            }
         }
         // Push the request down. This covers if we move to LoginRequired so we can restore where
         // we were / what we were doing.
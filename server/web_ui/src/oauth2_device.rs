@@ -0,0 +1,554 @@
+//! The user code verification page for the oauth2 device authorisation grant. A device
+//! that can't open a browser shows the person a short user code, which they enter
+//! here to be presented with the same consent prompt as the authorisation code flow.
+
+use gloo::console;
+pub use kanidm_proto::oauth2::AuthorisationResponse;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{JsCast, JsValue, UnwrapThrowExt};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Request, RequestCredentials, RequestInit, RequestMode, Response};
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::error::*;
+use crate::manager::Route;
+use crate::{models, utils};
+
+use std::collections::BTreeSet;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct DeviceQuery {
+    user_code: Option<String>,
+}
+
+enum State {
+    LoginRequired,
+    // We are in the process of check the auth token to be sure we can proceed.
+    TokenCheck,
+    EnterCode,
+    SubmitCode,
+    Consent {
+        client_name: String,
+        pii_scopes: BTreeSet<String>,
+        consent_token: String,
+    },
+    // Waiting on the permit or reject to complete.
+    Submitting,
+    Permitted(String),
+    Rejected,
+    AccessDenied(Option<String>),
+    ErrInvalidCode,
+    ErrInvalidRequest,
+}
+
+pub struct Oauth2DeviceApp {
+    state: State,
+    user_code: String,
+}
+
+pub enum Oauth2DeviceMsg {
+    LoginRequired,
+    LoginProceed,
+    TokenValid,
+    Input(String),
+    SubmitCode,
+    Consent {
+        client_name: String,
+        pii_scopes: BTreeSet<String>,
+        consent_token: String,
+    },
+    ConsentGranted,
+    ConsentRejected,
+    Permitted,
+    Rejected,
+    InvalidCode,
+    AccessDenied {
+        kopid: Option<String>,
+    },
+    Error {
+        emsg: String,
+        kopid: Option<String>,
+    },
+}
+
+impl From<FetchError> for Oauth2DeviceMsg {
+    fn from(fe: FetchError) -> Self {
+        Oauth2DeviceMsg::Error {
+            emsg: fe.as_string(),
+            kopid: None,
+        }
+    }
+}
+
+impl Oauth2DeviceApp {
+    async fn fetch_session_valid() -> Result<Oauth2DeviceMsg, FetchError> {
+        let mut opts = RequestInit::new();
+        opts.method("GET");
+        opts.mode(RequestMode::SameOrigin);
+        opts.credentials(RequestCredentials::SameOrigin);
+        let request = Request::new_with_str_and_init("/v1/auth/valid", &opts)?;
+
+        request
+            .headers()
+            .set("content-type", "application/json")
+            .expect_throw("failed to set header");
+
+        let window = utils::window();
+        let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+        let resp: Response = resp_value.dyn_into().expect_throw("Invalid response type");
+        let status = resp.status();
+
+        if status == 200 {
+            Ok(Oauth2DeviceMsg::TokenValid)
+        } else if status == 401 {
+            Ok(Oauth2DeviceMsg::LoginRequired)
+        } else {
+            let headers = resp.headers();
+            let kopid = headers.get("x-kanidm-opid").ok().flatten();
+            let text = JsFuture::from(resp.text()?).await?;
+            let emsg = text.as_string().unwrap_or_default();
+            Ok(Oauth2DeviceMsg::Error { emsg, kopid })
+        }
+    }
+
+    async fn fetch_user_code(user_code: String) -> Result<Oauth2DeviceMsg, FetchError> {
+        let user_code_jsvalue = serde_json::to_string(&user_code)
+            .map(|s| JsValue::from(&s))
+            .expect_throw("Failed to serialise user_code");
+
+        let mut opts = RequestInit::new();
+        opts.method("POST");
+        opts.mode(RequestMode::SameOrigin);
+        opts.credentials(RequestCredentials::SameOrigin);
+
+        opts.body(Some(&user_code_jsvalue));
+
+        let request = Request::new_with_str_and_init("/oauth2/device/authorise", &opts)?;
+        request
+            .headers()
+            .set("content-type", "application/json")
+            .expect_throw("failed to set header");
+
+        let window = utils::window();
+        let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+        let resp: Response = resp_value.dyn_into().expect_throw("Invalid response type");
+        let status = resp.status();
+        let headers = resp.headers();
+        let kopid = headers.get("x-kanidm-opid").ok().flatten();
+
+        if status == 200 {
+            let jsval = JsFuture::from(resp.json()?).await?;
+            let state: AuthorisationResponse = serde_wasm_bindgen::from_value(jsval)
+                .map_err(|e| {
+                    let e_msg = format!("serde error -> {:?}", e);
+                    console::error!(e_msg.as_str());
+                })
+                .expect_throw("Invalid response type");
+            match state {
+                AuthorisationResponse::ConsentRequested {
+                    client_name,
+                    scopes: _,
+                    pii_scopes,
+                    consent_token,
+                } => Ok(Oauth2DeviceMsg::Consent {
+                    client_name,
+                    pii_scopes,
+                    consent_token,
+                }),
+                AuthorisationResponse::Permitted => Ok(Oauth2DeviceMsg::Error {
+                    emsg: "device authorisation must request consent".to_string(),
+                    kopid,
+                }),
+            }
+        } else if status == 400 {
+            Ok(Oauth2DeviceMsg::InvalidCode)
+        } else if status == 401 {
+            Ok(Oauth2DeviceMsg::LoginRequired)
+        } else if status == 403 {
+            Ok(Oauth2DeviceMsg::AccessDenied { kopid })
+        } else {
+            let text = JsFuture::from(resp.text()?).await?;
+            let emsg = text.as_string().unwrap_or_default();
+            Ok(Oauth2DeviceMsg::Error { emsg, kopid })
+        }
+    }
+
+    async fn fetch_consent_token(
+        consent_token: String,
+        permit: bool,
+    ) -> Result<Oauth2DeviceMsg, FetchError> {
+        let consentreq_jsvalue = serde_json::to_string(&consent_token)
+            .map(|s| JsValue::from(&s))
+            .expect_throw("Failed to serialise consent_req");
+
+        let mut opts = RequestInit::new();
+        opts.method("POST");
+        opts.mode(RequestMode::SameOrigin);
+        opts.credentials(RequestCredentials::SameOrigin);
+
+        opts.body(Some(&consentreq_jsvalue));
+
+        let url = if permit {
+            "/oauth2/device/permit"
+        } else {
+            "/oauth2/device/reject"
+        };
+
+        let request = Request::new_with_str_and_init(url, &opts)?;
+        request
+            .headers()
+            .set("content-type", "application/json")
+            .expect_throw("failed to set header");
+
+        let window = utils::window();
+        let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+        let resp: Response = resp_value.dyn_into().expect_throw("Invalid response type");
+        let status = resp.status();
+        let headers = resp.headers();
+        let kopid = headers.get("x-kanidm-opid").ok().flatten();
+
+        if status == 200 {
+            if permit {
+                Ok(Oauth2DeviceMsg::Permitted)
+            } else {
+                Ok(Oauth2DeviceMsg::Rejected)
+            }
+        } else {
+            let text = JsFuture::from(resp.text()?).await?;
+            let emsg = text.as_string().unwrap_or_default();
+            Ok(Oauth2DeviceMsg::Error { emsg, kopid })
+        }
+    }
+}
+
+impl Component for Oauth2DeviceApp {
+    type Message = Oauth2DeviceMsg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        #[cfg(debug_assertions)]
+        console::debug!("oauth2_device::create");
+
+        // The verification_uri_complete includes the user code, so we can fill it in
+        // for the user. Stash it in case we need to go and login first.
+        let query: Option<DeviceQuery> = ctx
+            .link()
+            .location()
+            .and_then(|location| location.query().ok());
+
+        if let Some(user_code) = query.and_then(|q| q.user_code) {
+            models::push_oauth2_device_user_code(user_code);
+        }
+
+        add_body_form_classes!();
+
+        ctx.link().send_future(async {
+            match Self::fetch_session_valid().await {
+                Ok(v) => v,
+                Err(v) => v.into(),
+            }
+        });
+
+        Oauth2DeviceApp {
+            state: State::TokenCheck,
+            user_code: String::new(),
+        }
+    }
+
+    fn changed(&mut self, _ctx: &Context<Self>, _props: &Self::Properties) -> bool {
+        #[cfg(debug_assertions)]
+        console::debug!("oauth2_device::change");
+        false
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        #[cfg(debug_assertions)]
+        console::debug!("oauth2_device::update");
+
+        match msg {
+            Oauth2DeviceMsg::LoginRequired => {
+                self.state = State::LoginRequired;
+                true
+            }
+            Oauth2DeviceMsg::LoginProceed => {
+                models::push_return_location(models::Location::Manager(Route::Oauth2Device));
+
+                ctx.link()
+                    .navigator()
+                    .expect_throw("failed to read history")
+                    .push(&Route::Login);
+                // Don't need to redraw as we are yolo-ing out.
+                false
+            }
+            Oauth2DeviceMsg::TokenValid => {
+                self.user_code = models::pop_oauth2_device_user_code().unwrap_or_default();
+                self.state = State::EnterCode;
+                true
+            }
+            Oauth2DeviceMsg::Input(user_code) => {
+                self.user_code = user_code;
+                false
+            }
+            Oauth2DeviceMsg::SubmitCode => {
+                let user_code = self.user_code.clone();
+                ctx.link().send_future(async {
+                    match Self::fetch_user_code(user_code).await {
+                        Ok(v) => v,
+                        Err(v) => v.into(),
+                    }
+                });
+                self.state = State::SubmitCode;
+                true
+            }
+            Oauth2DeviceMsg::Consent {
+                client_name,
+                pii_scopes,
+                consent_token,
+            } => {
+                self.state = match &self.state {
+                    State::SubmitCode => State::Consent {
+                        client_name,
+                        pii_scopes,
+                        consent_token,
+                    },
+                    _ => {
+                        console::error!("Invalid state transition");
+                        State::ErrInvalidRequest
+                    }
+                };
+                true
+            }
+            Oauth2DeviceMsg::ConsentGranted | Oauth2DeviceMsg::ConsentRejected => {
+                let permit = matches!(msg, Oauth2DeviceMsg::ConsentGranted);
+                self.state = match &self.state {
+                    State::Consent {
+                        consent_token,
+                        client_name,
+                        ..
+                    } => {
+                        let cr_c = consent_token.clone();
+                        ctx.link().send_future(async move {
+                            match Self::fetch_consent_token(cr_c, permit).await {
+                                Ok(v) => v,
+                                Err(v) => v.into(),
+                            }
+                        });
+                        if permit {
+                            State::Permitted(client_name.clone())
+                        } else {
+                            State::Submitting
+                        }
+                    }
+                    _ => {
+                        console::error!("Invalid state transition");
+                        State::ErrInvalidRequest
+                    }
+                };
+                true
+            }
+            Oauth2DeviceMsg::Permitted => {
+                // We optimistically rendered the permitted state already.
+                false
+            }
+            Oauth2DeviceMsg::Rejected => {
+                self.state = State::Rejected;
+                true
+            }
+            Oauth2DeviceMsg::InvalidCode => {
+                self.state = State::ErrInvalidCode;
+                true
+            }
+            Oauth2DeviceMsg::AccessDenied { kopid } => {
+                console::error!(format!("{:?}", kopid).as_str());
+                self.state = State::AccessDenied(kopid);
+                true
+            }
+            Oauth2DeviceMsg::Error { emsg, kopid } => {
+                self.state = State::ErrInvalidRequest;
+                console::error!(format!("{:?}", kopid).as_str());
+                console::error!(emsg.as_str());
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        #[cfg(debug_assertions)]
+        console::debug!("oauth2_device::view");
+
+        let code_form = |invalid: bool| {
+            html! {
+                <form
+                  onsubmit={ ctx.link().callback(|e: SubmitEvent| {
+                      e.prevent_default();
+                      Oauth2DeviceMsg::SubmitCode
+                  } ) }
+                  action="javascript:void(0);"
+                >
+                  <h2 class="h3 mb-3 fw-normal">{ "Connect a Device" }</h2>
+                  <p>{ "Enter the code shown on your device." }</p>
+                  if invalid {
+                    <div class="alert alert-danger" role="alert">
+                      { "That code is invalid or has expired. Please check the code on your device." }
+                    </div>
+                  }
+                  <div class="mb-3">
+                    <input
+                      autofocus=true
+                      class="autofocus form-control"
+                      id="user_code"
+                      name="user_code"
+                      oninput={ ctx.link().callback(|e: InputEvent| Oauth2DeviceMsg::Input(utils::get_value_from_input_event(e))) }
+                      type="text"
+                      autocomplete="off"
+                      value={ self.user_code.clone() }
+                    />
+                  </div>
+                  <button class="w-100 btn btn-lg btn-primary" type="submit">{ "Continue" }</button>
+                </form>
+            }
+        };
+
+        let body_content = match &self.state {
+            State::LoginRequired => {
+                html! {
+                    <form
+                      onsubmit={ ctx.link().callback(|e: SubmitEvent| {
+                          console::debug!("oauth2_device::view -> LoginRequired - prevent_default()");
+                          e.prevent_default();
+                          Oauth2DeviceMsg::LoginProceed
+                      } ) }
+                      action="javascript:void(0);"
+                    >
+                      <h1 class="h3 mb-3 fw-normal">
+                        {"Sign in to proceed" }
+                        </h1>
+                      <button autofocus=true class="w-100 btn btn-lg btn-primary" type="submit">
+                        { "Sign in" }
+                      </button>
+                    </form>
+                }
+            }
+            State::EnterCode => code_form(false),
+            State::ErrInvalidCode => code_form(true),
+            State::Consent {
+                client_name,
+                pii_scopes,
+                consent_token: _,
+            } => {
+                let pii_req = if pii_scopes.is_empty() {
+                    html! {
+                      <div>
+                        <p>{ "This device will not have access to your personal information." }</p>
+                      </div>
+                    }
+                } else {
+                    html! {
+                      <div>
+                        <p>{ "This device has requested to see the following personal information." }</p>
+                        <ul>
+                          {
+                            pii_scopes.iter().map(|s| html! { <li>{ s }</li> } ).collect::<Html>()
+                          }
+                        </ul>
+                      </div>
+                    }
+                };
+
+                html! {
+                      <form
+                        onsubmit={ ctx.link().callback(|e: SubmitEvent| {
+                            console::debug!("oauth2_device::view -> Consent - prevent_default()");
+                            e.prevent_default();
+                            Oauth2DeviceMsg::ConsentGranted
+                        } ) }
+                        action="javascript:void(0);"
+                      >
+                        <h2 class="h3 mb-3 fw-normal">{"Allow a Device to Access " }{ client_name }</h2>
+                        <p>{ "Only continue if you started this request on a device you control." }</p>
+                        { pii_req }
+
+                        <div class="text-center">
+                            <button autofocus=true class="w-100 btn btn-lg btn-primary mb-2" type="submit">{ "Allow" }</button>
+                            <button
+                              class="w-100 btn btn-lg btn-secondary"
+                              type="button"
+                              onclick={ ctx.link().callback(|_| Oauth2DeviceMsg::ConsentRejected) }
+                            >{ "Deny" }</button>
+                        </div>
+                      </form>
+                }
+            }
+            State::Permitted(app_name) => {
+                html! {
+                    <div class="alert alert-success" role="alert">
+                        <h2 class="text-center">{ "Your device is now connected to " }{ app_name }</h2>
+                        <p class="text-center">{ "You can close this window." }</p>
+                    </div>
+                }
+            }
+            State::Rejected => {
+                html! {
+                    <div class="alert alert-light" role="alert">
+                        <h2 class="text-center">{ "The device was denied access" }</h2>
+                        <p class="text-center">{ "You can close this window." }</p>
+                    </div>
+                }
+            }
+            State::SubmitCode | State::Submitting | State::TokenCheck => {
+                html! {
+                    <div class="alert alert-light" role="alert">
+                        <h2 class="text-center">{ "Processing ... " }</h2>
+                    </div>
+                }
+            }
+            State::AccessDenied(kopid) => {
+                html! {
+                    <div class="alert alert-danger" role="alert">
+                        <h1>{ "Access Denied" } </h1>
+                        <p>
+                        { "You do not have access to the requested resources." }
+                        </p>
+                        <p>
+                        { if let Some(opid) = kopid {
+                            format!("Operation ID: {}", opid)
+                          } else {
+                            "Operation ID: -".to_string()
+                          }
+                        }
+                        </p>
+                    </div>
+                }
+            }
+            State::ErrInvalidRequest => {
+                html! {
+                    <div class="alert alert-danger" role="alert">
+                        <h1>{ "Invalid request" } </h1>
+                        <p>
+                        { "Please close this window and try again again from the beginning." }
+                        </p>
+                    </div>
+                }
+            }
+        };
+        html! {
+        <>
+            <main class="form-signin">
+            <center>
+                <img src="/pkg/img/logo-square.svg" alt="Kanidm" class="kanidm_logo"/>
+            </center>
+            <div class="container">
+            { body_content }
+            </div>
+            </main>
+            { crate::utils::do_footer() }
+        </>
+        }
+    }
+
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        console::debug!("oauth2_device::destroy");
+        remove_body_form_classes!();
+    }
+}
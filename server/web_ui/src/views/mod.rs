@@ -14,10 +14,12 @@ use crate::{models, utils};
 
 mod apps;
 mod components;
+mod notifications;
 mod profile;
 mod security;
 
 use apps::AppsApp;
+use notifications::NotificationsApp;
 use profile::ProfileApp;
 use security::SecurityApp;
 
@@ -35,6 +37,9 @@ pub enum ViewRoute {
     #[at("/ui/view/security")]
     Security,
 
+    #[at("/ui/view/notifications")]
+    Notifications,
+
     #[not_found]
     #[at("/ui/view/404")]
     NotFound,
@@ -275,6 +280,13 @@ impl ViewsApp {
                       </li>
                     }
 
+                    <li class="mb-1">
+                      <Link<ViewRoute> classes="nav-link" to={ViewRoute::Notifications}>
+                        <span data-feather="file"></span>
+                        { "Notifications" }
+                      </Link<ViewRoute>>
+                    </li>
+
                     if ui_hint_experimental {
                       <li class="mb-1">
                         <Link<AdminRoute> classes="nav-link" to={AdminRoute::AdminMenu}>
@@ -332,6 +344,8 @@ impl ViewsApp {
                         ViewRoute::Apps => html! { <AppsApp /> },
                         ViewRoute::Profile => html! { <ProfileApp current_user_uat={ current_user_uat.clone() } /> },
                         ViewRoute::Security => html! { <SecurityApp current_user_uat={ current_user_uat.clone() } /> },
+                        #[allow(clippy::let_unit_value)]
+                        ViewRoute::Notifications => html! { <NotificationsApp /> },
                         ViewRoute::NotFound => html! {
                             <Redirect<Route> to={Route::NotFound}/>
                         },
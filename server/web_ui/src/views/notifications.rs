@@ -0,0 +1,214 @@
+#[cfg(debug_assertions)]
+use gloo::console;
+use yew::prelude::*;
+
+use crate::constants::{CSS_CELL, CSS_PAGE_HEADER, CSS_TABLE};
+use crate::error::FetchError;
+use crate::utils;
+use time::format_description::well_known::Rfc3339;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Request, RequestCredentials, RequestInit, RequestMode, Response};
+
+use kanidm_proto::internal::UserNotification;
+
+pub enum Msg {
+    Ready {
+        notifications: Vec<UserNotification>,
+    },
+    Error {
+        emsg: String,
+        kopid: Option<String>,
+    },
+}
+
+impl From<FetchError> for Msg {
+    fn from(fe: FetchError) -> Self {
+        Msg::Error {
+            emsg: fe.as_string(),
+            kopid: None,
+        }
+    }
+}
+
+pub enum State {
+    Waiting,
+    Ready {
+        notifications: Vec<UserNotification>,
+    },
+    Error {
+        emsg: String,
+        kopid: Option<String>,
+    },
+}
+
+pub struct NotificationsApp {
+    state: State,
+}
+
+impl Component for NotificationsApp {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        #[cfg(debug_assertions)]
+        console::debug!("views::notifications::create");
+
+        ctx.link().send_future(async {
+            match Self::fetch_user_notifications().await {
+                Ok(v) => v,
+                Err(v) => v.into(),
+            }
+        });
+
+        let state = State::Waiting;
+
+        NotificationsApp { state }
+    }
+
+    fn changed(&mut self, _ctx: &Context<Self>, _props: &Self::Properties) -> bool {
+        #[cfg(debug_assertions)]
+        console::debug!("views::notifications::changed");
+        false
+    }
+
+    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+        #[cfg(debug_assertions)]
+        console::debug!("views::notifications::update");
+        match msg {
+            Msg::Ready { notifications } => self.state = State::Ready { notifications },
+            Msg::Error { emsg, kopid } => self.state = State::Error { emsg, kopid },
+        }
+
+        true
+    }
+
+    fn rendered(&mut self, _ctx: &Context<Self>, _first_render: bool) {
+        #[cfg(debug_assertions)]
+        console::debug!("views::notifications::rendered");
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        match &self.state {
+            State::Waiting => self.view_waiting(),
+            State::Ready { notifications } => self.view_ready(ctx, notifications.as_slice()),
+            State::Error { emsg, kopid } => self.view_error(ctx, emsg, kopid.as_deref()),
+        }
+    }
+}
+
+impl NotificationsApp {
+    fn view_waiting(&self) -> Html {
+        html! {
+            <>
+              <div class="vert-center">
+                <div class="spinner-border text-dark" role="status">
+                  <span class="visually-hidden">{ "Loading..." }</span>
+                </div>
+              </div>
+            </>
+        }
+    }
+
+    fn view_ready(&self, _ctx: &Context<Self>, notifications: &[UserNotification]) -> Html {
+        html! {
+            <>
+        <div class={CSS_PAGE_HEADER}>
+        <h2>{ "Notifications" }</h2>
+        </div>
+          if notifications.is_empty() {
+            <p>{ "There are no notifications about your account." }</p>
+          } else {
+            <table class={CSS_TABLE}>
+              <thead>
+                <tr>
+                  <th scope="col" class={CSS_CELL}>{ "Time" }</th>
+                  <th scope="col" class={CSS_CELL}>{ "Notification" }</th>
+                  <th scope="col" class={CSS_CELL}>{ "Sent by mail" }</th>
+                </tr>
+              </thead>
+              <tbody>
+                {
+                    notifications.iter().map(|notification| {
+                        let time = notification
+                            .time
+                            .format(&Rfc3339)
+                            .unwrap_or_else(|_| notification.time.to_string());
+                        html!{
+                            <tr>
+                              <td class={CSS_CELL}>{ time }</td>
+                              <td class={CSS_CELL}>
+                                <strong>{ notification.subject.clone() }</strong>
+                                <pre class="mb-0">{ notification.body.clone() }</pre>
+                              </td>
+                              <td class={CSS_CELL}>{ if notification.delivered { "Yes" } else { "No" } }</td>
+                            </tr>
+                        }
+                    }).collect::<Html>()
+                }
+              </tbody>
+            </table>
+          }
+        </>
+        }
+    }
+
+    fn view_error(&self, _ctx: &Context<Self>, msg: &str, kopid: Option<&str>) -> Html {
+        html! {
+          <>
+            <p class="text-center">
+                <img src="/pkg/img/logo-square.svg" alt="Kanidm" class="kanidm_logo"/>
+            </p>
+            <div class="alert alert-danger" role="alert">
+              <h2>{ "An Error Occurred 🥺" }</h2>
+            <p>{ msg.to_string() }</p>
+            <p>
+                {
+                    if let Some(opid) = kopid.as_ref() {
+                        format!("Operation ID: {}", opid)
+                    } else {
+                        "Local Error".to_string()
+                    }
+                }
+            </p>
+            </div>
+            <p class="text-center">
+              <a href="/"><button href="/" class="btn btn-secondary" aria-label="Return home">{"Return to the home page"}</button></a>
+            </p>
+          </>
+        }
+    }
+
+    async fn fetch_user_notifications() -> Result<Msg, FetchError> {
+        let mut opts = RequestInit::new();
+        opts.method("GET");
+        opts.mode(RequestMode::SameOrigin);
+        opts.credentials(RequestCredentials::SameOrigin);
+
+        let request = Request::new_with_str_and_init("/v1/self/_notifications", &opts)?;
+
+        request
+            .headers()
+            .set("content-type", "application/json")
+            .expect_throw("failed to set header");
+
+        let window = utils::window();
+        let resp_value = JsFuture::from(window.fetch_with_request(&request)).await?;
+        let resp: Response = resp_value.dyn_into().expect_throw("Invalid response type");
+        let status = resp.status();
+
+        if status == 200 {
+            let jsval = JsFuture::from(resp.json()?).await?;
+            let notifications: Vec<UserNotification> = serde_wasm_bindgen::from_value(jsval)
+                .expect_throw("Invalid response type - notifications::UserNotification");
+            Ok(Msg::Ready { notifications })
+        } else {
+            let headers = resp.headers();
+            let kopid = headers.get("x-kanidm-opid").ok().flatten();
+            let text = JsFuture::from(resp.text()?).await?;
+            let emsg = text.as_string().unwrap_or_default();
+            Ok(Msg::Error { emsg, kopid })
+        }
+    }
+}
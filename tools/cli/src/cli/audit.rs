@@ -0,0 +1,63 @@
+use kanidm_proto::internal::AuditLogQuery;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::common::OpType;
+use crate::AuditOpt;
+
+fn parse_time(name: &str, t: &Option<String>) -> Result<Option<OffsetDateTime>, ()> {
+    t.as_ref()
+        .map(|t| {
+            OffsetDateTime::parse(t, &Rfc3339)
+                .map_err(|e| error!("Error parsing {} (input: {t:?}) -> {:?}", name, e))
+        })
+        .transpose()
+}
+
+impl AuditOpt {
+    pub fn debug(&self) -> bool {
+        match self {
+            AuditOpt::Search(sopt) => sopt.copt.debug,
+        }
+    }
+
+    pub async fn exec(&self) {
+        match self {
+            AuditOpt::Search(sopt) => {
+                let (from, to) = match (parse_time("from", &sopt.from), parse_time("to", &sopt.to))
+                {
+                    (Ok(from), Ok(to)) => (from, to),
+                    _ => return,
+                };
+
+                let query = AuditLogQuery {
+                    actor: sopt.actor.clone(),
+                    target: sopt.target.clone(),
+                    from,
+                    to,
+                    kind: sopt.kind.clone(),
+                    limit: sopt.limit,
+                };
+
+                let client = sopt.copt.to_client(OpType::Read).await;
+                match client.idm_audit_log_search(&query).await {
+                    Ok(entries) => entries.iter().for_each(|entry| {
+                        let time = entry
+                            .time
+                            .format(&Rfc3339)
+                            .unwrap_or_else(|_| entry.time.to_string());
+                        let actor = entry
+                            .actor
+                            .map(|u| u.to_string())
+                            .unwrap_or_else(|| "-".to_string());
+                        println!(
+                            "{} {} actor={} target={} {}",
+                            time, entry.kind, actor, entry.target, entry.event
+                        );
+                    }),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+        }
+    }
+}
@@ -1,12 +1,34 @@
+use std::fs;
+
 use crate::common::OpType;
 use crate::DomainOpt;
 
+/// Extract the base64 DER content of each certificate in a PEM file, since that is
+/// how the server stores them.
+fn pem_certificates_to_b64(pem: &str) -> Vec<String> {
+    let mut cas = Vec::new();
+    let mut current: Option<String> = None;
+    for line in pem.lines().map(str::trim) {
+        if line == "-----BEGIN CERTIFICATE-----" {
+            current = Some(String::new());
+        } else if line == "-----END CERTIFICATE-----" {
+            cas.extend(current.take());
+        } else if let Some(b64) = current.as_mut() {
+            b64.push_str(line);
+        }
+    }
+    cas
+}
+
 impl DomainOpt {
     pub fn debug(&self) -> bool {
         match self {
             DomainOpt::SetDisplayName(copt) => copt.copt.debug,
-            DomainOpt::SetLdapBasedn { copt, .. } => copt.debug,
-            DomainOpt::Show(copt) | DomainOpt::ResetTokenKey(copt) => copt.debug,
+            DomainOpt::SetLdapBasedn { copt, .. }
+            | DomainOpt::SetAttestationCaList { copt, .. } => copt.debug,
+            DomainOpt::Show(copt)
+            | DomainOpt::ClearAttestationCaList(copt)
+            | DomainOpt::ResetTokenKey(copt) => copt.debug,
         }
     }
 
@@ -37,6 +59,36 @@ impl DomainOpt {
                     Err(e) => eprintln!("{:?}", e),
                 }
             }
+            DomainOpt::SetAttestationCaList { copt, ca_paths } => {
+                let mut cas = Vec::new();
+                for path in ca_paths {
+                    match fs::read_to_string(path) {
+                        Ok(pem) => cas.extend(pem_certificates_to_b64(&pem)),
+                        Err(e) => {
+                            error!("Unable to read {:?} -> {:?}", path, e);
+                            return;
+                        }
+                    }
+                }
+
+                if cas.is_empty() {
+                    error!("No PEM certificates were found");
+                    return;
+                }
+
+                let client = copt.to_client(OpType::Write).await;
+                match client.idm_domain_set_attestation_ca_list(&cas).await {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            DomainOpt::ClearAttestationCaList(copt) => {
+                let client = copt.to_client(OpType::Write).await;
+                match client.idm_domain_clear_attestation_ca_list().await {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
             DomainOpt::Show(copt) => {
                 let client = copt.to_client(OpType::Read).await;
                 match client.idm_domain_get().await {
@@ -1,5 +1,5 @@
 use crate::common::OpType;
-use crate::{GroupOpt, GroupPosix};
+use crate::{GroupAccountPolicy, GroupOpt, GroupPosix};
 
 impl GroupOpt {
     pub fn debug(&self) -> bool {
@@ -17,6 +17,14 @@ impl GroupOpt {
                 GroupPosix::Show(gcopt) => gcopt.copt.debug,
                 GroupPosix::Set(gcopt) => gcopt.copt.debug,
             },
+            GroupOpt::AccountPolicy { commands } => match commands {
+                GroupAccountPolicy::Enable(gcopt) => gcopt.copt.debug,
+                GroupAccountPolicy::AuthSessionExpiry(gcopt)
+                | GroupAccountPolicy::PrivilegedSessionExpiry(gcopt)
+                | GroupAccountPolicy::PasswordMinimumLength(gcopt)
+                | GroupAccountPolicy::Oauth2AccessTokenExpiry(gcopt) => gcopt.copt.debug,
+                GroupAccountPolicy::CredentialTypeMinimum(gcopt) => gcopt.copt.debug,
+            },
         }
     }
 
@@ -153,6 +161,85 @@ impl GroupOpt {
                     }
                 }
             },
+            GroupOpt::AccountPolicy { commands } => match commands {
+                GroupAccountPolicy::Enable(gcopt) => {
+                    let client = gcopt.copt.to_client(OpType::Write).await;
+                    match client
+                        .idm_group_account_policy_enable(gcopt.name.as_str())
+                        .await
+                    {
+                        Err(e) => error!("Error -> {:?}", e),
+                        Ok(_) => {
+                            println!("Enabled account policy for group {}", gcopt.name.as_str())
+                        }
+                    }
+                }
+                GroupAccountPolicy::AuthSessionExpiry(gcopt) => {
+                    let client = gcopt.copt.to_client(OpType::Write).await;
+                    match client
+                        .idm_group_account_policy_authsession_expiry_set(
+                            gcopt.name.as_str(),
+                            gcopt.value,
+                        )
+                        .await
+                    {
+                        Err(e) => error!("Error -> {:?}", e),
+                        Ok(_) => println!("Updated authsession expiry."),
+                    }
+                }
+                GroupAccountPolicy::PrivilegedSessionExpiry(gcopt) => {
+                    let client = gcopt.copt.to_client(OpType::Write).await;
+                    match client
+                        .idm_group_account_policy_privilege_expiry_set(
+                            gcopt.name.as_str(),
+                            gcopt.value,
+                        )
+                        .await
+                    {
+                        Err(e) => error!("Error -> {:?}", e),
+                        Ok(_) => println!("Updated privilege expiry."),
+                    }
+                }
+                GroupAccountPolicy::PasswordMinimumLength(gcopt) => {
+                    let client = gcopt.copt.to_client(OpType::Write).await;
+                    match client
+                        .idm_group_account_policy_password_minimum_length_set(
+                            gcopt.name.as_str(),
+                            gcopt.value,
+                        )
+                        .await
+                    {
+                        Err(e) => error!("Error -> {:?}", e),
+                        Ok(_) => println!("Updated password minimum length."),
+                    }
+                }
+                GroupAccountPolicy::CredentialTypeMinimum(gcopt) => {
+                    let client = gcopt.copt.to_client(OpType::Write).await;
+                    match client
+                        .idm_group_account_policy_credential_type_minimum_set(
+                            gcopt.name.as_str(),
+                            gcopt.value.as_str(),
+                        )
+                        .await
+                    {
+                        Err(e) => error!("Error -> {:?}", e),
+                        Ok(_) => println!("Updated credential type minimum."),
+                    }
+                }
+                GroupAccountPolicy::Oauth2AccessTokenExpiry(gcopt) => {
+                    let client = gcopt.copt.to_client(OpType::Write).await;
+                    match client
+                        .idm_group_account_policy_oauth2_access_token_expiry_set(
+                            gcopt.name.as_str(),
+                            gcopt.value,
+                        )
+                        .await
+                    {
+                        Err(e) => error!("Error -> {:?}", e),
+                        Ok(_) => println!("Updated oauth2 access token expiry."),
+                    }
+                }
+            },
         } // end match
     }
 }
@@ -20,16 +20,20 @@ use uuid::Uuid;
 
 include!("../opt/kanidm.rs");
 
+pub mod audit;
 pub mod badlist;
 pub mod common;
 pub mod domain;
 pub mod group;
+pub mod message;
 pub mod oauth2;
 pub mod person;
 pub mod raw;
 pub mod recycle;
+pub mod schema;
 pub mod serviceaccount;
 pub mod session;
+pub mod sourcelock;
 pub mod synch;
 mod webauthn;
 
@@ -71,6 +75,9 @@ impl SystemOpt {
             SystemOpt::Oauth2 { commands } => commands.debug(),
             SystemOpt::Domain { commands } => commands.debug(),
             SystemOpt::Synch { commands } => commands.debug(),
+            SystemOpt::MessageQueue { commands } => commands.debug(),
+            SystemOpt::SourceLock { commands } => commands.debug(),
+            SystemOpt::PasswordReport(copt) => copt.debug,
         }
     }
 
@@ -80,6 +87,15 @@ impl SystemOpt {
             SystemOpt::Oauth2 { commands } => commands.exec().await,
             SystemOpt::Domain { commands } => commands.exec().await,
             SystemOpt::Synch { commands } => commands.exec().await,
+            SystemOpt::MessageQueue { commands } => commands.exec().await,
+            SystemOpt::SourceLock { commands } => commands.exec().await,
+            SystemOpt::PasswordReport(copt) => {
+                let client = copt.to_client(OpType::Read).await;
+                match client.system_password_kdf_report().await {
+                    Ok(report) => print!("{}", report),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
         }
     }
 }
@@ -98,6 +114,8 @@ impl KanidmClientOpt {
             KanidmClientOpt::ServiceAccount { commands } => commands.debug(),
             KanidmClientOpt::System { commands } => commands.debug(),
             KanidmClientOpt::Recycle { commands } => commands.debug(),
+            KanidmClientOpt::Audit { commands } => commands.debug(),
+            KanidmClientOpt::Schema { commands } => commands.debug(),
             KanidmClientOpt::Version {} => {
                 kanidm_proto::utils::show_version("kanidm");
                 true
@@ -118,6 +136,8 @@ impl KanidmClientOpt {
             KanidmClientOpt::Group { commands } => commands.exec().await,
             KanidmClientOpt::System { commands } => commands.exec().await,
             KanidmClientOpt::Recycle { commands } => commands.exec().await,
+            KanidmClientOpt::Audit { commands } => commands.exec().await,
+            KanidmClientOpt::Schema { commands } => commands.exec().await,
             KanidmClientOpt::Version {} => (),
         }
     }
@@ -0,0 +1,38 @@
+use crate::common::OpType;
+use crate::MessageQueueOpt;
+
+impl MessageQueueOpt {
+    pub fn debug(&self) -> bool {
+        match self {
+            MessageQueueOpt::List(copt) => copt.debug,
+            MessageQueueOpt::Get(nopt) | MessageQueueOpt::Delete(nopt) => nopt.copt.debug,
+        }
+    }
+
+    pub async fn exec(&self) {
+        match self {
+            MessageQueueOpt::List(copt) => {
+                let client = copt.to_client(OpType::Read).await;
+                match client.idm_message_queue_list().await {
+                    Ok(r) => r.iter().for_each(|ent| println!("{}", ent)),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            MessageQueueOpt::Get(nopt) => {
+                let client = nopt.copt.to_client(OpType::Read).await;
+                match client.idm_message_queue_get(nopt.name.as_str()).await {
+                    Ok(Some(e)) => println!("{}", e),
+                    Ok(None) => println!("No matching entries"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            MessageQueueOpt::Delete(nopt) => {
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client.idm_message_queue_delete(nopt.name.as_str()).await {
+                    Ok(()) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+        }
+    }
+}
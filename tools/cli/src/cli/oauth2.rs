@@ -1,3 +1,8 @@
+use std::fs;
+
+use compact_jwt::Jwk;
+use url::Url;
+
 use crate::common::OpType;
 use crate::Oauth2Opt;
 
@@ -11,6 +16,8 @@ impl Oauth2Opt {
             Oauth2Opt::DeleteScopeMap(cbopt) => cbopt.nopt.copt.debug,
             Oauth2Opt::UpdateSupScopeMap(cbopt) => cbopt.nopt.copt.debug,
             Oauth2Opt::DeleteSupScopeMap(cbopt) => cbopt.nopt.copt.debug,
+            Oauth2Opt::AddTokenExchangeAudience(cbopt) => cbopt.nopt.copt.debug,
+            Oauth2Opt::RemoveTokenExchangeAudience(cbopt) => cbopt.nopt.copt.debug,
             Oauth2Opt::ResetSecrets(cbopt) => cbopt.copt.debug,
             Oauth2Opt::ShowBasicSecret(nopt) => nopt.copt.debug,
             Oauth2Opt::Delete(nopt) => nopt.copt.debug,
@@ -23,6 +30,16 @@ impl Oauth2Opt {
             Oauth2Opt::DisableLegacyCrypto(nopt) => nopt.copt.debug,
             Oauth2Opt::PreferShortUsername(nopt) => nopt.copt.debug,
             Oauth2Opt::PreferSPNUsername(nopt) => nopt.copt.debug,
+            Oauth2Opt::EnableRequirePar(nopt) => nopt.copt.debug,
+            Oauth2Opt::DisableRequirePar(nopt) => nopt.copt.debug,
+            Oauth2Opt::SetClientJwks { nopt, .. } => nopt.copt.debug,
+            Oauth2Opt::ClearClientJwks(nopt) => nopt.copt.debug,
+            Oauth2Opt::SetClientJwksUrl { nopt, .. } => nopt.copt.debug,
+            Oauth2Opt::RefreshClientJwks(nopt) => nopt.copt.debug,
+            Oauth2Opt::SetBackchannelLogoutUri { nopt, .. } => nopt.copt.debug,
+            Oauth2Opt::ClearBackchannelLogoutUri(nopt) => nopt.copt.debug,
+            Oauth2Opt::SetServiceAccount { nopt, .. } => nopt.copt.debug,
+            Oauth2Opt::ClearServiceAccount(nopt) => nopt.copt.debug,
         }
     }
 
@@ -108,6 +125,32 @@ impl Oauth2Opt {
                     Err(e) => error!("Error -> {:?}", e),
                 }
             }
+            Oauth2Opt::AddTokenExchangeAudience(cbopt) => {
+                let client = cbopt.nopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_oauth2_rs_add_token_exchange_audience(
+                        cbopt.nopt.name.as_str(),
+                        cbopt.audience.as_str(),
+                    )
+                    .await
+                {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            Oauth2Opt::RemoveTokenExchangeAudience(cbopt) => {
+                let client = cbopt.nopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_oauth2_rs_remove_token_exchange_audience(
+                        cbopt.nopt.name.as_str(),
+                        cbopt.audience.as_str(),
+                    )
+                    .await
+                {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
             Oauth2Opt::ResetSecrets(cbopt) => {
                 let client = cbopt.copt.to_client(OpType::Write).await;
                 match client
@@ -261,6 +304,149 @@ impl Oauth2Opt {
                     Err(e) => error!("Error -> {:?}", e),
                 }
             }
+            Oauth2Opt::EnableRequirePar(nopt) => {
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_oauth2_rs_enable_require_par(nopt.name.as_str())
+                    .await
+                {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            Oauth2Opt::DisableRequirePar(nopt) => {
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_oauth2_rs_disable_require_par(nopt.name.as_str())
+                    .await
+                {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            Oauth2Opt::SetClientJwks { nopt, paths } => {
+                let mut jwks = Vec::with_capacity(paths.len());
+                for path in paths {
+                    let contents = match fs::read_to_string(path) {
+                        Ok(c) => c,
+                        Err(e) => {
+                            error!("Unable to read {} -> {:?}", path.display(), e);
+                            return;
+                        }
+                    };
+                    // Check this really is a jwk before we send it.
+                    let jwk: Jwk = match serde_json::from_str(&contents) {
+                        Ok(j) => j,
+                        Err(e) => {
+                            error!("Invalid jwk in {} -> {:?}", path.display(), e);
+                            return;
+                        }
+                    };
+                    match serde_json::to_string(&jwk) {
+                        Ok(j) => jwks.push(j),
+                        Err(e) => {
+                            error!("Unable to serialise jwk -> {:?}", e);
+                            return;
+                        }
+                    }
+                }
+
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_oauth2_rs_set_client_jwks(nopt.name.as_str(), jwks)
+                    .await
+                {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            Oauth2Opt::ClearClientJwks(nopt) => {
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_oauth2_rs_set_client_jwks(nopt.name.as_str(), Vec::new())
+                    .await
+                {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            Oauth2Opt::SetClientJwksUrl { nopt, url } => {
+                let url = match Url::parse(url) {
+                    Ok(u) => u,
+                    Err(e) => {
+                        error!("Invalid jwks url -> {:?}", e);
+                        return;
+                    }
+                };
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_oauth2_rs_set_client_jwks_url(nopt.name.as_str(), &url)
+                    .await
+                {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            Oauth2Opt::RefreshClientJwks(nopt) => {
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_oauth2_rs_refresh_client_jwks(nopt.name.as_str())
+                    .await
+                {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            Oauth2Opt::SetBackchannelLogoutUri { nopt, uri } => {
+                let uri = match Url::parse(uri) {
+                    Ok(u) => u,
+                    Err(e) => {
+                        error!("Invalid backchannel logout uri -> {:?}", e);
+                        return;
+                    }
+                };
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_oauth2_rs_set_backchannel_logout_uri(nopt.name.as_str(), &uri)
+                    .await
+                {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            Oauth2Opt::ClearBackchannelLogoutUri(nopt) => {
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_oauth2_rs_clear_backchannel_logout_uri(nopt.name.as_str())
+                    .await
+                {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            Oauth2Opt::SetServiceAccount {
+                nopt,
+                service_account,
+            } => {
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_oauth2_rs_set_service_account(nopt.name.as_str(), service_account.as_str())
+                    .await
+                {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            Oauth2Opt::ClearServiceAccount(nopt) => {
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_oauth2_rs_clear_service_account(nopt.name.as_str())
+                    .await
+                {
+                    Ok(_) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
         }
     }
 }
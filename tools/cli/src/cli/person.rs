@@ -480,6 +480,7 @@ impl AccountCredential {
         match self {
             AccountCredential::Status(aopt) => aopt.copt.debug,
             AccountCredential::CreateResetToken(aopt) => aopt.copt.debug,
+            AccountCredential::SendResetToken(aopt) => aopt.copt.debug,
             AccountCredential::UseResetToken(aopt) => aopt.copt.debug,
             AccountCredential::Update(aopt) => aopt.copt.debug,
         }
@@ -597,6 +598,23 @@ impl AccountCredential {
                     }
                 }
             }
+            AccountCredential::SendResetToken(aopt) => {
+                let client = aopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_person_account_credential_update_intent_send(
+                        aopt.aopts.account_id.as_str(),
+                    )
+                    .await
+                {
+                    Ok(()) => println!(
+                        "A credential reset token has been queued for delivery to {}",
+                        aopt.aopts.account_id
+                    ),
+                    Err(e) => {
+                        error!("Error sending credential reset -> {:?}", e);
+                    }
+                }
+            }
         }
     }
 }
@@ -612,6 +630,8 @@ enum CUAction {
     Remove,
     Passkey,
     PasskeyRemove,
+    DeviceKey,
+    DeviceKeyRemove,
     End,
     Commit,
 }
@@ -634,6 +654,9 @@ remove (rm) - Remove only the password based credential
 -- Passkeys
 passkey (pk) - Add a new Passkey
 passkey remove (passkey rm, pkrm) - Remove a Passkey
+-- Device Keys
+devicekey (dk) - Add a new attested Device Key
+devicekey remove (devicekey rm, dkrm) - Remove a Device Key
 "#
         )
     }
@@ -656,6 +679,8 @@ impl FromStr for CUAction {
             "remove" | "rm" => Ok(CUAction::Remove),
             "passkey" | "pk" => Ok(CUAction::Passkey),
             "passkey remove" | "passkey rm" | "pkrm" => Ok(CUAction::PasskeyRemove),
+            "devicekey" | "dk" => Ok(CUAction::DeviceKey),
+            "devicekey remove" | "devicekey rm" | "dkrm" => Ok(CUAction::DeviceKeyRemove),
             _ => Err(()),
         }
     }
@@ -880,6 +905,57 @@ async fn passkey_enroll_prompt(session_token: &CUSessionToken, client: &KanidmCl
     };
 }
 
+async fn devicekey_enroll_prompt(session_token: &CUSessionToken, client: &KanidmClient) {
+    let dk_reg = match client
+        .idm_account_credential_update_devicekey_init(session_token)
+        .await
+    {
+        Ok(CUStatus {
+            mfaregstate: CURegState::DeviceKey(dk_reg),
+            ..
+        }) => dk_reg,
+        Ok(status) => {
+            debug!(?status);
+            eprintln!("An error occurred -> InvalidState");
+            return;
+        }
+        Err(e) => {
+            eprintln!("An error occurred -> {:?}", e);
+            return;
+        }
+    };
+
+    // Setup and connect to the webauthn handler ...
+    let mut wa = get_authenticator();
+
+    eprintln!("Your authenticator will now flash for you to interact with.");
+    eprintln!("You may be asked to enter the PIN for your device.");
+
+    let rego = match wa.do_registration(client.get_origin().clone(), dk_reg) {
+        Ok(rego) => rego,
+        Err(e) => {
+            error!("Error Signing -> {:?}", e);
+            return;
+        }
+    };
+
+    let label: String = Input::new()
+        .with_prompt("\nEnter a label for this Device Key # ")
+        .allow_empty(false)
+        .interact_text()
+        .expect("Failed to interact with interactive session");
+
+    match client
+        .idm_account_credential_update_devicekey_finish(session_token, label, rego)
+        .await
+    {
+        Ok(_) => println!("success"),
+        Err(e) => {
+            eprintln!("An error occurred -> {:?}", e);
+        }
+    };
+}
+
 fn display_status(status: CUStatus) {
     let CUStatus {
         spn,
@@ -888,6 +964,7 @@ fn display_status(status: CUStatus) {
         primary,
         mfaregstate: _,
         passkeys,
+        devicekeys,
     } = status;
 
     println!("spn: {}", spn);
@@ -907,6 +984,14 @@ fn display_status(status: CUStatus) {
             println!("  {} ({})", pk.tag, pk.uuid);
         }
     }
+    println!("Device Keys:");
+    if devicekeys.is_empty() {
+        println!("  not set");
+    } else {
+        for dk in devicekeys {
+            println!("  {} ({})", dk.tag, dk.uuid);
+        }
+    }
 
     // We may need to be able to display if there are dangling
     // curegstates, but the cli ui statemachine can match the
@@ -1138,6 +1223,57 @@ async fn credential_update_exec(
                     println!("Passkeys were NOT changed");
                 }
             }
+            CUAction::DeviceKey => devicekey_enroll_prompt(&session_token, &client).await,
+            CUAction::DeviceKeyRemove => {
+                match client
+                    .idm_account_credential_update_status(&session_token)
+                    .await
+                {
+                    Ok(status) => {
+                        if status.devicekeys.is_empty() {
+                            println!("No device keys are configured for this user");
+                            continue;
+                        }
+                        println!("Current device keys:");
+                        for dk in status.devicekeys {
+                            println!("  {} ({})", dk.tag, dk.uuid);
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "An error occurred retrieving existing credentials -> {:?}",
+                            e
+                        );
+                    }
+                }
+
+                let uuid_s: String = Input::new()
+                    .with_prompt("\nEnter the UUID of the Device Key to remove (blank to stop) # ")
+                    .validate_with(|input: &String| -> Result<(), &str> {
+                        if input.is_empty() || Uuid::parse_str(input).is_ok() {
+                            Ok(())
+                        } else {
+                            Err("This is not a valid UUID")
+                        }
+                    })
+                    .allow_empty(true)
+                    .interact_text()
+                    .expect("Failed to interact with interactive session");
+
+                // Remember, if it's NOT a valid uuid, it must have been empty as a termination.
+                if let Ok(uuid) = Uuid::parse_str(&uuid_s) {
+                    if let Err(e) = client
+                        .idm_account_credential_update_devicekey_remove(&session_token, uuid)
+                        .await
+                    {
+                        eprintln!("An error occurred -> {:?}", e);
+                    } else {
+                        println!("success");
+                    }
+                } else {
+                    println!("Device Keys were NOT changed");
+                }
+            }
             CUAction::End => {
                 println!("Changes were NOT saved.");
                 break;
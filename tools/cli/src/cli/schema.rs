@@ -0,0 +1,207 @@
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Confirm;
+use kanidm_proto::internal::{
+    SchemaAttributeCreateRequest, SchemaAttributeUpdateRequest, SchemaClassCreateRequest,
+    SchemaClassUpdateRequest,
+};
+use kanidm_proto::v1::Entry;
+
+use crate::common::OpType;
+use crate::{CommonOpt, SchemaAttributeOpt, SchemaClassOpt, SchemaOpt};
+
+fn print_entry(copt: &CommonOpt, ent: &Entry) {
+    match copt.output_mode.as_str() {
+        "json" => {
+            println!(
+                "{}",
+                serde_json::to_string(&ent.attrs).expect("Failed to serialise json")
+            );
+        }
+        _ => println!("{}", ent),
+    }
+}
+
+/// Values given on the command line replace the current list. Since no values means the option
+/// wasn't given, clearing the list has its own flag.
+fn replacement(values: &[String], clear: bool) -> Option<Vec<String>> {
+    if clear {
+        Some(Vec::new())
+    } else if values.is_empty() {
+        None
+    } else {
+        Some(values.to_vec())
+    }
+}
+
+impl SchemaOpt {
+    pub fn debug(&self) -> bool {
+        match self {
+            SchemaOpt::Attribute { commands } => commands.debug(),
+            SchemaOpt::Class { commands } => commands.debug(),
+        }
+    }
+
+    pub async fn exec(&self) {
+        match self {
+            SchemaOpt::Attribute { commands } => commands.exec().await,
+            SchemaOpt::Class { commands } => commands.exec().await,
+        }
+    }
+}
+
+impl SchemaAttributeOpt {
+    pub fn debug(&self) -> bool {
+        match self {
+            SchemaAttributeOpt::List(copt) => copt.debug,
+            SchemaAttributeOpt::Get(nopt) => nopt.copt.debug,
+            SchemaAttributeOpt::Create(copt) => copt.copt.debug,
+            SchemaAttributeOpt::Update(uopt) => uopt.copt.debug,
+            SchemaAttributeOpt::Delete(dopt) => dopt.copt.debug,
+        }
+    }
+
+    pub async fn exec(&self) {
+        match self {
+            SchemaAttributeOpt::List(copt) => {
+                let client = copt.to_client(OpType::Read).await;
+                match client.idm_schema_attributetype_list().await {
+                    Ok(r) => r.iter().for_each(|ent| print_entry(copt, ent)),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            SchemaAttributeOpt::Get(nopt) => {
+                let client = nopt.copt.to_client(OpType::Read).await;
+                match client
+                    .idm_schema_attributetype_get(nopt.name.as_str())
+                    .await
+                {
+                    Ok(Some(ent)) => print_entry(&nopt.copt, &ent),
+                    Ok(None) => warn!("No matching attribute '{}'", nopt.name.as_str()),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            SchemaAttributeOpt::Create(copt) => {
+                let client = copt.copt.to_client(OpType::Write).await;
+                let req = SchemaAttributeCreateRequest {
+                    name: copt.name.clone(),
+                    description: copt.description.clone(),
+                    syntax: copt.syntax.clone(),
+                    multivalue: copt.multivalue,
+                    unique: copt.unique,
+                    index: copt.index.clone(),
+                };
+                match client.idm_schema_attributetype_create(&req).await {
+                    Ok(()) => println!("Successfully created attribute '{}'", copt.name),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            SchemaAttributeOpt::Update(uopt) => {
+                let client = uopt.copt.to_client(OpType::Write).await;
+                let req = SchemaAttributeUpdateRequest {
+                    description: uopt.description.clone(),
+                    multivalue: uopt.multivalue,
+                    unique: uopt.unique,
+                    index: replacement(&uopt.index, uopt.clear_index),
+                };
+                match client
+                    .idm_schema_attributetype_update(uopt.name.as_str(), &req)
+                    .await
+                {
+                    Ok(()) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            SchemaAttributeOpt::Delete(dopt) => {
+                if dopt.purge
+                    && !Confirm::with_theme(&ColorfulTheme::default())
+                        .with_prompt(format!(
+                            "Purging removes '{}' and all of its values from every entry and class. Do you want to continue?",
+                            dopt.name
+                        ))
+                        .default(false)
+                        .interact()
+                        .expect("Failed to interact with interactive session")
+                {
+                    println!("Attribute '{}' was NOT deleted", dopt.name);
+                    return;
+                }
+
+                let client = dopt.copt.to_client(OpType::Write).await;
+                match client
+                    .idm_schema_attributetype_delete(dopt.name.as_str(), dopt.purge)
+                    .await
+                {
+                    Ok(()) => println!("Successfully deleted attribute '{}'", dopt.name),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+        }
+    }
+}
+
+impl SchemaClassOpt {
+    pub fn debug(&self) -> bool {
+        match self {
+            SchemaClassOpt::List(copt) => copt.debug,
+            SchemaClassOpt::Get(nopt) => nopt.copt.debug,
+            SchemaClassOpt::Create(copt) => copt.copt.debug,
+            SchemaClassOpt::Update(uopt) => uopt.copt.debug,
+            SchemaClassOpt::Delete(nopt) => nopt.copt.debug,
+        }
+    }
+
+    pub async fn exec(&self) {
+        match self {
+            SchemaClassOpt::List(copt) => {
+                let client = copt.to_client(OpType::Read).await;
+                match client.idm_schema_classtype_list().await {
+                    Ok(r) => r.iter().for_each(|ent| print_entry(copt, ent)),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            SchemaClassOpt::Get(nopt) => {
+                let client = nopt.copt.to_client(OpType::Read).await;
+                match client.idm_schema_classtype_get(nopt.name.as_str()).await {
+                    Ok(Some(ent)) => print_entry(&nopt.copt, &ent),
+                    Ok(None) => warn!("No matching class '{}'", nopt.name.as_str()),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            SchemaClassOpt::Create(copt) => {
+                let client = copt.copt.to_client(OpType::Write).await;
+                let req = SchemaClassCreateRequest {
+                    name: copt.name.clone(),
+                    description: copt.description.clone(),
+                    may: copt.may.clone(),
+                    must: copt.must.clone(),
+                };
+                match client.idm_schema_classtype_create(&req).await {
+                    Ok(()) => println!("Successfully created class '{}'", copt.name),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            SchemaClassOpt::Update(uopt) => {
+                let client = uopt.copt.to_client(OpType::Write).await;
+                let req = SchemaClassUpdateRequest {
+                    description: uopt.description.clone(),
+                    may: replacement(&uopt.may, uopt.clear_may),
+                    must: replacement(&uopt.must, uopt.clear_must),
+                };
+                match client
+                    .idm_schema_classtype_update(uopt.name.as_str(), &req)
+                    .await
+                {
+                    Ok(()) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            SchemaClassOpt::Delete(nopt) => {
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client.idm_schema_classtype_delete(nopt.name.as_str()).await {
+                    Ok(()) => println!("Successfully deleted class '{}'", nopt.name),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+        }
+    }
+}
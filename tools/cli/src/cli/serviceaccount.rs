@@ -1,10 +1,12 @@
 use crate::common::OpType;
 use kanidm_proto::messages::{AccountChangeMessage, ConsoleOutputMode, MessageStatus};
 use time::OffsetDateTime;
+use url::Url;
 
 use crate::{
     AccountSsh, AccountUserAuthToken, AccountValidity, ServiceAccountApiToken,
-    ServiceAccountCredential, ServiceAccountOpt, ServiceAccountPosix,
+    ServiceAccountCredential, ServiceAccountOauth2Registration, ServiceAccountOpt,
+    ServiceAccountPosix,
 };
 use time::format_description::well_known::Rfc3339;
 
@@ -24,6 +26,12 @@ impl ServiceAccountOpt {
                 ServiceAccountPosix::Show(apo) => apo.copt.debug,
                 ServiceAccountPosix::Set(apo) => apo.copt.debug,
             },
+            ServiceAccountOpt::Oauth2Registration { commands } => match commands {
+                ServiceAccountOauth2Registration::SetOrigins { copt, .. } => copt.debug,
+                ServiceAccountOauth2Registration::ClearOrigins(apo) => apo.copt.debug,
+                ServiceAccountOauth2Registration::UpdateScopeMap { copt, .. } => copt.debug,
+                ServiceAccountOauth2Registration::DeleteScopeMap { copt, .. } => copt.debug,
+            },
             ServiceAccountOpt::Session { commands } => match commands {
                 AccountUserAuthToken::Status(apo) => apo.copt.debug,
                 AccountUserAuthToken::Destroy { copt, .. } => copt.debug,
@@ -210,6 +218,80 @@ impl ServiceAccountOpt {
                     }
                 }
             }, // end ServiceAccountOpt::Posix
+            ServiceAccountOpt::Oauth2Registration { commands } => match commands {
+                ServiceAccountOauth2Registration::SetOrigins {
+                    aopts,
+                    copt,
+                    origins,
+                } => {
+                    let origins = match origins
+                        .iter()
+                        .map(|o| Url::parse(o))
+                        .collect::<Result<Vec<_>, _>>()
+                    {
+                        Ok(origins) => origins,
+                        Err(e) => {
+                            error!("Invalid origin -> {:?}", e);
+                            return;
+                        }
+                    };
+                    let client = copt.to_client(OpType::Write).await;
+                    match client
+                        .idm_service_account_set_oauth2_registration_origins(
+                            aopts.account_id.as_str(),
+                            &origins,
+                        )
+                        .await
+                    {
+                        Ok(_) => println!("Success"),
+                        Err(e) => error!("Error -> {:?}", e),
+                    }
+                }
+                ServiceAccountOauth2Registration::ClearOrigins(apo) => {
+                    let client = apo.copt.to_client(OpType::Write).await;
+                    match client
+                        .idm_service_account_clear_oauth2_registration_origins(
+                            apo.aopts.account_id.as_str(),
+                        )
+                        .await
+                    {
+                        Ok(_) => println!("Success"),
+                        Err(e) => error!("Error -> {:?}", e),
+                    }
+                }
+                ServiceAccountOauth2Registration::UpdateScopeMap {
+                    aopts,
+                    copt,
+                    group,
+                    scopes,
+                } => {
+                    let client = copt.to_client(OpType::Write).await;
+                    match client
+                        .idm_service_account_update_oauth2_registration_scope_map(
+                            aopts.account_id.as_str(),
+                            group.as_str(),
+                            scopes.iter().map(|s| s.as_str()).collect(),
+                        )
+                        .await
+                    {
+                        Ok(_) => println!("Success"),
+                        Err(e) => error!("Error -> {:?}", e),
+                    }
+                }
+                ServiceAccountOauth2Registration::DeleteScopeMap { aopts, copt, group } => {
+                    let client = copt.to_client(OpType::Write).await;
+                    match client
+                        .idm_service_account_delete_oauth2_registration_scope_map(
+                            aopts.account_id.as_str(),
+                            group.as_str(),
+                        )
+                        .await
+                    {
+                        Ok(_) => println!("Success"),
+                        Err(e) => error!("Error -> {:?}", e),
+                    }
+                }
+            }, // end ServiceAccountOpt::Oauth2Registration
             ServiceAccountOpt::Session { commands } => match commands {
                 AccountUserAuthToken::Status(apo) => {
                     let client = apo.copt.to_client(OpType::Read).await;
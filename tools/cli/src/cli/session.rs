@@ -229,6 +229,23 @@ async fn do_securitykey(
     client.auth_step_securitykey_complete(auth).await
 }
 
+async fn do_devicekey(
+    client: &mut KanidmClient,
+    pkr: RequestChallengeResponse,
+) -> Result<AuthResponse, ClientError> {
+    let mut wa = get_authenticator();
+    println!("Your authenticator will now flash for you to interact with it.");
+    let auth = wa
+        .do_authentication(client.get_origin().clone(), pkr)
+        .map(Box::new)
+        .unwrap_or_else(|e| {
+            error!("Failed to interact with webauthn device. -- {:?}", e);
+            std::process::exit(1);
+        });
+
+    client.auth_step_devicekey_complete(auth).await
+}
+
 async fn process_auth_state(
     mut allowed: Vec<AuthAllowed>,
     mut client: KanidmClient,
@@ -271,6 +288,7 @@ async fn process_auth_state(
             AuthAllowed::Totp => do_totp(&mut client).await,
             AuthAllowed::Passkey(chal) => do_passkey(&mut client, chal.clone()).await,
             AuthAllowed::SecurityKey(chal) => do_securitykey(&mut client, chal.clone()).await,
+            AuthAllowed::DeviceKey(chal) => do_devicekey(&mut client, chal.clone()).await,
         };
 
         // Now update state.
@@ -0,0 +1,31 @@
+use crate::common::OpType;
+use crate::SourceLockOpt;
+
+impl SourceLockOpt {
+    pub fn debug(&self) -> bool {
+        match self {
+            SourceLockOpt::List(copt) => copt.debug,
+            SourceLockOpt::Clear(nopt) => nopt.copt.debug,
+        }
+    }
+
+    pub async fn exec(&self) {
+        match self {
+            SourceLockOpt::List(copt) => {
+                let client = copt.to_client(OpType::Read).await;
+                match client.idm_source_lock_list().await {
+                    Ok(r) if r.is_empty() => println!("No recent authentication failures"),
+                    Ok(r) => r.iter().for_each(|status| println!("{}", status)),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+            SourceLockOpt::Clear(nopt) => {
+                let client = nopt.copt.to_client(OpType::Write).await;
+                match client.idm_source_lock_clear(nopt.name.as_str()).await {
+                    Ok(()) => println!("Success"),
+                    Err(e) => error!("Error -> {:?}", e),
+                }
+            }
+        }
+    }
+}
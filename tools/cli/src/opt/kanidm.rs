@@ -61,6 +61,46 @@ pub enum GroupPosix {
     Set(GroupPosixOpt),
 }
 
+#[derive(Debug, Args)]
+pub struct GroupAccountPolicyValueOpt {
+    name: String,
+    value: u32,
+    #[clap(flatten)]
+    copt: CommonOpt,
+}
+
+#[derive(Debug, Args)]
+pub struct GroupAccountPolicyCredentialTypeOpt {
+    name: String,
+    /// One of "any", "mfa" or "passkey"
+    #[clap(value_parser = ["any", "mfa", "passkey"])]
+    value: String,
+    #[clap(flatten)]
+    copt: CommonOpt,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum GroupAccountPolicy {
+    /// Enable account policy for this group, so that it applies to the group's members
+    #[clap(name = "enable")]
+    Enable(Named),
+    /// Set the maximum time in seconds that an authentication session is valid
+    #[clap(name = "auth-expiry")]
+    AuthSessionExpiry(GroupAccountPolicyValueOpt),
+    /// Set the maximum time in seconds that a session may hold privileges after reauthentication
+    #[clap(name = "privilege-expiry")]
+    PrivilegedSessionExpiry(GroupAccountPolicyValueOpt),
+    /// Set the minimum length of passwords
+    #[clap(name = "password-minimum-length")]
+    PasswordMinimumLength(GroupAccountPolicyValueOpt),
+    /// Set the minimum class of credential that members may use
+    #[clap(name = "credential-type-minimum")]
+    CredentialTypeMinimum(GroupAccountPolicyCredentialTypeOpt),
+    /// Set the maximum time in seconds that an oauth2 access token is valid
+    #[clap(name = "oauth2-token-expiry")]
+    Oauth2AccessTokenExpiry(GroupAccountPolicyValueOpt),
+}
+
 #[derive(Debug, Subcommand)]
 pub enum GroupOpt {
     /// List all groups
@@ -97,6 +137,12 @@ pub enum GroupOpt {
         #[clap(subcommand)]
         commands: GroupPosix,
     },
+    /// Manage the account policy that applies to members of this group
+    #[clap(name = "account-policy")]
+    AccountPolicy {
+        #[clap(subcommand)]
+        commands: GroupAccountPolicy,
+    },
 }
 
 #[derive(Debug, Args)]
@@ -193,6 +239,10 @@ pub enum AccountCredential {
     /// recover or reset their account credentials.
     #[clap(name = "create-reset-token")]
     CreateResetToken(AccountNamedOpt),
+    /// Create a reset token and have the server send it to the primary mail address of
+    /// the person, rather than displaying it.
+    #[clap(name = "send-reset-token")]
+    SendResetToken(AccountNamedOpt),
 }
 
 /// RADIUS secret management
@@ -404,6 +454,48 @@ pub enum ServiceAccountApiToken {
     },
 }
 
+#[derive(Debug, Subcommand)]
+pub enum ServiceAccountOauth2Registration {
+    /// Set the origins that oauth2 clients registered by this service account may use.
+    /// Clients may use these origins, or any subdomain of them.
+    #[clap(name = "set-origins")]
+    SetOrigins {
+        #[clap(flatten)]
+        aopts: AccountCommonOpt,
+        #[clap(flatten)]
+        copt: CommonOpt,
+        #[clap(name = "origins", required = true)]
+        origins: Vec<String>,
+    },
+    /// Remove all registration origins, preventing this service account from registering
+    /// new oauth2 clients.
+    #[clap(name = "clear-origins")]
+    ClearOrigins(AccountNamedOpt),
+    /// Update or add a mapping from a group to the scopes that registered clients may provide
+    /// to its members.
+    #[clap(name = "update-scope-map")]
+    UpdateScopeMap {
+        #[clap(flatten)]
+        aopts: AccountCommonOpt,
+        #[clap(flatten)]
+        copt: CommonOpt,
+        #[clap(name = "group")]
+        group: String,
+        #[clap(name = "scopes")]
+        scopes: Vec<String>,
+    },
+    /// Remove a mapping from a group to scopes
+    #[clap(name = "delete-scope-map")]
+    DeleteScopeMap {
+        #[clap(flatten)]
+        aopts: AccountCommonOpt,
+        #[clap(flatten)]
+        copt: CommonOpt,
+        #[clap(name = "group")]
+        group: String,
+    },
+}
+
 #[derive(Debug, Args)]
 pub struct ServiceAccountUpdateOpt {
     #[clap(flatten)]
@@ -446,6 +538,12 @@ pub enum ServiceAccountOpt {
         #[clap(subcommand)]
         commands: ServiceAccountPosix,
     },
+    /// Manage the oauth2 clients this service account may register with its api tokens.
+    #[clap(name = "oauth2-registration")]
+    Oauth2Registration {
+        #[clap(subcommand)]
+        commands: ServiceAccountOauth2Registration,
+    },
     /// Manage sessions (user auth tokens) associated to this service account.
     #[clap(name = "session")]
     Session {
@@ -621,6 +719,14 @@ pub struct Oauth2DeleteScopeMapOpt {
     group: String,
 }
 
+#[derive(Debug, Args)]
+pub struct Oauth2TokenExchangeAudienceOpt {
+    #[clap(flatten)]
+    nopt: Named,
+    #[clap(name = "audience")]
+    audience: String,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Oauth2Opt {
     #[clap(name = "list")]
@@ -649,6 +755,14 @@ pub enum Oauth2Opt {
     /// Remove a mapping from groups to scopes
     DeleteSupScopeMap(Oauth2DeleteScopeMapOpt),
 
+    #[clap(name = "add-token-exchange-audience")]
+    /// Allow this resource server to exchange access tokens it was issued for tokens
+    /// to another resource server (the audience), on behalf of the same user.
+    AddTokenExchangeAudience(Oauth2TokenExchangeAudienceOpt),
+    #[clap(name = "remove-token-exchange-audience")]
+    /// Remove permission for this resource server to exchange tokens for the audience
+    RemoveTokenExchangeAudience(Oauth2TokenExchangeAudienceOpt),
+
     #[clap(name = "reset-secrets")]
     /// Reset the secrets associated to this resource server
     ResetSecrets(Named),
@@ -701,6 +815,57 @@ pub enum Oauth2Opt {
     #[clap(name = "prefer-spn-username")]
     /// Use the 'spn' attribute instead of 'name' for the preferred_username
     PreferSPNUsername(Named),
+    #[clap(name = "enable-require-par")]
+    /// Require that this resource server uses pushed authorisation requests.
+    EnableRequirePar(Named),
+    #[clap(name = "disable-require-par")]
+    /// Allow this resource server to send authorisation requests through the browser. This is the default.
+    DisableRequirePar(Named),
+    #[clap(name = "set-client-jwks")]
+    /// Set the public keys (in jwk format) this resource server signs request objects with.
+    /// This replaces any keys that were previously set.
+    SetClientJwks {
+        #[clap(flatten)]
+        nopt: Named,
+        #[clap(parse(from_os_str), required = true, min_values = 1)]
+        paths: Vec<PathBuf>,
+    },
+    #[clap(name = "clear-client-jwks")]
+    /// Remove all the request object signing keys of this resource server
+    ClearClientJwks(Named),
+    #[clap(name = "set-client-jwks-url")]
+    /// Set the url the jwk set of this resource server is published at
+    SetClientJwksUrl {
+        #[clap(flatten)]
+        nopt: Named,
+        #[clap(name = "jwks-url")]
+        url: String,
+    },
+    #[clap(name = "refresh-client-jwks")]
+    /// Fetch the jwk set of this resource server from its jwks url, replacing the current keys
+    RefreshClientJwks(Named),
+    #[clap(name = "set-backchannel-logout-uri")]
+    /// Set the url this resource server receives logout tokens at when a user's session ends
+    SetBackchannelLogoutUri {
+        #[clap(flatten)]
+        nopt: Named,
+        #[clap(name = "uri")]
+        uri: String,
+    },
+    #[clap(name = "clear-backchannel-logout-uri")]
+    /// Stop sending logout tokens to this resource server
+    ClearBackchannelLogoutUri(Named),
+    #[clap(name = "set-service-account")]
+    /// Set the service account that client credential grants of this resource server act as
+    SetServiceAccount {
+        #[clap(flatten)]
+        nopt: Named,
+        #[clap(name = "service-account")]
+        service_account: String,
+    },
+    #[clap(name = "clear-service-account")]
+    /// Remove the linked service account, disabling client credential grants
+    ClearServiceAccount(Named),
 }
 
 #[derive(Args, Debug)]
@@ -756,6 +921,18 @@ pub enum DomainOpt {
         #[clap(name = "new-basedn")]
         new_basedn: String,
     },
+    #[clap(name = "set-attestation-ca-list")]
+    /// Set the CAs that device keys must be attested by during enrolment. Each file
+    /// must contain one or more PEM encoded certificates.
+    SetAttestationCaList {
+        #[clap(flatten)]
+        copt: CommonOpt,
+        #[clap(name = "ca-path", parse(from_os_str), required = true, min_values = 1)]
+        ca_paths: Vec<PathBuf>,
+    },
+    #[clap(name = "clear-attestation-ca-list")]
+    /// Remove the attestation CAs, preventing any new device keys from being enrolled.
+    ClearAttestationCaList(CommonOpt),
     #[clap(name = "show")]
     /// Show information about this system's domain
     Show(CommonOpt),
@@ -765,6 +942,205 @@ pub enum DomainOpt {
     ResetTokenKey(CommonOpt),
 }
 
+#[derive(Debug, Subcommand)]
+pub enum MessageQueueOpt {
+    #[clap(name = "list")]
+    /// List all queued messages and their delivery status
+    List(CommonOpt),
+    #[clap(name = "get")]
+    /// Display a queued message by its uuid
+    Get(Named),
+    #[clap(name = "delete")]
+    /// Delete a queued message, preventing it from being delivered
+    Delete(Named),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SourceLockOpt {
+    #[clap(name = "list")]
+    /// List the addresses and networks that have recently failed to authenticate
+    List(CommonOpt),
+    #[clap(name = "clear")]
+    /// Lift the lock or ban on an address or network, such as "192.0.2.0/24"
+    Clear(Named),
+}
+
+#[derive(Debug, Args)]
+pub struct AuditSearchOpt {
+    #[clap(flatten)]
+    copt: CommonOpt,
+    /// Only show events caused by this account
+    #[clap(long)]
+    actor: Option<String>,
+    /// Only show events that affected this entry
+    #[clap(long)]
+    target: Option<String>,
+    /// Only show events at or after this rfc3339 time, such as "2020-09-25T11:22:02+10:00"
+    #[clap(long)]
+    from: Option<String>,
+    /// Only show events before this rfc3339 time
+    #[clap(long)]
+    to: Option<String>,
+    /// Only show events of this kind, such as "authentication_denied"
+    #[clap(long)]
+    kind: Option<String>,
+    /// The most events to show
+    #[clap(long)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuditOpt {
+    #[clap(name = "search")]
+    /// Search the audit log, showing the newest events first
+    Search(AuditSearchOpt),
+}
+
+#[derive(Debug, Args)]
+pub struct SchemaAttributeCreateOpt {
+    #[clap(flatten)]
+    copt: CommonOpt,
+    /// The name of the attribute, which must start with "ext_"
+    name: String,
+    /// A description of what the attribute holds
+    #[clap(long)]
+    description: String,
+    /// The syntax of the attribute's values, such as "UTF8STRING", "INT64" or "DATE"
+    #[clap(long)]
+    syntax: String,
+    /// Allow entries to have more than one value of the attribute
+    #[clap(long)]
+    multivalue: bool,
+    /// Require that no two entries have the same value of the attribute
+    #[clap(long)]
+    unique: bool,
+    /// An index to maintain for the attribute, such as "EQUALITY". May be repeated
+    #[clap(long)]
+    index: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct SchemaAttributeUpdateOpt {
+    #[clap(flatten)]
+    copt: CommonOpt,
+    name: String,
+    #[clap(long)]
+    description: Option<String>,
+    #[clap(long)]
+    multivalue: Option<bool>,
+    #[clap(long)]
+    unique: Option<bool>,
+    /// Replace the indexes of the attribute. May be repeated. Existing values are reindexed
+    #[clap(long)]
+    index: Vec<String>,
+    /// Remove all indexes of the attribute
+    #[clap(long, conflicts_with = "index")]
+    clear_index: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SchemaAttributeDeleteOpt {
+    #[clap(flatten)]
+    copt: CommonOpt,
+    name: String,
+    /// Remove the attribute from every entry and class that has it. Without this, an attribute
+    /// that is in use can not be deleted
+    #[clap(long)]
+    purge: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SchemaAttributeOpt {
+    #[clap(name = "list")]
+    /// List all schema attributes
+    List(CommonOpt),
+    #[clap(name = "get")]
+    /// Display a schema attribute
+    Get(Named),
+    #[clap(name = "create")]
+    /// Create a custom schema attribute
+    Create(SchemaAttributeCreateOpt),
+    #[clap(name = "update")]
+    /// Change the description, value constraints or indexes of a custom schema attribute
+    Update(SchemaAttributeUpdateOpt),
+    #[clap(name = "delete")]
+    /// Delete a custom schema attribute
+    Delete(SchemaAttributeDeleteOpt),
+}
+
+#[derive(Debug, Args)]
+pub struct SchemaClassCreateOpt {
+    #[clap(flatten)]
+    copt: CommonOpt,
+    /// The name of the class, which must start with "ext_"
+    name: String,
+    /// A description of the class
+    #[clap(long)]
+    description: String,
+    /// An attribute that entries of this class may have. May be repeated
+    #[clap(long)]
+    may: Vec<String>,
+    /// An attribute that entries of this class must have. May be repeated
+    #[clap(long)]
+    must: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct SchemaClassUpdateOpt {
+    #[clap(flatten)]
+    copt: CommonOpt,
+    name: String,
+    #[clap(long)]
+    description: Option<String>,
+    /// Replace the attributes that entries of this class may have. May be repeated
+    #[clap(long)]
+    may: Vec<String>,
+    /// Remove all attributes that entries of this class may have
+    #[clap(long, conflicts_with = "may")]
+    clear_may: bool,
+    /// Replace the attributes that entries of this class must have. May be repeated
+    #[clap(long)]
+    must: Vec<String>,
+    /// Remove all attributes that entries of this class must have
+    #[clap(long, conflicts_with = "must")]
+    clear_must: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SchemaClassOpt {
+    #[clap(name = "list")]
+    /// List all schema classes
+    List(CommonOpt),
+    #[clap(name = "get")]
+    /// Display a schema class
+    Get(Named),
+    #[clap(name = "create")]
+    /// Create a custom schema class
+    Create(SchemaClassCreateOpt),
+    #[clap(name = "update")]
+    /// Change the description or attributes of a custom schema class
+    Update(SchemaClassUpdateOpt),
+    #[clap(name = "delete")]
+    /// Delete a custom schema class that no entries have
+    Delete(Named),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SchemaOpt {
+    #[clap(name = "attribute")]
+    /// Manage schema attributes
+    Attribute {
+        #[clap(subcommand)]
+        commands: SchemaAttributeOpt,
+    },
+    #[clap(name = "class")]
+    /// Manage schema classes
+    Class {
+        #[clap(subcommand)]
+        commands: SchemaClassOpt,
+    },
+}
+
 #[derive(Debug, Subcommand)]
 pub enum SynchOpt {
     #[clap(name = "list")]
@@ -863,6 +1239,21 @@ pub enum SystemOpt {
         #[clap(subcommand)]
         commands: SynchOpt,
     },
+    #[clap(name = "message-queue")]
+    /// Review the messages the server has queued for delivery
+    MessageQueue {
+        #[clap(subcommand)]
+        commands: MessageQueueOpt,
+    },
+    #[clap(name = "source-lock")]
+    /// Review and clear the client addresses locked for failing to authenticate
+    SourceLock {
+        #[clap(subcommand)]
+        commands: SourceLockOpt,
+    },
+    #[clap(name = "password-report")]
+    /// Show how many accounts still have passwords hashed with a legacy algorithm
+    PasswordReport(CommonOpt),
 }
 
 #[derive(Debug, Subcommand)]
@@ -912,6 +1303,16 @@ pub enum KanidmClientOpt {
         #[clap(subcommand)]
         commands: RecycleOpt,
     },
+    /// Search the audit log of security relevant events
+    Audit {
+        #[clap(subcommand)]
+        commands: AuditOpt,
+    },
+    /// Extend the schema with custom attributes and classes
+    Schema {
+        #[clap(subcommand)]
+        commands: SchemaOpt,
+    },
     /// Unsafe - low level, raw database queries and operations.
     #[clap(hide = true)]
     Raw {